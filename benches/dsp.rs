@@ -0,0 +1,217 @@
+//! The DSP hot path, on the same made-up data every run
+//!
+//! `cargo bench --bench dsp`. Each name carries the time it took when the
+//! benchmark was written, on a release build, so a regression shows in the
+//! output without a saved baseline to compare against.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use num_complex::Complex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ringbuf::traits::{Consumer, Split};
+use ringbuf::HeapRb;
+use rtl_sdr_tui::dsp::decoder::decoder_channel;
+use rtl_sdr_tui::dsp::thread::demodulate;
+use rtl_sdr_tui::dsp::{start_dsp_thread, Channelizer, DspOutputs, Interpolation};
+use rtl_sdr_tui::sdr::samples_u8_to_complex;
+use rtl_sdr_tui::state::mode_settings::ModeSettings;
+use rtl_sdr_tui::{AppState, DemodMode, FftProcessor, Resampler, SampleBuffer};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Samples in a buffer, as the dongle delivers them
+const LEN: usize = 16_384;
+/// The default capture rate
+const RATE: u32 = 2_048_000;
+
+/// A benchmark's name, with what it took when this file was written
+fn named(name: &str, baseline: &str) -> String {
+    format!("{} (baseline {})", name, baseline)
+}
+
+/// Raw dongle bytes: noise about the ADC's center
+fn raw_bytes() -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(1);
+    (0..2 * LEN).map(|_| rng.gen_range(96..160)).collect()
+}
+
+/// An NFM carrier 25 kHz off the center, a 1 kHz tone at 3 kHz deviation,
+/// in a little noise
+fn iq(len: usize) -> Vec<Complex<f32>> {
+    let mut rng = StdRng::seed_from_u64(2);
+    let rate = RATE as f64;
+    let mut phase = 0.0f64;
+    (0..len)
+        .map(|n| {
+            let tone = (std::f64::consts::TAU * 1_000.0 * n as f64 / rate).sin();
+            phase += std::f64::consts::TAU * (25_000.0 + 3_000.0 * tone) / rate;
+            let noise = Complex::new(rng.gen_range(-0.01..0.01), rng.gen_range(-0.01..0.01));
+            Complex::from_polar(0.5, phase as f32) + noise
+        })
+        .collect()
+}
+
+/// The conversion by table, for comparison
+fn u8_to_complex_lut(bytes: &[u8], table: &[f32; 256]) -> Vec<Complex<f32>> {
+    bytes
+        .chunks_exact(2)
+        .map(|iq| Complex::new(table[iq[0] as usize], table[iq[1] as usize]))
+        .collect()
+}
+
+fn conversion(c: &mut Criterion) {
+    let bytes = raw_bytes();
+    let table: [f32; 256] = std::array::from_fn(|byte| (byte as f32 - 127.5) / 128.0);
+    assert_eq!(
+        u8_to_complex_lut(&bytes, &table),
+        samples_u8_to_complex(&bytes)
+    );
+
+    let mut group = c.benchmark_group("u8_to_complex");
+    group.throughput(Throughput::Elements(LEN as u64));
+    group.bench_function(named("arithmetic", "21 us"), |b| {
+        b.iter(|| samples_u8_to_complex(black_box(&bytes)))
+    });
+    group.bench_function(named("lut", "16 us"), |b| {
+        b.iter(|| u8_to_complex_lut(black_box(&bytes), &table))
+    });
+    group.finish();
+}
+
+fn fft(c: &mut Criterion) {
+    let samples = iq(LEN);
+    let mut group = c.benchmark_group("fft");
+    for (size, baseline) in [(1024, "16 us"), (2048, "52 us"), (4096, "72 us")] {
+        let mut fft = FftProcessor::new(size);
+        group.bench_function(named(&size.to_string(), baseline), |b| {
+            b.iter(|| fft.process(black_box(&samples)))
+        });
+    }
+    group.finish();
+}
+
+fn demodulation(c: &mut Criterion) {
+    let samples = iq(LEN);
+    let mut group = c.benchmark_group("demodulate");
+    group.throughput(Throughput::Elements(LEN as u64));
+    for (mode, baseline) in [
+        (DemodMode::FmNarrow, "467 us"),
+        (DemodMode::FmWide, "458 us"),
+        (DemodMode::Am, "66 us"),
+        (DemodMode::Usb, "266 us"),
+    ] {
+        let settings = ModeSettings::default_for(mode);
+        group.bench_function(named(mode.name(), baseline), |b| {
+            b.iter(|| demodulate(mode, black_box(&samples), RATE, &settings))
+        });
+    }
+    group.finish();
+}
+
+fn channel_filter(c: &mut Criterion) {
+    let samples = iq(LEN);
+    let mut channelizer = Channelizer::new();
+    let mut group = c.benchmark_group("channel_filter");
+    group.throughput(Throughput::Elements(LEN as u64));
+    group.bench_function(named("nfm_offset", "721 us"), |b| {
+        b.iter(|| {
+            let channel = channelizer.process(25_000, DemodMode::FmNarrow, None, RATE, &samples);
+            black_box(channel.len())
+        })
+    });
+    group.finish();
+}
+
+fn resampler(c: &mut Criterion) {
+    let audio: Vec<f32> = (0..LEN).map(|n| (n as f32 * 0.13).sin() * 0.5).collect();
+    let mut group = c.benchmark_group("resample_48k_to_44k1");
+    group.throughput(Throughput::Elements(LEN as u64));
+    for (interpolation, baseline) in [
+        (Interpolation::Polyphase, "1.14 ms"),
+        (Interpolation::Linear, "77 us"),
+    ] {
+        let mut resampler = Resampler::with_interpolation(48_000, 44_100, interpolation);
+        let name = format!("{:?}", interpolation).to_lowercase();
+        group.bench_function(named(&name, baseline), |b| {
+            b.iter(|| resampler.resample(black_box(&audio)))
+        });
+    }
+    group.finish();
+}
+
+/// A buffer through the DSP thread, from the SDR's channel to the speaker's
+/// ring, with the state updated as the app has it by default
+fn pipeline(c: &mut Criterion) {
+    let state = AppState::new_shared();
+    state.write().sdr.sample_rate = RATE;
+    state.write().decoder.switch_mode(DemodMode::FmNarrow);
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let (samples_tx, samples_rx) = crossbeam::channel::bounded(4);
+    let (recorder_tx, _recorder_rx) = crossbeam::channel::unbounded();
+    let (decoder_tap, _decoder_rx) = decoder_channel();
+    let (channel_b_tap, _channel_b_rx) = decoder_channel();
+    let (producer, mut consumer) = HeapRb::<f32>::new(8 * LEN).split();
+    let outputs = DspOutputs {
+        audio: Some(producer),
+        audible: Arc::new(AtomicBool::new(false)),
+        speaker_rate: Arc::new(AtomicU32::new(48_000)),
+        streams: Vec::new(),
+        decoder_tap,
+        channel_b_tap,
+        recorder_tx,
+    };
+    let dsp = start_dsp_thread(state, samples_rx, outputs, shutdown.clone());
+
+    // The speaker, keeping count of what it plays
+    let played = Arc::new(AtomicUsize::new(0));
+    let speaker = {
+        let (played, shutdown) = (played.clone(), shutdown.clone());
+        thread::spawn(move || {
+            while !shutdown.load(Ordering::Relaxed) {
+                let popped = consumer.pop_iter().count();
+                played.fetch_add(popped, Ordering::Relaxed);
+                if popped == 0 {
+                    thread::yield_now();
+                }
+            }
+        })
+    };
+
+    let buffer = SampleBuffer::new(iq(LEN), RATE);
+    // The FM discriminator makes one sample fewer than it is given
+    let mut expected = 0;
+    c.bench_function(&named("pipeline/nfm_buffer", "1.37 ms"), |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            for _ in 0..iters {
+                samples_tx.send(buffer.clone()).unwrap();
+            }
+            expected += iters as usize * (LEN - 1);
+            while played.load(Ordering::Relaxed) < expected {
+                assert!(
+                    start.elapsed() < Duration::from_secs(60),
+                    "pipeline stalled"
+                );
+                thread::yield_now();
+            }
+            start.elapsed()
+        })
+    });
+
+    shutdown.store(true, Ordering::Relaxed);
+    dsp.join().unwrap();
+    speaker.join().unwrap();
+}
+
+criterion_group!(
+    benches,
+    conversion,
+    fft,
+    demodulation,
+    channel_filter,
+    resampler,
+    pipeline
+);
+criterion_main!(benches);
@@ -0,0 +1,140 @@
+//! Audio latency: the buffer sizes that bound it and an estimate of it
+//!
+//! Audio reaches the speaker after waiting in the IQ channel to the DSP
+//! thread and in the audio ring, and a stream client after the IQ channel
+//! and its own queue. Each is sized here: the ring holds no more than the
+//! target latency, so the output never plays audio older than that, and
+//! `--low-latency` shrinks everything for about 100 ms in all at the cost
+//! of dropouts when the DSP thread stalls.
+
+/// Audio samples per second through the ring to the output
+pub const AUDIO_RATE: u32 = 48_000;
+/// Audio ring target by default, in ms
+pub const DEFAULT_TARGET_MS: u32 = 200;
+/// Audio ring target with `--low-latency`, in ms; with the shorter IQ
+/// queue about 100 ms from antenna to speaker
+pub const LOW_LATENCY_TARGET_MS: u32 = 60;
+
+/// Queue and buffer sizes between the receiver and the listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Buffering {
+    /// IQ buffers the SDR thread may queue for the DSP thread
+    pub iq_queue: usize,
+    /// Audio ring capacity in samples
+    pub audio_ring: usize,
+    /// Audio buffers each stream or pipe may queue
+    pub stream_queue: usize,
+}
+
+impl Buffering {
+    /// Buffers for an audio ring of `target_ms`, or the low latency sizes
+    pub fn new(target_ms: u32, low_latency: bool) -> Self {
+        if low_latency {
+            Self {
+                iq_queue: 4,
+                audio_ring: samples(target_ms.min(LOW_LATENCY_TARGET_MS)),
+                stream_queue: 8,
+            }
+        } else {
+            Self {
+                iq_queue: 64,
+                audio_ring: samples(target_ms),
+                stream_queue: 64,
+            }
+        }
+    }
+}
+
+impl Default for Buffering {
+    fn default() -> Self {
+        Self::new(DEFAULT_TARGET_MS, false)
+    }
+}
+
+/// Audio samples in `ms` milliseconds, at least one
+fn samples(ms: u32) -> usize {
+    (AUDIO_RATE as usize * ms as usize / 1000).max(1)
+}
+
+/// What is queued on the way to the listener, as the DSP thread last saw it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyEstimate {
+    /// Duration of one IQ buffer in seconds
+    pub iq_buffer_secs: f64,
+    /// IQ buffers waiting for the DSP thread
+    pub iq_queued: usize,
+    /// Audio samples waiting in the ring
+    pub audio_queued: usize,
+    /// Audio buffers waiting for the slowest stream or pipe, None when
+    /// there are none
+    pub stream_queued: Option<usize>,
+}
+
+impl LatencyEstimate {
+    /// From capture to the DSP thread: the buffer being captured and those
+    /// waiting, in ms
+    pub fn iq_ms(&self) -> f64 {
+        (self.iq_queued + 1) as f64 * self.iq_buffer_secs * 1000.0
+    }
+
+    /// Audio waiting to be played, in ms
+    pub fn audio_ms(&self) -> f64 {
+        self.audio_queued as f64 * 1000.0 / AUDIO_RATE as f64
+    }
+
+    /// From capture to the speaker, in ms
+    pub fn total_ms(&self) -> f64 {
+        self.iq_ms() + self.audio_ms()
+    }
+
+    /// From capture to leaving for a stream client or pipe, in ms; each
+    /// audio buffer covers one IQ buffer
+    pub fn stream_ms(&self) -> Option<f64> {
+        let queued = self.stream_queued?;
+        Some(self.iq_ms() + queued as f64 * self.iq_buffer_secs * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffering() {
+        let normal = Buffering::default();
+        assert_eq!(normal.audio_ring, 9_600);
+        assert_eq!(normal.iq_queue, 64);
+
+        // Low latency caps the audio target but honors a lower one
+        let low = Buffering::new(DEFAULT_TARGET_MS, true);
+        assert_eq!(low.audio_ring, 2_880);
+        assert_eq!(low.iq_queue, 4);
+        assert_eq!(Buffering::new(20, true).audio_ring, 960);
+        assert_eq!(Buffering::new(0, false).audio_ring, 1);
+    }
+
+    #[test]
+    fn test_estimate() {
+        // 16384 samples at 2.048 MS/s is 8 ms a buffer
+        let mut estimate = LatencyEstimate {
+            iq_buffer_secs: 16_384.0 / 2_048_000.0,
+            iq_queued: 2,
+            audio_queued: 4_800,
+            stream_queued: None,
+        };
+        assert!((estimate.iq_ms() - 24.0).abs() < 1e-9);
+        assert!((estimate.audio_ms() - 100.0).abs() < 1e-9);
+        assert!((estimate.total_ms() - 124.0).abs() < 1e-9);
+        assert_eq!(estimate.stream_ms(), None);
+
+        estimate.stream_queued = Some(10);
+        assert!((estimate.stream_ms().unwrap() - 104.0).abs() < 1e-9);
+
+        // Nothing queued still waits for the buffer being captured
+        let idle = LatencyEstimate {
+            iq_buffer_secs: 0.008,
+            ..Default::default()
+        };
+        assert!((idle.total_ms() - 8.0).abs() < 1e-9);
+    }
+}
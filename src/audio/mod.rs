@@ -1,5 +1,7 @@
 pub mod buffer;
+pub mod latency;
 pub mod output;
+pub mod reconnect;
 
 // Re-export commonly used types
 pub use output::AudioOutput;
@@ -1,13 +1,46 @@
+use super::latency::AUDIO_RATE;
 use anyhow::Result;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Host, Stream, StreamConfig};
+use cpal::{
+    Device, FromSample, Host, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig,
+    SupportedStreamConfig,
+};
+use parking_lot::Mutex;
 use ringbuf::traits::Consumer;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Each receiver's audio and which of it is heard, shared by the output
+/// stream and any stream opened to replace it
+pub struct Routing<C> {
+    /// Ring buffer consumer for each receiver's audio
+    consumers: Mutex<Vec<C>>,
+    /// Whether each receiver's audio should be heard; what a quiet one has
+    /// queued is discarded rather than played late
+    audible: Vec<Arc<AtomicBool>>,
+    /// Index of the consumer to play; the others are discarded
+    selected: Arc<AtomicUsize>,
+}
+
+impl<C> Routing<C> {
+    pub fn new(
+        consumers: Vec<C>,
+        audible: Vec<Arc<AtomicBool>>,
+        selected: Arc<AtomicUsize>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            consumers: Mutex::new(consumers),
+            audible,
+            selected,
+        })
+    }
+}
 
 /// Audio output manager
 pub struct AudioOutput {
     _host: Host,
     _device: Device,
-    _config: StreamConfig,
+    config: StreamConfig,
     stream: Stream,
 }
 
@@ -16,7 +49,32 @@ impl AudioOutput {
     ///
     /// # Arguments
     /// * `consumer` - Ring buffer consumer for audio samples
-    pub fn new<C: Consumer<Item = f32> + Send + 'static>(mut consumer: C) -> Result<Self> {
+    pub fn new<C: Consumer<Item = f32> + Send + 'static>(consumer: C) -> Result<Self> {
+        Self::open(
+            Routing::new(
+                vec![consumer],
+                vec![Arc::new(AtomicBool::new(true))],
+                Arc::new(AtomicUsize::new(0)),
+            ),
+            AUDIO_RATE,
+            Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    /// Create and start an audio output stream on the default device,
+    /// playing one of several receivers
+    ///
+    /// # Arguments
+    /// * `routing` - Each receiver's audio and which of it to play
+    /// * `preferred_rate` - Sample rate to run the device at if it can;
+    ///   otherwise it runs at its default, which [`Self::sample_rate`] gives
+    /// * `lost` - Set when the stream fails, e.g. because the device was
+    ///   unplugged; it plays nothing more after that
+    pub fn open<C: Consumer<Item = f32> + Send + 'static>(
+        routing: Arc<Routing<C>>,
+        preferred_rate: u32,
+        lost: Arc<AtomicBool>,
+    ) -> Result<Self> {
         // Get default audio output device
         let host = cpal::default_host();
         let device = host
@@ -25,30 +83,23 @@ impl AudioOutput {
 
         log::info!("Audio output device: {}", device.name()?);
 
-        // Get default output config
-        let config = device.default_output_config()?;
+        let supported = output_config(&device, preferred_rate)?;
+        let config = supported.config();
         log::info!(
-            "Audio config: {} Hz, {} channels",
-            config.sample_rate().0,
-            config.channels()
+            "Audio config: {} Hz, {} channels, {} samples",
+            config.sample_rate.0,
+            config.channels,
+            supported.sample_format()
         );
 
-        let config: StreamConfig = config.into();
-
-        // Create output stream
-        let stream = device.build_output_stream(
-            &config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                // Fill output buffer from ring buffer
-                for sample in data.iter_mut() {
-                    *sample = consumer.try_pop().unwrap_or(0.0);
-                }
-            },
-            |err| {
-                log::error!("Audio stream error: {}", err);
-            },
-            None,
-        )?;
+        // Create output stream, in the format the device takes
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32, C>(&device, &config, routing, lost),
+            SampleFormat::I16 => build_stream::<i16, C>(&device, &config, routing, lost),
+            SampleFormat::U16 => build_stream::<u16, C>(&device, &config, routing, lost),
+            SampleFormat::I32 => build_stream::<i32, C>(&device, &config, routing, lost),
+            format => anyhow::bail!("Audio device takes {} samples, which can't be played", format),
+        }?;
 
         // Start the stream
         stream.play()?;
@@ -57,11 +108,16 @@ impl AudioOutput {
         Ok(Self {
             _host: host,
             _device: device,
-            _config: config,
+            config,
             stream,
         })
     }
 
+    /// The rate the device runs at, which the audio is to be played at
+    pub fn sample_rate(&self) -> u32 {
+        self.config.sample_rate.0
+    }
+
     /// Pause the audio stream
     pub fn pause(&self) -> Result<()> {
         self.stream.pause()?;
@@ -80,3 +136,138 @@ impl Drop for AudioOutput {
         log::info!("Audio output stopped");
     }
 }
+
+/// An output stream playing `routing` in the device's sample format `T`
+///
+/// WASAPI in shared mode takes only the format the device mixes in, which
+/// need not be f32, so the audio is converted as it is played.
+fn build_stream<T, C>(
+    device: &Device,
+    config: &StreamConfig,
+    routing: Arc<Routing<C>>,
+    lost: Arc<AtomicBool>,
+) -> Result<Stream>
+where
+    T: SizedSample + FromSample<f32>,
+    C: Consumer<Item = f32> + Send + 'static,
+{
+    let channels = config.channels as usize;
+    let mut buffer = Vec::new();
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let selected = routing.selected.load(Ordering::Relaxed);
+            let playing = routing.audible[selected]
+                .load(Ordering::Relaxed)
+                .then_some(selected);
+            buffer.resize(data.len(), 0.0);
+            fill(&mut buffer, channels, &mut routing.consumers.lock(), playing);
+            for (out, &sample) in data.iter_mut().zip(&buffer) {
+                *out = T::from_sample(sample);
+            }
+        },
+        move |err| {
+            log::error!("Audio stream error: {}", err);
+            lost.store(true, Ordering::Relaxed);
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// The device's config at `preferred` Hz if it supports that, in its
+/// default format and channels; its default config otherwise
+fn output_config(device: &Device, preferred: u32) -> Result<SupportedStreamConfig> {
+    let default = device.default_output_config()?;
+    if default.sample_rate().0 == preferred {
+        return Ok(default);
+    }
+    let at_preferred = device
+        .supported_output_configs()
+        .ok()
+        .and_then(|mut ranges| {
+            ranges.find(|range| {
+                range.channels() == default.channels()
+                    && range.sample_format() == default.sample_format()
+                    && (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&preferred)
+            })
+        });
+    Ok(match at_preferred {
+        Some(range) => range.with_sample_rate(SampleRate(preferred)),
+        None => {
+            log::info!(
+                "Audio device can't run at {} Hz, using {} Hz",
+                preferred,
+                default.sample_rate().0
+            );
+            default
+        }
+    })
+}
+
+/// Fill the output buffer of `channels` interleaved channels from the
+/// `playing` consumer, the same sample in each, or with silence if none,
+/// dropping what the others have queued so they don't back up
+fn fill<C: Consumer<Item = f32>>(
+    data: &mut [f32],
+    channels: usize,
+    consumers: &mut [C],
+    playing: Option<usize>,
+) {
+    if playing.is_none() {
+        data.fill(0.0);
+    }
+    for (index, consumer) in consumers.iter_mut().enumerate() {
+        if Some(index) == playing {
+            for frame in data.chunks_mut(channels.max(1)) {
+                frame.fill(consumer.try_pop().unwrap_or(0.0));
+            }
+        } else {
+            consumer.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ringbuf::traits::{Observer, Producer, Split};
+    use ringbuf::HeapRb;
+
+    #[test]
+    fn test_fill_plays_selected_receiver() {
+        let (mut producers, mut consumers): (Vec<_>, Vec<_>) =
+            (0..2).map(|_| HeapRb::<f32>::new(16).split()).unzip();
+        producers[0].push_slice(&[0.1, 0.2]);
+        producers[1].push_slice(&[0.5, 0.6, 0.7]);
+
+        let mut data = [1.0; 3];
+        fill(&mut data, 1, &mut consumers, Some(1));
+        assert_eq!(data, [0.5, 0.6, 0.7]);
+        assert!(consumers[0].is_empty());
+
+        // Underruns play silence
+        producers[0].push_slice(&[0.3]);
+        fill(&mut data, 1, &mut consumers, Some(0));
+        assert_eq!(data, [0.3, 0.0, 0.0]);
+
+        // Nothing to hear: silence, and nothing left to replay later
+        producers[0].push_slice(&[0.4, 0.5]);
+        producers[1].push_slice(&[0.8]);
+        let mut data = [1.0; 3];
+        fill(&mut data, 1, &mut consumers, None);
+        assert_eq!(data, [0.0; 3]);
+        assert!(consumers.iter().all(|consumer| consumer.is_empty()));
+    }
+
+    #[test]
+    fn test_fill_stereo() {
+        // Each sample goes to both channels, so stereo plays at the same
+        // pitch as mono
+        let (mut producer, consumer) = HeapRb::<f32>::new(16).split();
+        producer.push_slice(&[0.1, 0.2, 0.3]);
+        let mut data = [1.0; 6];
+        fill(&mut data, 2, &mut [consumer], Some(0));
+        assert_eq!(data, [0.1, 0.1, 0.2, 0.2, 0.3, 0.3]);
+    }
+}
@@ -0,0 +1,252 @@
+//! Getting the speaker back when the audio device goes away
+//!
+//! Unplugging a USB headset makes the output stream report an error, after
+//! which it plays nothing. The error sets a flag; the stream is then dropped
+//! and, after a short delay, opened again on whatever the default device is
+//! by then. After repeated failures the app carries on without a speaker,
+//! as with `--no-audio`. The audio rings outlive the streams, so a new
+//! stream picks up where the old one left off.
+//!
+//! cpal streams can't move between threads everywhere, so they are opened,
+//! watched and dropped on a thread of their own.
+
+use super::output::{AudioOutput, Routing};
+use anyhow::Result;
+use crossbeam::channel;
+use ringbuf::traits::Consumer;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Wait between a loss, or a failed reopen, and the next try
+pub const RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Failed reopens before giving up
+pub const MAX_ATTEMPTS: u32 = 10;
+/// How often the thread looks at the stream
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How the speaker is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioHealth {
+    Playing,
+    /// The device went away; `attempts` to reopen it have failed so far
+    Reconnecting {
+        attempts: u32,
+    },
+    /// Given up on; no audio until restart
+    Off,
+}
+
+impl AudioHealth {
+    /// What the status bar says about it, if anything
+    pub fn warning(self) -> Option<String> {
+        match self {
+            AudioHealth::Playing => None,
+            AudioHealth::Reconnecting { .. } => {
+                Some("AUDIO DEVICE LOST — reconnecting".to_string())
+            }
+            AudioHealth::Off => Some("AUDIO OFF — device lost".to_string()),
+        }
+    }
+}
+
+/// An output opened by `open`, reopened with it when lost
+///
+/// `open` gets a flag for the output to set when it fails.
+pub struct Reconnect<S, F> {
+    open: F,
+    output: Option<S>,
+    lost: Arc<AtomicBool>,
+    health: AudioHealth,
+    retry_at: Instant,
+}
+
+impl<S, F: FnMut(Arc<AtomicBool>) -> Result<S>> Reconnect<S, F> {
+    /// Open the output for the first time
+    pub fn start(mut open: F) -> Result<Self> {
+        let lost = Arc::new(AtomicBool::new(false));
+        let output = open(lost.clone())?;
+        Ok(Self {
+            open,
+            output: Some(output),
+            lost,
+            health: AudioHealth::Playing,
+            retry_at: Instant::now(),
+        })
+    }
+
+    /// Drop a lost output, or try reopening it if that is due at `now`
+    pub fn poll(&mut self, now: Instant) -> AudioHealth {
+        match self.health {
+            AudioHealth::Playing if self.lost.load(Ordering::Relaxed) => {
+                log::warn!("Audio device lost, reopening in {:?}", RETRY_DELAY);
+                self.output = None;
+                self.health = AudioHealth::Reconnecting { attempts: 0 };
+                self.retry_at = now + RETRY_DELAY;
+            }
+            AudioHealth::Reconnecting { attempts } if now >= self.retry_at => {
+                // A flag of its own, so the old stream can't mark it lost
+                let lost = Arc::new(AtomicBool::new(false));
+                match (self.open)(lost.clone()) {
+                    Ok(output) => {
+                        log::info!("Audio output reopened");
+                        self.output = Some(output);
+                        self.lost = lost;
+                        self.health = AudioHealth::Playing;
+                    }
+                    Err(e) if attempts + 1 >= MAX_ATTEMPTS => {
+                        log::error!("Can't reopen the audio output ({:#}), going without", e);
+                        self.health = AudioHealth::Off;
+                    }
+                    Err(e) => {
+                        log::warn!("Can't reopen the audio output yet: {:#}", e);
+                        self.health = AudioHealth::Reconnecting {
+                            attempts: attempts + 1,
+                        };
+                        self.retry_at = now + RETRY_DELAY;
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.health
+    }
+}
+
+/// Play `routing` on the default output device, at `preferred_rate` if it
+/// can run at it, reopening it when it goes away, until `shutdown` is set;
+/// `rate` is kept at the rate each output runs at, and `report` gets the
+/// speaker's health at every look
+///
+/// Returns once the output has first been opened, or with why it couldn't
+/// be.
+pub fn start_audio_thread<C, R>(
+    routing: Arc<Routing<C>>,
+    preferred_rate: u32,
+    rate: Arc<AtomicU32>,
+    mut report: R,
+    shutdown: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>>
+where
+    C: Consumer<Item = f32> + Send + 'static,
+    R: FnMut(AudioHealth) + Send + 'static,
+{
+    let (opened_tx, opened_rx) = channel::bounded(1);
+    let handle = thread::Builder::new()
+        .name("audio".to_string())
+        .spawn(move || {
+            let reconnect = Reconnect::start(|lost| {
+                let output = AudioOutput::open(routing.clone(), preferred_rate, lost)?;
+                rate.store(output.sample_rate(), Ordering::Relaxed);
+                Ok(output)
+            });
+            let mut reconnect = match reconnect {
+                Ok(reconnect) => {
+                    let _ = opened_tx.send(Ok(()));
+                    reconnect
+                }
+                Err(e) => {
+                    let _ = opened_tx.send(Err(e));
+                    return;
+                }
+            };
+            while !shutdown.load(Ordering::Relaxed) {
+                report(reconnect.poll(Instant::now()));
+                thread::sleep(POLL_INTERVAL);
+            }
+        })?;
+    opened_rx.recv()??;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use std::collections::VecDeque;
+
+    /// The flags each output opened was given
+    type Flags = Arc<parking_lot::Mutex<Vec<Arc<AtomicBool>>>>;
+
+    /// A device that opens or not as `outcomes` say; each output is numbered
+    fn device(
+        outcomes: impl IntoIterator<Item = bool>,
+    ) -> (impl FnMut(Arc<AtomicBool>) -> Result<usize>, Flags) {
+        let mut outcomes: VecDeque<bool> = outcomes.into_iter().collect();
+        let flags = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let given = flags.clone();
+        let open = move |lost| {
+            if !outcomes.pop_front().unwrap_or(false) {
+                bail!("no such device");
+            }
+            let mut given = given.lock();
+            given.push(lost);
+            Ok(given.len())
+        };
+        (open, flags)
+    }
+
+    #[test]
+    fn test_reconnects_after_loss() {
+        let (open, flags) = device([true, false, true]);
+        let mut reconnect = Reconnect::start(open).unwrap();
+        let start = Instant::now();
+        assert_eq!(reconnect.poll(start), AudioHealth::Playing);
+
+        // Unplugged: the output goes at once, the retry waits
+        flags.lock()[0].store(true, Ordering::Relaxed);
+        let lost = AudioHealth::Reconnecting { attempts: 0 };
+        assert_eq!(reconnect.poll(start), lost);
+        assert!(reconnect.output.is_none());
+        assert_eq!(reconnect.poll(start + RETRY_DELAY / 2), lost);
+
+        // No device yet, then one
+        let retried = start + RETRY_DELAY;
+        assert_eq!(
+            reconnect.poll(retried),
+            AudioHealth::Reconnecting { attempts: 1 }
+        );
+        assert_eq!(reconnect.poll(retried + RETRY_DELAY), AudioHealth::Playing);
+        assert_eq!(reconnect.output, Some(2));
+
+        // The old stream's flag no longer counts
+        flags.lock()[0].store(true, Ordering::Relaxed);
+        assert_eq!(
+            reconnect.poll(retried + RETRY_DELAY * 2),
+            AudioHealth::Playing
+        );
+        flags.lock()[1].store(true, Ordering::Relaxed);
+        assert_eq!(
+            reconnect.poll(retried + RETRY_DELAY * 2),
+            AudioHealth::Reconnecting { attempts: 0 }
+        );
+    }
+
+    #[test]
+    fn test_gives_up() {
+        let (open, flags) = device([true]);
+        let mut reconnect = Reconnect::start(open).unwrap();
+        flags.lock()[0].store(true, Ordering::Relaxed);
+        let mut now = Instant::now();
+        reconnect.poll(now);
+        for attempts in 1..MAX_ATTEMPTS {
+            now += RETRY_DELAY;
+            assert_eq!(reconnect.poll(now), AudioHealth::Reconnecting { attempts });
+        }
+        now += RETRY_DELAY;
+        assert_eq!(reconnect.poll(now), AudioHealth::Off);
+        assert_eq!(reconnect.poll(now + RETRY_DELAY * 10), AudioHealth::Off);
+        assert_eq!(
+            AudioHealth::Off.warning().as_deref(),
+            Some("AUDIO OFF — device lost")
+        );
+        assert_eq!(AudioHealth::Playing.warning(), None);
+    }
+
+    #[test]
+    fn test_first_open_fails() {
+        let (open, _) = device([false]);
+        assert!(Reconnect::start(open).is_err());
+    }
+}
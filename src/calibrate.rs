@@ -0,0 +1,477 @@
+//! PPM calibration against a known carrier
+//!
+//! With a strong carrier of known frequency in view (a NOAA weather station
+//! is ideal), the power spectrum is averaged over a few seconds and the
+//! strongest bin near where the carrier should be is refined to a fraction
+//! of a bin with a parabolic fit. How far the carrier lands from where it
+//! should, as a share of its frequency, is the dongle's frequency error.
+//!
+//! The carrier has to sit clear of the DC spike, so the receiver is tuned
+//! off to one side of it while measuring.
+
+use crate::dsp::FftProcessor;
+use crate::sdr::{SampleBuffer, SampleSink, SdrSource};
+use crate::state::{AppState, Modal, SharedState};
+use crate::types::Command;
+use anyhow::{bail, Result};
+use crossbeam::channel::{self, Sender};
+use num_complex::Complex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the spectrum is averaged
+pub const AVERAGE_TIME: Duration = Duration::from_secs(5);
+/// Where the center is tuned from the carrier, keeping it off the DC spike
+pub const TUNE_OFFSET: i32 = 250_000;
+/// A carrier at least this far from the center is measured where it is
+const CLEAR_OF_DC: i32 = 10_000;
+/// How far either side of the carrier to look, in PPM of its frequency
+const SEARCH_PPM: f64 = 150.0;
+/// Narrowest search either side of the carrier in Hz
+const MIN_SEARCH_HZ: f64 = 5_000.0;
+/// A carrier must stand this far over the median level, in dB
+const MIN_SNR: f32 = 10.0;
+/// Time for the tuner to settle after a retune before frames count
+const SETTLE: Duration = Duration::from_millis(100);
+/// Longest wait for a retune to show up in state
+const CHANGE_TIMEOUT: Duration = Duration::from_secs(1);
+/// FFT size for the command line calibration
+const FFT_SIZE: usize = 2048;
+
+/// Offset of a peak from its center bin, in bins, by fitting a parabola
+/// through the center bin and its neighbours; 0 if they don't form a peak
+pub fn parabolic_offset(left: f32, center: f32, right: f32) -> f32 {
+    let curvature = left - 2.0 * center + right;
+    if curvature >= 0.0 {
+        return 0.0;
+    }
+    (0.5 * (left - right) / curvature).clamp(-0.5, 0.5)
+}
+
+/// PPM correction for a tuner that shows a carrier at `expected` Hz at
+/// `measured` Hz: positive when the crystal runs fast, pulling the carrier
+/// low
+pub fn ppm_error(expected: u32, measured: f64) -> f64 {
+    (expected as f64 - measured) / expected as f64 * 1e6
+}
+
+/// Where the carrier turned up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// Hz
+    pub expected: u32,
+    /// Hz
+    pub measured: f64,
+    /// Carrier over the median level in dB
+    pub snr: f32,
+}
+
+impl Measurement {
+    /// Hz the carrier is off by
+    pub fn offset(&self) -> f64 {
+        self.measured - self.expected as f64
+    }
+
+    /// The error left with the correction that was in use
+    pub fn error_ppm(&self) -> f64 {
+        ppm_error(self.expected, self.measured)
+    }
+
+    /// The correction to use in place of `current`
+    pub fn corrected(&self, current: i32) -> i32 {
+        current + self.error_ppm().round() as i32
+    }
+
+    /// One line summary
+    pub fn describe(&self) -> String {
+        format!(
+            "{:.3} MHz carrier at {:+.0} Hz ({:+.2} ppm, {:.1} dB over the noise)",
+            self.expected as f64 / 1e6,
+            self.offset(),
+            self.error_ppm(),
+            self.snr
+        )
+    }
+}
+
+/// Power spectrum averaged while looking for a carrier at `expected` Hz
+pub struct Calibration {
+    expected: u32,
+    /// Linear power per bin, summed
+    power: Vec<f32>,
+    frames: usize,
+}
+
+impl Calibration {
+    pub fn new(expected: u32) -> Self {
+        Self {
+            expected,
+            power: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    /// Add a centered dB spectrum; a change of FFT size starts over
+    pub fn add_frame(&mut self, fft: &[f32]) {
+        if fft.len() != self.power.len() {
+            self.power = vec![0.0; fft.len()];
+            self.frames = 0;
+        }
+        for (total, db) in self.power.iter_mut().zip(fft) {
+            *total += 10f32.powf(db / 10.0);
+        }
+        self.frames += 1;
+    }
+
+    /// Find the carrier in the average so far, taken with the receiver
+    /// tuned to `center` at `sample_rate`
+    pub fn measure(&self, center: u32, sample_rate: u32) -> Result<Measurement> {
+        let len = self.power.len();
+        if self.frames == 0 || len < 4 {
+            bail!("no spectrum to measure");
+        }
+        let spectrum: Vec<f32> = self
+            .power
+            .iter()
+            .map(|power| 10.0 * (power / self.frames as f32).max(1e-20).log10())
+            .collect();
+
+        let bin_hz = sample_rate as f64 / len as f64;
+        let dc = len / 2;
+        let expected_bin = (self.expected as f64 - center as f64) / bin_hz + dc as f64;
+        let search = (self.expected as f64 * SEARCH_PPM / 1e6).max(MIN_SEARCH_HZ) / bin_hz;
+        // Keep a neighbour either side for the fit
+        let low = (expected_bin - search).ceil().max(1.0) as usize;
+        let high = ((expected_bin + search).floor() as usize).min(len - 2);
+        let Some(bin) = (low..=high)
+            .filter(|bin| bin.abs_diff(dc) > 1)
+            .max_by(|&a, &b| spectrum[a].total_cmp(&spectrum[b]))
+        else {
+            bail!(
+                "{:.3} MHz is outside the received band",
+                self.expected as f64 / 1e6
+            );
+        };
+
+        let mut sorted = spectrum.clone();
+        sorted.sort_by(f32::total_cmp);
+        let snr = spectrum[bin] - sorted[len / 2];
+        if snr < MIN_SNR {
+            bail!(
+                "no carrier near {:.3} MHz (strongest only {:.1} dB over the noise)",
+                self.expected as f64 / 1e6,
+                snr
+            );
+        }
+
+        let fraction = parabolic_offset(spectrum[bin - 1], spectrum[bin], spectrum[bin + 1]);
+        let offset = (bin as f64 + fraction as f64 - dc as f64) * bin_hz;
+        Ok(Measurement {
+            expected: self.expected,
+            measured: center as f64 + offset,
+            snr,
+        })
+    }
+}
+
+/// Start calibrating the receiver with `state` against a carrier at chain
+/// A's frequency, retuning through `command_tx`; false if it already is
+///
+/// Progress shows in `SdrState::ppm_calibration`. The result is offered in
+/// a `Modal::ApplyPpm`, or explained in the status message.
+pub fn start_calibration(state: SharedState, command_tx: Sender<Command>) -> bool {
+    {
+        let mut state_guard = state.write();
+        if state_guard.sdr.ppm_calibration.is_some() {
+            return false;
+        }
+        state_guard.sdr.ppm_calibration = Some("Calibrating".to_string());
+    }
+
+    thread::spawn(move || {
+        let current = state.read().sdr.ppm_error;
+        let result = calibrate(&state, &command_tx);
+        let mut state_guard = state.write();
+        state_guard.sdr.ppm_calibration = None;
+        match result {
+            Ok(measurement) => {
+                let ppm = measurement.corrected(current);
+                log::info!("Calibration: {}", measurement.describe());
+                state_guard.ui.modal = Some(Modal::ApplyPpm {
+                    ppm,
+                    message: format!(
+                        "{}: set PPM {} → {} and save? y/N",
+                        measurement.describe(),
+                        current,
+                        ppm
+                    ),
+                });
+            }
+            Err(e) => {
+                log::warn!("Calibration failed: {:#}", e);
+                state_guard.ui.status_message = format!("Calibration failed: {}", e);
+            }
+        }
+    });
+    true
+}
+
+/// Average the spectrum with the carrier clear of DC and measure it,
+/// putting the center back afterwards
+fn calibrate(state: &SharedState, command_tx: &Sender<Command>) -> Result<Measurement> {
+    let (center, carrier) = {
+        let state = state.read();
+        let offset = state.channels.offset_a;
+        (
+            state.sdr.frequency,
+            state.sdr.frequency.saturating_add_signed(offset),
+        )
+    };
+    log::info!("Calibrating against {} Hz", carrier);
+
+    let retuned = carrier.abs_diff(center) < CLEAR_OF_DC as u32;
+    if retuned {
+        let tuned = carrier.saturating_add_signed(TUNE_OFFSET);
+        command_tx.send(Command::SetFrequency(tuned))?;
+        let asked = Instant::now();
+        while state.read().sdr.frequency != tuned {
+            if asked.elapsed() > CHANGE_TIMEOUT {
+                bail!("the receiver didn't retune");
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread::sleep(SETTLE);
+    }
+
+    let result = average(state, carrier);
+    if retuned {
+        let _ = command_tx.send(Command::SetFrequency(center));
+    }
+    result
+}
+
+/// Average `AVERAGE_TIME` worth of new spectrum frames and measure the
+/// carrier in them
+fn average(state: &SharedState, carrier: u32) -> Result<Measurement> {
+    let mut calibration = Calibration::new(carrier);
+    let (mut last_frame, center, sample_rate) = {
+        let state = state.read();
+        (
+            state.spectrum.frames,
+            state.sdr.frequency,
+            state.sdr.sample_rate,
+        )
+    };
+    let started = Instant::now();
+    while started.elapsed() < AVERAGE_TIME {
+        thread::sleep(Duration::from_millis(5));
+        let mut state = state.write();
+        if (state.sdr.frequency, state.sdr.sample_rate) != (center, sample_rate) {
+            bail!("the receiver was retuned");
+        }
+        if state.spectrum.frames == last_frame {
+            continue;
+        }
+        last_frame = state.spectrum.frames;
+        calibration.add_frame(&state.spectrum.fft_data);
+        state.sdr.ppm_calibration = Some(format!(
+            "Calibrating against {:.3} MHz, {}s left",
+            carrier as f64 / 1e6,
+            AVERAGE_TIME.saturating_sub(started.elapsed()).as_secs() + 1
+        ));
+    }
+    calibration.measure(center, sample_rate)
+}
+
+/// What the command line calibration sets the receiver to
+pub struct Settings {
+    /// Carrier frequency in Hz
+    pub carrier: u32,
+    pub sample_rate: u32,
+    /// Tenths of dB, or None for automatic gain
+    pub gain: Option<i32>,
+    /// Correction in use while measuring
+    pub ppm: i32,
+}
+
+/// `--calibrate`: open a receiver with `open` and measure the carrier
+pub fn run(
+    open: impl FnOnce() -> Result<Box<dyn SdrSource>>,
+    settings: &Settings,
+) -> Result<Measurement> {
+    let mut source = open()?;
+    let center = source
+        .capabilities()
+        .clamp_frequency(settings.carrier.saturating_add_signed(TUNE_OFFSET));
+    source.set_ppm(settings.ppm)?;
+    source.set_frequency(center)?;
+    source.set_sample_rate(settings.sample_rate)?;
+    source.set_gain(settings.gain)?;
+
+    let (samples_tx, samples_rx) = channel::bounded(256);
+    let (recorder_tx, _recorder_rx) = channel::bounded(1);
+    let shutdown = Arc::new(AtomicBool::new(false));
+    // As in the self-test, the streaming thread ends with the process
+    let _ = source.start(SampleSink::new(
+        AppState::new_shared(),
+        samples_tx,
+        recorder_tx,
+        shutdown.clone(),
+    ))?;
+
+    let wanted = (settings.sample_rate as f64 * AVERAGE_TIME.as_secs_f64()) as usize;
+    let settle = (settings.sample_rate as f64 * SETTLE.as_secs_f64()) as usize;
+    let timeout = AVERAGE_TIME + SETTLE + CHANGE_TIMEOUT * 2;
+    let result = average_samples(&samples_rx, settings.carrier, settle, wanted, timeout);
+    shutdown.store(true, Ordering::Relaxed);
+    result?.measure(center, settings.sample_rate)
+}
+
+/// Average the spectrum of `wanted` samples from `samples_rx` after
+/// skipping `settle` of them
+fn average_samples(
+    samples_rx: &channel::Receiver<SampleBuffer>,
+    carrier: u32,
+    settle: usize,
+    wanted: usize,
+    timeout: Duration,
+) -> Result<Calibration> {
+    let mut fft = FftProcessor::new(FFT_SIZE);
+    let mut calibration = Calibration::new(carrier);
+    let mut pending: Vec<Complex<f32>> = Vec::with_capacity(FFT_SIZE * 2);
+    let (mut skipped, mut used) = (0, 0);
+    let started = Instant::now();
+    while used < wanted {
+        let left = timeout.saturating_sub(started.elapsed());
+        let Ok(buffer) = samples_rx.recv_timeout(left) else {
+            bail!("only {} of {} samples arrived", used, wanted);
+        };
+        let skip = settle.saturating_sub(skipped).min(buffer.len());
+        skipped += skip;
+        pending.extend_from_slice(&buffer.samples[skip..]);
+        for frame in pending.chunks_exact(FFT_SIZE) {
+            calibration.add_frame(&fft.process(frame));
+            used += FFT_SIZE;
+        }
+        let whole = pending.len() / FFT_SIZE * FFT_SIZE;
+        pending.drain(..whole);
+    }
+    Ok(calibration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `len` samples of a tone at `freq` Hz over a little noise
+    fn tone(freq: f64, rate: u32, len: usize) -> Vec<Complex<f32>> {
+        let mut seed = 1u32;
+        let mut noise = move || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+        };
+        (0..len)
+            .map(|n| {
+                let phase = 2.0 * std::f64::consts::PI * freq * n as f64 / rate as f64;
+                Complex::new(
+                    0.5 * phase.cos() as f32 + 0.01 * noise(),
+                    0.5 * phase.sin() as f32 + 0.01 * noise(),
+                )
+            })
+            .collect()
+    }
+
+    fn calibration(carrier: u32, samples: &[Complex<f32>]) -> Calibration {
+        let mut fft = FftProcessor::new(FFT_SIZE);
+        let mut calibration = Calibration::new(carrier);
+        for frame in samples.chunks_exact(FFT_SIZE) {
+            calibration.add_frame(&fft.process(frame));
+        }
+        calibration
+    }
+
+    #[test]
+    fn test_parabolic_offset() {
+        // Exact for a parabola
+        let parabola = |x: f32| 10.0 - (x - 0.3).powi(2);
+        let offset = parabolic_offset(parabola(-1.0), parabola(0.0), parabola(1.0));
+        assert!((offset - 0.3).abs() < 1e-5);
+        let parabola = |x: f32| -2.0 * (x + 0.45).powi(2);
+        let offset = parabolic_offset(parabola(-1.0), parabola(0.0), parabola(1.0));
+        assert!((offset + 0.45).abs() < 1e-5);
+
+        assert_eq!(parabolic_offset(-3.0, 0.0, -3.0), 0.0);
+        // Not a peak
+        assert_eq!(parabolic_offset(1.0, 1.0, 1.0), 0.0);
+        assert_eq!(parabolic_offset(2.0, 0.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn test_ppm_error() {
+        // 162.55 MHz showing 100 Hz low: the crystal runs fast
+        let ppm = ppm_error(162_550_000, 162_549_900.0);
+        assert!((ppm - 0.615).abs() < 1e-3);
+        assert!((ppm_error(100_000_000, 100_000_500.0) + 5.0).abs() < 1e-9);
+        assert_eq!(ppm_error(100_000_000, 100_000_000.0), 0.0);
+
+        let measurement = Measurement {
+            expected: 100_000_000,
+            measured: 99_999_420.0,
+            snr: 30.0,
+        };
+        assert_eq!(measurement.offset(), -580.0);
+        assert!((measurement.error_ppm() - 5.8).abs() < 1e-9);
+        // The error is on top of the correction already in use
+        assert_eq!(measurement.corrected(0), 6);
+        assert_eq!(measurement.corrected(-2), 4);
+    }
+
+    #[test]
+    fn test_measure_finds_carrier_between_bins() {
+        let rate = 2_048_000;
+        let carrier = 162_550_000;
+        let center = carrier + TUNE_OFFSET as u32;
+        // 1 kHz bins; the carrier shows 380 Hz low, 0.38 of a bin off
+        let shown = -TUNE_OFFSET as f64 - 380.0;
+        let calibration = calibration(carrier, &tone(shown, rate, FFT_SIZE * 8));
+        assert_eq!(calibration.frames, 8);
+
+        let measurement = calibration.measure(center, rate).unwrap();
+        assert!(
+            (measurement.offset() + 380.0).abs() < 25.0,
+            "{:?}",
+            measurement
+        );
+        assert!(measurement.snr > 30.0);
+        assert_eq!(measurement.corrected(0), 2);
+    }
+
+    #[test]
+    fn test_measure_failures() {
+        let rate = 2_048_000;
+        let carrier = 162_550_000;
+        let center = carrier + TUNE_OFFSET as u32;
+
+        assert!(Calibration::new(carrier).measure(center, rate).is_err());
+
+        // Noise alone
+        let quiet: Vec<Complex<f32>> = tone(0.0, rate, FFT_SIZE * 4)
+            .iter()
+            .map(|s| s - Complex::new(0.5, 0.0))
+            .collect();
+        let error = calibration(carrier, &quiet)
+            .measure(center, rate)
+            .unwrap_err();
+        assert!(error.to_string().starts_with("no carrier near 162.550 MHz"));
+
+        // Out of view
+        let tuned = calibration(carrier, &tone(-TUNE_OFFSET as f64, rate, FFT_SIZE * 4));
+        let error = tuned.measure(center + 2_000_000, rate).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "162.550 MHz is outside the received band"
+        );
+    }
+}
@@ -0,0 +1,545 @@
+//! Control socket for one-shot commands from the command line
+//!
+//! `rtl-sdr-tui remote set-freq 162.55M` connects to a running instance,
+//! sends a request as one line of JSON and prints the one-line response:
+//!
+//! ```text
+//! {"command":"set_frequency","frequency":162550000}
+//! {"ok":true,"message":"Tuned to 162.5500 MHz"}
+//! ```
+//!
+//! The socket only listens on localhost, since whoever reaches it can retune
+//! the receiver. Requests go to the selected receiver unless they name one
+//! with `"receiver"`, and a connection may send any number of them, one per
+//! line. Frequencies may be given in Hz or as text such as `"162.55M"`.
+
+use crate::http::Status;
+use crate::state::{Receiver, Receivers};
+use crate::types::config::GainSetting;
+use crate::types::{Chain, Command, DemodMode};
+use crate::util::net::accept_loop;
+use crate::util::units::parse_frequency;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How long a client may sit between requests before it is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long the command line waits for a response
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest request line read
+const MAX_REQUEST: u64 = 4096;
+
+/// A request, for the receiver numbered `receiver` or the selected one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receiver: Option<usize>,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// What a request asks for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Action {
+    /// Tune chain A
+    SetFrequency {
+        frequency: Frequency,
+    },
+    /// Switch chain A's mode, by name or file tag, e.g. "NFM"
+    SetMode {
+        mode: String,
+    },
+    /// Set the tuner gain in dB, or "auto"
+    SetGain {
+        gain: GainSetting,
+    },
+    Record {
+        action: RecordAction,
+    },
+    Status,
+}
+
+/// A frequency in Hz, or as text for [`parse_frequency`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Frequency {
+    Hz(u32),
+    Text(String),
+}
+
+impl Frequency {
+    fn hz(&self) -> Result<u32, String> {
+        match self {
+            Frequency::Hz(hz) => Ok(*hz),
+            Frequency::Text(text) => parse_frequency(text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordAction {
+    Start,
+    Stop,
+}
+
+impl std::str::FromStr for RecordAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "start" => Ok(RecordAction::Start),
+            "stop" => Ok(RecordAction::Stop),
+            _ => Err(format!("'{}' is neither start nor stop", s)),
+        }
+    }
+}
+
+/// A gain as given on the command line: dB or "auto"
+pub fn parse_gain(text: &str) -> Result<GainSetting, String> {
+    if text.eq_ignore_ascii_case("auto") {
+        return Ok(GainSetting::Word("auto".to_string()));
+    }
+    text.parse()
+        .map(GainSetting::Db)
+        .map_err(|_| format!("'{}' is neither a gain in dB nor \"auto\"", text))
+}
+
+/// The answer to a request: a message, or the status asked for, when it
+/// was carried out, and why not when it wasn't
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<serde_json::Value>,
+}
+
+impl Response {
+    fn done(message: impl Into<String>) -> Self {
+        Self {
+            ok: true,
+            message: Some(message.into()),
+            ..Self::default()
+        }
+    }
+
+    fn refused(error: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(error.into()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Carry out `request` on `receivers`
+pub fn execute(request: Request, receivers: &Receivers) -> Response {
+    let index = request.receiver.unwrap_or_else(|| receivers.selected());
+    let Some(receiver) = receivers.get(index) else {
+        return Response::refused(format!(
+            "no receiver {}; there are {}",
+            index,
+            receivers.len()
+        ));
+    };
+    match perform(request.action, index, receiver) {
+        Ok(response) => response,
+        Err(error) => Response::refused(error),
+    }
+}
+
+fn perform(action: Action, index: usize, receiver: &Receiver) -> Result<Response, String> {
+    let send = |command: Command| {
+        receiver
+            .commands
+            .send(command)
+            .map_err(|_| format!("receiver {} has stopped", index))
+    };
+    match action {
+        Action::SetFrequency { frequency } => {
+            let frequency = frequency.hz()?;
+            send(Command::SetFrequency(frequency))?;
+            send(Command::SetChannelOffset(Chain::A, 0))?;
+            Ok(Response::done(format!(
+                "Tuned to {:.4} MHz",
+                frequency as f64 / 1_000_000.0
+            )))
+        }
+        Action::SetMode { mode } => {
+            let mode: DemodMode = mode.parse()?;
+            send(Command::SetMode(mode))?;
+            Ok(Response::done(format!("Mode {}", mode.name())))
+        }
+        Action::SetGain { gain } => match gain {
+            GainSetting::Word(word) if word.eq_ignore_ascii_case("auto") => {
+                send(Command::SetTunerAgc(true))?;
+                Ok(Response::done("Gain auto"))
+            }
+            GainSetting::Db(db) => {
+                // Within the receiver's range, on the nearest step its tuner
+                // has, as the gain keys set it
+                let capabilities = receiver.state.read().sdr.capabilities.clone();
+                let (min, max) = (*capabilities.gain.start(), *capabilities.gain.end());
+                let tenths = (db * 10.0).round();
+                if !(min as f64..=max as f64).contains(&tenths) {
+                    return Err(format!(
+                        "gain {} dB is out of range {:.1}-{:.1} dB",
+                        db,
+                        min as f64 / 10.0,
+                        max as f64 / 10.0
+                    ));
+                }
+                let gain = capabilities.nearest_gain(tenths as i32);
+                send(Command::SetTunerGain(gain))?;
+                Ok(Response::done(format!("Gain {:.1} dB", gain as f64 / 10.0)))
+            }
+            GainSetting::Word(word) => Err(format!(
+                "gain '{}' is neither a number of dB nor \"auto\"",
+                word
+            )),
+        },
+        Action::Record { action } => {
+            // The recorder marks a recording started only once it has opened
+            // it, so a stop just after a start must go through regardless;
+            // stopping when idle does nothing
+            let command = {
+                let state = receiver.state.read();
+                match action {
+                    RecordAction::Start if state.recording.is_recording => {
+                        return Ok(Response::done("Already recording"))
+                    }
+                    RecordAction::Start => state.start_recording_command(),
+                    RecordAction::Stop => Command::StopRecording,
+                }
+            };
+            let message = match &command {
+                Command::StartRecording(path) => format!("Recording to {}", path.display()),
+                Command::StartSquelchRecording => "Squelch recording started".to_string(),
+                _ => "Recording stopped".to_string(),
+            };
+            send(command)?;
+            Ok(Response::done(message))
+        }
+        Action::Status => {
+            let status = Status::take(index, &receiver.state.read(), chrono::Utc::now());
+            Ok(Response {
+                ok: true,
+                status: serde_json::to_value(status).ok(),
+                ..Response::default()
+            })
+        }
+    }
+}
+
+/// Answer requests on `stream` until the client closes it or goes quiet
+fn handle(stream: TcpStream, receivers: &Receivers) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if (&mut reader).take(MAX_REQUEST).read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => execute(request, receivers),
+            Err(e) => Response::refused(format!("bad request: {}", e)),
+        };
+        let mut reply = serde_json::to_string(&response).unwrap_or_default();
+        reply.push('\n');
+        writer.write_all(reply.as_bytes())?;
+    }
+}
+
+/// Serve control requests on `listener` until `shutdown` is set
+pub fn serve(listener: TcpListener, receivers: Receivers, shutdown: Arc<AtomicBool>) {
+    accept_loop(listener, "Control socket", &shutdown, |stream| {
+        handle(stream, &receivers)
+    });
+}
+
+/// Start the control socket on `listener`, which should be on localhost
+pub fn start_control_server(
+    listener: TcpListener,
+    receivers: Receivers,
+    shutdown: Arc<AtomicBool>,
+) -> Result<()> {
+    log::info!("Control socket listening on {}", listener.local_addr()?);
+    thread::spawn(move || serve(listener, receivers, shutdown));
+    Ok(())
+}
+
+/// Send `request` to the instance at `host`:`port` and wait for its
+/// response
+pub fn send(host: &str, port: u16, request: &Request) -> Result<Response> {
+    let mut stream = TcpStream::connect((host, port)).with_context(|| {
+        format!(
+            "can't reach rtl-sdr-tui on {}:{}; is it running with --control-port or \
+             [control] enabled?",
+            host, port
+        )
+    })?;
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .context("no response")?;
+    if reply.trim().is_empty() {
+        bail!("the connection closed without a response");
+    }
+    serde_json::from_str(&reply).with_context(|| format!("bad response: {}", reply.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AppState, RecordingMode};
+    use crossbeam::channel::{self, Receiver as ChannelReceiver};
+    use std::sync::atomic::Ordering;
+
+    fn receivers(count: usize) -> (Receivers, Vec<ChannelReceiver<Command>>) {
+        let (receivers, commands) = (0..count)
+            .map(|_| {
+                let (tx, rx) = channel::unbounded();
+                (
+                    Receiver {
+                        state: AppState::new_shared(),
+                        commands: tx,
+                    },
+                    rx,
+                )
+            })
+            .unzip();
+        (Receivers::new(receivers), commands)
+    }
+
+    /// Start a server on a free port, returning the port
+    fn server(receivers: Receivers, shutdown: &Arc<AtomicBool>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let shutdown = shutdown.clone();
+        thread::spawn(move || serve(listener, receivers, shutdown));
+        port
+    }
+
+    fn request(action: Action) -> Request {
+        Request {
+            receiver: None,
+            action,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (receivers, commands) = receivers(2);
+        receivers.get(1).unwrap().state.write().sdr.frequency = 1_090_000_000;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let port = server(receivers, &shutdown);
+
+        let response = send(
+            "127.0.0.1",
+            port,
+            &request(Action::SetFrequency {
+                frequency: Frequency::Hz(162_550_000),
+            }),
+        )
+        .unwrap();
+        assert!(response.ok);
+        assert_eq!(response.message.as_deref(), Some("Tuned to 162.5500 MHz"));
+        assert_eq!(
+            commands[0].try_recv().unwrap(),
+            Command::SetFrequency(162_550_000)
+        );
+        assert_eq!(
+            commands[0].try_recv().unwrap(),
+            Command::SetChannelOffset(Chain::A, 0)
+        );
+
+        let response = send(
+            "127.0.0.1",
+            port,
+            &request(Action::SetMode {
+                mode: "am".to_string(),
+            }),
+        )
+        .unwrap();
+        assert_eq!(response.message.as_deref(), Some("Mode AM"));
+        assert_eq!(
+            commands[0].try_recv().unwrap(),
+            Command::SetMode(DemodMode::Am)
+        );
+
+        // The status of another receiver, and nothing sent to it
+        let response = send(
+            "127.0.0.1",
+            port,
+            &Request {
+                receiver: Some(1),
+                action: Action::Status,
+            },
+        )
+        .unwrap();
+        let status = response.status.unwrap();
+        assert_eq!(status["receiver"], 1);
+        assert_eq!(status["frequency"], 1_090_000_000);
+        assert!(commands[1].try_recv().is_err());
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_refusals() {
+        let (receivers, commands) = receivers(1);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let port = server(receivers, &shutdown);
+
+        for (action, error) in [
+            (
+                Action::SetFrequency {
+                    frequency: Frequency::Text("abc".into()),
+                },
+                "'abc' is not a frequency, e.g. 162.55M or 162550000",
+            ),
+            (Action::SetMode { mode: "xyz".into() }, "unknown mode 'xyz'"),
+            (
+                Action::SetGain {
+                    gain: GainSetting::Db(99.0),
+                },
+                "gain 99 dB is out of range 0.0-50.0 dB",
+            ),
+        ] {
+            let response = send("127.0.0.1", port, &request(action)).unwrap();
+            assert!(!response.ok);
+            assert_eq!(response.error.as_deref(), Some(error));
+        }
+        let response = send(
+            "127.0.0.1",
+            port,
+            &Request {
+                receiver: Some(3),
+                action: Action::Status,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            response.error.as_deref(),
+            Some("no receiver 3; there are 1")
+        );
+        assert!(commands[0].try_recv().is_err());
+
+        // Nothing listening
+        shutdown.store(true, Ordering::Relaxed);
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = closed.local_addr().unwrap().port();
+        drop(closed);
+        assert!(send("127.0.0.1", port, &request(Action::Status)).is_err());
+    }
+
+    #[test]
+    fn test_hand_written_requests() {
+        let (receivers, commands) = receivers(1);
+        receivers.current().state.write().recording.mode = RecordingMode::Squelch;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let port = server(receivers, &shutdown);
+
+        // Several requests on one connection, as typed into nc
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .write_all(
+                b"{\"command\":\"set_frequency\",\"frequency\":\"144.39M\"}\n\
+                  {\"command\":\"set_gain\",\"gain\":\"auto\"}\n\
+                  {\"command\":\"set_gain\",\"gain\":29.7}\n\
+                  {\"command\":\"set_gain\",\"gain\":30}\n\
+                  {\"command\":\"record\",\"action\":\"start\"}\n\
+                  {\"command\":\"record\",\"action\":\"stop\"}\n\
+                  not json\n",
+            )
+            .unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let mut replies = String::new();
+        stream.read_to_string(&mut replies).unwrap();
+        let replies: Vec<Response> = replies
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let messages: Vec<_> = replies.iter().map(|r| r.message.as_deref()).collect();
+        assert_eq!(
+            messages,
+            [
+                Some("Tuned to 144.3900 MHz"),
+                Some("Gain auto"),
+                Some("Gain 29.7 dB"),
+                Some("Gain 29.7 dB"),
+                Some("Squelch recording started"),
+                Some("Recording stopped"),
+                None,
+            ]
+        );
+        assert!(replies[6]
+            .error
+            .as_deref()
+            .unwrap()
+            .starts_with("bad request"));
+
+        let sent: Vec<Command> = commands[0].try_iter().collect();
+        assert_eq!(
+            sent,
+            [
+                Command::SetFrequency(144_390_000),
+                Command::SetChannelOffset(Chain::A, 0),
+                Command::SetTunerAgc(true),
+                Command::SetTunerGain(297),
+                Command::SetTunerGain(297),
+                Command::StartSquelchRecording,
+                Command::StopRecording,
+            ]
+        );
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_request_json() {
+        let request = Request {
+            receiver: None,
+            action: Action::SetGain {
+                gain: parse_gain("40.2").unwrap(),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"command":"set_gain","gain":40.2}"#
+        );
+        let request: Request =
+            serde_json::from_str(r#"{"receiver":1,"command":"record","action":"stop"}"#).unwrap();
+        assert_eq!(request.receiver, Some(1));
+        assert_eq!(
+            request.action,
+            Action::Record {
+                action: RecordAction::Stop
+            }
+        );
+        assert_eq!(parse_gain("Auto"), Ok(GainSetting::Word("auto".into())));
+        assert!(parse_gain("loud").is_err());
+        assert_eq!("STOP".parse(), Ok(RecordAction::Stop));
+    }
+}
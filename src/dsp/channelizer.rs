@@ -0,0 +1,482 @@
+//! Channel selection within the captured bandwidth
+//!
+//! Each receive chain owns a [`Channelizer`] that mixes its channel down to
+//! 0 Hz with a numerically controlled oscillator and low-pass filters it to
+//! the mode's bandwidth, so two chains can demodulate different signals
+//! from the same IQ buffer. The oscillator is a phasor turned by a fixed
+//! step each sample, and the mix and filters work in one output buffer kept
+//! per chain rather than allocated every block.
+//!
+//! A chain that only feeds decoders can have its channel decimated too:
+//! once filtered it needs only a few times its bandwidth, so everything
+//! after the channelizer runs at a fraction of the capture's rate. The chain
+//! that is heard is decimated less, to [`listening_rate`], which leaves room
+//! for the widest filter set and divides into the capture's rate so its
+//! audio can be brought to the audio rate exactly.
+//!
+//! The filter width can be changed at runtime within limits per mode; the
+//! old filter is faded out over a few milliseconds so the change doesn't
+//! click.
+
+use super::filters::{audio_passband, FilterChain};
+use crate::audio::latency::AUDIO_RATE;
+use crate::types::DemodMode;
+use num_complex::Complex;
+use std::f64::consts::TAU;
+
+/// Half the channel bandwidth kept for a mode in Hz; None keeps everything
+pub fn channel_cutoff(mode: DemodMode) -> Option<f32> {
+    match mode {
+        DemodMode::FmNarrow | DemodMode::Aprs | DemodMode::Acars => Some(8_000.0),
+        DemodMode::FmWide => Some(100_000.0),
+        DemodMode::Am => Some(5_000.0),
+        DemodMode::Usb | DemodMode::Lsb | DemodMode::Cw => Some(3_000.0),
+        DemodMode::Ais => Some(50_000.0),
+        DemodMode::Ism => Some(100_000.0),
+        // About 34 kHz deviation plus Doppler
+        DemodMode::Apt => Some(20_000.0),
+        // Sondes drift a few kHz off their nominal frequency
+        DemodMode::Rs41 => Some(10_000.0),
+        DemodMode::Adsb | DemodMode::Raw => None,
+    }
+}
+
+/// How wide the channel filter can be set for a mode, in Hz
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WidthLimits {
+    pub min: u32,
+    pub max: u32,
+    /// Change per key press
+    pub step: u32,
+}
+
+/// Filter width limits for a mode, None where the width is fixed (data
+/// decoders expect theirs) or there is no filter
+pub fn width_limits(mode: DemodMode) -> Option<WidthLimits> {
+    let (min, max, step) = match mode {
+        // Covers 12.5 and 25 kHz channel spacing
+        DemodMode::FmNarrow => (6_000, 30_000, 1_000),
+        DemodMode::FmWide => (100_000, 250_000, 10_000),
+        DemodMode::Am => (2_000, 16_000, 1_000),
+        // One sideband's width; the audio passband stops at 2.7 kHz
+        DemodMode::Usb | DemodMode::Lsb => (1_000, 3_000, 100),
+        DemodMode::Cw => (200, 3_000, 100),
+        _ => return None,
+    };
+    Some(WidthLimits { min, max, step })
+}
+
+/// Whether a mode hears one side of its carrier, so its width is that of
+/// the one sideband rather than both
+fn one_sided(mode: DemodMode) -> bool {
+    matches!(mode, DemodMode::Usb | DemodMode::Lsb | DemodMode::Cw)
+}
+
+/// Channel filter width for a mode in Hz by default: both sides of the
+/// carrier, or the one a sideband mode hears
+pub fn default_width(mode: DemodMode) -> Option<u32> {
+    let cutoff = channel_cutoff(mode)? as u32;
+    Some(if one_sided(mode) { cutoff } else { 2 * cutoff })
+}
+
+/// `width` within the mode's limits, None if it can't be changed
+pub fn clamp_width(mode: DemodMode, width: u32) -> Option<u32> {
+    width_limits(mode).map(|limits| width.clamp(limits.min, limits.max))
+}
+
+/// One step wider or narrower than `width` (the default if None), None if
+/// the mode's width can't be changed
+pub fn step_width(mode: DemodMode, width: Option<u32>, wider: bool) -> Option<u32> {
+    let limits = width_limits(mode)?;
+    let current = width.or(default_width(mode))?;
+    let next = if wider {
+        current + limits.step
+    } else {
+        current.saturating_sub(limits.step)
+    };
+    Some(next.clamp(limits.min, limits.max))
+}
+
+/// The span a mode demodulates as (low, high) offsets from its carrier in
+/// Hz, with the filter `width` if set; sideband modes hear only their side,
+/// from the audio passband
+pub fn passband(mode: DemodMode, width: Option<u32>) -> Option<(i32, i32)> {
+    let cutoff = cutoff(mode, width)? as i32;
+    let (low_cut, high_cut) = audio_passband(mode);
+    let sideband = (
+        low_cut.map_or(0, |edge| edge as i32),
+        high_cut.map_or(cutoff, |edge| (edge as i32).min(cutoff)),
+    );
+    Some(match mode {
+        DemodMode::Usb | DemodMode::Cw => sideband,
+        DemodMode::Lsb => (-sideband.1, -sideband.0),
+        _ => (-cutoff, cutoff),
+    })
+}
+
+/// Filter cutoff for `mode` in Hz: half of `width` (all of it for a
+/// sideband mode), or the mode's default
+fn cutoff(mode: DemodMode, width: Option<u32>) -> Option<f32> {
+    match width {
+        Some(width) if channel_cutoff(mode).is_some() && one_sided(mode) => Some(width as f32),
+        Some(width) if channel_cutoff(mode).is_some() => Some(width as f32 / 2.0),
+        _ => channel_cutoff(mode),
+    }
+}
+
+/// Time taken to fade from the old filter to the new one, in seconds
+const CROSSFADE_SECS: f64 = 0.005;
+
+/// The filters being replaced, faded out over the first samples after a
+/// width change
+struct Crossfade {
+    filter_i: FilterChain,
+    filter_q: FilterChain,
+    length: usize,
+    remaining: usize,
+}
+
+impl Crossfade {
+    /// `filtered` (the new filters' output for `sample`) mixed from the old
+    /// filters' output
+    fn blend(&mut self, sample: Complex<f32>, filtered: Complex<f32>) -> Complex<f32> {
+        let new = (self.length - self.remaining) as f32 / self.length as f32;
+        let old = Complex::new(self.filter_i.step(sample.re), self.filter_q.step(sample.im));
+        self.remaining = self.remaining.saturating_sub(1);
+        old * (1.0 - new) + filtered * new
+    }
+}
+
+/// How far above the channel's cutoff a decimated channel's rate is kept,
+/// so the filter's skirt has room before it folds back
+const DECIMATION_MARGIN: f32 = 4.0;
+
+/// One sample in how many a decimating channelizer keeps for a mode
+fn decimation(mode: DemodMode, sample_rate: u32) -> usize {
+    // The mode's own cutoff, not the width set, so the decoders downstream
+    // keep their rate
+    channel_cutoff(mode).map_or(1, |cutoff| {
+        ((sample_rate as f32 / (DECIMATION_MARGIN * cutoff)) as usize).max(1)
+    })
+}
+
+/// Rate in Hz of a decimating channelizer's output for a mode
+pub fn decimated_rate(mode: DemodMode, sample_rate: u32) -> u32 {
+    sample_rate / decimation(mode, sample_rate) as u32
+}
+
+/// One sample in how many a listening channelizer keeps for a mode: the
+/// most that divides `sample_rate` and keeps the rate clear of the widest
+/// filter, and no lower than the audio rate
+fn listening_decimation(mode: DemodMode, sample_rate: u32) -> usize {
+    let widest = width_limits(mode).map(|limits| limits.max);
+    let Some(cutoff) = cutoff(mode, widest) else {
+        return 1;
+    };
+    let lowest = (DECIMATION_MARGIN * cutoff).max(AUDIO_RATE as f32);
+    let most = (sample_rate as f32 / lowest) as u32;
+    (1..=most)
+        .rev()
+        .find(|&factor| sample_rate.is_multiple_of(factor))
+        .unwrap_or(1) as usize
+}
+
+/// Rate in Hz of a listening channelizer's output for a mode, which its
+/// audio is made at
+pub fn listening_rate(mode: DemodMode, sample_rate: u32) -> u32 {
+    sample_rate / listening_decimation(mode, sample_rate) as u32
+}
+
+/// Shifts one channel to baseband and filters it to the mode's bandwidth
+pub struct Channelizer {
+    mode: DemodMode,
+    /// Filter width set, None for the mode's default
+    width: Option<u32>,
+    sample_rate: u32,
+    /// Samples kept for a mode at a rate, one in how many
+    decimation: fn(DemodMode, u32) -> usize,
+    /// Samples kept, one in this many
+    factor: usize,
+    /// Input samples until the next one kept, across blocks
+    skip: usize,
+    /// Oscillator phase, kept across blocks
+    phasor: Complex<f64>,
+    filter_i: FilterChain,
+    filter_q: FilterChain,
+    crossfade: Option<Crossfade>,
+    output: Vec<Complex<f32>>,
+}
+
+impl Channelizer {
+    /// A channelizer whose output stays at the input's rate
+    pub fn new() -> Self {
+        Self {
+            mode: DemodMode::Raw,
+            width: None,
+            sample_rate: 0,
+            decimation: |_, _| 1,
+            factor: 1,
+            skip: 0,
+            phasor: Complex::new(1.0, 0.0),
+            filter_i: FilterChain::default(),
+            filter_q: FilterChain::default(),
+            crossfade: None,
+            output: Vec::new(),
+        }
+    }
+
+    /// A channelizer that also decimates its output, as far as the channel
+    /// allows; to [`decimated_rate`]
+    pub fn decimating() -> Self {
+        Self {
+            decimation,
+            ..Self::new()
+        }
+    }
+
+    /// A channelizer that decimates its output as far as listening allows;
+    /// to [`listening_rate`]
+    pub fn listening() -> Self {
+        Self {
+            decimation: listening_decimation,
+            ..Self::new()
+        }
+    }
+
+    /// The channel `offset` Hz from the center of `samples`, at baseband,
+    /// filtered to `width` Hz (None for the mode's default)
+    ///
+    /// The filters are rebuilt when the mode, width or rate changes, fading
+    /// over from the old ones for a new width; the oscillator keeps its
+    /// phase so retuning the offset doesn't click.
+    pub fn process(
+        &mut self,
+        offset: i32,
+        mode: DemodMode,
+        width: Option<u32>,
+        sample_rate: u32,
+        samples: &[Complex<f32>],
+    ) -> &[Complex<f32>] {
+        if mode != self.mode || width != self.width || sample_rate != self.sample_rate {
+            let cutoff = cutoff(mode, width);
+            let filter_i = FilterChain::band(sample_rate, None, cutoff);
+            let filter_q = FilterChain::band(sample_rate, None, cutoff);
+            let old_i = std::mem::replace(&mut self.filter_i, filter_i);
+            let old_q = std::mem::replace(&mut self.filter_q, filter_q);
+            self.crossfade = (mode == self.mode && sample_rate == self.sample_rate).then(|| {
+                let length = ((sample_rate as f64 * CROSSFADE_SECS) as usize).max(1);
+                Crossfade {
+                    filter_i: old_i,
+                    filter_q: old_q,
+                    length,
+                    remaining: length,
+                }
+            });
+            self.factor = (self.decimation)(mode, sample_rate);
+            self.skip = 0;
+            self.mode = mode;
+            self.width = width;
+            self.sample_rate = sample_rate;
+        }
+
+        self.output.clear();
+        self.output.extend_from_slice(samples);
+        if offset != 0 {
+            let turn = -TAU * offset as f64 / sample_rate.max(1) as f64;
+            let step = Complex::from_polar(1.0, turn);
+            for sample in self.output.iter_mut() {
+                *sample *= Complex::new(self.phasor.re as f32, self.phasor.im as f32);
+                self.phasor *= step;
+            }
+            // Rounding would otherwise grow or shrink it
+            self.phasor /= self.phasor.norm();
+        }
+
+        // Filtered at the full rate, keeping every `factor`th sample
+        let mut kept = 0;
+        for i in 0..self.output.len() {
+            let sample = self.output[i];
+            let mut filtered =
+                Complex::new(self.filter_i.step(sample.re), self.filter_q.step(sample.im));
+            if let Some(crossfade) = &mut self.crossfade {
+                filtered = crossfade.blend(sample, filtered);
+                if crossfade.remaining == 0 {
+                    self.crossfade = None;
+                }
+            }
+            if self.skip == 0 {
+                self.output[kept] = filtered;
+                kept += 1;
+                self.skip = self.factor;
+            }
+            self.skip -= 1;
+        }
+        self.output.truncate(kept);
+
+        &self.output
+    }
+}
+
+impl Default for Channelizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 240_000;
+
+    fn carrier(freq: f32, amplitude: f32, len: usize) -> Vec<Complex<f32>> {
+        (0..len)
+            .map(|i| Complex::from_polar(amplitude, TAU as f32 * freq * i as f32 / RATE as f32))
+            .collect()
+    }
+
+    fn mean_power(samples: &[Complex<f32>]) -> f32 {
+        samples.iter().map(|s| s.norm_sqr()).sum::<f32>() / samples.len() as f32
+    }
+
+    #[test]
+    fn test_selects_channel_at_offset() {
+        let wanted = carrier(50_000.0, 0.5, 24_000);
+        let other = carrier(-60_000.0, 0.5, 24_000);
+        let mixed: Vec<Complex<f32>> = wanted.iter().zip(&other).map(|(a, b)| a + b).collect();
+
+        let mut channelizer = Channelizer::new();
+        // In blocks, so the oscillator and filters carry across them
+        let mut output = Vec::new();
+        for block in mixed.chunks(4096) {
+            let channel = channelizer.process(50_000, DemodMode::FmNarrow, None, RATE, block);
+            output.extend_from_slice(channel);
+        }
+        assert_eq!(output.len(), mixed.len());
+
+        // The wanted carrier lands at 0 Hz: steady phase, full power
+        let settled = &output[4000..];
+        assert!((mean_power(settled) - 0.25).abs() < 0.02, "power {}", mean_power(settled));
+        let drift = (settled[1000] * settled[0].conj()).arg();
+        assert!(drift.abs() < 0.05, "phase drift {}", drift);
+
+        // Tuned to nothing, both carriers are filtered out
+        let mut channelizer = Channelizer::new();
+        let empty = channelizer.process(-5_000, DemodMode::FmNarrow, None, RATE, &mixed);
+        assert!(mean_power(&empty[4000..]) < 1e-4);
+    }
+
+    #[test]
+    fn test_decimated_channel() {
+        // NFM's ±8 kHz keeps 32 kHz of 240 kHz: one sample in seven
+        assert_eq!(decimated_rate(DemodMode::FmNarrow, RATE), 34_285);
+        assert_eq!(decimated_rate(DemodMode::Adsb, 2_000_000), 2_000_000);
+
+        let wanted = carrier(50_000.0, 0.5, 24_000);
+        let other = carrier(-60_000.0, 0.5, 24_000);
+        let mixed: Vec<Complex<f32>> = wanted.iter().zip(&other).map(|(a, b)| a + b).collect();
+        let mut channelizer = Channelizer::decimating();
+        // Blocks that don't divide by seven, so the count carries over
+        let mut output = Vec::new();
+        for block in mixed.chunks(1000) {
+            let channel = channelizer.process(50_000, DemodMode::FmNarrow, None, RATE, block);
+            output.extend_from_slice(channel);
+        }
+        assert_eq!(output.len(), mixed.len().div_ceil(7));
+
+        // Still the wanted carrier alone, at 0 Hz
+        let settled = &output[600..];
+        assert!((mean_power(settled) - 0.25).abs() < 0.02, "power {}", mean_power(settled));
+        let drift = (settled[140] * settled[0].conj()).arg();
+        assert!(drift.abs() < 0.05, "phase drift {}", drift);
+    }
+
+    #[test]
+    fn test_listening_rate() {
+        // Room for NFM's widest 30 kHz, and a rate that divides 1.024 MHz
+        assert_eq!(listening_rate(DemodMode::FmNarrow, 1_024_000), 64_000);
+        assert_eq!(listening_rate(DemodMode::FmNarrow, 2_400_000), 60_000);
+        // Never below the audio rate, however narrow
+        assert_eq!(listening_rate(DemodMode::Cw, 2_048_000), 51_200);
+        assert_eq!(listening_rate(DemodMode::Am, 1_024_000), 51_200);
+        assert_eq!(listening_rate(DemodMode::FmWide, 1_024_000), 512_000);
+        // Nothing to hear is left at the full rate
+        assert_eq!(listening_rate(DemodMode::Raw, 1_024_000), 1_024_000);
+    }
+
+    #[test]
+    fn test_passband() {
+        assert_eq!(passband(DemodMode::FmNarrow, None), Some((-8_000, 8_000)));
+        assert_eq!(passband(DemodMode::Am, None), Some((-5_000, 5_000)));
+        assert_eq!(passband(DemodMode::Am, Some(6_000)), Some((-3_000, 3_000)));
+        // Sidebands sit on their own side of the carrier
+        assert_eq!(passband(DemodMode::Usb, None), Some((300, 2_700)));
+        assert_eq!(passband(DemodMode::Lsb, None), Some((-2_700, -300)));
+        // Their widths are one-sided
+        assert_eq!(passband(DemodMode::Lsb, Some(2_000)), Some((-2_000, -300)));
+        assert_eq!(passband(DemodMode::Usb, Some(2_400)), Some((300, 2_400)));
+        assert_eq!(passband(DemodMode::Cw, None), Some((300, 1_200)));
+        assert_eq!(passband(DemodMode::Cw, Some(800)), Some((300, 800)));
+        assert_eq!(passband(DemodMode::Adsb, None), None);
+        assert_eq!(passband(DemodMode::Adsb, Some(10_000)), None);
+    }
+
+    #[test]
+    fn test_width_limits() {
+        assert_eq!(default_width(DemodMode::FmNarrow), Some(16_000));
+        assert_eq!(clamp_width(DemodMode::FmNarrow, 12_500), Some(12_500));
+        assert_eq!(clamp_width(DemodMode::FmNarrow, 1_000), Some(6_000));
+        assert_eq!(clamp_width(DemodMode::Am, 50_000), Some(16_000));
+        // Data decoders keep their filters
+        assert_eq!(clamp_width(DemodMode::Ais, 50_000), None);
+        assert_eq!(clamp_width(DemodMode::Raw, 50_000), None);
+
+        // Steps start from the default and stop at the limits
+        assert_eq!(step_width(DemodMode::Am, None, false), Some(9_000));
+        assert_eq!(default_width(DemodMode::Usb), Some(3_000));
+        assert_eq!(step_width(DemodMode::Usb, None, false), Some(2_900));
+        assert_eq!(step_width(DemodMode::Usb, Some(2_400), true), Some(2_500));
+        assert_eq!(step_width(DemodMode::Cw, Some(200), false), Some(200));
+        assert_eq!(step_width(DemodMode::FmWide, Some(250_000), true), Some(250_000));
+        assert_eq!(step_width(DemodMode::Aprs, None, true), None);
+    }
+
+    #[test]
+    fn test_width_change_rebuilds_filter() {
+        // 12 kHz away: inside the default ±8 kHz NFM filter after the
+        // offset, outside once narrowed to 6 kHz
+        let samples = carrier(12_000.0, 0.5, 24_000);
+        let mut channelizer = Channelizer::new();
+        let wide = channelizer.process(6_000, DemodMode::FmNarrow, None, RATE, &samples);
+        assert!(mean_power(&wide[4000..]) > 0.2);
+
+        let narrow = channelizer.process(6_000, DemodMode::FmNarrow, Some(6_000), RATE, &samples);
+        assert!(mean_power(&narrow[12_000..]) < 0.05, "power {}", mean_power(&narrow[12_000..]));
+    }
+
+    #[test]
+    fn test_width_change_is_click_free() {
+        // A steady carrier well inside both filters
+        let samples = carrier(500.0, 0.5, 4_800);
+        let mut channelizer = Channelizer::new();
+        channelizer.process(0, DemodMode::Am, None, RATE, &samples);
+
+        let output = channelizer.process(0, DemodMode::Am, Some(6_000), RATE, &samples).to_vec();
+        // No sample-to-sample jump bigger than the carrier's own movement
+        let largest_step = output
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).norm())
+            .fold(0.0, f32::max);
+        let carrier_step = (samples[1] - samples[0]).norm();
+        assert!(largest_step < carrier_step * 2.0, "step {} vs {}", largest_step, carrier_step);
+    }
+
+    #[test]
+    fn test_wideband_modes_unfiltered() {
+        assert_eq!(channel_cutoff(DemodMode::Raw), None);
+        let samples = carrier(100_000.0, 1.0, 1000);
+        let mut channelizer = Channelizer::new();
+        let output = channelizer.process(0, DemodMode::Adsb, None, RATE, &samples);
+        assert_eq!(output, samples.as_slice());
+    }
+}
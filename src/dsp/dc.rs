@@ -0,0 +1,338 @@
+//! Keeping the receiver's DC spike out of the audio
+//!
+//! Whatever IQ correction the tuner does, a residual spike sits at the
+//! hardware center, and tuning straight onto a signal puts it in the middle
+//! of the channel. It can be notched out: a complex notch a few Hz wide at
+//! 0 Hz, used while the spike is in chain A's passband and stands above the
+//! noise. Or the hardware can be tuned off to one side and the capture shifted
+//! back in software, so the displayed frequency stays where it was and the
+//! spike lands outside the channel. The shift wraps the capture's far edge
+//! round to the near one, where that sliver is blanked in the spectrum.
+//!
+//! With the hardware offset, `SdrState::frequency` is the frequency shown
+//! and the capture is centered on; the receiver and IQ recordings are at
+//! `SdrState::hardware_frequency()`.
+
+use super::channelizer;
+use crate::types::DemodMode;
+use num_complex::Complex;
+use std::f64::consts::TAU;
+use std::ops::{Range, RangeInclusive};
+
+/// Hardware offset used by default for [`DcAvoidance::Offset`], in Hz
+pub const DEFAULT_DC_OFFSET: u32 = 250_000;
+/// Hz beyond the passband edges within which the spike still counts as heard
+const DC_GUARD: i32 = 250;
+/// dB the DC bins must stand above their neighbors for the notch to be used
+const DC_PROMINENCE_DB: f32 = 10.0;
+/// Width of the notch in Hz
+const NOTCH_HZ: f64 = 20.0;
+
+/// How the DC spike is kept out of chain A
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DcAvoidance {
+    /// Notch out 0 Hz while the spike is in the passband
+    #[default]
+    Notch,
+    /// Tune the hardware off the frequency shown and shift back in software
+    Offset,
+    /// Leave the spike alone
+    Off,
+}
+
+impl DcAvoidance {
+    pub fn name(self) -> &'static str {
+        match self {
+            DcAvoidance::Notch => "notch",
+            DcAvoidance::Offset => "offset",
+            DcAvoidance::Off => "off",
+        }
+    }
+}
+
+impl std::str::FromStr for DcAvoidance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "notch" => Ok(DcAvoidance::Notch),
+            "offset" => Ok(DcAvoidance::Offset),
+            "off" => Ok(DcAvoidance::Off),
+            _ => Err(format!(
+                "unknown DC avoidance '{}' (expected notch, offset or off)",
+                s
+            )),
+        }
+    }
+}
+
+/// Whether the hardware center falls in the passband of a channel `offset`
+/// Hz from it, demodulated as `mode` with filter `width`
+pub fn dc_in_passband(offset: i32, mode: DemodMode, width: Option<u32>) -> bool {
+    // The center sits `-offset` from the channel's carrier
+    channelizer::passband(mode, width)
+        .is_some_and(|(low, high)| (low - DC_GUARD..=high + DC_GUARD).contains(&-offset))
+}
+
+/// Hz to tune the hardware above `frequency` so the spike lands `nudge` Hz
+/// from it, below if above is out of `tunable`; 0 if neither fits
+///
+/// The nudge is kept within a quarter of `sample_rate` so the channel stays
+/// well inside the capture.
+pub fn nudge(frequency: u32, nudge: u32, sample_rate: u32, tunable: &RangeInclusive<u32>) -> i32 {
+    let nudge = nudge.min(sample_rate / 4) as i32;
+    [nudge, -nudge]
+        .into_iter()
+        .find(|&offset| {
+            frequency
+                .checked_add_signed(offset)
+                .is_some_and(|hardware| tunable.contains(&hardware))
+        })
+        .unwrap_or(0)
+}
+
+/// Whether the spectrum's DC bins stand out from the bins around them
+pub fn dc_prominent(fft: &[f32]) -> bool {
+    let center = fft.len() / 2;
+    if center < 20 {
+        return false;
+    }
+    let spike = fft[center - 1..=center + 1]
+        .iter()
+        .copied()
+        .fold(f32::MIN, f32::max);
+    let mut around: Vec<f32> = fft[center - 20..center - 3]
+        .iter()
+        .chain(&fft[center + 4..center + 21])
+        .copied()
+        .collect();
+    around.sort_by(f32::total_cmp);
+    spike - around[around.len() / 2] > DC_PROMINENCE_DB
+}
+
+/// Shifts the whole capture in frequency, keeping its phase across buffers
+#[derive(Debug, Clone, Default)]
+pub struct FrequencyShift {
+    /// Oscillator phase in cycles
+    phase: f64,
+}
+
+impl FrequencyShift {
+    /// Move everything in `samples` up by `shift` Hz
+    pub fn process(&mut self, shift: i32, sample_rate: u32, samples: &mut [Complex<f32>]) {
+        if shift == 0 {
+            return;
+        }
+        let step = shift as f64 / sample_rate.max(1) as f64;
+        for sample in samples {
+            let (sin, cos) = (self.phase * TAU).sin_cos();
+            self.phase = (self.phase + step).rem_euclid(1.0);
+            *sample *= Complex::new(cos as f32, sin as f32);
+        }
+    }
+
+    /// The bins of a `len`-bin spectrum, lowest frequency first, that a
+    /// shift by `shift` Hz fills with what wrapped round from the far edge
+    pub fn wrapped_bins(shift: i32, sample_rate: u32, len: usize) -> Range<usize> {
+        let width = (shift.unsigned_abs() as u64 * len as u64).div_ceil(sample_rate.max(1) as u64);
+        let width = (width as usize).min(len);
+        if shift >= 0 {
+            0..width
+        } else {
+            len - width..len
+        }
+    }
+
+    /// Blank the bins of `fft`, in dB, that hold what a shift by `shift` Hz
+    /// wrapped round, down to the lowest bin left
+    pub fn mask_wrapped(shift: i32, sample_rate: u32, fft: &mut [f32]) {
+        let wrapped = Self::wrapped_bins(shift, sample_rate, fft.len());
+        if wrapped.is_empty() {
+            return;
+        }
+        let floor = fft
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !wrapped.contains(i))
+            .map(|(_, &db)| db)
+            .fold(f32::INFINITY, f32::min);
+        if floor.is_finite() {
+            fft[wrapped].fill(floor);
+        }
+    }
+}
+
+/// Narrow complex notch at 0 Hz: the DC level is tracked by a one-pole
+/// average and taken away
+#[derive(Debug, Clone, Default)]
+pub struct DcNotch {
+    level: Complex<f32>,
+}
+
+impl DcNotch {
+    pub fn process(&mut self, sample_rate: u32, samples: &mut [Complex<f32>]) {
+        let alpha = (TAU * NOTCH_HZ / sample_rate.max(1) as f64).min(1.0) as f32;
+        for sample in samples {
+            self.level += (*sample - self.level) * alpha;
+            *sample -= self.level;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr::Capabilities;
+    use crate::state::AppState;
+
+    const RATE: u32 = 2_048_000;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("Offset".parse::<DcAvoidance>(), Ok(DcAvoidance::Offset));
+        assert_eq!("off".parse::<DcAvoidance>(), Ok(DcAvoidance::Off));
+        assert!("shift".parse::<DcAvoidance>().is_err());
+        assert_eq!(DcAvoidance::default().name(), "notch");
+    }
+
+    #[test]
+    fn test_dc_in_passband() {
+        // On the center, or a few kHz off in NFM
+        assert!(dc_in_passband(0, DemodMode::FmNarrow, None));
+        assert!(dc_in_passband(-7_000, DemodMode::FmNarrow, None));
+        assert!(!dc_in_passband(50_000, DemodMode::FmNarrow, None));
+        assert!(!dc_in_passband(10_000, DemodMode::FmNarrow, Some(12_500)));
+        // USB doesn't hear its own carrier, but does hear a center below it
+        assert!(!dc_in_passband(0, DemodMode::Usb, None));
+        assert!(dc_in_passband(-1_000, DemodMode::Usb, None));
+        // No channel filter, nothing heard
+        assert!(!dc_in_passband(0, DemodMode::Adsb, None));
+    }
+
+    #[test]
+    fn test_nudge() {
+        let rtl = Capabilities::rtl().frequency;
+        assert_eq!(nudge(145_000_000, DEFAULT_DC_OFFSET, RATE, &rtl), 250_000);
+        // Too close to the top of the range: below instead
+        assert_eq!(
+            nudge(1_765_900_000, DEFAULT_DC_OFFSET, RATE, &rtl),
+            -250_000
+        );
+        // Kept inside the capture at low rates
+        assert_eq!(nudge(145_000_000, DEFAULT_DC_OFFSET, 250_000, &rtl), 62_500);
+        // A recording being played back can't be retuned at all
+        assert_eq!(
+            nudge(
+                145_000_000,
+                DEFAULT_DC_OFFSET,
+                RATE,
+                &(145_000_000..=145_000_000)
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn test_hardware_frequency_mapping() {
+        let rtl = Capabilities::rtl().frequency;
+        let mut state = AppState::default();
+        state.sdr.frequency = 145_500_000;
+        state.sdr.sample_rate = RATE;
+        state.decoder.mode = DemodMode::FmNarrow;
+
+        // Notch and off leave the hardware on the frequency shown
+        for avoidance in [DcAvoidance::Notch, DcAvoidance::Off] {
+            state.sdr.dc_avoidance = avoidance;
+            assert_eq!(state.hardware_offset_for(145_500_000, &rtl), 0);
+        }
+
+        state.sdr.dc_avoidance = DcAvoidance::Offset;
+        let offset = state.hardware_offset_for(145_500_000, &rtl);
+        assert_eq!(offset, 250_000);
+        state.sdr.hardware_offset = offset;
+        assert_eq!(state.sdr.frequency, 145_500_000);
+        assert_eq!(state.sdr.hardware_frequency(), 145_750_000);
+
+        // Listening away from the center: no need to move
+        state.channels.offset_a = 100_000;
+        assert_eq!(state.hardware_offset_for(145_500_000, &rtl), 0);
+        state.channels.offset_a = 0;
+
+        // The channel, shown at the frequency, is at -offset in the capture
+        // from the hardware, and the shift brings it back to 0 Hz
+        let mut shift = FrequencyShift::default();
+        let mut capture: Vec<Complex<f32>> = (0..1_000)
+            .map(|i| Complex::from_polar(1.0, (-TAU * 250_000.0 * i as f64 / RATE as f64) as f32))
+            .collect();
+        shift.process(offset, RATE, &mut capture[..300]);
+        shift.process(offset, RATE, &mut capture[300..]);
+        for sample in &capture {
+            assert!(
+                (sample - Complex::new(1.0, 0.0)).norm() < 1e-3,
+                "{}",
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn test_notch() {
+        // A DC offset under a 1 kHz tone
+        let tone = |i: usize| Complex::from_polar(0.5, (TAU * 1000.0 * i as f64 / 48_000.0) as f32);
+        let mut samples: Vec<Complex<f32>> = (0..48_000)
+            .map(|i| tone(i) + Complex::new(0.2, -0.1))
+            .collect();
+        let mut notch = DcNotch::default();
+        notch.process(48_000, &mut samples);
+
+        // Settled after the first half second: the DC gone, the tone kept
+        let tail = &samples[24_000..];
+        let mean = tail.iter().sum::<Complex<f32>>() / tail.len() as f32;
+        assert!(mean.norm() < 1e-3, "{}", mean);
+        let error = tail
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s - tone(i + 24_000)).norm())
+            .fold(0.0, f32::max);
+        assert!(error < 0.02, "{}", error);
+    }
+
+    #[test]
+    fn test_wrapped_edge_masked() {
+        // A quarter of the capture shifted up wraps the top quarter round to
+        // the bottom; shifted down, the bottom round to the top
+        assert_eq!(FrequencyShift::wrapped_bins(512_000, RATE, 2048), 0..512);
+        assert_eq!(FrequencyShift::wrapped_bins(-250_000, RATE, 2048), 1798..2048);
+        assert_eq!(FrequencyShift::wrapped_bins(0, RATE, 2048), 0..0);
+        assert_eq!(FrequencyShift::wrapped_bins(i32::MAX, RATE, 2048), 0..2048);
+
+        // A signal just below the top edge, shifted up past it
+        let len = 2048;
+        let bin = 2000;
+        let freq = (bin as f64 / len as f64 - 0.5) * RATE as f64;
+        let mut samples: Vec<Complex<f32>> = (0..len)
+            .map(|i| Complex::from_polar(0.5, (TAU * freq * i as f64 / RATE as f64) as f32))
+            .collect();
+        let shift = 250_000;
+        FrequencyShift::default().process(shift, RATE, &mut samples);
+        let mut fft = crate::dsp::FftProcessor::new(len).process(&samples);
+        let peak = |fft: &[f32]| (0..len).max_by(|&a, &b| fft[a].total_cmp(&fft[b])).unwrap();
+        // It lands in the wrapped bins at the bottom, and is blanked there
+        assert!(FrequencyShift::wrapped_bins(shift, RATE, len).contains(&peak(&fft)));
+        FrequencyShift::mask_wrapped(shift, RATE, &mut fft);
+        let wrapped = FrequencyShift::wrapped_bins(shift, RATE, len);
+        let floor = fft[wrapped.end..].iter().copied().fold(f32::INFINITY, f32::min);
+        assert!(fft[wrapped].iter().all(|&db| db == floor));
+    }
+
+    #[test]
+    fn test_dc_prominent() {
+        let mut fft = vec![-60.0; 2048];
+        assert!(!dc_prominent(&fft));
+        fft[1024] = -35.0;
+        assert!(dc_prominent(&fft));
+        // A wide signal at the center isn't a spike
+        fft[1000..1050].fill(-35.0);
+        assert!(!dc_prominent(&fft));
+    }
+}
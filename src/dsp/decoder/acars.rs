@@ -0,0 +1,397 @@
+//! ACARS (VHF airband datalink) decoder
+//!
+//! ACARS is 2400 baud MSK with 1200 Hz and 2400 Hz tones, carried as AM
+//! audio. Bits are differentially encoded: the same tone as the previous bit
+//! is a 1, a tone change a 0. A burst is a pre-key of ones, the "+*" bit sync
+//! and SYN SYN character sync, then odd-parity 7-bit characters sent LSB
+//! first: SOH, mode, aircraft address, technical acknowledgement, label,
+//! block id, STX, text, ETX/ETB, a two-byte CRC-16 block check and DEL.
+//!
+//! Demodulation is the non-coherent front end in [`super::fsk`] the SAME
+//! decoder uses: sliding one-bit correlations against both tones, with the bit
+//! clock pulled onto their crossovers. The deframer is a small state machine that returns to
+//! hunting for sync after every burst, good or bad.
+
+use super::fsk::{BitClock, Boxcar, ToneCorrelator};
+use super::{Decoder, DecoderInput, InputKind};
+use crate::dsp::filters::Biquad;
+use crate::types::{DecodedMessage, DemodMode};
+
+/// Rate the audio is averaged down to before demodulation
+const DETECT_RATE: u32 = 24_000;
+/// Bit rate in bits per second
+const BAUD: f32 = 2400.0;
+/// The two MSK tones in Hz
+const LOW_TONE: f32 = 1200.0;
+const HIGH_TONE: f32 = 2400.0;
+/// Clock correction applied at each tone change
+const CLOCK_GAIN: f32 = 0.3;
+/// Longest block accepted between SOH and the block check
+const MAX_BLOCK_LEN: usize = 260;
+
+/// Control characters (with odd parity applied where they appear on air)
+const SYN: u8 = 0x16;
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const ETX: u8 = 0x03;
+const ETB: u8 = 0x17;
+
+/// A decoded ACARS block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcarsMessage {
+    /// Mode character ('2' is the common category A mode)
+    pub mode: char,
+    /// Aircraft registration, without the leading dots
+    pub registration: String,
+    /// Technical acknowledgement (NAK is shown as '!')
+    pub ack: char,
+    /// Two-character message label, e.g. "H1" or "_d"
+    pub label: String,
+    pub block_id: char,
+    /// Message text, if the block carries any
+    pub text: String,
+}
+
+impl AcarsMessage {
+    /// Parse the characters between SOH and ETX/ETB (parity already removed)
+    pub fn parse(block: &[u8]) -> Option<Self> {
+        let header = block.get(..12)?;
+        let ch = |i: usize| header[i] as char;
+        let registration: String = header[1..8].iter().map(|&b| b as char).collect();
+        let text = match block.get(12) {
+            Some(&STX) => String::from_utf8_lossy(&block[13..]).trim_end().to_string(),
+            _ => String::new(),
+        };
+
+        Some(Self {
+            mode: ch(0),
+            registration: registration.trim_start_matches('.').trim().to_string(),
+            ack: if header[8] == 0x15 { '!' } else { ch(8) },
+            label: header[9..11].iter().map(|&b| b as char).collect(),
+            block_id: ch(11),
+            text,
+        })
+    }
+
+    /// One-line summary for the message list
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "ACARS {} label {} mode {} blk {}",
+            if self.registration.is_empty() { "(ground)" } else { &self.registration },
+            self.label,
+            self.mode,
+            self.block_id
+        );
+        if !self.text.is_empty() {
+            summary.push_str(": ");
+            summary.push_str(&self.text.replace(['\r', '\n'], " "));
+        }
+        summary
+    }
+
+    /// Structured fields for logs
+    pub fn fields(&self) -> serde_json::Value {
+        serde_json::json!({
+            "mode": self.mode.to_string(),
+            "registration": self.registration,
+            "ack": self.ack.to_string(),
+            "label": self.label,
+            "block_id": self.block_id.to_string(),
+            "text": self.text,
+        })
+    }
+}
+
+/// Deframer state
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrameState {
+    /// Looking for SYN SYN in the bit stream
+    Hunt,
+    /// Byte aligned, expecting SOH
+    Soh,
+    /// Collecting characters up to ETX/ETB
+    Block,
+    /// Collecting the two block check bytes
+    Check,
+}
+
+/// CRC-16 used for the ACARS block check (CCITT polynomial, reflected, zero init)
+pub fn crc16_kermit(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x8408 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// Streaming ACARS decoder on AM-demodulated audio
+pub struct AcarsDecoder {
+    /// Input sample rate the decoder was built for
+    sample_rate: u32,
+    /// Averages the audio down to the detection rate
+    boxcar: Boxcar,
+    /// Removes the AM carrier's DC and hum before the correlators
+    highpass: Biquad,
+    /// Low and high tone correlators: true for the high tone
+    tones: ToneCorrelator,
+    clock: BitClock,
+    /// Tone of the previous bit, for differential decoding
+    prev_bit_tone: bool,
+    /// Bits shifted in LSB first, and bits of the current character
+    shift: u16,
+    bit_count: u32,
+    state: FrameState,
+    /// Characters from mode to ETX/ETB, as received (with parity)
+    block: Vec<u8>,
+    check: Vec<u8>,
+}
+
+impl AcarsDecoder {
+    /// Create a decoder for audio at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        let boxcar = Boxcar::new(sample_rate, DETECT_RATE);
+        let detect_rate = boxcar.rate(sample_rate);
+
+        Self {
+            sample_rate,
+            boxcar,
+            highpass: Biquad::highpass(detect_rate as f64, 400.0, std::f64::consts::FRAC_1_SQRT_2),
+            tones: ToneCorrelator::new(detect_rate, BAUD, [LOW_TONE, HIGH_TONE]),
+            clock: BitClock::new(detect_rate, BAUD, CLOCK_GAIN),
+            prev_bit_tone: false,
+            shift: 0,
+            bit_count: 0,
+            state: FrameState::Hunt,
+            block: Vec::new(),
+            check: Vec::new(),
+        }
+    }
+
+    /// Feed audio; returns any blocks that passed the CRC
+    pub fn process(&mut self, audio: &[f32]) -> Vec<AcarsMessage> {
+        let mut messages = Vec::new();
+
+        for &sample in audio {
+            let Some(x) = self.boxcar.push(sample) else {
+                continue;
+            };
+            let x = self.highpass.process(x);
+            if let Some(message) = self.demodulate(x) {
+                messages.push(message);
+            }
+        }
+
+        messages
+    }
+
+    /// Run one detection sample through the correlators and bit clock
+    fn demodulate(&mut self, x: f32) -> Option<AcarsMessage> {
+        let tone = self.clock.push(self.tones.push(x))?;
+        let bit = tone == self.prev_bit_tone;
+        self.prev_bit_tone = tone;
+        self.receive_bit(bit)
+    }
+
+    /// Shift in one bit and advance the deframer
+    fn receive_bit(&mut self, bit: bool) -> Option<AcarsMessage> {
+        self.shift = (self.shift >> 1) | if bit { 0x8000 } else { 0 };
+
+        if self.state == FrameState::Hunt {
+            if self.shift == u16::from_le_bytes([SYN, SYN]) {
+                self.state = FrameState::Soh;
+                self.bit_count = 0;
+            }
+            return None;
+        }
+
+        self.bit_count += 1;
+        if self.bit_count < 8 {
+            return None;
+        }
+        self.bit_count = 0;
+        let byte = (self.shift >> 8) as u8;
+
+        match self.state {
+            FrameState::Soh => {
+                // Further SYNs may precede SOH
+                if byte == with_parity(SOH) {
+                    self.block.clear();
+                    self.state = FrameState::Block;
+                } else if byte != with_parity(SYN) {
+                    self.state = FrameState::Hunt;
+                }
+                None
+            }
+            FrameState::Block => {
+                if byte.count_ones().is_multiple_of(2) || self.block.len() >= MAX_BLOCK_LEN {
+                    // Parity error or runaway: this burst is lost
+                    self.state = FrameState::Hunt;
+                    return None;
+                }
+                self.block.push(byte);
+                if byte & 0x7F == ETX || byte & 0x7F == ETB {
+                    self.check.clear();
+                    self.state = FrameState::Check;
+                }
+                None
+            }
+            FrameState::Check => {
+                self.check.push(byte);
+                if self.check.len() < 2 {
+                    return None;
+                }
+                self.state = FrameState::Hunt;
+                let mut covered = self.block.clone();
+                covered.extend(&self.check);
+                if crc16_kermit(&covered) != 0 {
+                    log::debug!("ACARS: block check failed");
+                    return None;
+                }
+                let chars: Vec<u8> = self.block[..self.block.len() - 1].iter().map(|b| b & 0x7F).collect();
+                AcarsMessage::parse(&chars)
+            }
+            FrameState::Hunt => None,
+        }
+    }
+}
+
+/// Set bit 7 so the character has odd parity
+fn with_parity(c: u8) -> u8 {
+    if c.count_ones().is_multiple_of(2) { c | 0x80 } else { c }
+}
+
+impl Decoder for AcarsDecoder {
+    fn name(&self) -> &'static str {
+        "ACARS"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Audio
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Audio(audio) = input else {
+            return Vec::new();
+        };
+        AcarsDecoder::process(self, audio)
+            .iter()
+            .map(|message| {
+                DecodedMessage::new(DemodMode::Acars, message.summary())
+                    .with_fields(message.fields())
+            })
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        *self = AcarsDecoder::new(self.sample_rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::PI;
+
+    /// On-air bytes of a block from mode through BCS and DEL
+    fn burst_bytes(mode: char, address: &str, label: &str, text: &str) -> Vec<u8> {
+        let mut block: Vec<u8> = format!("{}{:.>7}{}{}{}", mode, address, 'Q', label, '2')
+            .bytes()
+            .chain([STX])
+            .chain(text.bytes())
+            .chain([ETX])
+            .map(with_parity)
+            .collect();
+        let crc = crc16_kermit(&block);
+        block.extend(crc.to_le_bytes());
+        block.push(0x7F);
+        block
+    }
+
+    /// MSK-modulate a burst: pre-key, "+*", SYN SYN, SOH, block
+    fn modulate(block: &[u8], rate: u32, noise: f32) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut bits = vec![true; 128];
+        for byte in [b'+', b'*', SYN, SYN, SOH].iter().map(|&b| with_parity(b)).chain(block.iter().copied()) {
+            bits.extend((0..8).map(|i| byte >> i & 1 == 1));
+        }
+
+        let spb = rate as f32 / BAUD;
+        let mut audio = vec![0.0; rate as usize / 10];
+        let mut tone = false;
+        let mut phase = 0.0f32;
+        let mut end = audio.len() as f32;
+        for bit in bits {
+            if !bit {
+                tone = !tone;
+            }
+            let freq = if tone { HIGH_TONE } else { LOW_TONE };
+            end += spb;
+            while (audio.len() as f32) < end {
+                phase += 2.0 * PI * freq / rate as f32;
+                audio.push(0.4 * phase.sin() + rng.gen_range(-noise..=noise));
+            }
+        }
+        audio.extend(std::iter::repeat_n(0.0, rate as usize / 10));
+        audio
+    }
+
+    fn decode(audio: &[f32], rate: u32) -> Vec<AcarsMessage> {
+        let mut decoder = AcarsDecoder::new(rate);
+        audio.chunks(4096).flat_map(|c| decoder.process(c)).collect()
+    }
+
+    #[test]
+    fn test_crc_check_value() {
+        assert_eq!(crc16_kermit(b"123456789"), 0x2189);
+    }
+
+    #[test]
+    fn test_decode_canned_burst() {
+        let text = "M23AUA0417/12 KSFO/KORD .N12345";
+        let audio = modulate(&burst_bytes('2', "N12345", "H1", text), 48_000, 0.05);
+        let messages = decode(&audio, 48_000);
+
+        assert_eq!(
+            messages,
+            vec![AcarsMessage {
+                mode: '2',
+                registration: "N12345".to_string(),
+                ack: 'Q',
+                label: "H1".to_string(),
+                block_id: '2',
+                text: text.to_string(),
+            }]
+        );
+        assert!(messages[0].summary().starts_with("ACARS N12345 label H1"));
+    }
+
+    #[test]
+    fn test_back_to_back_bursts_at_iq_rate() {
+        let rate = 240_000;
+        let mut audio = modulate(&burst_bytes('2', "G-ABCD", "5Z", "FIRST"), rate, 0.05);
+        audio.extend(modulate(&burst_bytes('2', "D-EFGH", "_d", ""), rate, 0.05));
+
+        let messages = decode(&audio, rate);
+        let regs: Vec<&str> = messages.iter().map(|m| m.registration.as_str()).collect();
+        assert_eq!(regs, vec!["G-ABCD", "D-EFGH"]);
+        assert!(messages[1].text.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_corrupted_block() {
+        let mut bytes = burst_bytes('2', "N12345", "H1", "HELLO");
+        // Flip two bits in one character: parity still holds, the CRC doesn't
+        bytes[14] ^= 0x03;
+        let audio = modulate(&bytes, 48_000, 0.0);
+        assert!(decode(&audio, 48_000).is_empty());
+    }
+}
@@ -0,0 +1,462 @@
+//! ADS-B (1090 MHz Extended Squitter) decoder
+//!
+//! Mode S replies are 1 µs pulse-position bits after an 8 µs preamble of
+//! four pulses. The IQ magnitude is averaged into quarter-microsecond
+//! bins, whatever the sample rate, and the preamble is looked for at each
+//! of them, so however the pulses fall against the samples there is a
+//! reading of half-microsecond halves close to their edges. Each bit is then
+//! read from which half of its microsecond holds the pulse. Extended
+//! squitters (DF17) whose CRC checks
+//! are parsed with adsb_deku for identity, altitude, position and velocity,
+//! and gathered per aircraft for the live table.
+//!
+//! Positions are CPR-encoded: an even and an odd frame from the same
+//! aircraft at most ten seconds apart give a global position, without
+//! needing to know where the receiver is.
+
+use super::{Decoder, DecoderInput, InputKind};
+use crate::state::DecoderState;
+use crate::types::{Aircraft, DecodedMessage, DemodMode};
+use adsb_deku::deku::DekuContainerRead;
+use adsb_deku::{Altitude, CPRFormat, Frame, DF, ME};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Quarter-microsecond bins per second
+const BIN_RATE: f64 = 4_000_000.0;
+/// Preamble then 112 bits, in half-microsecond chips
+const PREAMBLE_CHIPS: usize = 16;
+const FRAME_CHIPS: usize = PREAMBLE_CHIPS + 112 * 2;
+/// A frame's length in bins
+const FRAME_BINS: usize = 2 * FRAME_CHIPS;
+/// Downlink format of an extended squitter from a transponder
+const DF_EXTENDED_SQUITTER: u8 = 17;
+/// Mode S CRC generator polynomial, with its leading bit
+const CRC_POLY: u32 = 0x1FF_F409;
+/// Longest gap between an even and odd position frame that are paired
+const CPR_MAX_AGE: Duration = Duration::from_secs(10);
+/// Latitude zones of the CPR encoding between the equator and a pole
+const CPR_ZONES: f64 = 15.0;
+
+/// Mode S CRC-24 of `data`; zero over a whole message whose parity checks
+fn crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in data {
+        crc ^= (byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= CRC_POLY;
+            }
+        }
+    }
+    crc & 0xFF_FFFF
+}
+
+/// A DF17 message starting at the first of `bins`, if its preamble looks
+/// right and its CRC checks
+fn read_frame(bins: &[f32]) -> Option<[u8; 14]> {
+    let chip = |at: usize| bins[2 * at] + bins[2 * at + 1];
+    let m: [f32; PREAMBLE_CHIPS] = std::array::from_fn(chip);
+    // Pulses at 0, 1, 3.5 and 4.5 µs with quiet between and after
+    let shape = m[0] > m[1]
+        && m[1] < m[2]
+        && m[2] > m[3]
+        && m[3] < m[0]
+        && m[4] < m[0]
+        && m[5] < m[0]
+        && m[6] < m[0]
+        && m[7] > m[8]
+        && m[8] < m[9]
+        && m[9] > m[6];
+    if !shape {
+        return None;
+    }
+    let high = (m[0] + m[2] + m[7] + m[9]) / 6.0;
+    if m[4] >= high || m[5] >= high || m[11..15].iter().any(|&x| x >= high) {
+        return None;
+    }
+
+    let mut bytes = [0u8; 14];
+    for bit in 0..112 {
+        if chip(PREAMBLE_CHIPS + 2 * bit) > chip(PREAMBLE_CHIPS + 2 * bit + 1) {
+            bytes[bit / 8] |= 0x80 >> (bit % 8);
+        }
+        // Not worth reading on when it isn't an extended squitter
+        if bit == 4 && bytes[0] >> 3 != DF_EXTENDED_SQUITTER {
+            return None;
+        }
+    }
+    (crc(&bytes) == 0).then_some(bytes)
+}
+
+/// Number of longitude zones at `lat`
+fn longitude_zones(lat: f64) -> f64 {
+    if lat == 0.0 {
+        return 59.0;
+    }
+    if lat.abs() >= 87.0 {
+        return if lat.abs() == 87.0 { 2.0 } else { 1.0 };
+    }
+    let a = 1.0 - (std::f64::consts::PI / (2.0 * CPR_ZONES)).cos();
+    let b = lat.to_radians().cos().powi(2);
+    (std::f64::consts::TAU / (1.0 - a / b).acos()).floor()
+}
+
+/// A CPR-encoded position as received: (latitude, longitude), each as a
+/// fraction of its zone
+type Cpr = (f64, f64);
+
+fn cpr(altitude: &Altitude) -> Cpr {
+    const SCALE: f64 = 131_072.0;
+    (
+        altitude.lat_cpr as f64 / SCALE,
+        altitude.lon_cpr as f64 / SCALE,
+    )
+}
+
+/// Globally unambiguous position from an even and an odd frame, as of
+/// whichever came last; None when they straddle a zone boundary
+fn global_position(even: Cpr, odd: Cpr, odd_last: bool) -> Option<(f64, f64)> {
+    let zone = |zones: f64, lat: f64, index: f64| {
+        let lat = 360.0 / zones * (index.rem_euclid(zones) + lat);
+        if lat >= 270.0 {
+            lat - 360.0
+        } else {
+            lat
+        }
+    };
+    let index = (59.0 * even.0 - 60.0 * odd.0 + 0.5).floor();
+    let lat_even = zone(4.0 * CPR_ZONES, even.0, index);
+    let lat_odd = zone(4.0 * CPR_ZONES - 1.0, odd.0, index);
+    if longitude_zones(lat_even) != longitude_zones(lat_odd) {
+        return None;
+    }
+
+    let (lat, lon, odd_offset) = if odd_last {
+        (lat_odd, odd.1, 1.0)
+    } else {
+        (lat_even, even.1, 0.0)
+    };
+    let zones = longitude_zones(lat);
+    let lon_zones = (zones - odd_offset).max(1.0);
+    let index = (even.1 * (zones - 1.0) - odd.1 * zones + 0.5).floor();
+    let lon = 360.0 / lon_zones * (index.rem_euclid(lon_zones) + lon);
+    Some((lat, if lon >= 180.0 { lon - 360.0 } else { lon }))
+}
+
+/// An aircraft and the position frames waiting for their pair
+#[derive(Debug, Clone)]
+struct Tracked {
+    aircraft: Aircraft,
+    even: Option<(Cpr, Instant)>,
+    odd: Option<(Cpr, Instant)>,
+}
+
+impl Tracked {
+    /// Add a position frame, updating the position once it has a pair
+    fn position(&mut self, altitude: &Altitude, now: Instant) {
+        let odd = altitude.odd_flag == CPRFormat::Odd;
+        if odd {
+            self.odd = Some((cpr(altitude), now));
+        } else {
+            self.even = Some((cpr(altitude), now));
+        }
+        let (Some((even, even_at)), Some((odd_cpr, odd_at))) = (self.even, self.odd) else {
+            return;
+        };
+        let apart = even_at
+            .max(odd_at)
+            .saturating_duration_since(even_at.min(odd_at));
+        if apart > CPR_MAX_AGE {
+            return;
+        }
+        if let Some((lat, lon)) = global_position(even, odd_cpr, odd) {
+            self.aircraft.lat = Some(lat);
+            self.aircraft.lon = Some(lon);
+        }
+    }
+}
+
+/// Decodes extended squitters from the IQ magnitude into aircraft
+pub struct AdsbDecoder {
+    sample_rate: u32,
+    /// Bins each input sample spans
+    step: f64,
+    /// How much of the bin being filled is, and its sum so far
+    fill: f64,
+    sum: f32,
+    /// Magnitude in quarter-microsecond bins, not yet searched
+    bins: Vec<f32>,
+    aircraft: HashMap<u32, Tracked>,
+}
+
+impl AdsbDecoder {
+    /// Create a decoder for magnitude at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            step: BIN_RATE / sample_rate.max(1) as f64,
+            fill: 0.0,
+            sum: 0.0,
+            bins: Vec::new(),
+            aircraft: HashMap::new(),
+        }
+    }
+
+    /// Feed magnitude samples; returns the frames whose CRC checked
+    pub fn process(&mut self, magnitude: &[f32]) -> Vec<[u8; 14]> {
+        for &sample in magnitude {
+            let mut left = self.step;
+            while left > 0.0 {
+                let take = left.min(1.0 - self.fill);
+                self.sum += sample * take as f32;
+                self.fill += take;
+                left -= take;
+                if self.fill >= 1.0 - 1e-9 {
+                    self.bins.push(self.sum);
+                    self.sum = 0.0;
+                    self.fill = 0.0;
+                }
+            }
+        }
+
+        let mut frames = Vec::new();
+        let mut at = 0;
+        while at + FRAME_BINS <= self.bins.len() {
+            match read_frame(&self.bins[at..at + FRAME_BINS]) {
+                Some(frame) => {
+                    frames.push(frame);
+                    at += FRAME_BINS;
+                }
+                None => at += 1,
+            }
+        }
+        self.bins.drain(..at);
+        frames
+    }
+
+    /// Update the aircraft a frame came from; a message when it first
+    /// gives its callsign
+    pub fn handle(&mut self, bytes: &[u8; 14], now: Instant) -> Option<DecodedMessage> {
+        let (_, frame) = Frame::from_bytes((bytes, 0)).ok()?;
+        let DF::ADSB(adsb) = frame.df else {
+            return None;
+        };
+        let [a, b, c] = adsb.icao.0;
+        let icao = u32::from_be_bytes([0, a, b, c]);
+        let tracked = self.aircraft.entry(icao).or_insert_with(|| Tracked {
+            aircraft: Aircraft::new(icao, now),
+            even: None,
+            odd: None,
+        });
+        let aircraft = &mut tracked.aircraft;
+        aircraft.messages += 1;
+        aircraft.last_seen = now;
+
+        match adsb.me {
+            ME::AircraftIdentification(identification) => {
+                let callsign = identification.cn.trim_matches([' ', '#']).to_string();
+                if callsign.is_empty() || aircraft.callsign.as_ref() == Some(&callsign) {
+                    return None;
+                }
+                let hex = aircraft.hex();
+                aircraft.callsign = Some(callsign.clone());
+                let message = DecodedMessage::new(DemodMode::Adsb, format!("{} {}", hex, callsign))
+                    .with_fields(serde_json::json!({ "icao": hex, "callsign": callsign }))
+                    .with_raw(bytes.iter().map(|byte| format!("{:02X}", byte)).collect());
+                return Some(message);
+            }
+            ME::AirbornePositionBaroAltitude(altitude) => {
+                aircraft.altitude = altitude.alt.map(i32::from);
+                tracked.position(&altitude, now);
+            }
+            ME::AirborneVelocity(velocity) => {
+                if let Some((heading, ground_speed, _)) = velocity.calculate() {
+                    aircraft.heading = Some(heading);
+                    aircraft.ground_speed = Some(ground_speed as f32);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+impl Decoder for AdsbDecoder {
+    fn name(&self) -> &'static str {
+        "ADS-B"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Magnitude
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Magnitude(magnitude) = input else {
+            return Vec::new();
+        };
+        let now = Instant::now();
+        let frames = AdsbDecoder::process(self, magnitude);
+        let messages = frames
+            .iter()
+            .filter_map(|frame| self.handle(frame, now))
+            .collect();
+        self.aircraft
+            .retain(|_, tracked| !tracked.aircraft.is_expired(now));
+        messages
+    }
+
+    fn reset(&mut self) {
+        // Aircraft outlast a gap; a frame cut by it doesn't
+        self.bins.clear();
+        self.fill = 0.0;
+        self.sum = 0.0;
+    }
+
+    fn publish(&self, state: &mut DecoderState) {
+        for (&icao, tracked) in &self.aircraft {
+            state.aircraft.insert(icao, tracked.aircraft.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 2_400_000;
+
+    fn hex(frame: &str) -> [u8; 14] {
+        let mut bytes = [0; 14];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&frame[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    /// The magnitude of `frames` transmitted one after another at `RATE`,
+    /// with a little noise floor and starting off the bin grid
+    fn transmit(frames: &[[u8; 14]]) -> Vec<f32> {
+        let mut pulses = Vec::new();
+        for frame in frames {
+            // Half-microsecond chips: preamble, bits, then a gap
+            pulses.extend([1, 0, 1, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0]);
+            for bit in 0..112 {
+                let one = frame[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                pulses.extend(if one { [1, 0] } else { [0, 1] });
+            }
+            pulses.extend([0; 40]);
+        }
+        const CHIP_RATE: f64 = 2_000_000.0;
+        let offset = 0.3;
+        let len = ((pulses.len() as f64 + offset) * RATE as f64 / CHIP_RATE) as usize;
+        (0..len)
+            .map(|i| {
+                let chip = i as f64 * CHIP_RATE / RATE as f64 - offset;
+                let on = chip >= 0.0 && pulses.get(chip as usize) == Some(&1);
+                if on {
+                    0.8
+                } else {
+                    0.05
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(crc(&hex("8D4840D6202CC371C32CE0576098")), 0);
+        assert_ne!(crc(&hex("8D4840D6202CC371C32CE0576099")), 0);
+    }
+
+    #[test]
+    fn test_demodulates_frames() {
+        let frames = [
+            hex("8D4840D6202CC371C32CE0576098"),
+            hex("8D485020994409940838175B284F"),
+        ];
+        let samples = transmit(&frames);
+        let mut decoder = AdsbDecoder::new(RATE);
+        // In uneven blocks, so frames straddle them
+        let mut found = Vec::new();
+        for block in samples.chunks(333) {
+            found.extend(decoder.process(block));
+        }
+        assert_eq!(found, frames);
+
+        // A flipped bit fails the CRC
+        let mut corrupt = frames[0];
+        corrupt[6] ^= 0x10;
+        assert!(AdsbDecoder::new(RATE)
+            .process(&transmit(&[corrupt]))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_identification_and_velocity() {
+        let now = Instant::now();
+        let mut decoder = AdsbDecoder::new(RATE);
+        let message = decoder
+            .handle(&hex("8D4840D6202CC371C32CE0576098"), now)
+            .unwrap();
+        assert_eq!(message.content, "4840D6 KLM1023");
+        // Said again, it isn't news
+        assert!(decoder
+            .handle(&hex("8D4840D6202CC371C32CE0576098"), now)
+            .is_none());
+
+        decoder.handle(&hex("8D485020994409940838175B284F"), now);
+        let aircraft = &decoder.aircraft[&0x485020].aircraft;
+        assert!((aircraft.ground_speed.unwrap() - 159.2).abs() < 0.1);
+        assert!((aircraft.heading.unwrap() - 182.88).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_position_from_cpr_pair() {
+        let now = Instant::now();
+        let mut decoder = AdsbDecoder::new(RATE);
+        decoder.handle(&hex("8D40621D58C386435CC412692AD6"), now);
+        let aircraft = &decoder.aircraft[&0x40621D].aircraft;
+        assert_eq!(aircraft.altitude, Some(38_000));
+        assert_eq!(aircraft.lat, None);
+
+        // The even frame completes the pair, and places it
+        decoder.handle(
+            &hex("8D40621D58C382D690C8AC2863A7"),
+            now + Duration::from_secs(1),
+        );
+        let aircraft = &decoder.aircraft[&0x40621D].aircraft;
+        assert!((aircraft.lat.unwrap() - 52.2572).abs() < 1e-3);
+        assert!((aircraft.lon.unwrap() - 3.9194).abs() < 1e-3);
+        assert_eq!(aircraft.messages, 2);
+
+        // Too far apart to pair
+        let mut decoder = AdsbDecoder::new(RATE);
+        decoder.handle(&hex("8D40621D58C386435CC412692AD6"), now);
+        decoder.handle(
+            &hex("8D40621D58C382D690C8AC2863A7"),
+            now + Duration::from_secs(11),
+        );
+        assert_eq!(decoder.aircraft[&0x40621D].aircraft.lat, None);
+    }
+
+    #[test]
+    fn test_publishes_aircraft() {
+        let mut decoder = AdsbDecoder::new(RATE);
+        let samples = transmit(&[hex("8D4840D6202CC371C32CE0576098")]);
+        let messages = Decoder::process(&mut decoder, DecoderInput::Magnitude(&samples));
+        assert_eq!(messages.len(), 1);
+
+        let mut state = DecoderState::default();
+        decoder.publish(&mut state);
+        assert_eq!(
+            state.aircraft[&0x4840D6].callsign.as_deref(),
+            Some("KLM1023")
+        );
+    }
+}
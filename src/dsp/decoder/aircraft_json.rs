@@ -0,0 +1,307 @@
+//! The ADS-B aircraft table as a file for web maps
+//!
+//! Once a second the decoder thread writes the aircraft being tracked in
+//! the schema of dump1090's `aircraft.json`, which tar1090 and most other
+//! maps read, or as a GeoJSON FeatureCollection that Leaflet and friends
+//! can load directly. Each write goes to a temporary file beside the target
+//! and is renamed over it, so a map polling the file never reads half of
+//! one.
+
+use crate::types::Aircraft;
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the file is rewritten
+pub const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the file holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// dump1090's `aircraft.json`
+    Dump1090,
+    /// A GeoJSON FeatureCollection of the aircraft with a position
+    GeoJson,
+}
+
+impl ExportFormat {
+    /// GeoJSON for a `.geojson` path, dump1090's schema otherwise
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("geojson") => ExportFormat::GeoJson,
+            _ => ExportFormat::Dump1090,
+        }
+    }
+}
+
+/// Seconds since the Unix epoch, as dump1090 gives `now`
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64())
+}
+
+/// Seconds since `aircraft` was last heard at `now`, to a tenth
+fn seen(aircraft: &Aircraft, now: Instant) -> f64 {
+    let seconds = now
+        .saturating_duration_since(aircraft.last_seen)
+        .as_secs_f64();
+    (seconds * 10.0).round() / 10.0
+}
+
+/// What is known of one aircraft in dump1090's field names; unknown fields
+/// are left out, as dump1090 does
+fn fields(aircraft: &Aircraft, now: Instant) -> Map<String, Value> {
+    let mut fields = Map::new();
+    fields.insert("hex".into(), aircraft.hex().to_ascii_lowercase().into());
+    if let Some(callsign) = &aircraft.callsign {
+        fields.insert("flight".into(), callsign.clone().into());
+    }
+    if let (Some(lat), Some(lon)) = (aircraft.lat, aircraft.lon) {
+        fields.insert("lat".into(), lat.into());
+        fields.insert("lon".into(), lon.into());
+    }
+    if let Some(altitude) = aircraft.altitude {
+        fields.insert("alt_baro".into(), altitude.into());
+    }
+    if let Some(speed) = aircraft.ground_speed {
+        fields.insert("gs".into(), f64::from(speed).into());
+    }
+    if let Some(track) = aircraft.heading {
+        fields.insert("track".into(), f64::from(track).into());
+    }
+    fields.insert("messages".into(), aircraft.messages.into());
+    fields.insert("seen".into(), seen(aircraft, now).into());
+    fields
+}
+
+/// The aircraft not yet expired, by ICAO address so the file is stable
+fn current(aircraft: &[Aircraft], now: Instant) -> Vec<&Aircraft> {
+    let mut current: Vec<&Aircraft> = aircraft.iter().filter(|a| !a.is_expired(now)).collect();
+    current.sort_by_key(|a| a.icao);
+    current
+}
+
+/// `aircraft` as dump1090's `aircraft.json` at `now` (wall clock) and
+/// `instant` (for how long ago each was seen)
+///
+/// `messages` is the total over the aircraft listed.
+pub fn dump1090_json(aircraft: &[Aircraft], now: SystemTime, instant: Instant) -> Value {
+    let current = current(aircraft, instant);
+    let messages: u64 = current.iter().map(|a| a.messages).sum();
+    let list: Vec<Value> = current
+        .iter()
+        .map(|a| Value::Object(fields(a, instant)))
+        .collect();
+    json!({
+        "now": unix_seconds(now),
+        "messages": messages,
+        "aircraft": list,
+    })
+}
+
+/// `aircraft` with a position as a GeoJSON FeatureCollection of points,
+/// with the rest of dump1090's fields as each one's properties
+pub fn geojson(aircraft: &[Aircraft], now: SystemTime, instant: Instant) -> Value {
+    let features: Vec<Value> = current(aircraft, instant)
+        .into_iter()
+        .filter_map(|a| {
+            let (lat, lon) = (a.lat?, a.lon?);
+            let mut properties = fields(a, instant);
+            properties.remove("lat");
+            properties.remove("lon");
+            Some(json!({
+                "type": "Feature",
+                "id": a.hex().to_ascii_lowercase(),
+                "geometry": { "type": "Point", "coordinates": [lon, lat] },
+                "properties": properties,
+            }))
+        })
+        .collect();
+    json!({
+        "type": "FeatureCollection",
+        "now": unix_seconds(now),
+        "features": features,
+    })
+}
+
+/// Replace `path` with `contents` in one step, by way of a temporary file
+/// in the same directory
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    std::fs::write(&temporary, contents)
+        .with_context(|| format!("Failed to write {}", temporary.display()))?;
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+/// Writes the aircraft table to a file every [`EXPORT_INTERVAL`]
+#[derive(Debug)]
+pub struct AircraftExport {
+    path: PathBuf,
+    format: ExportFormat,
+    next: Instant,
+    /// Whether the last write failed, so a stuck disk is logged once
+    failing: bool,
+}
+
+impl AircraftExport {
+    /// Export to `path`, in the format its extension calls for
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            format: ExportFormat::for_path(&path),
+            path,
+            next: Instant::now(),
+            failing: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether a write is due at `now`
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next
+    }
+
+    /// Write `aircraft` as it is at `now`; the next write is due an
+    /// interval later whether or not this one worked
+    pub fn write(&mut self, aircraft: &[Aircraft], now: Instant) {
+        self.next = now + EXPORT_INTERVAL;
+        let value = match self.format {
+            ExportFormat::Dump1090 => dump1090_json(aircraft, SystemTime::now(), now),
+            ExportFormat::GeoJson => geojson(aircraft, SystemTime::now(), now),
+        };
+        match write_atomic(&self.path, value.to_string().as_bytes()) {
+            Ok(()) if self.failing => {
+                log::info!("Writing aircraft to {} again", self.path.display());
+                self.failing = false;
+            }
+            Ok(()) => {}
+            Err(e) if !self.failing => {
+                log::warn!("{:#}", e);
+                self.failing = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_schemas() {
+        // One aircraft with a position, one without, one expired
+        let instant = Instant::now() + Duration::from_secs(1000);
+        let now = SystemTime::UNIX_EPOCH + Duration::from_millis(1_717_251_903_500);
+        let located = Aircraft {
+            callsign: Some("DLH4AB".to_string()),
+            altitude: Some(36000),
+            ground_speed: Some(451.5),
+            heading: Some(270.25),
+            lat: Some(47.45),
+            lon: Some(8.5625),
+            messages: 120,
+            ..Aircraft::new(0x3C6586, instant - Duration::from_millis(1_250))
+        };
+        let heard = Aircraft {
+            messages: 3,
+            ..Aircraft::new(0x4CA123, instant - Duration::from_secs(20))
+        };
+        let expired = Aircraft::new(0xABCDEF, instant - Duration::from_secs(600));
+        let aircraft = [heard, expired, located];
+
+        assert_eq!(
+            dump1090_json(&aircraft, now, instant),
+            json!({
+                "now": 1_717_251_903.5,
+                "messages": 123,
+                "aircraft": [
+                    {
+                        "hex": "3c6586",
+                        "flight": "DLH4AB",
+                        "lat": 47.45,
+                        "lon": 8.5625,
+                        "alt_baro": 36000,
+                        "gs": 451.5,
+                        "track": 270.25,
+                        "messages": 120,
+                        "seen": 1.3,
+                    },
+                    { "hex": "4ca123", "messages": 3, "seen": 20.0 },
+                ],
+            })
+        );
+        assert_eq!(
+            geojson(&aircraft, now, instant),
+            json!({
+                "type": "FeatureCollection",
+                "now": 1_717_251_903.5,
+                "features": [{
+                    "type": "Feature",
+                    "id": "3c6586",
+                    "geometry": { "type": "Point", "coordinates": [8.5625, 47.45] },
+                    "properties": {
+                        "hex": "3c6586",
+                        "flight": "DLH4AB",
+                        "alt_baro": 36000,
+                        "gs": 451.5,
+                        "track": 270.25,
+                        "messages": 120,
+                        "seen": 1.3,
+                    },
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_format_for_path() {
+        assert_eq!(
+            ExportFormat::for_path(Path::new("aircraft.json")),
+            ExportFormat::Dump1090
+        );
+        assert_eq!(
+            ExportFormat::for_path(Path::new("/srv/map/planes.GeoJSON")),
+            ExportFormat::GeoJson
+        );
+        assert_eq!(
+            ExportFormat::for_path(Path::new("aircraft")),
+            ExportFormat::Dump1090
+        );
+    }
+
+    #[test]
+    fn test_export_replaces_file() {
+        let dir = std::env::temp_dir().join(format!("rtl-sdr-tui-aircraft-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("aircraft.json");
+        std::fs::write(&path, "stale").unwrap();
+
+        let instant = Instant::now() + Duration::from_secs(1000);
+        let aircraft = [
+            Aircraft::new(0x3C6586, instant),
+            Aircraft::new(0x4CA123, instant - Duration::from_secs(20)),
+        ];
+        let mut export = AircraftExport::new(path.clone());
+        assert!(export.is_due(instant));
+        export.write(&aircraft, instant);
+        assert!(!export.is_due(instant + EXPORT_INTERVAL / 2));
+        assert!(export.is_due(instant + EXPORT_INTERVAL));
+
+        let written: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written["aircraft"].as_array().map(Vec::len), Some(2));
+        // Nothing left behind but the file itself
+        let files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(files, ["aircraft.json"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,596 @@
+//! AIS (marine Automatic Identification System) decoder
+//!
+//! AIS uses 9600 baud GMSK on 161.975 MHz (channel A) and 162.025 MHz
+//! (channel B). Each channel is mixed to baseband, averaged down to about
+//! 48 kHz and FM-demodulated; the sign of the discriminator output is sampled
+//! by a bit clock pulled onto its zero crossings, then NRZI/HDLC deframed.
+//! Tuned to 162.000 MHz both channels fall inside the IQ bandwidth and are
+//! decoded together.
+//!
+//! Frames with a good CRC are parsed for position reports (types 1-3) and
+//! static and voyage data (type 5), and re-armored as NMEA `!AIVDM`
+//! sentences for chart plotters.
+
+use super::fsk::BitClock;
+use super::hdlc::{HdlcDeframer, Nrzi};
+use super::{Decoder, DecoderInput, InputKind};
+use crate::types::{DecodedMessage, DemodMode};
+use crossbeam::channel::Sender;
+use num_complex::Complex;
+use std::f32::consts::PI;
+
+/// AIS channel A and B frequencies in Hz
+pub const CHANNEL_A: u32 = 161_975_000;
+pub const CHANNEL_B: u32 = 162_025_000;
+
+/// Bit rate in bits per second
+const BAUD: f32 = 9600.0;
+/// Rate each channel is averaged down to before demodulation
+const CHANNEL_RATE: u32 = 48_000;
+/// Clock correction applied at each zero crossing
+const CLOCK_GAIN: f32 = 0.25;
+/// Tuning tolerance for recognizing a channel, in Hz
+const TUNE_TOLERANCE: u32 = 5_000;
+/// Longest NMEA payload per sentence, in armored characters
+const NMEA_CHUNK: usize = 60;
+
+/// A decoded AIS message
+#[derive(Debug, Clone, PartialEq)]
+pub enum AisMessage {
+    /// Class A position report (types 1, 2 and 3)
+    Position {
+        mmsi: u32,
+        /// Navigational status code
+        status: u8,
+        /// Speed over ground in knots
+        sog: Option<f32>,
+        /// Latitude and longitude in degrees
+        lat: Option<f64>,
+        lon: Option<f64>,
+        /// Course over ground in degrees
+        cog: Option<f32>,
+        /// True heading in degrees
+        heading: Option<u16>,
+    },
+    /// Static and voyage related data (type 5)
+    Static {
+        mmsi: u32,
+        callsign: String,
+        name: String,
+        ship_type: u8,
+        destination: String,
+    },
+    /// Any other message type, identified only
+    Other { msg_type: u8, mmsi: u32 },
+}
+
+impl AisMessage {
+    /// Parse a message from its payload bytes (as deframed, FCS removed)
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bits = PayloadBits::from_bytes(bytes);
+        let msg_type = bits.uint(0, 6)? as u8;
+        let mmsi = bits.uint(8, 30)?;
+
+        match msg_type {
+            1..=3 => {
+                let sog = bits.uint(50, 10)?;
+                let lon = bits.int(61, 28)?;
+                let lat = bits.int(89, 27)?;
+                let cog = bits.uint(116, 12)?;
+                let heading = bits.uint(128, 9)?;
+                Some(AisMessage::Position {
+                    mmsi,
+                    status: bits.uint(38, 4)? as u8,
+                    sog: (sog != 1023).then_some(sog as f32 / 10.0),
+                    lat: (lat != 91 * 600_000).then_some(lat as f64 / 600_000.0),
+                    lon: (lon != 181 * 600_000).then_some(lon as f64 / 600_000.0),
+                    cog: (cog < 3600).then_some(cog as f32 / 10.0),
+                    heading: (heading < 360).then_some(heading as u16),
+                })
+            }
+            5 => Some(AisMessage::Static {
+                mmsi,
+                callsign: bits.text(70, 7)?,
+                name: bits.text(112, 20)?,
+                ship_type: bits.uint(232, 8)? as u8,
+                destination: bits.text(302, 20)?,
+            }),
+            _ => Some(AisMessage::Other { msg_type, mmsi }),
+        }
+    }
+
+    /// One-line summary for the message list
+    pub fn summary(&self) -> String {
+        let opt = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+        match self {
+            AisMessage::Position { mmsi, sog, lat, lon, cog, heading, .. } => format!(
+                "AIS {}: {} {} SOG {} kn COG {} HDG {}",
+                mmsi,
+                opt(lat.map(|v| format!("{:.5}", v))),
+                opt(lon.map(|v| format!("{:.5}", v))),
+                opt(sog.map(|v| format!("{:.1}", v))),
+                opt(cog.map(|v| format!("{:.1}", v))),
+                opt(heading.map(|v| v.to_string())),
+            ),
+            AisMessage::Static { mmsi, callsign, name, destination, .. } => format!(
+                "AIS {}: \"{}\" ({}) bound for {}",
+                mmsi,
+                name,
+                callsign,
+                if destination.is_empty() { "-" } else { destination }
+            ),
+            AisMessage::Other { msg_type, mmsi } => format!("AIS {}: message type {}", mmsi, msg_type),
+        }
+    }
+
+    /// Structured fields for logs
+    pub fn fields(&self) -> serde_json::Value {
+        match self {
+            AisMessage::Position { mmsi, status, sog, lat, lon, cog, heading } => {
+                serde_json::json!({
+                    "type": "position",
+                    "mmsi": mmsi,
+                    "status": status,
+                    "sog": sog,
+                    "lat": lat,
+                    "lon": lon,
+                    "cog": cog,
+                    "heading": heading,
+                })
+            }
+            AisMessage::Static { mmsi, callsign, name, ship_type, destination } => {
+                serde_json::json!({
+                    "type": "static",
+                    "mmsi": mmsi,
+                    "callsign": callsign,
+                    "name": name,
+                    "ship_type": ship_type,
+                    "destination": destination,
+                })
+            }
+            AisMessage::Other { msg_type, mmsi } => serde_json::json!({
+                "type": msg_type,
+                "mmsi": mmsi,
+            }),
+        }
+    }
+}
+
+/// Payload bits, read MSB first within each deframed byte
+struct PayloadBits {
+    bits: Vec<bool>,
+}
+
+impl PayloadBits {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let bits = bytes
+            .iter()
+            .flat_map(|&byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+            .collect();
+        Self { bits }
+    }
+
+    fn uint(&self, start: usize, len: usize) -> Option<u32> {
+        let field = self.bits.get(start..start + len)?;
+        Some(field.iter().fold(0, |v, &bit| (v << 1) | u32::from(bit)))
+    }
+
+    fn int(&self, start: usize, len: usize) -> Option<i32> {
+        let raw = self.uint(start, len)?;
+        Some(((raw << (32 - len)) as i32) >> (32 - len))
+    }
+
+    /// Six-bit ASCII text, with '@' padding and trailing spaces removed
+    fn text(&self, start: usize, chars: usize) -> Option<String> {
+        let text: String = (0..chars)
+            .map(|i| self.uint(start + i * 6, 6))
+            .collect::<Option<Vec<u32>>>()?
+            .into_iter()
+            .map(|v| if v < 32 { (v as u8 + 64) as char } else { v as u8 as char })
+            .collect();
+        Some(text.split('@').next().unwrap_or("").trim_end().to_string())
+    }
+
+    /// Armor into NMEA six-bit characters; returns the payload and fill bits
+    fn armor(&self) -> (String, usize) {
+        let fill = (6 - self.bits.len() % 6) % 6;
+        let payload = self
+            .bits
+            .chunks(6)
+            .map(|chunk| {
+                let v = (0..6).fold(0u8, |v, i| (v << 1) | u8::from(chunk.get(i).copied().unwrap_or(false)));
+                (if v < 40 { v + 48 } else { v + 56 }) as char
+            })
+            .collect();
+        (payload, fill)
+    }
+}
+
+/// `!AIVDM` sentences for a deframed payload on channel 'A' or 'B'
+pub fn nmea_sentences(bytes: &[u8], channel: char, sequence: u8) -> Vec<String> {
+    let (payload, fill) = PayloadBits::from_bytes(bytes).armor();
+    let chunks: Vec<&str> = payload
+        .as_bytes()
+        .chunks(NMEA_CHUNK)
+        .map(|c| std::str::from_utf8(c).unwrap_or(""))
+        .collect();
+    let count = chunks.len();
+    // Multi-sentence messages share a sequential message id
+    let seq_id = if count > 1 { (sequence % 10).to_string() } else { String::new() };
+
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let body = format!(
+                "AIVDM,{},{},{},{},{},{}",
+                count,
+                i + 1,
+                seq_id,
+                channel,
+                chunk,
+                if i + 1 == count { fill } else { 0 }
+            );
+            let checksum = body.bytes().fold(0u8, |c, b| c ^ b);
+            format!("!{}*{:02X}", body, checksum)
+        })
+        .collect()
+}
+
+/// A decoded frame
+#[derive(Debug, Clone)]
+pub struct AisReport {
+    /// AIS channel, 'A' or 'B'
+    pub channel: char,
+    pub message: AisMessage,
+    /// The frame as NMEA sentences
+    pub nmea: Vec<String>,
+}
+
+/// Demodulator for one AIS channel
+struct AisChannel {
+    name: char,
+    /// Mixer taking the channel to baseband
+    osc: Complex<f32>,
+    osc_step: Complex<f32>,
+    /// IQ samples averaged into one channel sample
+    decimation: usize,
+    decim_sum: Complex<f32>,
+    decim_count: usize,
+    prev: Complex<f32>,
+    clock: BitClock,
+    nrzi: Nrzi,
+    deframer: HdlcDeframer,
+}
+
+impl AisChannel {
+    fn new(name: char, sample_rate: u32, offset_hz: i32) -> Self {
+        let decimation = (sample_rate / CHANNEL_RATE).max(1) as usize;
+        let channel_rate = sample_rate as f32 / decimation as f32;
+        Self {
+            name,
+            osc: Complex::new(1.0, 0.0),
+            osc_step: Complex::from_polar(1.0, -2.0 * PI * offset_hz as f32 / sample_rate as f32),
+            decimation,
+            decim_sum: Complex::new(0.0, 0.0),
+            decim_count: 0,
+            prev: Complex::new(0.0, 0.0),
+            clock: BitClock::new(channel_rate, BAUD, CLOCK_GAIN),
+            nrzi: Nrzi::default(),
+            deframer: HdlcDeframer::new(),
+        }
+    }
+
+    /// Demodulate IQ; returns the payload of each good frame
+    fn process(&mut self, samples: &[Complex<f32>]) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+
+        for &sample in samples {
+            self.decim_sum += sample * self.osc;
+            self.osc *= self.osc_step;
+            self.decim_count += 1;
+            if self.decim_count < self.decimation {
+                continue;
+            }
+            let x = self.decim_sum;
+            self.decim_sum = Complex::new(0.0, 0.0);
+            self.decim_count = 0;
+            self.osc /= self.osc.norm();
+
+            // Polar discriminator: positive for the upper tone
+            let freq = (x * self.prev.conj()).arg();
+            self.prev = x;
+            if let Some(level) = self.clock.push(freq > 0.0) {
+                let bit = self.nrzi.decode(level);
+                if let Some(frame) = self.deframer.push(bit) {
+                    frames.push(frame);
+                }
+            }
+        }
+
+        frames
+    }
+}
+
+/// Streaming AIS decoder for one or both channels
+pub struct AisDecoder {
+    sample_rate: u32,
+    center_freq: u32,
+    channels: Vec<AisChannel>,
+    sequence: u8,
+    /// NMEA sentence output (e.g. the TCP server), if any
+    nmea_tx: Option<Sender<String>>,
+}
+
+impl AisDecoder {
+    /// Create a decoder for IQ at `sample_rate` tuned to `center_freq`
+    ///
+    /// Returns None when neither channel is within the IQ bandwidth.
+    pub fn new(sample_rate: u32, center_freq: u32) -> Option<Self> {
+        // Keep each channel's 25 kHz away from the band edge
+        let reach = (sample_rate / 2).saturating_sub(12_500) as i64 + TUNE_TOLERANCE as i64;
+        let channels: Vec<AisChannel> = [('A', CHANNEL_A), ('B', CHANNEL_B)]
+            .iter()
+            .filter_map(|&(name, freq)| {
+                let offset = freq as i64 - center_freq as i64;
+                // A channel tuned directly is demodulated without mixing
+                let offset = if offset.unsigned_abs() <= TUNE_TOLERANCE as u64 { 0 } else { offset };
+                (offset.abs() <= reach).then(|| AisChannel::new(name, sample_rate, offset as i32))
+            })
+            .collect();
+
+        (!channels.is_empty()).then_some(Self {
+            sample_rate,
+            center_freq,
+            channels,
+            sequence: 0,
+            nmea_tx: None,
+        })
+    }
+
+    /// Also send every decoded frame as NMEA sentences to `tx`
+    pub fn with_nmea(mut self, tx: Option<Sender<String>>) -> Self {
+        self.nmea_tx = tx;
+        self
+    }
+
+    /// Feed IQ samples; returns any messages decoded
+    pub fn process(&mut self, samples: &[Complex<f32>]) -> Vec<AisReport> {
+        let mut reports = Vec::new();
+
+        for channel in self.channels.iter_mut() {
+            for frame in channel.process(samples) {
+                let Some(message) = AisMessage::parse(&frame) else {
+                    continue;
+                };
+                self.sequence = self.sequence.wrapping_add(1);
+                reports.push(AisReport {
+                    channel: channel.name,
+                    message,
+                    nmea: nmea_sentences(&frame, channel.name, self.sequence),
+                });
+            }
+        }
+
+        reports
+    }
+}
+
+impl Decoder for AisDecoder {
+    fn name(&self) -> &'static str {
+        "AIS"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Iq
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Iq(samples) = input else {
+            return Vec::new();
+        };
+
+        let mut messages = Vec::new();
+        for report in AisDecoder::process(self, samples) {
+            if let Some(tx) = &self.nmea_tx {
+                for sentence in &report.nmea {
+                    let _ = tx.try_send(sentence.clone());
+                }
+            }
+            let mut fields = report.message.fields();
+            fields["channel"] = report.channel.to_string().into();
+            messages.push(
+                DecodedMessage::new(
+                    DemodMode::Ais,
+                    format!("[{}] {}", report.channel, report.message.summary()),
+                )
+                .with_fields(fields),
+            );
+        }
+        messages
+    }
+
+    fn reset(&mut self) {
+        if let Some(decoder) = AisDecoder::new(self.sample_rate, self.center_freq) {
+            self.channels = decoder.channels;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hdlc::{encode_frame, nrzi_encode};
+    use super::*;
+
+    /// gpsd's reference type 1 sentence
+    const SAMPLE_PAYLOAD: &str = "15RTgt0PAso;90TKcjM8h6g208CQ";
+
+    /// Payload bytes from an NMEA armored payload
+    fn dearmor(payload: &str) -> Vec<u8> {
+        let bits: Vec<bool> = payload
+            .bytes()
+            .flat_map(|c| {
+                let v = if c >= 96 { c - 56 } else { c - 48 };
+                (0..6).rev().map(move |i| v >> i & 1 == 1)
+            })
+            .collect();
+        bits.chunks(8)
+            .filter(|c| c.len() == 8)
+            .map(|c| c.iter().fold(0u8, |b, &bit| (b << 1) | u8::from(bit)))
+            .collect()
+    }
+
+    /// Build a payload MSB first from (value, width) fields
+    fn pack(fields: &[(u64, usize)]) -> Vec<u8> {
+        let mut bits = Vec::new();
+        for &(value, width) in fields {
+            bits.extend((0..width).rev().map(|i| value >> i & 1 == 1));
+        }
+        bits.resize(bits.len().div_ceil(8) * 8, false);
+        bits.chunks(8)
+            .map(|c| c.iter().fold(0u8, |b, &bit| (b << 1) | u8::from(bit)))
+            .collect()
+    }
+
+    fn sixbit(text: &str, chars: usize) -> Vec<(u64, usize)> {
+        format!("{:@<width$}", text, width = chars)
+            .bytes()
+            .map(|c| (u64::from(if c >= 64 { c - 64 } else { c }), 6))
+            .collect()
+    }
+
+    fn static_payload() -> Vec<u8> {
+        let mut fields = vec![(5, 6), (0, 2), (244_670_316, 30), (0, 2), (9_123_456, 30)];
+        fields.extend(sixbit("PD1234", 7));
+        fields.extend(sixbit("NORTH STAR", 20));
+        fields.extend([(70, 8), (0, 30), (0, 4), (0, 20), (0, 8)]);
+        fields.extend(sixbit("ROTTERDAM", 20));
+        fields.extend([(0, 1), (0, 1)]);
+        pack(&fields)
+    }
+
+    /// GMSK-modulate frames (with training sequences) into IQ
+    fn modulate(frames: &[Vec<u8>], sample_rate: u32, offset_hz: f32) -> Vec<Complex<f32>> {
+        let mut bits = Vec::new();
+        for frame in frames {
+            bits.extend((0..24).map(|i| i % 2 == 1));
+            bits.extend(encode_frame(frame));
+            bits.extend([true; 8]);
+        }
+        let levels = nrzi_encode(&bits);
+
+        // Gaussian pulse shaping (BT 0.4) of the NRZ frequency signal
+        let spb = sample_rate as f32 / BAUD;
+        let sigma = (2f32.ln()).sqrt() / (2.0 * PI * 0.4) * spb;
+        let half = (1.5 * spb) as i32;
+        let kernel: Vec<f32> = (-half..=half).map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+        let norm: f32 = kernel.iter().sum();
+
+        let padding = sample_rate as usize / 100;
+        let len = padding * 2 + (levels.len() as f32 * spb) as usize;
+        let nrz: Vec<f32> = (0..len)
+            .map(|i| {
+                let bit = (i as f32 - padding as f32) / spb;
+                match levels.get(bit as usize) {
+                    Some(&level) if bit >= 0.0 => if level { 1.0 } else { -1.0 },
+                    _ => 0.0,
+                }
+            })
+            .collect();
+
+        let mut phase = 0.0f32;
+        (0..len)
+            .map(|i| {
+                let shaped: f32 = kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| w * nrz.get((i + k).wrapping_sub(half as usize)).copied().unwrap_or(0.0))
+                    .sum::<f32>()
+                    / norm;
+                // Modulation index 0.5: +/- 2400 Hz deviation
+                phase += 2.0 * PI * (offset_hz + shaped * BAUD / 4.0) / sample_rate as f32;
+                Complex::from_polar(0.5, phase)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_reference_position() {
+        let Some(AisMessage::Position { mmsi, status, sog, lat, lon, cog, heading }) =
+            AisMessage::parse(&dearmor(SAMPLE_PAYLOAD))
+        else {
+            panic!("not a position report");
+        };
+        assert_eq!(mmsi, 371_798_000);
+        assert_eq!(status, 0);
+        assert_eq!(sog, Some(12.3));
+        assert!((lat.unwrap() - 48.38163).abs() < 1e-4);
+        assert!((lon.unwrap() + 123.39538).abs() < 1e-4);
+        assert_eq!(cog, Some(224.0));
+        assert_eq!(heading, Some(215));
+    }
+
+    #[test]
+    fn test_nmea_round_trip() {
+        let sentences = nmea_sentences(&dearmor(SAMPLE_PAYLOAD), 'A', 1);
+        assert_eq!(sentences, vec![format!("!AIVDM,1,1,,A,{},0*4A", SAMPLE_PAYLOAD)]);
+    }
+
+    #[test]
+    fn test_parse_static_data() {
+        let payload = static_payload();
+        assert_eq!(
+            AisMessage::parse(&payload),
+            Some(AisMessage::Static {
+                mmsi: 244_670_316,
+                callsign: "PD1234".to_string(),
+                name: "NORTH STAR".to_string(),
+                ship_type: 70,
+                destination: "ROTTERDAM".to_string(),
+            })
+        );
+        // 424 bits need two sentences
+        let sentences = nmea_sentences(&payload, 'B', 3);
+        assert_eq!(sentences.len(), 2);
+        assert!(sentences[0].starts_with("!AIVDM,2,1,3,B,"));
+        assert!(sentences[1].starts_with("!AIVDM,2,2,3,B,"));
+    }
+
+    #[test]
+    fn test_decode_gmsk_single_channel() {
+        let frames = vec![dearmor(SAMPLE_PAYLOAD), static_payload()];
+        let iq = modulate(&frames, 96_000, 0.0);
+
+        let mut decoder = AisDecoder::new(96_000, CHANNEL_A).unwrap();
+        let reports: Vec<AisReport> = iq.chunks(8192).flat_map(|c| decoder.process(c)).collect();
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].message, AisMessage::parse(&frames[0]).unwrap());
+        assert_eq!(reports[0].nmea[0], format!("!AIVDM,1,1,,A,{},0*4A", SAMPLE_PAYLOAD));
+        assert!(matches!(reports[1].message, AisMessage::Static { mmsi: 244_670_316, .. }));
+    }
+
+    #[test]
+    fn test_decode_both_channels_from_162_mhz() {
+        let rate = 240_000;
+        let mut iq = modulate(&[static_payload()], rate, 25_000.0);
+        for (x, a) in iq.iter_mut().zip(modulate(&[dearmor(SAMPLE_PAYLOAD)], rate, -25_000.0)) {
+            *x += a;
+        }
+
+        let mut decoder = AisDecoder::new(rate, 162_000_000).unwrap();
+        let reports = decoder.process(&iq);
+        let channels: Vec<char> = reports.iter().map(|r| r.channel).collect();
+        assert_eq!(channels, vec!['A', 'B']);
+    }
+
+    #[test]
+    fn test_out_of_band_tuning() {
+        assert!(AisDecoder::new(2_048_000, 144_390_000).is_none());
+        assert_eq!(AisDecoder::new(2_048_000, 162_000_000).unwrap().channels.len(), 2);
+    }
+}
@@ -0,0 +1,1264 @@
+//! APRS decoder: Bell 202 AFSK, AX.25 framing and APRS packet parsing
+//!
+//! APRS packets are AX.25 UI frames sent as 1200 baud AFSK on FM audio, with
+//! 1200 Hz and 2200 Hz tones. The line is NRZI coded and HDLC framed (see
+//! [`super::hdlc`]). The tones are told apart by the sliding one-bit
+//! correlators in [`super::fsk`], with the bit clock pulled onto tone changes.
+//!
+//! The information field is parsed into [`AprsData`] for the common types:
+//! positions (uncompressed, compressed and Mic-E, with any weather report),
+//! positionless weather, status, messages and telemetry. Anything else, or
+//! anything malformed, is kept as text; the raw packet in TNC2 form goes
+//! along with every parsed summary.
+//!
+//! The parsing is done here rather than with the aprs-parser crate. Frames
+//! arrive as AX.25 with the addresses already decoded, which the crate would
+//! need written back out as TNC2 text, and a summary wants whatever could be
+//! read: a position with a mangled comment, a weather report field by field.
+//! The crate's decode is all or nothing, one error for the whole packet.
+
+use super::fsk::{BitClock, Boxcar, ToneCorrelator};
+use super::hdlc::{HdlcDeframer, Nrzi};
+use super::{Decoder, DecoderInput, InputKind};
+use crate::dsp::filters::Biquad;
+use crate::types::{DecodedMessage, DemodMode};
+use serde::Serialize;
+use std::fmt;
+
+/// Rate the audio is averaged down to before demodulation
+const DETECT_RATE: u32 = 24_000;
+/// Bit rate in bits per second
+const BAUD: f32 = 1200.0;
+/// Bell 202 mark and space tones in Hz
+const MARK: f32 = 1200.0;
+const SPACE: f32 = 2200.0;
+/// Clock correction applied at each tone change
+const CLOCK_GAIN: f32 = 0.3;
+/// Most digipeaters an AX.25 frame can list
+const MAX_DIGIPEATERS: usize = 8;
+const KNOTS_TO_MPH: f32 = 1.150_779;
+const METRES_TO_FEET: f64 = 3.280_84;
+
+/// An AX.25 station address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub call: String,
+    pub ssid: u8,
+    /// Set on digipeaters the frame has been through
+    pub repeated: bool,
+}
+
+impl Address {
+    /// Parse a 7-byte address field: six shifted callsign characters and the
+    /// SSID byte
+    fn parse(field: &[u8]) -> Option<Self> {
+        let call: String = field[..6].iter().map(|&b| (b >> 1) as char).collect();
+        let call = call.trim_end().to_string();
+        if call.is_empty() || !call.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        Some(Self {
+            call,
+            ssid: field[6] >> 1 & 0x0F,
+            repeated: field[6] & 0x80 != 0,
+        })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.ssid {
+            0 => write!(f, "{}", self.call),
+            ssid => write!(f, "{}-{}", self.call, ssid),
+        }
+    }
+}
+
+/// Weather report fields, each present only if the station sent it
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Weather {
+    /// Direction the wind blows from, in degrees
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wind_direction: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wind_speed_mph: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wind_gust_mph: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature_f: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rain_hour_in: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rain_24h_in: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rain_midnight_in: Option<f32>,
+    /// Relative humidity in percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub humidity: Option<u8>,
+    /// Barometric pressure in hPa (mbar)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pressure_hpa: Option<f32>,
+}
+
+impl Weather {
+    /// Parse weather fields (a letter and a fixed number of digits each, dots
+    /// or spaces if unknown) from the start of `text`; returns the report and
+    /// the text after it
+    fn parse(text: &str) -> (Self, &str) {
+        let mut weather = Self::default();
+        let mut rest = text;
+        while let Some(letter) = rest.chars().next() {
+            let width = match letter {
+                'c' | 's' | 'g' | 't' | 'r' | 'p' | 'P' | 'L' | 'l' | '#' => 3,
+                'h' => 2,
+                'b' => 5,
+                _ => break,
+            };
+            let Some(digits) = rest.get(1..1 + width) else {
+                break;
+            };
+            let value = if digits.chars().all(|c| c == '.' || c == ' ') {
+                None
+            } else {
+                match digits.parse::<i32>() {
+                    Ok(value) => Some(value),
+                    Err(_) => break,
+                }
+            };
+            rest = &rest[1 + width..];
+
+            let unsigned = value.and_then(|v| u16::try_from(v).ok());
+            let inches = unsigned.map(|v| v as f32 / 100.0);
+            match letter {
+                'c' => weather.wind_direction = unsigned.filter(|&d| d <= 360),
+                's' => weather.wind_speed_mph = unsigned,
+                'g' => weather.wind_gust_mph = unsigned,
+                't' => weather.temperature_f = value.map(|v| v as i16),
+                'r' => weather.rain_hour_in = inches,
+                'p' => weather.rain_24h_in = inches,
+                'P' => weather.rain_midnight_in = inches,
+                // 00 is 100%
+                'h' => {
+                    weather.humidity =
+                        unsigned
+                            .filter(|&h| h < 100)
+                            .map(|h| if h == 0 { 100 } else { h as u8 })
+                }
+                'b' => weather.pressure_hpa = unsigned.map(|v| v as f32 / 10.0),
+                // Luminosity and the raw rain counter aren't kept
+                _ => {}
+            }
+        }
+        (weather, rest)
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The fields present, e.g. "wind 220° 4mph gust 5mph 77°F 50%"
+    fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        match (self.wind_direction, self.wind_speed_mph) {
+            (Some(direction), Some(speed)) => {
+                parts.push(format!("wind {}° {}mph", direction, speed))
+            }
+            (None, Some(speed)) => parts.push(format!("wind {}mph", speed)),
+            (Some(direction), None) => parts.push(format!("wind {}°", direction)),
+            (None, None) => {}
+        }
+        if let Some(gust) = self.wind_gust_mph {
+            parts.push(format!("gust {}mph", gust));
+        }
+        if let Some(temperature) = self.temperature_f {
+            parts.push(format!("{}°F", temperature));
+        }
+        if let Some(rain) = self.rain_hour_in {
+            parts.push(format!("rain {:.2}in/h", rain));
+        }
+        if let Some(rain) = self.rain_24h_in {
+            parts.push(format!("{:.2}in/24h", rain));
+        }
+        if let Some(humidity) = self.humidity {
+            parts.push(format!("{}%", humidity));
+        }
+        if let Some(pressure) = self.pressure_hpa {
+            parts.push(format!("{:.1}hPa", pressure));
+        }
+        parts.join(" ")
+    }
+}
+
+/// A position report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Position {
+    /// Degrees, north positive
+    #[serde(rename = "lat")]
+    pub latitude: f64,
+    /// Degrees, east positive
+    #[serde(rename = "lon")]
+    pub longitude: f64,
+    /// Symbol table ('/' primary, '\\' alternate, or an overlay character)
+    pub symbol_table: char,
+    pub symbol_code: char,
+    /// Course over ground in degrees
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub course: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_knots: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude_ft: Option<i32>,
+    /// Weather report from a weather station ('_' symbol)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weather: Option<Weather>,
+    pub comment: String,
+}
+
+impl Position {
+    /// Parse a position, uncompressed or compressed, and what follows it
+    fn parse(body: &str) -> Option<Self> {
+        let first = body.chars().next()?;
+        let (mut position, rest) = if first.is_ascii_digit() || first == ' ' {
+            Self::uncompressed(body)?
+        } else {
+            Self::compressed(body)?
+        };
+        position.extensions(rest);
+        Some(position)
+    }
+
+    /// "4903.50N/07201.75W-": latitude, symbol table, longitude, symbol code
+    fn uncompressed(body: &str) -> Option<(Self, &str)> {
+        let latitude = coordinate(body.get(..8)?, 2, 'N', 'S', 90.0)?;
+        let longitude = coordinate(body.get(9..18)?, 3, 'E', 'W', 180.0)?;
+        let position = Self::new(latitude, longitude, symbol(body, 8)?, symbol(body, 18)?);
+        Some((position, body.get(19..)?))
+    }
+
+    /// "/5L!!<*e7>7P[": symbol table, base-91 latitude and longitude, symbol
+    /// code, course and speed (or altitude) and the compression type
+    fn compressed(body: &str) -> Option<(Self, &str)> {
+        let field = body.get(..13)?.as_bytes();
+        let table = field[0] as char;
+        if !matches!(table, '/' | '\\' | 'A'..='Z' | 'a'..='j') {
+            return None;
+        }
+        let latitude = 90.0 - base91(&field[1..5])? as f64 / 380_926.0;
+        let longitude = -180.0 + base91(&field[5..9])? as f64 / 190_463.0;
+        if latitude.abs() > 90.0 || longitude.abs() > 180.0 {
+            return None;
+        }
+        let mut position = Self::new(latitude, longitude, table, symbol(body, 9)?);
+
+        if field[10] != b' ' {
+            let (c, s) = (base91(&field[10..11])?, base91(&field[11..12])?);
+            let kind = base91(&field[12..13])?;
+            if kind >> 3 & 3 == 2 {
+                // From a GGA sentence: the two bytes are the altitude
+                position.altitude_ft = Some(1.002f64.powi((c * 91 + s) as i32) as i32);
+            } else if c <= 89 {
+                position.course = Some(c as u16 * 4).filter(|&course| course > 0);
+                position.speed_knots = Some(1.08f32.powi(s as i32) - 1.0);
+            }
+        }
+        Some((position, body.get(13..)?))
+    }
+
+    fn new(latitude: f64, longitude: f64, symbol_table: char, symbol_code: char) -> Self {
+        Self {
+            latitude,
+            longitude,
+            symbol_table,
+            symbol_code,
+            course: None,
+            speed_knots: None,
+            altitude_ft: None,
+            weather: None,
+            comment: String::new(),
+        }
+    }
+
+    /// Take course and speed (wind for weather stations), weather, altitude
+    /// and the comment from the text after the position
+    fn extensions(&mut self, mut rest: &str) {
+        let weather_station = self.symbol_code == '_';
+        let course_speed = rest
+            .get(..7)
+            .filter(|field| field.as_bytes()[3] == b'/')
+            .and_then(|field| {
+                let number = |digits: &str| match digits.trim_matches(['.', ' ']) {
+                    "" => Some(None),
+                    digits => digits.parse::<u16>().ok().map(Some),
+                };
+                Some((number(&field[..3])?, number(&field[4..])?))
+            });
+
+        let mut weather = Weather::default();
+        if let Some((course, speed)) = course_speed {
+            rest = &rest[7..];
+            if weather_station {
+                weather.wind_direction = course.filter(|&d| d <= 360);
+                weather.wind_speed_mph = speed;
+            } else if self.course.is_none() && self.speed_knots.is_none() {
+                self.course = course.filter(|&c| (1..=360).contains(&c));
+                self.speed_knots = speed.map(f32::from);
+            }
+        }
+        if weather_station {
+            let (fields, after) = Weather::parse(rest);
+            rest = after;
+            weather = Weather {
+                wind_direction: weather.wind_direction.or(fields.wind_direction),
+                wind_speed_mph: weather.wind_speed_mph.or(fields.wind_speed_mph),
+                ..fields
+            };
+            if !weather.is_empty() {
+                self.weather = Some(weather);
+            }
+        }
+
+        let mut comment = rest.to_string();
+        if let Some(start) = comment.find("/A=") {
+            if let Some(altitude) = comment
+                .get(start + 3..start + 9)
+                .and_then(|a| a.parse().ok())
+            {
+                self.altitude_ft = Some(altitude);
+                comment.replace_range(start..start + 9, "");
+            }
+        }
+        self.comment = comment.trim().to_string();
+    }
+
+    /// e.g. "47.6062N 122.3321W 35mph 1200ft [En Route] → camping"
+    fn summary(&self, status: Option<&str>) -> String {
+        let mut parts = vec![format!(
+            "{:.4}{} {:.4}{}",
+            self.latitude.abs(),
+            if self.latitude < 0.0 { 'S' } else { 'N' },
+            self.longitude.abs(),
+            if self.longitude < 0.0 { 'W' } else { 'E' }
+        )];
+        if let Some(speed) = self.speed_knots.filter(|&s| s >= 0.5) {
+            parts.push(format!("{:.0}mph", speed * KNOTS_TO_MPH));
+        }
+        if let Some(altitude) = self.altitude_ft {
+            parts.push(format!("{}ft", altitude));
+        }
+        if let Some(status) = status {
+            parts.push(format!("[{}]", status));
+        }
+        if let Some(weather) = &self.weather {
+            parts.push(weather.summary());
+        }
+        if !self.comment.is_empty() {
+            parts.push(format!("→ {}", self.comment));
+        }
+        parts.join(" ")
+    }
+}
+
+/// The symbol character at byte `index`, if printable
+fn symbol(body: &str, index: usize) -> Option<char> {
+    body.as_bytes()
+        .get(index)
+        .map(|&b| b as char)
+        .filter(|c| c.is_ascii_graphic())
+}
+
+/// Parse "DDMM.hhN" (`degree_digits` of degrees) to signed degrees; spaces
+/// from position ambiguity count as zeros
+fn coordinate(
+    field: &str,
+    degree_digits: usize,
+    positive: char,
+    negative: char,
+    max: f64,
+) -> Option<f64> {
+    let field = field.replace(' ', "0");
+    let degrees = field.get(..degree_digits)?;
+    if !degrees.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let degrees: f64 = degrees.parse().ok()?;
+    let minutes_field = field.get(degree_digits..field.len() - 1)?;
+    if minutes_field.as_bytes().get(2) != Some(&b'.')
+        || !minutes_field
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b == b'.')
+    {
+        return None;
+    }
+    let minutes: f64 = minutes_field.parse().ok()?;
+    let value = degrees + minutes / 60.0;
+    if minutes >= 60.0 || value > max {
+        return None;
+    }
+    match field.chars().last()? {
+        c if c == positive => Some(value),
+        c if c == negative => Some(-value),
+        _ => None,
+    }
+}
+
+/// Base-91 number from printable characters ('!' is 0)
+fn base91(chars: &[u8]) -> Option<u32> {
+    chars.iter().try_fold(0u32, |value, &c| {
+        (b'!'..=b'{')
+            .contains(&c)
+            .then(|| value * 91 + (c - b'!') as u32)
+    })
+}
+
+/// Whether `text` starts with a "DDHHMMz"-style timestamp
+fn has_timestamp(text: &str) -> bool {
+    text.as_bytes().get(..7).is_some_and(|stamp| {
+        stamp[..6].iter().all(u8::is_ascii_digit) && matches!(stamp[6], b'z' | b'/' | b'h')
+    })
+}
+
+/// The parsed information field of an APRS packet
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AprsData {
+    Position(Position),
+    /// Mic-E position, with the status the destination address encodes
+    MicE {
+        #[serde(flatten)]
+        position: Position,
+        status: String,
+    },
+    /// Weather report without a position
+    Weather(Weather),
+    Status {
+        text: String,
+    },
+    Message {
+        addressee: String,
+        text: String,
+        /// Message number to acknowledge
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    Telemetry {
+        sequence: String,
+        analog: Vec<f32>,
+        /// The eight digital channels as 0s and 1s, first channel first
+        #[serde(skip_serializing_if = "Option::is_none")]
+        digital: Option<String>,
+    },
+    /// Any other or malformed information field, as text
+    Other {
+        text: String,
+    },
+}
+
+impl AprsData {
+    /// Parse an information field; `destination` is the destination call,
+    /// which Mic-E uses to carry the latitude
+    pub fn parse(destination: &str, info: &str) -> Self {
+        Self::try_parse(destination, info).unwrap_or_else(|| AprsData::Other {
+            text: info.to_string(),
+        })
+    }
+
+    fn try_parse(destination: &str, info: &str) -> Option<Self> {
+        let mut chars = info.chars();
+        let kind = chars.next()?;
+        let body = chars.as_str();
+        match kind {
+            '!' | '=' => Position::parse(body).map(AprsData::Position),
+            '/' | '@' => has_timestamp(body)
+                .then(|| Position::parse(&body[7..]).map(AprsData::Position))
+                .flatten(),
+            '`' | '\'' | '\x1c' | '\x1d' => mic_e(destination, body),
+            '_' => {
+                // Positionless weather starts with an MDHM timestamp
+                let stamp = body.get(..8)?;
+                if !stamp.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                let (weather, _) = Weather::parse(&body[8..]);
+                (!weather.is_empty()).then_some(AprsData::Weather(weather))
+            }
+            '>' => {
+                let text = if has_timestamp(body) {
+                    &body[7..]
+                } else {
+                    body
+                };
+                Some(AprsData::Status {
+                    text: text.trim().to_string(),
+                })
+            }
+            ':' => {
+                if body.as_bytes().get(9) != Some(&b':') {
+                    return None;
+                }
+                let addressee = body.get(..9)?.trim().to_string();
+                let text = &body[10..];
+                let (text, id) = match text.rfind('{') {
+                    Some(brace) => (&text[..brace], Some(text[brace + 1..].trim().to_string())),
+                    None => (text, None),
+                };
+                (!addressee.is_empty()).then(|| AprsData::Message {
+                    addressee,
+                    text: text.trim().to_string(),
+                    id,
+                })
+            }
+            'T' => telemetry(body),
+            _ => None,
+        }
+    }
+}
+
+/// "#005,199,000,255,073,123,01101001": sequence, up to five analog values
+/// and the digital channels
+fn telemetry(body: &str) -> Option<AprsData> {
+    let mut parts = body.strip_prefix('#')?.split(',');
+    let sequence = parts.next()?.trim().to_string();
+    let values: Vec<&str> = parts.collect();
+    let analog = values
+        .iter()
+        .take(5)
+        .map(|v| v.trim().parse::<f32>().ok().filter(|v| v.is_finite()))
+        .collect::<Option<Vec<f32>>>()?;
+    let digital = values
+        .get(5)
+        .and_then(|bits| bits.get(..8))
+        .filter(|bits| bits.bytes().all(|b| b == b'0' || b == b'1'))
+        .map(str::to_string);
+    (!sequence.is_empty() && !analog.is_empty()).then_some(AprsData::Telemetry {
+        sequence,
+        analog,
+        digital,
+    })
+}
+
+/// Mic-E: latitude, status bits and hemisphere flags in the destination
+/// call, longitude, speed, course and symbol in the information field
+fn mic_e(destination: &str, body: &str) -> Option<AprsData> {
+    let destination = destination.as_bytes();
+    if destination.len() != 6 {
+        return None;
+    }
+
+    // Each destination character is a latitude digit and a status bit:
+    // 0 = '0', 1 = standard '1', 2 = custom '1'
+    let mut digits = [0u8; 6];
+    let mut bits = [0u8; 3];
+    for (i, &c) in destination.iter().enumerate() {
+        let (digit, bit) = match c {
+            b'0'..=b'9' => (c - b'0', 0),
+            b'A'..=b'J' => (c - b'A', 2),
+            b'K' => (0, 2),
+            b'L' => (0, 0),
+            b'P'..=b'Y' => (c - b'P', 1),
+            b'Z' => (0, 1),
+            _ => return None,
+        };
+        digits[i] = digit;
+        if i < 3 {
+            bits[i] = bit;
+        }
+    }
+    let flag = |i: usize| destination[i] >= b'P';
+    let minutes = (digits[2] * 10 + digits[3]) as f64 + (digits[4] * 10 + digits[5]) as f64 / 100.0;
+    let latitude = (digits[0] * 10 + digits[1]) as f64 + minutes / 60.0;
+    if minutes >= 60.0 || latitude > 90.0 {
+        return None;
+    }
+
+    let info = body.as_bytes();
+    let value = |i: usize| info.get(i).and_then(|b| b.checked_sub(28)).map(u32::from);
+    let mut degrees = value(0)? + if flag(4) { 100 } else { 0 };
+    if (180..=189).contains(&degrees) {
+        degrees -= 80;
+    } else if (190..=199).contains(&degrees) {
+        degrees -= 190;
+    }
+    let mut lon_minutes = value(1)?;
+    if lon_minutes >= 60 {
+        lon_minutes -= 60;
+    }
+    let hundredths = value(2)?;
+    let longitude = degrees as f64 + (lon_minutes as f64 + hundredths as f64 / 100.0) / 60.0;
+    if hundredths >= 100 || longitude > 180.0 {
+        return None;
+    }
+
+    let (sp, dc, se) = (value(3)?, value(4)?, value(5)?);
+    let mut speed = sp * 10 + dc / 10;
+    let mut course = dc % 10 * 100 + se;
+    if speed >= 800 {
+        speed -= 800;
+    }
+    if course >= 400 {
+        course -= 400;
+    }
+
+    let mut position = Position::new(
+        if flag(3) { latitude } else { -latitude },
+        if flag(5) { -longitude } else { longitude },
+        symbol(body, 7)?,
+        symbol(body, 6)?,
+    );
+    position.speed_knots = Some(speed as f32);
+    position.course = Some(course as u16).filter(|&c| (1..=360).contains(&c));
+
+    // The comment may start with a radio type byte, then "xxx}" altitude in
+    // base-91 metres above -10 km
+    let mut comment = body.get(8..).unwrap_or("");
+    comment = comment.strip_prefix(['>', ']']).unwrap_or(comment);
+    if comment.as_bytes().get(3) == Some(&b'}') {
+        if let Some(metres) = base91(&comment.as_bytes()[..3]) {
+            position.altitude_ft =
+                Some(((metres as f64 - 10_000.0) * METRES_TO_FEET).round() as i32);
+            comment = &comment[4..];
+        }
+    }
+    position.comment = comment.trim().to_string();
+
+    Some(AprsData::MicE {
+        position,
+        status: mic_e_status(bits).to_string(),
+    })
+}
+
+/// The status named by the three Mic-E message bits
+fn mic_e_status(bits: [u8; 3]) -> &'static str {
+    const STANDARD: [&str; 8] = [
+        "Emergency",
+        "Priority",
+        "Special",
+        "Committed",
+        "Returning",
+        "In Service",
+        "En Route",
+        "Off Duty",
+    ];
+    const CUSTOM: [&str; 8] = [
+        "Emergency",
+        "Custom-6",
+        "Custom-5",
+        "Custom-4",
+        "Custom-3",
+        "Custom-2",
+        "Custom-1",
+        "Custom-0",
+    ];
+    let index = bits
+        .iter()
+        .fold(0, |index, &bit| index << 1 | usize::from(bit != 0));
+    match (bits.contains(&1), bits.contains(&2)) {
+        (true, true) => "Unknown",
+        (false, true) => CUSTOM[index],
+        _ => STANDARD[index],
+    }
+}
+
+/// A decoded APRS packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct AprsPacket {
+    pub source: Address,
+    pub destination: Address,
+    /// Digipeater path
+    pub path: Vec<Address>,
+    /// Information field as text
+    pub info: String,
+    pub data: AprsData,
+}
+
+impl AprsPacket {
+    /// Parse an AX.25 frame (without FCS); None unless it is a UI frame
+    /// without a layer 3 protocol, as APRS uses
+    pub fn from_frame(frame: &[u8]) -> Option<Self> {
+        let mut addresses = Vec::new();
+        let mut pos = 0;
+        loop {
+            let field = frame.get(pos..pos + 7)?;
+            addresses.push(Address::parse(field)?);
+            pos += 7;
+            if field[6] & 1 == 1 {
+                break;
+            }
+            if addresses.len() == 2 + MAX_DIGIPEATERS {
+                return None;
+            }
+        }
+        if addresses.len() < 2 || frame.get(pos..pos + 2)? != [0x03, 0xF0] {
+            return None;
+        }
+
+        let info = String::from_utf8_lossy(&frame[pos + 2..])
+            .trim_end_matches(['\r', '\n'])
+            .to_string();
+        let mut addresses = addresses.into_iter();
+        let mut destination = addresses.next()?;
+        let mut source = addresses.next()?;
+        // The top bits of these two are command/response flags
+        destination.repeated = false;
+        source.repeated = false;
+        Some(Self {
+            data: AprsData::parse(&destination.call, &info),
+            source,
+            destination,
+            path: addresses.collect(),
+            info,
+        })
+    }
+
+    /// The packet in TNC2 monitor form, e.g. "N0CALL-9>APRS,WIDE1-1*:!..."
+    pub fn tnc2(&self) -> String {
+        let last_repeated = self.path.iter().rposition(|a| a.repeated);
+        let path: String = self
+            .path
+            .iter()
+            .enumerate()
+            .map(|(i, address)| {
+                format!(
+                    ",{}{}",
+                    address,
+                    if Some(i) == last_repeated { "*" } else { "" }
+                )
+            })
+            .collect();
+        let info: String = self
+            .info
+            .chars()
+            .map(|c| if c.is_control() { '.' } else { c })
+            .collect();
+        format!("{}>{}{}:{}", self.source, self.destination, path, info)
+    }
+
+    /// One-line summary for the message list
+    pub fn summary(&self) -> String {
+        let source = &self.source;
+        match &self.data {
+            AprsData::Position(position) => format!("{} {}", source, position.summary(None)),
+            AprsData::MicE { position, status } => {
+                format!("{} {}", source, position.summary(Some(status)))
+            }
+            AprsData::Weather(weather) => format!("{} weather {}", source, weather.summary()),
+            AprsData::Status { text } => format!("{} status: {}", source, text),
+            AprsData::Message {
+                addressee,
+                text,
+                id,
+            } => match id {
+                Some(id) => format!("{} → {}: {} (#{})", source, addressee, text, id),
+                None => format!("{} → {}: {}", source, addressee, text),
+            },
+            AprsData::Telemetry {
+                sequence,
+                analog,
+                digital,
+            } => {
+                let values: Vec<String> = analog.iter().map(|v| v.to_string()).collect();
+                let mut summary =
+                    format!("{} telemetry #{}: {}", source, sequence, values.join(" "));
+                if let Some(digital) = digital {
+                    summary.push_str(&format!(" [{}]", digital));
+                }
+                summary
+            }
+            AprsData::Other { .. } => {
+                let info: String = self
+                    .info
+                    .chars()
+                    .map(|c| if c.is_control() { '.' } else { c })
+                    .collect();
+                format!("{} {}", source, info)
+            }
+        }
+    }
+
+    /// Structured fields for logs: addresses and the parsed data
+    pub fn fields(&self) -> serde_json::Value {
+        let mut fields = serde_json::json!({
+            "source": self.source.to_string(),
+            "destination": self.destination.to_string(),
+            "path": self.path.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        });
+        if let (Some(fields), Ok(serde_json::Value::Object(data))) =
+            (fields.as_object_mut(), serde_json::to_value(&self.data))
+        {
+            fields.extend(data);
+        }
+        fields
+    }
+}
+
+/// Streaming APRS decoder on FM-demodulated audio
+pub struct AprsDecoder {
+    /// Input sample rate the decoder was built for
+    sample_rate: u32,
+    /// Averages the audio down to the detection rate
+    boxcar: Boxcar,
+    /// Removes the discriminator's DC offset before the correlators
+    highpass: Biquad,
+    /// Mark and space correlators: true for a space
+    tones: ToneCorrelator,
+    clock: BitClock,
+    nrzi: Nrzi,
+    deframer: HdlcDeframer,
+}
+
+impl AprsDecoder {
+    /// Create a decoder for audio at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        let boxcar = Boxcar::new(sample_rate, DETECT_RATE);
+        let detect_rate = boxcar.rate(sample_rate);
+
+        Self {
+            sample_rate,
+            boxcar,
+            highpass: Biquad::highpass(detect_rate as f64, 300.0, std::f64::consts::FRAC_1_SQRT_2),
+            tones: ToneCorrelator::new(detect_rate, BAUD, [MARK, SPACE]),
+            clock: BitClock::new(detect_rate, BAUD, CLOCK_GAIN),
+            nrzi: Nrzi::default(),
+            deframer: HdlcDeframer::new(),
+        }
+    }
+
+    /// Feed audio; returns the APRS packets in frames that passed the FCS
+    pub fn process(&mut self, audio: &[f32]) -> Vec<AprsPacket> {
+        let mut packets = Vec::new();
+
+        for &sample in audio {
+            let Some(x) = self.boxcar.push(sample) else {
+                continue;
+            };
+            let x = self.highpass.process(x);
+            if let Some(frame) = self.demodulate(x) {
+                match AprsPacket::from_frame(&frame) {
+                    Some(packet) => packets.push(packet),
+                    None => log::debug!("APRS: {}-byte frame is not an APRS packet", frame.len()),
+                }
+            }
+        }
+
+        packets
+    }
+
+    /// Run one detection sample through the correlators, bit clock and
+    /// deframer
+    fn demodulate(&mut self, x: f32) -> Option<Vec<u8>> {
+        let tone = self.clock.push(self.tones.push(x))?;
+        let bit = self.nrzi.decode(tone);
+        self.deframer.push(bit)
+    }
+}
+
+impl Decoder for AprsDecoder {
+    fn name(&self) -> &'static str {
+        "APRS"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Audio
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Audio(audio) = input else {
+            return Vec::new();
+        };
+        AprsDecoder::process(self, audio)
+            .iter()
+            .map(|packet| {
+                DecodedMessage::new(DemodMode::Aprs, packet.summary())
+                    .with_fields(packet.fields())
+                    .with_raw(packet.tnc2())
+            })
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        *self = AprsDecoder::new(self.sample_rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hdlc::{encode_frame, nrzi_encode};
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::PI;
+
+    /// AX.25 UI frame bytes for a TNC2 header "SRC>DST,PATH" and info field
+    fn frame(header: &str, info: &str) -> Vec<u8> {
+        let calls: Vec<&str> = header.split(['>', ',']).collect();
+        // Destination first, then source and path
+        let order = std::iter::once(calls[1])
+            .chain(std::iter::once(calls[0]))
+            .chain(calls[2..].iter().copied());
+        let count = calls.len();
+        let mut bytes = Vec::new();
+        for (i, call) in order.enumerate() {
+            let repeated = call.ends_with('*');
+            let call = call.trim_end_matches('*');
+            let (name, ssid) = call.split_once('-').unwrap_or((call, "0"));
+            bytes.extend(format!("{:<6}", name).bytes().map(|b| b << 1));
+            let mut last = 0x60 | ssid.parse::<u8>().unwrap() << 1 | u8::from(i == count - 1);
+            if repeated {
+                last |= 0x80;
+            }
+            bytes.push(last);
+        }
+        bytes.extend([0x03, 0xF0]);
+        bytes.extend(info.bytes());
+        bytes
+    }
+
+    fn packet(header: &str, info: &str) -> AprsPacket {
+        AprsPacket::from_frame(&frame(header, info)).unwrap()
+    }
+
+    fn position(data: &AprsData) -> &Position {
+        match data {
+            AprsData::Position(position) | AprsData::MicE { position, .. } => position,
+            other => panic!("not a position: {:?}", other),
+        }
+    }
+
+    fn assert_near(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-4, "{} != {}", value, expected);
+    }
+
+    /// AFSK-modulate frames with continuous phase, preceded by flags
+    fn modulate(frames: &[Vec<u8>], rate: u32, noise: f32) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(1200);
+        let flag = [false, true, true, true, true, true, true, false];
+        let mut bits = Vec::new();
+        for frame in frames {
+            for _ in 0..20 {
+                bits.extend(flag);
+            }
+            bits.extend(encode_frame(frame));
+        }
+
+        let spb = rate as f32 / BAUD;
+        let mut audio = vec![0.0; rate as usize / 20];
+        let mut phase = 0.0f32;
+        let mut end = audio.len() as f32;
+        for level in nrzi_encode(&bits) {
+            let freq = if level { SPACE } else { MARK };
+            end += spb;
+            while (audio.len() as f32) < end {
+                phase += 2.0 * PI * freq / rate as f32;
+                audio.push(0.3 * phase.sin() + rng.gen_range(-noise..=noise));
+            }
+        }
+        audio.extend(std::iter::repeat_n(0.0, rate as usize / 20));
+        audio
+    }
+
+    #[test]
+    fn test_uncompressed_position() {
+        let packet = packet(
+            "K7ABC-9>APRS,WIDE1-1*,WIDE2-1",
+            "!4736.37N/12219.93W>088/030/A=000150camping",
+        );
+        assert_eq!(packet.source.to_string(), "K7ABC-9");
+        assert_eq!(
+            packet.tnc2(),
+            "K7ABC-9>APRS,WIDE1-1*,WIDE2-1:!4736.37N/12219.93W>088/030/A=000150camping"
+        );
+
+        let p = position(&packet.data);
+        assert_near(p.latitude, 47.6062);
+        assert_near(p.longitude, -122.3322);
+        assert_eq!((p.symbol_table, p.symbol_code), ('/', '>'));
+        assert_eq!(p.course, Some(88));
+        assert_eq!(p.speed_knots, Some(30.0));
+        assert_eq!(p.altitude_ft, Some(150));
+        assert_eq!(p.comment, "camping");
+        assert_eq!(
+            packet.summary(),
+            "K7ABC-9 47.6062N 122.3322W 35mph 150ft → camping"
+        );
+
+        let fields = packet.fields();
+        assert_eq!(fields["type"], "position");
+        assert_eq!(fields["path"], serde_json::json!(["WIDE1-1", "WIDE2-1"]));
+        assert_eq!(fields["symbol_code"], ">");
+        assert!(fields.get("weather").is_none());
+    }
+
+    #[test]
+    fn test_position_variants() {
+        // Timestamped with messaging, southern and eastern hemispheres
+        let data = AprsData::parse("APRS", "@092345z3352.12S\\15112.34E-PHG5130 Sydney");
+        let p = position(&data);
+        assert_near(p.latitude, -(33.0 + 52.12 / 60.0));
+        assert_near(p.longitude, 151.0 + 12.34 / 60.0);
+        assert_eq!(p.symbol_table, '\\');
+        assert_eq!(p.comment, "PHG5130 Sydney");
+
+        // Position ambiguity: blanked digits read as zeros
+        let data = AprsData::parse("APRS", "=49  .  N/072  .  W-");
+        let p = position(&data);
+        assert_near(p.latitude, 49.0);
+        assert_near(p.longitude, -72.0);
+        assert_eq!(p.comment, "");
+
+        // Unknown course and speed are left out
+        let data = AprsData::parse("APRS", "!4903.50N/07201.75W>000/000");
+        assert_eq!(position(&data).course, None);
+        assert_eq!(position(&data).speed_knots, Some(0.0));
+    }
+
+    #[test]
+    fn test_compressed_position() {
+        // The example from the APRS spec: 49°30'N 72°45'W, course 88°, 36.2 kn
+        let data = AprsData::parse("APRS", "=/5L!!<*e7>7P[test");
+        let p = position(&data);
+        assert_near(p.latitude, 49.5);
+        assert_near(p.longitude, -72.75);
+        assert_eq!(p.symbol_code, '>');
+        assert_eq!(p.course, Some(88));
+        assert!((p.speed_knots.unwrap() - 36.2).abs() < 0.1);
+        assert_eq!(p.comment, "test");
+
+        // Altitude from a GGA fix: 10004 ft
+        let data = AprsData::parse("APRS", "!/5L!!<*e7OS]S");
+        assert_eq!(position(&data).altitude_ft, Some(10004));
+        assert_eq!(position(&data).course, None);
+    }
+
+    #[test]
+    fn test_mic_e() {
+        // 33°25.64'S 112°07.74'W, 20 kn heading 251°, 22 m, "In Service"
+        let packet = packet("N0CALL-9>S3R5VT", "`(_fn\"Oj/]\"4-}Mobile");
+        let AprsData::MicE { position, status } = &packet.data else {
+            panic!("not Mic-E: {:?}", packet.data);
+        };
+        assert_near(position.latitude, -(33.0 + 25.64 / 60.0));
+        assert_near(position.longitude, -(112.0 + 7.74 / 60.0));
+        assert_eq!(position.speed_knots, Some(20.0));
+        assert_eq!(position.course, Some(251));
+        assert_eq!((position.symbol_table, position.symbol_code), ('/', 'j'));
+        assert_eq!(position.altitude_ft, Some(72));
+        assert_eq!(position.comment, "Mobile");
+        assert_eq!(status, "In Service");
+        assert_eq!(packet.fields()["status"], "In Service");
+        assert!(packet
+            .summary()
+            .ends_with("23mph 72ft [In Service] → Mobile"));
+
+        assert_eq!(mic_e_status([1, 1, 1]), "Off Duty");
+        assert_eq!(mic_e_status([0, 0, 0]), "Emergency");
+        assert_eq!(mic_e_status([2, 0, 2]), "Custom-2");
+        assert_eq!(mic_e_status([1, 2, 0]), "Unknown");
+    }
+
+    #[test]
+    fn test_weather() {
+        let data = AprsData::parse("APRS", "_10090556c220s004g005t077r000p000P000h50b09900wRSW");
+        let AprsData::Weather(weather) = &data else {
+            panic!("not weather: {:?}", data);
+        };
+        assert_eq!(weather.wind_direction, Some(220));
+        assert_eq!(weather.wind_speed_mph, Some(4));
+        assert_eq!(weather.wind_gust_mph, Some(5));
+        assert_eq!(weather.temperature_f, Some(77));
+        assert_eq!(weather.rain_hour_in, Some(0.0));
+        assert_eq!(weather.humidity, Some(50));
+        assert_eq!(weather.pressure_hpa, Some(990.0));
+        assert_eq!(
+            weather.summary(),
+            "wind 220° 4mph gust 5mph 77°F rain 0.00in/h 0.00in/24h 50% 990.0hPa"
+        );
+
+        // A weather station's position carries wind in the course/speed slot;
+        // missing values are dots, and the temperature can be negative
+        let data = AprsData::parse(
+            "APRS",
+            "@281215z4903.50N/07201.75W_180/010g...t-05h00b10132eMB51",
+        );
+        let weather = position(&data).weather.clone().unwrap();
+        assert_eq!(weather.wind_direction, Some(180));
+        assert_eq!(weather.wind_speed_mph, Some(10));
+        assert_eq!(weather.wind_gust_mph, None);
+        assert_eq!(weather.temperature_f, Some(-5));
+        assert_eq!(weather.humidity, Some(100));
+        assert_eq!(position(&data).comment, "eMB51");
+        assert_eq!(position(&data).course, None);
+    }
+
+    #[test]
+    fn test_status_message_telemetry() {
+        assert_eq!(
+            AprsData::parse("APRS", ">092345zNet at 8pm on 146.52"),
+            AprsData::Status {
+                text: "Net at 8pm on 146.52".to_string()
+            }
+        );
+
+        let message = packet("N0CALL>APRS", ":KB1XYZ-3 :Hello there{001");
+        assert_eq!(
+            message.data,
+            AprsData::Message {
+                addressee: "KB1XYZ-3".to_string(),
+                text: "Hello there".to_string(),
+                id: Some("001".to_string()),
+            }
+        );
+        assert_eq!(message.summary(), "N0CALL → KB1XYZ-3: Hello there (#001)");
+        assert!(matches!(
+            AprsData::parse("APRS", ":N0CALL   :ack001"),
+            AprsData::Message { id: None, .. }
+        ));
+
+        let telemetry = packet("N0CALL-11>APRS", "T#005,199,000,255,073,123,01101001");
+        assert_eq!(
+            telemetry.data,
+            AprsData::Telemetry {
+                sequence: "005".to_string(),
+                analog: vec![199.0, 0.0, 255.0, 73.0, 123.0],
+                digital: Some("01101001".to_string()),
+            }
+        );
+        assert_eq!(
+            telemetry.summary(),
+            "N0CALL-11 telemetry #005: 199 0 255 73 123 [01101001]"
+        );
+        assert!(matches!(
+            AprsData::parse("APRS", "T#MIC,12.5,4"),
+            AprsData::Telemetry { .. }
+        ));
+    }
+
+    #[test]
+    fn test_malformed_packets_do_not_panic() {
+        let infos = [
+            "",
+            "!",
+            "!4903.50N",
+            "!4903.50N/07201.75",
+            "!9903.50N/07201.75W-",
+            "!4963.50N/07201.75W-",
+            "!4903.50X/07201.75W-",
+            "!49O3.50N/07201.75W-",
+            "!4903.50N/18201.75W-",
+            "=/5L!!<*e7",
+            "=/5L\u{7f}!<*e7>7P[",
+            "@09234",
+            "@0923455N/07201.75W-",
+            "/092345z",
+            "`",
+            "`(_fn\"O",
+            "'\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}",
+            "_1009",
+            "_10090556",
+            "_10090556c2",
+            "_1009055€c220s004",
+            ":",
+            ":SHORT:text",
+            ":         :empty addressee",
+            ":N0CALL   :{",
+            "T#",
+            "T#005",
+            "T#005,abc",
+            "T#005,nan,inf",
+            "!4903.50N/07201.75W_12",
+            "!4903.50N/07201.75W-/A=",
+            "!4903.50N/07201.75W-/A=12€456",
+            "=4903.5€N/07201.75W-",
+            "}N0CALL>APRS,TCPIP:>third party",
+            "\u{0}\u{1}\u{2}",
+        ];
+        for info in infos {
+            for destination in ["APRS", "S32U6T", "", "ZZZZZZ", "S3€"] {
+                let data = AprsData::parse(destination, info);
+                let packet = AprsPacket {
+                    source: Address {
+                        call: "N0CALL".to_string(),
+                        ssid: 0,
+                        repeated: false,
+                    },
+                    destination: Address {
+                        call: destination.to_string(),
+                        ssid: 0,
+                        repeated: false,
+                    },
+                    path: Vec::new(),
+                    info: info.to_string(),
+                    data,
+                };
+                packet.summary();
+                packet.fields();
+                packet.tnc2();
+            }
+        }
+
+        assert!(matches!(
+            AprsData::parse("APRS", "!9903.50N/07201.75W-"),
+            AprsData::Other { .. }
+        ));
+        assert!(matches!(
+            AprsData::parse("APRS", "T#005,abc"),
+            AprsData::Other { .. }
+        ));
+        assert!(matches!(
+            AprsData::parse("S32€", "`(_fn\"Oj/]"),
+            AprsData::Other { .. }
+        ));
+
+        // Frames that aren't APRS UI frames, or are cut short
+        let good = frame("N0CALL>APRS", "!4903.50N/07201.75W-");
+        for len in 0..16 {
+            assert!(AprsPacket::from_frame(&good[..len]).is_none());
+        }
+        let mut connected = good.clone();
+        connected[14] = 0x3F;
+        assert!(AprsPacket::from_frame(&connected).is_none());
+        let mut bad_call = good;
+        bad_call[0] = b'@' << 1;
+        assert!(AprsPacket::from_frame(&bad_call).is_none());
+        assert!(AprsPacket::from_frame(&[0xFE; 80]).is_none());
+    }
+
+    #[test]
+    fn test_decode_afsk() {
+        let frames = vec![
+            frame(
+                "K7ABC-9>APRS,WIDE1-1,WIDE2-1",
+                "!4736.37N/12219.93W>088/030camping",
+            ),
+            frame("N0CALL>APRS", ">Back to back"),
+        ];
+        for rate in [48_000, 240_000] {
+            let mut decoder = AprsDecoder::new(rate);
+            let audio = modulate(&frames, rate, 0.1);
+            let messages: Vec<DecodedMessage> = audio
+                .chunks(4096)
+                .flat_map(|c| Decoder::process(&mut decoder, DecoderInput::Audio(c)))
+                .collect();
+
+            let contents: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+            assert_eq!(
+                contents,
+                [
+                    "K7ABC-9 47.6062N 122.3322W 35mph → camping",
+                    "N0CALL status: Back to back"
+                ],
+                "at {} Hz",
+                rate
+            );
+            assert_eq!(
+                messages[0].raw.as_deref(),
+                Some("K7ABC-9>APRS,WIDE1-1,WIDE2-1:!4736.37N/12219.93W>088/030camping")
+            );
+            assert_eq!(messages[0].fields["course"], 88);
+            let location = messages[0].location().unwrap();
+            assert!((location.lat - 47.6062).abs() < 1e-4);
+        }
+    }
+}
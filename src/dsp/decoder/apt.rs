@@ -0,0 +1,793 @@
+//! NOAA APT (Automatic Picture Transmission) image decoder
+//!
+//! The POES satellites broadcast APT as wideband FM (about 34 kHz
+//! deviation) carrying a 2400 Hz subcarrier, amplitude modulated with the
+//! image at 4160 words per second. Each line is 2080 words: sync A (seven
+//! 1040 Hz cycles), space A, 909 words of channel A, telemetry A, then the
+//! same for channel B with sync B (seven 832 Hz pulses) in front. Two lines
+//! go out every second.
+//!
+//! The FM audio is averaged down to about 20.8 kHz, the subcarrier mixed to
+//! baseband and its envelope resampled to the word rate. Lines are found by
+//! correlating against both sync patterns at once: the whole line is
+//! searched until a sync is found, then only a few words either side of
+//! where the next line should start. A missed sync is bridged at the same
+//! spacing while the whole line is searched again. Sync A swings between
+//! black and white, so its levels set the image contrast.
+//!
+//! A pass starts once enough lines have synced. Channels A and B are written
+//! to separate grayscale PNGs in the recording directory, rewritten every
+//! few lines so the image can be watched as it comes in; the pass ends when
+//! the sync has been lost for a while or the decoder is removed.
+
+use super::{Decoder, DecoderInput, InputKind};
+use crate::dsp::filters::FilterChain;
+use crate::state::DecoderState;
+use crate::types::{DecodedMessage, DemodMode};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use num_complex::Complex;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Words per second
+pub const WORD_RATE: f32 = 4160.0;
+/// Words per line, and from the start of a line to sync B
+pub const LINE_LEN: usize = 2080;
+pub const HALF_LINE: usize = LINE_LEN / 2;
+/// Words of sync, and of the space before each channel's image
+pub const SYNC_LEN: usize = 39;
+const SPACE_LEN: usize = 47;
+/// Words of image per channel, after its sync and space
+pub const IMAGE_LEN: usize = 909;
+pub const IMAGE_START: usize = SYNC_LEN + SPACE_LEN;
+/// Subcarrier frequency in Hz
+const SUBCARRIER: f32 = 2400.0;
+/// Rate the audio is averaged down to before the subcarrier is demodulated
+const DETECT_RATE: u32 = 20_800;
+/// Video bandwidth kept around the subcarrier
+const VIDEO_BANDWIDTH: f32 = 2080.0;
+/// Mean correlation of the two syncs taken as a line start
+pub const SYNC_THRESHOLD: f32 = 0.5;
+/// How far a line start is searched for either side of where it was expected
+const TRACK_WINDOW: usize = 8;
+/// Lines carried on without sync before the pass is taken as over (30 s)
+const LOST_LINES: u32 = 60;
+/// Synced lines needed before a pass is reported and written
+const MIN_LINES: usize = 10;
+/// The images are rewritten every this many lines
+const SAVE_EVERY: usize = 30;
+/// Smoothing of the black and white levels from line to line
+const LEVEL_ALPHA: f32 = 0.1;
+
+/// A sync pattern as words: four low, seven pulses of `high` words high and
+/// `low` low, then low to the end
+fn sync_pattern(high: usize, low: usize) -> [f32; SYNC_LEN] {
+    let mut pattern = [0.0; SYNC_LEN];
+    for pulse in 0..7 {
+        let start = 4 + pulse * (high + low);
+        pattern[start..start + high].fill(1.0);
+    }
+    pattern
+}
+
+/// Sync A: 1040 Hz, two words high and two low
+pub fn sync_a() -> [f32; SYNC_LEN] {
+    sync_pattern(2, 2)
+}
+
+/// Sync B: 832 Hz, three words high and two low
+pub fn sync_b() -> [f32; SYNC_LEN] {
+    sync_pattern(3, 2)
+}
+
+/// A pattern with zero mean and unit energy, ready for [`correlate`]
+fn normalize(pattern: &[f32]) -> Vec<f32> {
+    let mean = pattern.iter().sum::<f32>() / pattern.len() as f32;
+    let centered: Vec<f32> = pattern.iter().map(|&x| x - mean).collect();
+    let norm = centered.iter().map(|x| x * x).sum::<f32>().sqrt();
+    centered.iter().map(|x| x / norm).collect()
+}
+
+/// Correlation coefficient (-1 to 1) of a normalized pattern and the start
+/// of `window`; independent of the window's level and contrast
+pub fn correlate(pattern: &[f32], window: &[f32]) -> f32 {
+    let window = &window[..pattern.len()];
+    let mean = window.iter().sum::<f32>() / window.len() as f32;
+    let (dot, energy) = pattern
+        .iter()
+        .zip(window)
+        .fold((0.0, 0.0), |(dot, energy), (p, w)| {
+            (dot + p * (w - mean), energy + (w - mean) * (w - mean))
+        });
+    // A flat window has nothing to correlate but rounding
+    if energy <= 1e-6 * window.iter().map(|w| w * w).sum::<f32>() {
+        0.0
+    } else {
+        dot / energy.sqrt()
+    }
+}
+
+/// Finds line starts by correlating against sync A and, half a line later,
+/// sync B
+#[derive(Debug, Clone)]
+pub struct SyncCorrelator {
+    a: Vec<f32>,
+    b: Vec<f32>,
+}
+
+impl Default for SyncCorrelator {
+    fn default() -> Self {
+        Self {
+            a: normalize(&sync_a()),
+            b: normalize(&sync_b()),
+        }
+    }
+}
+
+impl SyncCorrelator {
+    /// Words needed after a candidate line start to score it
+    pub const SPAN: usize = HALF_LINE + SYNC_LEN;
+
+    /// Mean correlation of both syncs for a line starting at `words[0]`
+    pub fn score(&self, words: &[f32]) -> f32 {
+        (correlate(&self.a, words) + correlate(&self.b, &words[HALF_LINE..])) / 2.0
+    }
+
+    /// The best line start among `offsets` and its score; offsets without
+    /// [`Self::SPAN`] words after them are skipped
+    pub fn find(&self, words: &[f32], offsets: std::ops::Range<usize>) -> Option<(usize, f32)> {
+        offsets
+            .take_while(|&offset| offset + Self::SPAN <= words.len())
+            .map(|offset| (offset, self.score(&words[offset..])))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// One line of words, with whether it started on a sync
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub words: Vec<f32>,
+    pub synced: bool,
+}
+
+/// Cuts the word stream into lines at the syncs
+#[derive(Debug, Clone, Default)]
+pub struct LineAssembler {
+    correlator: SyncCorrelator,
+    words: Vec<f32>,
+    locked: bool,
+    /// Lines in a row carried on without a sync
+    missed: u32,
+}
+
+impl LineAssembler {
+    /// Whether the line timing is known
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Add words; returns the lines completed. Nothing is returned while no
+    /// sync has been found; once one has, lines keep coming at the same
+    /// spacing until `LOST_LINES` in a row have missed their sync.
+    pub fn push(&mut self, words: &[f32]) -> Vec<Line> {
+        self.words.extend_from_slice(words);
+        let mut lines = Vec::new();
+
+        while self.words.len() >= LINE_LEN + SyncCorrelator::SPAN {
+            // The expected line start is word TRACK_WINDOW while locked;
+            // after a miss the whole line is searched again
+            let search = if self.locked && self.missed == 0 {
+                0..2 * TRACK_WINDOW + 1
+            } else {
+                0..LINE_LEN
+            };
+            let found = self
+                .correlator
+                .find(&self.words, search)
+                .filter(|&(_, score)| score >= SYNC_THRESHOLD);
+
+            let start = match (found, self.locked) {
+                (Some((offset, _)), _) => {
+                    self.locked = true;
+                    self.missed = 0;
+                    Some(offset)
+                }
+                (None, true) => {
+                    self.missed += 1;
+                    if self.missed > LOST_LINES {
+                        self.locked = false;
+                        None
+                    } else {
+                        Some(TRACK_WINDOW)
+                    }
+                }
+                (None, false) => None,
+            };
+
+            match start {
+                Some(start) => {
+                    lines.push(Line {
+                        words: self.words[start..start + LINE_LEN].to_vec(),
+                        synced: found.is_some(),
+                    });
+                    // Keep TRACK_WINDOW words before the next expected start
+                    self.words.drain(..start + LINE_LEN - TRACK_WINDOW);
+                }
+                None => {
+                    self.words.drain(..LINE_LEN);
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Drop buffered words after a break in the input, searching the whole
+    /// of the next line for its sync; lock is kept so lines carry on
+    pub fn resync(&mut self) {
+        self.words.clear();
+        if self.locked {
+            self.missed = self.missed.max(1);
+        }
+    }
+}
+
+/// Black and white word levels, taken from sync A
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub black: f32,
+    pub white: f32,
+}
+
+impl Levels {
+    /// The levels of the sync A at the start of `words`
+    pub fn measure(words: &[f32]) -> Self {
+        let pattern = sync_a();
+        let mean = |high: bool| {
+            let (sum, count) = pattern[4..32]
+                .iter()
+                .zip(&words[4..32])
+                .filter(|(&p, _)| (p > 0.5) == high)
+                .fold((0.0, 0), |(sum, count), (_, &w)| (sum + w, count + 1));
+            sum / count as f32
+        };
+        Self {
+            black: mean(false),
+            white: mean(true),
+        }
+    }
+
+    /// Move part way towards `other`
+    fn follow(&mut self, other: Levels) {
+        self.black += (other.black - self.black) * LEVEL_ALPHA;
+        self.white += (other.white - self.white) * LEVEL_ALPHA;
+    }
+
+    /// A word as an 8-bit gray level
+    pub fn gray(&self, word: f32) -> u8 {
+        let span = (self.white - self.black).max(1e-9);
+        ((word - self.black) / span * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    }
+}
+
+/// The images of one pass, one row of gray levels per line for each channel
+#[derive(Debug, Clone)]
+pub struct PassImage {
+    pub started: DateTime<Local>,
+    pub a: Vec<u8>,
+    pub b: Vec<u8>,
+    /// Lines so far, and up to the last one that synced
+    pub lines: usize,
+    pub synced_lines: usize,
+    /// Number of synced lines altogether
+    pub sync_count: usize,
+}
+
+impl PassImage {
+    pub fn new(started: DateTime<Local>) -> Self {
+        Self {
+            started,
+            a: Vec::new(),
+            b: Vec::new(),
+            lines: 0,
+            synced_lines: 0,
+            sync_count: 0,
+        }
+    }
+
+    /// Add the image parts of a line
+    pub fn add(&mut self, line: &Line, levels: &Levels) {
+        let gray = |start: usize| {
+            line.words[start..start + IMAGE_LEN]
+                .iter()
+                .map(|&w| levels.gray(w))
+                .collect::<Vec<u8>>()
+        };
+        self.a.extend(gray(IMAGE_START));
+        self.b.extend(gray(HALF_LINE + IMAGE_START));
+        self.lines += 1;
+        if line.synced {
+            self.synced_lines = self.lines;
+            self.sync_count += 1;
+        }
+    }
+
+    /// Drop the lines after the last synced one, carried on after the
+    /// signal went
+    pub fn trim(&mut self) {
+        self.lines = self.synced_lines;
+        self.a.truncate(self.lines * IMAGE_LEN);
+        self.b.truncate(self.lines * IMAGE_LEN);
+    }
+
+    /// Where channel `channel` ('A' or 'B') of the pass is written in `dir`
+    ///
+    /// e.g. `apt_20240601_142503_A.png`
+    pub fn path(&self, dir: &Path, channel: char) -> PathBuf {
+        dir.join(format!(
+            "apt_{}_{}.png",
+            self.started.format("%Y%m%d_%H%M%S"),
+            channel
+        ))
+    }
+
+    /// Write both channels into `dir`, replacing what was there
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        write_gray_png(&self.path(dir, 'A'), &self.a, self.lines)?;
+        write_gray_png(&self.path(dir, 'B'), &self.b, self.lines)
+    }
+}
+
+/// Write rows of [`IMAGE_LEN`] gray levels as a PNG, through a temporary
+/// file so a viewer never sees half an image
+fn write_gray_png(path: &Path, pixels: &[u8], rows: usize) -> Result<()> {
+    let partial = path.with_extension("png.part");
+    let file = File::create(&partial)
+        .with_context(|| format!("Failed to create {}", partial.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), IMAGE_LEN as u32, rows as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Fast);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels[..rows * IMAGE_LEN]))
+        .with_context(|| format!("Failed to write {}", partial.display()))?;
+    std::fs::rename(&partial, path)
+        .with_context(|| format!("Failed to rename {}", partial.display()))
+}
+
+/// Streaming APT decoder on FM-demodulated audio
+pub struct AptDecoder {
+    /// Input sample rate the decoder was built for
+    sample_rate: u32,
+    /// Where the images are written
+    dir: PathBuf,
+    /// Input samples averaged into one detection sample
+    decimation: usize,
+    decim_sum: f32,
+    decim_count: usize,
+    /// Mixes the subcarrier to 0 Hz
+    osc: Complex<f32>,
+    osc_step: Complex<f32>,
+    lowpass_i: FilterChain,
+    lowpass_q: FilterChain,
+    /// Envelope resampler: words per detection sample, the phase towards
+    /// the next word, and the previous envelope sample
+    word_step: f32,
+    word_phase: f32,
+    last_envelope: f32,
+    assembler: LineAssembler,
+    levels: Option<Levels>,
+    pass: Option<PassImage>,
+    /// Lines of the current pass when its images were last written
+    saved_lines: usize,
+}
+
+impl AptDecoder {
+    /// Create a decoder for audio at `sample_rate`, writing images to `dir`
+    pub fn new(sample_rate: u32, dir: PathBuf) -> Self {
+        let decimation = (sample_rate / DETECT_RATE).max(1) as usize;
+        let detect_rate = sample_rate as f32 / decimation as f32;
+        let lowpass = || FilterChain::band(detect_rate.round() as u32, None, Some(VIDEO_BANDWIDTH));
+
+        Self {
+            sample_rate,
+            dir,
+            decimation,
+            decim_sum: 0.0,
+            decim_count: 0,
+            osc: Complex::new(1.0, 0.0),
+            osc_step: Complex::from_polar(1.0, -2.0 * PI * SUBCARRIER / detect_rate),
+            lowpass_i: lowpass(),
+            lowpass_q: lowpass(),
+            word_step: WORD_RATE / detect_rate,
+            word_phase: 0.0,
+            last_envelope: 0.0,
+            assembler: LineAssembler::default(),
+            levels: None,
+            pass: None,
+            saved_lines: 0,
+        }
+    }
+
+    /// Subcarrier envelope at the word rate
+    fn words(&mut self, audio: &[f32]) -> Vec<f32> {
+        let mut mixed_i = Vec::with_capacity(audio.len() / self.decimation + 1);
+        let mut mixed_q = Vec::with_capacity(audio.len() / self.decimation + 1);
+        for &sample in audio {
+            self.decim_sum += sample;
+            self.decim_count += 1;
+            if self.decim_count < self.decimation {
+                continue;
+            }
+            let mixed = self.osc * (self.decim_sum / self.decimation as f32);
+            self.decim_sum = 0.0;
+            self.decim_count = 0;
+            self.osc *= self.osc_step;
+            mixed_i.push(mixed.re);
+            mixed_q.push(mixed.im);
+        }
+        self.osc /= self.osc.norm();
+        self.lowpass_i.process(&mut mixed_i);
+        self.lowpass_q.process(&mut mixed_q);
+
+        let mut words = Vec::with_capacity((mixed_i.len() as f32 * self.word_step) as usize + 1);
+        for (i, q) in mixed_i.into_iter().zip(mixed_q) {
+            let envelope = 2.0 * i.hypot(q);
+            self.word_phase += self.word_step;
+            if self.word_phase >= 1.0 {
+                self.word_phase -= 1.0;
+                // The word falls this far back from the current sample
+                let back = self.word_phase / self.word_step;
+                words.push(envelope + (self.last_envelope - envelope) * back);
+            }
+            self.last_envelope = envelope;
+        }
+        words
+    }
+
+    /// Feed audio; returns a message when a pass starts or ends
+    pub fn process_audio(&mut self, audio: &[f32]) -> Vec<DecodedMessage> {
+        let words = self.words(audio);
+        let mut messages = Vec::new();
+
+        for line in self.assembler.push(&words) {
+            if line.synced {
+                let measured = Levels::measure(&line.words);
+                match &mut self.levels {
+                    Some(levels) => levels.follow(measured),
+                    None => self.levels = Some(measured),
+                }
+            }
+            let Some(levels) = self.levels else {
+                continue;
+            };
+            let pass = self
+                .pass
+                .get_or_insert_with(|| PassImage::new(Local::now()));
+            let was_started = pass.sync_count >= MIN_LINES;
+            pass.add(&line, &levels);
+
+            if !was_started && pass.sync_count >= MIN_LINES {
+                messages.push(self.started_message());
+            }
+            if self.pass_started() && self.pass_lines() >= self.saved_lines + SAVE_EVERY {
+                if let Some(pass) = self.pass.take() {
+                    self.save(&pass);
+                    self.pass = Some(pass);
+                }
+            }
+        }
+
+        if !self.assembler.is_locked() && self.pass.is_some() {
+            messages.extend(self.finish());
+        }
+        messages
+    }
+
+    /// Whether the current pass has synced enough to be kept
+    fn pass_started(&self) -> bool {
+        self.pass
+            .as_ref()
+            .is_some_and(|pass| pass.sync_count >= MIN_LINES)
+    }
+
+    fn pass_lines(&self) -> usize {
+        self.pass.as_ref().map_or(0, |pass| pass.lines)
+    }
+
+    fn started_message(&self) -> DecodedMessage {
+        let path = self
+            .pass
+            .as_ref()
+            .map(|pass| pass.path(&self.dir, 'A'))
+            .unwrap_or_default();
+        DecodedMessage::new(
+            DemodMode::Apt,
+            format!("APT signal acquired, writing {}", path.display()),
+        )
+    }
+
+    /// Write the images of `pass` so far, logging rather than failing
+    fn save(&mut self, pass: &PassImage) {
+        if let Err(e) = std::fs::create_dir_all(&self.dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| pass.write(&self.dir))
+        {
+            log::error!("APT image: {:#}", e);
+        }
+        self.saved_lines = pass.lines;
+    }
+
+    /// End the current pass: write the final images and report where they are
+    fn finish(&mut self) -> Option<DecodedMessage> {
+        let started = self.pass_started();
+        let mut pass = self.pass.take()?;
+        self.levels = None;
+        if !started {
+            return None;
+        }
+
+        pass.trim();
+        self.save(&pass);
+        self.saved_lines = 0;
+        let (a, b) = (pass.path(&self.dir, 'A'), pass.path(&self.dir, 'B'));
+        Some(
+            DecodedMessage::new(
+                DemodMode::Apt,
+                format!(
+                    "APT pass ended: {} lines ({:.0} s), images {} and {}",
+                    pass.lines,
+                    pass.lines as f32 * LINE_LEN as f32 / WORD_RATE,
+                    a.display(),
+                    b.display()
+                ),
+            )
+            .with_fields(serde_json::json!({
+                "lines": pass.lines,
+                "synced_lines": pass.sync_count,
+                "image_a": a.display().to_string(),
+                "image_b": b.display().to_string(),
+            })),
+        )
+    }
+}
+
+impl Decoder for AptDecoder {
+    fn name(&self) -> &'static str {
+        "APT"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Audio
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Audio(audio) = input else {
+            return Vec::new();
+        };
+        self.process_audio(audio)
+    }
+
+    /// Restart the demodulator but keep the pass; the line timing is found
+    /// again from the next sync
+    fn reset(&mut self) {
+        let fresh = AptDecoder::new(self.sample_rate, self.dir.clone());
+        let old = std::mem::replace(self, fresh);
+        self.assembler = old.assembler;
+        self.assembler.resync();
+        self.levels = old.levels;
+        self.pass = old.pass;
+        self.saved_lines = old.saved_lines;
+    }
+
+    fn flush(&mut self) -> Vec<DecodedMessage> {
+        self.finish().into_iter().collect()
+    }
+
+    fn publish(&self, state: &mut DecoderState) {
+        state.apt_lines = Some(if self.pass_started() {
+            self.pass_lines()
+        } else {
+            0
+        });
+    }
+
+    fn unpublish(&self, state: &mut DecoderState) {
+        state.apt_lines = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Words of one synthetic line, levels 0 to 1: channel A a left to
+    /// right ramp, channel B `row` shades of gray
+    fn synthetic_line(row: usize) -> Vec<f32> {
+        let mut words = Vec::with_capacity(LINE_LEN);
+        for (sync, image) in [
+            (
+                sync_a(),
+                (0..IMAGE_LEN)
+                    .map(|x| x as f32 / (IMAGE_LEN - 1) as f32)
+                    .collect(),
+            ),
+            (sync_b(), vec![(row % 8) as f32 / 8.0; IMAGE_LEN]),
+        ] {
+            words.extend(sync);
+            words.extend([0.0; SPACE_LEN]);
+            words.extend::<Vec<f32>>(image);
+            words.extend([0.5; HALF_LINE - IMAGE_START - IMAGE_LEN]);
+        }
+        words
+    }
+
+    /// `lines` synthetic lines after `lead` words of noise
+    fn segment(lead: usize, lines: usize, noise: f32) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(137);
+        let mut words: Vec<f32> = (0..lead).map(|_| rng.gen_range(0.0..1.0)).collect();
+        words.extend((0..lines).flat_map(synthetic_line));
+        words
+            .iter()
+            .map(|w| w + rng.gen_range(-noise..=noise))
+            .collect()
+    }
+
+    /// AM on the 2400 Hz subcarrier at `rate`, as it comes out of the FM
+    /// demodulator
+    fn modulate(words: &[f32], rate: u32) -> Vec<f32> {
+        let samples = (words.len() as f64 * rate as f64 / WORD_RATE as f64) as usize;
+        (0..samples)
+            .map(|n| {
+                let t = n as f32 / rate as f32;
+                let word = words[((t * WORD_RATE) as usize).min(words.len() - 1)];
+                0.05 * (0.1 + 0.8 * word) * (2.0 * PI * SUBCARRIER * t).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sync_patterns() {
+        let a = sync_a();
+        let b = sync_b();
+        assert_eq!(a.iter().sum::<f32>(), 14.0);
+        assert_eq!(b.iter().sum::<f32>(), 21.0);
+        assert_eq!(a[..8], [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(
+            b[..11],
+            [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0]
+        );
+
+        // Each matches itself whatever the level and contrast, not the other
+        let a_norm = normalize(&a);
+        let scaled: Vec<f32> = a.iter().map(|w| 0.2 + 0.3 * w).collect();
+        assert!((correlate(&a_norm, &scaled) - 1.0).abs() < 1e-4);
+        assert!(correlate(&a_norm, &b).abs() < 0.5);
+        assert_eq!(correlate(&a_norm, &[0.7; SYNC_LEN]), 0.0);
+    }
+
+    #[test]
+    fn test_correlator_finds_line_start() {
+        let correlator = SyncCorrelator::default();
+        let words = segment(1234, 2, 0.2);
+        let (offset, score) = correlator.find(&words, 0..LINE_LEN).unwrap();
+        assert_eq!(offset, 1234);
+        assert!(score > 0.8, "score {}", score);
+
+        // Noise alone stays below the threshold
+        let noise = segment(2 * LINE_LEN, 0, 0.0);
+        let (_, score) = correlator.find(&noise, 0..LINE_LEN).unwrap();
+        assert!(score < SYNC_THRESHOLD, "score {}", score);
+
+        // Too few words to score anything
+        assert_eq!(
+            correlator.find(&words[..SyncCorrelator::SPAN - 1], 0..LINE_LEN),
+            None
+        );
+    }
+
+    #[test]
+    fn test_line_assembly() {
+        let mut assembler = LineAssembler::default();
+        let words = segment(700, 6, 0.1);
+        // In odd-sized pieces, as buffers arrive
+        let lines: Vec<Line> = words
+            .chunks(777)
+            .flat_map(|chunk| assembler.push(chunk))
+            .collect();
+
+        // The last line waits for the next one's sync B to be scored
+        assert_eq!(lines.len(), 5);
+        assert!(lines.iter().all(|line| line.synced));
+        for (row, line) in lines.iter().enumerate() {
+            let expected = synthetic_line(row);
+            let error = line
+                .words
+                .iter()
+                .zip(&expected)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f32::max);
+            assert!(error <= 0.1 + 1e-6, "line {} off by {}", row, error);
+        }
+        assert!(assembler.is_locked());
+
+        // Lines carry on through a dropout, then lock is lost
+        let noise = segment(LINE_LEN * (LOST_LINES as usize + 4), 0, 0.0);
+        let carried = assembler.push(&noise);
+        assert!(carried.len() > LOST_LINES as usize);
+        assert!(carried.iter().skip(1).all(|line| !line.synced));
+        assert!(!assembler.is_locked());
+    }
+
+    #[test]
+    fn test_levels_from_sync() {
+        let words: Vec<f32> = synthetic_line(0).iter().map(|w| 0.1 + 0.5 * w).collect();
+        let levels = Levels::measure(&words);
+        assert!((levels.black - 0.1).abs() < 1e-6 && (levels.white - 0.6).abs() < 1e-6);
+        assert_eq!(levels.gray(0.1), 0);
+        assert_eq!(levels.gray(0.35), 128);
+        assert_eq!(levels.gray(0.9), 255);
+    }
+
+    #[test]
+    fn test_decode_pass_to_png() {
+        let dir = temp_dir("apt_pass");
+        let rate = 48_000;
+        let mut decoder = AptDecoder::new(rate, dir.clone());
+        let mut words = segment(3000, 40, 0.02);
+        words.extend(segment(LINE_LEN * (LOST_LINES as usize + 4), 0, 0.0));
+
+        let messages: Vec<DecodedMessage> = modulate(&words, rate)
+            .chunks(4800)
+            .flat_map(|chunk| decoder.process_audio(chunk))
+            .collect();
+        assert_eq!(messages.len(), 2, "{:?}", messages);
+        assert!(messages[0].content.starts_with("APT signal acquired"));
+        assert!(messages[1].content.starts_with("APT pass ended"));
+        let lines = messages[1].fields["lines"].as_u64().unwrap() as usize;
+        assert!((38..=40).contains(&lines), "{} lines", lines);
+
+        let path = messages[1].fields["image_a"].as_str().unwrap();
+        let mut reader = png::Decoder::new(File::open(path).unwrap())
+            .read_info()
+            .unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(
+            (info.width as usize, info.height as usize),
+            (IMAGE_LEN, lines)
+        );
+        assert_eq!(info.color_type, png::ColorType::Grayscale);
+        // The ramp runs dark to light along each line
+        let row = &pixels[5 * IMAGE_LEN..6 * IMAGE_LEN];
+        assert!(
+            row[20] < 40 && row[IMAGE_LEN - 20] > 215,
+            "{} {}",
+            row[20],
+            row[IMAGE_LEN - 20]
+        );
+        assert!(
+            (row[IMAGE_LEN / 2] as i32 - 128).abs() < 25,
+            "{}",
+            row[IMAGE_LEN / 2]
+        );
+        assert!(Path::new(messages[1].fields["image_b"].as_str().unwrap()).exists());
+
+        // Nothing left to report
+        assert!(decoder.flush().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
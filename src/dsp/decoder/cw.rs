@@ -0,0 +1,452 @@
+//! Morse (CW) decoder with adaptive speed tracking
+//!
+//! Works on the demodulated beat note. The audio is averaged down to about
+//! 8 kHz, band-limited around typical CW pitches, rectified and smoothed into
+//! an envelope sampled once per millisecond. A mark starts when the envelope
+//! rises above a threshold set between a tracked noise floor and a slowly
+//! decaying signal peak (so the threshold follows QSB), and ends when it
+//! falls below a lower threshold (hysteresis).
+//!
+//! The dit length is estimated from the shortest recent elements: every
+//! character with two or more elements contains one-dit gaps, so the short end
+//! of the mark/space distribution tracks the sending speed from 5 to 40+ WPM
+//! without a prior.
+
+use super::{Decoder, DecoderInput, InputKind};
+use crate::dsp::filters::Biquad;
+use crate::state::DecoderState;
+use crate::types::{DecodedMessage, DemodMode};
+
+/// Rate the audio is averaged down to before detection
+const DETECT_RATE: u32 = 8000;
+/// Envelope ticks per second (1 ms resolution)
+const TICK_RATE: u32 = 1000;
+/// Beat note passband edges in Hz
+const PASSBAND: (f64, f64) = (300.0, 1200.0);
+/// Envelope smoothing time constant in seconds
+const ENVELOPE_TAU: f32 = 0.004;
+/// Signal peak decay time constant in seconds
+const PEAK_TAU: f32 = 1.5;
+/// Noise floor rise time constant in seconds (falls much faster)
+const FLOOR_TAU: f32 = 5.0;
+/// Mark on/off thresholds as fractions of the peak-to-floor range
+const THRESHOLD_ON: f32 = 0.5;
+const THRESHOLD_OFF: f32 = 0.3;
+/// Minimum peak-to-floor ratio for anything to count as a signal
+const MIN_SNR: f32 = 3.0;
+/// Marks or gaps shorter than this (ms) are treated as glitches
+const GLITCH_MS: u32 = 8;
+/// Starting dit estimate (20 WPM)
+const INITIAL_DIT_MS: f32 = 60.0;
+/// Number of recent element durations kept for speed estimation
+const HISTORY_LEN: usize = 24;
+/// Silence after which the current line is emitted
+const LINE_TIMEOUT_MS: u32 = 2500;
+/// Lines are emitted once they reach this length
+const MAX_LINE_LEN: usize = 40;
+
+/// International Morse code
+const MORSE_TABLE: &[(&str, char)] = &[
+    (".-", 'A'), ("-...", 'B'), ("-.-.", 'C'), ("-..", 'D'), (".", 'E'),
+    ("..-.", 'F'), ("--.", 'G'), ("....", 'H'), ("..", 'I'), (".---", 'J'),
+    ("-.-", 'K'), (".-..", 'L'), ("--", 'M'), ("-.", 'N'), ("---", 'O'),
+    (".--.", 'P'), ("--.-", 'Q'), (".-.", 'R'), ("...", 'S'), ("-", 'T'),
+    ("..-", 'U'), ("...-", 'V'), (".--", 'W'), ("-..-", 'X'), ("-.--", 'Y'),
+    ("--..", 'Z'), ("-----", '0'), (".----", '1'), ("..---", '2'),
+    ("...--", '3'), ("....-", '4'), (".....", '5'), ("-....", '6'),
+    ("--...", '7'), ("---..", '8'), ("----.", '9'), (".-.-.-", '.'),
+    ("--..--", ','), ("..--..", '?'), ("-..-.", '/'), ("-...-", '='),
+    (".-.-.", '+'), ("-....-", '-'), (".--.-.", '@'),
+];
+
+/// Look up a dot/dash pattern
+fn lookup(pattern: &str) -> char {
+    MORSE_TABLE
+        .iter()
+        .find(|(code, _)| *code == pattern)
+        .map(|&(_, c)| c)
+        .unwrap_or('*')
+}
+
+/// Streaming Morse decoder
+pub struct CwDecoder {
+    /// Input sample rate the decoder was built for
+    sample_rate: u32,
+    /// Input samples averaged into one detection sample
+    decimation: usize,
+    decim_sum: f32,
+    decim_count: usize,
+    /// Beat note band-limiting filters
+    filters: [Biquad; 2],
+    /// Detection samples per envelope tick
+    tick_len: usize,
+    tick_count: usize,
+    /// Envelope smoothing coefficient and state
+    env_alpha: f32,
+    envelope: f32,
+    /// Adaptive level tracking (per-tick coefficients)
+    peak: f32,
+    peak_decay: f32,
+    floor: f32,
+    floor_rise: f32,
+    /// Current key state and how long it has lasted (ms)
+    key_down: bool,
+    run_ms: u32,
+    /// Recent mark and gap durations (ms)
+    history: Vec<u32>,
+    dit_ms: f32,
+    /// Dots and dashes of the character in progress
+    pattern: String,
+    /// Decoded text not yet emitted
+    line: String,
+    /// Whether the current gap already ended a character / word
+    char_ended: bool,
+    word_ended: bool,
+}
+
+impl CwDecoder {
+    /// Create a decoder for audio at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        let decimation = (sample_rate / DETECT_RATE).max(1) as usize;
+        let detect_rate = sample_rate as f32 / decimation as f32;
+        let per_tick = |tau: f32| 1.0 - (-1.0 / (tau * TICK_RATE as f32)).exp();
+
+        Self {
+            sample_rate,
+            decimation,
+            decim_sum: 0.0,
+            decim_count: 0,
+            filters: [
+                Biquad::highpass(detect_rate as f64, PASSBAND.0, std::f64::consts::FRAC_1_SQRT_2),
+                Biquad::lowpass(detect_rate as f64, PASSBAND.1, std::f64::consts::FRAC_1_SQRT_2),
+            ],
+            tick_len: ((detect_rate / TICK_RATE as f32).round() as usize).max(1),
+            tick_count: 0,
+            env_alpha: 1.0 - (-1.0 / (ENVELOPE_TAU * detect_rate)).exp(),
+            envelope: 0.0,
+            peak: 0.0,
+            peak_decay: per_tick(PEAK_TAU),
+            floor: 0.0,
+            floor_rise: per_tick(FLOOR_TAU),
+            key_down: false,
+            run_ms: 0,
+            history: Vec::with_capacity(HISTORY_LEN),
+            dit_ms: INITIAL_DIT_MS,
+            pattern: String::new(),
+            line: String::new(),
+            char_ended: true,
+            word_ended: true,
+        }
+    }
+
+    /// Current speed estimate in words per minute (PARIS standard)
+    pub fn wpm(&self) -> f32 {
+        1200.0 / self.dit_ms
+    }
+
+    /// Feed audio; returns any completed lines of text
+    pub fn process(&mut self, audio: &[f32]) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for &sample in audio {
+            self.decim_sum += sample;
+            self.decim_count += 1;
+            if self.decim_count < self.decimation {
+                continue;
+            }
+            let x = self.decim_sum / self.decimation as f32;
+            self.decim_sum = 0.0;
+            self.decim_count = 0;
+
+            let filtered = self.filters.iter_mut().fold(x, |x, f| f.process(x));
+            self.envelope += (filtered.abs() - self.envelope) * self.env_alpha;
+
+            self.tick_count += 1;
+            if self.tick_count == self.tick_len {
+                self.tick_count = 0;
+                if let Some(line) = self.tick() {
+                    lines.push(line);
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Emit whatever text is pending (e.g. when leaving CW mode)
+    pub fn flush(&mut self) -> Option<String> {
+        self.end_character();
+        self.take_line()
+    }
+
+    /// Advance the key detector by one millisecond
+    fn tick(&mut self) -> Option<String> {
+        let e = self.envelope;
+
+        // Peak: instant attack, slow decay. Floor: fast fall, slow rise.
+        if e > self.peak {
+            self.peak = e;
+        } else {
+            self.peak += (e - self.peak) * self.peak_decay;
+        }
+        if e < self.floor {
+            self.floor += (e - self.floor) * 0.05;
+        } else {
+            self.floor += (e - self.floor) * self.floor_rise;
+        }
+
+        let range = self.peak - self.floor;
+        let has_signal = self.peak > self.floor.max(1e-6) * MIN_SNR;
+        let level = if self.key_down { THRESHOLD_OFF } else { THRESHOLD_ON };
+        let key = has_signal && e > self.floor + range * level;
+
+        if key == self.key_down {
+            self.run_ms += 1;
+            return if key { None } else { self.gap_progress() };
+        }
+
+        // Too short to be real: treat as a continuation of the previous state
+        if self.run_ms < GLITCH_MS && !self.history.is_empty() {
+            self.key_down = key;
+            self.run_ms += 1;
+            return None;
+        }
+
+        let duration = self.run_ms;
+        self.key_down = key;
+        self.run_ms = 1;
+
+        if key {
+            // Gap ended; only element-length gaps inform the speed estimate
+            if (duration as f32) < self.dit_ms * 2.0 {
+                self.record(duration);
+            }
+        } else {
+            self.record(duration);
+            let symbol = if duration as f32 > self.dit_ms * 2.0 { '-' } else { '.' };
+            self.pattern.push(symbol);
+            self.char_ended = false;
+            self.word_ended = false;
+        }
+        None
+    }
+
+    /// Handle a gap growing past character, word and line boundaries
+    fn gap_progress(&mut self) -> Option<String> {
+        let gap = self.run_ms as f32;
+
+        if !self.char_ended && gap > self.dit_ms * 2.0 {
+            self.end_character();
+            self.char_ended = true;
+        }
+        if !self.word_ended && gap > self.dit_ms * 5.0 {
+            if !self.line.is_empty() && !self.line.ends_with(' ') {
+                self.line.push(' ');
+            }
+            self.word_ended = true;
+            if self.line.len() >= MAX_LINE_LEN {
+                return self.take_line();
+            }
+        }
+        if self.run_ms == LINE_TIMEOUT_MS {
+            return self.take_line();
+        }
+        None
+    }
+
+    /// Decode the pattern in progress into the line
+    fn end_character(&mut self) {
+        if !self.pattern.is_empty() {
+            self.line.push(lookup(&self.pattern));
+            self.pattern.clear();
+        }
+    }
+
+    fn take_line(&mut self) -> Option<String> {
+        let line = self.line.trim().to_string();
+        self.line.clear();
+        (!line.is_empty()).then_some(line)
+    }
+
+    /// Add an element duration and re-estimate the dit length
+    fn record(&mut self, duration: u32) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.remove(0);
+        }
+        self.history.push(duration);
+
+        let mut sorted = self.history.clone();
+        sorted.sort_unstable();
+        // Low percentile rather than the minimum, so one glitch can't skew it
+        let reference = sorted[sorted.len() / 5] as f32;
+        let short: Vec<f32> = sorted
+            .iter()
+            .map(|&d| d as f32)
+            .filter(|&d| d <= reference * 1.5)
+            .collect();
+        let estimate = short.iter().sum::<f32>() / short.len() as f32;
+
+        // Only trust the estimate once it has something to compare against
+        if self.history.len() >= 4 {
+            self.dit_ms = estimate.clamp(20.0, 300.0);
+        }
+    }
+}
+
+impl Decoder for CwDecoder {
+    fn name(&self) -> &'static str {
+        "CW"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Audio
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Audio(audio) = input else {
+            return Vec::new();
+        };
+        CwDecoder::process(self, audio)
+            .into_iter()
+            .map(|line| DecodedMessage::new(DemodMode::Cw, line))
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        // Keep the decoded text and speed estimate; only the key detector restarts
+        let line = std::mem::take(&mut self.line);
+        let dit_ms = self.dit_ms;
+        *self = CwDecoder::new(self.sample_rate);
+        self.line = line;
+        self.dit_ms = dit_ms;
+    }
+
+    fn flush(&mut self) -> Vec<DecodedMessage> {
+        CwDecoder::flush(self)
+            .map(|line| DecodedMessage::new(DemodMode::Cw, line))
+            .into_iter()
+            .collect()
+    }
+
+    fn publish(&self, state: &mut DecoderState) {
+        state.cw_wpm = Some(self.wpm());
+    }
+
+    fn unpublish(&self, state: &mut DecoderState) {
+        state.cw_wpm = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const RATE: u32 = 8000;
+
+    /// Key a message as a list of (on, duration in dits)
+    fn keying(text: &str) -> Vec<(bool, u32)> {
+        let mut keying = Vec::new();
+        for word in text.split(' ') {
+            for c in word.chars() {
+                let code = MORSE_TABLE.iter().find(|(_, ch)| *ch == c).unwrap().0;
+                for symbol in code.chars() {
+                    keying.push((true, if symbol == '-' { 3 } else { 1 }));
+                    keying.push((false, 1));
+                }
+                keying.last_mut().unwrap().1 = 3;
+            }
+            keying.last_mut().unwrap().1 = 7;
+        }
+        keying
+    }
+
+    /// Synthesize CW audio with noise and optional slow fading
+    fn synthesize(text: &str, wpm: f32, noise: f32, qsb: bool) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let dit = (1.2 / wpm * RATE as f32) as usize;
+        let mut audio = vec![0.0; RATE as usize / 2];
+
+        for (on, dits) in keying(text) {
+            for _ in 0..dit * dits as usize {
+                let t = audio.len() as f32 / RATE as f32;
+                let fade = if qsb { 0.65 + 0.35 * (t * 0.4).sin() } else { 1.0 };
+                let tone = if on { 0.5 * (2.0 * std::f32::consts::PI * 700.0 * t).sin() } else { 0.0 };
+                audio.push(tone * fade);
+            }
+        }
+        audio.extend(std::iter::repeat_n(0.0, RATE as usize * 3));
+
+        if noise > 0.0 {
+            for sample in audio.iter_mut() {
+                *sample += rng.gen_range(-noise..noise);
+            }
+        }
+        audio
+    }
+
+    fn decode(audio: &[f32]) -> (String, f32) {
+        let mut decoder = CwDecoder::new(RATE);
+        let mut text: Vec<String> = audio.chunks(1024).flat_map(|c| decoder.process(c)).collect();
+        text.extend(decoder.flush());
+        (text.join(" "), decoder.wpm())
+    }
+
+    /// Decode with a short training preamble the estimator can lock onto
+    fn assert_decodes(wpm: f32, noise: f32, qsb: bool) {
+        let audio = synthesize("VVV CQ CQ DE TEST K", wpm, noise, qsb);
+        let (text, estimate) = decode(&audio);
+        assert!(
+            text.ends_with("CQ CQ DE TEST K"),
+            "{} WPM noise {}: decoded '{}'",
+            wpm,
+            noise,
+            text
+        );
+        assert!(
+            (estimate - wpm).abs() / wpm < 0.2,
+            "estimated {:.1} WPM for {} WPM",
+            estimate,
+            wpm
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup("-.-."), 'C');
+        assert_eq!(lookup("..--.."), '?');
+        assert_eq!(lookup("........"), '*');
+    }
+
+    #[test]
+    fn test_decode_clean_speeds() {
+        for wpm in [5.0, 12.0, 20.0, 30.0, 40.0] {
+            assert_decodes(wpm, 0.0, false);
+        }
+    }
+
+    #[test]
+    fn test_decode_noisy() {
+        for wpm in [10.0, 25.0] {
+            assert_decodes(wpm, 0.4, false);
+        }
+    }
+
+    #[test]
+    fn test_decode_with_qsb() {
+        assert_decodes(18.0, 0.2, true);
+    }
+
+    #[test]
+    fn test_noise_only_is_silent() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let audio: Vec<f32> = (0..RATE * 5).map(|_| rng.gen_range(-0.3..0.3)).collect();
+        let (text, _) = decode(&audio);
+        assert!(text.len() < 4, "decoded '{}' from noise", text);
+    }
+}
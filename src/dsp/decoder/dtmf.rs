@@ -0,0 +1,342 @@
+//! DTMF (touch-tone) decoder
+//!
+//! Runs on demodulated NFM audio. The audio is first averaged down to about
+//! 8 kHz, then each ~25 ms block is run through eight Goertzel detectors on
+//! the DTMF row and column frequencies. A block counts as a digit when one
+//! row and one column tone dominate the block energy, each clearly beats the
+//! other tones in its group, and their levels are within the allowed twist.
+//! A digit must persist for two blocks to be accepted (rejecting speech
+//! falsing), and the same digit is only repeated after a gap. Digits are
+//! grouped into sequences that end after a period of silence.
+
+use super::{Decoder, DecoderInput, InputKind};
+use crate::types::{DecodedMessage, DemodMode};
+use std::f32::consts::PI;
+
+/// Row (low group) frequencies in Hz
+const ROW_FREQS: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+/// Column (high group) frequencies in Hz
+const COL_FREQS: [f32; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+/// Keypad layout indexed by [row][column]
+const KEYPAD: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// Rate the audio is averaged down to before detection
+const DETECT_RATE: u32 = 8000;
+/// Goertzel block length in seconds (205 samples at 8 kHz)
+const BLOCK_SECS: f32 = 0.025_6;
+/// Fraction of the block energy the two tones must hold together
+const MIN_TONE_ENERGY: f32 = 0.5;
+/// Each detected tone must beat the rest of its group by this power ratio (6 dB)
+const MIN_GROUP_RATIO: f32 = 4.0;
+/// Maximum level difference between row and column tone (8 dB)
+const MAX_TWIST: f32 = 6.3;
+/// Consecutive blocks a digit must persist for (~51 ms)
+const MIN_BLOCKS: u32 = 2;
+/// Silence that ends a sequence
+const SEQUENCE_GAP_SECS: f32 = 1.0;
+
+/// Streaming DTMF decoder
+pub struct DtmfDecoder {
+    /// Input sample rate the decoder was built for
+    sample_rate: u32,
+    /// Input samples averaged into one detection sample
+    decimation: usize,
+    /// Running sum for the current detection sample
+    decim_sum: f32,
+    decim_count: usize,
+    /// Current detection block
+    block: Vec<f32>,
+    block_len: usize,
+    /// Goertzel coefficients for rows then columns
+    coeffs: [f32; 8],
+    /// Digit seen in the previous block(s) and for how many blocks
+    candidate: Option<char>,
+    candidate_blocks: u32,
+    /// Whether the current candidate has already been emitted
+    emitted: bool,
+    /// Digits of the sequence in progress
+    sequence: String,
+    /// Blocks of silence since the last digit
+    silent_blocks: u32,
+    gap_blocks: u32,
+}
+
+impl DtmfDecoder {
+    /// Create a decoder for audio at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        let decimation = (sample_rate / DETECT_RATE).max(1) as usize;
+        let detect_rate = sample_rate as f32 / decimation as f32;
+        let block_len = (detect_rate * BLOCK_SECS).round() as usize;
+
+        let mut coeffs = [0.0; 8];
+        for (coeff, &freq) in coeffs.iter_mut().zip(ROW_FREQS.iter().chain(COL_FREQS.iter())) {
+            *coeff = 2.0 * (2.0 * PI * freq / detect_rate).cos();
+        }
+
+        Self {
+            sample_rate,
+            decimation,
+            decim_sum: 0.0,
+            decim_count: 0,
+            block: Vec::with_capacity(block_len),
+            block_len,
+            coeffs,
+            candidate: None,
+            candidate_blocks: 0,
+            emitted: false,
+            sequence: String::new(),
+            silent_blocks: 0,
+            gap_blocks: (SEQUENCE_GAP_SECS / BLOCK_SECS).ceil() as u32,
+        }
+    }
+
+    /// Feed audio; returns any sequences completed by this buffer
+    pub fn process(&mut self, audio: &[f32]) -> Vec<String> {
+        let mut sequences = Vec::new();
+
+        for &sample in audio {
+            self.decim_sum += sample;
+            self.decim_count += 1;
+            if self.decim_count < self.decimation {
+                continue;
+            }
+            self.block.push(self.decim_sum / self.decimation as f32);
+            self.decim_sum = 0.0;
+            self.decim_count = 0;
+
+            if self.block.len() == self.block_len {
+                let digit = self.detect_block();
+                self.block.clear();
+                if let Some(sequence) = self.update(digit) {
+                    sequences.push(sequence);
+                }
+            }
+        }
+
+        sequences
+    }
+
+    /// End the current sequence, if any (e.g. when the decoder is disabled)
+    pub fn flush(&mut self) -> Option<String> {
+        self.candidate = None;
+        self.candidate_blocks = 0;
+        self.silent_blocks = 0;
+        (!self.sequence.is_empty()).then(|| std::mem::take(&mut self.sequence))
+    }
+
+    /// Debounce per-block detections into digits and sequences
+    fn update(&mut self, digit: Option<char>) -> Option<String> {
+        if digit.is_some() && digit == self.candidate {
+            self.candidate_blocks += 1;
+        } else {
+            self.candidate = digit;
+            self.candidate_blocks = 1;
+            self.emitted = false;
+        }
+
+        match digit {
+            Some(digit) => {
+                self.silent_blocks = 0;
+                if !self.emitted && self.candidate_blocks >= MIN_BLOCKS {
+                    self.sequence.push(digit);
+                    self.emitted = true;
+                }
+                None
+            }
+            None => {
+                self.silent_blocks += 1;
+                if self.silent_blocks >= self.gap_blocks && !self.sequence.is_empty() {
+                    Some(std::mem::take(&mut self.sequence))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Detect a digit in the current block
+    fn detect_block(&self) -> Option<char> {
+        let energy: f32 = self.block.iter().map(|x| x * x).sum();
+        if energy <= f32::EPSILON {
+            return None;
+        }
+
+        // Normalize so a pure tone at a detector frequency scores ~1.0
+        let scale = energy * self.block_len as f32 / 2.0;
+        let mut powers = [0.0; 8];
+        for (power, &coeff) in powers.iter_mut().zip(self.coeffs.iter()) {
+            *power = goertzel(&self.block, coeff) / scale;
+        }
+
+        let (row, row_power) = strongest(&powers[..4])?;
+        let (col, col_power) = strongest(&powers[4..])?;
+
+        if row_power + col_power < MIN_TONE_ENERGY {
+            return None;
+        }
+        if row_power > col_power * MAX_TWIST || col_power > row_power * MAX_TWIST {
+            return None;
+        }
+
+        Some(KEYPAD[row][col])
+    }
+}
+
+/// Index and power of the strongest tone in a group, if it clearly dominates
+fn strongest(powers: &[f32]) -> Option<(usize, f32)> {
+    let (index, &best) = powers
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))?;
+    let runner_up = powers
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != index)
+        .map(|(_, &p)| p)
+        .fold(0.0, f32::max);
+
+    (best > runner_up * MIN_GROUP_RATIO).then_some((index, best))
+}
+
+/// Goertzel power of one frequency over a block
+fn goertzel(block: &[f32], coeff: f32) -> f32 {
+    let (mut s1, mut s2) = (0.0f32, 0.0f32);
+    for &x in block {
+        let s0 = x + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    s1 * s1 + s2 * s2 - coeff * s1 * s2
+}
+
+/// Decoded sequences are reported as NFM messages
+fn dtmf_message(sequence: String) -> DecodedMessage {
+    DecodedMessage::new(DemodMode::FmNarrow, format!("DTMF: {}", sequence))
+}
+
+impl Decoder for DtmfDecoder {
+    fn name(&self) -> &'static str {
+        "DTMF"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Audio
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Audio(audio) = input else {
+            return Vec::new();
+        };
+        DtmfDecoder::process(self, audio).into_iter().map(dtmf_message).collect()
+    }
+
+    fn reset(&mut self) {
+        *self = DtmfDecoder::new(self.sample_rate);
+    }
+
+    fn flush(&mut self) -> Vec<DecodedMessage> {
+        DtmfDecoder::flush(self).map(dtmf_message).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Synthesize a keyed DTMF sequence with additive noise
+    fn synthesize(digits: &str, rate: u32, on_ms: u32, off_ms: u32, noise: f32) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut audio = Vec::new();
+        let tone_len = (rate * on_ms / 1000) as usize;
+        let gap_len = (rate * off_ms / 1000) as usize;
+
+        for digit in digits.chars() {
+            let (row, col) = (0..4)
+                .flat_map(|r| (0..4).map(move |c| (r, c)))
+                .find(|&(r, c)| KEYPAD[r][c] == digit)
+                .unwrap();
+            for i in 0..tone_len {
+                let t = i as f32 / rate as f32;
+                audio.push(
+                    0.3 * (2.0 * PI * ROW_FREQS[row] * t).sin()
+                        + 0.3 * (2.0 * PI * COL_FREQS[col] * t).sin(),
+                );
+            }
+            audio.extend(std::iter::repeat_n(0.0, gap_len));
+        }
+
+        // Trailing silence ends the sequence
+        audio.extend(std::iter::repeat_n(0.0, rate as usize * 3 / 2));
+        if noise > 0.0 {
+            for sample in audio.iter_mut() {
+                *sample += rng.gen_range(-noise..noise);
+            }
+        }
+        audio
+    }
+
+    fn decode(audio: &[f32], rate: u32) -> Vec<String> {
+        let mut decoder = DtmfDecoder::new(rate);
+        // Feed in uneven chunks like the DSP thread does
+        audio.chunks(1000).flat_map(|chunk| decoder.process(chunk)).collect()
+    }
+
+    #[test]
+    fn test_decode_sequence_8k() {
+        let audio = synthesize("*123#", 8000, 70, 60, 0.1);
+        assert_eq!(decode(&audio, 8000), vec!["*123#".to_string()]);
+    }
+
+    #[test]
+    fn test_decode_all_digits_48k() {
+        let audio = synthesize("0123456789ABCD*#", 48_000, 60, 60, 0.15);
+        assert_eq!(decode(&audio, 48_000), vec!["0123456789ABCD*#".to_string()]);
+    }
+
+    #[test]
+    fn test_repeated_digits_need_gap() {
+        let audio = synthesize("55", 8000, 80, 60, 0.05);
+        assert_eq!(decode(&audio, 8000), vec!["55".to_string()]);
+    }
+
+    #[test]
+    fn test_sequences_split_by_silence() {
+        let mut audio = synthesize("12", 8000, 70, 60, 0.0);
+        audio.extend(synthesize("34", 8000, 70, 60, 0.0));
+        assert_eq!(decode(&audio, 8000), vec!["12".to_string(), "34".to_string()]);
+    }
+
+    #[test]
+    fn test_rejects_single_tone() {
+        let rate = 8000;
+        let audio: Vec<f32> = (0..rate)
+            .map(|i| 0.5 * (2.0 * PI * 1209.0 * i as f32 / rate as f32).sin())
+            .collect();
+        assert!(decode(&audio, rate).is_empty());
+    }
+
+    #[test]
+    fn test_rejects_short_blips() {
+        // 20 ms tones are shorter than the minimum duration
+        let audio = synthesize("123", 8000, 20, 80, 0.0);
+        assert!(decode(&audio, 8000).is_empty());
+    }
+
+    #[test]
+    fn test_rejects_noise() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let audio: Vec<f32> = (0..16_000).map(|_| rng.gen_range(-0.5..0.5)).collect();
+        assert!(decode(&audio, 8000).is_empty());
+    }
+}
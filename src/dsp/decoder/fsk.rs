@@ -0,0 +1,197 @@
+//! FSK front end shared by the tone decoders (SAME, ACARS, APRS)
+//!
+//! Audio is averaged down to a detection rate, the two tones are told apart
+//! by sliding one-bit correlations against each, and a bit clock pulled onto
+//! the changes of the decision samples it half a bit after each one.
+//! Detection is non-coherent, so neither tone's phase has to be tracked.
+//! AIS and RS41 discriminate their GMSK/GFSK otherwise but share the
+//! decimator and bit clock.
+
+use num_complex::Complex;
+use std::f32::consts::PI;
+
+/// Boxcar decimator: averages blocks of input samples into one output
+#[derive(Debug)]
+pub struct Boxcar {
+    factor: usize,
+    sum: f32,
+    count: usize,
+}
+
+impl Boxcar {
+    /// Average `sample_rate` down to `target` or just above it
+    pub fn new(sample_rate: u32, target: u32) -> Self {
+        Self {
+            factor: (sample_rate / target).max(1) as usize,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Output rate for input at `sample_rate`
+    pub fn rate(&self, sample_rate: u32) -> f32 {
+        sample_rate as f32 / self.factor as f32
+    }
+
+    /// Add one input sample; returns the average once a block is complete
+    pub fn push(&mut self, x: f32) -> Option<f32> {
+        self.sum += x;
+        self.count += 1;
+        if self.count < self.factor {
+            return None;
+        }
+        let average = self.sum / self.factor as f32;
+        self.sum = 0.0;
+        self.count = 0;
+        Some(average)
+    }
+
+    /// Drop the block in progress
+    pub fn reset(&mut self) {
+        self.sum = 0.0;
+        self.count = 0;
+    }
+}
+
+/// Sliding one-bit correlators against two tones
+#[derive(Debug)]
+pub struct ToneCorrelator {
+    /// Per-tone oscillator step, oscillator and running sum
+    steps: [Complex<f32>; 2],
+    oscs: [Complex<f32>; 2],
+    sums: [Complex<f32>; 2],
+    /// Products currently inside the window
+    window: Vec<[Complex<f32>; 2]>,
+    window_pos: usize,
+}
+
+impl ToneCorrelator {
+    /// Correlators one bit at `baud` long for the `tones` in Hz, at `rate`
+    pub fn new(rate: f32, baud: f32, tones: [f32; 2]) -> Self {
+        let step = |freq: f32| Complex::from_polar(1.0, -2.0 * PI * freq / rate);
+        let window_len = (rate / baud).round() as usize;
+        Self {
+            steps: tones.map(step),
+            oscs: [Complex::new(1.0, 0.0); 2],
+            sums: [Complex::new(0.0, 0.0); 2],
+            window: vec![[Complex::new(0.0, 0.0); 2]; window_len],
+            window_pos: 0,
+        }
+    }
+
+    /// Add one sample; true while the second tone is the stronger over the
+    /// last bit
+    pub fn push(&mut self, x: f32) -> bool {
+        let products = [self.oscs[0] * x, self.oscs[1] * x];
+        let old = std::mem::replace(&mut self.window[self.window_pos], products);
+        self.window_pos = (self.window_pos + 1) % self.window.len();
+        for i in 0..2 {
+            self.oscs[i] *= self.steps[i];
+            self.sums[i] += products[i] - old[i];
+        }
+
+        // Keep the oscillators on the unit circle
+        if self.window_pos == 0 {
+            for osc in &mut self.oscs {
+                *osc /= osc.norm();
+            }
+        }
+
+        self.sums[1].norm_sqr() > self.sums[0].norm_sqr()
+    }
+
+    /// Empty the window
+    pub fn reset(&mut self) {
+        self.sums = [Complex::new(0.0, 0.0); 2];
+        self.window.fill([Complex::new(0.0, 0.0); 2]);
+        self.window_pos = 0;
+    }
+}
+
+/// Bit clock recovered from the changes of a two-level decision
+#[derive(Debug)]
+pub struct BitClock {
+    /// Phase in bits, sampling when it wraps past 1.0
+    phase: f32,
+    step: f32,
+    /// Share of the phase error corrected at each change
+    gain: f32,
+    last: bool,
+}
+
+impl BitClock {
+    /// A clock for `baud` at `rate`, correcting by `gain` at each change
+    pub fn new(rate: f32, baud: f32, gain: f32) -> Self {
+        Self {
+            phase: 0.0,
+            step: baud / rate,
+            gain,
+            last: false,
+        }
+    }
+
+    /// Add one decision; returns it when the clock samples a bit
+    pub fn push(&mut self, level: bool) -> Option<bool> {
+        // Half a bit after a change is mid-bit for a level that changes on
+        // bit boundaries, and the end of the bit for a one-bit window, whose
+        // decision flips half way through it
+        if level != self.last {
+            self.last = level;
+            self.phase += (0.5 - self.phase) * self.gain;
+        }
+
+        self.phase += self.step;
+        if self.phase < 1.0 {
+            return None;
+        }
+        self.phase -= 1.0;
+        Some(level)
+    }
+
+    /// Start again from phase zero
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.last = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boxcar_averages_blocks() {
+        let mut boxcar = Boxcar::new(48_000, 10_000);
+        assert_eq!(boxcar.rate(48_000), 12_000.0);
+        let out: Vec<f32> = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]
+            .into_iter()
+            .filter_map(|x| boxcar.push(x))
+            .collect();
+        assert_eq!(out, [2.5, 6.5]);
+        assert_eq!(Boxcar::new(8_000, 10_000).rate(8_000), 8_000.0);
+    }
+
+    #[test]
+    fn test_tones_and_clock() {
+        // 1200 baud, 1200/2200 Hz: each bit sent as its tone for one bit
+        let (rate, baud) = (24_000.0, 1200.0);
+        let sent = [false, true, true, false, true, false, false, true, false, true];
+        let mut phase = 0.0f32;
+        let audio: Vec<f32> = sent
+            .iter()
+            .flat_map(|&bit| std::iter::repeat_n(bit, 20))
+            .map(|bit| {
+                phase += 2.0 * PI * if bit { 2200.0 } else { 1200.0 } / rate;
+                phase.sin()
+            })
+            .collect();
+
+        let mut correlator = ToneCorrelator::new(rate, baud, [1200.0, 2200.0]);
+        let mut clock = BitClock::new(rate, baud, 0.3);
+        let received: Vec<bool> = audio
+            .iter()
+            .filter_map(|&x| clock.push(correlator.push(x)))
+            .collect();
+        assert_eq!(received, sent);
+    }
+}
@@ -0,0 +1,192 @@
+//! HDLC framing shared by the packet decoders (AIS, APRS/AX.25)
+//!
+//! Both link layers send NRZI-coded bits (a 0 is a level change, a 1 is no
+//! change), bytes LSB first, a zero stuffed after every five consecutive ones,
+//! frames delimited by 0x7E flags and protected by a CRC-16/X.25 frame check
+//! sequence.
+
+/// Shortest frame accepted, in bytes including the FCS
+const MIN_FRAME_LEN: usize = 4;
+/// Longest frame accepted, in bytes including the FCS
+const MAX_FRAME_LEN: usize = 512;
+
+/// NRZI line decoder
+#[derive(Debug, Default)]
+pub struct Nrzi {
+    last: bool,
+}
+
+impl Nrzi {
+    /// Turn a received line level into a data bit
+    pub fn decode(&mut self, level: bool) -> bool {
+        let bit = level == self.last;
+        self.last = level;
+        bit
+    }
+}
+
+/// Bit-level HDLC deframer: flag detection, bit de-stuffing and FCS check
+#[derive(Debug, Default)]
+pub struct HdlcDeframer {
+    /// Consecutive ones seen on the line
+    ones: u32,
+    /// De-stuffed bits since the last flag
+    bits: Vec<bool>,
+    /// Whether a flag has been seen (bits are frame content)
+    in_frame: bool,
+}
+
+impl HdlcDeframer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Push one decoded bit; returns a frame (without FCS) when a valid one ends
+    pub fn push(&mut self, bit: bool) -> Option<Vec<u8>> {
+        if bit {
+            self.ones += 1;
+            if self.ones >= 7 {
+                // Abort / idle line
+                self.in_frame = false;
+                self.bits.clear();
+                return None;
+            }
+            self.bits.push(true);
+            return None;
+        }
+
+        let ones = std::mem::replace(&mut self.ones, 0);
+        match ones {
+            // Stuffed zero, not data
+            5 => None,
+            // Flag: the frame content is everything before its leading 0 and six ones
+            6 => {
+                let frame = if self.in_frame && self.bits.len() >= 7 {
+                    self.bits.truncate(self.bits.len() - 7);
+                    pack_frame(&self.bits)
+                } else {
+                    None
+                };
+                self.bits.clear();
+                self.in_frame = true;
+                frame
+            }
+            _ => {
+                if self.in_frame {
+                    self.bits.push(false);
+                    if self.bits.len() > (MAX_FRAME_LEN + 1) * 8 {
+                        self.in_frame = false;
+                        self.bits.clear();
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Pack LSB-first bits into bytes and check the FCS
+fn pack_frame(bits: &[bool]) -> Option<Vec<u8>> {
+    if !bits.len().is_multiple_of(8) || !(MIN_FRAME_LEN..=MAX_FRAME_LEN).contains(&(bits.len() / 8)) {
+        return None;
+    }
+
+    let mut bytes: Vec<u8> = bits
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, &bit)| byte | (u8::from(bit) << i))
+        })
+        .collect();
+
+    let fcs = u16::from_le_bytes([bytes[bytes.len() - 2], bytes[bytes.len() - 1]]);
+    bytes.truncate(bytes.len() - 2);
+    (crc16_x25(&bytes) == fcs).then_some(bytes)
+}
+
+/// CRC-16/X.25 (reflected CCITT polynomial, init and final XOR 0xFFFF)
+pub fn crc16_x25(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x8408 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Frame `data` as line bits: flag, stuffed data and FCS (LSB first), flag
+#[cfg(test)]
+pub fn encode_frame(data: &[u8]) -> Vec<bool> {
+    const FLAG: [bool; 8] = [false, true, true, true, true, true, true, false];
+
+    let mut bits = FLAG.to_vec();
+    let mut ones = 0;
+    let fcs = crc16_x25(data).to_le_bytes();
+    for &byte in data.iter().chain(fcs.iter()) {
+        for i in 0..8 {
+            let bit = byte >> i & 1 == 1;
+            bits.push(bit);
+            ones = if bit { ones + 1 } else { 0 };
+            if ones == 5 {
+                bits.push(false);
+                ones = 0;
+            }
+        }
+    }
+    bits.extend(FLAG);
+    bits
+}
+
+/// NRZI-encode data bits into line levels
+#[cfg(test)]
+pub fn nrzi_encode(bits: &[bool]) -> Vec<bool> {
+    let mut level = false;
+    bits.iter()
+        .map(|&bit| {
+            if !bit {
+                level = !level;
+            }
+            level
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deframe(levels: &[bool]) -> Vec<Vec<u8>> {
+        let mut nrzi = Nrzi::default();
+        let mut deframer = HdlcDeframer::new();
+        levels
+            .iter()
+            .filter_map(|&level| deframer.push(nrzi.decode(level)))
+            .collect()
+    }
+
+    #[test]
+    fn test_crc16_x25_check_value() {
+        assert_eq!(crc16_x25(b"123456789"), 0x906E);
+    }
+
+    #[test]
+    fn test_round_trip_with_stuffing() {
+        // 0xFF and 0x7E bytes force stuffed zeros inside the frame
+        let data = vec![0x12, 0xFF, 0x7E, 0xFF, 0x00, 0x3F, 0xFC];
+        let mut bits = vec![true; 10];
+        bits.extend(encode_frame(&data));
+        bits.extend(encode_frame(b"second"));
+        assert_eq!(deframe(&nrzi_encode(&bits)), vec![data, b"second".to_vec()]);
+    }
+
+    #[test]
+    fn test_rejects_bad_fcs() {
+        let mut bits = encode_frame(b"hello world");
+        bits[20] = !bits[20];
+        assert!(deframe(&nrzi_encode(&bits)).is_empty());
+    }
+}
@@ -0,0 +1,623 @@
+//! ISM band OOK sensor decoder (rtl_433 style)
+//!
+//! The pulse extractor turns IQ magnitude into trains of (pulse, gap) durations: the
+//! magnitude is averaged to ~10 us resolution and sliced against a threshold
+//! halfway between a tracked noise floor and a decaying signal peak, with
+//! hysteresis. A long gap ends the train, which is then offered to each
+//! registered [`OokProtocol`] in turn.
+//!
+//! Protocols only ever see pulse durations, so adding a device means
+//! implementing the trait and listing it in [`default_protocols`]; the slicer
+//! helpers [`pwm_bits`] and [`manchester_bits`] cover the common encodings.
+
+use super::{Decoder, DecoderInput, InputKind};
+use crate::types::{DecodedMessage, DemodMode};
+
+/// Magnitude averaging target resolution in microseconds
+const TICK_US: f32 = 10.0;
+/// Gap that ends a pulse train
+const RESET_US: f32 = 10_000.0;
+/// Highs or lows shorter than this are merged into their neighbors
+const GLITCH_US: f32 = 40.0;
+/// Shortest train worth offering to the protocols
+const MIN_PULSES: usize = 8;
+/// Noise floor and signal peak decay time constants in microseconds
+const FLOOR_TAU_US: f32 = 5_000.0;
+const PEAK_TAU_US: f32 = 300_000.0;
+/// Minimum peak-to-floor magnitude ratio for anything to count as a signal
+const MIN_SNR: f32 = 4.0;
+/// Threshold position between floor and peak, rising and falling
+const THRESHOLD_ON: f32 = 0.5;
+const THRESHOLD_OFF: f32 = 0.35;
+/// Identical readings closer together than this are repeats of one transmission
+const REPEAT_WINDOW_US: f64 = 1_000_000.0;
+
+/// One pulse (carrier on) and the following gap, in microseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pulse {
+    pub width: f32,
+    pub gap: f32,
+}
+
+/// Fields decoded from a sensor transmission
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IsmReading {
+    /// Name of the protocol that decoded it
+    pub protocol: &'static str,
+    pub id: Option<u32>,
+    pub channel: Option<u8>,
+    pub battery_ok: Option<bool>,
+    pub temperature_c: Option<f32>,
+    pub humidity: Option<u8>,
+    /// Raw payload bytes, MSB first
+    pub data: Vec<u8>,
+}
+
+impl IsmReading {
+    /// One-line summary for the message list
+    pub fn summary(&self) -> String {
+        let mut parts = vec![self.protocol.to_string()];
+        if let Some(id) = self.id {
+            parts.push(format!("id {}", id));
+        }
+        if let Some(channel) = self.channel {
+            parts.push(format!("ch {}", channel));
+        }
+        if let Some(t) = self.temperature_c {
+            parts.push(format!("{:.1} C", t));
+        }
+        if let Some(h) = self.humidity {
+            parts.push(format!("{}% RH", h));
+        }
+        if let Some(ok) = self.battery_ok {
+            parts.push(format!("battery {}", if ok { "ok" } else { "LOW" }));
+        }
+        if self.temperature_c.is_none() && self.humidity.is_none() {
+            let hex: String = self.data.iter().map(|b| format!("{:02x}", b)).collect();
+            parts.push(format!("data {}", hex));
+        }
+        format!("ISM: {}", parts.join(", "))
+    }
+
+    /// Structured fields for logs
+    pub fn fields(&self) -> serde_json::Value {
+        serde_json::json!({
+            "protocol": self.protocol,
+            "id": self.id,
+            "channel": self.channel,
+            "battery_ok": self.battery_ok,
+            "temperature_c": self.temperature_c,
+            "humidity": self.humidity,
+            "data": self.data.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+        })
+    }
+}
+
+/// A device protocol matched against extracted pulse trains
+pub trait OokProtocol: Send {
+    /// Protocol name shown with its readings
+    fn name(&self) -> &'static str;
+
+    /// Decode a pulse train, or None if it isn't this protocol
+    fn decode(&self, pulses: &[Pulse]) -> Option<IsmReading>;
+}
+
+/// The shipped protocols, most specific first
+pub fn default_protocols() -> Vec<Box<dyn OokProtocol>> {
+    vec![
+        Box::new(Acurite592Txr),
+        Box::new(GenericPwm),
+        Box::new(GenericManchester),
+    ]
+}
+
+/// Slice pulse-width coded bits: pulses wider than `threshold` are 1
+pub fn pwm_bits(pulses: &[Pulse], threshold: f32) -> Vec<bool> {
+    pulses.iter().map(|p| p.width > threshold).collect()
+}
+
+/// Decode Manchester coded bits from a pulse train with the given half-bit
+/// period; a high-to-low transition mid-bit is a 1
+///
+/// Every pulse and gap must be one or two half-bits long. The final gap is
+/// the end of the train, so only as much of it is used as completes a bit.
+pub fn manchester_bits(pulses: &[Pulse], half_bit: f32) -> Option<Vec<bool>> {
+    let quantize = |d: f32| {
+        let n = (d / half_bit).round();
+        ((1.0..=2.0).contains(&n) && (d / half_bit - n).abs() < 0.35).then_some(n as usize)
+    };
+
+    let mut levels = Vec::new();
+    for (i, pulse) in pulses.iter().enumerate() {
+        levels.extend(std::iter::repeat_n(true, quantize(pulse.width)?));
+        if i + 1 < pulses.len() {
+            levels.extend(std::iter::repeat_n(false, quantize(pulse.gap)?));
+        }
+    }
+    let decode = |levels: &[bool]| -> Option<Vec<bool>> {
+        levels
+            .chunks(2)
+            .map(|pair| match pair {
+                [a, b] if a != b => Some(*a),
+                // A trailing half bit is the start of the idle gap
+                [a] => Some(*a),
+                _ => None,
+            })
+            .collect()
+    };
+
+    // The leading low half of a first 0 bit is indistinguishable from idle
+    decode(&levels).or_else(|| {
+        levels.insert(0, false);
+        decode(&levels)
+    })
+}
+
+/// Pack bits MSB first, zero-padding the last byte
+fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, &bit)| byte | (u8::from(bit) << (7 - i)))
+        })
+        .collect()
+}
+
+/// Acurite 592TXR-style thermo-hygrometer (PWM)
+///
+/// Four ~600 us sync pulses, then 56 bits: 0 is a 200 us pulse, 1 a 400 us
+/// pulse. Bytes: channel(2) id(14), status (bit 6 = battery ok), then
+/// humidity, temperature high and low bytes with even parity in bit 7, and a
+/// sum-of-bytes checksum. Temperature is tenths of a degree C offset by 1000.
+pub struct Acurite592Txr;
+
+impl Acurite592Txr {
+    fn decode_at(&self, data: &[Pulse]) -> Option<IsmReading> {
+        if data.len() < 56 || data[..56].iter().any(|p| !(100.0..=520.0).contains(&p.width)) {
+            return None;
+        }
+        let b = pack_bits(&pwm_bits(&data[..56], 300.0));
+
+        let sum = b[..6].iter().fold(0u8, |s, &x| s.wrapping_add(x));
+        if sum != b[6] || b[3..6].iter().any(|x| x.count_ones() % 2 != 0) {
+            return None;
+        }
+
+        // Channel bits: 11 = A, 10 = B, 00 = C
+        let channel = match b[0] >> 6 {
+            3 => 1,
+            2 => 2,
+            0 => 3,
+            _ => return None,
+        };
+        let raw_temp = ((b[4] & 0x0F) as i32) << 7 | (b[5] & 0x7F) as i32;
+
+        Some(IsmReading {
+            protocol: self.name(),
+            id: Some(((b[0] & 0x3F) as u32) << 8 | b[1] as u32),
+            channel: Some(channel),
+            battery_ok: Some(b[2] & 0x40 != 0),
+            temperature_c: Some((raw_temp - 1000) as f32 / 10.0),
+            humidity: Some(b[3] & 0x7F).filter(|&h| h <= 100),
+            data: b,
+        })
+    }
+}
+
+impl OokProtocol for Acurite592Txr {
+    fn name(&self) -> &'static str {
+        "Acurite 592TXR"
+    }
+
+    fn decode(&self, pulses: &[Pulse]) -> Option<IsmReading> {
+        let is_sync = |p: &Pulse| (500.0..=800.0).contains(&p.width);
+        // The packet repeats; take the first copy that checks out
+        (0..pulses.len().saturating_sub(4))
+            .filter(|&i| pulses[i..i + 4].iter().all(is_sync) && pulses.get(i + 4).is_some_and(|p| !is_sync(p)))
+            .find_map(|i| self.decode_at(&pulses[i + 4..]))
+    }
+}
+
+/// Fallback for any two-width PWM train with a fixed bit period; long
+/// pulses are 1
+pub struct GenericPwm;
+
+impl OokProtocol for GenericPwm {
+    fn name(&self) -> &'static str {
+        "PWM"
+    }
+
+    fn decode(&self, pulses: &[Pulse]) -> Option<IsmReading> {
+        let short = pulses.iter().map(|p| p.width).fold(f32::MAX, f32::min);
+        let long = pulses.iter().map(|p| p.width).fold(0.0, f32::max);
+        if pulses.len() < 16 || long < short * 1.6 {
+            return None;
+        }
+        let near = |w: f32, target: f32| (w - target).abs() < target * 0.3;
+        if !pulses.iter().all(|p| near(p.width, short) || near(p.width, long)) {
+            return None;
+        }
+        // The last gap runs into the end of the train
+        let mut periods: Vec<f32> = pulses[..pulses.len() - 1].iter().map(|p| p.width + p.gap).collect();
+        periods.sort_by(f32::total_cmp);
+        let period = periods[periods.len() / 2];
+        if !periods.iter().all(|&t| (t - period).abs() < period * 0.25) {
+            return None;
+        }
+
+        Some(IsmReading {
+            protocol: self.name(),
+            data: pack_bits(&pwm_bits(pulses, (short + long) / 2.0)),
+            ..Default::default()
+        })
+    }
+}
+
+/// Fallback for Manchester trains; the half-bit is the shortest duration
+pub struct GenericManchester;
+
+impl OokProtocol for GenericManchester {
+    fn name(&self) -> &'static str {
+        "Manchester"
+    }
+
+    fn decode(&self, pulses: &[Pulse]) -> Option<IsmReading> {
+        let half_bit = pulses
+            .iter()
+            .flat_map(|p| [p.width, p.gap])
+            .fold(f32::MAX, f32::min);
+        let bits = manchester_bits(pulses, half_bit)?;
+        (bits.len() >= 16).then(|| IsmReading {
+            protocol: self.name(),
+            data: pack_bits(&bits),
+            ..Default::default()
+        })
+    }
+}
+
+/// Slices IQ magnitude into pulse trains
+pub struct PulseExtractor {
+    /// IQ samples averaged into one tick, and the tick length in us
+    decimation: usize,
+    mag_sum: f32,
+    mag_count: usize,
+    tick_us: f32,
+    /// Adaptive levels and their per-tick coefficients
+    floor: f32,
+    floor_coeff: f32,
+    peak: f32,
+    peak_decay: f32,
+    /// Current state, its duration, and the width of the pulse in progress
+    high: bool,
+    run_us: f32,
+    width: f32,
+    pulses: Vec<Pulse>,
+}
+
+impl PulseExtractor {
+    pub fn new(sample_rate: u32) -> Self {
+        let decimation = ((sample_rate as f32 * TICK_US / 1e6).round() as usize).max(1);
+        let tick_us = decimation as f32 * 1e6 / sample_rate as f32;
+        Self {
+            decimation,
+            mag_sum: 0.0,
+            mag_count: 0,
+            tick_us,
+            floor: 0.0,
+            floor_coeff: tick_us / FLOOR_TAU_US,
+            peak: 0.0,
+            peak_decay: tick_us / PEAK_TAU_US,
+            high: false,
+            run_us: 0.0,
+            width: 0.0,
+            pulses: Vec::new(),
+        }
+    }
+
+    /// Feed IQ magnitude samples; returns completed pulse trains
+    pub fn process(&mut self, magnitude: &[f32]) -> Vec<Vec<Pulse>> {
+        let mut trains = Vec::new();
+
+        for &mag in magnitude {
+            self.mag_sum += mag;
+            self.mag_count += 1;
+            if self.mag_count < self.decimation {
+                continue;
+            }
+            let mag = self.mag_sum / self.decimation as f32;
+            self.mag_sum = 0.0;
+            self.mag_count = 0;
+
+            if let Some(train) = self.tick(mag) {
+                trains.push(train);
+            }
+        }
+
+        trains
+    }
+
+    fn tick(&mut self, mag: f32) -> Option<Vec<Pulse>> {
+        if mag > self.peak {
+            self.peak = mag;
+        } else {
+            self.peak += (mag - self.peak) * self.peak_decay;
+        }
+        // The floor follows the mean noise level while the carrier is off
+        if self.floor == 0.0 {
+            self.floor = mag;
+        } else if !self.high {
+            self.floor += (mag - self.floor) * self.floor_coeff;
+        }
+
+        let range = self.peak - self.floor;
+        let level = if self.high { THRESHOLD_OFF } else { THRESHOLD_ON };
+        let high = self.peak > self.floor * MIN_SNR && mag > self.floor + range * level;
+
+        if high == self.high || self.run_us < GLITCH_US {
+            // Glitches extend the current state instead of toggling it
+            self.run_us += self.tick_us;
+            if !self.high && !self.pulses.is_empty() && self.run_us >= RESET_US {
+                return self.end_train();
+            }
+            return None;
+        }
+
+        if high {
+            // Gap ended: record the previous pulse
+            if self.width > 0.0 {
+                self.pulses.push(Pulse { width: self.width, gap: self.run_us });
+            }
+        } else {
+            self.width = self.run_us;
+        }
+        self.high = high;
+        self.run_us = self.tick_us;
+        None
+    }
+
+    fn end_train(&mut self) -> Option<Vec<Pulse>> {
+        if self.width > 0.0 {
+            self.pulses.push(Pulse { width: self.width, gap: self.run_us });
+        }
+        self.width = 0.0;
+        let train = std::mem::take(&mut self.pulses);
+        (train.len() >= MIN_PULSES).then_some(train)
+    }
+}
+
+/// Pulse extraction plus protocol matching, with repeat suppression
+pub struct IsmDecoder {
+    sample_rate: u32,
+    extractor: PulseExtractor,
+    protocols: Vec<Box<dyn OokProtocol>>,
+    /// Microseconds of IQ processed, for repeat suppression
+    elapsed_us: f64,
+    sample_us: f64,
+    last: Option<(Vec<u8>, f64)>,
+}
+
+impl IsmDecoder {
+    pub fn new(sample_rate: u32) -> Self {
+        Self::with_protocols(sample_rate, default_protocols())
+    }
+
+    pub fn with_protocols(sample_rate: u32, protocols: Vec<Box<dyn OokProtocol>>) -> Self {
+        Self {
+            sample_rate,
+            extractor: PulseExtractor::new(sample_rate),
+            protocols,
+            elapsed_us: 0.0,
+            sample_us: 1e6 / sample_rate as f64,
+            last: None,
+        }
+    }
+
+    /// Feed IQ magnitude; returns readings from trains completed in this buffer
+    pub fn process(&mut self, magnitude: &[f32]) -> Vec<IsmReading> {
+        self.elapsed_us += magnitude.len() as f64 * self.sample_us;
+        let mut readings = Vec::new();
+
+        for train in self.extractor.process(magnitude) {
+            let Some(reading) = self.protocols.iter().find_map(|p| p.decode(&train)) else {
+                log::debug!("ISM: unmatched train of {} pulses", train.len());
+                continue;
+            };
+            let repeat = self.last.as_ref().is_some_and(|(data, at)| {
+                *data == reading.data && self.elapsed_us - at < REPEAT_WINDOW_US
+            });
+            self.last = Some((reading.data.clone(), self.elapsed_us));
+            if !repeat {
+                readings.push(reading);
+            }
+        }
+
+        readings
+    }
+}
+
+impl Decoder for IsmDecoder {
+    fn name(&self) -> &'static str {
+        "ISM"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Magnitude
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Magnitude(magnitude) = input else {
+            return Vec::new();
+        };
+        IsmDecoder::process(self, magnitude)
+            .iter()
+            .map(|reading| {
+                DecodedMessage::new(DemodMode::Ism, reading.summary()).with_fields(reading.fields())
+            })
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        let protocols = std::mem::take(&mut self.protocols);
+        *self = IsmDecoder::with_protocols(self.sample_rate, protocols);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_complex::Complex;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const RATE: u32 = 250_000;
+
+    /// OOK-modulate pulse trains (separated by 20 ms) into the magnitude of noisy IQ
+    fn modulate(trains: &[Vec<Pulse>], noise: f32) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(11);
+        let us = |d: f32| (d * RATE as f32 / 1e6) as usize;
+        let mut on = vec![false; us(5_000.0)];
+        for train in trains {
+            for p in train {
+                on.extend(std::iter::repeat_n(true, us(p.width)));
+                on.extend(std::iter::repeat_n(false, us(p.gap)));
+            }
+            on.extend(std::iter::repeat_n(false, us(20_000.0)));
+        }
+        on.iter()
+            .enumerate()
+            .map(|(i, &on)| {
+                let carrier = Complex::from_polar(0.5, i as f32 * 0.3);
+                let n = Complex::new(rng.gen_range(-noise..noise), rng.gen_range(-noise..noise));
+                if on { carrier + n } else { n }.norm()
+            })
+            .collect()
+    }
+
+    fn decode(trains: &[Vec<Pulse>]) -> Vec<IsmReading> {
+        let magnitude = modulate(trains, 0.04);
+        let mut decoder = IsmDecoder::new(RATE);
+        magnitude.chunks(16_384).flat_map(|c| decoder.process(c)).collect()
+    }
+
+    /// A 592TXR packet: channel A, id 0x1234, 22.5 C, 45% RH, battery ok
+    fn acurite_bytes(temp_c: f32) -> Vec<u8> {
+        let parity = |b: u8| b | (((b.count_ones() % 2) as u8) << 7);
+        let raw = ((temp_c * 10.0).round() as i32 + 1000) as u32;
+        let mut b = vec![
+            0xC0 | 0x12,
+            0x34,
+            0x44,
+            parity(45),
+            parity((raw >> 7) as u8 & 0x0F),
+            parity(raw as u8 & 0x7F),
+        ];
+        b.push(b.iter().fold(0u8, |s, &x| s.wrapping_add(x)));
+        b
+    }
+
+    fn acurite_train(bytes: &[u8], repeats: usize) -> Vec<Pulse> {
+        let mut train = Vec::new();
+        for _ in 0..repeats {
+            train.extend([Pulse { width: 600.0, gap: 600.0 }; 4]);
+            for byte in bytes {
+                for i in (0..8).rev() {
+                    train.push(if byte >> i & 1 == 1 {
+                        Pulse { width: 400.0, gap: 200.0 }
+                    } else {
+                        Pulse { width: 200.0, gap: 400.0 }
+                    });
+                }
+            }
+        }
+        train
+    }
+
+    fn manchester_train(bytes: &[u8], half_bit: f32) -> Vec<Pulse> {
+        let levels: Vec<bool> = bytes
+            .iter()
+            .flat_map(|&b| (0..8).rev().map(move |i| b >> i & 1 == 1))
+            .flat_map(|bit| [bit, !bit])
+            .collect();
+        let mut train: Vec<Pulse> = Vec::new();
+        let mut i = levels.iter().position(|&l| l).unwrap();
+        while i < levels.len() {
+            let width = levels[i..].iter().take_while(|&&l| l).count();
+            let gap = levels[i + width..].iter().take_while(|&&l| !l).count();
+            train.push(Pulse { width: width as f32 * half_bit, gap: gap.max(1) as f32 * half_bit });
+            i += width + gap;
+        }
+        train
+    }
+
+    #[test]
+    fn test_acurite_from_pulses() {
+        let reading = Acurite592Txr.decode(&acurite_train(&acurite_bytes(22.5), 1)).unwrap();
+        assert_eq!(reading.id, Some(0x1234));
+        assert_eq!(reading.channel, Some(1));
+        assert_eq!(reading.battery_ok, Some(true));
+        assert_eq!(reading.temperature_c, Some(22.5));
+        assert_eq!(reading.humidity, Some(45));
+    }
+
+    #[test]
+    fn test_acurite_rejects_bad_checksum() {
+        let mut bytes = acurite_bytes(22.5);
+        bytes[6] ^= 1;
+        assert!(Acurite592Txr.decode(&acurite_train(&bytes, 1)).is_none());
+    }
+
+    #[test]
+    fn test_acurite_over_the_air() {
+        // Three repeats in one train, below freezing; reported once
+        let readings = decode(&[acurite_train(&acurite_bytes(-7.3), 3)]);
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].protocol, "Acurite 592TXR");
+        assert_eq!(readings[0].temperature_c, Some(-7.3));
+    }
+
+    #[test]
+    fn test_generic_pwm_over_the_air() {
+        let train: Vec<Pulse> = pack_bits(&[true, false, true, true, false, false, true, false])
+            .iter()
+            .chain([0x5A, 0xC3].iter())
+            .flat_map(|&b| (0..8).rev().map(move |i| b >> i & 1 == 1))
+            .map(|bit| if bit { Pulse { width: 750.0, gap: 250.0 } } else { Pulse { width: 250.0, gap: 750.0 } })
+            .collect();
+        let readings = decode(&[train]);
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].protocol, "PWM");
+        assert_eq!(readings[0].data, vec![0xB2, 0x5A, 0xC3]);
+    }
+
+    #[test]
+    fn test_manchester_slicer() {
+        for bytes in [[0xA5u8, 0x0F, 0x96], [0x3C, 0xFF, 0x01]] {
+            let bits = manchester_bits(&manchester_train(&bytes, 500.0), 500.0).unwrap();
+            assert_eq!(pack_bits(&bits)[..3], bytes);
+        }
+    }
+
+    #[test]
+    fn test_generic_manchester_over_the_air() {
+        let readings = decode(&[manchester_train(&[0xD3, 0x91, 0x7E], 400.0)]);
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].protocol, "Manchester");
+        assert_eq!(readings[0].data[..3], [0xD3, 0x91, 0x7E]);
+    }
+
+    #[test]
+    fn test_noise_only() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let magnitude: Vec<f32> = (0..RATE)
+            .map(|_| Complex::new(rng.gen_range(-0.05f32..0.05), rng.gen_range(-0.05f32..0.05)).norm())
+            .collect();
+        let mut extractor = PulseExtractor::new(RATE);
+        assert!(extractor.process(&magnitude).is_empty());
+    }
+}
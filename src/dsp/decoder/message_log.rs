@@ -0,0 +1,340 @@
+//! Decoded message log files
+//!
+//! Messages are appended as JSON lines (timestamp, mode, content and any
+//! structured fields and raw message) and/or as plain text. Files are opened
+//! lazily, flushed every few seconds and rotated when the local date changes
+//! or they grow past the size limit; a rotated file keeps the date it was
+//! started on, e.g. `decodes.2024-06-01.jsonl`, then
+//! `decodes.2024-06-01.1.jsonl`.
+
+use crate::types::DecodedMessage;
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often buffered lines are flushed to disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Which files the log writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeLogFormat {
+    Jsonl,
+    Text,
+    /// JSON lines in `<path>.jsonl` and text in `<path>.log`
+    Both,
+}
+
+impl std::str::FromStr for DecodeLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "jsonl" | "json" => Ok(DecodeLogFormat::Jsonl),
+            "text" | "txt" => Ok(DecodeLogFormat::Text),
+            "both" => Ok(DecodeLogFormat::Both),
+            _ => Err(format!("unknown decode log format '{}' (expected jsonl, text or both)", s)),
+        }
+    }
+}
+
+/// One message as a JSON line
+pub fn json_line(message: &DecodedMessage) -> String {
+    json_record(message).to_string()
+}
+
+/// One message as a JSON object: timestamp, mode, content and, if there are
+/// any, fields and the raw message
+pub fn json_record(message: &DecodedMessage) -> serde_json::Value {
+    let mut record = serde_json::json!({
+        "timestamp": message.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        "mode": message.mode.file_tag(),
+        "content": message.content,
+    });
+    if !message.fields.is_empty() {
+        record["fields"] = serde_json::Value::Object(message.fields.clone());
+    }
+    if let Some(raw) = &message.raw {
+        record["raw"] = raw.clone().into();
+    }
+    record
+}
+
+/// One message as a line of text, in local time
+pub fn text_line(message: &DecodedMessage) -> String {
+    format!(
+        "{} [{}] {}",
+        message.timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+        message.mode.file_tag(),
+        message.content
+    )
+}
+
+/// An open log file and how much has been written to it
+struct LogFile {
+    path: PathBuf,
+    json: bool,
+    writer: BufWriter<File>,
+    size: u64,
+}
+
+/// Appends decoded messages to rotating log files
+pub struct MessageLog {
+    path: PathBuf,
+    format: DecodeLogFormat,
+    max_bytes: u64,
+    files: Vec<LogFile>,
+    /// Local date the open files were started on
+    opened_on: Option<NaiveDate>,
+    last_flush: Instant,
+}
+
+impl MessageLog {
+    /// A closed log; files are opened on the first write
+    pub fn new(path: PathBuf, format: DecodeLogFormat, max_bytes: u64) -> Self {
+        Self {
+            path,
+            format,
+            max_bytes,
+            files: Vec::new(),
+            opened_on: None,
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// The files written, as (path, is JSON)
+    fn targets(&self) -> Vec<(PathBuf, bool)> {
+        match self.format {
+            DecodeLogFormat::Jsonl => vec![(self.path.clone(), true)],
+            DecodeLogFormat::Text => vec![(self.path.clone(), false)],
+            DecodeLogFormat::Both => vec![
+                (self.path.with_extension("jsonl"), true),
+                (self.path.with_extension("log"), false),
+            ],
+        }
+    }
+
+    /// Append a message, rotating first if its date or the size calls for it
+    pub fn write(&mut self, message: &DecodedMessage) -> Result<()> {
+        let date = message.timestamp.with_timezone(&Local).date_naive();
+        let oversize = self.files.iter().any(|f| f.size >= self.max_bytes);
+        match self.opened_on {
+            Some(opened_on) if opened_on != date || oversize => {
+                self.rotate(opened_on)?;
+                self.open(date)?;
+            }
+            None => self.open(date)?,
+            _ => {}
+        }
+
+        for file in self.files.iter_mut() {
+            let mut line = if file.json { json_line(message) } else { text_line(message) };
+            line.push('\n');
+            file.writer
+                .write_all(line.as_bytes())
+                .with_context(|| format!("Failed to write {}", file.path.display()))?;
+            file.size += line.len() as u64;
+        }
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush();
+        }
+        Ok(())
+    }
+
+    /// Flush buffered lines if the flush interval has passed
+    pub fn flush_if_due(&mut self) {
+        if !self.files.is_empty() && self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        for file in self.files.iter_mut() {
+            if let Err(e) = file.writer.flush() {
+                log::error!("Failed to flush {}: {}", file.path.display(), e);
+            }
+        }
+        self.last_flush = Instant::now();
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// Flush and close the files; the next write reopens them
+    pub fn close(&mut self) {
+        self.flush();
+        self.files.clear();
+        self.opened_on = None;
+    }
+
+    fn open(&mut self, date: NaiveDate) -> Result<()> {
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        // An existing file from an earlier day is rotated out before appending
+        for (path, _) in self.targets() {
+            let started = std::fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .map(|t| chrono::DateTime::<Local>::from(t).date_naive());
+            if let Some(started) = started.ok().filter(|&d| d != date) {
+                std::fs::rename(&path, rotated_path(&path, started))
+                    .with_context(|| format!("Failed to rotate {}", path.display()))?;
+            }
+        }
+
+        self.files = self
+            .targets()
+            .into_iter()
+            .map(|(path, json)| {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+                Ok(LogFile { path, json, writer: BufWriter::new(file), size })
+            })
+            .collect::<Result<_>>()?;
+        self.opened_on = Some(date);
+        log::info!("Logging decoded messages to {}", self.path.display());
+        Ok(())
+    }
+
+    fn rotate(&mut self, opened_on: NaiveDate) -> Result<()> {
+        self.flush();
+        for file in std::mem::take(&mut self.files) {
+            let LogFile { path, writer, .. } = file;
+            drop(writer);
+            std::fs::rename(&path, rotated_path(&path, opened_on))
+                .with_context(|| format!("Failed to rotate {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// First free `<stem>.<date>[.<n>].<ext>` next to `path`
+fn rotated_path(path: &Path, date: NaiveDate) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (0..)
+        .map(|n| {
+            let suffix = if n == 0 { String::new() } else { format!(".{}", n) };
+            path.with_file_name(format!("{}.{}{}{}", stem, date.format("%Y-%m-%d"), suffix, ext))
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+    use crate::types::DemodMode;
+    use chrono::TimeZone;
+
+    fn message_on(day: u32, content: &str) -> DecodedMessage {
+        let mut message = DecodedMessage::new(DemodMode::Ais, content.to_string());
+        message.timestamp = Local
+            .with_ymd_and_hms(2024, 6, day, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        message
+    }
+
+    fn read_lines(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_json_line_schema() {
+        let message = message_on(1, "[A] AIS 211234567")
+            .with_fields(serde_json::json!({ "mmsi": 211234567, "lat": 53.5 }));
+        let value: serde_json::Value = serde_json::from_str(&json_line(&message)).unwrap();
+
+        assert_eq!(value["mode"], "AIS");
+        assert_eq!(value["content"], "[A] AIS 211234567");
+        assert_eq!(value["fields"]["mmsi"], 211234567);
+        let timestamp = value["timestamp"].as_str().unwrap();
+        assert_eq!(chrono::DateTime::parse_from_rfc3339(timestamp).unwrap(), message.timestamp);
+
+        // Messages without structured fields omit the key
+        let plain = DecodedMessage::new(DemodMode::Cw, "CQ".to_string());
+        let value: serde_json::Value = serde_json::from_str(&json_line(&plain)).unwrap();
+        assert!(value.get("fields").is_none());
+        assert!(value.get("raw").is_none());
+
+        let packet = DecodedMessage::new(DemodMode::Aprs, "N0CALL status: QRV".to_string())
+            .with_raw("N0CALL>APRS:>QRV".to_string());
+        let value: serde_json::Value = serde_json::from_str(&json_line(&packet)).unwrap();
+        assert_eq!(value["raw"], "N0CALL>APRS:>QRV");
+    }
+
+    #[test]
+    fn test_rotates_daily() {
+        let dir = temp_dir("decode_log_daily");
+        let path = dir.join("decodes.jsonl");
+        let mut log = MessageLog::new(path.clone(), DecodeLogFormat::Jsonl, u64::MAX);
+
+        log.write(&message_on(1, "first")).unwrap();
+        log.write(&message_on(1, "second")).unwrap();
+        log.write(&message_on(2, "third")).unwrap();
+        log.close();
+
+        let rotated = read_lines(&dir.join("decodes.2024-06-01.jsonl"));
+        assert_eq!(rotated.len(), 2);
+        assert!(rotated[1].contains("\"second\""));
+        assert_eq!(read_lines(&path).len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotates_by_size_and_writes_both_formats() {
+        let dir = temp_dir("decode_log_size");
+        let path = dir.join("decodes");
+        let mut log = MessageLog::new(path, DecodeLogFormat::Both, 150);
+
+        for i in 0..6 {
+            log.write(&message_on(3, &format!("message {}", i))).unwrap();
+        }
+        log.close();
+
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert!(names.contains(&"decodes.2024-06-03.jsonl".to_string()));
+        assert!(names.contains(&"decodes.2024-06-03.1.jsonl".to_string()));
+        assert!(names.contains(&"decodes.2024-06-03.log".to_string()));
+
+        // Nothing is lost across rotations, and text lines match JSON lines
+        let all = |ext: &str| -> usize {
+            names
+                .iter()
+                .filter(|n| n.ends_with(ext))
+                .map(|n| read_lines(&dir.join(n)).len())
+                .sum()
+        };
+        assert_eq!(all(".jsonl"), 6);
+        assert_eq!(all(".log"), 6);
+        assert!(read_lines(&dir.join("decodes.log"))[0].contains("[AIS] message"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("JSONL".parse(), Ok(DecodeLogFormat::Jsonl));
+        assert_eq!("text".parse(), Ok(DecodeLogFormat::Text));
+        assert_eq!("both".parse(), Ok(DecodeLogFormat::Both));
+        assert!("xml".parse::<DecodeLogFormat>().is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! Digital decoders and the pipeline that feeds them
+//!
+//! Every decoder implements [`Decoder`], declaring which form of the signal
+//! it consumes. The [`registry::DecoderRegistry`] builds the decoders that
+//! apply to the current mode, frequency and toggles. It lives on the decoder
+//! thread, which receives each signal form the DSP thread tees off and routes
+//! it to the decoders that asked for it.
+
+pub mod acars;
+pub mod adsb;
+pub mod aircraft_json;
+pub mod ais;
+pub mod aprs;
+pub mod apt;
+pub mod cw;
+pub mod dtmf;
+pub mod fsk;
+pub mod hdlc;
+pub mod ism;
+pub mod message_log;
+pub mod reed_solomon;
+pub mod registry;
+pub mod rs41;
+pub mod same;
+pub mod thread;
+
+use crate::state::DecoderState;
+use crate::types::DecodedMessage;
+use num_complex::Complex;
+
+pub use aircraft_json::AircraftExport;
+pub use message_log::{DecodeLogFormat, MessageLog};
+pub use registry::{DecoderOutputs, DecoderRegistry, DecoderSelection};
+pub use thread::{decoder_channel, start_decoder_thread, DecoderTap};
+
+/// Signal forms a decoder can consume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// Raw IQ samples
+    Iq,
+    /// Demodulated audio for the current mode, before the squelch mute
+    Audio,
+    /// IQ magnitude (envelope)
+    Magnitude,
+}
+
+/// One buffer of input in the form a decoder asked for
+#[derive(Debug, Clone, Copy)]
+pub enum DecoderInput<'a> {
+    Iq(&'a [Complex<f32>]),
+    Audio(&'a [f32]),
+    Magnitude(&'a [f32]),
+}
+
+impl DecoderInput<'_> {
+    pub fn kind(&self) -> InputKind {
+        match self {
+            DecoderInput::Iq(_) => InputKind::Iq,
+            DecoderInput::Audio(_) => InputKind::Audio,
+            DecoderInput::Magnitude(_) => InputKind::Magnitude,
+        }
+    }
+}
+
+/// A streaming decoder driven by the DSP thread
+pub trait Decoder: Send {
+    /// Short name for logs
+    fn name(&self) -> &'static str;
+
+    /// The signal form this decoder consumes
+    fn input_kind(&self) -> InputKind;
+
+    /// Sample rate of the input the decoder was built for
+    fn sample_rate(&self) -> u32;
+
+    /// Decode one buffer; only called with input of [`Self::input_kind`]
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage>;
+
+    /// Drop any partial state, e.g. after a retune
+    fn reset(&mut self);
+
+    /// Anything still pending when the decoder is being removed
+    fn flush(&mut self) -> Vec<DecodedMessage> {
+        Vec::new()
+    }
+
+    /// Publish live status (e.g. CW speed) into the decoder state
+    fn publish(&self, _state: &mut DecoderState) {}
+
+    /// Clear whatever [`Self::publish`] set, when the decoder is removed
+    fn unpublish(&self, _state: &mut DecoderState) {}
+}
@@ -0,0 +1,252 @@
+//! Reed-Solomon RS(255, 231) over GF(2^8), as used by the RS41 radiosonde
+//!
+//! The field is built on the primitive polynomial x^8 + x^4 + x^3 + x^2 + 1
+//! (0x11D) with α = 2, and the generator has the 24 roots α^0 to α^23, so up
+//! to 12 byte errors per codeword can be corrected. Codewords are held with
+//! the coefficient of x^i at index i: the 24 parity bytes first, then the
+//! data. Shorter frames are padded with zeros, which can't be in error.
+//!
+//! Decoding is the textbook chain: syndromes, Berlekamp-Massey for the error
+//! locator, a Chien search for its roots and Forney's formula for the values.
+
+/// Codeword length in bytes
+pub const N: usize = 255;
+/// Parity bytes per codeword
+pub const PARITY: usize = 24;
+/// Data bytes per codeword
+pub const K: usize = N - PARITY;
+
+/// Log and antilog tables for GF(2^8)
+struct Field {
+    /// α^i for i up to 2 * 255, so sums of two logs need no reduction
+    exp: [u8; 2 * N],
+    log: [u8; 256],
+}
+
+impl Field {
+    const fn new() -> Self {
+        let mut exp = [0u8; 2 * N];
+        let mut log = [0u8; 256];
+        let mut x: u16 = 1;
+        let mut i = 0;
+        while i < N {
+            exp[i] = x as u8;
+            exp[i + N] = x as u8;
+            log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= 0x11D;
+            }
+            i += 1;
+        }
+        Self { exp, log }
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            0
+        } else {
+            self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+        }
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        if a == 0 {
+            0
+        } else {
+            self.exp[self.log[a as usize] as usize + N - self.log[b as usize] as usize]
+        }
+    }
+
+    /// α^power
+    fn pow(&self, power: usize) -> u8 {
+        self.exp[power % N]
+    }
+
+    /// Evaluate a polynomial (coefficient of x^i at index i) at `x`
+    fn eval(&self, poly: &[u8], x: u8) -> u8 {
+        poly.iter().rev().fold(0, |acc, &c| self.mul(acc, x) ^ c)
+    }
+}
+
+static GF: Field = Field::new();
+
+/// Correct `codeword` in place; returns the number of bytes corrected, or
+/// None when there are more errors than the code can fix
+pub fn decode(codeword: &mut [u8; N]) -> Option<usize> {
+    let syndromes: Vec<u8> = (0..PARITY).map(|j| GF.eval(codeword, GF.pow(j))).collect();
+    if syndromes.iter().all(|&s| s == 0) {
+        return Some(0);
+    }
+
+    // Berlekamp-Massey
+    let mut locator = vec![1u8];
+    let mut previous = vec![1u8];
+    let mut length = 0;
+    let mut shift = 1;
+    let mut last_discrepancy = 1u8;
+    for n in 0..PARITY {
+        let discrepancy = (1..=length)
+            .filter(|&i| i < locator.len())
+            .fold(syndromes[n], |d, i| {
+                d ^ GF.mul(locator[i], syndromes[n - i])
+            });
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = GF.div(discrepancy, last_discrepancy);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, &c) in previous.iter().enumerate() {
+            next[i + shift] ^= GF.mul(scale, c);
+        }
+        if 2 * length <= n {
+            previous = std::mem::replace(&mut locator, next);
+            length = n + 1 - length;
+            last_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+    locator.truncate(length + 1);
+    if length > PARITY / 2 {
+        return None;
+    }
+
+    // Chien search: an error at position i makes α^-i a root
+    let positions: Vec<usize> = (0..N)
+        .filter(|&i| GF.eval(&locator, GF.pow(N - i)) == 0)
+        .collect();
+    if positions.len() != length {
+        return None;
+    }
+
+    // Forney, for roots starting at α^0: e = X Ω(X^-1) / Λ'(X^-1)
+    let mut evaluator: Vec<u8> = vec![0; PARITY];
+    for (i, &l) in locator.iter().enumerate() {
+        for (j, &s) in syndromes.iter().enumerate().take(PARITY - i) {
+            evaluator[i + j] ^= GF.mul(l, s);
+        }
+    }
+    // The formal derivative keeps the odd powers
+    let derivative: Vec<u8> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &c)| if i % 2 == 1 { c } else { 0 })
+        .collect();
+    for &position in &positions {
+        let x = GF.pow(position);
+        let x_inv = GF.pow(N - position);
+        let denominator = GF.eval(&derivative, x_inv);
+        if denominator == 0 {
+            return None;
+        }
+        let value = GF.mul(x, GF.div(GF.eval(&evaluator, x_inv), denominator));
+        codeword[position] ^= value;
+    }
+    Some(positions.len())
+}
+
+/// Fill in the parity bytes of `codeword` from its data
+#[cfg(test)]
+pub fn encode(codeword: &mut [u8; N]) {
+    // Generator: the product of (x - α^j) for j in 0..PARITY
+    let mut generator = vec![1u8];
+    for j in 0..PARITY {
+        let root = GF.pow(j);
+        let mut next = vec![0u8; generator.len() + 1];
+        for (i, &c) in generator.iter().enumerate() {
+            next[i + 1] ^= c;
+            next[i] ^= GF.mul(c, root);
+        }
+        generator = next;
+    }
+
+    // Parity is the remainder of data * x^PARITY divided by the generator
+    let mut remainder = [0u8; PARITY];
+    for &data in codeword[PARITY..].iter().rev() {
+        let factor = data ^ remainder[PARITY - 1];
+        for i in (1..PARITY).rev() {
+            remainder[i] = remainder[i - 1] ^ GF.mul(factor, generator[i]);
+        }
+        remainder[0] = GF.mul(factor, generator[0]);
+    }
+    codeword[..PARITY].copy_from_slice(&remainder);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_codeword(rng: &mut StdRng) -> [u8; N] {
+        let mut codeword = [0u8; N];
+        // Shortened as in an RS41 frame: only the first 132 data bytes used
+        rng.fill(&mut codeword[PARITY..PARITY + 132]);
+        encode(&mut codeword);
+        codeword
+    }
+
+    #[test]
+    fn test_field() {
+        assert_eq!(GF.pow(8), 0x1D);
+        assert_eq!(GF.mul(0x53, 0xCA), GF.mul(0xCA, 0x53));
+        for a in 1..=255u8 {
+            assert_eq!(GF.div(GF.mul(a, 0x37), 0x37), a);
+        }
+    }
+
+    #[test]
+    fn test_codeword_has_no_syndrome() {
+        let mut rng = StdRng::seed_from_u64(41);
+        let mut codeword = random_codeword(&mut rng);
+        for j in 0..PARITY {
+            assert_eq!(GF.eval(&codeword, GF.pow(j)), 0);
+        }
+        let clean = codeword;
+        assert_eq!(decode(&mut codeword), Some(0));
+        assert_eq!(codeword, clean);
+    }
+
+    #[test]
+    fn test_corrects_up_to_twelve_errors() {
+        let mut rng = StdRng::seed_from_u64(403);
+        for errors in 1..=PARITY / 2 {
+            let clean = random_codeword(&mut rng);
+            let mut codeword = clean;
+            let mut positions = std::collections::HashSet::new();
+            while positions.len() < errors {
+                positions.insert(rng.gen_range(0..PARITY + 132));
+            }
+            for &position in &positions {
+                codeword[position] ^= rng.gen_range(1..=255u8);
+            }
+            assert_eq!(decode(&mut codeword), Some(errors), "{} errors", errors);
+            assert_eq!(codeword, clean);
+        }
+    }
+
+    #[test]
+    fn test_gives_up_beyond_twelve_errors() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut failures = 0;
+        for _ in 0..20 {
+            let clean = random_codeword(&mut rng);
+            let mut codeword = clean;
+            for position in 0..20 {
+                codeword[position * 7] ^= 0x5A;
+            }
+            match decode(&mut codeword) {
+                None => failures += 1,
+                // Miscorrection to another codeword is possible, never to this one
+                Some(_) => assert_ne!(codeword, clean),
+            }
+        }
+        assert!(failures >= 18, "{} of 20 detected", failures);
+    }
+}
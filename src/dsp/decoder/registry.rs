@@ -0,0 +1,352 @@
+//! Decoder registry: which decoders run, and what input each one gets
+//!
+//! The DSP thread describes the receiver setup as a [`DecoderSelection`]
+//! once per buffer. When it changes, the registry flushes and removes the
+//! running decoders and builds the set that applies to the new selection.
+//! Each signal form is then routed only to the decoders that consume it.
+
+use super::acars::AcarsDecoder;
+use super::adsb::AdsbDecoder;
+use super::ais::AisDecoder;
+use super::aprs::AprsDecoder;
+use super::apt::AptDecoder;
+use super::cw::CwDecoder;
+use super::dtmf::DtmfDecoder;
+use super::ism::IsmDecoder;
+use super::rs41::Rs41Decoder;
+use super::same::SameDecoder;
+use super::{Decoder, DecoderInput, InputKind};
+use crate::dsp::channelizer::listening_rate;
+use crate::sdr::config::is_weather_channel;
+use crate::state::{AppState, DecoderState};
+use crate::types::{DecodedMessage, DemodMode};
+use crossbeam::channel::Sender;
+use std::path::PathBuf;
+
+/// The receiver settings that decide which decoders run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderSelection {
+    pub mode: DemodMode,
+    pub frequency: u32,
+    pub sample_rate: u32,
+    pub dtmf_enabled: bool,
+}
+
+impl DecoderSelection {
+    /// Chain A's, at the rate its channel is decimated to for listening
+    pub fn from_state(state: &AppState) -> Self {
+        Self {
+            mode: state.decoder.mode,
+            frequency: state.sdr.frequency,
+            sample_rate: listening_rate(state.decoder.mode, state.sdr.sample_rate),
+            dtmf_enabled: state.decoder.dtmf_enabled,
+        }
+    }
+}
+
+/// Where decoders send what they make besides messages
+#[derive(Debug, Clone, Default)]
+pub struct DecoderOutputs {
+    /// AIS as NMEA sentences
+    pub nmea_tx: Option<Sender<String>>,
+    /// Radiosonde telemetry as SondeHub JSON lines
+    pub sonde_tx: Option<Sender<String>>,
+    /// Where images (APT) are written
+    pub image_dir: PathBuf,
+}
+
+/// Builds the decoders for a selection
+type DecoderFactory = Box<dyn Fn(&DecoderSelection) -> Vec<Box<dyn Decoder>> + Send>;
+
+/// The set of decoders active for the current selection
+pub struct DecoderRegistry {
+    factory: DecoderFactory,
+    selection: Option<DecoderSelection>,
+    active: Vec<Box<dyn Decoder>>,
+}
+
+impl DecoderRegistry {
+    /// Registry with the built-in decoders, sending their other output to
+    /// `outputs`
+    pub fn new(outputs: DecoderOutputs) -> Self {
+        Self::with_factory(move |selection| default_decoders(selection, &outputs))
+    }
+
+    /// Registry with a custom set of decoders per selection
+    pub fn with_factory<F>(factory: F) -> Self
+    where
+        F: Fn(&DecoderSelection) -> Vec<Box<dyn Decoder>> + Send + 'static,
+    {
+        Self {
+            factory: Box::new(factory),
+            selection: None,
+            active: Vec::new(),
+        }
+    }
+
+    /// Switch to `selection`, rebuilding the decoders if it changed
+    ///
+    /// Returns whatever the removed decoders still had pending.
+    pub fn select(
+        &mut self,
+        selection: DecoderSelection,
+        state: &mut DecoderState,
+    ) -> Vec<DecodedMessage> {
+        if self.selection == Some(selection) {
+            return Vec::new();
+        }
+
+        let mut pending = Vec::new();
+        for mut decoder in self.active.drain(..) {
+            pending.extend(decoder.flush());
+            decoder.unpublish(state);
+        }
+
+        self.active = (self.factory)(&selection);
+        for decoder in &self.active {
+            log::debug!(
+                "{} decoder active ({:?} input at {} Hz)",
+                decoder.name(),
+                decoder.input_kind(),
+                decoder.sample_rate()
+            );
+        }
+        self.selection = Some(selection);
+        pending
+    }
+
+    /// Whether any active decoder consumes `kind`, so it is worth computing
+    pub fn wants(&self, kind: InputKind) -> bool {
+        self.active.iter().any(|d| d.input_kind() == kind)
+    }
+
+    /// Feed one buffer to the decoders that consume its kind
+    pub fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let kind = input.kind();
+        self.active
+            .iter_mut()
+            .filter(|d| d.input_kind() == kind)
+            .flat_map(|d| d.process(input))
+            .collect()
+    }
+
+    /// Reset every active decoder, dropping partial state
+    pub fn reset(&mut self) {
+        for decoder in self.active.iter_mut() {
+            decoder.reset();
+        }
+    }
+
+    /// Publish the live status of every active decoder
+    pub fn publish(&self, state: &mut DecoderState) {
+        for decoder in &self.active {
+            decoder.publish(state);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+}
+
+/// The built-in decoders for a selection
+pub fn default_decoders(
+    selection: &DecoderSelection,
+    outputs: &DecoderOutputs,
+) -> Vec<Box<dyn Decoder>> {
+    let rate = selection.sample_rate;
+    let mut decoders: Vec<Box<dyn Decoder>> = Vec::new();
+
+    match selection.mode {
+        DemodMode::FmNarrow => {
+            if selection.dtmf_enabled {
+                decoders.push(Box::new(DtmfDecoder::new(rate)));
+            }
+            // SAME bursts precede the alert audio on NOAA weather channels
+            if is_weather_channel(selection.frequency) {
+                decoders.push(Box::new(SameDecoder::new(rate)));
+            }
+        }
+        DemodMode::Cw => decoders.push(Box::new(CwDecoder::new(rate))),
+        DemodMode::Ais => match AisDecoder::new(rate, selection.frequency) {
+            Some(decoder) => decoders.push(Box::new(decoder.with_nmea(outputs.nmea_tx.clone()))),
+            None => log::warn!(
+                "No AIS channel within the IQ bandwidth at {:.3} MHz",
+                selection.frequency as f64 / 1e6
+            ),
+        },
+        DemodMode::Aprs => decoders.push(Box::new(AprsDecoder::new(rate))),
+        DemodMode::Ism => decoders.push(Box::new(IsmDecoder::new(rate))),
+        DemodMode::Acars => decoders.push(Box::new(AcarsDecoder::new(rate))),
+        DemodMode::Adsb => decoders.push(Box::new(AdsbDecoder::new(rate))),
+        DemodMode::Apt => decoders.push(Box::new(AptDecoder::new(rate, outputs.image_dir.clone()))),
+        DemodMode::Rs41 => decoders.push(Box::new(
+            Rs41Decoder::new(rate, selection.frequency).with_json(outputs.sonde_tx.clone()),
+        )),
+        _ => {}
+    }
+
+    decoders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_complex::Complex;
+    use std::sync::{Arc, Mutex};
+
+    /// Buffers seen by the mocks: decoder name, input kind and length
+    type Seen = Arc<Mutex<Vec<(&'static str, InputKind, usize)>>>;
+
+    /// Records the kind and length of every buffer it is given
+    struct MockDecoder {
+        name: &'static str,
+        kind: InputKind,
+        seen: Seen,
+    }
+
+    impl Decoder for MockDecoder {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn input_kind(&self) -> InputKind {
+            self.kind
+        }
+
+        fn sample_rate(&self) -> u32 {
+            48_000
+        }
+
+        fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+            let len = match input {
+                DecoderInput::Iq(s) => s.len(),
+                DecoderInput::Audio(s) | DecoderInput::Magnitude(s) => s.len(),
+            };
+            self.seen.lock().unwrap().push((self.name, input.kind(), len));
+            vec![DecodedMessage::new(DemodMode::Raw, self.name.to_string())]
+        }
+
+        fn reset(&mut self) {}
+
+        fn flush(&mut self) -> Vec<DecodedMessage> {
+            vec![DecodedMessage::new(DemodMode::Raw, format!("{} flushed", self.name))]
+        }
+
+        fn publish(&self, state: &mut DecoderState) {
+            state.cw_wpm = Some(20.0);
+        }
+
+        fn unpublish(&self, state: &mut DecoderState) {
+            state.cw_wpm = None;
+        }
+    }
+
+    fn selection(mode: DemodMode, frequency: u32) -> DecoderSelection {
+        DecoderSelection {
+            mode,
+            frequency,
+            sample_rate: 250_000,
+            dtmf_enabled: false,
+        }
+    }
+
+    /// A registry of mocks for every kind in NFM and nothing otherwise,
+    /// counting how often the factory ran
+    fn mock_registry() -> (DecoderRegistry, Seen, Arc<Mutex<usize>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let builds = Arc::new(Mutex::new(0));
+        let (seen_f, builds_f) = (seen.clone(), builds.clone());
+        let registry = DecoderRegistry::with_factory(move |sel| {
+            *builds_f.lock().unwrap() += 1;
+            if sel.mode != DemodMode::FmNarrow {
+                return Vec::new();
+            }
+            [("iq", InputKind::Iq), ("audio", InputKind::Audio), ("mag", InputKind::Magnitude)]
+                .into_iter()
+                .map(|(name, kind)| {
+                    Box::new(MockDecoder { name, kind, seen: seen_f.clone() }) as Box<dyn Decoder>
+                })
+                .collect()
+        });
+        (registry, seen, builds)
+    }
+
+    #[test]
+    fn test_routes_input_by_kind() {
+        let (mut registry, seen, _) = mock_registry();
+        let mut state = DecoderState::default();
+        registry.select(selection(DemodMode::FmNarrow, 0), &mut state);
+
+        let iq = vec![Complex::new(0.0, 0.0); 3];
+        let audio = vec![0.0; 5];
+        let messages = registry.process(DecoderInput::Iq(&iq));
+        registry.process(DecoderInput::Audio(&audio));
+        registry.process(DecoderInput::Magnitude(&audio[..2]));
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "iq");
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                ("iq", InputKind::Iq, 3),
+                ("audio", InputKind::Audio, 5),
+                ("mag", InputKind::Magnitude, 2),
+            ]
+        );
+        assert!(registry.wants(InputKind::Magnitude));
+    }
+
+    #[test]
+    fn test_rebuilds_only_on_selection_change() {
+        let (mut registry, seen, builds) = mock_registry();
+        let mut state = DecoderState::default();
+
+        assert!(registry.select(selection(DemodMode::FmNarrow, 0), &mut state).is_empty());
+        registry.publish(&mut state);
+        assert_eq!(state.cw_wpm, Some(20.0));
+        assert!(registry.select(selection(DemodMode::FmNarrow, 0), &mut state).is_empty());
+        assert_eq!(*builds.lock().unwrap(), 1);
+
+        // Leaving the mode flushes and unpublishes the old decoders
+        let pending = registry.select(selection(DemodMode::Am, 0), &mut state);
+        assert_eq!(*builds.lock().unwrap(), 2);
+        assert_eq!(pending.len(), 3);
+        assert_eq!(pending[1].content, "audio flushed");
+        assert_eq!(state.cw_wpm, None);
+
+        // Nothing is active, so nothing is routed or wanted
+        assert!(registry.is_empty());
+        assert!(!registry.wants(InputKind::Audio));
+        assert!(registry.process(DecoderInput::Audio(&[0.0; 4])).is_empty());
+        assert!(seen.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_default_decoders_per_mode() {
+        let names = |sel: DecoderSelection| -> Vec<&'static str> {
+            default_decoders(&sel, &DecoderOutputs::default()).iter().map(|d| d.name()).collect()
+        };
+
+        assert!(names(selection(DemodMode::FmNarrow, 146_520_000)).is_empty());
+        assert_eq!(names(selection(DemodMode::FmNarrow, 162_550_000)), vec!["SAME"]);
+        let dtmf = DecoderSelection { dtmf_enabled: true, ..selection(DemodMode::FmNarrow, 162_550_000) };
+        assert_eq!(names(dtmf), vec!["DTMF", "SAME"]);
+        assert_eq!(names(selection(DemodMode::Cw, 7_030_000)), vec!["CW"]);
+        assert_eq!(names(selection(DemodMode::Ais, 162_000_000)), vec!["AIS"]);
+        assert!(names(selection(DemodMode::Ais, 100_000_000)).is_empty());
+        assert_eq!(names(selection(DemodMode::Aprs, 144_390_000)), vec!["APRS"]);
+        assert_eq!(names(selection(DemodMode::Ism, 433_920_000)), vec!["ISM"]);
+        assert_eq!(names(selection(DemodMode::Acars, 131_550_000)), vec!["ACARS"]);
+        assert_eq!(names(selection(DemodMode::Apt, 137_100_000)), vec!["APT"]);
+        assert_eq!(names(selection(DemodMode::Rs41, 403_000_000)), vec!["RS41"]);
+        assert!(names(selection(DemodMode::FmWide, 98_100_000)).is_empty());
+
+        let kinds: Vec<InputKind> = [DemodMode::Ais, DemodMode::Ism, DemodMode::Cw]
+            .iter()
+            .flat_map(|&m| default_decoders(&selection(m, 162_000_000), &DecoderOutputs::default()))
+            .map(|d| d.input_kind())
+            .collect();
+        assert_eq!(kinds, vec![InputKind::Iq, InputKind::Magnitude, InputKind::Audio]);
+    }
+}
@@ -0,0 +1,859 @@
+//! Vaisala RS41 radiosonde decoder
+//!
+//! RS41 sondes send one frame a second in the 400-406 MHz band as 4800 baud
+//! GFSK, which the FM discriminator turns into a two-level signal. Bytes go
+//! LSB first and are whitened with a repeating 64-byte mask; a frame starts
+//! with an 8-byte header, then 48 bytes of Reed-Solomon parity for two
+//! interleaved RS(255, 231) codewords covering the rest of the frame. The
+//! frame type byte after the parity says whether the frame is the standard
+//! 320 bytes or the extended 518.
+//!
+//! The body is a run of blocks, each an id byte, a length byte, the data and
+//! a CRC-16 of the data: status (frame number, serial, battery), GPS time,
+//! GPS position and velocity in ECEF, and others not decoded here. Blocks
+//! are checked on their own, so a frame the RS code can't fix may still
+//! give up some fields.
+//!
+//! Bits are recovered like the ACARS decoder does: a one-bit moving average
+//! as the matched filter, with the bit clock pulled onto its zero crossings.
+//! The header is matched in both polarities, since which way round the
+//! discriminator output comes depends on the receiver.
+
+use super::fsk::{BitClock, Boxcar};
+use super::reed_solomon::{self, K, N, PARITY};
+use super::{Decoder, DecoderInput, InputKind};
+use crate::dsp::filters::Biquad;
+use crate::types::{DecodedMessage, DemodMode};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use crossbeam::channel::Sender;
+
+/// Rate the audio is averaged down to before demodulation
+const DETECT_RATE: u32 = 48_000;
+/// Bit rate in bits per second
+const BAUD: f32 = 4800.0;
+/// Clock correction applied at each zero crossing
+const CLOCK_GAIN: f32 = 0.3;
+/// Header bits that may be wrong in a sync
+const SYNC_ERRORS: u32 = 3;
+
+/// Frame lengths in bytes
+pub const FRAME_LEN: usize = 320;
+pub const EXTENDED_FRAME_LEN: usize = 518;
+/// The header as sent, before de-whitening
+const HEADER: [u8; 8] = [0x10, 0xB6, 0xCA, 0x11, 0x22, 0x96, 0x12, 0xF8];
+/// Where the RS parity and the data it covers start
+const PARITY_POS: usize = 8;
+const MSG_POS: usize = PARITY_POS + 2 * PARITY;
+/// Frame type byte of an extended frame
+const EXTENDED_FRAME: u8 = 0xF0;
+/// Where the first block starts, after the frame type
+const BLOCKS_POS: usize = MSG_POS + 1;
+
+/// Block ids
+const BLOCK_STATUS: u8 = 0x79;
+const BLOCK_GPS_TIME: u8 = 0x7C;
+const BLOCK_GPS_POSITION: u8 = 0x7B;
+
+/// Whitening mask, applied to every byte from the header on
+pub const MASK: [u8; 64] = [
+    0x96, 0x83, 0x3E, 0x51, 0xB1, 0x49, 0x08, 0x98, 0x32, 0x05, 0x59, 0x0E, 0xF9, 0x44, 0xC6, 0x26,
+    0x21, 0x60, 0xC2, 0xEA, 0x79, 0x5D, 0x6D, 0xA1, 0x54, 0x69, 0x47, 0x0C, 0xDC, 0xE8, 0x5C, 0xF1,
+    0xF7, 0x76, 0x82, 0x7F, 0x07, 0x99, 0xA2, 0x2C, 0x93, 0x7C, 0x30, 0x63, 0xF5, 0x10, 0x2E, 0x61,
+    0xD0, 0xBC, 0xB4, 0xB6, 0x06, 0xAA, 0xF4, 0x23, 0x78, 0x6E, 0x3B, 0xAE, 0xBF, 0x7B, 0x4C, 0xC1,
+];
+
+/// Seconds GPS time is ahead of UTC
+const GPS_LEAP_SECONDS: i64 = 18;
+
+/// WGS84 ellipsoid
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+/// CRC-16/CCITT-FALSE, as used for the block checks
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Whiten or de-whiten bytes that start `offset` bytes into a frame
+pub fn whiten(bytes: &mut [u8], offset: usize) {
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte ^= MASK[(offset + i) % MASK.len()];
+    }
+}
+
+/// The two interleaved codewords of a frame: parity, then every other byte
+/// of the data from [`MSG_POS`], zero past the end of the frame
+fn codewords(frame: &[u8]) -> [[u8; N]; 2] {
+    let mut codewords = [[0u8; N]; 2];
+    for (c, codeword) in codewords.iter_mut().enumerate() {
+        let parity = PARITY_POS + c * PARITY;
+        codeword[..PARITY].copy_from_slice(&frame[parity..parity + PARITY]);
+        for i in 0..K {
+            codeword[PARITY + i] = frame.get(MSG_POS + 2 * i + c).copied().unwrap_or(0);
+        }
+    }
+    codewords
+}
+
+/// Correct a de-whitened frame in place; returns the bytes corrected, or
+/// None if either codeword has too many errors (the frame is left alone)
+pub fn correct(frame: &mut [u8]) -> Option<usize> {
+    let mut codewords = codewords(frame);
+    let mut corrected = 0;
+    for (c, codeword) in codewords.iter_mut().enumerate() {
+        corrected += reed_solomon::decode(codeword)?;
+        // Padding past the end of the frame is known to be zero
+        let padding = (0..K).filter(|i| MSG_POS + 2 * i + c >= frame.len());
+        if padding.map(|i| codeword[PARITY + i]).any(|byte| byte != 0) {
+            return None;
+        }
+    }
+    for (c, codeword) in codewords.iter().enumerate() {
+        let parity = PARITY_POS + c * PARITY;
+        frame[parity..parity + PARITY].copy_from_slice(&codeword[..PARITY]);
+        for i in 0..K {
+            if let Some(byte) = frame.get_mut(MSG_POS + 2 * i + c) {
+                *byte = codeword[PARITY + i];
+            }
+        }
+    }
+    Some(corrected)
+}
+
+/// Latitude and longitude in degrees, altitude above the WGS84 ellipsoid
+/// in meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: f64,
+}
+
+impl Position {
+    /// The position of an Earth-centered, Earth-fixed point in meters
+    pub fn from_ecef(x: f64, y: f64, z: f64) -> Self {
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let b = WGS84_A * (1.0 - WGS84_F);
+        let ep2 = (WGS84_A * WGS84_A - b * b) / (b * b);
+        let p = x.hypot(y);
+
+        // Bowring's method; one step is good to well under a meter
+        let theta = (z * WGS84_A).atan2(p * b);
+        let lat = (z + ep2 * b * theta.sin().powi(3)).atan2(p - e2 * WGS84_A * theta.cos().powi(3));
+        let n = WGS84_A / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+
+        Self {
+            lat: lat.to_degrees(),
+            lon: y.atan2(x).to_degrees(),
+            alt: p / lat.cos() - n,
+        }
+    }
+}
+
+/// Velocity as ground speed and heading, and climb rate, in m/s and degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Velocity {
+    pub horizontal: f64,
+    pub heading: f64,
+    pub vertical: f64,
+}
+
+impl Velocity {
+    /// Velocity from ECEF components at `position`
+    pub fn from_ecef(vx: f64, vy: f64, vz: f64, position: &Position) -> Self {
+        let (lat, lon) = (position.lat.to_radians(), position.lon.to_radians());
+        let east = -lon.sin() * vx + lon.cos() * vy;
+        let north = -lat.sin() * lon.cos() * vx - lat.sin() * lon.sin() * vy + lat.cos() * vz;
+        let up = lat.cos() * lon.cos() * vx + lat.cos() * lon.sin() * vy + lat.sin() * vz;
+        Self {
+            horizontal: east.hypot(north),
+            heading: east.atan2(north).to_degrees().rem_euclid(360.0),
+            vertical: up,
+        }
+    }
+}
+
+/// What a frame says about its sonde
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rs41Frame {
+    pub frame: u16,
+    pub serial: String,
+    /// Battery voltage
+    pub battery: f32,
+    /// GPS time, converted to UTC
+    pub time: Option<DateTime<Utc>>,
+    pub position: Option<Position>,
+    pub velocity: Option<Velocity>,
+    pub sats: Option<u8>,
+    /// Bytes the RS code corrected, None if it couldn't
+    pub corrected: Option<usize>,
+}
+
+fn u16_le(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn i16_le(data: &[u8], at: usize) -> i16 {
+    u16_le(data, at) as i16
+}
+
+fn i32_le(data: &[u8], at: usize) -> i32 {
+    i32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+impl Rs41Frame {
+    /// Correct and parse a de-whitened frame; None without a good status block
+    pub fn decode(frame: &mut [u8]) -> Option<Self> {
+        let corrected = correct(frame);
+        let mut parsed = Self::parse(frame)?;
+        parsed.corrected = corrected;
+        Some(parsed)
+    }
+
+    /// Parse the blocks of a frame, skipping any that fail their CRC
+    pub fn parse(frame: &[u8]) -> Option<Self> {
+        let mut status = None;
+        let mut time = None;
+        let mut gps = None;
+
+        let mut pos = BLOCKS_POS;
+        while pos + 2 <= frame.len() {
+            let (id, len) = (frame[pos], frame[pos + 1] as usize);
+            let end = pos + 2 + len;
+            if end + 2 > frame.len() {
+                break;
+            }
+            let data = &frame[pos + 2..end];
+            if crc16_ccitt(data) == u16_le(frame, end) {
+                match id {
+                    BLOCK_STATUS if len >= 11 => status = Some(data),
+                    BLOCK_GPS_TIME if len >= 6 => time = Some(data),
+                    BLOCK_GPS_POSITION if len >= 19 => gps = Some(data),
+                    _ => {}
+                }
+            }
+            pos = end + 2;
+        }
+
+        let status = status?;
+        let serial: String = status[2..10]
+            .iter()
+            .map(|&b| b as char)
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect();
+        let position = gps.map(|data| {
+            let cm = |at| i32_le(data, at) as f64 / 100.0;
+            Position::from_ecef(cm(0), cm(4), cm(8))
+        });
+        let velocity = gps.zip(position).map(|(data, position)| {
+            let cm_s = |at| i16_le(data, at) as f64 / 100.0;
+            Velocity::from_ecef(cm_s(12), cm_s(14), cm_s(16), &position)
+        });
+
+        Some(Self {
+            frame: u16_le(status, 0),
+            serial,
+            battery: status[10] as f32 / 10.0,
+            time: time.and_then(|data| {
+                gps_time(
+                    u16_le(data, 0),
+                    u32::from_le_bytes([data[2], data[3], data[4], data[5]]),
+                )
+            }),
+            position,
+            velocity,
+            sats: gps.map(|data| data[18]),
+            corrected: None,
+        })
+    }
+
+    /// One-line summary for the message list
+    pub fn summary(&self) -> String {
+        let mut summary = format!("RS41 {} frame {}", self.serial, self.frame);
+        if let Some(position) = &self.position {
+            summary.push_str(&format!(
+                " {:.5}, {:.5} {:.0} m",
+                position.lat, position.lon, position.alt
+            ));
+        }
+        if let Some(velocity) = &self.velocity {
+            summary.push_str(&format!(" {:+.1} m/s", velocity.vertical));
+        }
+        summary.push_str(&format!(" {:.1} V", self.battery));
+        if let Some(sats) = self.sats {
+            summary.push_str(&format!(" {} sats", sats));
+        }
+        summary
+    }
+
+    /// Structured fields for logs
+    pub fn fields(&self) -> serde_json::Value {
+        let mut fields = serde_json::json!({
+            "serial": self.serial,
+            "frame": self.frame,
+            "batt": self.battery,
+            "rs_errors": self.corrected,
+        });
+        if let Some(time) = self.time {
+            fields["datetime"] = time
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+                .into();
+        }
+        if let Some(position) = &self.position {
+            fields["lat"] = position.lat.into();
+            fields["lon"] = position.lon.into();
+            fields["alt"] = position.alt.into();
+        }
+        if let Some(velocity) = &self.velocity {
+            fields["vel_h"] = velocity.horizontal.into();
+            fields["heading"] = velocity.heading.into();
+            fields["vel_v"] = velocity.vertical.into();
+        }
+        if let Some(sats) = self.sats {
+            fields["sats"] = sats.into();
+        }
+        fields
+    }
+
+    /// The frame as a SondeHub telemetry line, None without a time and
+    /// position fix
+    pub fn sondehub_json(&self, frequency: u32, received: DateTime<Utc>) -> Option<String> {
+        let (time, position) = (self.time?, self.position?);
+        let millis =
+            |time: DateTime<Utc>| time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let mut line = serde_json::json!({
+            "software_name": env!("CARGO_PKG_NAME"),
+            "software_version": env!("CARGO_PKG_VERSION"),
+            "time_received": millis(received),
+            "manufacturer": "Vaisala",
+            "type": "RS41",
+            "serial": self.serial,
+            "frame": self.frame,
+            "datetime": millis(time),
+            "lat": position.lat,
+            "lon": position.lon,
+            "alt": position.alt,
+            "batt": self.battery,
+            "frequency": frequency as f64 / 1e6,
+        });
+        if let Some(velocity) = &self.velocity {
+            line["vel_h"] = velocity.horizontal.into();
+            line["vel_v"] = velocity.vertical.into();
+            line["heading"] = velocity.heading.into();
+        }
+        if let Some(sats) = self.sats {
+            line["sats"] = sats.into();
+        }
+        Some(line.to_string())
+    }
+}
+
+/// UTC from a GPS week and time of week in milliseconds
+pub fn gps_time(week: u16, tow_ms: u32) -> Option<DateTime<Utc>> {
+    let epoch = Utc.with_ymd_and_hms(1980, 1, 6, 0, 0, 0).single()?;
+    Some(
+        epoch + Duration::weeks(week as i64) + Duration::milliseconds(tow_ms as i64)
+            - Duration::seconds(GPS_LEAP_SECONDS),
+    )
+}
+
+/// The header as bits in the order they arrive
+fn header_bits() -> u64 {
+    HEADER
+        .iter()
+        .flat_map(|&byte| (0..8).map(move |i| (byte >> i) & 1))
+        .fold(0, |bits, bit| (bits << 1) | bit as u64)
+}
+
+/// Finds the header in the bit stream and collects the frame after it
+#[derive(Debug, Clone)]
+pub struct Deframer {
+    header: u64,
+    /// Last 64 bits while hunting for a header
+    shift: u64,
+    /// Whether the header was found with the bits inverted
+    inverted: bool,
+    /// De-whitened frame so far, None while hunting
+    frame: Option<Vec<u8>>,
+    frame_len: usize,
+    byte: u8,
+    bit_count: u32,
+}
+
+impl Default for Deframer {
+    fn default() -> Self {
+        Self {
+            header: header_bits(),
+            shift: 0,
+            inverted: false,
+            frame: None,
+            frame_len: FRAME_LEN,
+            byte: 0,
+            bit_count: 0,
+        }
+    }
+}
+
+impl Deframer {
+    /// Push one bit; returns a complete de-whitened frame
+    pub fn push(&mut self, bit: bool) -> Option<Vec<u8>> {
+        let Some(frame) = &mut self.frame else {
+            self.shift = (self.shift << 1) | bit as u64;
+            let errors = (self.shift ^ self.header).count_ones();
+            if errors <= SYNC_ERRORS || errors >= 64 - SYNC_ERRORS {
+                self.inverted = errors >= 64 - SYNC_ERRORS;
+                let mut header = HEADER.to_vec();
+                whiten(&mut header, 0);
+                self.frame = Some(header);
+                self.frame_len = FRAME_LEN;
+                self.byte = 0;
+                self.bit_count = 0;
+            }
+            return None;
+        };
+
+        self.byte |= ((bit ^ self.inverted) as u8) << self.bit_count;
+        self.bit_count += 1;
+        if self.bit_count < 8 {
+            return None;
+        }
+        let pos = frame.len();
+        frame.push(self.byte ^ MASK[pos % MASK.len()]);
+        self.byte = 0;
+        self.bit_count = 0;
+        if pos == MSG_POS && frame[pos] == EXTENDED_FRAME {
+            self.frame_len = EXTENDED_FRAME_LEN;
+        }
+        if frame.len() < self.frame_len {
+            return None;
+        }
+        self.shift = 0;
+        self.frame.take()
+    }
+}
+
+/// Streaming RS41 decoder on FM-demodulated audio
+pub struct Rs41Decoder {
+    /// Input sample rate the decoder was built for
+    sample_rate: u32,
+    /// Frequency tuned, reported with the telemetry
+    frequency: u32,
+    /// Averages the audio down to the detection rate
+    boxcar: Boxcar,
+    /// Removes the discriminator's offset from mistuning
+    highpass: Biquad,
+    /// One-bit moving average
+    window: Vec<f32>,
+    window_pos: usize,
+    sum: f32,
+    clock: BitClock,
+    deframer: Deframer,
+    /// SondeHub JSON line output (e.g. the TCP server), if any
+    json_tx: Option<Sender<String>>,
+}
+
+impl Rs41Decoder {
+    /// Create a decoder for audio at `sample_rate` from `frequency`
+    pub fn new(sample_rate: u32, frequency: u32) -> Self {
+        let boxcar = Boxcar::new(sample_rate, DETECT_RATE);
+        let detect_rate = boxcar.rate(sample_rate);
+        let window_len = (detect_rate / BAUD).round() as usize;
+
+        Self {
+            sample_rate,
+            frequency,
+            boxcar,
+            highpass: Biquad::highpass(detect_rate as f64, 20.0, std::f64::consts::FRAC_1_SQRT_2),
+            window: vec![0.0; window_len],
+            window_pos: 0,
+            sum: 0.0,
+            clock: BitClock::new(detect_rate, BAUD, CLOCK_GAIN),
+            deframer: Deframer::default(),
+            json_tx: None,
+        }
+    }
+
+    /// Also send every frame with a fix as a SondeHub JSON line to `tx`
+    pub fn with_json(mut self, tx: Option<Sender<String>>) -> Self {
+        self.json_tx = tx;
+        self
+    }
+
+    /// Feed audio; returns the frames decoded
+    pub fn process(&mut self, audio: &[f32]) -> Vec<Rs41Frame> {
+        let mut frames = Vec::new();
+
+        for &sample in audio {
+            let Some(x) = self.boxcar.push(sample) else {
+                continue;
+            };
+            let x = self.highpass.process(x);
+            let Some(bit) = self.demodulate(x) else {
+                continue;
+            };
+            let frame = self.deframer.push(bit);
+            if let Some(frame) = frame.and_then(|mut frame| Rs41Frame::decode(&mut frame)) {
+                frames.push(frame);
+            }
+        }
+
+        frames
+    }
+
+    /// Run one detection sample through the matched filter and bit clock
+    fn demodulate(&mut self, x: f32) -> Option<bool> {
+        self.sum += x - self.window[self.window_pos];
+        self.window[self.window_pos] = x;
+        self.window_pos = (self.window_pos + 1) % self.window.len();
+        if self.window_pos == 0 {
+            // Keep rounding from building up in the running sum
+            self.sum = self.window.iter().sum();
+        }
+
+        self.clock.push(self.sum > 0.0)
+    }
+}
+
+impl Decoder for Rs41Decoder {
+    fn name(&self) -> &'static str {
+        "RS41"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Audio
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Audio(audio) = input else {
+            return Vec::new();
+        };
+
+        let mut messages = Vec::new();
+        for frame in Rs41Decoder::process(self, audio) {
+            if let Some(tx) = &self.json_tx {
+                if let Some(line) = frame.sondehub_json(self.frequency, Utc::now()) {
+                    let _ = tx.try_send(line);
+                }
+            }
+            messages.push(
+                DecodedMessage::new(DemodMode::Rs41, frame.summary()).with_fields(frame.fields()),
+            );
+        }
+        messages
+    }
+
+    fn reset(&mut self) {
+        let json_tx = self.json_tx.take();
+        *self = Rs41Decoder::new(self.sample_rate, self.frequency).with_json(json_tx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// ECEF in meters of a WGS84 position
+    fn ecef(position: &Position) -> (f64, f64, f64) {
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let (lat, lon) = (position.lat.to_radians(), position.lon.to_radians());
+        let n = WGS84_A / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+        (
+            (n + position.alt) * lat.cos() * lon.cos(),
+            (n + position.alt) * lat.cos() * lon.sin(),
+            (n * (1.0 - e2) + position.alt) * lat.sin(),
+        )
+    }
+
+    /// A block with its CRC
+    fn block(id: u8, data: &[u8]) -> Vec<u8> {
+        let mut block = vec![id, data.len() as u8];
+        block.extend_from_slice(data);
+        block.extend(crc16_ccitt(data).to_le_bytes());
+        block
+    }
+
+    /// A de-whitened standard frame laid out as the sondes send it, RS
+    /// parity included. The launch site is Lindenberg, 52.2099 N 14.1201 E.
+    fn test_frame(frame_number: u16) -> Vec<u8> {
+        let mut frame = HEADER.to_vec();
+        whiten(&mut frame, 0);
+        frame.resize(MSG_POS, 0);
+        frame.push(0x0F);
+
+        let mut status = vec![0u8; 0x28];
+        status[..2].copy_from_slice(&frame_number.to_le_bytes());
+        status[2..10].copy_from_slice(b"S3240851");
+        status[10] = 29;
+        frame.extend(block(BLOCK_STATUS, &status));
+        frame.extend(block(0x7A, &[0x11; 0x2A]));
+
+        // Week 2316, Saturday 12:00:18 GPS, 12:00:00 UTC
+        let mut time = vec![0u8; 0x1E];
+        time[..2].copy_from_slice(&2316u16.to_le_bytes());
+        time[2..6].copy_from_slice(&((6 * 86_400 + 12 * 3600 + 18) * 1000u32).to_le_bytes());
+        frame.extend(block(BLOCK_GPS_TIME, &time));
+        frame.extend(block(0x7D, &[0x22; 0x59]));
+
+        let position = Position {
+            lat: 52.2099,
+            lon: 14.1201,
+            alt: 12_345.0,
+        };
+        let (x, y, z) = ecef(&position);
+        let mut gps = vec![0u8; 0x15];
+        for (i, m) in [x, y, z].into_iter().enumerate() {
+            gps[4 * i..4 * i + 4].copy_from_slice(&((m * 100.0).round() as i32).to_le_bytes());
+        }
+        // Straight up at 5 m/s: the local vertical in ECEF
+        let (lat, lon) = (position.lat.to_radians(), position.lon.to_radians());
+        let up = [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()];
+        for (i, u) in up.into_iter().enumerate() {
+            gps[12 + 2 * i..14 + 2 * i]
+                .copy_from_slice(&((u * 500.0).round() as i16).to_le_bytes());
+        }
+        gps[18] = 9;
+        frame.extend(block(BLOCK_GPS_POSITION, &gps));
+        frame.extend(block(0x76, &[0; 0x11]));
+        assert_eq!(frame.len(), FRAME_LEN);
+
+        let mut codewords = codewords(&frame);
+        for (c, codeword) in codewords.iter_mut().enumerate() {
+            reed_solomon::encode(codeword);
+            frame[PARITY_POS + c * PARITY..PARITY_POS + (c + 1) * PARITY]
+                .copy_from_slice(&codeword[..PARITY]);
+        }
+        frame
+    }
+
+    /// Discriminator output for frames sent one a second: a preamble, the
+    /// whitened frame LSB first, then idle carrier, as ±`level` GFSK with
+    /// the transitions smoothed
+    fn modulate(frames: &[Vec<u8>], rate: u32, level: f32, noise: f32) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(41);
+        let per_bit = rate as f32 / BAUD;
+        let mut audio = Vec::new();
+        for frame in frames {
+            let mut sent = frame.clone();
+            whiten(&mut sent, 0);
+            let bits: Vec<bool> = [0xAAu8; 40]
+                .iter()
+                .chain(&sent)
+                .flat_map(|&byte| (0..8).map(move |i| (byte >> i) & 1 == 1))
+                .collect();
+            let start = audio.len();
+            let mut smoothed = 0.0;
+            let samples = (rate as usize).max((bits.len() as f32 * per_bit) as usize);
+            for n in 0..samples {
+                let target = match bits.get((n as f32 / per_bit) as usize) {
+                    Some(true) => level,
+                    Some(false) => -level,
+                    None => 0.0,
+                };
+                smoothed += (target - smoothed) * 0.5;
+                audio.push(smoothed + rng.gen_range(-noise..=noise));
+            }
+            assert_eq!(audio.len() - start, samples);
+        }
+        audio
+    }
+
+    #[test]
+    fn test_crc_check_value() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn test_header_dewhitens() {
+        let mut header = HEADER.to_vec();
+        whiten(&mut header, 0);
+        assert_eq!(header, [0x86, 0x35, 0xF4, 0x40, 0x93, 0xDF, 0x1A, 0x60]);
+    }
+
+    #[test]
+    fn test_parse_frame() {
+        let frame = Rs41Frame::decode(&mut test_frame(4321)).unwrap();
+        assert_eq!(frame.serial, "S3240851");
+        assert_eq!(frame.frame, 4321);
+        assert_eq!(frame.battery, 2.9);
+        assert_eq!(frame.sats, Some(9));
+        assert_eq!(frame.corrected, Some(0));
+        assert_eq!(
+            frame.time,
+            Some(Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap())
+        );
+
+        let position = frame.position.unwrap();
+        assert!((position.lat - 52.2099).abs() < 1e-6, "{}", position.lat);
+        assert!((position.lon - 14.1201).abs() < 1e-6, "{}", position.lon);
+        assert!((position.alt - 12_345.0).abs() < 0.1, "{}", position.alt);
+        let velocity = frame.velocity.unwrap();
+        assert!((velocity.vertical - 5.0).abs() < 0.05 && velocity.horizontal < 0.05);
+
+        assert_eq!(
+            frame.summary(),
+            "RS41 S3240851 frame 4321 52.20990, 14.12010 12345 m +5.0 m/s 2.9 V 9 sats"
+        );
+        let line = frame.sondehub_json(403_010_000, Utc::now()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["serial"], "S3240851");
+        assert_eq!(json["datetime"], "2024-06-01T12:00:00.000Z");
+        assert_eq!(json["frequency"], 403.01);
+        assert_eq!(json["manufacturer"], "Vaisala");
+    }
+
+    #[test]
+    fn test_rs_corrects_frame() {
+        let clean = test_frame(7);
+        let mut frame = clean.clone();
+        let mut rng = StdRng::seed_from_u64(5);
+        // Twelve errors in each codeword: even and odd bytes of the data
+        for i in 0..12 {
+            frame[MSG_POS + 20 * i] ^= rng.gen_range(1..=255u8);
+            frame[MSG_POS + 20 * i + 11] ^= rng.gen_range(1..=255u8);
+        }
+        assert_eq!(correct(&mut frame), Some(24));
+        assert_eq!(frame, clean);
+
+        // Beyond repair the blocks are still checked one by one
+        let mut frame = clean.clone();
+        for i in 0..40 {
+            frame[PARITY_POS + i] ^= 0xFF;
+        }
+        frame[0x120] ^= 0xFF;
+        let parsed = Rs41Frame::decode(&mut frame).unwrap();
+        assert_eq!(parsed.corrected, None);
+        assert_eq!(parsed.serial, "S3240851");
+        assert_eq!(parsed.position, None);
+        assert!(parsed.time.is_some());
+    }
+
+    #[test]
+    fn test_decodes_frames_across_buffers() {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let rate = 1_024_000;
+        let mut decoder = Rs41Decoder::new(rate, 403_010_000).with_json(Some(tx));
+        let audio = modulate(&[test_frame(100), test_frame(101)], rate, 0.005, 0.004);
+
+        let messages: Vec<DecodedMessage> = audio
+            .chunks(40_961)
+            .flat_map(|chunk| Decoder::process(&mut decoder, DecoderInput::Audio(chunk)))
+            .collect();
+        assert_eq!(messages.len(), 2, "{:?}", messages);
+        assert!(messages[0].content.starts_with("RS41 S3240851 frame 100 "));
+        assert_eq!(messages[1].fields["frame"], 101);
+        assert_eq!(rx.try_iter().count(), 2);
+    }
+
+    // De-whitened standard frames with RS parity from an encoder written
+    // apart from this crate, after the RS41 documentation: parity is the
+    // remainder of the data times x^24 over the generator with roots
+    // α^0..α^23 in GF(2^8) mod 0x11D, stored lowest power first
+    /// Frame 1802 of T2130711 over Stuttgart
+    const FRAME_T2130711_1802: &str = concat!(
+        "8635F44093DF1A60B2E37E15937F8D68BA39C64AF7BE393945DC8B701C60916D1CD7D95ACC1278E3",
+        "97CE5ED8C969FE09CD02ACE0F54D31900F79280A0754323133303731311CB238D7C03E293971EB20",
+        "0347328FEFE711BB82F36D810B424ACA716D5A74B67A2AAAD9050B160B698C62C60E6219EF3DABB5",
+        "8BB0C9A0F8D2C886D36BA5DB09521FCFBB28FD49E36F0F2A8340677C1E0C097055DD11FE26ED85E6",
+        "77FCDE0EF2B76C153EB46C30C214CA9F8545B8E97A7D5912E9E26278F172566278AA94ADC75D8E0B",
+        "0E1B53C3331D39ECF1C4927CFAD3FDD207BF4994FCF8962C721792560BBDE35988D29A715AD3379F",
+        "BCF63278D05626F01D48682BC0849337C51A9C00DCEA6C8C8E41015E06401218C17B7B1599A8C318",
+        "DFCF02043F147E1C8501E8012C0108DE3643BE76110000000000000000000000000000000000ECC7",
+    );
+
+    /// The next frame of T2130711, one second on
+    const FRAME_T2130711_1803: &str = concat!(
+        "8635F44093DF1A6007D0D0925ECBCFD52957A9C2EA9B6A9C1AD81F3085FBE61782EBE478C0F53FA8",
+        "4C10D6D43129683C4A8101BFEAED56E40F79280B0754323133303731311CD86DB9B2F4E8B46386D7",
+        "D9BB5887BEA25839A0994FEEA245736694F7926DD57A2A0175BF1DE1FF59CE82F510F829D33E6639",
+        "8E6B553994C380C421DF7C33ECA1B8CF2AE130280742FDAA3098197C1E0C095859DD11D8592BDBEA",
+        "6EBC7567BD92D7D0B98875572E707531D77E6E70BE7D598FD67CE605E03F361BEC3A0120405FA799",
+        "1899FFEE31C8742C9007CA82FBC72C1DE27D764616432B4004B4FB523EA024F99D48BA5907D35673",
+        "95AC980D77C840F355F5029FD0A77C3FBC6AE7969E6BA34034EE2607518341007A7A7B157CA9C318",
+        "ECD20204CE157E1C8501E8012C01092E9AEDD276110000000000000000000000000000000000ECC7",
+    );
+
+    /// Frame 245 of V1520346 over Adelaide with eleven bytes in each codeword
+    /// damaged, in the data and in the parity
+    const FRAME_V1520346_245_DAMAGED: &str = concat!(
+        "8635F44093DF1A60CE8A2C053156BC183E3A881297733940D230E4484EF4175D10212FACE19E9354",
+        "835DA2877EA540B50D6F99BCE79A43985C7928F500563135323033C7361EE208DFC9ACC6A3F46A82",
+        "A02B1EB5316522E4FD8D3324E60AC24E0082D9BB887A2A6FA8A2A57224C24BE10C41448DEE341B43",
+        "A4521CAE2475D8CDBEBBD1DE0965179A67DC74FC33095BC604E93C7C1E0C09F08AE61E96C720E272",
+        "4FF095EDCBA2DDDD8DB05937DA45055A456B7B0C697D59445AB10AEDBC23A796E370DB920F97D5F6",
+        "DD0B93A1658DE27A88A76F6F489D49DC1D63F1330B7A245A1ADF3684BA692F27219F48210C7FAC18",
+        "9C3165AC6E838E99279DCE4DE446E3B9BBEA05320629936504808671890FEA51CD937B151EFF9FE8",
+        "9B58AA7890D257EAF3FEEE00BD040B572FC1CA76110000000000000000000000000000000000ECC7",
+    );
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len() / 2)
+            .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_reference_frames() {
+        let first = Rs41Frame::decode(&mut from_hex(FRAME_T2130711_1802)).unwrap();
+        assert_eq!(first.serial, "T2130711");
+        assert_eq!(first.frame, 1802);
+        assert_eq!(first.battery, 2.8);
+        assert_eq!(first.sats, Some(8));
+        assert_eq!(first.corrected, Some(0));
+        // Week 2316, Wednesday 11:15:18 GPS
+        assert_eq!(
+            first.time,
+            Some(Utc.with_ymd_and_hms(2024, 5, 29, 11, 15, 0).unwrap())
+        );
+        let position = first.position.unwrap();
+        assert!((position.lat - 48.8275).abs() < 1e-6, "{}", position.lat);
+        assert!((position.lon - 9.2001).abs() < 1e-6, "{}", position.lon);
+        assert!((position.alt - 3050.4).abs() < 0.1, "{}", position.alt);
+        let velocity = first.velocity.unwrap();
+        assert!((velocity.vertical - 5.3).abs() < 0.05, "{}", velocity.vertical);
+        assert!((velocity.horizontal - 4.46).abs() < 0.05, "{}", velocity.horizontal);
+
+        let next = Rs41Frame::decode(&mut from_hex(FRAME_T2130711_1803)).unwrap();
+        assert_eq!((next.serial.as_str(), next.frame), ("T2130711", 1803));
+        assert_eq!(next.corrected, Some(0));
+        assert!((next.position.unwrap().alt - 3055.7).abs() < 0.1);
+
+        let mut frame = from_hex(FRAME_V1520346_245_DAMAGED);
+        let damaged = Rs41Frame::decode(&mut frame).unwrap();
+        assert_eq!(damaged.corrected, Some(22));
+        assert_eq!((damaged.serial.as_str(), damaged.frame), ("V1520346", 245));
+        assert_eq!(damaged.sats, Some(11));
+        let position = damaged.position.unwrap();
+        assert!((position.lat + 34.9461).abs() < 1e-6, "{}", position.lat);
+        assert!((position.lon - 138.5207).abs() < 1e-6, "{}", position.lon);
+        assert!((position.alt - 812.0).abs() < 0.1, "{}", position.alt);
+        // Repaired in place: a second pass finds nothing left to fix
+        assert_eq!(correct(&mut frame), Some(0));
+    }
+
+    #[test]
+    fn test_inverted_polarity() {
+        let rate = 48_000;
+        let mut decoder = Rs41Decoder::new(rate, 403_010_000);
+        let audio = modulate(&[test_frame(9)], rate, -0.01, 0.0);
+        let frames = decoder.process(&audio);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].frame, 9);
+    }
+}
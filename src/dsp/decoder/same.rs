@@ -0,0 +1,580 @@
+//! NOAA Weather Radio SAME (EAS) header decoder
+//!
+//! SAME headers are 520.83 baud AFSK bursts (mark 2083.3 Hz, space 1562.5 Hz)
+//! sent LSB first, each preceded by sixteen 0xAB preamble bytes. Every header
+//! is transmitted three times about a second apart; the copies are voted
+//! byte by byte before the header is parsed, so a single corrupted burst
+//! neither falses nor loses an alert. The end of message is the same
+//! arrangement carrying "NNNN".
+//!
+//! Demodulation is the non-coherent front end in [`super::fsk`]: sliding
+//! one-bit correlations against the mark and space tones give a soft bit, and
+//! the bit clock is pulled onto its zero crossings.
+
+use super::fsk::{BitClock, Boxcar, ToneCorrelator};
+use super::{Decoder, DecoderInput, InputKind};
+use crate::state::DecoderState;
+use crate::types::{DecodedMessage, DemodMode};
+use std::time::{Duration, Instant};
+
+/// Rate the audio is averaged down to before demodulation
+const DETECT_RATE: u32 = 10_000;
+/// Bit rate in bits per second
+const BAUD: f32 = 520.833_3;
+/// Mark (1) and space (0) tone frequencies in Hz
+const MARK_FREQ: f32 = 2_083.333;
+const SPACE_FREQ: f32 = 1_562.5;
+/// Preamble byte, also used for byte synchronization
+const PREAMBLE: u8 = 0xAB;
+/// Longest header allowed by the format (31 locations)
+const MAX_HEADER_LEN: usize = 268;
+/// Bursts further apart than this start a new group of repeats
+const GROUP_GAP_SECS: f32 = 3.0;
+/// Number of repeats in a complete group
+const REPEATS: usize = 3;
+/// Clock correction applied at each bit transition
+const CLOCK_GAIN: f32 = 0.3;
+
+/// A parsed SAME header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SameHeader {
+    /// Originator code, e.g. "WXR"
+    pub originator: String,
+    /// Event code, e.g. "TOR"
+    pub event: String,
+    /// PSSCCC location codes (portion, state FIPS, county FIPS)
+    pub locations: Vec<String>,
+    /// Purge time (validity period) in minutes
+    pub purge_minutes: u32,
+    /// Issue time as JJJHHMM (day of year, UTC hours and minutes)
+    pub issued: String,
+    /// Identifier of the sending station, e.g. "KCLE/NWS"
+    pub sender: String,
+}
+
+impl SameHeader {
+    /// Parse the text of a header starting at "ZCZC"
+    ///
+    /// Anything after the final field (trailing noise once the carrier drops)
+    /// is ignored.
+    pub fn parse(text: &str) -> Option<Self> {
+        let body = text.strip_prefix("ZCZC-")?;
+        let (head, tail) = body.split_once('+')?;
+
+        let mut fields = head.split('-');
+        let originator = fields.next().filter(|f| f.len() == 3)?.to_string();
+        let event = fields.next().filter(|f| f.len() == 3)?.to_string();
+        let locations: Vec<String> = fields.map(str::to_string).collect();
+        if locations.is_empty()
+            || locations
+                .iter()
+                .any(|l| l.len() != 6 || !l.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return None;
+        }
+
+        // TTTT-JJJHHMM-LLLLLLLL-
+        let tail = tail.get(..22)?;
+        let purge = tail.get(..4)?;
+        let issued = tail.get(5..12)?;
+        let sender = tail.get(13..21)?;
+        if tail.as_bytes()[4] != b'-' || tail.as_bytes()[12] != b'-' || !tail.ends_with('-') {
+            return None;
+        }
+        if !purge.bytes().chain(issued.bytes()).all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let purge_minutes = purge[..2].parse::<u32>().ok()? * 60 + purge[2..].parse::<u32>().ok()?;
+
+        Some(Self {
+            originator,
+            event,
+            locations,
+            purge_minutes,
+            issued: issued.to_string(),
+            sender: sender.trim().to_string(),
+        })
+    }
+
+    /// Human-readable event name
+    pub fn event_name(&self) -> &str {
+        event_name(&self.event).unwrap_or(&self.event)
+    }
+
+    /// Human-readable originator
+    pub fn originator_name(&self) -> &str {
+        match self.originator.as_str() {
+            "WXR" => "National Weather Service",
+            "EAS" => "EAS Participant",
+            "CIV" => "Civil Authorities",
+            "PEP" => "Primary Entry Point",
+            other => other,
+        }
+    }
+
+    /// One-line summary for the message list and logs
+    pub fn summary(&self) -> String {
+        format!(
+            "SAME: {} ({}) from {} for {} area(s) [{}], valid {}h{:02}m, issued {} by {}",
+            self.event_name(),
+            self.event,
+            self.originator_name(),
+            self.locations.len(),
+            self.locations.join(" "),
+            self.purge_minutes / 60,
+            self.purge_minutes % 60,
+            self.issued,
+            self.sender
+        )
+    }
+
+    /// Structured fields for logs
+    pub fn fields(&self) -> serde_json::Value {
+        serde_json::json!({
+            "originator": self.originator,
+            "event": self.event,
+            "event_name": self.event_name(),
+            "locations": self.locations,
+            "purge_minutes": self.purge_minutes,
+            "issued": self.issued,
+            "sender": self.sender,
+        })
+    }
+}
+
+/// Names for the common SAME event codes
+fn event_name(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "EAN" => "Emergency Action Notification",
+        "NPT" => "National Periodic Test",
+        "RMT" => "Required Monthly Test",
+        "RWT" => "Required Weekly Test",
+        "ADR" => "Administrative Message",
+        "AVW" => "Avalanche Warning",
+        "BZW" => "Blizzard Warning",
+        "CEM" => "Civil Emergency Message",
+        "CFW" => "Coastal Flood Warning",
+        "DSW" => "Dust Storm Warning",
+        "EQW" => "Earthquake Warning",
+        "EVI" => "Evacuation Immediate",
+        "EWW" => "Extreme Wind Warning",
+        "FFA" => "Flash Flood Watch",
+        "FFW" => "Flash Flood Warning",
+        "FFS" => "Flash Flood Statement",
+        "FLA" => "Flood Watch",
+        "FLW" => "Flood Warning",
+        "FLS" => "Flood Statement",
+        "FRW" => "Fire Warning",
+        "HUA" => "Hurricane Watch",
+        "HUW" => "Hurricane Warning",
+        "HLS" => "Hurricane Statement",
+        "SMW" => "Special Marine Warning",
+        "SPS" => "Special Weather Statement",
+        "SQW" => "Snow Squall Warning",
+        "SVA" => "Severe Thunderstorm Watch",
+        "SVR" => "Severe Thunderstorm Warning",
+        "SVS" => "Severe Weather Statement",
+        "TOA" => "Tornado Watch",
+        "TOR" => "Tornado Warning",
+        "TRA" => "Tropical Storm Watch",
+        "TRW" => "Tropical Storm Warning",
+        "TSA" => "Tsunami Watch",
+        "TSW" => "Tsunami Warning",
+        "WSA" => "Winter Storm Watch",
+        "WSW" => "Winter Storm Warning",
+        _ => return None,
+    })
+}
+
+/// Result of a voted group of bursts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SameEvent {
+    /// An alert header
+    Alert(SameHeader),
+    /// End of message (NNNN)
+    EndOfMessage,
+}
+
+/// Streaming SAME decoder
+pub struct SameDecoder {
+    /// Input sample rate the decoder was built for
+    sample_rate: u32,
+    /// Averages the audio down to the detection rate
+    boxcar: Boxcar,
+    detect_rate: f32,
+    /// Space and mark correlators: true for a mark
+    tones: ToneCorrelator,
+    clock: BitClock,
+    /// Bits shifted in LSB first
+    shift: u8,
+    /// Bits of the current byte once synchronized
+    bit_count: u32,
+    synced: bool,
+    /// Characters of the burst in progress
+    burst: Vec<u8>,
+    /// Bursts of the current group and time since the last one (seconds)
+    group: Vec<Vec<u8>>,
+    since_burst: f32,
+    /// Banner text of the alert in effect, and when it runs out unless its
+    /// end of message comes first
+    alert: Option<(String, Instant)>,
+}
+
+impl SameDecoder {
+    /// Create a decoder for audio at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        let boxcar = Boxcar::new(sample_rate, DETECT_RATE);
+        let detect_rate = boxcar.rate(sample_rate);
+
+        Self {
+            sample_rate,
+            boxcar,
+            detect_rate,
+            tones: ToneCorrelator::new(detect_rate, BAUD, [SPACE_FREQ, MARK_FREQ]),
+            clock: BitClock::new(detect_rate, BAUD, CLOCK_GAIN),
+            shift: 0,
+            bit_count: 0,
+            synced: false,
+            burst: Vec::new(),
+            group: Vec::new(),
+            since_burst: 0.0,
+            alert: None,
+        }
+    }
+
+    /// Drop the bit clock and byte sync, and any burst they were part way
+    /// through, keeping the bursts already heard and the alert in effect
+    fn resync(&mut self) {
+        self.boxcar.reset();
+        self.tones.reset();
+        self.clock.reset();
+        self.shift = 0;
+        self.bit_count = 0;
+        self.synced = false;
+        self.burst.clear();
+    }
+
+    /// Clear the alert if it has run out by `now`
+    fn expire(&mut self, now: Instant) {
+        if self.alert.as_ref().is_some_and(|(_, until)| now >= *until) {
+            self.alert = None;
+        }
+    }
+
+    /// Feed audio; returns any alerts or end-of-message markers completed
+    pub fn process(&mut self, audio: &[f32]) -> Vec<SameEvent> {
+        let mut events = Vec::new();
+
+        for &sample in audio {
+            let Some(x) = self.boxcar.push(sample) else {
+                continue;
+            };
+            if let Some(event) = self.demodulate(x) {
+                events.push(event);
+            }
+        }
+
+        events
+    }
+
+    /// Run one detection sample through the demodulator and bit clock
+    fn demodulate(&mut self, x: f32) -> Option<SameEvent> {
+        let soft = self.tones.push(x);
+        self.since_burst += 1.0 / self.detect_rate;
+        if let Some(bit) = self.clock.push(soft) {
+            self.receive_bit(bit);
+        }
+
+        // Vote once the group is complete or no more repeats are coming
+        let complete = self.group.len() >= REPEATS
+            || (!self.group.is_empty() && !self.synced && self.since_burst > GROUP_GAP_SECS);
+        if complete {
+            let group = std::mem::take(&mut self.group);
+            return resolve(&group);
+        }
+        None
+    }
+
+    /// Shift in one bit and assemble bytes
+    fn receive_bit(&mut self, bit: bool) {
+        self.shift = (self.shift >> 1) | if bit { 0x80 } else { 0 };
+
+        if !self.synced {
+            if self.shift == PREAMBLE {
+                self.synced = true;
+                self.bit_count = 0;
+                self.burst.clear();
+            }
+            return;
+        }
+
+        self.bit_count += 1;
+        if self.bit_count < 8 {
+            return;
+        }
+        self.bit_count = 0;
+
+        let byte = self.shift;
+        if byte == PREAMBLE && self.burst.is_empty() {
+            return;
+        }
+        if (0x20..0x7f).contains(&byte) && self.burst.len() < MAX_HEADER_LEN {
+            self.burst.push(byte);
+        } else {
+            self.end_burst();
+        }
+    }
+
+    /// Close the burst in progress and add it to the current group
+    fn end_burst(&mut self) {
+        self.synced = false;
+        let burst = std::mem::take(&mut self.burst);
+        if burst.len() < 4 {
+            return;
+        }
+        let is_eom = |b: &[u8]| b.starts_with(b"NN");
+        // A different kind of burst starts a new group
+        if self.group.first().is_some_and(|first| is_eom(first) != is_eom(&burst)) {
+            self.group.clear();
+        }
+        self.group.push(burst);
+        self.since_burst = 0.0;
+    }
+}
+
+/// Vote the repeats of a group byte by byte and interpret the result
+///
+/// At least two copies are required, so a lone burst (e.g. the tail of a
+/// transmission tuned in late) is never acted on.
+fn resolve(group: &[Vec<u8>]) -> Option<SameEvent> {
+    if group.len() < 2 {
+        return None;
+    }
+
+    let len = group.iter().map(Vec::len).max().unwrap_or(0);
+    let mut voted = Vec::with_capacity(len);
+    for i in 0..len {
+        let candidates: Vec<u8> = group.iter().filter_map(|b| b.get(i).copied()).collect();
+        let majority = candidates
+            .iter()
+            .copied()
+            .find(|&c| candidates.iter().filter(|&&o| o == c).count() * 2 > candidates.len());
+        match majority {
+            Some(c) => voted.push(c),
+            // No agreement: two copies that differ can't be trusted
+            None if candidates.len() == 2 => break,
+            None => voted.push(candidates[0]),
+        }
+    }
+
+    let text = String::from_utf8_lossy(&voted);
+    if text.starts_with("NNNN") {
+        Some(SameEvent::EndOfMessage)
+    } else {
+        SameHeader::parse(&text).map(SameEvent::Alert)
+    }
+}
+
+impl Decoder for SameDecoder {
+    fn name(&self) -> &'static str {
+        "SAME"
+    }
+
+    fn input_kind(&self) -> InputKind {
+        InputKind::Audio
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+        let DecoderInput::Audio(audio) = input else {
+            return Vec::new();
+        };
+
+        let now = Instant::now();
+        self.expire(now);
+        let mut messages = Vec::new();
+        for event in SameDecoder::process(self, audio) {
+            let message = match event {
+                SameEvent::Alert(header) => {
+                    let banner = format!(
+                        "{} ({} areas, {}h{:02}m)",
+                        header.event_name(),
+                        header.locations.len(),
+                        header.purge_minutes / 60,
+                        header.purge_minutes % 60
+                    );
+                    let lasts = Duration::from_secs(header.purge_minutes as u64 * 60);
+                    self.alert = Some((banner, now + lasts));
+                    DecodedMessage::new(DemodMode::FmNarrow, header.summary())
+                        .with_fields(header.fields())
+                }
+                SameEvent::EndOfMessage => {
+                    self.alert = None;
+                    DecodedMessage::new(DemodMode::FmNarrow, "SAME: end of message".to_string())
+                }
+            };
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn reset(&mut self) {
+        // A gap costs the burst it cut, not the alert already heard
+        self.resync();
+    }
+
+    fn publish(&self, state: &mut DecoderState) {
+        state.same_alert = self.alert.as_ref().map(|(banner, _)| banner.clone());
+    }
+
+    fn unpublish(&self, state: &mut DecoderState) {
+        state.same_alert = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::PI;
+
+    const HEADER: &str = "ZCZC-WXR-TOR-039173-039051+0030-1591829-KCLE/NWS-";
+
+    /// AFSK-modulate one burst: preamble then text, LSB first
+    fn burst(text: &str, rate: u32, audio: &mut Vec<f32>, phase: &mut f32) {
+        let spb = rate as f32 / BAUD;
+        let bytes: Vec<u8> = std::iter::repeat_n(PREAMBLE, 16).chain(text.bytes()).collect();
+        let mut end = audio.len() as f32;
+        for byte in bytes {
+            for bit in 0..8 {
+                let freq = if byte >> bit & 1 == 1 { MARK_FREQ } else { SPACE_FREQ };
+                end += spb;
+                while (audio.len() as f32) < end {
+                    *phase += 2.0 * PI * freq / rate as f32;
+                    audio.push(0.5 * phase.sin());
+                }
+            }
+        }
+    }
+
+    /// Three repeats of each text, a second apart, with noise
+    fn transmission(texts: &[&str], rate: u32, noise: f32) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut audio = vec![0.0; rate as usize / 2];
+        let mut phase = 0.0;
+        for text in texts {
+            burst(text, rate, &mut audio, &mut phase);
+            audio.extend(std::iter::repeat_n(0.0, rate as usize));
+        }
+        audio.extend(std::iter::repeat_n(0.0, rate as usize * 4));
+        if noise > 0.0 {
+            for sample in audio.iter_mut() {
+                *sample += rng.gen_range(-noise..noise);
+            }
+        }
+        audio
+    }
+
+    fn decode(audio: &[f32], rate: u32) -> Vec<SameEvent> {
+        let mut decoder = SameDecoder::new(rate);
+        audio.chunks(4096).flat_map(|c| decoder.process(c)).collect()
+    }
+
+    fn expected() -> SameHeader {
+        SameHeader::parse(HEADER).unwrap()
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header = SameHeader::parse(&format!("{}\u{7f}junk", HEADER)).unwrap();
+        assert_eq!(header.originator, "WXR");
+        assert_eq!(header.event, "TOR");
+        assert_eq!(header.event_name(), "Tornado Warning");
+        assert_eq!(header.locations, vec!["039173", "039051"]);
+        assert_eq!(header.purge_minutes, 30);
+        assert_eq!(header.issued, "1591829");
+        assert_eq!(header.sender, "KCLE/NWS");
+
+        assert!(SameHeader::parse("ZCZC-WXR-TOR-0391+0030-1591829-KCLE/NWS-").is_none());
+        assert!(SameHeader::parse("ZCZC-WXR-TOR-039173+0030-159").is_none());
+    }
+
+    #[test]
+    fn test_decode_three_repeats() {
+        let audio = transmission(&[HEADER, HEADER, HEADER], 48_000, 0.1);
+        assert_eq!(decode(&audio, 48_000), vec![SameEvent::Alert(expected())]);
+    }
+
+    #[test]
+    fn test_decode_at_iq_rate() {
+        let audio = transmission(&[HEADER, HEADER, HEADER], 240_000, 0.1);
+        assert_eq!(decode(&audio, 240_000), vec![SameEvent::Alert(expected())]);
+    }
+
+    #[test]
+    fn test_vote_corrects_corrupted_copies() {
+        // Each copy has a different byte wrong; the vote restores all of them
+        let bad1 = HEADER.replace("TOR", "TOX");
+        let bad2 = HEADER.replace("KCLE", "KCLF");
+        let audio = transmission(&[&bad1, HEADER, &bad2], 48_000, 0.05);
+        assert_eq!(decode(&audio, 48_000), vec![SameEvent::Alert(expected())]);
+    }
+
+    #[test]
+    fn test_two_copies_must_agree() {
+        let bad = HEADER.replace("039173", "039179");
+        let mut audio = transmission(&[HEADER, &bad], 48_000, 0.05);
+        // The disagreement leaves a truncated, unparseable header
+        assert!(decode(&audio, 48_000).is_empty());
+
+        audio = transmission(&[HEADER, HEADER], 48_000, 0.05);
+        assert_eq!(decode(&audio, 48_000), vec![SameEvent::Alert(expected())]);
+    }
+
+    #[test]
+    fn test_single_burst_ignored() {
+        let audio = transmission(&[HEADER], 48_000, 0.05);
+        assert!(decode(&audio, 48_000).is_empty());
+    }
+
+    #[test]
+    fn test_end_of_message() {
+        let audio = transmission(&[HEADER, HEADER, HEADER, "NNNN", "NNNN", "NNNN"], 48_000, 0.1);
+        assert_eq!(
+            decode(&audio, 48_000),
+            vec![SameEvent::Alert(expected()), SameEvent::EndOfMessage]
+        );
+    }
+
+    #[test]
+    fn test_alert_outlasts_gap() {
+        let audio = transmission(&[HEADER, HEADER, HEADER], 48_000, 0.1);
+        let mut decoder = SameDecoder::new(48_000);
+        let messages = Decoder::process(&mut decoder, DecoderInput::Audio(&audio));
+        assert_eq!(messages.len(), 1);
+        let (_, until) = decoder.alert.clone().unwrap();
+
+        // A gap resyncs, and the alert stands
+        Decoder::reset(&mut decoder);
+        let mut state = DecoderState::default();
+        decoder.publish(&mut state);
+        assert_eq!(state.same_alert.as_deref(), Some("Tornado Warning (2 areas, 0h30m)"));
+
+        // Until the half hour it was sent for is up
+        decoder.expire(until - Duration::from_secs(1));
+        assert!(decoder.alert.is_some());
+        decoder.expire(until);
+        assert!(decoder.alert.is_none());
+    }
+
+    #[test]
+    fn test_noise_is_silent() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let audio: Vec<f32> = (0..48_000 * 5).map(|_| rng.gen_range(-0.5..0.5)).collect();
+        assert!(decode(&audio, 48_000).is_empty());
+    }
+}
@@ -0,0 +1,548 @@
+//! Decoder thread
+//!
+//! Decoding runs apart from the DSP thread so a slow decoder can never stall
+//! audio. The DSP thread tees the signal forms the active decoders want into
+//! a bounded channel through a [`DecoderTap`]; when the decoder thread falls
+//! behind, decoder input is dropped (and the decoders reset at the gap) while
+//! audio carries on. The decode log is written here too, so disk latency
+//! never reaches the DSP thread. Each receive chain has its own decoder
+//! thread, publishing its decoders' status to its own
+//! [`DecoderState`](crate::state::DecoderState);
+//! they share the decode log and the message list.
+//!
+//! Messages are dated by when the signal that completed them was captured,
+//! as stamped on the input by the DSP thread, rather than by when the
+//! decoder thread got round to them.
+//!
+//! The thread wakes at least every 100 ms, which also times the aircraft
+//! table's export to a file for web maps.
+
+use super::{
+    AircraftExport, DecoderInput, DecoderRegistry, DecoderSelection, InputKind, MessageLog,
+};
+use crate::mqtt::Outbox;
+use crate::state::SharedState;
+use crate::types::{Chain, DecodedMessage};
+use crossbeam::channel::{Receiver, Sender, TrySendError};
+use num_complex::Complex;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Instant, SystemTime};
+use tracing::field::Empty;
+
+/// Buffers queued for the decoder thread before input is dropped
+const QUEUE_LEN: usize = 16;
+
+/// Work for the decoder thread, in stream order
+#[derive(Debug)]
+pub enum DecoderEvent {
+    /// The receiver setup changed; rebuild the decoders
+    Select(DecoderSelection),
+    /// Input was lost; partial frames can't be completed
+    Gap,
+    /// Input, with when the end of the buffer it came from was captured
+    Iq(Vec<Complex<f32>>, Option<SystemTime>),
+    Magnitude(Vec<f32>, Option<SystemTime>),
+    Audio(Vec<f32>, Option<SystemTime>),
+}
+
+impl DecoderEvent {
+    /// Whether this carries signal for the decoders
+    fn is_input(&self) -> bool {
+        matches!(
+            self,
+            DecoderEvent::Iq(..) | DecoderEvent::Magnitude(..) | DecoderEvent::Audio(..)
+        )
+    }
+}
+
+/// DSP-side end of the decoder channel
+pub struct DecoderTap {
+    tx: Sender<DecoderEvent>,
+    /// Input kinds the active decoders consume, one bit per [`InputKind`]
+    wanted: Arc<AtomicU8>,
+    /// Last selection the decoder thread was told about
+    sent: Option<DecoderSelection>,
+    /// Selection change still waiting for room in the queue
+    pending: Option<DecoderSelection>,
+    /// A gap to report before the next input buffer
+    gap: bool,
+    /// Buffers dropped since the decoder thread last kept up
+    dropped: u64,
+    /// Buffers dropped since the tap was created
+    total_dropped: u64,
+    /// When the end of the buffer being teed was captured
+    captured: Option<SystemTime>,
+}
+
+/// Create a tap and the receiver to hand to [`start_decoder_thread`]
+pub fn decoder_channel() -> (DecoderTap, DecoderReceiver) {
+    let (tx, rx) = crossbeam::channel::bounded(QUEUE_LEN);
+    let wanted = Arc::new(AtomicU8::new(0));
+    let tap = DecoderTap {
+        tx,
+        wanted: wanted.clone(),
+        sent: None,
+        pending: None,
+        gap: false,
+        dropped: 0,
+        total_dropped: 0,
+        captured: None,
+    };
+    (tap, DecoderReceiver { rx, wanted })
+}
+
+/// Decoder-thread end of the decoder channel
+pub struct DecoderReceiver {
+    rx: Receiver<DecoderEvent>,
+    wanted: Arc<AtomicU8>,
+}
+
+fn kind_bit(kind: InputKind) -> u8 {
+    1 << kind as u8
+}
+
+impl DecoderTap {
+    /// Tell the decoder thread about the current selection, if it changed
+    pub fn select(&mut self, selection: DecoderSelection) {
+        if self.sent != Some(selection) {
+            self.sent = Some(selection);
+            self.pending = Some(selection);
+        }
+        self.send_control();
+    }
+
+    /// Whether the active decoders consume `kind`, so it is worth computing
+    pub fn wants(&self, kind: InputKind) -> bool {
+        self.wanted.load(Ordering::Relaxed) & kind_bit(kind) != 0
+    }
+
+    /// Buffers dropped since the tap was created
+    pub fn total_dropped(&self) -> u64 {
+        self.total_dropped
+    }
+
+    /// Date the input sent from now on as captured at `time`
+    pub fn stamp(&mut self, time: SystemTime) {
+        self.captured = Some(time);
+    }
+
+    /// Note a break in the input stream (e.g. the SDR stalled)
+    pub fn mark_gap(&mut self) {
+        self.gap = true;
+    }
+
+    /// Queue a buffer for the decoders if they want it; never blocks
+    pub fn send(&mut self, input: DecoderInput) {
+        if !self.wants(input.kind()) {
+            return;
+        }
+        // Input from a new selection must not overtake its Select
+        if !self.send_control() {
+            self.drop_input();
+            return;
+        }
+
+        let captured = self.captured;
+        let event = match input {
+            DecoderInput::Iq(samples) => DecoderEvent::Iq(samples.to_vec(), captured),
+            DecoderInput::Magnitude(samples) => {
+                DecoderEvent::Magnitude(samples.to_vec(), captured)
+            }
+            DecoderInput::Audio(samples) => DecoderEvent::Audio(samples.to_vec(), captured),
+        };
+        match self.tx.try_send(event) {
+            Ok(()) => {
+                if self.dropped > 0 {
+                    log::debug!("Decoder caught up after {} dropped buffers", self.dropped);
+                    self.dropped = 0;
+                }
+            }
+            Err(TrySendError::Full(_)) => self.drop_input(),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Send any pending selection change and gap; false if the queue is full
+    fn send_control(&mut self) -> bool {
+        if let Some(selection) = self.pending {
+            match self.tx.try_send(DecoderEvent::Select(selection)) {
+                Err(TrySendError::Full(_)) => return false,
+                _ => self.pending = None,
+            }
+        }
+        if self.gap {
+            match self.tx.try_send(DecoderEvent::Gap) {
+                Err(TrySendError::Full(_)) => return false,
+                _ => self.gap = false,
+            }
+        }
+        true
+    }
+
+    fn drop_input(&mut self) {
+        if self.dropped == 0 {
+            log::warn!("Decoder is falling behind, dropping decoder input");
+        }
+        self.dropped += 1;
+        self.total_dropped += 1;
+        self.gap = true;
+    }
+}
+
+/// Start `chain`'s decoder thread, which owns `registry`, writes the decode
+/// log, queues messages for MQTT in `outbox` and keeps the aircraft file of
+/// `aircraft_export` up to date
+#[allow(clippy::too_many_arguments)]
+pub fn start_decoder_thread(
+    state: SharedState,
+    chain: Chain,
+    mut registry: DecoderRegistry,
+    receiver: DecoderReceiver,
+    message_log: Arc<Mutex<MessageLog>>,
+    outbox: Option<Outbox>,
+    mut aircraft_export: Option<AircraftExport>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        log::info!("Chain {} decoder thread started", chain.name());
+
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                log::info!("Decoder thread shutting down");
+                break;
+            }
+
+            let event = match receiver.rx.recv_timeout(std::time::Duration::from_millis(100)) {
+                Ok(event) => Some(event),
+                Err(crossbeam::channel::RecvTimeoutError::Timeout) => None,
+                Err(crossbeam::channel::RecvTimeoutError::Disconnected) => {
+                    log::info!("DSP thread disconnected, decoder thread exiting");
+                    break;
+                }
+            };
+
+            let started = std::time::Instant::now();
+            let decoded = event.as_ref().is_some_and(DecoderEvent::is_input);
+            let messages = match event {
+                None => Vec::new(),
+                Some(event) => handle_event(&state, chain, &mut registry, &receiver, event),
+            };
+            let decode_time = started.elapsed();
+
+            write_log(&state, &mut message_log.lock(), &messages);
+            if let Some(outbox) = &outbox {
+                messages.iter().for_each(|message| outbox.push(message));
+            }
+
+            if !messages.is_empty() || !registry.is_empty() {
+                let mut state_guard = state.write();
+                if decoded {
+                    let micros = decode_time.as_secs_f32() * 1e6;
+                    let stats = &mut state_guard.stats;
+                    match chain {
+                        Chain::A => stats.decode_us.update(micros),
+                        Chain::B => stats.decode_b_us.update(micros),
+                    };
+                }
+                registry.publish(state_guard.decoder_status(chain));
+                state_guard.decoder.prune_aircraft(std::time::Instant::now());
+                if !messages.is_empty() {
+                    state_guard.changed.notify();
+                }
+                for message in messages {
+                    log::info!("{}", message.content);
+                    state_guard.decoder.add_message(message);
+                }
+            }
+
+            let now = Instant::now();
+            if let Some(export) = aircraft_export.as_mut().filter(|e| e.is_due(now)) {
+                let aircraft: Vec<_> = state.read().decoder.aircraft.values().cloned().collect();
+                export.write(&aircraft, now);
+            }
+        }
+
+        message_log.lock().close();
+        log::info!("Decoder thread stopped");
+    })
+}
+
+/// Run one event through the registry; returns the messages it produced
+fn handle_event(
+    state: &SharedState,
+    chain: Chain,
+    registry: &mut DecoderRegistry,
+    receiver: &DecoderReceiver,
+    event: DecoderEvent,
+) -> Vec<DecodedMessage> {
+    match event {
+        DecoderEvent::Select(selection) => {
+            let pending = registry.select(selection, state.write().decoder_status(chain));
+            let wanted = [InputKind::Iq, InputKind::Audio, InputKind::Magnitude]
+                .into_iter()
+                .filter(|&kind| registry.wants(kind))
+                .fold(0, |bits, kind| bits | kind_bit(kind));
+            receiver.wanted.store(wanted, Ordering::Relaxed);
+            pending
+        }
+        DecoderEvent::Gap => {
+            registry.reset();
+            Vec::new()
+        }
+        DecoderEvent::Iq(samples, captured) => {
+            decode(registry, DecoderInput::Iq(&samples), captured)
+        }
+        DecoderEvent::Magnitude(samples, captured) => {
+            decode(registry, DecoderInput::Magnitude(&samples), captured)
+        }
+        DecoderEvent::Audio(samples, captured) => {
+            decode(registry, DecoderInput::Audio(&samples), captured)
+        }
+    }
+}
+
+/// Run a frame of input through the registry, in a span giving its size,
+/// the messages it produced and the time it took; the messages are dated
+/// `captured`, if known
+fn decode(
+    registry: &mut DecoderRegistry,
+    input: DecoderInput,
+    captured: Option<SystemTime>,
+) -> Vec<DecodedMessage> {
+    let started = Instant::now();
+    let len = match input {
+        DecoderInput::Iq(samples) => samples.len(),
+        DecoderInput::Audio(samples) | DecoderInput::Magnitude(samples) => samples.len(),
+    };
+    let span = tracing::debug_span!(
+        "decoder.frame",
+        kind = ?input.kind(),
+        len,
+        messages = Empty,
+        micros = Empty
+    )
+    .entered();
+    let mut messages = registry.process(input);
+    if let Some(captured) = captured {
+        for message in &mut messages {
+            message.timestamp = captured.into();
+        }
+    }
+    span.record("messages", messages.len());
+    span.record("micros", started.elapsed().as_micros() as u64);
+    messages
+}
+
+/// Append messages to the decode log while it is enabled, closing it when not
+fn write_log(state: &SharedState, message_log: &mut MessageLog, messages: &[DecodedMessage]) {
+    if !state.read().decoder.log_enabled {
+        if message_log.is_open() {
+            message_log.close();
+        }
+        return;
+    }
+
+    for message in messages {
+        if let Err(e) = message_log.write(message) {
+            log::error!("Decode log: {:#}", e);
+            message_log.close();
+            let mut state = state.write();
+            state.decoder.log_enabled = false;
+            state.ui.status_message = format!("Decode log stopped: {}", e);
+            return;
+        }
+    }
+    message_log.flush_if_due();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace_capture::capture;
+    use crate::types::DemodMode;
+
+    fn selection(mode: DemodMode) -> DecoderSelection {
+        DecoderSelection {
+            mode,
+            frequency: 0,
+            sample_rate: 48_000,
+            dtmf_enabled: false,
+        }
+    }
+
+    /// Makes a message of every audio buffer
+    struct Echo;
+
+    impl super::super::Decoder for Echo {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn input_kind(&self) -> InputKind {
+            InputKind::Audio
+        }
+
+        fn sample_rate(&self) -> u32 {
+            48_000
+        }
+
+        fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+            vec![DecodedMessage::new(DemodMode::Cw, format!("{:?}", input.kind()))]
+        }
+
+        fn reset(&mut self) {}
+    }
+
+    #[test]
+    fn test_frame_span() {
+        let state = crate::state::AppState::new_shared();
+        let mut registry = DecoderRegistry::with_factory(|_| vec![Box::new(Echo)]);
+        let (_tap, receiver) = decoder_channel();
+        let (messages, spans) = capture(|| {
+            let mut messages = handle_event(
+                &state,
+                Chain::A,
+                &mut registry,
+                &receiver,
+                DecoderEvent::Select(selection(DemodMode::Cw)),
+            );
+            for event in [DecoderEvent::Audio(vec![0.0; 480], None), DecoderEvent::Gap] {
+                messages.extend(handle_event(&state, Chain::A, &mut registry, &receiver, event));
+            }
+            messages
+        });
+        assert_eq!(messages.len(), 1);
+
+        // Only input makes a frame
+        assert_eq!(spans.len(), 1);
+        let frame = &spans[0];
+        assert_eq!(frame.name, "decoder.frame");
+        assert_eq!(frame.field("kind"), Some("Audio"));
+        assert_eq!(frame.field("len"), Some("480"));
+        assert_eq!(frame.field("messages"), Some("1"));
+        assert!(frame.field("micros").is_some());
+    }
+
+    /// Publishes a CW speed, as the CW decoder does
+    struct Wpm(f32);
+
+    impl super::super::Decoder for Wpm {
+        fn name(&self) -> &'static str {
+            "wpm"
+        }
+
+        fn input_kind(&self) -> InputKind {
+            InputKind::Audio
+        }
+
+        fn sample_rate(&self) -> u32 {
+            48_000
+        }
+
+        fn process(&mut self, _input: DecoderInput) -> Vec<DecodedMessage> {
+            Vec::new()
+        }
+
+        fn reset(&mut self) {}
+
+        fn publish(&self, state: &mut crate::state::DecoderState) {
+            state.cw_wpm = Some(self.0);
+        }
+    }
+
+    #[test]
+    fn test_chains_publish_apart() {
+        use super::super::{DecodeLogFormat, MessageLog};
+
+        let state = crate::state::AppState::new_shared();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let log = MessageLog::new("decodes.jsonl".into(), DecodeLogFormat::Jsonl, u64::MAX);
+        let log = Arc::new(Mutex::new(log));
+        let mut taps = Vec::new();
+        let mut threads = Vec::new();
+        for (chain, wpm) in [(Chain::A, 18.0), (Chain::B, 25.0)] {
+            let (mut tap, receiver) = decoder_channel();
+            tap.select(selection(DemodMode::Cw));
+            let registry = DecoderRegistry::with_factory(move |_| vec![Box::new(Wpm(wpm))]);
+            threads.push(start_decoder_thread(
+                state.clone(),
+                chain,
+                registry,
+                receiver,
+                log.clone(),
+                None,
+                None,
+                shutdown.clone(),
+            ));
+            taps.push(tap);
+        }
+
+        // Each keeps publishing, neither over the other
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        assert_eq!(state.read().decoder.cw_wpm, Some(18.0));
+        assert_eq!(state.read().decoder_b.cw_wpm, Some(25.0));
+        shutdown.store(true, Ordering::Relaxed);
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_messages_dated_by_capture() {
+        let state = crate::state::AppState::new_shared();
+        let mut registry = DecoderRegistry::with_factory(|_| vec![Box::new(Echo)]);
+        let (mut tap, receiver) = decoder_channel();
+        tap.select(selection(DemodMode::Cw));
+        receiver.wanted.store(kind_bit(InputKind::Audio), Ordering::Relaxed);
+        let captured = SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(1_717_200_000_250);
+        tap.stamp(captured);
+        tap.send(DecoderInput::Audio(&[0.0; 4]));
+
+        let messages: Vec<DecodedMessage> = receiver
+            .rx
+            .try_iter()
+            .flat_map(|event| handle_event(&state, Chain::A, &mut registry, &receiver, event))
+            .collect();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].timestamp.to_rfc3339(),
+            "2024-06-01T00:00:00.250+00:00"
+        );
+    }
+
+    #[test]
+    fn test_tap_sends_only_wanted_input() {
+        let (mut tap, receiver) = decoder_channel();
+        tap.select(selection(DemodMode::Cw));
+        tap.send(DecoderInput::Audio(&[0.0; 4]));
+        receiver.wanted.store(kind_bit(InputKind::Audio), Ordering::Relaxed);
+        tap.send(DecoderInput::Magnitude(&[0.0; 4]));
+        tap.send(DecoderInput::Audio(&[0.0; 4]));
+
+        let events: Vec<DecoderEvent> = receiver.rx.try_iter().collect();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], DecoderEvent::Select(s) if s.mode == DemodMode::Cw));
+        assert!(matches!(&events[1], DecoderEvent::Audio(a, None) if a.len() == 4));
+    }
+
+    #[test]
+    fn test_tap_drops_input_and_reports_gap() {
+        let (mut tap, receiver) = decoder_channel();
+        receiver.wanted.store(kind_bit(InputKind::Audio), Ordering::Relaxed);
+        for _ in 0..QUEUE_LEN + 5 {
+            tap.send(DecoderInput::Audio(&[0.0; 4]));
+        }
+        assert_eq!(tap.dropped, 5);
+        assert_eq!(tap.total_dropped(), 5);
+
+        // Once there is room, the gap goes ahead of the next buffer
+        while receiver.rx.try_recv().is_ok() {}
+        tap.send(DecoderInput::Audio(&[1.0; 4]));
+        assert!(matches!(receiver.rx.try_recv(), Ok(DecoderEvent::Gap)));
+        assert!(matches!(receiver.rx.try_recv(), Ok(DecoderEvent::Audio(..))));
+        assert_eq!(tap.dropped, 0);
+    }
+}
@@ -0,0 +1,322 @@
+//! Signal activity detection from the live spectrum
+//!
+//! FFT frames are averaged between detections, which steadies the noise
+//! enough to threshold. The noise floor is a low percentile of each of a
+//! number of regions across the band, so it follows the tuner's roll-off,
+//! and a wide signal filling most of a region doesn't lift its own floor.
+//! Bins standing far enough above their region's floor are clustered into
+//! signals, and the signals are kept in a table by absolute frequency so it
+//! survives retuning.
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Regions the band is split into for the noise floor
+const REGIONS: usize = 16;
+/// Share of each region's bins below its floor
+const FLOOR_PERCENTILE: f32 = 0.2;
+/// Height above the floor a bin needs to count as signal, in dB
+pub const THRESHOLD_DB: f32 = 10.0;
+/// Quiet bins a signal may span before it is split in two
+const MAX_GAP: usize = 1;
+/// Bins either side of DC whose spike alone isn't reported
+const DC_BINS: usize = 1;
+/// Signals not seen for this long leave the table
+pub const ACTIVITY_EXPIRE: Duration = Duration::from_secs(120);
+
+/// A signal found in one averaged spectrum
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    /// Power-weighted center, in Hz from the center frequency
+    pub offset: i32,
+    /// Width of the bins above the threshold in Hz
+    pub bandwidth: u32,
+    pub peak_db: f32,
+}
+
+/// Per-bin noise floor: the `FLOOR_PERCENTILE` level of each region
+pub fn noise_floor(spectrum: &[f32]) -> Vec<f32> {
+    let region_len = spectrum.len().div_ceil(REGIONS).max(1);
+    let mut floor = Vec::with_capacity(spectrum.len());
+    for region in spectrum.chunks(region_len) {
+        let mut sorted = region.to_vec();
+        sorted.sort_by(f32::total_cmp);
+        let level = sorted[(sorted.len() as f32 * FLOOR_PERCENTILE) as usize];
+        floor.extend(std::iter::repeat_n(level, region.len()));
+    }
+    floor
+}
+
+/// Runs of bins at least `threshold_db` above the floor, bridging gaps of
+/// up to `MAX_GAP` bins
+fn clusters(spectrum: &[f32], floor: &[f32], threshold_db: f32) -> Vec<Range<usize>> {
+    let mut clusters: Vec<Range<usize>> = Vec::new();
+    for (bin, (db, floor)) in spectrum.iter().zip(floor).enumerate() {
+        if db - floor < threshold_db {
+            continue;
+        }
+        match clusters.last_mut() {
+            Some(cluster) if bin - cluster.end <= MAX_GAP => cluster.end = bin + 1,
+            _ => clusters.push(bin..bin + 1),
+        }
+    }
+    clusters
+}
+
+/// Signals in a centered dB spectrum spanning `sample_rate`
+pub fn find_signals(spectrum: &[f32], sample_rate: u32, threshold_db: f32) -> Vec<Detection> {
+    let floor = noise_floor(spectrum);
+    let bin_hz = sample_rate as f64 / spectrum.len().max(1) as f64;
+    let dc = spectrum.len() / 2;
+
+    clusters(spectrum, &floor, threshold_db)
+        .into_iter()
+        // A lone DC spike is the receiver, not a signal
+        .filter(|c| c.start + DC_BINS < dc || c.end > dc + DC_BINS + 1)
+        .map(|cluster| {
+            let bins = &spectrum[cluster.clone()];
+            let power: Vec<f64> = bins
+                .iter()
+                .map(|&db| 10f64.powf(db as f64 / 10.0))
+                .collect();
+            let total: f64 = power.iter().sum();
+            let centroid = cluster
+                .clone()
+                .zip(&power)
+                .map(|(bin, p)| bin as f64 * p)
+                .sum::<f64>()
+                / total;
+            Detection {
+                offset: ((centroid - dc as f64) * bin_hz).round() as i32,
+                bandwidth: (cluster.len() as f64 * bin_hz).round() as u32,
+                peak_db: bins.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            }
+        })
+        .collect()
+}
+
+/// Averages FFT frames in linear power between detections
+#[derive(Debug, Default)]
+pub struct ActivityDetector {
+    sum: Vec<f64>,
+    frames: usize,
+}
+
+impl ActivityDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a dB frame to the average; a frame of another size starts over
+    pub fn add_frame(&mut self, frame: &[f32]) {
+        if frame.len() != self.sum.len() {
+            self.sum = vec![0.0; frame.len()];
+            self.frames = 0;
+        }
+        for (sum, &db) in self.sum.iter_mut().zip(frame) {
+            *sum += 10f64.powf(db as f64 / 10.0);
+        }
+        self.frames += 1;
+    }
+
+    /// Signals in the average so far, which then starts over
+    pub fn take(&mut self, sample_rate: u32) -> Vec<Detection> {
+        if self.frames == 0 {
+            return Vec::new();
+        }
+        let average: Vec<f32> = self
+            .sum
+            .iter()
+            .map(|&sum| (10.0 * (sum / self.frames as f64).max(1e-20).log10()) as f32)
+            .collect();
+        self.reset();
+        find_signals(&average, sample_rate, THRESHOLD_DB)
+    }
+
+    /// Drop the frames so far, e.g. after a retune
+    pub fn reset(&mut self) {
+        self.sum.fill(0.0);
+        self.frames = 0;
+    }
+}
+
+/// A signal in the activity table
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveSignal {
+    /// Latest center frequency in Hz
+    pub frequency: u32,
+    /// Latest bandwidth in Hz
+    pub bandwidth: u32,
+    /// Highest level seen, in dB
+    pub peak_db: f32,
+    pub first_seen: Instant,
+    pub last_seen: Instant,
+}
+
+/// Signals seen recently, by frequency
+#[derive(Debug, Clone, Default)]
+pub struct ActivityTable {
+    signals: Vec<ActiveSignal>,
+}
+
+impl ActivityTable {
+    /// Fold in `detections` from a spectrum centered on `center`, and drop
+    /// signals not seen for `ACTIVITY_EXPIRE`
+    pub fn update(&mut self, detections: &[Detection], center: u32, now: Instant) {
+        for detection in detections {
+            let frequency = center.saturating_add_signed(detection.offset);
+            // The same signal if either one's width covers the other's center
+            let existing = self.signals.iter_mut().find(|signal| {
+                signal.frequency.abs_diff(frequency)
+                    <= signal.bandwidth.max(detection.bandwidth) / 2
+            });
+            match existing {
+                Some(signal) => {
+                    signal.frequency = frequency;
+                    signal.bandwidth = detection.bandwidth;
+                    signal.peak_db = signal.peak_db.max(detection.peak_db);
+                    signal.last_seen = now;
+                }
+                None => self.signals.push(ActiveSignal {
+                    frequency,
+                    bandwidth: detection.bandwidth,
+                    peak_db: detection.peak_db,
+                    first_seen: now,
+                    last_seen: now,
+                }),
+            }
+        }
+
+        self.signals
+            .retain(|signal| now.saturating_duration_since(signal.last_seen) <= ACTIVITY_EXPIRE);
+        self.signals.sort_by_key(|signal| signal.frequency);
+    }
+
+    /// Signals, lowest frequency first
+    pub fn signals(&self) -> &[ActiveSignal] {
+        &self.signals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 2_048_000;
+
+    /// 2048 bins of 1 kHz: noise at -90 dB rising to -80 dB across the
+    /// band, a -40 dB carrier at +300 kHz, a 12 kHz wide -60 dB signal
+    /// centered on -500 kHz, and a DC spike
+    fn synthetic() -> Vec<f32> {
+        let mut spectrum: Vec<f32> = (0..2048)
+            .map(|bin| -90.0 + 10.0 * bin as f32 / 2048.0 + if bin % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        spectrum[1324] = -40.0;
+        spectrum[1323] = -55.0;
+        spectrum[1325] = -55.0;
+        for db in &mut spectrum[518..530] {
+            *db = -60.0;
+        }
+        spectrum[1024] = -50.0;
+        spectrum
+    }
+
+    #[test]
+    fn test_noise_floor_follows_slope() {
+        let spectrum = synthetic();
+        let floor = noise_floor(&spectrum);
+        assert_eq!(floor.len(), spectrum.len());
+        // Within a couple of dB of the noise in every region, signals or not
+        for (bin, level) in floor.iter().enumerate().step_by(128) {
+            let noise = -90.0 + 10.0 * bin as f32 / 2048.0;
+            assert!(
+                (level - noise).abs() < 2.0,
+                "bin {}: {} vs {}",
+                bin,
+                level,
+                noise
+            );
+        }
+    }
+
+    #[test]
+    fn test_finds_known_carriers() {
+        let signals = find_signals(&synthetic(), RATE, THRESHOLD_DB);
+        assert_eq!(signals.len(), 2, "{:?}", signals);
+
+        let wide = &signals[0];
+        assert!((wide.offset + 500_500).abs() <= 1_000, "{:?}", wide);
+        assert_eq!(wide.bandwidth, 12_000);
+        assert_eq!(wide.peak_db, -60.0);
+
+        let carrier = &signals[1];
+        assert!((carrier.offset - 300_000).abs() <= 100, "{:?}", carrier);
+        assert_eq!(carrier.bandwidth, 3_000);
+        assert_eq!(carrier.peak_db, -40.0);
+    }
+
+    #[test]
+    fn test_gaps_bridged_up_to_limit() {
+        let spectrum = [-90.0, -50.0, -90.0, -50.0, -90.0, -90.0, -50.0, -90.0];
+        let floor = [-90.0; 8];
+        assert_eq!(clusters(&spectrum, &floor, 10.0), [1..4, 6..7]);
+    }
+
+    #[test]
+    fn test_detector_averages_frames() {
+        let mut detector = ActivityDetector::new();
+        assert!(detector.take(RATE).is_empty());
+
+        // A carrier in one frame of many averages away; a steady one stays
+        let mut flash = vec![-90.0; 2048];
+        flash[100] = -75.0;
+        let mut steady = vec![-90.0; 2048];
+        steady[1500] = -75.0;
+        detector.add_frame(&flash);
+        for _ in 0..31 {
+            detector.add_frame(&steady);
+        }
+        let signals = detector.take(RATE);
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].offset, 476_000);
+
+        // Taking starts a new average
+        assert!(detector.take(RATE).is_empty());
+    }
+
+    #[test]
+    fn test_table_merges_and_expires() {
+        let t0 = Instant::now();
+        let detection = |offset, bandwidth, peak_db| Detection {
+            offset,
+            bandwidth,
+            peak_db,
+        };
+        let mut table = ActivityTable::default();
+
+        table.update(&[detection(300_000, 12_000, -50.0)], 145_000_000, t0);
+        let later = t0 + Duration::from_secs(10);
+        // The same signal seen after a retune, slightly off; and a new one
+        table.update(
+            &[
+                detection(-197_000, 12_000, -45.0),
+                detection(-900_000, 3_000, -70.0),
+            ],
+            145_500_000,
+            later,
+        );
+
+        let signals = table.signals();
+        assert_eq!(signals.len(), 2);
+        assert_eq!(signals[0].frequency, 144_600_000);
+        assert_eq!(signals[1].frequency, 145_303_000);
+        assert_eq!(signals[1].peak_db, -45.0);
+        assert_eq!(signals[1].first_seen, t0);
+        assert_eq!(signals[1].last_seen, later);
+
+        let expired = later + ACTIVITY_EXPIRE + Duration::from_secs(1);
+        table.update(&[detection(-900_000, 3_000, -70.0)], 145_500_000, expired);
+        assert_eq!(table.signals().len(), 1);
+        assert_eq!(table.signals()[0].frequency, 144_600_000);
+    }
+}
@@ -1,6 +1,15 @@
 use num_complex::Complex;
-use rustfft::{FftPlanner, num_complex::Complex32};
+use rustfft::{Fft, FftPlanner, num_complex::Complex32};
 use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Highest audio frequency in the AF spectrum
+pub const AF_MAX_FREQ: f32 = 5_000.0;
+/// AF spectrum FFT size
+const AF_FFT_SIZE: usize = 512;
+/// Lowest rate audio is averaged down to before the AF FFT, keeping
+/// Nyquist just above [`AF_MAX_FREQ`]
+const AF_MIN_RATE: u32 = 11_000;
 
 /// FFT processor for spectrum analysis
 pub struct FftProcessor {
@@ -126,6 +135,104 @@ impl FftProcessor {
     }
 }
 
+/// Spectrum of the demodulated audio, 0 Hz to [`AF_MAX_FREQ`]
+///
+/// Audio is averaged down to about 11 kHz first; a 512-point frame then
+/// resolves ~22 Hz, enough to pick out CTCSS tones.
+pub struct AfSpectrum {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    /// Input rate the decimation was set up for
+    sample_rate: u32,
+    /// Input samples averaged into each decimated sample
+    decimation: usize,
+    acc: f32,
+    acc_count: usize,
+    /// Decimated samples of the frame being filled
+    frame: Vec<f32>,
+}
+
+impl AfSpectrum {
+    pub fn new() -> Self {
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(AF_FFT_SIZE),
+            window: FftProcessor::hann_window(AF_FFT_SIZE),
+            sample_rate: 0,
+            decimation: 1,
+            acc: 0.0,
+            acc_count: 0,
+            frame: Vec::with_capacity(AF_FFT_SIZE),
+        }
+    }
+
+    /// Feed audio at `sample_rate`; returns the newest completed spectrum
+    /// in dB relative to a full-scale tone
+    pub fn process(&mut self, sample_rate: u32, audio: &[f32]) -> Option<Vec<f32>> {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            self.decimation = (sample_rate / AF_MIN_RATE).max(1) as usize;
+            self.acc = 0.0;
+            self.acc_count = 0;
+            self.frame.clear();
+        }
+
+        let mut spectrum = None;
+        for &sample in audio {
+            self.acc += sample;
+            self.acc_count += 1;
+            if self.acc_count < self.decimation {
+                continue;
+            }
+            self.frame.push(self.acc / self.acc_count as f32);
+            self.acc = 0.0;
+            self.acc_count = 0;
+
+            if self.frame.len() == AF_FFT_SIZE {
+                spectrum = Some(self.transform());
+                self.frame.clear();
+            }
+        }
+        spectrum
+    }
+
+    /// Rate of the decimated audio the FFT sees
+    fn rate(&self) -> f32 {
+        self.sample_rate as f32 / self.decimation as f32
+    }
+
+    fn transform(&self) -> Vec<f32> {
+        let mut buffer: Vec<Complex32> = self
+            .frame
+            .iter()
+            .zip(&self.window)
+            .map(|(&s, &w)| Complex32::new(s * w, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+
+        // A Hann-windowed full-scale sine peaks at N/4
+        let scale = 4.0 / AF_FFT_SIZE as f32;
+        let bin_hz = self.rate() / AF_FFT_SIZE as f32;
+        let bins = ((AF_MAX_FREQ / bin_hz) as usize + 1).min(AF_FFT_SIZE / 2);
+        buffer[..bins]
+            .iter()
+            .map(|c| {
+                let magnitude = c.norm() * scale;
+                if magnitude > 1e-5 {
+                    20.0 * magnitude.log10()
+                } else {
+                    -100.0
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for AfSpectrum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Utility function to normalize FFT output to a specified range
 pub fn normalize_fft(fft_data: &[f32], min_db: f32, max_db: f32) -> Vec<f32> {
     fft_data
@@ -165,6 +272,51 @@ mod tests {
         assert!(max_value > -50.0); // Should have a significant peak
     }
 
+    /// Frequency of the strongest AF bin between `lo` and `hi` Hz
+    fn peak_between(spectrum: &[f32], lo: f32, hi: f32) -> (f32, f32) {
+        let bin_hz = AF_MAX_FREQ / (spectrum.len() - 1) as f32;
+        spectrum
+            .iter()
+            .enumerate()
+            .map(|(i, &db)| (i as f32 * bin_hz, db))
+            .filter(|&(f, _)| f >= lo && f <= hi)
+            .fold((0.0, f32::NEG_INFINITY), |a, b| if b.1 > a.1 { b } else { a })
+    }
+
+    #[test]
+    fn test_af_spectrum_shows_tones() {
+        let rate = 250_000;
+        // 1 kHz voice tone plus a quiet 100 Hz CTCSS tone, for half a second
+        let audio: Vec<f32> = (0..rate / 2)
+            .map(|i| {
+                let t = i as f32 / rate as f32;
+                0.5 * (2.0 * PI * 1000.0 * t).sin() + 0.05 * (2.0 * PI * 100.0 * t).sin()
+            })
+            .collect();
+
+        let mut af = AfSpectrum::new();
+        let spectrum = audio.chunks(16_384).filter_map(|c| af.process(rate, c)).last().unwrap();
+
+        // Bins run from 0 Hz to 5 kHz
+        assert!((spectrum.len() as f32 - 1.0) * af.rate() / 512.0 <= AF_MAX_FREQ);
+        let (freq, db) = peak_between(&spectrum, 500.0, 5000.0);
+        assert!((freq - 1000.0).abs() < 40.0, "voice peak at {} Hz", freq);
+        assert!((db - (-6.0)).abs() < 2.0, "voice peak {} dB", db);
+        let (freq, db) = peak_between(&spectrum, 50.0, 300.0);
+        assert!((freq - 100.0).abs() < 40.0, "CTCSS peak at {} Hz", freq);
+        assert!(db > -35.0 && db < -20.0, "CTCSS peak {} dB", db);
+    }
+
+    #[test]
+    fn test_af_spectrum_restarts_on_rate_change() {
+        let mut af = AfSpectrum::new();
+        // Not quite a full frame at 48 kHz
+        assert!(af.process(48_000, &[0.1; 511 * 4]).is_none());
+        // The partial frame is discarded when the rate changes
+        assert!(af.process(22_050, &[0.1; 2]).is_none());
+        assert!(af.process(22_050, &[0.1; 1022]).is_some());
+    }
+
     #[test]
     fn test_normalize_fft() {
         let data = vec![-100.0, -80.0, -60.0, -40.0, -20.0, 0.0];
@@ -0,0 +1,449 @@
+use crate::types::DemodMode;
+use std::f64::consts::PI;
+
+/// Q values for the two sections of a 4th-order Butterworth filter
+const BUTTERWORTH4_Q: [f64; 2] = [0.541_196_1, 1.306_563];
+
+/// Second-order IIR filter section (RBJ cookbook, direct form I)
+///
+/// Coefficients and state are kept in f64: audio is made at its channel's
+/// rate, up to the IQ sample rate, where low cutoffs put the poles very close
+/// to the unit circle.
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    /// High-pass section with cutoff `cutoff` Hz
+    pub fn highpass(sample_rate: f64, cutoff: f64, q: f64) -> Self {
+        let (cos_w, alpha) = Self::prewarp(sample_rate, cutoff, q);
+        Self::normalized(
+            (1.0 + cos_w) / 2.0,
+            -(1.0 + cos_w),
+            (1.0 + cos_w) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos_w,
+            1.0 - alpha,
+        )
+    }
+
+    /// Low-pass section with cutoff `cutoff` Hz
+    pub fn lowpass(sample_rate: f64, cutoff: f64, q: f64) -> Self {
+        let (cos_w, alpha) = Self::prewarp(sample_rate, cutoff, q);
+        Self::normalized(
+            (1.0 - cos_w) / 2.0,
+            1.0 - cos_w,
+            (1.0 - cos_w) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos_w,
+            1.0 - alpha,
+        )
+    }
+
+    fn prewarp(sample_rate: f64, cutoff: f64, q: f64) -> (f64, f64) {
+        let w = 2.0 * PI * cutoff / sample_rate;
+        (w.cos(), w.sin() / (2.0 * q))
+    }
+
+    fn normalized(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    /// Filter one sample
+    pub fn process(&mut self, input: f32) -> f32 {
+        let x = input as f64;
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y as f32
+    }
+}
+
+/// Cascade of biquad sections
+#[derive(Debug, Clone, Default)]
+pub struct FilterChain {
+    sections: Vec<Biquad>,
+}
+
+impl FilterChain {
+    /// 4th-order Butterworth band limits; either edge may be omitted
+    ///
+    /// A low-pass edge at or above Nyquist is dropped.
+    pub fn band(sample_rate: u32, low_cut: Option<f32>, high_cut: Option<f32>) -> Self {
+        let fs = sample_rate as f64;
+        let mut sections = Vec::new();
+
+        if let Some(cutoff) = low_cut {
+            sections.extend(BUTTERWORTH4_Q.iter().map(|&q| Biquad::highpass(fs, cutoff as f64, q)));
+        }
+        if let Some(cutoff) = high_cut.filter(|&c| (c as f64) < fs * 0.45) {
+            sections.extend(BUTTERWORTH4_Q.iter().map(|&q| Biquad::lowpass(fs, cutoff as f64, q)));
+        }
+
+        Self { sections }
+    }
+
+    /// Filter a buffer in place
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            *sample = self.step(*sample);
+        }
+    }
+
+    /// Filter one sample
+    pub fn step(&mut self, sample: f32) -> f32 {
+        self.sections
+            .iter_mut()
+            .fold(sample, |x, section| section.process(x))
+    }
+}
+
+/// Audio passband for a mode as (high-pass, low-pass) edges in Hz
+pub fn audio_passband(mode: DemodMode) -> (Option<f32>, Option<f32>) {
+    match mode {
+        // Removes CTCSS tones and DC drift
+        DemodMode::FmNarrow => (Some(300.0), None),
+        DemodMode::FmWide => (Some(30.0), Some(15_000.0)),
+        DemodMode::Am => (Some(100.0), Some(5_000.0)),
+        DemodMode::Usb | DemodMode::Lsb => (Some(300.0), Some(2_700.0)),
+        // Narrow window around the usual 400-1000 Hz beat note
+        DemodMode::Cw => (Some(300.0), Some(1_200.0)),
+        // OOK bursts are only monitored, keep the AM audio full-band
+        DemodMode::Ism => (Some(100.0), None),
+        // Data modes need the unshaped demodulator output
+        DemodMode::Aprs
+        | DemodMode::Adsb
+        | DemodMode::Ais
+        | DemodMode::Acars
+        | DemodMode::Apt
+        | DemodMode::Rs41
+        | DemodMode::Raw => (None, None),
+    }
+}
+
+/// Per-mode audio shaping, rebuilt whenever the mode or audio rate changes
+pub struct AudioShaper {
+    mode: DemodMode,
+    sample_rate: u32,
+    chain: FilterChain,
+}
+
+impl AudioShaper {
+    /// Create a shaper for a mode at the given audio sample rate
+    pub fn new(mode: DemodMode, sample_rate: u32) -> Self {
+        let (low_cut, high_cut) = audio_passband(mode);
+        Self {
+            mode,
+            sample_rate,
+            chain: FilterChain::band(sample_rate, low_cut, high_cut),
+        }
+    }
+
+    /// Shape a buffer of audio in place
+    pub fn process(&mut self, mode: DemodMode, sample_rate: u32, audio: &mut [f32]) {
+        if mode != self.mode || sample_rate != self.sample_rate {
+            *self = Self::new(mode, sample_rate);
+        }
+        self.chain.process(audio);
+    }
+}
+
+/// Soft limiter for the final audio, in place of hard clipping at full scale
+///
+/// Samples up to `threshold` pass unchanged; above it the excess is folded
+/// into the remaining headroom with tanh, so peaks round off smoothly and
+/// never reach full scale. Without memory it adds no delay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftLimiter {
+    threshold: f32,
+}
+
+impl SoftLimiter {
+    /// A limiter starting at `threshold` of full scale, kept within 0.1-1.0
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold: threshold.clamp(0.1, 1.0),
+        }
+    }
+
+    /// Limit one sample
+    pub fn limit(&self, sample: f32) -> f32 {
+        let level = sample.abs();
+        if level <= self.threshold {
+            return sample;
+        }
+        let headroom = 1.0 - self.threshold;
+        if headroom <= 0.0 {
+            return sample.clamp(-1.0, 1.0);
+        }
+        let limited = self.threshold + headroom * ((level - self.threshold) / headroom).tanh();
+        limited.copysign(sample)
+    }
+
+    /// Limit a buffer in place
+    pub fn process(&self, audio: &mut [f32]) {
+        for sample in audio.iter_mut() {
+            *sample = self.limit(*sample);
+        }
+    }
+}
+
+/// Level the audio AGC brings the envelope to, of full scale
+const AGC_TARGET: f32 = 0.5;
+/// Most the AGC turns quiet audio up by: 30 dB
+const AGC_MAX_GAIN: f32 = 31.6;
+/// How quickly the AGC turns a louder signal down, in seconds
+const AGC_ATTACK: f32 = 0.005;
+
+/// How quickly the audio AGC lets the level back up after a peak
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AgcSpeed {
+    #[default]
+    Off,
+    Slow,
+    Fast,
+}
+
+impl AgcSpeed {
+    pub fn next(self) -> Self {
+        match self {
+            AgcSpeed::Off => AgcSpeed::Slow,
+            AgcSpeed::Slow => AgcSpeed::Fast,
+            AgcSpeed::Fast => AgcSpeed::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AgcSpeed::Off => "Off",
+            AgcSpeed::Slow => "Slow",
+            AgcSpeed::Fast => "Fast",
+        }
+    }
+
+    /// Parse a speed from its name, e.g. "slow"
+    pub fn parse(name: &str) -> Option<Self> {
+        [AgcSpeed::Off, AgcSpeed::Slow, AgcSpeed::Fast]
+            .into_iter()
+            .find(|speed| speed.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Seconds the envelope takes to fall back, None when off
+    fn decay(self) -> Option<f32> {
+        match self {
+            AgcSpeed::Off => None,
+            AgcSpeed::Slow => Some(1.0),
+            AgcSpeed::Fast => Some(0.2),
+        }
+    }
+}
+
+/// Audio AGC: follows the envelope, quickly up and at the chosen speed
+/// down, and scales the audio so the envelope sits at [`AGC_TARGET`]
+#[derive(Debug, Clone, Default)]
+pub struct AudioAgc {
+    envelope: f32,
+}
+
+impl AudioAgc {
+    /// Level a buffer in place; nothing changes while `speed` is off
+    pub fn process(&mut self, speed: AgcSpeed, sample_rate: u32, audio: &mut [f32]) {
+        let Some(decay) = speed.decay() else {
+            self.envelope = 0.0;
+            return;
+        };
+        let rate = sample_rate.max(1) as f32;
+        let attack = 1.0 - (-1.0 / (AGC_ATTACK * rate)).exp();
+        let decay = 1.0 - (-1.0 / (decay * rate)).exp();
+        let floor = AGC_TARGET / AGC_MAX_GAIN;
+        for sample in audio.iter_mut() {
+            let level = sample.abs();
+            let step = if level > self.envelope { attack } else { decay };
+            self.envelope += (level - self.envelope) * step;
+            *sample *= AGC_TARGET / self.envelope.max(floor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    /// Gain in dB of the mode's shaping at `freq` Hz, measured with a tone
+    fn gain_db(mode: DemodMode, freq: f32) -> f32 {
+        let mut shaper = AudioShaper::new(mode, RATE);
+        let mut tone: Vec<f32> = (0..RATE)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / RATE as f32).sin() * 0.5)
+            .collect();
+        shaper.process(mode, RATE, &mut tone);
+
+        // Skip the filter's settling time
+        let settled = &tone[RATE as usize / 2..];
+        let rms = (settled.iter().map(|x| x * x).sum::<f32>() / settled.len() as f32).sqrt();
+        20.0 * (rms / (0.5 / 2f32.sqrt())).log10()
+    }
+
+    fn assert_passes(mode: DemodMode, freqs: &[f32]) {
+        for &f in freqs {
+            let gain = gain_db(mode, f);
+            assert!(gain > -3.5, "{:?} should pass {} Hz, gain {:.1} dB", mode, f, gain);
+        }
+    }
+
+    fn assert_rejects(mode: DemodMode, freqs: &[f32], min_atten: f32) {
+        for &f in freqs {
+            let gain = gain_db(mode, f);
+            assert!(
+                gain < -min_atten,
+                "{:?} should reject {} Hz, gain {:.1} dB",
+                mode,
+                f,
+                gain
+            );
+        }
+    }
+
+    #[test]
+    fn test_nfm_highpass() {
+        assert_passes(DemodMode::FmNarrow, &[400.0, 1000.0, 3000.0]);
+        // CTCSS tones
+        assert_rejects(DemodMode::FmNarrow, &[67.0, 100.0, 151.4], 12.0);
+    }
+
+    #[test]
+    fn test_ssb_bandpass() {
+        assert_passes(DemodMode::Usb, &[400.0, 1000.0, 2000.0]);
+        assert_rejects(DemodMode::Lsb, &[60.0, 100.0, 6000.0, 10_000.0], 20.0);
+    }
+
+    #[test]
+    fn test_cw_bandpass() {
+        assert_passes(DemodMode::Cw, &[500.0, 700.0, 900.0]);
+        assert_rejects(DemodMode::Cw, &[100.0, 3000.0], 15.0);
+    }
+
+    #[test]
+    fn test_am_bandpass() {
+        assert_passes(DemodMode::Am, &[200.0, 1000.0, 4000.0]);
+        assert_rejects(DemodMode::Am, &[20.0, 12_000.0], 20.0);
+    }
+
+    #[test]
+    fn test_wfm_bandpass() {
+        assert_passes(DemodMode::FmWide, &[60.0, 1000.0, 10_000.0]);
+        assert_rejects(DemodMode::FmWide, &[5.0, 22_000.0], 20.0);
+    }
+
+    #[test]
+    fn test_data_modes_unshaped() {
+        assert!(gain_db(DemodMode::Aprs, 50.0).abs() < 0.1);
+    }
+
+    /// Total harmonic distortion of a 1 kHz tone, from its first 9 harmonics
+    fn thd(audio: &[f32]) -> f32 {
+        let power = |harmonic: f32| {
+            let w = 2.0 * std::f32::consts::PI * 1000.0 * harmonic / RATE as f32;
+            let (re, im) = audio.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, x)| {
+                (re + x * (w * i as f32).cos(), im - x * (w * i as f32).sin())
+            });
+            re * re + im * im
+        };
+        let harmonics: f32 = (2..=10).map(|h| power(h as f32)).sum();
+        (harmonics / power(1.0)).sqrt()
+    }
+
+    #[test]
+    fn test_soft_limiter() {
+        let tone = |amplitude: f32| -> Vec<f32> {
+            (0..RATE / 10)
+                .map(|i| {
+                    amplitude * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / RATE as f32).sin()
+                })
+                .collect()
+        };
+        let limiter = SoftLimiter::new(0.8);
+
+        // Below the threshold nothing changes
+        let quiet = tone(0.75);
+        let mut limited = quiet.clone();
+        limiter.process(&mut limited);
+        assert_eq!(limited, quiet);
+
+        // Overdriven 2x: under full scale, and cleaner than hard clipping
+        let loud = tone(2.0);
+        let mut limited = loud.clone();
+        limiter.process(&mut limited);
+        let clipped: Vec<f32> = loud.iter().map(|x| x.clamp(-1.0, 1.0)).collect();
+        assert!(limited.iter().all(|x| x.abs() < 1.0));
+        let (soft, hard) = (thd(&limited), thd(&clipped));
+        assert!(soft < hard, "soft limiter THD {:.3} vs hard clip {:.3}", soft, hard);
+        assert!(soft < 0.25, "THD {:.3}", soft);
+
+        // Symmetric, and continuous at the threshold
+        assert_eq!(limiter.limit(-1.5), -limiter.limit(1.5));
+        assert!((limiter.limit(0.8001) - 0.8001).abs() < 1e-4);
+        assert_eq!(SoftLimiter::new(1.0).limit(1.5), 1.0);
+    }
+
+    #[test]
+    fn test_rebuild_on_rate_change() {
+        let mut shaper = AudioShaper::new(DemodMode::FmNarrow, 48_000);
+        let mut audio = vec![0.0; 16];
+        shaper.process(DemodMode::FmNarrow, 2_048_000, &mut audio);
+        assert_eq!(shaper.sample_rate, 2_048_000);
+        shaper.process(DemodMode::Usb, 2_048_000, &mut audio);
+        assert_eq!(shaper.mode, DemodMode::Usb);
+    }
+
+    #[test]
+    fn test_agc_levels() {
+        let rate = 48_000;
+        let tone = |amplitude: f32, len: usize| -> Vec<f32> {
+            (0..len)
+                .map(|i| amplitude * (2.0 * PI as f32 * 1_000.0 * i as f32 / rate as f32).sin())
+                .collect()
+        };
+        let peak = |audio: &[f32]| audio.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+
+        // Quiet and loud come out at much the same level
+        let mut agc = AudioAgc::default();
+        let mut quiet = tone(0.05, 48_000);
+        agc.process(AgcSpeed::Fast, rate, &mut quiet);
+        assert!((peak(&quiet[24_000..]) - AGC_TARGET).abs() < 0.05);
+        let mut loud = tone(0.9, 4_800);
+        agc.process(AgcSpeed::Fast, rate, &mut loud);
+        assert!((peak(&loud[2_400..]) - AGC_TARGET).abs() < 0.05);
+
+        // Off leaves the audio alone
+        let mut audio = tone(0.05, 480);
+        agc.process(AgcSpeed::Off, rate, &mut audio);
+        assert_eq!(audio, tone(0.05, 480));
+        assert_eq!(AgcSpeed::parse("SLOW"), Some(AgcSpeed::Slow));
+        assert_eq!(AgcSpeed::Fast.next(), AgcSpeed::Off);
+    }
+}
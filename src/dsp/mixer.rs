@@ -0,0 +1,270 @@
+//! The speaker's mix: the program audio, and now and then something more
+//! urgent over it
+//!
+//! An announcement (the test tone, a SAME alert's beeps, the cue that the
+//! priority channel has come up) plays on top of the program audio, which
+//! is ducked by [`DUCK_DB`] underneath it and comes back up once it ends,
+//! ramping over [`RAMP`] each way so neither clicks. One announcement plays
+//! at a time: a more urgent one cuts off whatever is playing, and a less
+//! urgent one is turned away until it's over.
+//!
+//! The announcement is taken a buffer of program audio at a time, so the
+//! two stay in step. With no program audio reaching the speaker (the
+//! monitor off, a mode without audio, the receiver stalled) it plays alone,
+//! paced by the clock.
+
+use std::time::{Duration, Instant};
+
+/// How far the program audio is turned down under an announcement, in dB
+pub const DUCK_DB: f32 = -18.0;
+/// How long the program audio takes to duck, and to come back
+pub const RAMP: Duration = Duration::from_millis(100);
+/// How far ahead of the clock an announcement playing alone is queued, so
+/// the output doesn't run dry between pushes
+const LEAD: Duration = Duration::from_millis(50);
+
+/// How urgent an announcement is, least first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// The test tone
+    Test,
+    /// The priority channel became active
+    Channel,
+    /// A SAME weather alert
+    Alert,
+}
+
+/// An announcement and how much of it has been played
+#[derive(Debug, Clone)]
+struct Announcement {
+    priority: Priority,
+    samples: Vec<f32>,
+    played: usize,
+}
+
+/// Ducks the program audio under announcements and mixes them in
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    rate: u32,
+    /// The program audio's gain now, 1 when not ducked
+    gain: f32,
+    /// The gain under an announcement
+    ducked: f32,
+    /// Gain change per sample while ramping
+    step: f32,
+    playing: Option<Announcement>,
+    /// While playing alone: when that started, and samples sent since
+    alone: Option<(Instant, u64)>,
+}
+
+impl Mixer {
+    /// A mixer for audio at `rate` Hz
+    pub fn new(rate: u32) -> Self {
+        let ducked = 10f32.powf(DUCK_DB / 20.0);
+        let ramp = (RAMP.as_secs_f32() * rate as f32).max(1.0);
+        Self {
+            rate,
+            gain: 1.0,
+            ducked,
+            step: (1.0 - ducked) / ramp,
+            playing: None,
+            alone: None,
+        }
+    }
+
+    /// Play `samples` over the program audio unless something more urgent
+    /// is playing; whether it will be
+    ///
+    /// One as urgent as what is playing replaces it, so a repeated alert
+    /// starts again.
+    pub fn announce(&mut self, priority: Priority, samples: Vec<f32>) -> bool {
+        if samples.is_empty() {
+            return false;
+        }
+        if self.playing().is_some_and(|playing| playing > priority) {
+            log::debug!("{:?} announcement held off by a more urgent one", priority);
+            return false;
+        }
+        self.playing = Some(Announcement {
+            priority,
+            samples,
+            played: 0,
+        });
+        true
+    }
+
+    /// How urgent the announcement playing is, if one is
+    pub fn playing(&self) -> Option<Priority> {
+        self.playing
+            .as_ref()
+            .map(|announcement| announcement.priority)
+    }
+
+    /// Duck `program` as needed and mix the announcement into it
+    pub fn mix(&mut self, program: &mut [f32]) {
+        self.alone = None;
+        self.overlay(program);
+    }
+
+    /// While no program audio is being mixed: the announcement due by
+    /// `now` and not yet sent, on its own; nothing once it's over
+    pub fn alone(&mut self, now: Instant) -> Vec<f32> {
+        let Some(announcement) = &self.playing else {
+            self.alone = None;
+            return Vec::new();
+        };
+        let left = (announcement.samples.len() - announcement.played) as u64;
+        let (started, sent) = self.alone.get_or_insert((now, 0));
+        let elapsed = now.saturating_duration_since(*started) + LEAD;
+        let due = (elapsed.as_secs_f64() * self.rate as f64) as u64;
+        let count = due.saturating_sub(*sent).min(left);
+        *sent += count;
+        let mut samples = vec![0.0; count as usize];
+        self.overlay(&mut samples);
+        samples
+    }
+
+    fn overlay(&mut self, program: &mut [f32]) {
+        for sample in program {
+            let target = if self.playing.is_some() {
+                self.ducked
+            } else {
+                1.0
+            };
+            self.gain = if self.gain > target {
+                (self.gain - self.step).max(target)
+            } else {
+                (self.gain + self.step).min(target)
+            };
+            *sample *= self.gain;
+
+            if let Some(announcement) = &mut self.playing {
+                *sample += announcement
+                    .samples
+                    .get(announcement.played)
+                    .unwrap_or(&0.0);
+                announcement.played += 1;
+                if announcement.played >= announcement.samples.len() {
+                    self.playing = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+    const RAMP_SAMPLES: usize = 4_800;
+
+    /// Program audio of `len` ones mixed in uneven pieces, so what comes
+    /// out is the gain at each sample plus the announcement
+    fn mix_ones(mixer: &mut Mixer, len: usize) -> Vec<f32> {
+        let mut out = Vec::new();
+        let mut sizes = [1, 333, 4_096, 17].iter().cycle();
+        while out.len() < len {
+            let mut piece = vec![1.0; (*sizes.next().unwrap()).min(len - out.len())];
+            mixer.mix(&mut piece);
+            out.extend(piece);
+        }
+        out
+    }
+
+    #[test]
+    fn test_ramp_continuity() {
+        let mut mixer = Mixer::new(RATE);
+        let ducked = 10f32.powf(DUCK_DB / 20.0);
+        // Half a second of silence over a second of program
+        assert!(mixer.announce(Priority::Test, vec![0.0; 24_000]));
+        let gains = mix_ones(&mut mixer, 48_000);
+
+        // Down over the ramp, held, then back up over the ramp
+        assert!((gains[0] - 1.0).abs() < 1e-3);
+        assert!((gains[RAMP_SAMPLES] - ducked).abs() < 1e-3);
+        assert!(gains[RAMP_SAMPLES..24_000]
+            .iter()
+            .all(|&gain| (gain - ducked).abs() < 1e-3));
+        assert!((gains[24_000 + RAMP_SAMPLES] - 1.0).abs() < 1e-3);
+        assert_eq!(gains.last(), Some(&1.0));
+        assert_eq!(mixer.playing(), None);
+
+        // No step anywhere bigger than the ramp's, where pieces meet too
+        let largest = gains
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0f32, f32::max);
+        assert!(largest <= (1.0 - ducked) / RAMP_SAMPLES as f32 + 1e-6);
+
+        // Pieces or not, the same
+        let mut whole = Mixer::new(RATE);
+        whole.announce(Priority::Test, vec![0.0; 24_000]);
+        let mut program = vec![1.0; 48_000];
+        whole.mix(&mut program);
+        assert!(program
+            .iter()
+            .zip(&gains)
+            .all(|(a, b)| (a - b).abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_announcement_mixed_in() {
+        let mut mixer = Mixer::new(RATE);
+        mixer.announce(Priority::Alert, vec![0.5; 3]);
+        let mut program = [0.25; 5];
+        mixer.mix(&mut program);
+        // Added to the program, whose gain has only begun to fall
+        assert!(program[..3].iter().all(|&x| (x - 0.75).abs() < 1e-3));
+        assert!(program[3..].iter().all(|&x| (x - 0.25).abs() < 1e-3));
+        assert_eq!(mixer.playing(), None);
+    }
+
+    #[test]
+    fn test_priority_arbitration() {
+        let mut mixer = Mixer::new(RATE);
+        assert!(mixer.announce(Priority::Test, vec![0.1; 1_000]));
+        assert_eq!(mixer.playing(), Some(Priority::Test));
+        // More urgent cuts in
+        assert!(mixer.announce(Priority::Channel, vec![0.2; 1_000]));
+        assert_eq!(mixer.playing(), Some(Priority::Channel));
+        // Less urgent waits its turn, and isn't heard
+        assert!(!mixer.announce(Priority::Test, vec![0.1; 1_000]));
+        assert!(mixer.announce(Priority::Alert, vec![0.3; 1_000]));
+        assert!(!mixer.announce(Priority::Channel, vec![0.2; 1_000]));
+        let mut silence = [0.0; 500];
+        mixer.mix(&mut silence);
+        assert!(silence.iter().all(|&x| x == 0.3));
+        // As urgent starts over
+        assert!(mixer.announce(Priority::Alert, vec![0.4; 1_000]));
+        let mut silence = [0.0; 1_000];
+        mixer.mix(&mut silence);
+        assert!(silence.iter().all(|&x| x == 0.4));
+
+        // Once it's over, anything may play
+        assert_eq!(mixer.playing(), None);
+        assert!(mixer.announce(Priority::Test, vec![0.1; 1_000]));
+    }
+
+    #[test]
+    fn test_alone_paced_by_clock() {
+        let start = Instant::now();
+        let mut mixer = Mixer::new(RATE);
+        assert!(mixer.alone(start).is_empty());
+        mixer.announce(Priority::Test, vec![0.5; 96_000]);
+        // The lead goes out straight away
+        assert_eq!(mixer.alone(start), vec![0.5; 2_400]);
+        assert!(mixer.alone(start).is_empty());
+        assert_eq!(mixer.alone(start + Duration::from_millis(100)).len(), 4_800);
+        // Taken with program audio, then alone again from there
+        mixer.mix(&mut [0.0; 4_800]);
+        let later = start + Duration::from_secs(1);
+        assert_eq!(mixer.alone(later).len(), 2_400);
+        assert_eq!(
+            mixer.alone(later + Duration::from_secs(5)).len(),
+            96_000 - 14_400
+        );
+        assert_eq!(mixer.playing(), None);
+        assert!(mixer.alone(later + Duration::from_secs(6)).is_empty());
+    }
+}
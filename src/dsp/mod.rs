@@ -1,11 +1,19 @@
+pub mod channelizer;
+pub mod dc;
 pub mod decoder;
 pub mod demod;
+pub mod detect;
 pub mod fft;
 pub mod filters;
+pub mod mixer;
+pub mod noise;
 pub mod resampler;
 pub mod thread;
+pub mod tone;
 
 // Re-export commonly used types
-pub use fft::{normalize_fft, FftProcessor};
-pub use resampler::Resampler;
-pub use thread::start_dsp_thread;
+pub use channelizer::Channelizer;
+pub use detect::{ActivityDetector, ActivityTable};
+pub use fft::{normalize_fft, AfSpectrum, FftProcessor, AF_MAX_FREQ};
+pub use resampler::{Interpolation, Resampler};
+pub use thread::{start_dsp_thread, AudioSink, DspOutputs};
@@ -0,0 +1,338 @@
+//! Noise reduction for voice audio by spectral subtraction
+//!
+//! Audio is cut into half-overlapping frames under a square-root Hann
+//! window, which windowed again after the inverse FFT adds back up to the
+//! input exactly. Each frame's bins are scaled down by how much of their
+//! power the noise estimate accounts for, oversubtracted and kept above a
+//! floor so the residue stays hiss rather than warbling. The noise estimate
+//! follows frames heard while the squelch is closed or the audio is quiet.
+//!
+//! Frames are the longest power of two within 20 ms at the audio rate, and
+//! the delay added is one frame.
+
+use crate::types::DemodMode;
+use num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Longest frame, in seconds
+const FRAME_SECS: f32 = 0.02;
+/// Shortest frame, for very low audio rates
+const MIN_FRAME: usize = 64;
+/// Weight of each quiet frame in the noise estimate
+const NOISE_ALPHA: f32 = 0.1;
+/// A frame with less than this multiple of the estimated noise power is
+/// taken as quiet even with the squelch open
+const QUIET_RATIO: f32 = 2.0;
+
+/// How hard noise is reduced
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoiseReduction {
+    #[default]
+    Off,
+    Low,
+    Medium,
+    High,
+}
+
+impl NoiseReduction {
+    pub fn next(self) -> Self {
+        match self {
+            NoiseReduction::Off => NoiseReduction::Low,
+            NoiseReduction::Low => NoiseReduction::Medium,
+            NoiseReduction::Medium => NoiseReduction::High,
+            NoiseReduction::High => NoiseReduction::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NoiseReduction::Off => "Off",
+            NoiseReduction::Low => "Low",
+            NoiseReduction::Medium => "Medium",
+            NoiseReduction::High => "High",
+        }
+    }
+
+    /// Oversubtraction factor and least gain, None when off
+    fn strength(self) -> Option<(f32, f32)> {
+        match self {
+            NoiseReduction::Off => None,
+            NoiseReduction::Low => Some((1.0, 0.3)),
+            NoiseReduction::Medium => Some((2.0, 0.15)),
+            NoiseReduction::High => Some((3.0, 0.08)),
+        }
+    }
+
+    /// Whether noise reduction is used in `mode`; data modes need the
+    /// demodulator's output as it is
+    pub fn applies_to(mode: DemodMode) -> bool {
+        matches!(
+            mode,
+            DemodMode::FmNarrow
+                | DemodMode::FmWide
+                | DemodMode::Am
+                | DemodMode::Usb
+                | DemodMode::Lsb
+                | DemodMode::Cw
+        )
+    }
+}
+
+/// Spectral subtraction, rebuilt whenever the audio rate changes
+pub struct NoiseReducer {
+    sample_rate: u32,
+    size: usize,
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    /// Square-root periodic Hann, for analysis and synthesis
+    window: Vec<f32>,
+    /// The last frame's second half followed by new audio
+    input: Vec<f32>,
+    /// Overlap-add of processed frames not yet complete
+    overlap: Vec<f32>,
+    /// Finished audio, starting with half a frame of silence so there is
+    /// always enough to hand back
+    output: VecDeque<f32>,
+    /// Noise power per bin, None until a frame has been heard
+    noise: Option<Vec<f32>>,
+    spectrum: Vec<Complex<f32>>,
+    /// Gain forced to one, for checking reconstruction
+    #[cfg(test)]
+    unity: bool,
+}
+
+impl NoiseReducer {
+    /// A reducer for audio at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        let size = Self::frame_size(sample_rate);
+        let mut planner = FftPlanner::new();
+        let window = (0..size)
+            .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos()).sqrt())
+            .collect();
+        let hop = size / 2;
+        Self {
+            sample_rate,
+            size,
+            forward: planner.plan_fft_forward(size),
+            inverse: planner.plan_fft_inverse(size),
+            window,
+            input: vec![0.0; hop],
+            overlap: vec![0.0; size],
+            output: VecDeque::from(vec![0.0; hop]),
+            noise: None,
+            spectrum: vec![Complex::default(); size],
+            #[cfg(test)]
+            unity: false,
+        }
+    }
+
+    /// Largest power of two within [`FRAME_SECS`] at `sample_rate`
+    fn frame_size(sample_rate: u32) -> usize {
+        let longest = (sample_rate as f32 * FRAME_SECS) as usize;
+        let size = if longest.is_power_of_two() {
+            longest
+        } else {
+            longest.next_power_of_two() / 2
+        };
+        size.max(MIN_FRAME)
+    }
+
+    /// Reduce the noise in `audio` in place at `level`, learning the noise
+    /// from it while `quiet` (the squelch closed); off, it passes through
+    pub fn process(
+        &mut self,
+        level: NoiseReduction,
+        sample_rate: u32,
+        quiet: bool,
+        audio: &mut [f32],
+    ) {
+        if sample_rate != self.sample_rate {
+            *self = Self::new(sample_rate);
+        }
+        let Some((oversubtraction, floor)) = level.strength() else {
+            return;
+        };
+        let hop = self.size / 2;
+        for chunk in audio.chunks_mut(hop) {
+            self.input.extend_from_slice(chunk);
+            while self.input.len() >= self.size {
+                self.frame(oversubtraction, floor, quiet);
+                self.input.drain(..hop);
+            }
+            for sample in chunk.iter_mut() {
+                *sample = self.output.pop_front().unwrap_or(0.0);
+            }
+        }
+    }
+
+    /// Process the frame at the start of `input`, finishing half a frame
+    fn frame(&mut self, oversubtraction: f32, floor: f32, quiet: bool) {
+        let size = self.size;
+        let hop = size / 2;
+        for ((bin, &x), &w) in self.spectrum.iter_mut().zip(&self.input).zip(&self.window) {
+            *bin = Complex::new(x * w, 0.0);
+        }
+        self.forward.process(&mut self.spectrum);
+
+        let power: Vec<f32> = self.spectrum.iter().map(|bin| bin.norm_sqr()).collect();
+        let total: f32 = power.iter().sum();
+        let noise = self.noise.get_or_insert_with(|| power.clone());
+        let noise_total: f32 = noise.iter().sum();
+        if quiet || total < QUIET_RATIO * noise_total {
+            for (estimate, &p) in noise.iter_mut().zip(&power) {
+                *estimate += NOISE_ALPHA * (p - *estimate);
+            }
+        }
+
+        #[cfg(test)]
+        let unity = self.unity;
+        #[cfg(not(test))]
+        let unity = false;
+        if !unity {
+            for ((bin, &p), &n) in self.spectrum.iter_mut().zip(&power).zip(noise.iter()) {
+                let gain = (1.0 - oversubtraction * n / p.max(1e-20))
+                    .max(floor * floor)
+                    .sqrt();
+                *bin *= gain;
+            }
+        }
+
+        self.inverse.process(&mut self.spectrum);
+        let scale = 1.0 / size as f32;
+        for ((acc, bin), &w) in self
+            .overlap
+            .iter_mut()
+            .zip(&self.spectrum)
+            .zip(&self.window)
+        {
+            *acc += bin.re * scale * w;
+        }
+        self.output.extend(self.overlap.drain(..hop));
+        self.overlap.resize(size, 0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    /// Deterministic white noise, -1 to 1
+    fn noise(len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    /// Speech-like: a few formant tones, syllables switching on and off
+    fn speech(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                let syllable = if (t * 4.0).fract() < 0.6 { 1.0 } else { 0.0 };
+                let voice: f32 = [(300.0, 0.5), (900.0, 0.3), (2200.0, 0.15)]
+                    .iter()
+                    .map(|&(f, a)| a * (2.0 * PI * f * t).sin())
+                    .sum();
+                0.5 * syllable * voice
+            })
+            .collect()
+    }
+
+    fn snr_db(clean: &[f32], audio: &[f32]) -> f32 {
+        let signal: f32 = clean.iter().map(|x| x * x).sum();
+        let error: f32 = clean
+            .iter()
+            .zip(audio)
+            .map(|(c, a)| (c - a) * (c - a))
+            .sum();
+        10.0 * (signal / error).log10()
+    }
+
+    #[test]
+    fn test_frame_size() {
+        assert_eq!(NoiseReducer::frame_size(48_000), 512);
+        assert_eq!(NoiseReducer::frame_size(2_048_000), 32_768);
+        assert_eq!(NoiseReducer::frame_size(1000), MIN_FRAME);
+        // The delay stays within 30 ms
+        for rate in [8_000, 48_000, 250_000, 1_024_000, 2_048_000, 2_400_000] {
+            let delay = NoiseReducer::new(rate).size as f32 / rate as f32;
+            assert!(delay <= 0.03, "{} s at {}", delay, rate);
+        }
+    }
+
+    #[test]
+    fn test_unity_gain_reconstructs() {
+        let input = noise(10_000, 1);
+        let mut reducer = NoiseReducer::new(RATE);
+        reducer.unity = true;
+        let mut audio = input.clone();
+        // Uneven buffers, as the DSP thread hands them over
+        let mut start = 0;
+        for len in [100, 1_000, 37, 5_000, 3_863] {
+            reducer.process(
+                NoiseReduction::High,
+                RATE,
+                false,
+                &mut audio[start..start + len],
+            );
+            start += len;
+        }
+        // A whole frame behind
+        let delay = reducer.size;
+        assert!(audio[..delay].iter().all(|&x| x.abs() < 1e-6));
+        for (out, x) in audio[delay..].iter().zip(&input) {
+            assert!((out - x).abs() < 1e-4, "{} vs {}", out, x);
+        }
+    }
+
+    #[test]
+    fn test_snr_improves() {
+        let len = RATE as usize * 4;
+        let clean = speech(len);
+        let hiss: Vec<f32> = noise(len, 7).iter().map(|x| x * 0.15).collect();
+        let mixed: Vec<f32> = clean.iter().zip(&hiss).map(|(c, n)| c + n).collect();
+
+        // A second of hiss alone with the squelch closed, then the mixture
+        let mut reducer = NoiseReducer::new(RATE);
+        let mut learn = noise(RATE as usize, 3)
+            .iter()
+            .map(|x| x * 0.15)
+            .collect::<Vec<_>>();
+        reducer.process(NoiseReduction::Medium, RATE, true, &mut learn);
+        let mut audio = mixed.clone();
+        reducer.process(NoiseReduction::Medium, RATE, false, &mut audio);
+
+        // Line the output up with the input, past the first second
+        let delay = reducer.size;
+        let skip = RATE as usize;
+        let before = snr_db(&clean[skip..len - delay], &mixed[skip..len - delay]);
+        let after = snr_db(&clean[skip..len - delay], &audio[skip + delay..]);
+        assert!(
+            after > before + 3.0,
+            "SNR {:.1} dB -> {:.1} dB",
+            before,
+            after
+        );
+    }
+
+    #[test]
+    fn test_off_passes_through() {
+        let input = noise(1_000, 5);
+        let mut audio = input.clone();
+        let mut reducer = NoiseReducer::new(RATE);
+        reducer.process(NoiseReduction::Off, RATE, false, &mut audio);
+        assert_eq!(audio, input);
+        assert!(!NoiseReduction::applies_to(DemodMode::Aprs));
+        assert!(NoiseReduction::applies_to(DemodMode::Usb));
+        assert_eq!(NoiseReduction::High.next(), NoiseReduction::Off);
+    }
+}
@@ -1,85 +1,416 @@
-/// Simple linear interpolation resampler
+//! Sample rate conversion for the audio
+//!
+//! The audio is demodulated at the rate its channel was decimated to and
+//! brought to [`AUDIO_RATE`] by [`ChannelRate`]. The network streams and
+//! pipes carry it as it is; a speaker running at another rate gets it
+//! through [`SpeakerRate`] so it doesn't play pitch-shifted.
+//!
+//! Conversion is by a polyphase filter. The ratio of the rates is reduced
+//! to L/M and a windowed-sinc low-pass is designed as if for the input
+//! upsampled by L, then split into L banks of taps, one per phase; each
+//! output sample is one bank's dot product with the latest input. The
+//! low-pass cuts off just below half the lower of the two rates, keeping
+//! images and aliases some 80 dB down. Linear interpolation is still there
+//! through [`Interpolation::Linear`] for platforms where the filter costs
+//! too much, but tones towards the top of the band alias audibly with it.
+
+use crate::audio::latency::AUDIO_RATE;
+use std::borrow::Cow;
+use std::f64::consts::{PI, TAU};
+
+/// Attenuation the filter is designed for beyond its passband, dB
+const STOPBAND_DB: f64 = 80.0;
+/// Where the passband ends, as a share of the lower rate
+const PASSBAND_EDGE: f64 = 0.45;
+/// Where the stopband starts, as a share of the lower rate
+const STOPBAND_EDGE: f64 = 0.5;
+/// Most phases a ratio can be given banks for; rates whose ratio needs
+/// more are interpolated linearly
+const MAX_PHASES: usize = 1024;
+/// Most times over the audio rate a channel is filtered down from; the
+/// taps grow with the ratio, so a channel left at the capture's full rate
+/// is interpolated linearly
+const MAX_FILTERED_RATIO: u32 = 16;
+
+/// How the resampler works out samples between the input's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Straight lines between neighbouring samples: cheap, but aliases
+    Linear,
+    /// A windowed-sinc polyphase filter
+    #[default]
+    Polyphase,
+}
+
+/// Sample rate converter, keeping its state from one buffer to the next
 pub struct Resampler {
     /// Input sample rate
     input_rate: u32,
     /// Output sample rate
     output_rate: u32,
     /// Resampling ratio (output / input)
-    ratio: f32,
-    /// Accumulated phase
-    phase: f32,
+    ratio: f64,
+    /// As asked for; a ratio without banks falls back to linear
+    interpolation: Interpolation,
+    kernel: Kernel,
+}
+
+enum Kernel {
+    Linear(Linear),
+    Polyphase(Polyphase),
+}
+
+impl Kernel {
+    fn new(input_rate: u32, output_rate: u32, interpolation: Interpolation) -> Self {
+        if interpolation == Interpolation::Polyphase {
+            match Polyphase::new(input_rate, output_rate) {
+                Some(polyphase) => return Kernel::Polyphase(polyphase),
+                None => log::debug!(
+                    "No filter banks for {} Hz to {} Hz, interpolating linearly",
+                    input_rate,
+                    output_rate
+                ),
+            }
+        }
+        Kernel::Linear(Linear::default())
+    }
+}
+
+/// Linear interpolation's state
+#[derive(Default)]
+struct Linear {
+    /// Position of the next output sample, in input samples from `last`
+    phase: f64,
+    /// Last input sample of the previous buffer, interpolated from into
+    /// the next one
+    last: Option<f32>,
+}
+
+impl Linear {
+    /// The last sample of each buffer is kept for the next, so buffers of
+    /// any size join up without a skip.
+    fn process(&mut self, ratio: f64, input: &[f32], output: &mut Vec<f32>) {
+        // The previous buffer's last sample, then this one
+        let offset = usize::from(self.last.is_some());
+        let at = |index: usize| match (index, self.last) {
+            (0, Some(last)) => last,
+            _ => input[index - offset],
+        };
+        let len = input.len() + offset;
+
+        let step = 1.0 / ratio;
+        let mut pos = self.phase;
+        while pos < (len - 1) as f64 {
+            let idx = pos as usize;
+            let frac = (pos - idx as f64) as f32;
+
+            // Linear interpolation
+            output.push(at(idx) * (1.0 - frac) + at(idx + 1) * frac);
+
+            pos += step;
+        }
+
+        // Carry on from the last sample next time
+        self.phase = pos - (len - 1) as f64;
+        self.last = input.last().copied();
+    }
+}
+
+/// The filter banks for a rational ratio, and the input still needed
+struct Polyphase {
+    /// Phases per input sample (L)
+    up: usize,
+    /// Phases per output sample (M)
+    down: usize,
+    /// Taps per bank
+    taps: usize,
+    /// One bank per phase, oldest input's tap first, each summing to one
+    banks: Vec<Vec<f32>>,
+    /// The last `taps - 1` inputs, then those being worked through
+    buffer: Vec<f32>,
+    /// Where the next output falls, in phases from the first new input
+    position: usize,
+}
+
+impl Polyphase {
+    /// Banks for `input_rate` to `output_rate`; None if their ratio needs
+    /// too many
+    fn new(input_rate: u32, output_rate: u32) -> Option<Self> {
+        let divisor = gcd(input_rate, output_rate);
+        if divisor == 0 {
+            return None;
+        }
+        let up = (output_rate / divisor) as usize;
+        let down = (input_rate / divisor) as usize;
+        if up == 0 || down == 0 || up > MAX_PHASES {
+            return None;
+        }
+
+        // Frequencies in cycles per input sample
+        let lower = input_rate.min(output_rate) as f64 / input_rate as f64;
+        let transition = (STOPBAND_EDGE - PASSBAND_EDGE) * lower;
+        let cutoff = (STOPBAND_EDGE + PASSBAND_EDGE) / 2.0 * lower;
+        // Kaiser's estimates of the span and shape the attenuation needs
+        let span = (STOPBAND_DB - 7.95) / (2.285 * TAU * transition);
+        let beta = 0.1102 * (STOPBAND_DB - 8.7);
+        let taps = span.ceil() as usize + 1;
+
+        let length = up * taps;
+        let middle = (length - 1) as f64 / 2.0;
+        let prototype: Vec<f64> = (0..length)
+            .map(|i| {
+                let time = (i as f64 - middle) / up as f64;
+                let window = 1.0 - ((i as f64 - middle) / middle).powi(2);
+                sinc(2.0 * cutoff * time) * bessel_i0(beta * window.max(0.0).sqrt())
+            })
+            .collect();
+        let banks = (0..up)
+            .map(|phase| {
+                let mut bank: Vec<f64> = prototype[phase..].iter().step_by(up).copied().collect();
+                bank.reverse();
+                let sum: f64 = bank.iter().sum();
+                bank.iter().map(|tap| (tap / sum) as f32).collect()
+            })
+            .collect();
+
+        Some(Self {
+            up,
+            down,
+            taps,
+            banks,
+            buffer: vec![0.0; taps - 1],
+            position: 0,
+        })
+    }
+
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        self.buffer.extend_from_slice(input);
+        let end = input.len() * self.up;
+        while self.position < end {
+            let (newest, phase) = (self.position / self.up, self.position % self.up);
+            let window = &self.buffer[newest..newest + self.taps];
+            let sample: f32 = self.banks[phase]
+                .iter()
+                .zip(window)
+                .map(|(tap, x)| tap * x)
+                .sum();
+            output.push(sample);
+            self.position += self.down;
+        }
+        self.position -= end;
+        self.buffer.drain(..input.len());
+    }
+
+    fn reset(&mut self) {
+        self.buffer.iter_mut().for_each(|x| *x = 0.0);
+        self.position = 0;
+    }
+
+    /// How far the output lags the input, in input samples
+    fn delay(&self) -> f64 {
+        (self.up * self.taps - 1) as f64 / (2 * self.up) as f64
+    }
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// The modified Bessel function of the first kind, order zero, which
+/// shapes the Kaiser window
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
 }
 
 impl Resampler {
-    /// Create a new resampler
+    /// Create a new resampler, filtering with polyphase banks
     pub fn new(input_rate: u32, output_rate: u32) -> Self {
-        let ratio = output_rate as f32 / input_rate as f32;
+        Self::with_interpolation(input_rate, output_rate, Interpolation::Polyphase)
+    }
 
+    /// Create a new resampler interpolating as given
+    pub fn with_interpolation(
+        input_rate: u32,
+        output_rate: u32,
+        interpolation: Interpolation,
+    ) -> Self {
         Self {
             input_rate,
             output_rate,
-            ratio,
-            phase: 0.0,
+            ratio: output_rate as f64 / input_rate.max(1) as f64,
+            interpolation,
+            kernel: Kernel::new(input_rate, output_rate, interpolation),
         }
     }
 
     /// Resample audio samples
     ///
-    /// Uses linear interpolation for simplicity.
-    /// For production use, consider using a proper polyphase filter.
+    /// State is kept from one call to the next, so buffers of any size
+    /// join up as if they had been one.
     pub fn resample(&mut self, input: &[f32]) -> Vec<f32> {
         if input.is_empty() {
             return vec![];
         }
-
-        // Calculate expected output size
-        let output_len = (input.len() as f32 * self.ratio) as usize;
-        let mut output = Vec::with_capacity(output_len);
-
-        let mut pos = self.phase;
-
-        while pos < input.len() as f32 - 1.0 {
-            let idx = pos as usize;
-            let frac = pos - idx as f32;
-
-            // Linear interpolation
-            let sample = input[idx] * (1.0 - frac) + input[idx + 1] * frac;
-            output.push(sample);
-
-            pos += 1.0 / self.ratio;
-        }
-
-        // Save phase for next call (for continuity between buffers)
-        self.phase = pos - (input.len() - 1) as f32;
-        if self.phase < 0.0 {
-            self.phase = 0.0;
+        let mut output = Vec::with_capacity((input.len() as f64 * self.ratio) as usize + 1);
+        match &mut self.kernel {
+            Kernel::Linear(linear) => linear.process(self.ratio, input, &mut output),
+            Kernel::Polyphase(polyphase) => polyphase.process(input, &mut output),
         }
-
         output
     }
 
     /// Reset the resampler state
     pub fn reset(&mut self) {
-        self.phase = 0.0;
+        match &mut self.kernel {
+            Kernel::Linear(linear) => *linear = Linear::default(),
+            Kernel::Polyphase(polyphase) => polyphase.reset(),
+        }
     }
 
     /// Get the resampling ratio
     pub fn ratio(&self) -> f32 {
-        self.ratio
+        self.ratio as f32
+    }
+
+    /// How far the output lags the input, in input samples
+    pub fn delay(&self) -> f64 {
+        match &self.kernel {
+            Kernel::Linear(_) => 0.0,
+            Kernel::Polyphase(polyphase) => polyphase.delay(),
+        }
     }
 
     /// Set new sample rates
     pub fn set_rates(&mut self, input_rate: u32, output_rate: u32) {
-        self.input_rate = input_rate;
-        self.output_rate = output_rate;
-        self.ratio = output_rate as f32 / input_rate as f32;
-        self.reset();
+        *self = Self::with_interpolation(input_rate, output_rate, self.interpolation);
+    }
+}
+
+/// Audio at [`AUDIO_RATE`] for a speaker at whatever rate its device runs,
+/// which may change when the output is reopened on another device
+pub struct SpeakerRate {
+    resampler: Resampler,
+}
+
+impl Default for SpeakerRate {
+    fn default() -> Self {
+        Self {
+            resampler: Resampler::new(AUDIO_RATE, AUDIO_RATE),
+        }
+    }
+}
+
+impl SpeakerRate {
+    /// `audio` for a speaker at `rate` Hz; as it is when that is the audio
+    /// rate
+    pub fn convert<'a>(&mut self, rate: u32, audio: &'a [f32]) -> Cow<'a, [f32]> {
+        if rate != self.resampler.output_rate {
+            self.resampler.set_rates(AUDIO_RATE, rate);
+        }
+        if rate == AUDIO_RATE || rate == 0 {
+            Cow::Borrowed(audio)
+        } else {
+            Cow::Owned(self.resampler.resample(audio))
+        }
+    }
+}
+
+/// A channel's audio, made at whatever rate its channelizer left it, at
+/// [`AUDIO_RATE`]
+pub struct ChannelRate {
+    resampler: Resampler,
+}
+
+impl Default for ChannelRate {
+    fn default() -> Self {
+        Self {
+            resampler: Resampler::new(AUDIO_RATE, AUDIO_RATE),
+        }
+    }
+}
+
+impl ChannelRate {
+    /// `audio` made at `rate` Hz, at the audio rate; as it is when that is
+    /// the audio rate
+    pub fn convert(&mut self, rate: u32, audio: Vec<f32>) -> Vec<f32> {
+        if rate != self.resampler.input_rate {
+            let interpolation = if rate > MAX_FILTERED_RATIO * AUDIO_RATE {
+                Interpolation::Linear
+            } else {
+                Interpolation::Polyphase
+            };
+            self.resampler = Resampler::with_interpolation(rate, AUDIO_RATE, interpolation);
+        }
+        if rate == AUDIO_RATE || rate == 0 {
+            audio
+        } else {
+            self.resampler.resample(&audio)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dsp::tone::ToneGenerator;
+    use std::time::Instant;
+
+    /// Outputs left out of measurements while the filter fills
+    const SETTLE: usize = 512;
+
+    /// Power left after taking out the best fit of a tone at `frequency`,
+    /// in dB relative to that tone
+    fn spurious_db(signal: &[f32], frequency: f64, rate: u32) -> f64 {
+        let step = TAU * frequency / rate as f64;
+        let basis = |n: usize| (step * n as f64).sin_cos();
+        let (mut ss, mut cc, mut sc, mut ys, mut yc) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (n, &y) in signal.iter().enumerate() {
+            let (s, c) = basis(n);
+            ss += s * s;
+            cc += c * c;
+            sc += s * c;
+            ys += y as f64 * s;
+            yc += y as f64 * c;
+        }
+        let det = ss * cc - sc * sc;
+        let a = (ys * cc - yc * sc) / det;
+        let b = (yc * ss - ys * sc) / det;
+        let (mut tone, mut rest) = (0.0, 0.0);
+        for (n, &y) in signal.iter().enumerate() {
+            let (s, c) = basis(n);
+            let fit = a * s + b * c;
+            tone += fit * fit;
+            rest += (y as f64 - fit).powi(2);
+        }
+        10.0 * (rest / tone).log10()
+    }
+
+    /// `len` samples of a tone at `frequency` through `resampler`, after
+    /// the filter has settled
+    fn through(resampler: &mut Resampler, frequency: f64, len: usize) -> Vec<f32> {
+        resampler.reset();
+        let input = ToneGenerator::new(frequency as f32, resampler.input_rate, 0.0).generate(len);
+        resampler.resample(&input).split_off(SETTLE)
+    }
 
     #[test]
     fn test_resampler_downsample() {
@@ -111,7 +442,236 @@ mod tests {
         let _ = resampler.resample(&input);
 
         resampler.reset();
-        assert_eq!(resampler.phase, 0.0);
+        // As if new
+        let input: Vec<f32> = (0..100).map(|i| (i as f32 * 0.1).sin()).collect();
+        let fresh = Resampler::new(48000, 44100).resample(&input);
+        assert_eq!(resampler.resample(&input), fresh);
+
+        let mut linear = Resampler::with_interpolation(48000, 44100, Interpolation::Linear);
+        let fresh = linear.resample(&input);
+        linear.reset();
+        assert_eq!(linear.resample(&input), fresh);
+    }
+
+    #[test]
+    fn test_image_rejection() {
+        // Tones swept across the band, each left alone once it has been
+        // converted either way
+        for (from, to) in [(48_000, 44_100), (44_100, 48_000)] {
+            let mut resampler = Resampler::new(from, to);
+            for frequency in (500..20_000).step_by(1500) {
+                let output = through(&mut resampler, frequency as f64, 8192);
+                let spurious = spurious_db(&output, frequency as f64, to);
+                assert!(
+                    spurious < -60.0,
+                    "{} to {}, {} Hz: {:.1} dB",
+                    from,
+                    to,
+                    frequency,
+                    spurious
+                );
+            }
+        }
+
+        // Beyond the speaker's Nyquist rate: filtered out, not aliased
+        let mut resampler = Resampler::new(48_000, 44_100);
+        for frequency in [22_600.0, 23_500.0] {
+            let output = through(&mut resampler, frequency, 8192);
+            let power = output.iter().map(|x| x * x).sum::<f32>() / output.len() as f32;
+            // Against the tone's own power of one half
+            let db = 10.0 * (power / 0.5).log10();
+            assert!(db < -60.0, "{} Hz: {:.1} dB", frequency, db);
+        }
+
+        // Which is more than linear interpolation manages
+        let mut linear = Resampler::with_interpolation(48_000, 44_100, Interpolation::Linear);
+        let output = through(&mut linear, 15_500.0, 8192);
+        assert!(spurious_db(&output, 15_500.0, 44_100) > -40.0);
+    }
+
+    #[test]
+    fn test_sample_counts() {
+        for (from, to) in [
+            (48_000, 44_100),
+            (44_100, 48_000),
+            (48_000, 16_000),
+            (8_000, 48_000),
+        ] {
+            for interpolation in [Interpolation::Polyphase, Interpolation::Linear] {
+                let mut resampler = Resampler::with_interpolation(from, to, interpolation);
+                let mut made = 0;
+                for len in [480, 1024, 7, 4096, 333].into_iter().cycle().take(50) {
+                    made += resampler.resample(&vec![0.0; len]).len();
+                }
+                let fed = 10 * (480 + 1024 + 7 + 4096 + 333);
+                let expected = fed as f64 * to as f64 / from as f64;
+                // Linear interpolation holds back what falls after the last
+                // input until the next one comes
+                let slack = match interpolation {
+                    Interpolation::Polyphase => 1.0,
+                    Interpolation::Linear => 1.0 + (to as f64 / from as f64).ceil(),
+                };
+                assert!(
+                    (made as f64 - expected).abs() <= slack,
+                    "{} to {} {:?}: {} for {}",
+                    from,
+                    to,
+                    interpolation,
+                    made,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunk_continuity() {
+        // Uneven buffers come out just as the whole does
+        let input: Vec<f32> = ToneGenerator::new(3_100.0, AUDIO_RATE, 0.0)
+            .generate(20_000)
+            .iter()
+            .enumerate()
+            .map(|(n, x)| x * 0.5 + (n as f32 * 0.37).sin() * 0.3)
+            .collect();
+        for interpolation in [Interpolation::Polyphase, Interpolation::Linear] {
+            let whole =
+                Resampler::with_interpolation(AUDIO_RATE, 44_100, interpolation).resample(&input);
+            let mut resampler = Resampler::with_interpolation(AUDIO_RATE, 44_100, interpolation);
+            let mut chunked = Vec::new();
+            let mut rest = &input[..];
+            for len in [1, 480, 7, 4096, 333, 2].into_iter().cycle() {
+                if rest.is_empty() {
+                    break;
+                }
+                let (chunk, after) = rest.split_at(len.min(rest.len()));
+                chunked.extend(resampler.resample(chunk));
+                rest = after;
+            }
+            // Exactly, through the banks; the linear path's position picks
+            // up rounding as it is carried over
+            assert_eq!(chunked.len(), whole.len(), "{:?}", interpolation);
+            let tolerance = match interpolation {
+                Interpolation::Polyphase => 0.0,
+                Interpolation::Linear => 1e-4,
+            };
+            for (a, b) in chunked.iter().zip(&whole) {
+                assert!(
+                    (a - b).abs() <= tolerance,
+                    "{:?}: {} {}",
+                    interpolation,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fallback_to_linear() {
+        // 48 000 to 44 101 Hz reduces to 44 101 phases, too many for banks
+        let resampler = Resampler::new(48_000, 44_101);
+        assert!(matches!(resampler.kernel, Kernel::Linear(_)));
+        assert_eq!(resampler.delay(), 0.0);
+        let resampler = Resampler::new(48_000, 44_100);
+        assert!(matches!(resampler.kernel, Kernel::Polyphase(_)));
+        assert!(resampler.delay() > 10.0);
+    }
+
+    /// Throughput of both ways, best measured in a release build:
+    /// `cargo test --release bench_resampler -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_resampler() {
+        let input = ToneGenerator::new(1_000.0, AUDIO_RATE, 0.0).generate(AUDIO_RATE as usize);
+        for interpolation in [Interpolation::Linear, Interpolation::Polyphase] {
+            let mut resampler = Resampler::with_interpolation(AUDIO_RATE, 44_100, interpolation);
+            let start = Instant::now();
+            for _ in 0..10 {
+                for chunk in input.chunks(1024) {
+                    std::hint::black_box(resampler.resample(chunk));
+                }
+            }
+            let seconds = start.elapsed().as_secs_f64();
+            println!(
+                "{:?}: {:.1} Msamples/s, {:.0}x real time",
+                interpolation,
+                10.0 * input.len() as f64 / seconds / 1e6,
+                10.0 / seconds
+            );
+        }
+    }
+
+    #[test]
+    fn test_speaker_at_44100() {
+        // A second of 1 kHz tone in uneven buffers, for a 44.1 kHz device
+        let mut tone = ToneGenerator::new(1000.0, AUDIO_RATE, 0.0);
+        let mut speaker = SpeakerRate::default();
+        let mut output = Vec::new();
+        let mut made = 0;
+        for len in [480, 1024, 7, 4096, 333].into_iter().cycle() {
+            let len = len.min(AUDIO_RATE as usize - made);
+            if len == 0 {
+                break;
+            }
+            output.extend_from_slice(&speaker.convert(44_100, &tone.generate(len)));
+            made += len;
+        }
+        assert!(
+            (44_098..=44_100).contains(&output.len()),
+            "{}",
+            output.len()
+        );
+
+        // The same tone as made at 44.1 kHz, less the filter's delay, with
+        // no jump where the buffers meet
+        let delay = speaker.resampler.delay() / AUDIO_RATE as f64;
+        let error = output
+            .iter()
+            .enumerate()
+            .skip(SETTLE)
+            .map(|(n, a)| {
+                let time = n as f64 / 44_100.0 - delay;
+                (a - (TAU * 1000.0 * time).sin() as f32).abs()
+            })
+            .fold(0.0, f32::max);
+        assert!(error < 0.01, "{}", error);
+    }
+
+    #[test]
+    fn test_speaker_at_audio_rate() {
+        let mut speaker = SpeakerRate::default();
+        let audio = [0.1, 0.2, 0.3];
+        assert!(matches!(
+            speaker.convert(AUDIO_RATE, &audio),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(speaker.convert(96_000, &audio).len(), 6);
+        // Back to the audio rate when the device changes again
+        assert_eq!(*speaker.convert(AUDIO_RATE, &audio), audio);
+    }
+
+    #[test]
+    fn test_channel_at_51200() {
+        // A second of 1 kHz tone as AM is demodulated at 1.024 MHz / 20
+        let mut tone = ToneGenerator::new(1000.0, 51_200, 0.0);
+        let mut channel = ChannelRate::default();
+        let mut output = Vec::new();
+        for _ in 0..10 {
+            output.extend(channel.convert(51_200, tone.generate(5_120)));
+        }
+        assert!(
+            (47_998..=48_000).contains(&output.len()),
+            "{}",
+            output.len()
+        );
+        let error = spurious_db(&output[SETTLE..], 1000.0, AUDIO_RATE);
+        assert!(error < -60.0, "{:.1} dB", error);
+
+        // Made at the audio rate it passes through; at the full rate of a
+        // capture it is still brought down
+        assert_eq!(channel.convert(AUDIO_RATE, vec![0.1, 0.2]), [0.1, 0.2]);
+        let full = channel.convert(2_048_000, vec![0.0; 204_800]);
+        assert!((4_799..=4_800).contains(&full.len()), "{}", full.len());
     }
 
     #[test]
@@ -1,30 +1,143 @@
-use super::FftProcessor;
-use crate::state::SharedState;
+use super::channelizer::{decimated_rate, listening_rate};
+use super::dc::{self, DcAvoidance, DcNotch, FrequencyShift};
+use super::decoder::{DecoderInput, DecoderSelection, DecoderTap, InputKind};
+use super::filters::{AudioAgc, AudioShaper};
+use super::mixer::{Mixer, Priority};
+use super::noise::{NoiseReducer, NoiseReduction};
+use super::resampler::{ChannelRate, SpeakerRate};
+use super::tone;
+use super::{ActivityDetector, AfSpectrum, Channelizer, FftProcessor};
+use crate::audio::latency::{LatencyEstimate, AUDIO_RATE};
+use crate::recorder::RecorderEvent;
+use crate::sdr::SampleBuffer;
+use crate::state::mode_settings::ModeSettings;
+use crate::state::{RateMeter, RecordingMode, SharedState};
 use crate::types::DemodMode;
 use crossbeam::channel::{Receiver, Sender};
 use num_complex::Complex;
 use ringbuf::traits::Producer;
-use ringbuf::HeapRb;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+
+/// Most IQ samples kept for the constellation each frame
+const IQ_SNAPSHOT_LEN: usize = 1000;
+/// How often averaged spectra are searched for active signals
+const DETECT_INTERVAL: Duration = Duration::from_millis(500);
+/// How often an announcement playing alone is topped up
+const ANNOUNCEMENT_POLL: Duration = Duration::from_millis(20);
+/// How long without program audio for the speaker before an announcement
+/// plays on its own
+const PROGRAM_GAP: Duration = Duration::from_millis(200);
+
+/// Where the heard audio goes: the speaker's ring, or whatever stands in
+/// for it
+pub trait AudioSink: Send + 'static {
+    /// Queue `sample` to be played, dropping it if there is no room
+    fn play(&mut self, sample: f32);
+
+    /// Samples queued and not played yet
+    fn queued(&self) -> usize;
+}
+
+impl<P: Producer<Item = f32> + Send + 'static> AudioSink for P {
+    fn play(&mut self, sample: f32) {
+        let _ = self.try_push(sample);
+    }
+
+    fn queued(&self) -> usize {
+        self.occupied_len()
+    }
+}
+
+/// Everything the DSP thread sends its results to, besides the state
+pub struct DspOutputs<A> {
+    /// The speaker, if there is one
+    pub audio: Option<A>,
+    /// Kept telling the audio output whether anything should be heard
+    pub audible: Arc<AtomicBool>,
+    /// The rate the speaker runs at
+    pub speaker_rate: Arc<AtomicU32>,
+    /// Network streams and audio pipes
+    pub streams: Vec<Sender<Vec<f32>>>,
+    /// Chain A's decoders
+    pub decoder_tap: DecoderTap,
+    /// Chain B's decoders
+    pub channel_b_tap: DecoderTap,
+    pub recorder_tx: Sender<RecorderEvent>,
+}
 
 /// Start the DSP processing thread
-pub fn start_dsp_thread<P>(
+///
+/// Chain A is heard and feeds the decoder tap; chain B, when it has a mode,
+/// feeds only chain B's. `audible` is kept telling the audio output whether
+/// anything should be heard, so it can drop what it still holds once the
+/// monitor is off or the mode has no audio. Chain A's audio is brought from
+/// its channel's rate to the audio rate once its decoders have it; what goes
+/// to the speaker is resampled to `speaker_rate`, and the streams get it at
+/// the audio rate.
+pub fn start_dsp_thread<A: AudioSink>(
     state: SharedState,
-    samples_rx: Receiver<Vec<Complex<f32>>>,
-    mut audio_tx: Option<P>,
-    stream_tx: Option<Sender<Vec<f32>>>,
+    samples_rx: Receiver<SampleBuffer>,
+    outputs: DspOutputs<A>,
     shutdown: Arc<AtomicBool>,
-) -> thread::JoinHandle<()>
-where
-    P: Producer<Item = f32> + Send + 'static,
-{
+) -> thread::JoinHandle<()> {
+    let DspOutputs {
+        audio: mut audio_tx,
+        audible,
+        speaker_rate,
+        streams: stream_txs,
+        mut decoder_tap,
+        mut channel_b_tap,
+        recorder_tx,
+    } = outputs;
     thread::spawn(move || {
         log::info!("DSP processing thread started");
 
         // Create FFT processor
         let mut fft_processor = FftProcessor::new(2048);
+        let mut af_spectrum = AfSpectrum::new();
+        let mut rate_meter = RateMeter::new(Instant::now());
+        let mut detector = ActivityDetector::new();
+        let mut detect_center = state.read().sdr.frequency;
+        let mut last_detect = Instant::now();
+
+        // Per-mode audio filters (rebuilt on mode or rate change)
+        let mut audio_shaper =
+            AudioShaper::new(state.read().decoder.mode, state.read().sdr.sample_rate);
+
+        // Channel selection for each receive chain
+        let mut channelizer_a = Channelizer::listening();
+        let mut channelizer_b = Channelizer::decimating();
+        let mut audio_shaper_b = AudioShaper::new(DemodMode::Raw, state.read().sdr.sample_rate);
+        // Shifts the capture back when the hardware is tuned off center
+        let mut capture_shift = FrequencyShift::default();
+        let mut dc_notch = DcNotch::default();
+        let mut notch_active = false;
+        // Chain A's audio at the audio rate, from its channel's
+        let mut channel_rate = ChannelRate::default();
+        // Noise estimate and overlap for chain A's voice audio
+        let mut noise_reducer = NoiseReducer::new(AUDIO_RATE);
+        // Levels chain A's audio at each mode's AGC speed
+        let mut agc = AudioAgc::default();
+        // Measures the priority channel while the priority watch asks
+        let mut priority_probe = Channelizer::decimating();
+        let mut was_muted = false;
+        // Announcements over the speaker's audio, and what set them off
+        let mut mixer = Mixer::new(AUDIO_RATE);
+        let mut test_tone = None;
+        let mut same_alert = None;
+        let mut priority_active = false;
+        // When program audio last went to the speaker
+        let mut program_at: Option<Instant> = None;
+        // The test tone over what the streams get, so their listeners can
+        // check them too, and when program audio last went to them
+        let mut stream_mixer = Mixer::new(AUDIO_RATE);
+        let mut streamed_at: Option<Instant> = None;
+        // Audio for a speaker that doesn't run at the audio rate
+        let mut speaker = SpeakerRate::default();
 
         loop {
             // Check for shutdown
@@ -33,58 +146,391 @@ where
                 break;
             }
 
-            // Receive samples from SDR thread (blocking with timeout)
-            match samples_rx.recv_timeout(std::time::Duration::from_millis(100)) {
-                Ok(samples) => {
-                    // 1. Compute FFT for spectrum display
-                    let fft_data = fft_processor.process(&samples);
+            // The test tone, a new SAME alert and the priority channel
+            // coming up are each announced over the audio
+            let now = Instant::now();
+            let (tone_until, alert, active) = {
+                let state = state.read();
+                (state.ui.test_tone, state.same_alert().cloned(), state.priority.active)
+            };
+            if tone_until != test_tone {
+                if let Some(until) = tone_until.filter(|&until| now < until) {
+                    let tone = tone::test_tone(until - now);
+                    if !stream_txs.is_empty() {
+                        stream_mixer.announce(Priority::Test, tone.clone());
+                    }
+                    mixer.announce(Priority::Test, tone);
+                }
+                test_tone = tone_until;
+            }
+            if alert.is_some() && alert != same_alert {
+                mixer.announce(Priority::Alert, tone::alert_tones());
+            }
+            same_alert = alert;
+            if active && !priority_active {
+                mixer.announce(Priority::Channel, tone::priority_cue());
+            }
+            priority_active = active;
+
+            // With no program audio going to the speaker, an announcement
+            // plays on its own
+            if program_at.is_none_or(|at| now.duration_since(at) > PROGRAM_GAP) {
+                let alone = mixer.alone(now);
+                if !alone.is_empty() {
+                    audible.store(true, Ordering::Relaxed);
+                    if let Some(audio_producer) = audio_tx.as_mut() {
+                        let rate = speaker_rate.load(Ordering::Relaxed);
+                        send_audio_samples(audio_producer, &speaker.convert(rate, &alone));
+                    }
+                }
+            }
+            if streamed_at.is_none_or(|at| now.duration_since(at) > PROGRAM_GAP) {
+                let alone = stream_mixer.alone(now);
+                if !alone.is_empty() {
+                    for stream in &stream_txs {
+                        let _ = stream.try_send(alone.clone());
+                    }
+                }
+            }
+
+            // Receive samples from SDR thread (blocking with timeout); an
+            // announcement is topped up more often than that
+            let timeout = match mixer.playing().or(stream_mixer.playing()) {
+                Some(_) => ANNOUNCEMENT_POLL,
+                None => Duration::from_millis(100),
+            };
+            match samples_rx.recv_timeout(timeout) {
+                Ok(buffer) => {
+                    let started = Instant::now();
+                    let captured = buffer.capture_time;
+                    // Decoded messages are dated by the end of the buffer
+                    // that completed them
+                    decoder_tap.stamp(buffer.end_time());
+                    channel_b_tap.stamp(buffer.end_time());
+                    // What came before a seek in a file being played
+                    // doesn't belong with what comes after
+                    let seeked = buffer.seeked;
+                    let mut samples = buffer.samples;
+                    let span = tracing::debug_span!(
+                        "dsp.buffer",
+                        len = samples.len(),
+                        micros = Empty,
+                        decoder_dropped = Empty
+                    )
+                    .entered();
+
+                    // With the hardware tuned off to one side, shift the
+                    // capture back so the frequency shown is at its center
+                    let (hardware_offset, capture_rate, dc_avoidance) = {
+                        let state = state.read();
+                        (state.sdr.hardware_offset, state.sdr.sample_rate, state.sdr.dc_avoidance)
+                    };
+                    capture_shift.process(hardware_offset, capture_rate, &mut samples);
+
+                    // 1. Compute FFT for spectrum display, blanking what the
+                    // shift wrapped round from the far edge
+                    let mut fft_data = fft_processor.process(&samples);
+                    FrequencyShift::mask_wrapped(hardware_offset, capture_rate, &mut fft_data);
+                    let dc_spike = dc::dc_prominent(&fft_data);
+                    let fft_time = started.elapsed();
 
                     // Update spectrum state
-                    state.write().spectrum.add_fft_data(fft_data);
+                    {
+                        let mut state_guard = state.write();
+                        // For the watchdog, which reopens a receiver gone quiet
+                        state_guard.sdr.last_samples = Some(started);
+                        // Frames from before a retune don't belong in the average
+                        let center = state_guard.sdr.frequency;
+                        if center != detect_center {
+                            detector.reset();
+                            state_guard.spectrum.restart_row();
+                            detect_center = center;
+                        }
+                        if seeked {
+                            detector.reset();
+                            channelizer_a = Channelizer::listening();
+                            channelizer_b = Channelizer::decimating();
+                            priority_probe = Channelizer::decimating();
+                            audio_shaper = AudioShaper::new(
+                                state_guard.decoder.mode,
+                                state_guard.sdr.sample_rate,
+                            );
+                            audio_shaper_b =
+                                AudioShaper::new(DemodMode::Raw, state_guard.sdr.sample_rate);
+                            channel_rate = ChannelRate::default();
+                            noise_reducer = NoiseReducer::new(AUDIO_RATE);
+                            dc_notch = DcNotch::default();
+                            state_guard.spectrum.restart_row();
+                            decoder_tap.mark_gap();
+                            channel_b_tap.mark_gap();
+                        }
+                        detector.add_frame(&fft_data);
+                        if last_detect.elapsed() >= DETECT_INTERVAL {
+                            let detections = detector.take(state_guard.sdr.sample_rate);
+                            last_detect = Instant::now();
+                            state_guard.spectrum.activity.update(&detections, center, last_detect);
+                        }
+                        // Waterfall rows follow the buffers actually
+                        // arriving, or the nominal rate until that is known
+                        let measured = state_guard.stats.buffers_per_sec;
+                        state_guard.spectrum.frame_rate = if measured > 0.0 {
+                            measured
+                        } else {
+                            capture_rate as f32 / samples.len().max(1) as f32
+                        };
+                        state_guard.spectrum.add_fft_data(fft_data, captured.into());
+                        if state_guard.ui.show_constellation {
+                            state_guard.spectrum.iq_snapshot =
+                                iq_snapshot(&samples, IQ_SNAPSHOT_LEN);
+                        }
+                    }
 
-                    // 2. Demodulate based on current mode
-                    let mode = state.read().decoder.mode;
+                    // Chain A listens at the center unless moved off it
+                    let (mode, filter_width, sample_rate, frequency, channels, probe_offset) = {
+                        let state = state.read();
+                        (
+                            state.decoder.mode,
+                            state.decoder.filter_width,
+                            state.sdr.sample_rate,
+                            state.sdr.frequency,
+                            state.channels.clone(),
+                            state.priority.probe_offset,
+                        )
+                    };
+                    // The DC notch is used while the spike would be heard
+                    let notch = dc_avoidance == DcAvoidance::Notch
+                        && dc_spike
+                        && dc::dc_in_passband(channels.offset_a, mode, filter_width);
+                    if notch {
+                        dc_notch.process(sample_rate, &mut samples);
+                    }
+                    if notch != notch_active {
+                        state.write().sdr.dc_notch_active = notch;
+                        notch_active = notch;
+                    }
+                    if let Some(offset) = probe_offset {
+                        let probe =
+                            priority_probe.process(offset, mode, None, sample_rate, &samples);
+                        state.write().priority.probe_level = Some(signal_level_db(probe));
+                    }
+                    // Filtered even at the center, so the width set applies
+                    let channel = channelizer_a.process(
+                        channels.offset_a,
+                        mode,
+                        filter_width,
+                        sample_rate,
+                        &samples,
+                    );
+
+                    // 2. Measure channel power and update the squelch
+                    let level = signal_level_db(channel);
+                    let squelch_open = {
+                        let mut state_guard = state.write();
+                        let decoder = &mut state_guard.decoder;
+                        decoder.signal_level = level;
+                        decoder.squelch_open =
+                            decoder.squelch_level.is_none_or(|threshold| level >= threshold);
+                        decoder.squelch_open
+                    };
+
+                    // 3. Demodulate based on current mode, at the channel's
+                    // rate
+                    let rate_a = listening_rate(mode, sample_rate);
+                    let (shaping, recording_squelch, selection, show_af, show_scope, muted) = {
+                        let state = state.read();
+                        (
+                            state.decoder.audio_filters,
+                            state.recording.is_recording
+                                && state.recording.mode == RecordingMode::Squelch,
+                            DecoderSelection::from_state(&state),
+                            state.ui.show_af_spectrum,
+                            state.ui.show_scope,
+                            state.priority.muted,
+                        )
+                    };
+                    let (monitor, limiter, noise_reduction, mode_settings) = {
+                        let state = state.read();
+                        (
+                            state.ui.monitor,
+                            state.decoder.limiter,
+                            state.decoder.noise_reduction,
+                            state.decoder.mode_settings(),
+                        )
+                    };
+
+                    // Tee whatever the decoder thread wants; if it falls
+                    // behind its input is dropped, never the audio
+                    decoder_tap.select(selection);
+                    decoder_tap.send(DecoderInput::Iq(channel));
+                    if decoder_tap.wants(InputKind::Magnitude) {
+                        let magnitude: Vec<f32> = channel.iter().map(|s| s.norm()).collect();
+                        decoder_tap.send(DecoderInput::Magnitude(&magnitude));
+                    }
 
                     // Demodulate to get audio samples
-                    let audio: Option<Vec<f32>> = match mode {
-                        DemodMode::FmNarrow | DemodMode::FmWide => {
-                            Some(demodulate_fm(&samples, mode == DemodMode::FmWide))
+                    let demod_started = Instant::now();
+                    let audio = demodulate(mode, channel, rate_a, &mode_settings);
+
+                    let mut demod_time = demod_started.elapsed();
+                    let announcing = mixer.playing().is_some();
+                    audible.store((monitor && audio.is_some()) || announcing, Ordering::Relaxed);
+
+                    // Send audio to local output and/or network stream
+                    if let Some(mut audio_samples) = audio {
+                        // Tone detectors (e.g. CTCSS) must tap the audio here,
+                        // before the high-pass removes sub-audible tones
+                        if shaping {
+                            audio_shaper.process(mode, rate_a, &mut audio_samples);
+                        }
+
+                        // Audio decoders run ahead of the squelch mute: SAME
+                        // bursts precede the alert audio and CW tracks its own floor
+                        decoder_tap.send(DecoderInput::Audio(&audio_samples));
+
+                        // Listened to from here on at the audio rate
+                        audio_samples = channel_rate.convert(rate_a, audio_samples);
+                        demod_time = demod_started.elapsed();
+
+                        // Noise reduction is for listening; data modes pass
+                        // through untouched
+                        if NoiseReduction::applies_to(mode) {
+                            noise_reducer.process(
+                                noise_reduction,
+                                AUDIO_RATE,
+                                !squelch_open,
+                                &mut audio_samples,
+                            );
+                        }
+
+                        // Levelled after the decoders' tap, for listening
+                        agc.process(mode_settings.agc_speed, AUDIO_RATE, &mut audio_samples);
+
+                        // Everything heard, streamed or recorded from here is
+                        // limited the same way
+                        if let Some(limiter) = limiter {
+                            limiter.process(&mut audio_samples);
                         }
-                        DemodMode::Am => {
-                            Some(demodulate_am(&samples))
+
+                        // The AF spectrum shows the filtered audio, unmuted
+                        if show_af {
+                            if let Some(af_fft) = af_spectrum.process(AUDIO_RATE, &audio_samples) {
+                                state.write().spectrum.af_fft = af_fft;
+                            }
                         }
-                        DemodMode::Usb => {
-                            Some(demodulate_ssb(&samples, true))
+
+                        // The squelch recorder gets unmuted audio so its
+                        // pre-roll holds what came before the squelch opened
+                        if recording_squelch
+                            && recorder_tx
+                                .try_send(RecorderEvent::Audio {
+                                    samples: audio_samples.clone(),
+                                    sample_rate: AUDIO_RATE,
+                                    squelch_open,
+                                })
+                                .is_err()
+                        {
+                            log::warn!("Recorder is falling behind, dropping audio buffer");
+                            state.write().stats.recorder_dropped += 1;
                         }
-                        DemodMode::Lsb => {
-                            Some(demodulate_ssb(&samples, false))
+
+                        if !squelch_open {
+                            audio_samples.fill(0.0);
                         }
-                        DemodMode::Aprs | DemodMode::Adsb => {
-                            // Digital modes - demodulate FM for APRS, raw for ADS-B
-                            // TODO: Add packet decoding
-                            Some(demodulate_fm(&samples, false))
+                        // Priority checks retune under this mute
+                        apply_mute(&mut audio_samples, was_muted, muted);
+                        was_muted = muted;
+
+                        // The scope shows what is heard, squelch included
+                        if show_scope {
+                            state.write().spectrum.scope.push(AUDIO_RATE, &audio_samples);
                         }
-                        DemodMode::Raw => {
-                            // No demodulation, just visualization
-                            None
+
+                        // Send to the network stream and audio pipe, which
+                        // drop what they can't keep up with; the listeners
+                        // chose to hear it. The test tone plays over it
+                        if !stream_txs.is_empty() {
+                            let mut streamed = audio_samples.clone();
+                            stream_mixer.mix(&mut streamed);
+                            streamed_at = Some(started);
+                            for stream in &stream_txs {
+                                let _ = stream.try_send(streamed.clone());
+                            }
                         }
-                    };
 
-                    // Send audio to local output and/or network stream
-                    if let Some(ref audio_samples) = audio {
-                        // Send to local audio output
-                        if let Some(audio_producer) = audio_tx.as_mut() {
-                            send_audio_samples(audio_producer, audio_samples);
+                        // Send to local audio output, unless only decoding,
+                        // ducked under any announcement
+                        if let Some(audio_producer) = audio_tx.as_mut().filter(|_| monitor) {
+                            mixer.mix(&mut audio_samples);
+                            program_at = Some(started);
+                            let rate = speaker_rate.load(Ordering::Relaxed);
+                            let heard = speaker.convert(rate, &audio_samples);
+                            send_audio_samples(audio_producer, &heard);
                         }
+                    }
 
-                        // Send to network stream
-                        if let Some(ref stream) = stream_tx {
-                            let _ = stream.try_send(audio_samples.clone());
+                    // 4. Chain B: a second channel, for the decoders only,
+                    // decimated to suit it
+                    let (offset_b, mode_b) = (channels.offset_b, channels.mode_b);
+                    let rate_b = decimated_rate(mode_b, sample_rate);
+                    channel_b_tap.select(DecoderSelection {
+                        mode: mode_b,
+                        frequency: frequency.saturating_add_signed(offset_b),
+                        sample_rate: rate_b,
+                        dtmf_enabled: false,
+                    });
+                    if mode_b != DemodMode::Raw {
+                        let channel =
+                            channelizer_b.process(offset_b, mode_b, None, sample_rate, &samples);
+                        channel_b_tap.send(DecoderInput::Iq(channel));
+                        if channel_b_tap.wants(InputKind::Magnitude) {
+                            let magnitude: Vec<f32> = channel.iter().map(|s| s.norm()).collect();
+                            channel_b_tap.send(DecoderInput::Magnitude(&magnitude));
+                        }
+                        if channel_b_tap.wants(InputKind::Audio) {
+                            // Chain B only decodes, so takes its mode's defaults
+                            let settings = ModeSettings::default_for(mode_b);
+                            let audio = demodulate(mode_b, channel, rate_b, &settings);
+                            if let Some(mut audio) = audio {
+                                if shaping {
+                                    audio_shaper_b.process(mode_b, rate_b, &mut audio);
+                                }
+                                channel_b_tap.send(DecoderInput::Audio(&audio));
+                            }
                         }
                     }
+
+                    // 5. Processing stats; load is time spent against the
+                    // time the buffer covers
+                    let buffer_secs = samples.len() as f32 / sample_rate.max(1) as f32;
+                    let load = started.elapsed().as_secs_f32() / buffer_secs;
+                    let rates = rate_meter.tick(Instant::now(), samples.len());
+                    let decoder_dropped =
+                        decoder_tap.total_dropped() + channel_b_tap.total_dropped();
+                    span.record("micros", started.elapsed().as_micros() as u64);
+                    span.record("decoder_dropped", decoder_dropped);
+                    let latency = LatencyEstimate {
+                        iq_buffer_secs: buffer_secs as f64,
+                        iq_queued: samples_rx.len(),
+                        audio_queued: audio_tx.as_ref().map_or(0, |tx| tx.queued()),
+                        stream_queued: stream_txs.iter().map(|tx| tx.len()).max(),
+                    };
+                    let mut state_guard = state.write();
+                    let stats = &mut state_guard.stats;
+                    stats.fft_us.update(fft_time.as_secs_f32() * 1e6);
+                    stats.demod_us.update(demod_time.as_secs_f32() * 1e6);
+                    stats.dsp_load.update(load);
+                    stats.latency = latency;
+                    stats.decoder_dropped = decoder_dropped;
+                    if let Some((buffers_per_sec, samples_per_sec)) = rates {
+                        stats.buffers_per_sec = buffers_per_sec;
+                        stats.samples_per_sec = samples_per_sec;
+                    }
                 }
                 Err(crossbeam::channel::RecvTimeoutError::Timeout) => {
-                    // No samples available, continue
+                    // No samples available; partial frames can't be completed
+                    // across the gap
+                    decoder_tap.mark_gap();
+                    channel_b_tap.mark_gap();
                     continue;
                 }
                 Err(crossbeam::channel::RecvTimeoutError::Disconnected) => {
@@ -98,25 +544,86 @@ where
     })
 }
 
-/// FM demodulator using phase difference with de-emphasis
-fn demodulate_fm(samples: &[Complex<f32>], wideband: bool) -> Vec<f32> {
-    if samples.len() < 2 {
-        return vec![];
+/// At most `len` samples spread evenly across `samples`
+fn iq_snapshot(samples: &[Complex<f32>], len: usize) -> Vec<Complex<f32>> {
+    let step = samples.len().div_ceil(len).max(1);
+    samples.iter().step_by(step).copied().collect()
+}
+
+/// Silence `audio` while `muted`, ramping across the buffer where that
+/// changes so it doesn't click
+fn apply_mute(audio: &mut [f32], was_muted: bool, muted: bool) {
+    let len = audio.len() as f32;
+    match (was_muted, muted) {
+        (false, false) => {}
+        (true, true) => audio.fill(0.0),
+        (_, fading_out) => {
+            for (i, sample) in audio.iter_mut().enumerate() {
+                let ramp = i as f32 / len;
+                *sample *= if fading_out { 1.0 - ramp } else { ramp };
+            }
+        }
     }
+}
 
-    let mut audio = Vec::with_capacity(samples.len());
+/// Audio for `mode` from baseband IQ at `sample_rate`, None for modes
+/// without any, with the de-emphasis and BFO offset in `settings`
+///
+/// Audio is produced at the IQ's sample rate.
+pub fn demodulate(
+    mode: DemodMode,
+    samples: &[Complex<f32>],
+    sample_rate: u32,
+    settings: &ModeSettings,
+) -> Option<Vec<f32>> {
+    let rate = sample_rate as f32;
+    let bfo = settings.bfo_offset as f32;
+    match mode {
+        DemodMode::FmNarrow | DemodMode::FmWide => Some(demodulate_fm(
+            samples,
+            mode == DemodMode::FmWide,
+            settings.deemphasis,
+            rate,
+        )),
+        DemodMode::Am | DemodMode::Ism | DemodMode::Acars => Some(demodulate_am(samples)),
+        // The APT subcarrier and RS41 bits need a flat response, so no
+        // de-emphasis
+        DemodMode::Apt | DemodMode::Rs41 => Some(discriminate_fm(samples)),
+        DemodMode::Usb | DemodMode::Cw => Some(demodulate_ssb(samples, true, bfo, rate)),
+        DemodMode::Lsb => Some(demodulate_ssb(samples, false, bfo, rate)),
+        DemodMode::Aprs | DemodMode::Adsb | DemodMode::Ais => {
+            // Digital modes - FM audio carries the APRS tones; ADS-B and AIS
+            // decode from IQ
+            Some(demodulate_fm(samples, false, settings.deemphasis, rate))
+        }
+        // No demodulation, just visualization
+        DemodMode::Raw => None,
+    }
+}
 
-    // FM demodulation via phase difference (polar discriminator)
-    for window in samples.windows(2) {
-        // Phase difference between consecutive samples
-        // This gives us the instantaneous frequency deviation
-        let phase_diff = (window[1] * window[0].conj()).arg();
+/// Mean power of a block of IQ samples in dBFS
+fn signal_level_db(samples: &[Complex<f32>]) -> f32 {
+    if samples.is_empty() {
+        return -100.0;
+    }
+    let power = samples.iter().map(|s| s.norm_sqr()).sum::<f32>() / samples.len() as f32;
+    10.0 * power.max(1e-10).log10()
+}
 
-        // Normalize to audio range
-        let sample = phase_diff / std::f32::consts::PI;
-        audio.push(sample);
+/// FM demodulator using phase difference, with de-emphasis at `deemphasis`
+/// µs unless None
+fn demodulate_fm(
+    samples: &[Complex<f32>],
+    wideband: bool,
+    deemphasis: Option<u32>,
+    sample_rate: f32,
+) -> Vec<f32> {
+    if samples.len() < 2 {
+        return vec![];
     }
 
+    let audio = discriminate_fm(samples);
+
     // Apply lowpass filtering
     // Wideband FM (broadcast): ~15 kHz audio bandwidth
     // Narrowband FM (NOAA, voice): ~3 kHz audio bandwidth
@@ -126,28 +633,37 @@ fn demodulate_fm(samples: &[Complex<f32>], wideband: bool) -> Vec<f32> {
     // Apply de-emphasis filter (75µs for NA, 50µs for EU)
     // This compensates for the pre-emphasis used in FM transmission
     // Improves audio quality significantly for NOAA and FM broadcast
-    apply_deemphasis(&filtered, wideband)
+    match deemphasis {
+        Some(tau_us) => apply_deemphasis(&filtered, tau_us, sample_rate),
+        None => filtered,
+    }
+}
+
+/// Instantaneous frequency from the phase difference between samples
+/// (polar discriminator), scaled so ±1 is half the sample rate
+fn discriminate_fm(samples: &[Complex<f32>]) -> Vec<f32> {
+    samples
+        .windows(2)
+        .map(|window| (window[1] * window[0].conj()).arg() / std::f32::consts::PI)
+        .collect()
 }
 
 /// Apply de-emphasis filter to FM audio
 /// FM broadcasts use pre-emphasis to boost high frequencies
 /// We need de-emphasis to restore flat frequency response
-fn apply_deemphasis(input: &[f32], wideband: bool) -> Vec<f32> {
+fn apply_deemphasis(input: &[f32], tau_us: u32, sample_rate: f32) -> Vec<f32> {
     if input.is_empty() {
         return vec![];
     }
 
     // De-emphasis time constant
     // 75µs for North America, 50µs for Europe
-    // Using 75µs as default (good for NOAA in NA)
-    let tau = if wideband { 75e-6 } else { 50e-6 };
-
-    // Assume ~48kHz sample rate after decimation
-    let sample_rate = 48000.0;
+    let tau = tau_us as f32 * 1e-6;
 
-    // Single-pole IIR lowpass filter coefficient
-    // alpha = 1 / (1 + 2*pi*tau*fs)
-    let alpha = 1.0 / (1.0 + 2.0 * std::f32::consts::PI * tau * sample_rate);
+    // Single-pole IIR lowpass filter coefficient, with its corner at
+    // 1 / (2*pi*tau) (3.2 kHz for 50µs); tau already carries the 2*pi
+    // alpha = 1 - exp(-1 / (tau*fs))
+    let alpha = 1.0 - (-1.0f32 / (tau * sample_rate)).exp();
 
     let mut output = Vec::with_capacity(input.len());
     let mut prev = input[0];
@@ -175,7 +691,13 @@ fn demodulate_am(samples: &[Complex<f32>]) -> Vec<f32> {
 /// SSB (Single Sideband) demodulator
 /// For USB: use upper sideband (positive frequencies)
 /// For LSB: use lower sideband (negative frequencies)
-fn demodulate_ssb(samples: &[Complex<f32>], upper: bool) -> Vec<f32> {
+/// The beat oscillator sits `bfo_freq` Hz from the carrier
+fn demodulate_ssb(
+    samples: &[Complex<f32>],
+    upper: bool,
+    bfo_freq: f32,
+    sample_rate: f32,
+) -> Vec<f32> {
     // SSB demodulation using the Weaver method (simplified)
     // The IQ samples from the SDR already give us the analytic signal
     // For USB: take the real part directly (I channel)
@@ -189,8 +711,6 @@ fn demodulate_ssb(samples: &[Complex<f32>], upper: bool) -> Vec<f32> {
 
     // Apply a simple BFO (Beat Frequency Oscillator) mixing
     // This shifts the sideband to audio frequencies
-    let bfo_freq = 1500.0; // 1.5 kHz BFO offset for typical SSB
-    let sample_rate = 48000.0; // Assumed audio sample rate
 
     for (i, sample) in samples.iter().enumerate() {
         let t = i as f32 / sample_rate;
@@ -231,20 +751,27 @@ fn lowpass_filter(input: &[f32], window_size: usize) -> Vec<f32> {
     output
 }
 
-/// Send audio samples to the ring buffer
-fn send_audio_samples<P: Producer<Item = f32>>(producer: &mut P, samples: &[f32]) {
+/// Send audio samples to the speaker
+fn send_audio_samples<A: AudioSink>(sink: &mut A, samples: &[f32]) {
     for &sample in samples {
-        // Clamp to valid audio range
-        let clamped = sample.max(-1.0).min(1.0);
-
-        // Try to push, drop if buffer is full
-        let _ = producer.try_push(clamped);
+        // Clamp to valid audio range; dropped if the buffer is full
+        sink.play(sample.max(-1.0).min(1.0));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::audio::latency::AUDIO_RATE;
+    use crate::dsp::decoder::{
+        decoder_channel, start_decoder_thread, DecodeLogFormat, Decoder, DecoderRegistry,
+        MessageLog,
+    };
+    use crate::state::AppState;
+    use crate::types::{Chain, DecodedMessage};
+    use ringbuf::traits::{Consumer, Observer, Split};
+    use ringbuf::HeapRb;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn test_demodulate_fm() {
@@ -256,7 +783,7 @@ mod tests {
             })
             .collect();
 
-        let audio = demodulate_fm(&samples, false);
+        let audio = demodulate_fm(&samples, false, Some(50), 48_000.0);
         assert_eq!(audio.len(), samples.len() - 1);
     }
 
@@ -269,7 +796,7 @@ mod tests {
             })
             .collect();
 
-        let audio = demodulate_fm(&samples, true);
+        let audio = demodulate_fm(&samples, true, Some(75), 48_000.0);
         assert_eq!(audio.len(), samples.len() - 1);
     }
 
@@ -295,7 +822,7 @@ mod tests {
             })
             .collect();
 
-        let audio = demodulate_ssb(&samples, true);
+        let audio = demodulate_ssb(&samples, true, 1_500.0, 48_000.0);
         assert_eq!(audio.len(), samples.len());
     }
 
@@ -308,7 +835,7 @@ mod tests {
             })
             .collect();
 
-        let audio = demodulate_ssb(&samples, false);
+        let audio = demodulate_ssb(&samples, false, 1_500.0, 48_000.0);
         assert_eq!(audio.len(), samples.len());
     }
 
@@ -322,10 +849,379 @@ mod tests {
         assert!((filtered[2] - 3.0).abs() < 0.1);
     }
 
+    #[test]
+    fn test_signal_level_db() {
+        let full_scale = vec![Complex::new(1.0, 0.0); 64];
+        assert!(signal_level_db(&full_scale).abs() < 0.01);
+
+        let quiet = vec![Complex::new(0.01, 0.0); 64];
+        assert!((signal_level_db(&quiet) + 40.0).abs() < 0.01);
+
+        assert_eq!(signal_level_db(&[]), -100.0);
+    }
+
+    #[test]
+    fn test_apply_mute() {
+        let mut audio = [1.0; 4];
+        apply_mute(&mut audio, false, true);
+        assert_eq!(audio, [1.0, 0.75, 0.5, 0.25]);
+
+        let mut audio = [1.0; 4];
+        apply_mute(&mut audio, true, false);
+        assert_eq!(audio, [0.0, 0.25, 0.5, 0.75]);
+
+        let mut audio = [1.0; 4];
+        apply_mute(&mut audio, true, true);
+        assert_eq!(audio, [0.0; 4]);
+        apply_mute(&mut audio, false, false);
+        assert_eq!(audio, [0.0; 4]);
+    }
+
+    #[test]
+    fn test_iq_snapshot() {
+        let samples: Vec<Complex<f32>> = (0..16_384).map(|i| Complex::new(i as f32, 0.0)).collect();
+        let snapshot = iq_snapshot(&samples, 1000);
+        assert_eq!(snapshot.len(), 964);
+        assert_eq!(snapshot[1].re, 17.0);
+        assert_eq!(iq_snapshot(&samples[..10], 1000).len(), 10);
+    }
+
     #[test]
     fn test_deemphasis() {
         let input = vec![1.0, 0.5, 0.0, -0.5, -1.0];
-        let output = apply_deemphasis(&input, false);
+        let output = apply_deemphasis(&input, 50, 48_000.0);
         assert_eq!(output.len(), input.len());
     }
+
+    fn message_log() -> Arc<parking_lot::Mutex<MessageLog>> {
+        let log = MessageLog::new("decodes.jsonl".into(), DecodeLogFormat::Jsonl, u64::MAX);
+        Arc::new(parking_lot::Mutex::new(log))
+    }
+
+    /// An audio decoder that takes far longer than real time
+    struct SlowDecoder;
+
+    impl Decoder for SlowDecoder {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        fn input_kind(&self) -> InputKind {
+            InputKind::Audio
+        }
+
+        fn sample_rate(&self) -> u32 {
+            48_000
+        }
+
+        fn process(&mut self, _input: DecoderInput) -> Vec<DecodedMessage> {
+            thread::sleep(Duration::from_millis(50));
+            vec![DecodedMessage::new(DemodMode::Cw, "slow".to_string())]
+        }
+
+        fn reset(&mut self) {}
+    }
+
+    #[test]
+    fn test_slow_decoder_does_not_starve_audio() {
+        const BUFFERS: usize = 60;
+        const LEN: usize = 2048;
+        // Heard at the audio rate, from IQ at 2.048 MHz
+        const AUDIO_LEN: usize = LEN * AUDIO_RATE as usize / 2_048_000;
+
+        let state = AppState::new_shared();
+        state.write().decoder.mode = DemodMode::Cw;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (samples_tx, samples_rx) = crossbeam::channel::unbounded();
+        let (recorder_tx, _recorder_rx) = crossbeam::channel::unbounded();
+        let (producer, consumer) = HeapRb::<f32>::new(BUFFERS * LEN).split();
+
+        let registry =
+            DecoderRegistry::with_factory(|_| vec![Box::new(SlowDecoder) as Box<dyn Decoder>]);
+        let (tap, decoder_rx) = decoder_channel();
+        let (channel_b_tap, _channel_b_rx) = decoder_channel();
+        let decoder_thread = start_decoder_thread(
+            state.clone(),
+            Chain::A,
+            registry,
+            decoder_rx,
+            message_log(),
+            None,
+            None,
+            shutdown.clone(),
+        );
+        let outputs = DspOutputs {
+            audio: Some(producer),
+            audible: Arc::new(AtomicBool::new(true)),
+            speaker_rate: Arc::new(AtomicU32::new(AUDIO_RATE)),
+            streams: Vec::new(),
+            decoder_tap: tap,
+            channel_b_tap,
+            recorder_tx,
+        };
+        let dsp_thread = start_dsp_thread(state.clone(), samples_rx, outputs, shutdown.clone());
+
+        // Inline, the decoder alone would need 3 s for this much audio
+        let start = Instant::now();
+        for _ in 0..BUFFERS {
+            let samples = vec![Complex::new(0.1, 0.0); LEN];
+            samples_tx.send(SampleBuffer::new(samples, 2_048_000)).unwrap();
+        }
+        while consumer.occupied_len() < BUFFERS * AUDIO_LEN {
+            assert!(start.elapsed() < Duration::from_millis(1500), "audio stalled");
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        shutdown.store(true, Ordering::Relaxed);
+        dsp_thread.join().unwrap();
+        decoder_thread.join().unwrap();
+        let decoded = state.read().decoder.messages.len();
+        assert!(decoded > 0 && decoded < BUFFERS);
+    }
+
+    /// An audio decoder that keeps everything it is given
+    struct CaptureDecoder(Arc<parking_lot::Mutex<Vec<f32>>>);
+
+    impl Decoder for CaptureDecoder {
+        fn name(&self) -> &'static str {
+            "capture"
+        }
+
+        fn input_kind(&self) -> InputKind {
+            InputKind::Audio
+        }
+
+        fn sample_rate(&self) -> u32 {
+            240_000
+        }
+
+        fn process(&mut self, input: DecoderInput) -> Vec<DecodedMessage> {
+            if let DecoderInput::Audio(audio) = input {
+                self.0.lock().extend_from_slice(audio);
+            }
+            Vec::new()
+        }
+
+        fn reset(&mut self) {}
+    }
+
+    /// Power of `audio` at `freq`, by correlation
+    fn tone_power(audio: &[f32], freq: f32, sample_rate: f32) -> f32 {
+        let (mut re, mut im) = (0.0f32, 0.0f32);
+        for (i, &sample) in audio.iter().enumerate() {
+            let phase = std::f32::consts::TAU * freq * i as f32 / sample_rate;
+            re += sample * phase.cos();
+            im += sample * phase.sin();
+        }
+        (re * re + im * im) / (audio.len() as f32).powi(2)
+    }
+
+    #[test]
+    fn test_chains_demodulate_separate_channels() {
+        const RATE: u32 = 240_000;
+        const LEN: usize = 4800;
+        const BUFFERS: usize = 20;
+        const DECIMATION: usize = 7;
+        // Chain A is heard at the audio rate
+        const AUDIO_LEN: usize = LEN * AUDIO_RATE as usize / RATE as usize;
+
+        let state = AppState::new_shared();
+        {
+            let mut state = state.write();
+            state.sdr.sample_rate = RATE;
+            state.decoder.mode = DemodMode::FmNarrow;
+            state.channels.offset_a = 50_000;
+            state.channels.offset_b = -60_000;
+            state.channels.mode_b = DemodMode::FmNarrow;
+        }
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (samples_tx, samples_rx) = crossbeam::channel::unbounded();
+        let (recorder_tx, _recorder_rx) = crossbeam::channel::unbounded();
+        let (producer, mut consumer) = HeapRb::<f32>::new(BUFFERS * LEN).split();
+
+        let captured = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let sink = captured.clone();
+        let registry = DecoderRegistry::with_factory(move |selection| {
+            if selection.mode == DemodMode::Raw {
+                return Vec::new();
+            }
+            vec![Box::new(CaptureDecoder(sink.clone())) as Box<dyn Decoder>]
+        });
+        let (tap, _decoder_rx) = decoder_channel();
+        let (channel_b_tap, channel_b_rx) = decoder_channel();
+        let decoder_thread = start_decoder_thread(
+            state.clone(),
+            Chain::A,
+            registry,
+            channel_b_rx,
+            message_log(),
+            None,
+            None,
+            shutdown.clone(),
+        );
+        let outputs = DspOutputs {
+            audio: Some(producer),
+            audible: Arc::new(AtomicBool::new(true)),
+            speaker_rate: Arc::new(AtomicU32::new(AUDIO_RATE)),
+            streams: Vec::new(),
+            decoder_tap: tap,
+            channel_b_tap,
+            recorder_tx,
+        };
+        let dsp_thread = start_dsp_thread(state.clone(), samples_rx, outputs, shutdown.clone());
+
+        // A 1 kHz tone at +50 kHz and a 2.5 kHz tone at -60 kHz, both with
+        // 3 kHz deviation
+        let carriers = [(50_000.0, 1_000.0), (-60_000.0, 2_500.0)];
+        let mut phases = [0.0f64; 2];
+        for block in 0..BUFFERS {
+            let samples: Vec<Complex<f32>> = (0..LEN)
+                .map(|i| {
+                    let t = (block * LEN + i) as f64 / RATE as f64;
+                    let mut sample = Complex::new(0.0, 0.0);
+                    for ((carrier, tone), phase) in carriers.iter().zip(phases.iter_mut()) {
+                        let deviation = 3_000.0 * (std::f64::consts::TAU * tone * t).sin();
+                        *phase += std::f64::consts::TAU * (carrier + deviation) / RATE as f64;
+                        sample += Complex::from_polar(0.4, *phase as f32);
+                    }
+                    sample
+                })
+                .collect();
+            samples_tx.send(SampleBuffer::new(samples, RATE)).unwrap();
+            // Give the decoder thread time to ask for chain B's audio
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let start = Instant::now();
+        while consumer.occupied_len() < BUFFERS * AUDIO_LEN - AUDIO_LEN
+            || captured.lock().len() < BUFFERS * LEN / 2 / DECIMATION
+        {
+            assert!(start.elapsed() < Duration::from_secs(2), "chains stalled");
+            thread::sleep(Duration::from_millis(5));
+        }
+        shutdown.store(true, Ordering::Relaxed);
+        dsp_thread.join().unwrap();
+        decoder_thread.join().unwrap();
+
+        // Skip the filters settling
+        let heard: Vec<f32> = consumer.pop_iter().skip(2 * AUDIO_LEN).collect();
+        // Chain B's comes decimated
+        let decoded = captured.lock().split_off(2 * LEN / DECIMATION);
+        let rate = AUDIO_RATE as f32;
+        assert!(tone_power(&heard, 1_000.0, rate) > 10.0 * tone_power(&heard, 2_500.0, rate));
+        let rate_b = decimated_rate(DemodMode::FmNarrow, RATE);
+        assert_eq!(rate_b, RATE / DECIMATION as u32);
+        let rate_b = rate_b as f32;
+        assert!(
+            tone_power(&decoded, 2_500.0, rate_b) > 10.0 * tone_power(&decoded, 1_000.0, rate_b)
+        );
+    }
+
+    #[test]
+    fn test_monitor_off_keeps_decoding() {
+        const LEN: usize = 4800;
+
+        let state = AppState::new_shared();
+        state.write().decoder.mode = DemodMode::Cw;
+        state.write().ui.monitor = false;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (samples_tx, samples_rx) = crossbeam::channel::unbounded();
+        let (recorder_tx, _recorder_rx) = crossbeam::channel::unbounded();
+        let (producer, consumer) = HeapRb::<f32>::new(4 * LEN).split();
+        let audible = Arc::new(AtomicBool::new(true));
+
+        let captured = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let sink = captured.clone();
+        let registry = DecoderRegistry::with_factory(move |_| {
+            vec![Box::new(CaptureDecoder(sink.clone())) as Box<dyn Decoder>]
+        });
+        let (tap, decoder_rx) = decoder_channel();
+        let (channel_b_tap, _channel_b_rx) = decoder_channel();
+        let decoder_thread = start_decoder_thread(
+            state.clone(),
+            Chain::A,
+            registry,
+            decoder_rx,
+            message_log(),
+            None,
+            None,
+            shutdown.clone(),
+        );
+        let outputs = DspOutputs {
+            audio: Some(producer),
+            audible: audible.clone(),
+            speaker_rate: Arc::new(AtomicU32::new(AUDIO_RATE)),
+            streams: Vec::new(),
+            decoder_tap: tap,
+            channel_b_tap,
+            recorder_tx,
+        };
+        let dsp_thread = start_dsp_thread(state.clone(), samples_rx, outputs, shutdown.clone());
+        // Feed buffers until `done`, giving the decoder thread time to ask
+        // for audio
+        let feed_until = |done: &dyn Fn() -> bool| {
+            let start = Instant::now();
+            while !done() {
+                assert!(start.elapsed() < Duration::from_secs(2), "timed out");
+                let samples = vec![Complex::new(0.1, 0.0); LEN];
+                samples_tx.send(SampleBuffer::new(samples, 2_048_000)).unwrap();
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        // The decoder hears it, the speaker doesn't
+        feed_until(&|| !captured.lock().is_empty());
+        assert!(!audible.load(Ordering::Relaxed));
+        assert!(consumer.is_empty());
+
+        state.write().ui.monitor = true;
+        feed_until(&|| !consumer.is_empty());
+        assert!(audible.load(Ordering::Relaxed));
+
+        // Raw mode has nothing to hear
+        state.write().decoder.mode = DemodMode::Raw;
+        feed_until(&|| !audible.load(Ordering::Relaxed));
+
+        shutdown.store(true, Ordering::Relaxed);
+        dsp_thread.join().unwrap();
+        decoder_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_test_tone_reaches_streams() {
+        let state = AppState::new_shared();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (_samples_tx, samples_rx) = crossbeam::channel::unbounded::<SampleBuffer>();
+        let (recorder_tx, _recorder_rx) = crossbeam::channel::unbounded();
+        let (stream_tx, stream_rx) = crossbeam::channel::unbounded();
+        let (tap, _decoder_rx) = decoder_channel();
+        let (channel_b_tap, _channel_b_rx) = decoder_channel();
+        let outputs = DspOutputs::<ringbuf::HeapProd<f32>> {
+            audio: None,
+            audible: Arc::new(AtomicBool::new(false)),
+            speaker_rate: Arc::new(AtomicU32::new(AUDIO_RATE)),
+            streams: vec![stream_tx],
+            decoder_tap: tap,
+            channel_b_tap,
+            recorder_tx,
+        };
+        let dsp_thread = start_dsp_thread(state.clone(), samples_rx, outputs, shutdown.clone());
+
+        // With the receiver silent, the tone alone goes to the streams
+        state.write().ui.test_tone = Some(Instant::now() + Duration::from_millis(500));
+        let start = Instant::now();
+        let mut streamed = Vec::new();
+        while streamed.len() < AUDIO_RATE as usize / 4 {
+            assert!(start.elapsed() < Duration::from_secs(2), "timed out");
+            if let Ok(audio) = stream_rx.recv_timeout(Duration::from_millis(100)) {
+                streamed.extend(audio);
+            }
+        }
+        let level = 10f32.powf(crate::dsp::tone::TEST_TONE_LEVEL / 20.0);
+        let peak = streamed.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!((peak - level).abs() < 0.01, "{}", peak);
+
+        shutdown.store(true, Ordering::Relaxed);
+        dsp_thread.join().unwrap();
+    }
 }
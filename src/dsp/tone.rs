@@ -0,0 +1,169 @@
+//! Test tone for checking the audio path without the receiver, and the
+//! beeps that announce alerts
+//!
+//! Each plays at the speaker over the ducked program audio (see
+//! [`super::mixer`]), and plays even with no program audio at all, so a
+//! silent speaker with the tone playing points at the audio output rather
+//! than the radio chain. The test tone goes to the streams the same way.
+
+use crate::audio::latency::AUDIO_RATE;
+use std::time::Duration;
+
+/// Test tone frequency in Hz
+pub const TEST_TONE_FREQ: f32 = 1000.0;
+/// Test tone level in dBFS
+pub const TEST_TONE_LEVEL: f32 = -12.0;
+/// How long the test tone plays
+pub const TEST_TONE_DURATION: Duration = Duration::from_secs(2);
+/// The two tones of the EAS attention signal, sounded together for a SAME
+/// alert
+pub const ALERT_FREQS: [f32; 2] = [853.0, 960.0];
+/// How long a beep takes to rise and to fall, so it doesn't click
+const BEEP_EDGE: Duration = Duration::from_millis(5);
+
+/// Sine generator that carries its phase from one buffer to the next
+#[derive(Debug, Clone)]
+pub struct ToneGenerator {
+    /// Phase in cycles, 0 to 1
+    phase: f64,
+    /// Cycles per sample
+    step: f64,
+    amplitude: f32,
+}
+
+impl ToneGenerator {
+    /// A `freq` Hz sine at `level` dBFS, sampled at `sample_rate`
+    pub fn new(freq: f32, sample_rate: u32, level: f32) -> Self {
+        Self {
+            phase: 0.0,
+            step: freq as f64 / sample_rate.max(1) as f64,
+            amplitude: 10f32.powf(level / 20.0),
+        }
+    }
+
+    /// The next `count` samples
+    pub fn generate(&mut self, count: usize) -> Vec<f32> {
+        (0..count)
+            .map(|_| {
+                let sample = (std::f64::consts::TAU * self.phase).sin() as f32 * self.amplitude;
+                self.phase = (self.phase + self.step).fract();
+                sample
+            })
+            .collect()
+    }
+}
+
+/// `duration` of the test tone at the audio rate
+pub fn test_tone(duration: Duration) -> Vec<f32> {
+    ToneGenerator::new(TEST_TONE_FREQ, AUDIO_RATE, TEST_TONE_LEVEL).generate(samples(duration))
+}
+
+/// A SAME alert's beeps: three bursts of the attention signal
+pub fn alert_tones() -> Vec<f32> {
+    let mut tones = Vec::new();
+    for _ in 0..3 {
+        tones.extend(beep(&ALERT_FREQS, Duration::from_millis(300)));
+        tones.resize(tones.len() + samples(Duration::from_millis(100)), 0.0);
+    }
+    tones
+}
+
+/// Two rising beeps for the priority channel coming up
+pub fn priority_cue() -> Vec<f32> {
+    let mut cue = beep(&[660.0], Duration::from_millis(100));
+    cue.resize(cue.len() + samples(Duration::from_millis(50)), 0.0);
+    cue.extend(beep(&[880.0], Duration::from_millis(150)));
+    cue
+}
+
+/// `freqs` together for `duration`, peaking no higher than the test tone,
+/// faded in and out over [`BEEP_EDGE`]
+fn beep(freqs: &[f32], duration: Duration) -> Vec<f32> {
+    let len = samples(duration);
+    let level = TEST_TONE_LEVEL - 20.0 * (freqs.len().max(1) as f32).log10();
+    let mut beep = vec![0.0; len];
+    for &freq in freqs {
+        let tone = ToneGenerator::new(freq, AUDIO_RATE, level).generate(len);
+        for (sum, sample) in beep.iter_mut().zip(tone) {
+            *sum += sample;
+        }
+    }
+    let edge = samples(BEEP_EDGE).min(len / 2).max(1);
+    for (i, sample) in beep.iter_mut().enumerate() {
+        let from_edge = i.min(len - 1 - i);
+        if from_edge < edge {
+            *sample *= from_edge as f32 / edge as f32;
+        }
+    }
+    beep
+}
+
+/// Samples in `duration` at the audio rate
+fn samples(duration: Duration) -> usize {
+    (duration.as_secs_f64() * AUDIO_RATE as f64).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sign changes from negative to non-negative
+    fn rising_crossings(samples: &[f32]) -> usize {
+        samples
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count()
+    }
+
+    #[test]
+    fn test_frequency_and_level() {
+        // One second in uneven pieces: the phase carries across them
+        let mut generator = ToneGenerator::new(1000.0, 48_000, TEST_TONE_LEVEL);
+        let mut samples = Vec::new();
+        for count in [1, 4_799, 12_345, 30_855] {
+            samples.extend(generator.generate(count));
+        }
+        assert_eq!(samples.len(), 48_000);
+        let crossings = rising_crossings(&samples);
+        assert!((999..=1000).contains(&crossings), "{} crossings", crossings);
+
+        // -12 dBFS peaks
+        let peak = samples.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!((20.0 * peak.log10() - TEST_TONE_LEVEL).abs() < 0.01);
+
+        // No jump where the pieces meet
+        let steps = samples.windows(2).map(|w| (w[1] - w[0]).abs());
+        let max_step = steps.fold(0.0f32, f32::max);
+        assert!(max_step < 0.26 * 2.0 * std::f32::consts::PI * 1000.0 / 48_000.0 + 1e-4);
+
+        let mut odd = ToneGenerator::new(440.0, 8_000, 0.0);
+        assert!((4_399..=4_400).contains(&rising_crossings(&odd.generate(80_000))));
+    }
+
+    #[test]
+    fn test_alert_tones() {
+        let peak = |samples: &[f32]| samples.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        let level = 10f32.powf(TEST_TONE_LEVEL / 20.0);
+        assert_eq!(test_tone(TEST_TONE_DURATION).len(), 96_000);
+
+        // Three 300 ms bursts, each followed by 100 ms of quiet
+        let alert = alert_tones();
+        assert_eq!(alert.len(), 57_600);
+        for burst in alert.chunks(19_200) {
+            let (tones, quiet) = burst.split_at(14_400);
+            assert!(quiet.iter().all(|&x| x == 0.0));
+            // No louder than the test tone, and without clicks at the ends
+            assert!(peak(tones) <= level + 1e-3);
+            assert!(peak(tones) > level / 2.0);
+            assert!(tones[0].abs() < 1e-3 && tones[14_399].abs() < 0.01);
+        }
+
+        // The priority cue rises from its first beep to its second
+        let cue = priority_cue();
+        assert_eq!(cue.len(), 14_400);
+        let (first, second) = (&cue[..4_800], &cue[7_200..]);
+        assert!(cue[4_800..7_200].iter().all(|&x| x == 0.0));
+        assert!((65..=66).contains(&rising_crossings(first)));
+        assert!((131..=132).contains(&rising_crossings(second)));
+    }
+}
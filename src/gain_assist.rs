@@ -0,0 +1,273 @@
+//! Gain assistant
+//!
+//! Steps the tuner through its gain table, dwelling on each gain to measure
+//! the tuned channel's SNR (its peak over the spectrum's median level) and
+//! how much of the raw IQ is clipping, then settles on the gain with the
+//! best SNR that stays clear of clipping. Past a point more gain only
+//! raises the noise along with the signal, so the lowest gain within
+//! `SNR_MARGIN` of the best is chosen, leaving headroom for strong signals.
+//!
+//! The sweep runs on a thread of its own and changes the gain through the
+//! normal command channel, like the band scan. Cancelling it puts the gain
+//! back as it was.
+
+use crate::dsp::channelizer;
+use crate::scan::{peak_in, step_bins};
+use crate::state::{AppState, SharedState};
+use crate::types::Command;
+use crossbeam::channel::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time spent measuring each gain
+const DWELL: Duration = Duration::from_millis(300);
+/// Time for the tuner to settle after a gain change before frames count
+const SETTLE: Duration = Duration::from_millis(50);
+/// Longest wait for a gain change to show up in state
+const CHANGE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Share of clipped IQ samples above which a gain is overloading
+pub const CLIP_LIMIT: f32 = 0.001;
+/// SNR in dB a lower gain may give up against the best
+const SNR_MARGIN: f32 = 1.0;
+
+/// What was measured at one gain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GainSample {
+    /// Tenths of dB
+    pub gain: i32,
+    /// Channel peak over the median level in dB
+    pub snr: f32,
+    /// Share of IQ samples clipping
+    pub clip: f32,
+}
+
+/// The gain to settle on: the lowest within `SNR_MARGIN` of the best SNR
+/// among those clipping no more than `clip_limit`, or the least clipping
+/// one when they all clip
+pub fn choose_gain(samples: &[GainSample], clip_limit: f32) -> Option<GainSample> {
+    let clean: Vec<&GainSample> = samples.iter().filter(|s| s.clip <= clip_limit).collect();
+    let Some(best) = clean.iter().map(|s| s.snr).reduce(f32::max) else {
+        return samples
+            .iter()
+            .min_by(|a, b| a.clip.total_cmp(&b.clip).then(a.gain.cmp(&b.gain)))
+            .copied();
+    };
+    clean
+        .into_iter()
+        .filter(|s| s.snr >= best - SNR_MARGIN)
+        .min_by_key(|s| s.gain)
+        .copied()
+}
+
+/// Peak of the `low..high` Hz span (offsets from the center) over the
+/// median level of a centered dB spectrum across `sample_rate`, leaving out
+/// the DC spike where the span has other bins
+pub fn channel_snr(fft: &[f32], sample_rate: u32, low: i32, high: i32) -> Option<f32> {
+    if fft.is_empty() {
+        return None;
+    }
+    let center = (low as i64 + high as i64) / 2;
+    let bins = step_bins(center, high.abs_diff(low), sample_rate, fft.len());
+    let peak = peak_in(fft, bins)?;
+    let mut sorted = fft.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    Some(peak - sorted[sorted.len() / 2])
+}
+
+/// Start a gain sweep on the receiver with `state`, changing its gain
+/// through `command_tx`; false if one is already running
+///
+/// Progress shows in `SdrState::gain_sweep` and the outcome in the status
+/// message; setting `SdrState::gain_sweep_cancel` stops it.
+pub fn start_gain_sweep(state: SharedState, command_tx: Sender<Command>) -> bool {
+    {
+        let mut state_guard = state.write();
+        if state_guard.sdr.gain_sweep.is_some() {
+            return false;
+        }
+        state_guard.sdr.gain_sweep = Some("Gain sweep starting".to_string());
+        state_guard.sdr.gain_sweep_cancel = false;
+    }
+
+    thread::spawn(move || {
+        log::info!("Gain sweep started");
+        let message = sweep(&state, &command_tx);
+        log::info!("{}", message);
+        let mut state_guard = state.write();
+        state_guard.sdr.gain_sweep = None;
+        state_guard.ui.status_message = message;
+    });
+    true
+}
+
+/// Measure every gain and apply the best, returning how it went
+fn sweep(state: &SharedState, command_tx: &Sender<Command>) -> String {
+    let (gains, restore) = {
+        let state = state.read();
+        let restore = if state.sdr.tuner_agc {
+            Command::SetTunerAgc(true)
+        } else {
+            Command::SetTunerGain(state.sdr.tuner_gain)
+        };
+        (state.sdr.capabilities.gain_steps(), restore)
+    };
+    let cancelled = || state.read().sdr.gain_sweep_cancel;
+
+    let mut samples = Vec::with_capacity(gains.len());
+    for (n, &gain) in gains.iter().enumerate() {
+        state.write().sdr.gain_sweep = Some(format!(
+            "Gain sweep {:.1} dB ({}/{}), Esc to cancel",
+            gain as f32 / 10.0,
+            n + 1,
+            gains.len()
+        ));
+        if command_tx.send(Command::SetTunerGain(gain)).is_err() {
+            return "Gain sweep stopped".to_string();
+        }
+
+        // Wait for the change, then for the tuner to settle
+        let asked = Instant::now();
+        while asked.elapsed() < CHANGE_TIMEOUT && !cancelled() {
+            let sdr = &state.read().sdr;
+            if sdr.tuner_gain == gain && !sdr.tuner_agc {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread::sleep(SETTLE);
+
+        let measured = measure(state, gain, &cancelled);
+        if cancelled() {
+            let _ = command_tx.send(restore);
+            return "Gain sweep cancelled, gain restored".to_string();
+        }
+        match measured {
+            Some(sample) => {
+                log::debug!("Gain sweep: {:?}", sample);
+                samples.push(sample);
+            }
+            None => log::warn!("Gain sweep: no spectrum at {:.1} dB", gain as f32 / 10.0),
+        }
+    }
+
+    match choose_gain(&samples, CLIP_LIMIT) {
+        Some(best) => {
+            let _ = command_tx.send(Command::SetTunerGain(best.gain));
+            format!(
+                "Gain set to {:.1} dB (SNR {:.1} dB, {:.2}% clipping)",
+                best.gain as f32 / 10.0,
+                best.snr,
+                best.clip * 100.0
+            )
+        }
+        None => {
+            let _ = command_tx.send(restore);
+            "Gain sweep measured nothing, gain restored".to_string()
+        }
+    }
+}
+
+/// Average SNR and clipping over `DWELL` worth of new spectrum frames; None
+/// if none came or the sweep was cancelled
+fn measure(state: &SharedState, gain: i32, cancelled: &dyn Fn() -> bool) -> Option<GainSample> {
+    let mut last_frame = state.read().spectrum.frames;
+    let (mut snr, mut clip, mut frames) = (0.0, 0.0, 0);
+    let until = Instant::now() + DWELL;
+    while Instant::now() < until {
+        if cancelled() {
+            return None;
+        }
+        thread::sleep(Duration::from_millis(5));
+
+        let state = state.read();
+        if state.spectrum.frames == last_frame {
+            continue;
+        }
+        last_frame = state.spectrum.frames;
+        let (low, high) = tuned_span(&state);
+        let sample_rate = state.sdr.sample_rate;
+        if let Some(frame_snr) = channel_snr(&state.spectrum.fft_data, sample_rate, low, high) {
+            snr += frame_snr;
+            clip += state.stats.clip_fraction;
+            frames += 1;
+        }
+    }
+    (frames > 0).then(|| GainSample {
+        gain,
+        snr: snr / frames as f32,
+        clip: clip / frames as f32,
+    })
+}
+
+/// Chain A's passband as offsets from the center, or the whole band for
+/// modes without one
+fn tuned_span(state: &AppState) -> (i32, i32) {
+    let offset = state.channels.offset_a;
+    match channelizer::passband(state.decoder.mode, state.decoder.filter_width) {
+        Some((low, high)) => (offset + low, offset + high),
+        None => {
+            let half = (state.sdr.sample_rate / 2) as i32;
+            (-half, half)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(gain: i32, snr: f32, clip: f32) -> GainSample {
+        GainSample { gain, snr, clip }
+    }
+
+    #[test]
+    fn test_choose_gain_prefers_best_snr_without_clipping() {
+        let sweep = [
+            sample(0, 3.0, 0.0),
+            sample(125, 12.0, 0.0),
+            sample(297, 20.0, 0.0),
+            sample(402, 24.0, 0.0005),
+            // Overloaded: the SNR looks better but the front end is clipping
+            sample(496, 26.0, 0.02),
+        ];
+        assert_eq!(choose_gain(&sweep, CLIP_LIMIT).unwrap().gain, 402);
+    }
+
+    #[test]
+    fn test_choose_gain_takes_lowest_on_a_plateau() {
+        // Past 29.7 dB more gain only raises the noise with the signal
+        let sweep = [
+            sample(197, 15.0, 0.0),
+            sample(297, 21.5, 0.0),
+            sample(372, 22.0, 0.0),
+            sample(445, 22.3, 0.0),
+        ];
+        assert_eq!(choose_gain(&sweep, CLIP_LIMIT).unwrap().gain, 297);
+    }
+
+    #[test]
+    fn test_choose_gain_when_everything_clips() {
+        let sweep = [
+            sample(0, 10.0, 0.01),
+            sample(90, 11.0, 0.01),
+            sample(200, 12.0, 0.05),
+        ];
+        assert_eq!(choose_gain(&sweep, CLIP_LIMIT).unwrap().gain, 0);
+        assert_eq!(choose_gain(&[], CLIP_LIMIT), None);
+    }
+
+    #[test]
+    fn test_channel_snr() {
+        // 1 kHz bins across 1.024 MHz, noise at -80 dB
+        let mut fft = vec![-80.0; 1024];
+        // A signal 100 kHz up and the DC spike
+        fft[612] = -40.0;
+        fft[512] = -20.0;
+        assert_eq!(channel_snr(&fft, 1_024_000, 95_000, 105_000), Some(40.0));
+        // Nothing there
+        assert_eq!(channel_snr(&fft, 1_024_000, -105_000, -95_000), Some(0.0));
+        // The DC spike isn't taken for a signal at the center
+        assert_eq!(channel_snr(&fft, 1_024_000, -8_000, 8_000), Some(0.0));
+        assert_eq!(channel_snr(&[], 1_024_000, -8_000, 8_000), None);
+    }
+}
@@ -0,0 +1,720 @@
+//! HTTP status server
+//!
+//! A small HTTP/1.1 server over `TcpListener` for monitoring: `GET /status`
+//! returns each receiver's state as JSON and `GET /metrics` the numeric
+//! values in the Prometheus text format. Each receiver's values come from a
+//! single read of its state, so they are consistent with each other.
+//!
+//! `GET /` is a web page drawing a live waterfall from the `/spectrum`
+//! WebSocket, e.g. for a phone on the roof while aiming an antenna.
+
+pub mod websocket;
+
+use crate::state::{AppState, SharedState};
+use crate::util::net::accept_loop;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How long a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest request head read; the rest is ignored
+const MAX_REQUEST: usize = 8192;
+/// Prefix of every metric name
+const METRIC_PREFIX: &str = "rtlsdr_";
+/// The waterfall page served at `/`
+const INDEX_HTML: &str = include_str!("index.html");
+
+/// One receiver's state at a moment
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub receiver: usize,
+    /// RFC 3339 time of the snapshot
+    pub time: String,
+    pub running: bool,
+    pub device_serial: Option<String>,
+    /// Center frequency in Hz
+    pub frequency: u32,
+    /// Frequency chain A is tuned to, in Hz
+    pub tuned_frequency: u32,
+    pub sample_rate: u32,
+    pub mode: &'static str,
+    pub filter_width: Option<u32>,
+    /// Manual tuner gain in dB, None for auto
+    pub gain_db: Option<f32>,
+    pub ppm: i32,
+    /// Signal level in dBFS
+    pub signal_level: f32,
+    pub squelch_level: Option<f32>,
+    pub squelch_open: bool,
+    pub decoder: DecoderStatus,
+    pub recording: RecordingStatus,
+    pub streaming: StreamingStatus,
+    pub stats: StatsStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DecoderStatus {
+    /// Messages decoded since start
+    pub messages: u64,
+    /// Messages decoded in the last minute
+    pub messages_per_minute: usize,
+    pub aircraft: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingStatus {
+    pub active: bool,
+    pub file: Option<String>,
+    pub bytes: u64,
+    pub seconds: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamingStatus {
+    pub port: Option<u16>,
+    pub clients: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsStatus {
+    /// DSP thread's share of real time
+    pub dsp_load: Option<f32>,
+    pub fft_us: Option<f32>,
+    pub demod_us: Option<f32>,
+    pub decode_us: Option<f32>,
+    pub buffers_per_sec: f32,
+    pub samples_per_sec: f32,
+    /// Smoothed share of clipped IQ samples
+    pub clipping: Option<f32>,
+    pub sdr_dropped: u64,
+    pub decoder_dropped: u64,
+    pub recorder_dropped: u64,
+}
+
+impl Status {
+    /// The status of `state`, receiver number `receiver`, at `now`
+    pub fn take(receiver: usize, state: &AppState, now: DateTime<Utc>) -> Self {
+        let minute_ago = now - chrono::Duration::minutes(1);
+        let stats = &state.stats;
+        Self {
+            receiver,
+            time: now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            running: state.sdr.is_running,
+            device_serial: state.sdr.device_serial.clone(),
+            frequency: state.sdr.frequency,
+            tuned_frequency: state.live_vfo().frequency,
+            sample_rate: state.sdr.sample_rate,
+            mode: state.decoder.mode.name(),
+            filter_width: state.decoder.filter_width,
+            gain_db: (state.sdr.tuner_gain >= 0).then(|| state.sdr.tuner_gain as f32 / 10.0),
+            ppm: state.sdr.ppm_error,
+            signal_level: state.decoder.signal_level,
+            squelch_level: state.decoder.squelch_level,
+            squelch_open: state.decoder.squelch_open,
+            decoder: DecoderStatus {
+                messages: state.decoder.received,
+                messages_per_minute: state
+                    .decoder
+                    .messages
+                    .iter()
+                    .rev()
+                    .take_while(|message| message.timestamp > minute_ago)
+                    .count(),
+                aircraft: state.decoder.aircraft.len(),
+            },
+            recording: RecordingStatus {
+                active: state.recording.is_recording,
+                file: state
+                    .recording
+                    .file_path
+                    .as_ref()
+                    .map(|path| path.display().to_string()),
+                bytes: state.recording.bytes_written(),
+                seconds: state.recording.elapsed_secs(),
+            },
+            streaming: StreamingStatus {
+                port: state.streaming.port,
+                clients: state.streaming.clients,
+            },
+            stats: StatsStatus {
+                dsp_load: stats.dsp_load.value(),
+                fft_us: stats.fft_us.value(),
+                demod_us: stats.demod_us.value(),
+                decode_us: stats.decode_us.value(),
+                buffers_per_sec: stats.buffers_per_sec,
+                samples_per_sec: stats.samples_per_sec,
+                clipping: stats.clipping.value(),
+                sdr_dropped: stats.sdr_dropped,
+                decoder_dropped: stats.decoder_dropped,
+                recorder_dropped: stats.recorder_dropped,
+            },
+        }
+    }
+}
+
+/// Every receiver's status, reading each state once
+pub fn snapshot(states: &[SharedState]) -> Vec<Status> {
+    let now = Utc::now();
+    states
+        .iter()
+        .enumerate()
+        .map(|(receiver, state)| Status::take(receiver, &state.read(), now))
+        .collect()
+}
+
+/// The `/status` body
+pub fn status_json(statuses: &[Status]) -> String {
+    serde_json::json!({ "receivers": statuses }).to_string()
+}
+
+/// A metric: name without the prefix, type, help text and value, if any
+type Metric = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&Status) -> Option<f64>,
+);
+
+const METRICS: &[Metric] = &[
+    ("up", "gauge", "Whether the receiver is running", |s| {
+        Some(s.running as u8 as f64)
+    }),
+    ("frequency_hz", "gauge", "Center frequency", |s| {
+        Some(s.frequency as f64)
+    }),
+    (
+        "tuned_frequency_hz",
+        "gauge",
+        "Frequency being demodulated",
+        |s| Some(s.tuned_frequency as f64),
+    ),
+    ("sample_rate_hz", "gauge", "IQ sample rate", |s| {
+        Some(s.sample_rate as f64)
+    }),
+    ("gain_db", "gauge", "Manual tuner gain", |s| {
+        s.gain_db.map(f64::from)
+    }),
+    ("signal_level_dbfs", "gauge", "Signal level", |s| {
+        Some(s.signal_level as f64)
+    }),
+    (
+        "squelch_open",
+        "gauge",
+        "Whether the squelch is open",
+        |s| Some(s.squelch_open as u8 as f64),
+    ),
+    (
+        "decoded_messages_total",
+        "counter",
+        "Messages decoded",
+        |s| Some(s.decoder.messages as f64),
+    ),
+    (
+        "decoded_messages_per_minute",
+        "gauge",
+        "Messages decoded in the last minute",
+        |s| Some(s.decoder.messages_per_minute as f64),
+    ),
+    ("aircraft", "gauge", "Aircraft being tracked", |s| {
+        Some(s.decoder.aircraft as f64)
+    }),
+    (
+        "recording",
+        "gauge",
+        "Whether a recording is running",
+        |s| Some(s.recording.active as u8 as f64),
+    ),
+    (
+        "recording_bytes",
+        "gauge",
+        "Bytes in the running recording",
+        |s| Some(s.recording.bytes as f64),
+    ),
+    ("stream_clients", "gauge", "Audio stream listeners", |s| {
+        Some(s.streaming.clients as f64)
+    }),
+    (
+        "dsp_load_ratio",
+        "gauge",
+        "DSP time as a share of real time",
+        |s| s.stats.dsp_load.map(f64::from),
+    ),
+    (
+        "fft_microseconds",
+        "gauge",
+        "Spectrum FFT time per buffer",
+        |s| s.stats.fft_us.map(f64::from),
+    ),
+    (
+        "demod_microseconds",
+        "gauge",
+        "Demodulation time per buffer",
+        |s| s.stats.demod_us.map(f64::from),
+    ),
+    (
+        "decode_microseconds",
+        "gauge",
+        "Decoding time per buffer",
+        |s| s.stats.decode_us.map(f64::from),
+    ),
+    (
+        "buffers_per_second",
+        "gauge",
+        "IQ buffers processed per second",
+        |s| Some(s.stats.buffers_per_sec as f64),
+    ),
+    (
+        "samples_per_second",
+        "gauge",
+        "IQ samples processed per second",
+        |s| Some(s.stats.samples_per_sec as f64),
+    ),
+    (
+        "clipping_ratio",
+        "gauge",
+        "Smoothed share of clipped IQ samples",
+        |s| s.stats.clipping.map(f64::from),
+    ),
+    (
+        "sdr_dropped_total",
+        "counter",
+        "IQ buffers dropped by the SDR thread",
+        |s| Some(s.stats.sdr_dropped as f64),
+    ),
+    (
+        "decoder_dropped_total",
+        "counter",
+        "Buffers dropped before the decoder",
+        |s| Some(s.stats.decoder_dropped as f64),
+    ),
+    (
+        "recorder_dropped_total",
+        "counter",
+        "Buffers dropped before the recorder",
+        |s| Some(s.stats.recorder_dropped as f64),
+    ),
+];
+
+/// The `/metrics` body, in the Prometheus text exposition format
+pub fn metrics_text(statuses: &[Status]) -> String {
+    let mut text = String::new();
+    for (name, kind, help, value) in METRICS {
+        text.push_str(&format!("# HELP {}{} {}\n", METRIC_PREFIX, name, help));
+        text.push_str(&format!("# TYPE {}{} {}\n", METRIC_PREFIX, name, kind));
+        for status in statuses {
+            if let Some(value) = value(status) {
+                text.push_str(&format!(
+                    "{}{}{{receiver=\"{}\"}} {}\n",
+                    METRIC_PREFIX, name, status.receiver, value
+                ));
+            }
+        }
+    }
+    text
+}
+
+/// The parts of a request the server looks at
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    /// Header names in lowercase, with their values
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Parse a request head, leaving out anything that isn't understood
+    fn parse(head: &str) -> Self {
+        let mut lines = head.lines();
+        let mut parts = lines.next().unwrap_or("").split_whitespace();
+        let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let headers = lines
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            headers,
+        }
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The client's key if this asks to switch to a WebSocket
+    fn websocket_key(&self) -> Option<&str> {
+        let upgrade = self.header("upgrade")?;
+        upgrade
+            .eq_ignore_ascii_case("websocket")
+            .then(|| self.header("sec-websocket-key"))
+            .flatten()
+    }
+
+    /// The receiver a `receiver=N` query names, the first if none
+    fn receiver(&self) -> Option<usize> {
+        match self
+            .query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("receiver="))
+        {
+            Some(receiver) => receiver.parse().ok(),
+            None => Some(0),
+        }
+    }
+}
+
+/// Status line, content type and body for a plain request
+fn respond(request: &Request, states: &[SharedState]) -> (&'static str, &'static str, String) {
+    const TEXT: &str = "text/plain; charset=utf-8";
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => ("200 OK", "text/html; charset=utf-8", INDEX_HTML.to_string()),
+        ("GET", "/status") => ("200 OK", "application/json", status_json(&snapshot(states))),
+        ("GET", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics_text(&snapshot(states)),
+        ),
+        ("GET", "/spectrum") => (
+            "400 Bad Request",
+            TEXT,
+            "Expected a WebSocket upgrade, for a receiver that exists\n".to_string(),
+        ),
+        ("GET", _) => (
+            "404 Not Found",
+            TEXT,
+            "Try /, /status or /metrics\n".to_string(),
+        ),
+        _ => (
+            "405 Method Not Allowed",
+            TEXT,
+            "Only GET is supported\n".to_string(),
+        ),
+    }
+}
+
+/// Answer one request on `stream`, then close it, unless it opens a
+/// WebSocket
+fn handle(
+    mut stream: TcpStream,
+    states: &[SharedState],
+    shutdown: &Arc<AtomicBool>,
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let request = Request::parse(&String::from_utf8_lossy(&head));
+
+    if request.method == "GET" && request.path == "/spectrum" {
+        let state = request.receiver().and_then(|receiver| states.get(receiver));
+        if let (Some(key), Some(state)) = (request.websocket_key(), state) {
+            return websocket::start(stream, key, state.clone(), shutdown.clone());
+        }
+    }
+
+    let (status, content_type, body) = respond(&request, states);
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Serve status requests on `listener` until `shutdown` is set
+pub fn serve(listener: TcpListener, states: Vec<SharedState>, shutdown: Arc<AtomicBool>) {
+    accept_loop(listener, "HTTP server", &shutdown, |stream| {
+        handle(stream, &states, &shutdown)
+    });
+}
+
+/// Start the HTTP status server on `listener` for every receiver's state
+pub fn start_http_server(
+    listener: TcpListener,
+    states: Vec<SharedState>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<()> {
+    log::info!("HTTP status server started on {}", listener.local_addr()?);
+    thread::spawn(move || serve(listener, states, shutdown));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DecodedMessage, DemodMode};
+    use std::net::SocketAddr;
+    use std::sync::atomic::Ordering;
+
+    fn states() -> Vec<SharedState> {
+        let first = AppState::new_shared();
+        {
+            let mut state = first.write();
+            state.sdr.frequency = 162_000_000;
+            state.sdr.tuner_gain = 297;
+            state.decoder.mode = DemodMode::Ais;
+            state
+                .decoder
+                .add_message(DecodedMessage::new(DemodMode::Ais, "one".into()));
+            state
+                .decoder
+                .add_message(DecodedMessage::new(DemodMode::Ais, "two".into()));
+            state.stats.sdr_dropped = 3;
+            state.stats.dsp_load.update(0.25);
+            state.streaming.clients = 2;
+        }
+        vec![first, AppState::new_shared()]
+    }
+
+    /// Start a server on a free port, returning its address
+    fn server(states: Vec<SharedState>, shutdown: &Arc<AtomicBool>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = shutdown.clone();
+        thread::spawn(move || serve(listener, states, shutdown));
+        addr
+    }
+
+    /// Status line, headers and body of a response
+    fn get(addr: SocketAddr, request: &str) -> (String, String, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let (status, headers) = head.split_once("\r\n").unwrap();
+        (status.to_string(), headers.to_string(), body.to_string())
+    }
+
+    #[test]
+    fn test_status_endpoint() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let addr = server(states(), &shutdown);
+        let (status, headers, body) = get(addr, "GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(headers.contains("Content-Type: application/json"));
+        assert!(headers.contains(&format!("Content-Length: {}", body.len())));
+
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let receivers = json["receivers"].as_array().unwrap();
+        assert_eq!(receivers.len(), 2);
+        let first = &receivers[0];
+        for key in [
+            "receiver",
+            "time",
+            "running",
+            "frequency",
+            "tuned_frequency",
+            "sample_rate",
+            "mode",
+            "filter_width",
+            "gain_db",
+            "ppm",
+            "signal_level",
+            "squelch_level",
+            "squelch_open",
+            "decoder",
+            "recording",
+            "streaming",
+            "stats",
+        ] {
+            assert!(first.get(key).is_some(), "missing {}", key);
+        }
+        assert_eq!(first["frequency"], 162_000_000);
+        assert_eq!(first["mode"], "AIS");
+        assert!((first["gain_db"].as_f64().unwrap() - 29.7).abs() < 1e-4);
+        assert_eq!(first["decoder"]["messages"], 2);
+        assert_eq!(first["decoder"]["messages_per_minute"], 2);
+        assert_eq!(first["streaming"]["clients"], 2);
+        assert_eq!(first["stats"]["sdr_dropped"], 3);
+        assert_eq!(first["stats"]["dsp_load"], 0.25);
+        assert_eq!(receivers[1]["receiver"], 1);
+        assert!(receivers[1]["gain_db"].is_null());
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_metrics_endpoint() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let addr = server(states(), &shutdown);
+        let (status, headers, body) = get(addr, "GET /metrics HTTP/1.0\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(headers.contains("Content-Type: text/plain; version=0.0.4"));
+
+        // Every line is a comment naming a metric or a sample of one
+        let mut described = Vec::new();
+        for line in body.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let words: Vec<&str> = comment.splitn(3, ' ').collect();
+                assert!(matches!(words[0], "HELP" | "TYPE"), "{}", line);
+                assert!(words[1].starts_with(METRIC_PREFIX));
+                if words[0] == "TYPE" {
+                    assert!(matches!(words[2], "gauge" | "counter"), "{}", line);
+                    described.push(words[1].to_string());
+                }
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            let (name, labels) = series.split_once('{').unwrap();
+            assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+            assert!(described.last() == Some(&name.to_string()), "{}", line);
+            assert!(
+                labels.starts_with("receiver=\"") && labels.ends_with("\"}"),
+                "{}",
+                line
+            );
+            assert!(value.parse::<f64>().is_ok(), "{}", line);
+            if name.ends_with("_total") {
+                assert!(body.contains(&format!("# TYPE {} counter", name)));
+            }
+        }
+        assert!(body.contains("rtlsdr_frequency_hz{receiver=\"0\"} 162000000\n"));
+        assert!(body.contains("rtlsdr_sdr_dropped_total{receiver=\"0\"} 3\n"));
+        assert!(body.contains("rtlsdr_decoded_messages_per_minute{receiver=\"0\"} 2\n"));
+        // Values not known yet are left out
+        assert!(!body.contains("rtlsdr_gain_db{receiver=\"1\""));
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_other_requests() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let addr = server(states(), &shutdown);
+        let (status, headers, body) = get(addr, "GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(headers.contains("Content-Type: text/html"));
+        assert!(body.contains("/spectrum"));
+        assert_eq!(
+            get(addr, "GET /nothing HTTP/1.1\r\n\r\n").0,
+            "HTTP/1.1 404 Not Found"
+        );
+        // The spectrum only as a WebSocket
+        assert_eq!(
+            get(addr, "GET /spectrum HTTP/1.1\r\n\r\n").0,
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            get(addr, "POST /status HTTP/1.1\r\n\r\n").0,
+            "HTTP/1.1 405 Method Not Allowed"
+        );
+        // Query strings are ignored
+        assert_eq!(
+            get(addr, "GET /metrics?x=1 HTTP/1.1\r\n\r\n").0,
+            "HTTP/1.1 200 OK"
+        );
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_server_stops_on_shutdown() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let handle = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || serve(listener, states(), shutdown))
+        };
+        shutdown.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_request_parse() {
+        let request = Request::parse(
+            "GET /spectrum?receiver=1 HTTP/1.1\r\nHost: x\r\nUpgrade: WebSocket\r\n\
+             Sec-WebSocket-Key: abc==\r\n\r\n",
+        );
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("GET", "/spectrum")
+        );
+        assert_eq!(request.receiver(), Some(1));
+        assert_eq!(request.websocket_key(), Some("abc=="));
+
+        let request = Request::parse("GET /spectrum?receiver=x HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert_eq!(request.receiver(), None);
+        assert_eq!(request.websocket_key(), None);
+        assert_eq!(Request::parse("").receiver(), Some(0));
+    }
+
+    #[test]
+    fn test_spectrum_websocket() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let states = states();
+        let addr = server(states.clone(), &shutdown);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+            .write_all(
+                b"GET /spectrum HTTP/1.1\r\nHost: x\r\nUpgrade: websocket\r\n\
+                  Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .unwrap();
+        let mut head = Vec::new();
+        let mut byte = [0u8];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        // A new row arrives as a binary frame of the header and a byte a bin
+        states[0]
+            .write()
+            .spectrum
+            .add_fft_data(vec![-50.0; 64], Utc::now());
+        let mut frame_head = [0u8; 4];
+        stream.read_exact(&mut frame_head).unwrap();
+        assert_eq!(frame_head[..2], [0x82, 126]);
+        let len = u16::from_be_bytes([frame_head[2], frame_head[3]]) as usize;
+        let mut message = vec![0u8; len];
+        stream.read_exact(&mut message).unwrap();
+        let bins = crate::state::app_state::WATERFALL_WIDTH;
+        assert_eq!(len, websocket::HEADER_LEN + bins);
+        assert_eq!(u16::from_le_bytes([message[0], message[1]]) as usize, bins);
+        assert_eq!(message[2..6], 162_000_000u32.to_le_bytes());
+        assert!(message[websocket::HEADER_LEN..]
+            .iter()
+            .all(|&db| db as i8 == -50));
+
+        // Shutting down closes it
+        shutdown.store(true, Ordering::Relaxed);
+        let mut close = [0u8; 2];
+        stream.read_exact(&mut close).unwrap();
+        assert_eq!(close, [0x88, 0]);
+    }
+}
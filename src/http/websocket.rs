@@ -0,0 +1,209 @@
+//! WebSocket spectrum stream (RFC 6455)
+//!
+//! Each connection gets a thread that sends the newest waterfall row about
+//! ten times a second as a binary message: a 10-byte little-endian header of
+//! u16 bin count, u32 center frequency in Hz and u32 sample rate in Hz,
+//! then one i8 per bin in dB. Only the newest row is ever sent, so a client
+//! slower than the spectrum just misses rows rather than falling behind.
+
+use crate::state::{AppState, SharedState};
+use base64::Engine;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Appended to the client's key before hashing, per the RFC
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Time between messages, for about 10 frames per second
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+/// A client that can't take a message in this long is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Length of a spectrum message's header
+pub const HEADER_LEN: usize = 10;
+
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+
+/// The `Sec-WebSocket-Accept` value for a client's `Sec-WebSocket-Key`
+pub fn accept_key(key: &str) -> String {
+    let digest = sha1_smol::Sha1::from(format!("{}{}", key.trim(), HANDSHAKE_GUID)).digest();
+    base64::engine::general_purpose::STANDARD.encode(digest.bytes())
+}
+
+/// A final, unmasked frame as a server sends it
+pub fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// A spectrum message for `bins` in dB, rounded and clamped to i8
+pub fn spectrum_message(center_freq: u32, sample_rate: u32, bins: &[f32]) -> Vec<u8> {
+    let bins = &bins[..bins.len().min(u16::MAX as usize)];
+    let mut message = Vec::with_capacity(HEADER_LEN + bins.len());
+    message.extend_from_slice(&(bins.len() as u16).to_le_bytes());
+    message.extend_from_slice(&center_freq.to_le_bytes());
+    message.extend_from_slice(&sample_rate.to_le_bytes());
+    message.extend(
+        bins.iter()
+            .map(|&db| db.round().clamp(-128.0, 127.0) as i8 as u8),
+    );
+    message
+}
+
+/// A message with the newest waterfall row if one has arrived since row
+/// number `sent`, which is then updated
+pub fn latest_message(state: &AppState, sent: &mut u64) -> Option<Vec<u8>> {
+    let rows = state.spectrum.waterfall_rows;
+    if rows == *sent {
+        return None;
+    }
+    let row = *state.spectrum.get_waterfall_display(0, 1).first()?;
+    *sent = rows;
+    Some(spectrum_message(
+        state.sdr.frequency,
+        state.sdr.sample_rate,
+        &row.bins,
+    ))
+}
+
+/// Complete the handshake on `stream` and stream `state`'s spectrum to it
+/// from a thread of its own until it goes away or `shutdown` is set
+pub fn start(
+    mut stream: TcpStream,
+    key: &str,
+    state: SharedState,
+    shutdown: Arc<AtomicBool>,
+) -> std::io::Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    let peer = stream.peer_addr()?;
+    log::info!("Spectrum client connected from {}", peer);
+
+    thread::spawn(move || {
+        let mut sent = 0;
+        let result = loop {
+            if shutdown.load(Ordering::Relaxed) {
+                break stream.write_all(&frame(OPCODE_CLOSE, &[]));
+            }
+            let message = latest_message(&state.read(), &mut sent);
+            if let Some(message) = message {
+                if let Err(e) = stream.write_all(&frame(OPCODE_BINARY, &message)) {
+                    break Err(e);
+                }
+            }
+            thread::sleep(FRAME_INTERVAL);
+        };
+        match result {
+            Ok(()) => log::info!("Spectrum client {} closed", peer),
+            Err(e) => log::info!("Spectrum client {} disconnected: {}", peer, e),
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    /// Opcode and payload of a server frame, and the bytes it took
+    fn decode_frame(bytes: &[u8]) -> (u8, &[u8], usize) {
+        assert_eq!(bytes[0] & 0x80, 0x80, "not final");
+        assert_eq!(bytes[1] & 0x80, 0, "servers don't mask");
+        let (len, start) = match bytes[1] {
+            126 => (u16::from_be_bytes([bytes[2], bytes[3]]) as usize, 4),
+            127 => (
+                u64::from_be_bytes(bytes[2..10].try_into().unwrap()) as usize,
+                10,
+            ),
+            len => (len as usize, 2),
+        };
+        (bytes[0] & 0x0f, &bytes[start..start + len], start + len)
+    }
+
+    /// Center frequency, sample rate and bins of a spectrum message
+    fn decode_spectrum(message: &[u8]) -> (u32, u32, Vec<i8>) {
+        let bins = u16::from_le_bytes([message[0], message[1]]) as usize;
+        assert_eq!(message.len(), HEADER_LEN + bins);
+        (
+            u32::from_le_bytes(message[2..6].try_into().unwrap()),
+            u32::from_le_bytes(message[6..10].try_into().unwrap()),
+            message[HEADER_LEN..].iter().map(|&b| b as i8).collect(),
+        )
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // The example from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_frame_lengths() {
+        for len in [0, 125, 126, 1034, 0xffff, 0x10000] {
+            let payload = vec![7u8; len];
+            let bytes = frame(OPCODE_BINARY, &payload);
+            let (opcode, decoded, used) = decode_frame(&bytes);
+            assert_eq!(opcode, OPCODE_BINARY);
+            assert_eq!(decoded, payload);
+            assert_eq!(used, bytes.len());
+        }
+        assert_eq!(frame(OPCODE_CLOSE, &[]), [0x88, 0]);
+    }
+
+    #[test]
+    fn test_spectrum_message() {
+        let bins = [-60.4, -60.6, 0.0, 12.0, -200.0, 300.0];
+        let message = spectrum_message(1_090_000_000, 2_400_000, &bins);
+        assert_eq!(message.len(), HEADER_LEN + bins.len());
+        let (center, rate, decoded) = decode_spectrum(&message);
+        assert_eq!((center, rate), (1_090_000_000, 2_400_000));
+        assert_eq!(decoded, [-60, -61, 0, 12, -128, 127]);
+    }
+
+    #[test]
+    fn test_latest_message_skips_rows() {
+        let mut state = AppState::default();
+        state.sdr.frequency = 100_000_000;
+        let mut sent = 0;
+        assert_eq!(latest_message(&state, &mut sent), None);
+
+        // Three rows arrive between sends; only the newest goes out
+        for level in [-90.0, -80.0, -70.0] {
+            state.spectrum.add_fft_data(vec![level; 64], Utc::now());
+        }
+        let message = latest_message(&state, &mut sent).unwrap();
+        assert_eq!(sent, 3);
+        let (center, _, bins) = decode_spectrum(&message);
+        assert_eq!(center, 100_000_000);
+        assert!(bins.iter().all(|&db| db == -70));
+
+        // Nothing new, nothing sent
+        assert_eq!(latest_message(&state, &mut sent), None);
+    }
+}
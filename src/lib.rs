@@ -0,0 +1,54 @@
+//! The receiver without its terminal: SDR sources, the DSP chain, the
+//! shared state and what it feeds
+//!
+//! The binary puts the TUI and the command line on top; the benchmarks use
+//! the pieces directly. The types most often wanted are re-exported here.
+//!
+//! A carrier an eighth of the sample rate above the center, through the
+//! spectrum, an FM discriminator and the resampler to a 44.1 kHz speaker:
+//!
+//! ```
+//! use num_complex::Complex;
+//! use rtl_sdr_tui::{FftProcessor, FmDemodulator, Resampler};
+//! use std::f32::consts::TAU;
+//!
+//! let samples: Vec<Complex<f32>> = (0..4800)
+//!     .map(|n| Complex::from_polar(0.5, TAU * n as f32 / 8.0))
+//!     .collect();
+//!
+//! // The spectrum has the center in the middle
+//! let spectrum = FftProcessor::new(1024).process(&samples);
+//! let peak = (0..spectrum.len()).max_by(|&a, &b| spectrum[a].total_cmp(&spectrum[b]));
+//! assert_eq!(peak, Some(512 + 128));
+//!
+//! // An eighth of a turn per sample, of the half turn at full scale
+//! let audio = FmDemodulator::new(48_000, 75.0).demodulate(&samples);
+//! assert!((audio[4799] - 0.25).abs() < 1e-3);
+//!
+//! let speaker = Resampler::new(48_000, 44_100).resample(&audio);
+//! assert!(speaker.len().abs_diff(4410) < 64);
+//! ```
+
+pub mod audio;
+pub mod dsp;
+pub mod http;
+pub mod mqtt;
+pub mod recorder;
+pub mod scan;
+pub mod scheduler;
+pub mod sdr;
+pub mod service;
+pub mod state;
+pub mod streaming;
+#[cfg(test)]
+mod testing;
+#[cfg(test)]
+mod trace_capture;
+pub mod types;
+pub mod util;
+
+pub use dsp::demod::FmDemodulator;
+pub use dsp::{AudioSink, FftProcessor, Resampler};
+pub use sdr::{SampleBuffer, SampleSink, SdrSource};
+pub use state::{AppState, SharedState};
+pub use types::{Command, DemodMode};
@@ -0,0 +1,268 @@
+//! The application log file
+//!
+//! Log output goes to a file, since the TUI owns the terminal. The file is
+//! rotated by size: when it would grow past the limit, `rtl-sdr-tui.log`
+//! becomes `rtl-sdr-tui.log.1`, the previous `.1` becomes `.2` and so on,
+//! dropping whatever is past the number of files kept.
+//!
+//! Records go through `tracing`, the `log` macros' included, so a record
+//! made while a thread works on a buffer carries that buffer's span: which
+//! SDR read, DSP buffer or decoder frame it belongs to. A JSON trace can be
+//! written alongside for offline analysis, with a line for every span as
+//! it closes, giving its fields and how long it took.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+use tracing::Subscriber;
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::{self as format, MakeWriter};
+use tracing_subscriber::prelude::*;
+
+/// Size a log file grows to before it is rotated, in MB
+pub const DEFAULT_MAX_SIZE_MB: u64 = 10;
+/// Rotated files kept besides the current one
+pub const DEFAULT_KEEP: usize = 3;
+
+/// Where the log goes unless told otherwise: the XDG state directory
+/// (~/.local/state/rtl-sdr-tui), or the local data directory on systems
+/// without one
+pub fn default_path() -> PathBuf {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join("rtl-sdr-tui"))
+        .unwrap_or_default()
+        .join("rtl-sdr-tui.log")
+}
+
+/// Send records at `level` and above, or as `RUST_LOG` says if it is set,
+/// to a [`RotatingFile`] at `path`; with `trace_json`, everything from debug
+/// up goes to that file too, as JSON lines
+pub fn init(
+    path: &Path,
+    level: LevelFilter,
+    max_size: u64,
+    keep: usize,
+    trace_json: Option<&Path>,
+) -> io::Result<()> {
+    let file = RotatingFile::open(path, max_size, keep).map_err(|e| in_file(path, e))?;
+    let trace = trace_json
+        .map(|path| File::create(path).map_err(|e| in_file(path, e)))
+        .transpose()?;
+    let filter = EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env_lossy();
+    subscriber(Mutex::new(file), filter, trace.map(Mutex::new))
+        .try_init()
+        .map_err(io::Error::other)
+}
+
+/// Text records through `filter` to `log`, and, given a `trace`, a JSON line
+/// there for each record from debug up and for each span as it closes
+fn subscriber<L, T>(log: L, filter: EnvFilter, trace: Option<T>) -> impl Subscriber + Send + Sync
+where
+    L: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    T: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let log = format::layer()
+        .with_writer(log)
+        .with_ansi(false)
+        .with_filter(filter);
+    let trace = trace.map(|trace| {
+        format::layer()
+            .json()
+            .with_span_events(FmtSpan::CLOSE)
+            .with_thread_names(true)
+            .with_writer(trace)
+            .with_filter(LevelFilter::DEBUG)
+    });
+    tracing_subscriber::registry().with(log).with(trace)
+}
+
+/// `e` with the file it happened to
+fn in_file(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+/// A log file that rotates once it grows past `max_size` bytes
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Append to `path`, creating it and its directory if needed
+    pub fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    /// Shift the rotated files along and start the current one afresh
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep > 0 {
+            for n in (1..self.keep).rev() {
+                rename_if_present(
+                    &rotated_path(&self.path, n),
+                    &rotated_path(&self.path, n + 1),
+                )?;
+            }
+            rename_if_present(&self.path, &rotated_path(&self.path, 1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A record longer than the limit still goes in a file of its own
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// `path` with `.n` appended, e.g. rtl-sdr-tui.log.2
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn rename_if_present(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+    use tracing::field::Empty;
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_rotates_and_keeps_n_files() {
+        let dir = temp_dir("log_rotation");
+        let path = dir.join("nested").join("app.log");
+        let mut log = RotatingFile::open(&path, 11, 2).unwrap();
+
+        for line in ["one\n", "two\n", "three\n", "four\n", "five\n"] {
+            log.write_all(line.as_bytes()).unwrap();
+        }
+
+        // The lines fit two to a file, and only two old files stay
+        assert_eq!(read(&path), "five\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "three\nfour\n");
+        assert_eq!(read(&rotated_path(&path, 2)), "one\ntwo\n");
+        assert!(!rotated_path(&path, 3).exists());
+    }
+
+    #[test]
+    fn test_existing_file_counts_toward_limit() {
+        let dir = temp_dir("log_reopen");
+        let path = dir.join("app.log");
+        RotatingFile::open(&path, 100, 1)
+            .unwrap()
+            .write_all(b"earlier run\n")
+            .unwrap();
+
+        let mut log = RotatingFile::open(&path, 16, 1).unwrap();
+        log.write_all(b"this run\n").unwrap();
+        assert_eq!(read(&path), "this run\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "earlier run\n");
+    }
+
+    #[test]
+    fn test_keeping_none_truncates() {
+        let dir = temp_dir("log_keep_none");
+        let path = dir.join("app.log");
+        let mut log = RotatingFile::open(&path, 8, 0).unwrap();
+
+        log.write_all(b"first\n").unwrap();
+        log.write_all(b"second\n").unwrap();
+        // Oversized records aren't split
+        log.write_all(b"a much longer line\n").unwrap();
+
+        assert_eq!(read(&path), "a much longer line\n");
+        assert!(!rotated_path(&path, 1).exists());
+    }
+
+    #[test]
+    fn test_trace_json() {
+        let dir = temp_dir("trace_json");
+        let (log_path, trace_path) = (dir.join("app.log"), dir.join("trace.jsonl"));
+        let log = Mutex::new(File::create(&log_path).unwrap());
+        let trace = Mutex::new(File::create(&trace_path).unwrap());
+        let subscriber = subscriber(log, EnvFilter::new("info"), Some(trace));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::debug_span!("dsp.buffer", len = 16384, micros = Empty).entered();
+            tracing::debug!("channel filtered");
+            tracing::warn!("falling behind");
+            span.record("micros", 250);
+        });
+
+        // The log file keeps to its level
+        let log = read(&log_path);
+        assert_eq!(log.lines().count(), 1);
+        assert!(log.contains("WARN") && log.contains("falling behind"));
+
+        // The trace has everything, in the buffer's span, then the span
+        let lines: Vec<serde_json::Value> = read(&trace_path)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["fields"]["message"], "channel filtered");
+        assert_eq!(lines[0]["span"]["name"], "dsp.buffer");
+        assert_eq!(lines[1]["level"], "WARN");
+        let closed = &lines[2];
+        assert_eq!(closed["fields"]["message"], "close");
+        assert!(closed["fields"]["time.busy"].is_string());
+        assert_eq!(closed["span"]["name"], "dsp.buffer");
+        assert_eq!(closed["span"]["len"], 16384);
+        assert_eq!(closed["span"]["micros"], 250);
+    }
+
+    #[test]
+    fn test_default_path() {
+        let path = default_path();
+        assert!(
+            path.ends_with("rtl-sdr-tui/rtl-sdr-tui.log") || path == Path::new("rtl-sdr-tui.log")
+        );
+    }
+}
@@ -1,177 +1,1442 @@
 // Module declarations
-mod audio;
-mod dsp;
-mod recorder;
-mod sdr;
-mod state;
-mod streaming;
-mod types;
+mod calibrate;
+mod control;
+mod gain_assist;
+mod logging;
+mod priority;
+mod profile;
+mod selftest;
+// The library's test fixtures aren't built for the binary's tests
+#[cfg(test)]
+#[path = "testing/files.rs"]
+mod testing;
 mod ui;
+mod waterfall_png;
 
-use anyhow::Result;
-use audio::AudioOutput;
-use clap::Parser;
+// The receiver itself, from the library
+use rtl_sdr_tui::{
+    audio, dsp, http, mqtt, recorder, scan, scheduler, sdr, service, state, streaming, types,
+    util,
+};
+
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use crossbeam::channel;
-use ringbuf::{traits::Split, HeapRb};
+use ringbuf::{traits::Split, HeapCons, HeapRb};
 use state::AppState;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
+use ui::events::{Dispatch, UiEvents};
 use ui::App;
 
 /// RTL-SDR TUI - A terminal-based SDR receiver
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// Config file (default: ~/.config/rtl-sdr-tui/config.toml if present)
+    #[arg(short, long)]
+    config: Option<std::path::PathBuf>,
+
+    /// Start with the [profiles.<NAME>] section of the config file; the
+    /// options given here still override it
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
     /// Initial frequency in MHz (e.g., 162.425 for NOAA)
     #[arg(short, long)]
     frequency: Option<f64>,
 
     /// Stream audio over TCP on specified port
-    /// Connect with: nc localhost <port> | aplay -r 48000 -f S16_LE -c 1
+    /// Connect with: nc localhost <port> | aplay -r 48000 -f S16_LE -c 1 (raw)
     #[arg(short = 'p', long = "audio-port")]
     audio_port: Option<u16>,
 
-    /// SDR device index (default: 0)
-    #[arg(short, long, default_value_t = 0)]
-    device: usize,
+    /// Audio stream encoding: "raw" (16-bit PCM), "wav" (PCM after a WAV
+    /// header) or "opus" (Ogg Opus, needs the opus feature)
+    #[arg(long = "stream-codec", default_value = "raw")]
+    stream_codec: streaming::codec::StreamCodec,
+
+    /// Opus stream bitrate in kbit/s
+    #[arg(
+        long = "stream-bitrate",
+        default_value_t = streaming::codec::DEFAULT_OPUS_BITRATE / 1000
+    )]
+    stream_bitrate: u32,
+
+    /// Write the audio as 48 kHz 16-bit mono PCM (S16LE) to this named pipe,
+    /// made if missing, or to stdout with "-" (which runs headless).
+    /// Audio is dropped rather than waiting on a slow reader, and the pipe
+    /// is reopened when a reader comes back. For example:
+    ///   --audio-pipe - --headless | direwolf -r 48000 -
+    ///   --audio-pipe - | sox -t raw -r 48000 -e signed -b 16 -c 1 - -t raw -r 22050 - |
+    ///     multimon-ng -t raw -a POCSAG1200 -
+    ///   --audio-pipe /tmp/sdr.fifo, then: direwolf -r 48000 - < /tmp/sdr.fifo
+    #[arg(long = "audio-pipe", value_name = "PATH|-", verbatim_doc_comment)]
+    audio_pipe: Option<std::path::PathBuf>,
+
+    /// Most audio kept queued for the speaker, in ms; less keeps what is
+    /// heard closer to the waterfall, but risks dropouts
+    #[arg(
+        long = "audio-latency",
+        value_name = "MS",
+        default_value_t = audio::latency::DEFAULT_TARGET_MS
+    )]
+    audio_latency: u32,
+
+    /// Don't play audio on the speaker; streams, pipes and recordings still
+    /// get it
+    #[arg(long = "no-audio")]
+    no_audio: bool,
+
+    /// Play a 1 kHz test tone at -12 dBFS for two seconds on start, over the
+    /// ducked audio, to check the speaker and streams (Ctrl+T plays it again)
+    #[arg(long = "test-tone")]
+    test_tone: bool,
+
+    /// Shrink the IQ, audio and stream queues for about 100 ms from antenna
+    /// to speaker, at the cost of dropouts whenever processing stalls
+    #[arg(long = "low-latency")]
+    low_latency: bool,
+
+    /// Serve decoded AIS as NMEA !AIVDM sentences over TCP on this port (e.g. for OpenCPN)
+    #[arg(long = "ais-port")]
+    ais_port: Option<u16>,
+
+    /// Serve decoded RS41 radiosonde telemetry as SondeHub JSON lines over
+    /// TCP on this port
+    #[arg(long = "sonde-port")]
+    sonde_port: Option<u16>,
+
+    /// Append decoded RS41 radiosonde telemetry as SondeHub JSON lines to
+    /// this file
+    #[arg(long = "sonde-log")]
+    sonde_log: Option<std::path::PathBuf>,
+
+    /// Serve GET /status (JSON), GET /metrics (Prometheus) and a live web
+    /// waterfall at / on this port
+    #[arg(long = "http-port")]
+    http_port: Option<u16>,
+
+    /// Take commands from `rtl-sdr-tui remote` on this localhost port
+    /// (default: [control] in the config file, off unless enabled there)
+    #[arg(long = "control-port")]
+    control_port: Option<u16>,
+
+    /// Once the ports are open, switch to this user and its groups for the
+    /// rest of the run, with the config, log and recordings in their home
+    /// (needs starting as root, e.g. for a port below 1024)
+    #[arg(long, value_name = "NAME")]
+    user: Option<String>,
+
+    /// Publish decoded messages, status and SAME alerts to this MQTT broker,
+    /// e.g. "mqtt://broker.local:1883" (topics are set in [mqtt])
+    #[arg(long, value_name = "BROKER_URL")]
+    mqtt: Option<String>,
+
+    /// Station location as lat,lon in degrees (e.g. 47.61,-122.33), for the
+    /// distance and bearing to decoded positions; overrides [station]
+    #[arg(long, value_name = "LAT,LON", allow_hyphen_values = true)]
+    qth: Option<util::geo::Location>,
+
+    /// SDR device index (default: 0); give it twice, or a list such as
+    /// --devices 0,1, to run a receiver on each dongle (R switches between them;
+    /// the audio stream, schedule, priority watch and scan use the first)
+    #[arg(short, long, alias = "devices", value_delimiter = ',', default_value = "0")]
+    device: Vec<usize>,
+
+    /// Receiver backend: "rtl" (librtlsdr) or "soapy" (SoapySDR, needs the
+    /// soapy feature)
+    #[arg(long, default_value = "rtl")]
+    driver: sdr::Driver,
+
+    /// SoapySDR device arguments, e.g. "driver=airspy" or "driver=hackrf,serial=..."
+    #[arg(long = "device-args", default_value = "")]
+    device_args: String,
+
+    /// Receive from an rtl_tcp server instead of a local device
+    #[arg(long, value_name = "HOST:PORT", conflicts_with_all = ["driver", "device_args"])]
+    remote: Option<String>,
+
+    /// Play back an IQ recording instead of receiving: .iq, .cs16 or .cf32
+    /// as recorded (with its .json sidecar or recorded name) or .sigmf-data.
+    /// Ctrl+P pauses, Alt+Left/Right seek 10 s, > changes speed and Alt+0-9
+    /// jump ahead
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["remote", "wait_for_device"])]
+    play: Option<std::path::PathBuf>,
+
+    /// Start without the receiver if it can't be opened, retrying every 2
+    /// seconds until it appears
+    #[arg(long = "wait-for-device")]
+    wait_for_device: bool,
 
     /// Initial gain in dB (default: auto)
     #[arg(short, long)]
     gain: Option<f32>,
+
+    /// Directory recordings are written to (created if missing)
+    #[arg(long = "record-dir", default_value = ".")]
+    record_dir: std::path::PathBuf,
+
+    /// Write recordings as SigMF (.sigmf-data + .sigmf-meta) instead of raw .iq
+    #[arg(long)]
+    sigmf: bool,
+
+    /// IQ recording sample format: "u8" (as received, .iq), "i16" (.cs16) or
+    /// "f32" (.cf32, as GQRX reads); E in the Record control changes it
+    #[arg(long = "record-format", default_value = "u8")]
+    record_format: recorder::SampleFormat,
+
+    /// Warn when free disk space for recordings drops below this many MB
+    #[arg(long = "record-warn-free", default_value_t = 2048)]
+    record_warn_free_mb: u64,
+
+    /// Stop recording when free disk space drops below this many MB
+    #[arg(long = "record-min-free", default_value_t = 256)]
+    record_min_free_mb: u64,
+
+    /// Split IQ recordings into numbered parts every duration or size,
+    /// e.g. "30m", "1h" or "500M", "2G" (lowercase m = minutes)
+    #[arg(long = "record-split")]
+    record_split: Option<recorder::SplitPolicy>,
+
+    /// What to record: "iq" (continuous raw IQ) or "squelch" (one WAV per transmission)
+    #[arg(long = "record-mode", default_value = "iq")]
+    record_mode: state::RecordingMode,
+
+    /// Squelch threshold in dBFS (default: off)
+    #[arg(long, allow_hyphen_values = true)]
+    squelch: Option<f32>,
+
+    /// Seconds of audio kept from before the squelch opens
+    #[arg(long = "vox-pre-roll", default_value_t = 0.5)]
+    vox_pre_roll: f32,
+
+    /// Seconds the squelch must stay closed before a squelch recording is finalized
+    #[arg(long = "vox-hang", default_value_t = 1.0)]
+    vox_hang: f32,
+
+    /// Discard squelch recordings shorter than this many seconds
+    #[arg(long = "vox-min-length", default_value_t = 0.3)]
+    vox_min_length: f32,
+
+    /// Append decoded messages to this file (toggle at runtime with L)
+    #[arg(long = "decode-log")]
+    decode_log: Option<std::path::PathBuf>,
+
+    /// Rewrite the first receiver's ADS-B aircraft to this file every
+    /// second, as dump1090's aircraft.json for tar1090 and other maps, or
+    /// as a GeoJSON FeatureCollection if it ends in .geojson
+    #[arg(long = "aircraft-json", value_name = "PATH")]
+    aircraft_json: Option<std::path::PathBuf>,
+
+    /// Decode log format: "jsonl", "text" or "both" (.jsonl and .log files)
+    #[arg(long = "decode-log-format")]
+    decode_log_format: Option<dsp::decoder::DecodeLogFormat>,
+
+    /// Sweep a band and write peak power per step to scan_<timestamp>.csv in
+    /// the recording directory, e.g. "144:148:12.5" or "144:148:12.5:200"
+    /// (<start_mhz>:<end_mhz>:<step_khz>[:dwell_ms])
+    #[arg(long)]
+    scan: Option<scan::ScanSpec>,
+
+    /// Keep sweeping until quit, adding a column per sweep
+    #[arg(long = "scan-repeat", requires = "scan")]
+    scan_repeat: bool,
+
+    /// Run without the terminal UI, until a scan finishes or Ctrl-C
+    #[arg(long)]
+    headless: bool,
+
+    /// Check the receiver, audio output and streaming port, print the
+    /// results and exit (non-zero if any check failed)
+    #[arg(long = "self-test")]
+    self_test: bool,
+
+    /// List the connected RTL-SDRs with their tuners and exit
+    #[arg(long = "list-devices")]
+    list_devices: bool,
+
+    /// Measure the PPM correction against a strong carrier at this
+    /// frequency in MHz (a NOAA weather station is ideal), print it and exit
+    #[arg(long, value_name = "MHZ")]
+    calibrate: Option<f64>,
+
+    /// Merge the bookmarks in a CSV file (this program's or GQRX's
+    /// bookmarks.csv) into the config file's and exit
+    #[arg(long = "import-bookmarks", value_name = "FILE")]
+    import_bookmarks: Option<std::path::PathBuf>,
+
+    /// Write the config file's bookmarks to a CSV file and exit
+    #[arg(long = "export-bookmarks", value_name = "FILE")]
+    export_bookmarks: Option<std::path::PathBuf>,
+
+    /// Log file (default: ~/.local/state/rtl-sdr-tui/rtl-sdr-tui.log)
+    #[arg(long = "log-file")]
+    log_file: Option<std::path::PathBuf>,
+
+    /// Least severe messages logged: error, warn, info, debug or trace
+    #[arg(long = "log-level", default_value = "info")]
+    log_level: tracing::level_filters::LevelFilter,
+
+    /// Rotate the log when it grows past this many MB
+    #[arg(long = "log-max-size", default_value_t = logging::DEFAULT_MAX_SIZE_MB)]
+    log_max_size_mb: u64,
+
+    /// Rotated log files kept (.1 is the newest)
+    #[arg(long = "log-keep", default_value_t = logging::DEFAULT_KEEP)]
+    log_keep: usize,
+
+    /// Also write a trace to this file as JSON lines, for offline analysis:
+    /// everything from debug up, and each SDR read, DSP buffer and decoder
+    /// frame with its timing
+    #[arg(long = "trace-json", value_name = "FILE")]
+    trace_json: Option<std::path::PathBuf>,
+
+    /// On exit, save the whole waterfall history as a PNG in the recording
+    /// directory (X saves one at any time)
+    #[arg(long = "dump-waterfall-on-exit")]
+    dump_waterfall_on_exit: bool,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Commands run in place of the receiver
+#[derive(clap::Subcommand, Debug)]
+enum CliCommand {
+    /// Send one command to a running instance and print its response.
+    /// Exits 0 when it was carried out, 1 when it was refused, 2 when the
+    /// command line is wrong and 3 when the instance couldn't be reached
+    Remote {
+        /// Control port (default: [control] port in the config file, 7357)
+        #[arg(long)]
+        port: Option<u16>,
 
-    // Initialize logging to file to avoid corrupting TUI
-    use std::fs::OpenOptions;
+        /// Receiver number, when running more than one (default: the
+        /// selected one)
+        #[arg(long)]
+        receiver: Option<usize>,
 
-    let log_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("rtl-sdr-tui.log")
-        .expect("Failed to open log file");
+        #[command(subcommand)]
+        action: RemoteAction,
+    },
+}
 
-    env_logger::Builder::from_default_env()
-        .target(env_logger::Target::Pipe(Box::new(log_file)))
-        .filter_level(log::LevelFilter::Info)
-        .init();
+/// What `remote` asks of a running instance
+#[derive(clap::Subcommand, Debug)]
+enum RemoteAction {
+    /// Tune to a frequency, e.g. 162.55M, 27185k, 1090000000 (Hz) or 162.55
+    /// (MHz)
+    SetFreq {
+        #[arg(value_parser = util::units::parse_frequency)]
+        frequency: u32,
+    },
+    /// Switch the mode, e.g. nfm, wfm, am, usb
+    SetMode { mode: types::DemodMode },
+    /// Set the tuner gain in dB, or "auto"
+    SetGain {
+        #[arg(value_parser = control::parse_gain)]
+        gain: types::config::GainSetting,
+    },
+    /// Start or stop recording, as the Record control would
+    Record { action: control::RecordAction },
+    /// Print the receiver's status as JSON
+    Status,
+}
+
+fn main() -> Result<()> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(CliCommand::Remote {
+        port,
+        receiver,
+        action,
+    }) = &args.command
+    {
+        std::process::exit(remote(&args, *port, *receiver, action));
+    }
+    let given = cli_settings(&args, &matches);
+    // Stdout is the TUI's unless the audio goes there instead
+    args.headless |= audio_to_stdout(&args);
+    // Everything from here on is the user's, in their home, though the
+    // switch to them waits until the ports are open
+    let account = args
+        .user
+        .as_deref()
+        .map(service::Account::lookup)
+        .transpose()?;
+    if let Some(account) = &account {
+        account.take_environment();
+    }
+
+    // Initialize logging to file to avoid corrupting TUI; without one the
+    // app runs unlogged rather than not at all
+    let log_path = args.log_file.clone().unwrap_or_else(logging::default_path);
+    if let Err(e) = logging::init(
+        &log_path,
+        args.log_level,
+        args.log_max_size_mb * 1024 * 1024,
+        args.log_keep,
+        args.trace_json.as_deref(),
+    ) {
+        eprintln!("Warning: can't open the log: {}", e);
+        eprintln!("Continuing without a log");
+    }
+    if let Some(account) = &account {
+        let made = [log_path.parent(), Some(&log_path), args.trace_json.as_deref()];
+        for path in made.into_iter().flatten().filter(|path| path.exists()) {
+            if let Err(e) = account.give(path) {
+                eprintln!("Warning: can't hand {} to {}: {}", path.display(), account.name, e);
+            }
+        }
+    }
 
     log::info!("RTL-SDR TUI v0.1.0 starting...");
 
+    if args.list_devices {
+        sdr::stderr::capture();
+        let devices = sdr::device::list_devices_with_tuners();
+        sdr::stderr::restore();
+        if devices.is_empty() {
+            println!("No RTL-SDR devices are connected");
+        }
+        for device in devices {
+            println!("{}", device);
+        }
+        return Ok(());
+    }
+
+    if args.self_test {
+        let passed = self_test(&args);
+        sdr::stderr::restore();
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    if let Some(mhz) = args.calibrate {
+        let result = calibrate(&args, mhz);
+        sdr::stderr::restore();
+        if let Err(e) = result {
+            log::error!("Calibration failed: {:#}", e);
+            eprintln!("Calibration failed: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.import_bookmarks.is_some() || args.export_bookmarks.is_some() {
+        if let Err(e) = bookmark_files(&args) {
+            log::error!("Bookmark import/export failed: {:#}", e);
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Run the application; driver stderr went to the log while it ran, and
+    // comes back so the error below reaches the console
+    let result = run(args, given, account);
+    sdr::stderr::restore();
+    if let Err(e) = result {
+        log::error!("Application error: {}", e);
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn run(
+    mut args: Args,
+    given: profile::Settings,
+    account: Option<service::Account>,
+) -> Result<()> {
+    let config = types::AppConfig::load(args.config.as_deref())?;
+    let profiles = profile::Profile::all(&config.profiles)?;
+    // What the command line gives wins over the profile, which wins over
+    // the rest of the config file
+    let startup = given.with_profile(&profiles, args.profile.as_deref())?;
+    args.audio_port = startup.audio_port;
+    args.stream_codec = startup.stream_codec.unwrap_or(args.stream_codec);
+    args.record_dir = startup.record_dir.clone().unwrap_or(args.record_dir);
+    args.record_mode = startup.record_mode.unwrap_or(args.record_mode);
+    args.record_format = startup.record_format.unwrap_or(args.record_format);
+
+    let schedule = config
+        .schedule
+        .iter()
+        .map(scheduler::ScheduleEntry::from_config)
+        .collect::<Result<Vec<_>>>()?;
+    let priority_interval = util::units::parse_duration(&config.priority.interval)?;
+
+    if audio_to_stdout(&args) && args.scan.is_some() {
+        anyhow::bail!("--audio-pipe - can't be used with --scan, which prints to stdout");
+    }
+
+    if args.device.len() > 1 {
+        if args.remote.is_some() || args.play.is_some() || args.driver != sdr::Driver::Rtl {
+            anyhow::bail!("More than one receiver needs local RTL-SDR devices");
+        }
+        let mut devices = args.device.clone();
+        devices.sort_unstable();
+        devices.dedup();
+        if devices.len() != args.device.len() {
+            anyhow::bail!("Each --device can only be used once");
+        }
+    }
+
+    // Decode logging starts enabled when a log path is given; otherwise L
+    // turns it on with a log in the recording directory
+    let decode_log_path = args.decode_log.clone().or(config.decode_log.path.clone());
+    let decode_log_format = match args.decode_log_format {
+        Some(format) => format,
+        None => config.decode_log.format.parse().map_err(anyhow::Error::msg)?,
+    };
+
+    // Initialize shared state, one per receiver
+    let states: Vec<state::SharedState> = args
+        .device
+        .iter()
+        .map(|_| initial_state(&args, &startup, &config, decode_log_path.clone()))
+        .collect();
+    // A recording played back stands in for the receiver, as recorded
+    let playback = args.play.as_deref().map(sdr::PlaybackFile::probe).transpose()?;
+    if let Some(file) = &playback {
+        let mut state = states[0].write();
+        state.sdr.frequency = file.frequency;
+        state.sdr.sample_rate = file.sample_rate;
+        state.sdr.auto_rate = false;
+        state.playback = Some(file.playback_state());
+    }
+    // The first receiver is the one scans, schedules and streams follow
+    let state = states[0].clone();
+    state.write().ui.clock = config.ui.clock.parse().map_err(anyhow::Error::msg)?;
+    let integration: state::waterfall::Integration =
+        config.ui.waterfall_integration.parse().map_err(anyhow::Error::msg)?;
+    let dc_avoidance: dsp::dc::DcAvoidance =
+        config.sdr.dc_avoidance.parse().map_err(anyhow::Error::msg)?;
+    let retune: recorder::RetunePolicy =
+        config.recording.retune.parse().map_err(anyhow::Error::msg)?;
+    for state in &states {
+        let mut state = state.write();
+        state.spectrum.line_rate = config.ui.waterfall_lps;
+        state.spectrum.integration = integration;
+        state.sdr.dc_avoidance = dc_avoidance;
+        state.recording.retune = retune;
+        // Starting on a range doesn't count as crossing into it
+        let frequency = state.sdr.frequency;
+        state.sdr.gain_profiles =
+            sdr::gain_profile::GainProfiles::from_config(&config.gain_profiles, frequency)?;
+    }
+    log::info!("DC spike avoidance: {}", dc_avoidance.name());
+    log::info!("Retuning during an IQ recording: {}", retune.name());
+    if let Some(profiles) = &state.read().sdr.gain_profiles {
+        for range in profiles.ranges() {
+            log::info!("Gain profile {}", range.describe());
+        }
+    }
+    let station = station(&args, &config)?;
+
+    // Every receiver's decodes go to the same log
+    let message_log = Arc::new(parking_lot::Mutex::new(dsp::decoder::MessageLog::new(
+        state.read().decoder.log_path.clone(),
+        decode_log_format,
+        config.decode_log.max_size_mb * 1024 * 1024,
+    )));
+    let spectrum_mode: ui::widgets::SpectrumMode =
+        config.ui.spectrum_mode.parse().map_err(anyhow::Error::msg)?;
+
+    // Create shutdown signal
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let buffering = audio::latency::Buffering::new(args.audio_latency, args.low_latency);
+
+    // Under systemd socket activation the ports come ready-made, and a
+    // server whose socket was passed runs whether or not its port was given
+    let mut sockets = service::Sockets::from_env();
+    args.audio_port = args.audio_port.or(sockets.port("audio"));
+    args.ais_port = args.ais_port.or(sockets.port("nmea"));
+    args.sonde_port = args.sonde_port.or(sockets.port("sonde"));
+    args.http_port = args.http_port.or(sockets.port("http"));
+    let control_port = args
+        .control_port
+        .or(sockets.port("control"))
+        .or(config.control.enabled.then_some(config.control.port));
+
+    // Start TCP streaming server and audio pipe if requested
+    let mut stream_txs = Vec::new();
     if let Some(port) = args.audio_port {
+        log::info!("Starting audio streaming server on port {}...", port);
+        stream_txs.push(streaming::start_streaming_server(
+            sockets.listen("audio", ("0.0.0.0", port))?,
+            args.stream_codec,
+            args.stream_bitrate * 1000,
+            buffering.stream_queue,
+            state.clone(),
+            shutdown.clone(),
+        )?);
+        // Said now, while stderr is still the console's; it goes to the log
+        // once a receiver is opened
         log::info!("Audio streaming enabled on port {}", port);
         eprintln!("Audio streaming on port {}. Connect with:", port);
-        eprintln!("  nc localhost {} | aplay -r 48000 -f S16_LE -c 1", port);
+        eprintln!("  {}", args.stream_codec.receive_command(port));
         eprintln!();
     }
+    if let Some(path) = &args.audio_pipe {
+        log::info!("Starting audio pipe to {}...", path.display());
+        stream_txs.push(streaming::pipe::start_audio_pipe(
+            streaming::pipe::PipeTarget::from_arg(path),
+            buffering.stream_queue,
+            shutdown.clone(),
+        )?);
+    }
 
-    // Run the application
-    if let Err(e) = run(args) {
-        log::error!("Application error: {}", e);
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    // Start the NMEA server for AIS if requested
+    let nmea_tx = if let Some(port) = args.ais_port {
+        log::info!("Starting AIS NMEA server on port {}...", port);
+        let listener = sockets.listen("nmea", ("0.0.0.0", port))?;
+        Some(streaming::start_nmea_server(listener, shutdown.clone())?)
+    } else {
+        None
+    };
+
+    // Radiosonde telemetry goes out over TCP and/or to a file if requested
+    let sonde_tx = if args.sonde_port.is_some() || args.sonde_log.is_some() {
+        let listener = match args.sonde_port {
+            Some(port) => Some(sockets.listen("sonde", ("0.0.0.0", port))?),
+            None => None,
+        };
+        Some(streaming::start_line_output(
+            "Sonde",
+            listener,
+            args.sonde_log.as_deref(),
+            shutdown.clone(),
+        )?)
+    } else {
+        None
+    };
+    let decoder_outputs = dsp::decoder::DecoderOutputs {
+        nmea_tx,
+        sonde_tx,
+        ..Default::default()
+    };
+
+    // Start the HTTP status server if requested
+    if let Some(port) = args.http_port {
+        log::info!("Starting HTTP status server on port {}...", port);
+        let listener = sockets.listen("http", ("0.0.0.0", port))?;
+        http::start_http_server(listener, states.clone(), shutdown.clone())?;
+    }
+
+    // Opened now, so every port is open before giving up root
+    let control_listener = match control_port {
+        Some(port) => Some(
+            sockets
+                .listen("control", ("127.0.0.1", port))
+                .with_context(|| format!("can't open the control socket on port {}", port))?,
+        ),
+        None => None,
+    };
+    sockets.finish();
+    if let Some(account) = &account {
+        service::drop_privileges(account)?;
+    }
+    // Made as the user it will be written as
+    std::fs::create_dir_all(&args.record_dir)?;
+
+    // Start a pipeline per receiver
+    let mut receivers = Vec::new();
+    let mut audio_consumers = Vec::new();
+    let mut audible = Vec::new();
+    let mut pipelines = Vec::new();
+    // The rate the speaker runs at, which each receiver's audio is
+    // resampled to on its way there
+    let speaker_rate = Arc::new(AtomicU32::new(audio::latency::AUDIO_RATE));
+    // Decoded messages wait here for the MQTT broker
+    let outbox = args.mqtt.as_ref().map(|_| mqtt::Outbox::new(config.mqtt.queue));
+    // Aircraft for web maps, from the first receiver's decoder thread
+    let mut aircraft_export = args.aircraft_json.clone().map(|path| {
+        log::info!("Writing aircraft to {}", path.display());
+        dsp::decoder::AircraftExport::new(path)
+    });
+
+    for (index, (&device, state)) in args.device.iter().zip(&states).enumerate() {
+        let (remote, driver, device_args) =
+            (args.remote.clone(), args.driver, args.device_args.clone());
+        let play = playback.clone().map(|file| (file, state.clone()));
+        let open = move || -> Result<Box<dyn sdr::SdrSource>> {
+            if let Some((file, state)) = &play {
+                return Ok(Box::new(sdr::FileSource::open(file.clone(), state.clone())));
+            }
+            match &remote {
+                Some(addr) => Ok(Box::new(sdr::RtlTcpSource::open(addr)?)),
+                None => sdr::open_source(driver, device, &device_args),
+            }
+        };
+        // Local devices are reopened if they stall; rtl_tcp reconnects on
+        // its own and a file can't stall
+        let reopen = args.remote.is_none() && playback.is_none();
+        let (receiver, (audio_consumer, heard), pipeline) = start_receiver(
+            open,
+            args.wait_for_device,
+            reopen,
+            state,
+            buffering,
+            &speaker_rate,
+            std::mem::take(&mut stream_txs),
+            decoder_outputs.clone(),
+            &message_log,
+            outbox.as_ref().map(|outbox| outbox.for_receiver(index)),
+            aircraft_export.take(),
+            &config.recording,
+            &shutdown,
+        )?;
+        receivers.push(receiver);
+        audio_consumers.push(audio_consumer);
+        audible.push(heard);
+        pipelines.push(pipeline);
+    }
+    let receivers = state::Receivers::new(receivers);
+    let command_tx = receivers.current().commands.clone();
+
+    // Take commands from `rtl-sdr-tui remote` if asked
+    if let Some(listener) = control_listener {
+        control::start_control_server(listener, receivers.clone(), shutdown.clone())?;
+    }
+
+    // Publish to the MQTT broker if asked
+    let mqtt_thread = match (&args.mqtt, outbox) {
+        (Some(broker), Some(outbox)) => Some(mqtt::start_mqtt_thread(
+            broker,
+            &config.mqtt,
+            outbox,
+            states.clone(),
+            shutdown.clone(),
+        )?),
+        _ => None,
+    };
+
+    // Play the selected receiver on the speaker, reopening it if it goes
+    // away, and say so in the status bar while it is gone
+    let audio_thread = if args.no_audio {
+        log::info!("Audio output off");
+        None
+    } else {
+        log::info!("Starting audio output...");
+        let routing = audio::output::Routing::new(audio_consumers, audible, receivers.selection());
+        let shown = receivers.clone();
+        let report = move |health: audio::reconnect::AudioHealth| {
+            let warning = health.warning();
+            let state = &shown.current().state;
+            if state.read().ui.audio_warning != warning {
+                state.write().ui.audio_warning = warning;
+            }
+        };
+        let thread = audio::reconnect::start_audio_thread(
+            routing,
+            config.audio.sample_rate,
+            speaker_rate,
+            report,
+            shutdown.clone(),
+        )
+        .map_err(|e| anyhow::anyhow!("{:#} (--no-audio runs without a speaker)", e))?;
+        Some(thread)
+    };
+    if args.test_tone {
+        let until = std::time::Instant::now() + dsp::tone::TEST_TONE_DURATION;
+        receivers.current().state.write().ui.test_tone = Some(until);
+    }
+
+    // Start the recording scheduler if anything is scheduled
+    let scheduler_thread = if schedule.is_empty() {
+        None
+    } else {
+        log::info!("Starting scheduler thread...");
+        Some(scheduler::start_scheduler_thread(
+            schedule,
+            state.clone(),
+            command_tx.clone(),
+            shutdown.clone(),
+        ))
+    };
+
+    // Start the priority watch if there is a priority channel
+    let priority_frequency = state.read().priority.frequency;
+    let priority_thread = priority_frequency.map(|frequency| {
+        log::info!("Starting priority watch thread...");
+        priority::start_priority_thread(
+            priority::PriorityWatch::new(frequency, priority_interval, std::time::Instant::now()),
+            config.priority.threshold,
+            state.clone(),
+            command_tx.clone(),
+            shutdown.clone(),
+        )
+    });
+
+    // Sweep a band if asked
+    let scan_thread = args.scan.clone().map(|spec| {
+        log::info!("Starting scan thread...");
+        scan::start_scan_thread(
+            spec,
+            args.scan_repeat,
+            scan::scan_path(&args.record_dir, &chrono::Local::now()),
+            args.headless,
+            state.clone(),
+            command_tx.clone(),
+            shutdown.clone(),
+        )
+    });
+
+    if args.headless {
+        wait_headless(scan_thread.as_ref());
+        if args.dump_waterfall_on_exit {
+            let colormap = ui::theme::Theme::from_config(&config.theme).0.waterfall;
+            dump_waterfall(&state, colormap, config.ui.spectrum_invert);
+        }
+    } else {
+        let config_path = args.config.clone().or_else(types::AppConfig::default_path);
+        run_tui(
+            receivers,
+            &config,
+            profiles,
+            config_path,
+            spectrum_mode,
+            station,
+            args.dump_waterfall_on_exit,
+        )?;
+    }
+
+    // Signal all threads to stop
+    log::info!("Shutting down threads...");
+    shutdown.store(true, Ordering::Relaxed);
+
+    // Wait for threads to finish; the scan writes out what it has, and the
+    // recorders go before the rest so active recordings are flushed to
+    // disk before anything else can block
+    if let Some(thread) = scan_thread {
+        let _ = thread.join();
+    }
+    if let Some(thread) = scheduler_thread {
+        let _ = thread.join();
+    }
+    if let Some(thread) = priority_thread {
+        let _ = thread.join();
+    }
+    if let Some(thread) = mqtt_thread {
+        let _ = thread.join();
+    }
+    if let Some(thread) = audio_thread {
+        let _ = thread.join();
+    }
+    let (recorders, others): (Vec<_>, Vec<_>) = pipelines
+        .into_iter()
+        .map(|pipeline| (pipeline.recorder, pipeline.others))
+        .unzip();
+    for thread in recorders.into_iter().chain(others.into_iter().flatten()) {
+        let _ = thread.join();
     }
 
+    log::info!("RTL-SDR TUI shutting down");
     Ok(())
 }
 
-fn run(args: Args) -> Result<()> {
-    // Initialize shared state
+/// The startup settings given on the command line rather than defaulted
+fn cli_settings(args: &Args, matches: &clap::ArgMatches) -> profile::Settings {
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    profile::Settings {
+        frequency: args.frequency.map(|mhz| (mhz * 1_000_000.0).round() as u32),
+        mode: None,
+        sample_rate: None,
+        gain: args.gain,
+        squelch: args.squelch,
+        audio_port: args.audio_port,
+        stream_codec: given("stream_codec").then_some(args.stream_codec),
+        record_dir: given("record_dir").then(|| args.record_dir.clone()),
+        record_mode: given("record_mode").then_some(args.record_mode),
+        record_format: given("record_format").then_some(args.record_format),
+    }
+}
+
+/// Whether `--audio-pipe -` sends the audio to stdout
+fn audio_to_stdout(args: &Args) -> bool {
+    args.audio_pipe.as_deref().map(streaming::pipe::PipeTarget::from_arg)
+        == Some(streaming::pipe::PipeTarget::Stdout)
+}
+
+/// A receiver's state as the command line, profile and config file set it up
+fn initial_state(
+    args: &Args,
+    startup: &profile::Settings,
+    config: &types::AppConfig,
+    decode_log_path: Option<std::path::PathBuf>,
+) -> state::SharedState {
     let state = AppState::new_shared();
 
-    // Apply command-line arguments to initial state
-    if let Some(freq_mhz) = args.frequency {
-        let freq_hz = (freq_mhz * 1_000_000.0) as u32;
+    // Apply command-line arguments and the profile to initial state
+    if let Some(freq_hz) = startup.frequency {
         state.write().sdr.frequency = freq_hz;
-        log::info!("Initial frequency set to {} MHz", freq_mhz);
+        log::info!("Initial frequency set to {} MHz", freq_hz as f64 / 1_000_000.0);
     }
 
-    if let Some(gain) = args.gain {
+    if let Some(rate) = startup.sample_rate {
+        state.write().sdr.sample_rate = rate;
+        log::info!("Initial sample rate set to {} Hz", rate);
+    }
+
+    if let Some(gain) = startup.gain {
         let gain_tenths = (gain * 10.0) as i32;
         state.write().sdr.tuner_gain = gain_tenths;
-        state.write().sdr.auto_gain = false;
+        state.write().sdr.tuner_agc = false;
         log::info!("Initial gain set to {} dB", gain);
     }
 
-    // Create shutdown signal
-    let shutdown = Arc::new(AtomicBool::new(false));
+    {
+        let mut state_guard = state.write();
+        state_guard.recording.low_space_warning = args.record_warn_free_mb * 1024 * 1024;
+        state_guard.recording.min_free_space = args.record_min_free_mb * 1024 * 1024;
+        state_guard.recording.output_dir = args.record_dir.clone();
+        state_guard.recording.sigmf = args.sigmf;
+        state_guard.recording.format = args.record_format;
+        state_guard.recording.split = args.record_split;
+        state_guard.recording.mode = args.record_mode;
+        state_guard.recording.vox = state::VoxSettings {
+            pre_roll: args.vox_pre_roll,
+            hang: args.vox_hang,
+            min_length: args.vox_min_length,
+        };
+        state_guard.sdr.auto_rate = config.sdr.auto_rate;
+        state_guard.sdr.dc_offset = config.sdr.dc_offset;
+        state_guard.sdr.stall_timeout = (config.sdr.stall_timeout > 0)
+            .then(|| std::time::Duration::from_secs(config.sdr.stall_timeout));
+        state_guard.sdr.calibration =
+            state::calibration::Calibration::from_config(&config.calibration);
+        state_guard.sdr.ppm_error = config.sdr.ppm_error;
+        state_guard.ui.frequency_history =
+            state::FrequencyHistory::from_config(&config.history.recent);
+        state_guard.ui.bookmarks = state::Bookmarks::from_config(&config.bookmarks.list);
+        state_guard.ui.level_override = state::LevelOverride::new(config.bookmarks.write_back);
+
+        // The VFOs as they were left; chain A starts on the active one
+        // unless the command line or profile tunes elsewhere
+        for (index, saved) in [&config.vfo.a, &config.vfo.b].into_iter().enumerate() {
+            match saved.as_ref().map(state::VfoConfig::from_config) {
+                Some(Ok(vfo)) => state_guard.sdr.vfo[index] = vfo,
+                Some(Err(e)) => log::warn!("VFO {}: {}", state::VfoConfig::name(index), e),
+                None => {}
+            }
+        }
+        let active = usize::from(config.vfo.active.eq_ignore_ascii_case("b"));
+        state_guard.sdr.active_vfo = active;
+        if startup.frequency.is_none() && [&config.vfo.a, &config.vfo.b][active].is_some() {
+            let vfo = state_guard.sdr.vfo[active];
+            state_guard.tuned_to_vfo(vfo, vfo.frequency);
+        }
+        // Each mode's settings as they were left, the starting mode's taken
+        // up now, from the built-in table if it hasn't been used; a VFO's
+        // filter width, the profile's mode and a squelch given win
+        let memory = state::ModeMemory::from_config(&config.modes);
+        let decoder = &mut state_guard.decoder;
+        let filter_width = decoder.filter_width;
+        decoder.restore(memory.get(decoder.mode));
+        decoder.filter_width = filter_width.or(decoder.filter_width);
+        decoder.mode_memory = memory;
+        startup.apply(&mut state_guard);
+        state_guard.decoder.audio_filters = config.audio.filters;
+        state_guard.decoder.limiter = config
+            .audio
+            .limiter
+            .then(|| dsp::filters::SoftLimiter::new(config.audio.limiter_threshold));
+        state_guard.spectrum.set_waterfall_history(config.ui.waterfall_history);
+        state_guard.priority.frequency = config
+            .priority
+            .frequency
+            .map(|mhz| (mhz * 1_000_000.0).round() as u32);
+        state_guard.priority.enabled = config.priority.enabled;
+        state_guard.decoder.log_enabled = decode_log_path.is_some();
+        state_guard.decoder.log_path =
+            decode_log_path.unwrap_or_else(|| args.record_dir.join("decodes.jsonl"));
+    }
 
+    state
+}
+
+/// A receiver's audio for the speaker, and whether it should be heard
+type ReceiverAudio = (HeapCons<f32>, Arc<AtomicBool>);
+
+/// Threads running one receiver
+struct Pipeline {
+    /// Joined first, so its recording is flushed
+    recorder: std::thread::JoinHandle<()>,
+    others: Vec<std::thread::JoinHandle<()>>,
+}
+
+/// Open a receiver with `open` and start its SDR, recorder, decoder and DSP
+/// threads, returning the commands into it and its audio, with whether that
+/// should be heard; with `wait`, the receiver is opened in the background
+/// once it can be, and with `reopen` it is opened again if it stalls
+#[allow(clippy::too_many_arguments)]
+fn start_receiver(
+    open: impl FnMut() -> Result<Box<dyn sdr::SdrSource>> + Send + 'static,
+    wait: bool,
+    reopen: bool,
+    state: &state::SharedState,
+    buffering: audio::latency::Buffering,
+    speaker_rate: &Arc<AtomicU32>,
+    stream_txs: Vec<channel::Sender<Vec<f32>>>,
+    outputs: dsp::decoder::DecoderOutputs,
+    message_log: &Arc<parking_lot::Mutex<dsp::decoder::MessageLog>>,
+    outbox: Option<mqtt::Outbox>,
+    aircraft_export: Option<dsp::decoder::AircraftExport>,
+    recording: &types::RecordingConfig,
+    shutdown: &Arc<AtomicBool>,
+) -> Result<(state::Receiver, ReceiverAudio, Pipeline)> {
     // Create channel for IQ samples (SDR -> DSP)
-    let (samples_tx, samples_rx) = channel::bounded(64);
+    let (samples_tx, samples_rx) = channel::bounded(buffering.iq_queue);
 
     // Create channel for commands (UI -> SDR)
     let (command_tx, command_rx) = channel::unbounded();
 
-    // Create ring buffer for audio (DSP -> Audio)
-    const AUDIO_BUFFER_SIZE: usize = 48000; // 1 second at 48kHz
-    let audio_ring = HeapRb::<f32>::new(AUDIO_BUFFER_SIZE);
+    // Create channel for recorder events (SDR/DSP -> Recorder)
+    let (recorder_tx, recorder_rx) = channel::bounded(256);
+
+    // Create ring buffer for audio (DSP -> Audio), holding no more than the
+    // latency asked for
+    let audio_ring = HeapRb::<f32>::new(buffering.audio_ring);
     let (audio_producer, audio_consumer) = audio_ring.split();
+    let audible = Arc::new(AtomicBool::new(true));
 
-    // Start TCP streaming server if requested
-    let stream_tx = if let Some(port) = args.audio_port {
-        log::info!("Starting audio streaming server on port {}...", port);
-        Some(streaming::start_streaming_server(port, shutdown.clone())?)
+    // Start SDR thread
+    log::info!("Starting SDR thread...");
+    let sdr_thread = if wait {
+        sdr::start_sdr_thread_when_available(
+            open,
+            reopen,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx.clone(),
+            shutdown.clone(),
+        )
     } else {
-        None
+        let mut open = open;
+        let source = open()?;
+        sdr::start_sdr_thread(
+            source,
+            reopen.then(|| Box::new(open) as sdr::Reopen),
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx.clone(),
+            shutdown.clone(),
+        )?
     };
 
-    // Start SDR thread
-    log::info!("Starting SDR thread...");
-    let sdr_thread = sdr::start_sdr_thread(
-        args.device,
+    // Start recorder thread, with the command run on its segments
+    log::info!("Starting recorder thread...");
+    let hook_state = state.clone();
+    let post_record_hook = recorder::PostRecordHook::new(
+        &recording.post_record_command,
+        recording.post_record_jobs,
+        Arc::new(move |failure| hook_state.write().ui.status_message = failure),
+    );
+    if post_record_hook.is_some() {
+        log::info!("Running {:?} on each squelch segment", recording.post_record_command);
+    }
+    let recorder_thread = recorder::start_recorder_thread(
         state.clone(),
-        samples_tx,
-        command_rx,
+        recorder_rx,
+        post_record_hook,
         shutdown.clone(),
-    )?;
+    );
 
-    // Start DSP processing thread
-    log::info!("Starting DSP thread...");
-    let dsp_thread = dsp::start_dsp_thread(
+    // Start decoder threads (DSP -> Decoders), one per receive chain
+    log::info!("Starting decoder threads...");
+    let (decoder_tap, decoder_rx) = dsp::decoder::decoder_channel();
+    let (channel_b_tap, channel_b_rx) = dsp::decoder::decoder_channel();
+    // Decoders that make images (APT) write them with the recordings
+    let outputs = dsp::decoder::DecoderOutputs {
+        image_dir: state.read().recording.output_dir.clone(),
+        ..outputs
+    };
+    let decoder_thread = dsp::decoder::start_decoder_thread(
+        state.clone(),
+        types::Chain::A,
+        dsp::decoder::DecoderRegistry::new(outputs.clone()),
+        decoder_rx,
+        message_log.clone(),
+        outbox.clone(),
+        aircraft_export,
+        shutdown.clone(),
+    );
+    let channel_b_thread = dsp::decoder::start_decoder_thread(
         state.clone(),
-        samples_rx,
-        Some(audio_producer),
-        stream_tx,
+        types::Chain::B,
+        dsp::decoder::DecoderRegistry::new(outputs),
+        channel_b_rx,
+        message_log.clone(),
+        outbox,
+        None,
         shutdown.clone(),
     );
 
-    // Initialize audio output (local speaker)
-    log::info!("Starting audio output...");
-    let _audio_output = AudioOutput::new(audio_consumer)?;
+    // Start DSP processing thread
+    log::info!("Starting DSP thread...");
+    let outputs = dsp::DspOutputs {
+        audio: Some(audio_producer),
+        audible: audible.clone(),
+        speaker_rate: speaker_rate.clone(),
+        streams: stream_txs,
+        decoder_tap,
+        channel_b_tap,
+        recorder_tx,
+    };
+    let dsp_thread = dsp::start_dsp_thread(state.clone(), samples_rx, outputs, shutdown.clone());
 
+    let receiver = state::Receiver {
+        state: state.clone(),
+        commands: command_tx,
+    };
+    let pipeline = Pipeline {
+        recorder: recorder_thread,
+        others: vec![sdr_thread, dsp_thread, decoder_thread, channel_b_thread],
+    };
+    Ok((receiver, (audio_consumer, audible), pipeline))
+}
+
+/// Run the self-test against the first receiver
+fn self_test(args: &Args) -> bool {
+    let defaults = AppState::default();
+    let settings = selftest::Settings {
+        frequency: args
+            .frequency
+            .map_or(defaults.sdr.frequency, |mhz| (mhz * 1_000_000.0) as u32),
+        sample_rate: defaults.sdr.sample_rate,
+        gain: args.gain.map(|gain| (gain * 10.0) as i32),
+        enumerate: args.remote.is_none() && args.driver == sdr::Driver::Rtl,
+        audio_port: args.audio_port,
+    };
+    selftest::run(
+        || match &args.remote {
+            Some(addr) => Ok(Box::new(sdr::RtlTcpSource::open(addr)?)),
+            None => sdr::open_source(args.driver, args.device[0], &args.device_args),
+        },
+        &settings,
+    )
+}
+
+/// Measure the first receiver's PPM correction against a carrier at `mhz`
+/// and print it
+fn calibrate(args: &Args, mhz: f64) -> Result<()> {
+    let config = types::AppConfig::load(args.config.as_deref())?;
+    let settings = calibrate::Settings {
+        carrier: (mhz * 1_000_000.0).round() as u32,
+        sample_rate: AppState::default().sdr.sample_rate,
+        gain: args.gain.map(|gain| (gain * 10.0) as i32),
+        ppm: config.sdr.ppm_error,
+    };
+    println!(
+        "Calibrating against {:.3} MHz for {}s...",
+        mhz,
+        calibrate::AVERAGE_TIME.as_secs()
+    );
+    let measurement = calibrate::run(
+        || match &args.remote {
+            Some(addr) => Ok(Box::new(sdr::RtlTcpSource::open(addr)?)),
+            None => sdr::open_source(args.driver, args.device[0], &args.device_args),
+        },
+        &settings,
+    )?;
+    println!("{}", measurement.describe());
+    println!(
+        "PPM correction: {} (was {}); set ppm_error = {} under [sdr] in the config",
+        measurement.corrected(settings.ppm),
+        settings.ppm,
+        measurement.corrected(settings.ppm)
+    );
+    Ok(())
+}
+
+/// Import bookmarks from a CSV file into the config file, then export the
+/// config file's to one, as asked
+fn bookmark_files(args: &Args) -> Result<()> {
+    let config = types::AppConfig::load(args.config.as_deref())?;
+    let mut bookmarks = state::Bookmarks::from_config(&config.bookmarks.list);
+    if let Some(path) = &args.import_bookmarks {
+        let config_path = args
+            .config
+            .clone()
+            .or_else(types::AppConfig::default_path)
+            .ok_or_else(|| anyhow::anyhow!("no config file location"))?;
+        let import = state::bookmark_csv::read(path)?;
+        for warning in &import.warnings {
+            eprintln!("{}: {}", path.display(), warning);
+        }
+        let summary = import.merge_into(&mut bookmarks);
+        types::AppConfig::save_value(&config_path, "bookmarks", "list", &bookmarks.to_toml())?;
+        println!("{} into {}", summary, config_path.display());
+    }
+    if let Some(path) = &args.export_bookmarks {
+        state::bookmark_csv::write(path, bookmarks.entries())?;
+        println!(
+            "{} bookmarks exported to {}",
+            bookmarks.entries().len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Send a `remote` command to the running instance on `port` (or the
+/// config file's), print the response and return the exit code
+fn remote(args: &Args, port: Option<u16>, receiver: Option<usize>, action: &RemoteAction) -> i32 {
+    let port = match port {
+        Some(port) => port,
+        None => match types::AppConfig::load(args.config.as_deref()) {
+            Ok(config) => config.control.port,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                return 2;
+            }
+        },
+    };
+    let action = match action {
+        RemoteAction::SetFreq { frequency } => control::Action::SetFrequency {
+            frequency: control::Frequency::Hz(*frequency),
+        },
+        RemoteAction::SetMode { mode } => control::Action::SetMode {
+            mode: mode.file_tag().to_string(),
+        },
+        RemoteAction::SetGain { gain } => control::Action::SetGain { gain: gain.clone() },
+        RemoteAction::Record { action } => control::Action::Record { action: *action },
+        RemoteAction::Status => control::Action::Status,
+    };
+    match control::send("127.0.0.1", port, &control::Request { receiver, action }) {
+        Ok(response) if response.ok => {
+            if let Some(status) = &response.status {
+                println!("{:#}", status);
+            }
+            if let Some(message) = &response.message {
+                println!("{}", message);
+            }
+            0
+        }
+        Ok(response) => {
+            eprintln!("Refused: {}", response.error.unwrap_or_default());
+            1
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            3
+        }
+    }
+}
+
+/// The station from --qth or the config file, None if neither places it
+fn station(args: &Args, config: &types::AppConfig) -> Result<Option<util::geo::Station>> {
+    let unit = config.station.units.parse().map_err(anyhow::Error::msg)?;
+    let location = match (args.qth, config.station.latitude, config.station.longitude) {
+        (Some(qth), _, _) => Some(qth),
+        (None, Some(lat), Some(lon)) => Some(
+            util::geo::Location::new(lat, lon).ok_or_else(|| {
+                anyhow::anyhow!("[station] latitude or longitude is out of range")
+            })?,
+        ),
+        _ => None,
+    };
+    Ok(location.map(|location| util::geo::Station { location, unit }))
+}
+
+/// Run the terminal UI until quit
+fn run_tui(
+    receivers: state::Receivers,
+    config: &types::AppConfig,
+    profiles: Vec<profile::Profile>,
+    config_path: Option<std::path::PathBuf>,
+    spectrum_mode: ui::widgets::SpectrumMode,
+    station: Option<util::geo::Station>,
+    dump_waterfall_on_exit: bool,
+) -> Result<()> {
     // Initialize the UI app
-    let mut app = App::new(state);
-    app.set_command_tx(command_tx);
+    let mut app = App::new(receivers.current().state.clone());
+    app.set_receivers(receivers);
+    let (keymap, problems) = ui::keymap::Keymap::from_config(&config.keys);
+    for problem in &problems {
+        log::warn!("Key bindings: {}", problem);
+    }
+    if let Some(first) = problems.first() {
+        app.set_status(match problems.len() {
+            1 => format!("Key bindings: {}", first),
+            n => format!("Key bindings: {} (+{} more in the log)", first, n - 1),
+        });
+    }
+    app.set_keymap(keymap);
+    let (theme, problems) = ui::theme::Theme::from_config(&config.theme);
+    for problem in &problems {
+        log::warn!("Theme: {}", problem);
+    }
+    if let Some(first) = problems.first() {
+        app.set_status(format!("Theme: {}", first));
+    }
+    app.set_theme(theme);
+    app.set_spectrum_mode(spectrum_mode);
+    app.set_spectrum_invert(config.ui.spectrum_invert);
+    app.set_channel_grid((config.ui.channel_grid > 0).then_some(config.ui.channel_grid));
+    let (layout, problems) = ui::layout::PaneLayout::from_config(&config.ui.layout);
+    for problem in &problems {
+        log::warn!("Layout: {}", problem);
+    }
+    if let Some(first) = problems.first() {
+        app.set_status(format!("Layout: {}", first));
+    }
+    app.set_layout(layout);
+    app.set_station(station);
+    app.set_profiles(profiles);
+    app.set_config_path(config_path);
 
     // Initialize terminal
     let mut terminal = ui::init()?;
+    let mut title = config.ui.terminal_title.then(ui::title::TitleUpdater::default);
+    if title.is_some() {
+        if let Err(e) = ui::title::save() {
+            log::warn!("Failed to save the terminal title: {}", e);
+        }
+    }
 
-    // Main application loop
+    // Main application loop: input comes from its own thread, and the
+    // screen is redrawn after each event, each change to the state from the
+    // other threads, or on the render tick
+    let events = UiEvents::start(ui::events::terminal_reader(), ui::events::RENDER_INTERVAL);
+    app.state.write().changed = events.notifier();
+    for receiver in app.receivers.iter().flat_map(|receivers| receivers.iter()) {
+        receiver.state.write().changed = events.notifier();
+    }
+    ui::render(&mut terminal, &app)?;
     loop {
-        // Render UI
-        ui::render(&mut terminal, &app)?;
-
-        // Handle input
-        ui::input::handle_input(&mut app)?;
+        let redraw = match events.next() {
+            Dispatch::Input(event) => {
+                ui::input::handle_event(&mut app, event)?;
+                // Keys that queued up during a slow render are all taken
+                // before the next one
+                !events.input_pending()
+            }
+            Dispatch::Render => {
+                ui::input::handle_tick(&mut app)?;
+                let now = std::time::Instant::now();
+                let changed = title
+                    .as_mut()
+                    .and_then(|title| title.update(ui::title::app_title(&app), now));
+                if let Some(changed) = changed {
+                    if let Err(e) = ui::title::set(&changed) {
+                        log::warn!("Failed to set the terminal title: {}", e);
+                    }
+                }
+                true
+            }
+            Dispatch::Changed => true,
+            Dispatch::Closed => break,
+        };
 
         // Check if we should quit
         if app.should_quit() {
             break;
         }
+        if redraw {
+            ui::render(&mut terminal, &app)?;
+        }
+    }
+    // The input thread stops before the terminal is handed back
+    drop(events);
+
+    if let Err(e) = app.save_vfos() {
+        log::warn!("Failed to save VFOs: {:#}", e);
+    }
+    if let Err(e) = app.save_mode_settings() {
+        log::warn!("Failed to save the mode settings: {:#}", e);
+    }
+    if let Err(e) = app.save_layout() {
+        log::warn!("Failed to save the layout: {:#}", e);
+    }
+    if app.state.write().ui.frequency_history.settle_now() {
+        let recent = app.state.read().ui.frequency_history.to_toml();
+        if let Err(e) = app.save_setting("history", "recent", &recent) {
+            log::warn!("Failed to save frequency history: {:#}", e);
+        }
+    }
+    if let Err(e) = app.write_all_settings() {
+        log::warn!("Failed to save settings: {:#}", e);
+    }
+    if dump_waterfall_on_exit {
+        dump_waterfall(&app.state, app.theme.waterfall, app.spectrum_invert);
     }
 
     // Restore terminal
-    ui::restore()?;
+    ui::restore()
+}
 
-    // Signal all threads to stop
-    log::info!("Shutting down threads...");
-    shutdown.store(true, Ordering::Relaxed);
+/// Save the waterfall history in `state` as a PNG in the recording
+/// directory, logging where
+fn dump_waterfall(state: &state::SharedState, colormap: ui::theme::Colormap, inverted: bool) {
+    let (snapshot, path) = {
+        let state = state.read();
+        let path =
+            waterfall_png::export_path(&state.recording.output_dir, &chrono::Local::now());
+        (waterfall_png::Snapshot::take(&state, colormap, inverted), path)
+    };
+    match waterfall_png::export(&snapshot, &path) {
+        Ok(rows) => log::info!("Saved {} waterfall rows to {}", rows, path.display()),
+        Err(e) => log::warn!("Failed to save the waterfall: {:#}", e),
+    }
+}
 
-    // Wait for threads to finish
-    let _ = sdr_thread.join();
-    let _ = dsp_thread.join();
+/// Set by Ctrl-C or SIGTERM in headless mode
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
-    log::info!("RTL-SDR TUI shutting down");
-    Ok(())
+/// Wait without a terminal until the scan (if any) finishes or Ctrl-C
+fn wait_headless(scan_thread: Option<&std::thread::JoinHandle<()>>) {
+    catch_interrupts();
+    log::info!("Running headless");
+    loop {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            log::info!("Interrupted");
+            break;
+        }
+        if scan_thread.is_some_and(|thread| thread.is_finished()) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+}
+
+/// Have Ctrl-C and SIGTERM set [`INTERRUPTED`] so shutdown runs as usual
+#[cfg(unix)]
+fn catch_interrupts() {
+    extern "C" fn interrupted(_signal: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+    let handler = interrupted as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Have Ctrl-C, Ctrl-Break and closing the console window set
+/// [`INTERRUPTED`] so shutdown runs as usual
+#[cfg(windows)]
+fn catch_interrupts() {
+    use windows_sys::Win32::Foundation::BOOL;
+    use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+
+    unsafe extern "system" fn interrupted(_event: u32) -> BOOL {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+        1
+    }
+    unsafe {
+        SetConsoleCtrlHandler(Some(interrupted), 1);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn catch_interrupts() {
+    // Ctrl-C ends the process without the orderly shutdown
 }
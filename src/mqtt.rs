@@ -0,0 +1,575 @@
+//! MQTT publishing
+//!
+//! With `--mqtt <broker>`, everything goes to the broker under the
+//! configured topic prefix (`rtl-sdr-tui` by default), as JSON:
+//!
+//! - `<prefix>/decoded/<mode>`, e.g. `rtl-sdr-tui/decoded/ais`: each decoded
+//!   message as the JSON decode log has it, plus the receiver:
+//!   `{"timestamp", "mode", "content", "fields"?, "receiver"}`
+//! - `<prefix>/status`, every `status_interval` seconds: every receiver's
+//!   state as the HTTP server's `/status` gives it, `{"receivers": [...]}`
+//! - `<prefix>/alert`, retained: the SAME alert being broadcast,
+//!   `{"active": true, "alert", "receiver", "time", "fields"?}`, or
+//!   `{"active": false, "time"}` when there is none
+//!
+//! Decoder threads only push onto a bounded queue, which drops its oldest
+//! message when full, so a slow or missing broker never holds them up; a
+//! thread of its own talks to the broker, reconnecting with backoff. Talking
+//! to a broker needs the `mqtt` feature.
+
+// Without the mqtt feature only the queue is used
+#![cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+
+use crate::dsp::decoder::message_log::json_record;
+use crate::state::SharedState;
+use crate::types::config::MqttConfig;
+use crate::types::{DecodedMessage, DemodMode};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use crossbeam::queue::ArrayQueue;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Port brokers listen on without TLS
+pub const DEFAULT_PORT: u16 = 1883;
+
+/// Decoded messages waiting for the broker, shared by every decoder thread
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    queue: Arc<ArrayQueue<(usize, DecodedMessage)>>,
+    dropped: Arc<AtomicU64>,
+    /// Receiver the messages pushed through this handle come from
+    receiver: usize,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queue: Arc::new(ArrayQueue::new(capacity.max(1))),
+            dropped: Arc::new(AtomicU64::new(0)),
+            receiver: 0,
+        }
+    }
+
+    /// A handle for receiver number `receiver`'s decoders
+    pub fn for_receiver(&self, receiver: usize) -> Self {
+        Self {
+            receiver,
+            ..self.clone()
+        }
+    }
+
+    /// Queue `message`, dropping the oldest one if the queue is full
+    pub fn push(&self, message: &DecodedMessage) {
+        if self
+            .queue
+            .force_push((self.receiver, message.clone()))
+            .is_some()
+        {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn pop(&self) -> Option<(usize, DecodedMessage)> {
+        self.queue.pop()
+    }
+
+    /// Messages dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Host and port from a broker URL such as "mqtt://broker.local:1883",
+/// "tcp://10.0.0.2" or "broker.local"
+pub fn parse_broker(url: &str) -> Result<(String, u16), String> {
+    let address = match url.split_once("://") {
+        Some(("mqtt" | "tcp", address)) => address,
+        Some((scheme, _)) => {
+            return Err(format!(
+                "unsupported broker scheme '{}' (expected mqtt:// or tcp://)",
+                scheme
+            ))
+        }
+        None => url,
+    };
+    let address = address.trim_end_matches('/');
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(':') => (
+            host,
+            port.parse()
+                .map_err(|_| format!("invalid broker port '{}'", port))?,
+        ),
+        _ => (address, DEFAULT_PORT),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(format!("no broker host in '{}'", url));
+    }
+    Ok((host.to_string(), port))
+}
+
+/// Topic for messages decoded in `mode`
+pub fn decoded_topic(prefix: &str, mode: DemodMode) -> String {
+    format!(
+        "{}/decoded/{}",
+        prefix,
+        mode.file_tag().to_ascii_lowercase()
+    )
+}
+
+/// A decoded message from receiver number `receiver`
+pub fn decoded_payload(receiver: usize, message: &DecodedMessage) -> Value {
+    let mut record = json_record(message);
+    record["receiver"] = receiver.into();
+    record
+}
+
+/// The first SAME alert being broadcast on any receiver: its receiver,
+/// description and the fields of the message that started it, if still held
+fn current_alert(states: &[SharedState]) -> Option<(usize, String, Option<Value>)> {
+    states.iter().enumerate().find_map(|(receiver, state)| {
+        let state = state.read();
+        let alert = state.same_alert()?.clone();
+        let fields = state
+            .decoder
+            .messages
+            .iter()
+            .rev()
+            .find(|message| message.fields.contains_key("originator"))
+            .map(|message| Value::Object(message.fields.clone()));
+        Some((receiver, alert, fields))
+    })
+}
+
+/// The alert topic's payload for `alert` as `current_alert` gives it
+pub fn alert_payload(alert: Option<&(usize, String, Option<Value>)>, now: DateTime<Utc>) -> Value {
+    let time = now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    match alert {
+        Some((receiver, alert, fields)) => {
+            let mut payload = json!({
+                "active": true,
+                "alert": alert,
+                "receiver": receiver,
+                "time": time,
+            });
+            if let Some(fields) = fields {
+                payload["fields"] = fields.clone();
+            }
+            payload
+        }
+        None => json!({ "active": false, "time": time }),
+    }
+}
+
+/// Tracks the alert last published, to publish only changes
+#[derive(Debug, Default)]
+struct AlertWatch {
+    /// Receiver and alert last published; None before the first
+    published: Option<Option<(usize, String)>>,
+}
+
+impl AlertWatch {
+    /// The payload to publish if the alert has changed
+    fn update(&mut self, states: &[SharedState], now: DateTime<Utc>) -> Option<Value> {
+        let alert = current_alert(states);
+        let key = alert
+            .as_ref()
+            .map(|(receiver, alert, _)| (*receiver, alert.clone()));
+        if self.published.as_ref() == Some(&key) {
+            return None;
+        }
+        self.published = Some(key);
+        Some(alert_payload(alert.as_ref(), now))
+    }
+
+    /// Publish the alert again, after reconnecting
+    fn reset(&mut self) {
+        self.published = None;
+    }
+}
+
+/// Start publishing to `broker` from `outbox` and `states` until `shutdown`
+/// is set
+pub fn start_mqtt_thread(
+    broker: &str,
+    config: &MqttConfig,
+    outbox: Outbox,
+    states: Vec<SharedState>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>> {
+    let (host, port) = parse_broker(broker).map_err(anyhow::Error::msg)?;
+
+    #[cfg(not(feature = "mqtt"))]
+    {
+        let _ = (host, port, config, outbox, states, shutdown);
+        anyhow::bail!("Built without MQTT support; rebuild with --features mqtt")
+    }
+
+    #[cfg(feature = "mqtt")]
+    {
+        let mut options = rumqttc::MqttOptions::new(config.client_id.clone(), host, port);
+        options.set_keep_alive(client::KEEP_ALIVE);
+        let publisher = client::Publisher {
+            prefix: config.topic_prefix.trim_end_matches('/').to_string(),
+            status_interval: std::time::Duration::from_secs(config.status_interval.max(1)),
+            outbox,
+            states,
+        };
+        log::info!("Publishing to MQTT broker {}", broker);
+        Ok(thread::spawn(move || publisher.run(options, shutdown)))
+    }
+}
+
+#[cfg(feature = "mqtt")]
+mod client {
+    use super::*;
+    use rumqttc::{Client, Event, MqttOptions, Packet, QoS, RecvTimeoutError};
+    use std::time::{Duration, Instant};
+
+    pub const KEEP_ALIVE: Duration = Duration::from_secs(30);
+    /// Longest wait for the broker before publishing what's queued
+    const POLL: Duration = Duration::from_millis(100);
+    /// Requests the client holds on its way to the broker
+    const CLIENT_QUEUE: usize = 64;
+    /// Queued messages handed to the client at a time
+    const BATCH: usize = 16;
+    const MIN_BACKOFF: Duration = Duration::from_secs(1);
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+    pub struct Publisher {
+        pub prefix: String,
+        pub status_interval: Duration,
+        pub outbox: Outbox,
+        pub states: Vec<SharedState>,
+    }
+
+    impl Publisher {
+        pub fn run(self, options: MqttOptions, shutdown: Arc<AtomicBool>) {
+            let (client, mut connection) = Client::new(options, CLIENT_QUEUE);
+            let mut connected = false;
+            let mut backoff = MIN_BACKOFF;
+            let mut alert = AlertWatch::default();
+            let mut next_status = Instant::now();
+
+            while !shutdown.load(Ordering::Relaxed) {
+                match connection.recv_timeout(POLL) {
+                    Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                        log::info!("MQTT connected");
+                        connected = true;
+                        backoff = MIN_BACKOFF;
+                        alert.reset();
+                        next_status = Instant::now();
+                    }
+                    Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+                    Ok(Err(e)) => {
+                        if connected {
+                            log::warn!("MQTT connection lost: {}", e);
+                        } else {
+                            log::debug!("MQTT connection failed: {}", e);
+                        }
+                        connected = false;
+                        sleep_unless_shutdown(backoff, &shutdown);
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if connected {
+                    self.publish_pending(&client, &mut alert, &mut next_status);
+                }
+            }
+
+            let _ = client.try_disconnect();
+            // Give the disconnect a moment to go out
+            let _ = connection.recv_timeout(POLL);
+            log::info!(
+                "MQTT publisher stopped ({} messages dropped)",
+                self.outbox.dropped()
+            );
+        }
+
+        /// Hand the client queued messages, status when due and any change
+        /// of alert
+        fn publish_pending(
+            &self,
+            client: &Client,
+            alert: &mut AlertWatch,
+            next_status: &mut Instant,
+        ) {
+            let publish = |topic: String, payload: Value, retain: bool| {
+                if let Err(e) =
+                    client.try_publish(topic, QoS::AtMostOnce, retain, payload.to_string())
+                {
+                    log::debug!("MQTT publish dropped: {}", e);
+                }
+            };
+
+            for _ in 0..BATCH {
+                let Some((receiver, message)) = self.outbox.pop() else {
+                    break;
+                };
+                publish(
+                    decoded_topic(&self.prefix, message.mode),
+                    decoded_payload(receiver, &message),
+                    false,
+                );
+            }
+
+            let now = Instant::now();
+            if now >= *next_status {
+                let statuses = crate::http::snapshot(&self.states);
+                publish(
+                    format!("{}/status", self.prefix),
+                    json!({ "receivers": statuses }),
+                    false,
+                );
+                *next_status = now + self.status_interval;
+            }
+
+            if let Some(payload) = alert.update(&self.states, Utc::now()) {
+                publish(format!("{}/alert", self.prefix), payload, true);
+            }
+        }
+    }
+
+    fn sleep_unless_shutdown(duration: Duration, shutdown: &AtomicBool) {
+        let until = Instant::now() + duration;
+        while Instant::now() < until && !shutdown.load(Ordering::Relaxed) {
+            thread::sleep(POLL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+    use chrono::TimeZone;
+
+    fn message(content: &str) -> DecodedMessage {
+        let mut message = DecodedMessage::new(DemodMode::Ais, content.to_string());
+        message.timestamp = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        message
+    }
+
+    #[test]
+    fn test_parse_broker() {
+        assert_eq!(
+            parse_broker("mqtt://broker.local:1884"),
+            Ok(("broker.local".into(), 1884))
+        );
+        assert_eq!(
+            parse_broker("tcp://10.0.0.2/"),
+            Ok(("10.0.0.2".into(), 1883))
+        );
+        assert_eq!(parse_broker("broker"), Ok(("broker".into(), 1883)));
+        assert_eq!(parse_broker("[::1]:1883"), Ok(("::1".into(), 1883)));
+        assert!(parse_broker("mqtts://broker").is_err());
+        assert!(parse_broker("broker:port").is_err());
+        assert!(parse_broker("mqtt://").is_err());
+    }
+
+    #[test]
+    fn test_outbox_drops_oldest() {
+        let outbox = Outbox::new(2);
+        let second = outbox.for_receiver(1);
+        outbox.push(&message("one"));
+        second.push(&message("two"));
+        outbox.push(&message("three"));
+        assert_eq!(outbox.dropped(), 1);
+        let (receiver, message) = outbox.pop().unwrap();
+        assert_eq!((receiver, message.content.as_str()), (1, "two"));
+        assert_eq!(second.pop().unwrap().1.content, "three");
+        assert!(outbox.pop().is_none());
+    }
+
+    #[test]
+    fn test_decoded_payload() {
+        assert_eq!(
+            decoded_topic("rtl-sdr-tui", DemodMode::Adsb),
+            "rtl-sdr-tui/decoded/adsb"
+        );
+        let payload = decoded_payload(
+            1,
+            &message("!AIVDM").with_fields(json!({ "mmsi": 211234567 })),
+        );
+        assert_eq!(
+            payload,
+            json!({
+                "timestamp": "2024-06-01T12:00:00.000Z",
+                "mode": "AIS",
+                "content": "!AIVDM",
+                "fields": { "mmsi": 211234567 },
+                "receiver": 1,
+            })
+        );
+        // No fields, no key
+        assert!(decoded_payload(0, &message("x")).get("fields").is_none());
+    }
+
+    #[test]
+    fn test_alert_payloads() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let states = vec![AppState::new_shared(), AppState::new_shared()];
+        let mut watch = AlertWatch::default();
+
+        // Published once at the start, then only on change
+        assert_eq!(
+            watch.update(&states, now),
+            Some(json!({ "active": false, "time": "2024-06-01T12:00:00Z" }))
+        );
+        assert_eq!(watch.update(&states, now), None);
+
+        {
+            let mut state = states[1].write();
+            state.decoder.add_message(
+                message("SAME: Tornado Warning").with_fields(json!({ "originator": "WXR" })),
+            );
+            state.decoder.same_alert = Some("Tornado Warning (2 areas, 0h30m)".into());
+        }
+        assert_eq!(
+            watch.update(&states, now),
+            Some(json!({
+                "active": true,
+                "alert": "Tornado Warning (2 areas, 0h30m)",
+                "receiver": 1,
+                "time": "2024-06-01T12:00:00Z",
+                "fields": { "originator": "WXR" },
+            }))
+        );
+        assert_eq!(watch.update(&states, now), None);
+
+        // Again after reconnecting
+        watch.reset();
+        assert!(watch.update(&states, now).is_some());
+
+        states[1].write().decoder.same_alert = None;
+        assert_eq!(watch.update(&states, now).unwrap()["active"], false);
+    }
+
+    #[cfg(not(feature = "mqtt"))]
+    #[test]
+    fn test_needs_feature() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let started = start_mqtt_thread(
+            "mqtt://localhost",
+            &MqttConfig::default(),
+            Outbox::new(1),
+            Vec::new(),
+            shutdown,
+        );
+        assert!(started.is_err());
+    }
+
+    /// A broker that accepts one client and hands back what it publishes
+    #[cfg(feature = "mqtt")]
+    mod broker {
+        use std::io::{Read, Write};
+        use std::net::{SocketAddr, TcpListener, TcpStream};
+
+        /// Topic, payload and retain flag of a PUBLISH
+        pub type Published = (String, String, bool);
+
+        pub fn start() -> (SocketAddr, crossbeam::channel::Receiver<Published>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (tx, rx) = crossbeam::channel::unbounded();
+            std::thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                while let Some((header, body)) = packet(&mut stream) {
+                    match header >> 4 {
+                        // CONNECT: accept it
+                        1 => stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap(),
+                        // PUBLISH at QoS 0: topic, then payload
+                        3 => {
+                            let len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                            let topic = String::from_utf8(body[2..2 + len].to_vec()).unwrap();
+                            let payload = String::from_utf8(body[2 + len..].to_vec()).unwrap();
+                            if tx.send((topic, payload, header & 1 == 1)).is_err() {
+                                break;
+                            }
+                        }
+                        // PINGREQ
+                        12 => stream.write_all(&[0xd0, 0x00]).unwrap(),
+                        // DISCONNECT
+                        14 => break,
+                        _ => {}
+                    }
+                }
+            });
+            (addr, rx)
+        }
+
+        /// The fixed header's first byte and the rest of a packet
+        fn packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+            let mut byte = [0u8];
+            stream.read_exact(&mut byte).ok()?;
+            let header = byte[0];
+            let (mut len, mut shift) = (0usize, 0);
+            loop {
+                stream.read_exact(&mut byte).ok()?;
+                len |= ((byte[0] & 0x7f) as usize) << shift;
+                shift += 7;
+                if byte[0] & 0x80 == 0 {
+                    break;
+                }
+            }
+            let mut body = vec![0u8; len];
+            stream.read_exact(&mut body).ok()?;
+            Some((header, body))
+        }
+    }
+
+    #[cfg(feature = "mqtt")]
+    #[test]
+    fn test_publishes_to_broker() {
+        use std::time::Duration;
+
+        let (addr, published) = broker::start();
+        let state = AppState::new_shared();
+        state.write().sdr.frequency = 162_000_000;
+        let outbox = Outbox::new(16);
+        outbox.push(&message("!AIVDM,1,1,,A,13u?etPv2;0n:dDPwUM1U1Cb069D,0*24"));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let config = MqttConfig {
+            topic_prefix: "test/".to_string(),
+            ..MqttConfig::default()
+        };
+        let thread = start_mqtt_thread(
+            &format!("mqtt://{}", addr),
+            &config,
+            outbox,
+            vec![state],
+            shutdown.clone(),
+        )
+        .unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            received.push(published.recv_timeout(Duration::from_secs(10)).unwrap());
+        }
+        shutdown.store(true, Ordering::Relaxed);
+        thread.join().unwrap();
+
+        let find = |topic: &str| received.iter().find(|(t, _, _)| t == topic).unwrap();
+        let (_, decoded, retained) = find("test/decoded/ais");
+        assert!(!retained);
+        let decoded: Value = serde_json::from_str(decoded).unwrap();
+        assert_eq!(decoded["receiver"], 0);
+        assert!(decoded["content"].as_str().unwrap().starts_with("!AIVDM"));
+
+        let (_, status, _) = find("test/status");
+        let status: Value = serde_json::from_str(status).unwrap();
+        assert_eq!(status["receivers"][0]["frequency"], 162_000_000);
+
+        let (_, alert, retained) = find("test/alert");
+        assert!(retained);
+        assert_eq!(
+            serde_json::from_str::<Value>(alert).unwrap()["active"],
+            false
+        );
+    }
+}
@@ -0,0 +1,478 @@
+//! Priority channel watch
+//!
+//! Every few seconds the watcher checks one priority frequency while the user
+//! listens elsewhere. A priority channel inside the captured band is measured
+//! by a probe channel in the DSP thread, with no retune. One outside it means
+//! briefly retuning: audio is faded out first and held muted until the tuner
+//! and filters have settled on the way back, so the check doesn't click.
+//!
+//! When the channel is above the squelch threshold, audio switches to it and
+//! the status bar shows PRIORITY ACTIVE until it has been quiet for a moment.
+
+use crate::state::SharedState;
+use crate::types::{Chain, Command};
+use crossbeam::channel::Sender;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time for audio to fade out before the tuner moves
+const FADE: Duration = Duration::from_millis(20);
+/// Time for the tuner and filters to settle after a retune
+const SETTLE: Duration = Duration::from_millis(100);
+/// How long the channel is measured for
+const DWELL: Duration = Duration::from_millis(50);
+/// Quiet time before audio leaves an active priority channel
+const HANG: Duration = Duration::from_secs(2);
+/// Room kept between an in-band priority channel and the band edge in Hz
+const EDGE_MARGIN: i64 = 25_000;
+
+/// Where the receiver is listening when the watcher looks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tuning {
+    /// Center frequency in Hz
+    pub frequency: u32,
+    pub sample_rate: u32,
+    /// Chain A's offset from the center in Hz
+    pub offset: i32,
+    /// Power in dBFS at or above which the priority channel is active
+    pub threshold: f32,
+    /// Whether checks may retune; off while recording so a recording stays
+    /// on one frequency
+    pub can_retune: bool,
+}
+
+/// Where audio goes back to once the priority channel is quiet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Home {
+    /// Chain A's offset, the priority channel being in band
+    Offset(i32),
+    /// The center frequency a check retuned away from
+    Frequency(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Idle { next_check: Instant },
+    /// Fading audio out before retuning to `tune`; a check follows if there
+    /// is a `home` to come back to, otherwise this is the way back
+    Muting { until: Instant, tune: u32, home: Option<Home> },
+    /// Measuring the priority channel at `measure_at`
+    Checking { home: Home, measure_at: Instant },
+    /// Listening to the priority channel
+    Active { home: Home, quiet_since: Option<Instant> },
+    /// Back on the home frequency, muted until the tuner settles
+    Returning { until: Instant },
+}
+
+/// What the watcher wants done on a tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriorityAction {
+    /// Measure power at this offset from the center, or stop measuring
+    Probe(Option<i32>),
+    Mute(bool),
+    /// Retune the center frequency
+    Tune(u32),
+    /// Move chain A, and with it the audio, to this offset
+    Listen(i32),
+    /// The priority channel became active or went quiet
+    Active(bool),
+}
+
+/// Power at the probe in dBFS, None until a measurement comes in
+pub trait PowerSource {
+    fn power(&mut self) -> Option<f32>;
+}
+
+impl<F: FnMut() -> Option<f32>> PowerSource for F {
+    fn power(&mut self) -> Option<f32> {
+        self()
+    }
+}
+
+/// Priority watch bookkeeping, driven by an external clock and power source
+/// so it can be tested
+pub struct PriorityWatch {
+    /// Priority frequency in Hz
+    frequency: u32,
+    interval: Duration,
+    phase: Phase,
+}
+
+impl PriorityWatch {
+    /// Create a watch; the first check is on the first enabled tick
+    pub fn new(frequency: u32, interval: Duration, now: Instant) -> Self {
+        Self {
+            frequency,
+            interval,
+            phase: Phase::Idle { next_check: now },
+        }
+    }
+
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+
+    /// Advance to `now`; once `enabled` goes false, audio returns home
+    pub fn tick(
+        &mut self,
+        now: Instant,
+        enabled: bool,
+        tuning: &Tuning,
+        power: &mut impl PowerSource,
+    ) -> Vec<PriorityAction> {
+        use PriorityAction::*;
+
+        match self.phase {
+            Phase::Idle { next_check } => {
+                if !enabled {
+                    // Check straight away once enabled again
+                    self.phase = Phase::Idle { next_check: now };
+                    return Vec::new();
+                }
+                if now < next_check {
+                    return Vec::new();
+                }
+                self.phase = Phase::Idle { next_check: now + self.interval };
+
+                let offset = self.frequency as i64 - tuning.frequency as i64;
+                if offset == tuning.offset as i64 {
+                    // Already listening to it
+                    Vec::new()
+                } else if offset.abs() + EDGE_MARGIN <= tuning.sample_rate as i64 / 2 {
+                    self.phase = Phase::Checking {
+                        home: Home::Offset(tuning.offset),
+                        measure_at: now + DWELL,
+                    };
+                    vec![Probe(Some(offset as i32))]
+                } else if tuning.can_retune {
+                    self.phase = Phase::Muting {
+                        until: now + FADE,
+                        tune: self.frequency,
+                        home: Some(Home::Frequency(tuning.frequency)),
+                    };
+                    vec![Mute(true)]
+                } else {
+                    Vec::new()
+                }
+            }
+            Phase::Muting { until, tune, home } => {
+                if now < until {
+                    return Vec::new();
+                }
+                match home {
+                    Some(home) => {
+                        self.phase = Phase::Checking {
+                            home,
+                            measure_at: now + SETTLE + DWELL,
+                        };
+                        vec![Probe(Some(0)), Tune(tune)]
+                    }
+                    None => {
+                        self.phase = Phase::Returning { until: now + SETTLE };
+                        vec![Probe(None), Tune(tune)]
+                    }
+                }
+            }
+            Phase::Checking { home, measure_at } => {
+                if enabled && now < measure_at {
+                    return Vec::new();
+                }
+                if !enabled || !busy(tuning, power) {
+                    // Nothing heard; audio never left home
+                    return match home {
+                        Home::Offset(_) => {
+                            self.phase = Phase::Idle { next_check: now + self.interval };
+                            vec![Probe(None)]
+                        }
+                        Home::Frequency(frequency) => {
+                            self.phase = Phase::Returning { until: now + SETTLE };
+                            vec![Probe(None), Tune(frequency)]
+                        }
+                    };
+                }
+                self.phase = Phase::Active { home, quiet_since: None };
+                match home {
+                    Home::Offset(_) => {
+                        let offset = self.frequency as i64 - tuning.frequency as i64;
+                        vec![Listen(offset as i32), Active(true)]
+                    }
+                    Home::Frequency(_) => vec![Mute(false), Active(true)],
+                }
+            }
+            Phase::Active { home, quiet_since } => {
+                let quiet_since = if busy(tuning, power) {
+                    None
+                } else {
+                    Some(quiet_since.unwrap_or(now))
+                };
+                if enabled && quiet_since.is_none_or(|since| now < since + HANG) {
+                    self.phase = Phase::Active { home, quiet_since };
+                    return Vec::new();
+                }
+                match home {
+                    Home::Offset(offset) => {
+                        self.phase = Phase::Idle { next_check: now + self.interval };
+                        vec![Probe(None), Listen(offset), Active(false)]
+                    }
+                    Home::Frequency(frequency) => {
+                        self.phase = Phase::Muting {
+                            until: now + FADE,
+                            tune: frequency,
+                            home: None,
+                        };
+                        vec![Mute(true), Active(false)]
+                    }
+                }
+            }
+            Phase::Returning { until } => {
+                if now < until {
+                    return Vec::new();
+                }
+                self.phase = Phase::Idle { next_check: now + self.interval };
+                vec![Mute(false)]
+            }
+        }
+    }
+}
+
+/// Whether the probe is at or above the threshold
+fn busy(tuning: &Tuning, power: &mut impl PowerSource) -> bool {
+    power.power().is_some_and(|level| level >= tuning.threshold)
+}
+
+/// Start the priority watch thread
+///
+/// `threshold` stands in for the squelch level while the squelch is off.
+pub fn start_priority_thread(
+    mut watch: PriorityWatch,
+    threshold: f32,
+    state: SharedState,
+    command_tx: Sender<Command>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        log::info!(
+            "Priority watch thread started for {:.3} MHz",
+            watch.frequency() as f64 / 1_000_000.0
+        );
+
+        while !shutdown.load(Ordering::Relaxed) {
+            let (enabled, tuning) = {
+                let state = state.read();
+                let tuning = Tuning {
+                    frequency: state.sdr.frequency,
+                    sample_rate: state.sdr.sample_rate,
+                    offset: state.channels.offset_a,
+                    threshold: state.decoder.squelch_level.unwrap_or(threshold),
+                    can_retune: !state.recording.is_recording,
+                };
+                (state.priority.enabled, tuning)
+            };
+            let mut power = || state.read().priority.probe_level;
+
+            for action in watch.tick(Instant::now(), enabled, &tuning, &mut power) {
+                match action {
+                    PriorityAction::Probe(offset) => {
+                        let mut state_guard = state.write();
+                        state_guard.priority.probe_offset = offset;
+                        state_guard.priority.probe_level = None;
+                    }
+                    PriorityAction::Mute(muted) => state.write().priority.muted = muted,
+                    PriorityAction::Tune(frequency) => {
+                        let _ = command_tx.send(Command::SetFrequency(frequency));
+                    }
+                    PriorityAction::Listen(offset) => {
+                        let _ = command_tx.send(Command::SetChannelOffset(Chain::A, offset));
+                    }
+                    PriorityAction::Active(active) => {
+                        let mhz = watch.frequency() as f64 / 1_000_000.0;
+                        let mut state_guard = state.write();
+                        state_guard.priority.active = active;
+                        state_guard.ui.status_message = if active {
+                            log::info!("Priority channel {:.3} MHz active", mhz);
+                            format!("Priority channel active: {:.3} MHz", mhz)
+                        } else {
+                            "Priority channel quiet".to_string()
+                        };
+                    }
+                }
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        log::info!("Priority watch thread stopped");
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const PRIORITY: u32 = 162_550_000;
+    const INTERVAL: Duration = Duration::from_secs(5);
+
+    fn tuning(frequency: u32) -> Tuning {
+        Tuning {
+            frequency,
+            sample_rate: 2_048_000,
+            offset: 0,
+            threshold: -40.0,
+            can_retune: true,
+        }
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// A watch and a clock starting at `t0`
+    fn watch() -> (PriorityWatch, Instant) {
+        let t0 = Instant::now();
+        (PriorityWatch::new(PRIORITY, INTERVAL, t0), t0)
+    }
+
+    #[test]
+    fn test_in_band_check_quiet() {
+        let (mut watch, t0) = watch();
+        let tuning = tuning(162_400_000);
+        let level = Cell::new(None);
+        let mut power = || level.get();
+
+        assert_eq!(
+            watch.tick(t0, true, &tuning, &mut power),
+            [PriorityAction::Probe(Some(150_000))]
+        );
+        // Nothing until the dwell is over, and no measurement yet reads quiet
+        assert!(watch.tick(t0 + ms(10), true, &tuning, &mut power).is_empty());
+        level.set(Some(-70.0));
+        assert_eq!(
+            watch.tick(t0 + DWELL, true, &tuning, &mut power),
+            [PriorityAction::Probe(None)]
+        );
+
+        // The next check waits out the interval
+        let back = t0 + DWELL;
+        assert!(watch.tick(back + INTERVAL - ms(1), true, &tuning, &mut power).is_empty());
+        assert_eq!(watch.tick(back + INTERVAL, true, &tuning, &mut power).len(), 1);
+    }
+
+    #[test]
+    fn test_in_band_active_until_quiet() {
+        let (mut watch, t0) = watch();
+        let tuning = Tuning { offset: -20_000, ..tuning(162_400_000) };
+        let level = Cell::new(Some(-30.0));
+        let mut power = || level.get();
+
+        watch.tick(t0, true, &tuning, &mut power);
+        assert_eq!(
+            watch.tick(t0 + DWELL, true, &tuning, &mut power),
+            [PriorityAction::Listen(150_000), PriorityAction::Active(true)]
+        );
+
+        // Stays while the channel is busy, and a gap shorter than the hang
+        // doesn't end it
+        let t = t0 + ms(1000);
+        level.set(Some(-60.0));
+        assert!(watch.tick(t, true, &tuning, &mut power).is_empty());
+        level.set(Some(-35.0));
+        assert!(watch.tick(t + ms(1500), true, &tuning, &mut power).is_empty());
+        level.set(Some(-60.0));
+        assert!(watch.tick(t + ms(2000), true, &tuning, &mut power).is_empty());
+        assert!(watch.tick(t + ms(2000) + HANG - ms(1), true, &tuning, &mut power).is_empty());
+        assert_eq!(
+            watch.tick(t + ms(2000) + HANG, true, &tuning, &mut power),
+            [
+                PriorityAction::Probe(None),
+                PriorityAction::Listen(-20_000),
+                PriorityAction::Active(false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_retune_check_is_muted_and_settled() {
+        let (mut watch, t0) = watch();
+        let home = tuning(144_390_000);
+        let away = tuning(PRIORITY);
+        let level = Cell::new(Some(-70.0));
+        let mut power = || level.get();
+
+        // Audio fades out before the tuner moves
+        assert_eq!(watch.tick(t0, true, &home, &mut power), [PriorityAction::Mute(true)]);
+        assert!(watch.tick(t0 + FADE - ms(1), true, &home, &mut power).is_empty());
+        assert_eq!(
+            watch.tick(t0 + FADE, true, &home, &mut power),
+            [PriorityAction::Probe(Some(0)), PriorityAction::Tune(PRIORITY)]
+        );
+
+        // Measured only once the tuner has settled
+        let tuned = t0 + FADE;
+        assert!(watch.tick(tuned + SETTLE, true, &away, &mut power).is_empty());
+        assert_eq!(
+            watch.tick(tuned + SETTLE + DWELL, true, &away, &mut power),
+            [PriorityAction::Probe(None), PriorityAction::Tune(144_390_000)]
+        );
+
+        // Muted until the way back has settled too
+        let back = tuned + SETTLE + DWELL;
+        assert!(watch.tick(back + SETTLE - ms(1), true, &home, &mut power).is_empty());
+        assert_eq!(
+            watch.tick(back + SETTLE, true, &home, &mut power),
+            [PriorityAction::Mute(false)]
+        );
+    }
+
+    #[test]
+    fn test_retune_active_then_disabled() {
+        let (mut watch, t0) = watch();
+        let home = tuning(144_390_000);
+        let away = tuning(PRIORITY);
+        let level = Cell::new(Some(-20.0));
+        let mut power = || level.get();
+
+        watch.tick(t0, true, &home, &mut power);
+        watch.tick(t0 + FADE, true, &home, &mut power);
+        let measured = t0 + FADE + SETTLE + DWELL;
+        assert_eq!(
+            watch.tick(measured, true, &away, &mut power),
+            [PriorityAction::Mute(false), PriorityAction::Active(true)]
+        );
+
+        // Turning the watch off returns home through the same fade
+        let t = measured + ms(500);
+        assert_eq!(
+            watch.tick(t, false, &away, &mut power),
+            [PriorityAction::Mute(true), PriorityAction::Active(false)]
+        );
+        assert_eq!(
+            watch.tick(t + FADE, false, &away, &mut power),
+            [PriorityAction::Probe(None), PriorityAction::Tune(144_390_000)]
+        );
+        assert_eq!(
+            watch.tick(t + FADE + SETTLE, false, &home, &mut power),
+            [PriorityAction::Mute(false)]
+        );
+        assert!(watch.tick(t + INTERVAL * 2, false, &home, &mut power).is_empty());
+    }
+
+    #[test]
+    fn test_no_check_needed_or_allowed() {
+        let (mut watch, t0) = watch();
+        let mut power = || Some(0.0);
+
+        // Already listening to the priority channel
+        let on_it = Tuning { offset: 150_000, ..tuning(162_400_000) };
+        assert!(watch.tick(t0, true, &on_it, &mut power).is_empty());
+
+        // Out of band while recording
+        let recording = Tuning { can_retune: false, ..tuning(144_390_000) };
+        assert!(watch.tick(t0 + INTERVAL, true, &recording, &mut power).is_empty());
+
+        // Disabled
+        assert!(watch.tick(t0 + INTERVAL * 2, false, &tuning(144_390_000), &mut power).is_empty());
+    }
+}
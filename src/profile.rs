@@ -0,0 +1,319 @@
+//! Startup profiles
+//!
+//! A `[profiles.<name>]` section of the config file sets the receiver up for
+//! one job, e.g. an ADS-B feeder or an APRS igate: frequency, mode, sample
+//! rate, gain, squelch and the streaming and recording options. One is
+//! picked with `--profile <name>` at startup, or from the profile list (O)
+//! while running.
+//!
+//! Each setting comes from the first of these that gives it: the command
+//! line, the profile, the config file (the VFO and each mode's settings as
+//! they were left), then the built-in default.
+
+use crate::recorder::SampleFormat;
+use crate::state::{AppState, RecordingMode};
+use crate::streaming::codec::StreamCodec;
+use crate::types::config::ProfileConfig;
+use crate::types::DemodMode;
+use anyhow::{anyhow, bail, Result};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Settings a profile or the command line can give, None where they don't
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Settings {
+    /// Center frequency in Hz
+    pub frequency: Option<u32>,
+    pub mode: Option<DemodMode>,
+    /// Sample rate in Hz, held until the mode is next changed
+    pub sample_rate: Option<u32>,
+    /// Manual tuner gain in dB
+    pub gain: Option<f32>,
+    /// Squelch threshold in dBFS
+    pub squelch: Option<f32>,
+    /// TCP audio streaming port
+    pub audio_port: Option<u16>,
+    pub stream_codec: Option<StreamCodec>,
+    pub record_dir: Option<PathBuf>,
+    pub record_mode: Option<RecordingMode>,
+    pub record_format: Option<SampleFormat>,
+}
+
+impl Settings {
+    /// Each setting from `self`, or from `lower` where `self` doesn't give it
+    pub fn or(self, lower: Settings) -> Settings {
+        Settings {
+            frequency: self.frequency.or(lower.frequency),
+            mode: self.mode.or(lower.mode),
+            sample_rate: self.sample_rate.or(lower.sample_rate),
+            gain: self.gain.or(lower.gain),
+            squelch: self.squelch.or(lower.squelch),
+            audio_port: self.audio_port.or(lower.audio_port),
+            stream_codec: self.stream_codec.or(lower.stream_codec),
+            record_dir: self.record_dir.or(lower.record_dir),
+            record_mode: self.record_mode.or(lower.record_mode),
+            record_format: self.record_format.or(lower.record_format),
+        }
+    }
+
+    /// These settings over those of the profile called `name`, if one is
+    pub fn with_profile(self, profiles: &[Profile], name: Option<&str>) -> Result<Settings> {
+        let Some(name) = name else {
+            return Ok(self);
+        };
+        let profile = Profile::find(profiles, name)?;
+        log::info!("Profile {}: {}", profile.name, profile.summary());
+        Ok(self.or(profile.settings.clone()))
+    }
+
+    /// Set the mode, squelch and recording options given in `state`
+    ///
+    /// The mode comes first, so its remembered squelch is taken up and then
+    /// replaced by the one given here. The frequency, sample rate and gain
+    /// are the receiver's to set, and streaming is set up at startup only.
+    pub fn apply(&self, state: &mut AppState) {
+        if let Some(mode) = self.mode {
+            state.decoder.switch_mode(mode);
+        }
+        if let Some(level) = self.squelch {
+            state.decoder.squelch_level = Some(level);
+        }
+        if let Some(dir) = &self.record_dir {
+            state.recording.output_dir = dir.clone();
+        }
+        if let Some(mode) = self.record_mode {
+            state.recording.mode = mode;
+        }
+        if let Some(format) = self.record_format {
+            state.recording.format = format;
+        }
+    }
+}
+
+/// A named profile from the config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub settings: Settings,
+}
+
+impl Profile {
+    /// Parse the profile `name` as written in the config file
+    pub fn from_config(name: &str, config: &ProfileConfig) -> Result<Self> {
+        let frequency = match config.frequency {
+            Some(mhz) if !(mhz > 0.0 && mhz < 100_000.0) => {
+                bail!("profile '{}': frequency {} MHz is out of range", name, mhz)
+            }
+            Some(mhz) => Some((mhz * 1_000_000.0).round() as u32),
+            None => None,
+        };
+        let settings = Settings {
+            frequency,
+            mode: parse(name, &config.mode)?,
+            sample_rate: config.sample_rate,
+            gain: config.gain,
+            squelch: config.squelch,
+            audio_port: config.audio_port,
+            stream_codec: parse(name, &config.stream_codec)?,
+            record_dir: config.record_dir.clone(),
+            record_mode: parse(name, &config.record_mode)?,
+            record_format: parse(name, &config.record_format)?,
+        };
+        Ok(Self {
+            name: name.to_string(),
+            settings,
+        })
+    }
+
+    /// Every profile in the config file, by name
+    pub fn all(config: &BTreeMap<String, ProfileConfig>) -> Result<Vec<Self>> {
+        config
+            .iter()
+            .map(|(name, profile)| Self::from_config(name, profile))
+            .collect()
+    }
+
+    /// The profile called `name`
+    pub fn find<'a>(profiles: &'a [Profile], name: &str) -> Result<&'a Profile> {
+        if let Some(profile) = profiles.iter().find(|p| p.name == name) {
+            return Ok(profile);
+        }
+        if profiles.is_empty() {
+            bail!("no profile '{}': the config file has no [profiles]", name);
+        }
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        bail!(
+            "no profile '{}' (the config file has {})",
+            name,
+            names.join(", ")
+        )
+    }
+
+    /// What the profile sets, e.g. "1090.0000 MHz ADS-B, 49.6 dB"
+    pub fn summary(&self) -> String {
+        let settings = &self.settings;
+        let mut parts = Vec::new();
+        let tuning = [
+            settings
+                .frequency
+                .map(|hz| format!("{:.4} MHz", hz as f64 / 1_000_000.0)),
+            settings.mode.map(|mode| mode.name().to_string()),
+        ];
+        let tuning: Vec<String> = tuning.into_iter().flatten().collect();
+        if !tuning.is_empty() {
+            parts.push(tuning.join(" "));
+        }
+        if let Some(rate) = settings.sample_rate {
+            parts.push(format!("{:.3} MS/s", rate as f64 / 1_000_000.0));
+        }
+        if let Some(gain) = settings.gain {
+            parts.push(format!("{:.1} dB", gain));
+        }
+        if let Some(level) = settings.squelch {
+            parts.push(format!("squelch {:.0} dBFS", level));
+        }
+        if let Some(mode) = settings.record_mode {
+            parts.push(format!("record {}", mode.name()));
+        }
+        if let Some(port) = settings.audio_port {
+            parts.push(format!("stream :{}", port));
+        }
+        parts.join(", ")
+    }
+}
+
+/// A setting of profile `name` written as text, parsed
+fn parse<T: FromStr<Err = String>>(name: &str, value: &Option<String>) -> Result<Option<T>> {
+    value
+        .as_deref()
+        .map(|value| value.trim().parse())
+        .transpose()
+        .map_err(|e| anyhow!("profile '{}': {}", name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AppConfig;
+
+    fn profiles() -> Vec<Profile> {
+        let config = AppConfig::parse(
+            r#"
+            [profiles.adsb]
+            frequency = 1090.0
+            mode = "adsb"
+            sample_rate = 2400000
+            gain = 49.6
+
+            [profiles.noaa]
+            frequency = 162.425
+            mode = "NFM"
+            squelch = -45.0
+            record_mode = "squelch"
+            record_dir = "/srv/noaa"
+            audio_port = 7355
+            stream_codec = "wav"
+            "#,
+        )
+        .unwrap();
+        Profile::all(&config.profiles).unwrap()
+    }
+
+    #[test]
+    fn test_from_config() {
+        let profiles = profiles();
+        let noaa = Profile::find(&profiles, "noaa").unwrap();
+        assert_eq!(noaa.settings.frequency, Some(162_425_000));
+        assert_eq!(noaa.settings.mode, Some(DemodMode::FmNarrow));
+        assert_eq!(noaa.settings.record_mode, Some(RecordingMode::Squelch));
+        assert_eq!(noaa.settings.stream_codec, Some(StreamCodec::Wav));
+        assert_eq!(noaa.settings.gain, None);
+        assert_eq!(
+            noaa.summary(),
+            "162.4250 MHz FM-NFM, squelch -45 dBFS, record Squelch (WAV), stream :7355"
+        );
+
+        let error = Profile::find(&profiles, "aprs").unwrap_err().to_string();
+        assert_eq!(error, "no profile 'aprs' (the config file has adsb, noaa)");
+
+        let bad = AppConfig::parse("[profiles.x]\nmode = \"fm stereo\"").unwrap();
+        let error = Profile::all(&bad.profiles).unwrap_err().to_string();
+        assert!(error.starts_with("profile 'x': unknown mode"), "{}", error);
+        let bad = AppConfig::parse("[profiles.x]\nfrequency = 162425000").unwrap();
+        assert!(Profile::all(&bad.profiles).is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        // command line < profile < config file < default, setting by setting,
+        // resolved as at startup and applied over the state the config file
+        // set up
+        let profiles = profiles();
+        let configured = || {
+            let mut state = AppState::default();
+            state.decoder.mode = DemodMode::Am;
+            state.decoder.squelch_level = Some(-30.0);
+            state.recording.output_dir = PathBuf::from("/var/recordings");
+            state.recording.mode = RecordingMode::Iq;
+            state
+        };
+        let cli = Settings {
+            gain: Some(40.2),
+            record_dir: Some(PathBuf::from("/tmp")),
+            ..Default::default()
+        };
+
+        let startup = cli.clone().with_profile(&profiles, Some("noaa")).unwrap();
+        assert_eq!(startup.frequency, Some(162_425_000));
+        assert_eq!(startup.gain, Some(40.2));
+        assert_eq!(startup.audio_port, Some(7355));
+        let mut state = configured();
+        startup.apply(&mut state);
+        assert_eq!(state.decoder.mode, DemodMode::FmNarrow);
+        assert_eq!(state.decoder.squelch_level, Some(-45.0));
+        assert_eq!(state.recording.mode, RecordingMode::Squelch);
+        assert_eq!(state.recording.output_dir, PathBuf::from("/tmp"));
+
+        // A squelch on the command line beats the profile's
+        let squelch = Settings {
+            squelch: Some(-20.0),
+            ..cli.clone()
+        };
+        let mut state = configured();
+        squelch.with_profile(&profiles, Some("noaa")).unwrap().apply(&mut state);
+        assert_eq!(state.decoder.squelch_level, Some(-20.0));
+
+        // Without a profile the config file shows through
+        let startup = cli.clone().with_profile(&profiles, None).unwrap();
+        assert_eq!(startup, cli);
+        let mut state = configured();
+        startup.apply(&mut state);
+        assert_eq!(state.decoder.mode, DemodMode::Am);
+        assert_eq!(state.decoder.squelch_level, Some(-30.0));
+        assert_eq!(state.recording.mode, RecordingMode::Iq);
+        assert_eq!(state.recording.output_dir, PathBuf::from("/tmp"));
+
+        assert!(cli.with_profile(&profiles, Some("aprs")).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let profiles = profiles();
+        let noaa = &Profile::find(&profiles, "noaa").unwrap().settings;
+        let mut state = AppState::default();
+        state.decoder.mode = DemodMode::Am;
+        state.decoder.squelch_level = None;
+        noaa.apply(&mut state);
+        assert_eq!(state.decoder.mode, DemodMode::FmNarrow);
+        assert_eq!(state.decoder.squelch_level, Some(-45.0));
+        assert_eq!(state.recording.mode, RecordingMode::Squelch);
+        assert_eq!(state.recording.output_dir, PathBuf::from("/srv/noaa"));
+
+        // What a profile leaves out stays as it was
+        let adsb = &Profile::find(&profiles, "adsb").unwrap().settings;
+        adsb.apply(&mut state);
+        assert_eq!(state.decoder.mode, DemodMode::Adsb);
+        assert_eq!(state.recording.mode, RecordingMode::Squelch);
+    }
+}
@@ -0,0 +1,198 @@
+//! Command run on each squelch segment once its WAV file is finished
+//!
+//! The program is run directly with its arguments, and the file's path as
+//! the last one; no shell is involved, so nothing in a path is ever
+//! interpreted. Commands run on a few worker threads, which caps how many
+//! run at once; segments finished while they are all busy wait their turn.
+//! What a command prints goes to the log.
+
+use crossbeam::channel::{unbounded, Sender};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+
+/// Commands run at once by default
+pub const DEFAULT_POST_RECORD_JOBS: usize = 2;
+
+/// Told why a command failed, for the status line
+pub type FailureReport = Arc<dyn Fn(String) + Send + Sync>;
+
+/// Hands finished recordings to the command's workers
+///
+/// The workers finish the queue and exit once every clone is dropped.
+#[derive(Clone)]
+pub struct PostRecordHook {
+    queue: Sender<PathBuf>,
+}
+
+impl PostRecordHook {
+    /// Run `command`, a program followed by its arguments, on each file,
+    /// at most `jobs` at a time; None if there is no program
+    pub fn new(command: &[String], jobs: usize, on_failure: FailureReport) -> Option<Self> {
+        let (program, args) = command.split_first()?;
+        if program.trim().is_empty() {
+            return None;
+        }
+        let (queue, files) = unbounded::<PathBuf>();
+        for _ in 0..jobs.max(1) {
+            let files = files.clone();
+            let program = program.clone();
+            let args = args.to_vec();
+            let on_failure = on_failure.clone();
+            thread::spawn(move || {
+                for path in files {
+                    if let Err(e) = run(&program, &args, &path) {
+                        log::warn!("{}", e);
+                        on_failure(e);
+                    }
+                }
+            });
+        }
+        Some(Self { queue })
+    }
+
+    /// Queue the command for `path`
+    pub fn finished(&self, path: &Path) {
+        // The workers only go once the hook does
+        let _ = self.queue.send(path.to_path_buf());
+    }
+}
+
+/// Run `program` with `args` and `path` and log its output; why it failed
+fn run(program: &str, args: &[String], path: &Path) -> Result<(), String> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    log::info!("Running {} on {}", program, path.display());
+    let output = Command::new(program)
+        .args(args)
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Post-record command {} failed to start: {}", program, e))?;
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        log::info!("{}: {}", program, line);
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        log::warn!("{}: {}", program, line);
+    }
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Post-record command {} failed on {} ({})",
+            program, name, output.status
+        ))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+    use parking_lot::Mutex;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, Instant};
+
+    /// A script that logs its start and end around a short sleep, failing on
+    /// files named "bad"; the file is its last argument
+    fn dummy_script(dir: &Path) -> PathBuf {
+        let script = dir.join("hook.sh");
+        let body = format!(
+            "#!/bin/sh\n\
+             for file; do :; done\n\
+             echo \"start $file\" >> {log}\n\
+             sleep 0.2\n\
+             echo \"end $*\" >> {log}\n\
+             case \"$file\" in *bad*) exit 3;; esac\n",
+            log = dir.join("calls.log").display()
+        );
+        std::fs::write(&script, body).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        script
+    }
+
+    /// Wait for `count` lines in the call log
+    fn calls(dir: &Path, count: usize) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let text = std::fs::read_to_string(dir.join("calls.log")).unwrap_or_default();
+            let lines: Vec<String> = text.lines().map(str::to_string).collect();
+            if lines.len() >= count || Instant::now() > deadline {
+                return lines;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_invocation() {
+        let dir = temp_dir("post_record_hook");
+        let script = dummy_script(&dir);
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let report = failures.clone();
+        let command = vec![script.display().to_string(), "--model".to_string()];
+        let hook = PostRecordHook::new(
+            &command,
+            1,
+            Arc::new(move |e: String| report.lock().push(e)),
+        )
+        .unwrap();
+
+        // The path is passed as it is, however it reads to a shell
+        let awkward = dir.join("a file; echo $HOME.wav");
+        hook.finished(&awkward);
+        hook.finished(&dir.join("bad.wav"));
+        let lines = calls(&dir, 4);
+        assert_eq!(lines[0], format!("start {}", awkward.display()));
+        assert_eq!(lines[1], format!("end --model {}", awkward.display()));
+
+        // The failure is reported once the command has finished
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while failures.lock().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        let failures = failures.lock();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].contains("bad.wav"), "{}", failures[0]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrency_cap() {
+        let dir = temp_dir("post_record_jobs");
+        let script = dummy_script(&dir);
+        let hook =
+            PostRecordHook::new(&[script.display().to_string()], 2, Arc::new(|_| {})).unwrap();
+        for i in 0..6 {
+            hook.finished(&dir.join(format!("{}.wav", i)));
+        }
+
+        // Every file is run, never more than two at once
+        let lines = calls(&dir, 12);
+        assert_eq!(lines.len(), 12);
+        let mut running = 0;
+        let mut most = 0;
+        for line in &lines {
+            if line.starts_with("start") {
+                running += 1;
+                most = most.max(running);
+            } else {
+                running -= 1;
+            }
+        }
+        assert_eq!(most, 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_no_command() {
+        assert!(PostRecordHook::new(&[], 2, Arc::new(|_| {})).is_none());
+        assert!(PostRecordHook::new(&[" ".to_string()], 2, Arc::new(|_| {})).is_none());
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Capture parameters written next to each raw IQ recording
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureMetadata {
+    /// Center frequency in Hz
+    pub center_frequency_hz: u32,
+    /// Sample rate in Hz
+    pub sample_rate_hz: u32,
+    /// Tuner gain in dB, or None for tuner auto gain
+    pub gain_db: Option<f32>,
+    /// Whether the RTL2832 digital AGC was on
+    pub rtl_agc: bool,
+    /// PPM correction applied by the tuner
+    pub ppm_error: i32,
+    /// Demodulation mode active when the capture started
+    pub mode: String,
+    /// Capture start time (UTC, RFC 3339)
+    pub start_time: String,
+    /// Sample format, e.g. "cu8" for interleaved unsigned 8-bit I/Q
+    pub sample_format: String,
+}
+
+impl CaptureMetadata {
+    /// Path of the sidecar file for a recording (`capture.iq` -> `capture.json`)
+    pub fn sidecar_path(recording: &Path) -> PathBuf {
+        recording.with_extension("json")
+    }
+
+    /// Serialize as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        // Serializing plain fields cannot fail
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Write the sidecar file for the given recording
+    pub fn write_sidecar(&self, recording: &Path) -> Result<PathBuf> {
+        let path = Self::sidecar_path(recording);
+        std::fs::write(&path, self.to_json())
+            .with_context(|| format!("Failed to write metadata {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_metadata_json() {
+        let meta = CaptureMetadata {
+            center_frequency_hz: 162_550_000,
+            sample_rate_hz: 2_048_000,
+            gain_db: Some(29.7),
+            rtl_agc: false,
+            ppm_error: 1,
+            mode: "NFM".to_string(),
+            start_time: "2024-06-01T14:25:03Z".to_string(),
+            sample_format: "cu8".to_string(),
+        };
+
+        let value: serde_json::Value = serde_json::from_str(&meta.to_json()).unwrap();
+        assert_eq!(value["center_frequency_hz"], 162_550_000);
+        assert_eq!(value["sample_rate_hz"], 2_048_000);
+        assert_eq!(value["sample_format"], "cu8");
+        assert_eq!(value["start_time"], "2024-06-01T14:25:03Z");
+
+        assert_eq!(
+            CaptureMetadata::sidecar_path(Path::new("dir/capture.iq")),
+            PathBuf::from("dir/capture.json")
+        );
+    }
+}
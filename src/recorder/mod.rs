@@ -0,0 +1,53 @@
+pub mod hook;
+pub mod metadata;
+pub mod naming;
+pub mod retune;
+pub mod session;
+pub mod sigmf;
+pub mod split;
+pub mod squelch;
+pub mod thread;
+pub mod wav;
+pub mod writer;
+
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// Re-export commonly used types
+pub use hook::PostRecordHook;
+pub use metadata::CaptureMetadata;
+pub use naming::{parse_recording_file_name, recording_path};
+pub use retune::{Retune, RetunePolicy};
+pub use session::{CaptureSettings, RecordingSession};
+pub use sigmf::SigMfMeta;
+pub use split::SplitPolicy;
+pub use squelch::{SegmenterConfig, SquelchSegmenter, WavSegmentSink};
+pub use thread::start_recorder_thread;
+pub use wav::WavWriter;
+pub use writer::{free_space, IqWriter, SampleFormat};
+
+/// Events sent to the recorder thread
+#[derive(Debug)]
+pub enum RecorderEvent {
+    /// Open a new recording at the given path
+    Start(PathBuf),
+    /// Start squelch-triggered audio recording into the output directory
+    StartSquelch,
+    /// Finalize the current recording
+    Stop,
+    /// Raw interleaved u8 IQ bytes as delivered by librtlsdr, the first
+    /// being sample `first` of the receiver's stream, captured at `captured`
+    Samples {
+        bytes: Vec<u8>,
+        first: u64,
+        captured: SystemTime,
+    },
+    /// The tuner was retuned to a new center frequency
+    Retune(Retune),
+    /// Demodulated audio with the squelch state it was produced under
+    Audio {
+        samples: Vec<f32>,
+        sample_rate: u32,
+        squelch_open: bool,
+    },
+}
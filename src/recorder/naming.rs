@@ -0,0 +1,152 @@
+use super::split::part_path;
+use crate::types::DemodMode;
+use chrono::{DateTime, TimeZone};
+use std::path::{Path, PathBuf};
+
+/// Build a descriptive recording file name
+///
+/// e.g. `20240601_142503_162.550MHz_NFM_2048k.iq`
+pub fn recording_file_name<Tz: TimeZone>(
+    start: &DateTime<Tz>,
+    frequency: u32,
+    mode: DemodMode,
+    sample_rate: u32,
+    extension: &str,
+) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    format!(
+        "{}_{:.3}MHz_{}_{}k.{}",
+        start.format("%Y%m%d_%H%M%S"),
+        frequency as f64 / 1_000_000.0,
+        mode.file_tag(),
+        sample_rate / 1000,
+        extension
+    )
+}
+
+/// Full path of a recording inside the output directory
+pub fn recording_path<Tz: TimeZone>(
+    dir: &Path,
+    start: &DateTime<Tz>,
+    frequency: u32,
+    mode: DemodMode,
+    sample_rate: u32,
+    extension: &str,
+) -> PathBuf
+where
+    Tz::Offset: std::fmt::Display,
+{
+    dir.join(recording_file_name(start, frequency, mode, sample_rate, extension))
+}
+
+/// `path`, or if a recording is already there, whole or in parts, the
+/// first of `<name>_2.<ext>`, `<name>_3.<ext>`, ... that is free
+///
+/// A retune back within the same second would otherwise name its file the
+/// same as the one before.
+pub fn unused_recording_path(path: &Path) -> PathBuf {
+    let taken = |path: &Path| path.exists() || part_path(path, 1).exists();
+    if !taken(path) {
+        return path.to_path_buf();
+    }
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (stem, extension) = file_name.rsplit_once('.').unwrap_or((&file_name, ""));
+    (2..)
+        .map(|n| path.with_file_name(format!("{}_{}.{}", stem, n, extension)))
+        .find(|path| !taken(path))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Center frequency and sample rate in Hz from a recording's file name, as
+/// [`recording_file_name`] writes it
+pub fn parse_recording_file_name(name: &str) -> Option<(u32, u32)> {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let mut frequency = None;
+    let mut sample_rate = None;
+    for field in stem.split('_') {
+        if let Some(mhz) = field.strip_suffix("MHz").and_then(|mhz| mhz.parse::<f64>().ok()) {
+            frequency = Some((mhz * 1_000_000.0).round() as u32);
+        } else if let Some(khz) = field.strip_suffix('k').and_then(|khz| khz.parse::<u32>().ok()) {
+            sample_rate = Some(khz * 1000);
+        }
+    }
+    frequency.zip(sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_recording_file_name() {
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 14, 25, 3).unwrap();
+        assert_eq!(
+            recording_file_name(&start, 162_550_000, DemodMode::FmNarrow, 2_048_000, "iq"),
+            "20240601_142503_162.550MHz_NFM_2048k.iq"
+        );
+        assert_eq!(
+            recording_file_name(&start, 1_090_000_000, DemodMode::Adsb, 2_400_000, "iq"),
+            "20240601_142503_1090.000MHz_ADSB_2400k.iq"
+        );
+    }
+
+    #[test]
+    fn test_parse_recording_file_name() {
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 14, 25, 3).unwrap();
+        let name = recording_file_name(&start, 162_550_000, DemodMode::FmNarrow, 2_048_000, "cs16");
+        assert_eq!(parse_recording_file_name(&name), Some((162_550_000, 2_048_000)));
+        assert_eq!(
+            parse_recording_file_name("20240601_142503_1090.000MHz_ADSB_2400k_part0002.iq"),
+            Some((1_090_000_000, 2_400_000))
+        );
+        assert_eq!(parse_recording_file_name("capture.iq"), None);
+    }
+
+    #[test]
+    fn test_unused_recording_path() {
+        let dir = std::env::temp_dir().join(format!("unused_path_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("20240601_142503_162.550MHz_NFM_2048k.sigmf-data");
+        assert_eq!(unused_recording_path(&path), path);
+
+        std::fs::write(&path, b"").unwrap();
+        let second = dir.join("20240601_142503_162.550MHz_NFM_2048k_2.sigmf-data");
+        assert_eq!(unused_recording_path(&path), second);
+        // One written in parts counts too
+        std::fs::write(part_path(&second, 1), b"").unwrap();
+        assert_eq!(
+            unused_recording_path(&path),
+            dir.join("20240601_142503_162.550MHz_NFM_2048k_3.sigmf-data")
+        );
+        assert_eq!(
+            parse_recording_file_name("20240601_142503_162.550MHz_NFM_2048k_2.iq"),
+            Some((162_550_000, 2_048_000))
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recording_path() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let path = recording_path(
+            Path::new("/tmp/captures"),
+            &start,
+            98_500_000,
+            DemodMode::FmWide,
+            1_024_000,
+            "iq",
+        );
+        assert_eq!(
+            path,
+            PathBuf::from("/tmp/captures/20240102_030405_98.500MHz_WFM_1024k.iq")
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! What a retune does to an IQ recording
+//!
+//! An IQ file is taken to be of one center frequency, so by default a
+//! retune closes the file and carries on in a new one named after the new
+//! frequency. The policy can instead refuse retunes while an IQ recording
+//! runs, or, for SigMF, note the retune as a new capture segment and carry
+//! on in the same file.
+//!
+//! The sample sink numbers every sample the receiver delivers, and a retune
+//! takes effect at the first sample delivered after the tuner took the new
+//! frequency. Buffers and retunes reach the recorder over one channel but
+//! from two threads, so a retune can arrive ahead of samples from before
+//! it; it waits in a [`RetuneQueue`] until the stream gets there, and the
+//! buffer it falls in is cut at that sample.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// What to do with an IQ recording when the receiver is retuned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetunePolicy {
+    /// Finish the file and continue in a new one
+    #[default]
+    Split,
+    /// Refuse to retune while recording
+    Block,
+    /// Add a capture segment to a SigMF recording and continue; raw
+    /// recordings are split
+    Annotate,
+}
+
+impl RetunePolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RetunePolicy::Split => "split",
+            RetunePolicy::Block => "block",
+            RetunePolicy::Annotate => "annotate",
+        }
+    }
+}
+
+impl std::str::FromStr for RetunePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "split" => Ok(RetunePolicy::Split),
+            "block" => Ok(RetunePolicy::Block),
+            "annotate" => Ok(RetunePolicy::Annotate),
+            _ => Err(format!(
+                "unknown retune policy '{}' (expected split, block or annotate)",
+                s
+            )),
+        }
+    }
+}
+
+/// A retune as the recorder sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retune {
+    /// Center frequency in Hz, as new recordings are named
+    pub frequency: u32,
+    /// Frequency the hardware is tuned to, which the samples are of
+    pub hardware: u32,
+    /// Index in the receiver's sample stream of the first sample at the new
+    /// frequency
+    pub sample_index: u64,
+    /// Made by a priority check, which comes back, so not worth a new file
+    pub priority: bool,
+}
+
+/// A stretch of a buffer all at one frequency
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// Retunes taking effect at the start of the run, in order
+    pub retunes: Vec<Retune>,
+    /// Sample range within the buffer
+    pub samples: Range<usize>,
+}
+
+/// Retunes waiting for the sample stream to reach them
+#[derive(Debug, Clone, Default)]
+pub struct RetuneQueue {
+    pending: VecDeque<Retune>,
+}
+
+impl RetuneQueue {
+    pub fn push(&mut self, retune: Retune) {
+        self.pending.push_back(retune);
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Cut a buffer of `len` samples, the first at stream index `first`,
+    /// into runs at one frequency each, taking the retunes that fall in it
+    ///
+    /// A retune from before the buffer takes effect at its start.
+    pub fn cut(&mut self, first: u64, len: usize) -> Vec<Run> {
+        let end = first + len as u64;
+        let mut runs = Vec::new();
+        let mut start = 0;
+        let mut retunes = Vec::new();
+        while let Some(retune) = self.pending.front().filter(|r| r.sample_index < end) {
+            let at = retune.sample_index.saturating_sub(first) as usize;
+            if at > start {
+                runs.push(Run {
+                    retunes: std::mem::take(&mut retunes),
+                    samples: start..at,
+                });
+                start = at;
+            }
+            retunes.push(*retune);
+            self.pending.pop_front();
+        }
+        runs.push(Run {
+            retunes,
+            samples: start..len,
+        });
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::{CaptureMetadata, CaptureSettings, RecordingSession, SampleFormat};
+    use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
+
+    fn retune(frequency: u32, sample_index: u64) -> Retune {
+        Retune {
+            frequency,
+            hardware: frequency,
+            sample_index,
+            priority: false,
+        }
+    }
+
+    #[test]
+    fn test_cut() {
+        let mut queue = RetuneQueue::default();
+        // Nothing pending: the buffer is one run
+        assert_eq!(
+            queue.cut(0, 100),
+            [Run {
+                retunes: vec![],
+                samples: 0..100
+            }]
+        );
+
+        // Mid-buffer, and one waiting for a later buffer
+        queue.push(retune(162_550_000, 130));
+        queue.push(retune(162_400_000, 250));
+        let runs = queue.cut(100, 100);
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].samples, 0..30);
+        assert!(runs[0].retunes.is_empty());
+        assert_eq!(runs[1].retunes, [retune(162_550_000, 130)]);
+        assert_eq!(runs[1].samples, 30..100);
+
+        // Late, it takes effect at the start; two at once go together
+        queue.push(retune(146_520_000, 260));
+        let runs = queue.cut(300, 50);
+        assert_eq!(
+            runs,
+            [Run {
+                retunes: vec![retune(162_400_000, 250), retune(146_520_000, 260)],
+                samples: 0..50
+            }]
+        );
+
+        // Exactly on a boundary it starts the buffer it falls in
+        queue.push(retune(144_390_000, 400));
+        assert_eq!(queue.cut(350, 50).len(), 1);
+        assert_eq!(queue.cut(400, 50)[0].retunes.len(), 1);
+
+        queue.push(retune(144_390_000, 1000));
+        queue.clear();
+        assert_eq!(queue.cut(1000, 10)[0].retunes, []);
+    }
+
+    fn settings(frequency: u32) -> CaptureSettings {
+        CaptureSettings {
+            frequency,
+            sample_rate: 2_048_000,
+            metadata: CaptureMetadata {
+                center_frequency_hz: frequency,
+                sample_rate_hz: 2_048_000,
+                gain_db: None,
+                rtl_agc: false,
+                ppm_error: 0,
+                mode: "NFM".to_string(),
+                start_time: "2024-06-01T00:00:00Z".to_string(),
+                sample_format: "cu8".to_string(),
+            },
+            sigmf: false,
+            format: SampleFormat::Cu8,
+            split: None,
+        }
+    }
+
+    /// Feed `buffers` of samples numbered from `first`, each sample's I and
+    /// Q byte being its index, splitting at each retune as the recorder does
+    fn record(
+        dir: &Path,
+        queue: &mut RetuneQueue,
+        mut first: u64,
+        buffers: &[usize],
+    ) -> Vec<PathBuf> {
+        let path = dir.join("0.iq");
+        let mut session = RecordingSession::open(&path, &settings(144_390_000)).unwrap();
+        let mut files = vec![path];
+        for &len in buffers {
+            let bytes: Vec<u8> = (first..first + len as u64)
+                .flat_map(|index| [index as u8; 2])
+                .collect();
+            for run in queue.cut(first, len) {
+                for retune in run.retunes {
+                    let path = dir.join(format!("{}.iq", files.len()));
+                    session.split(&path, retune.hardware).unwrap();
+                    files.push(path);
+                }
+                let samples = run.samples;
+                session
+                    .write(&bytes[samples.start * 2..samples.end * 2], SystemTime::now())
+                    .unwrap();
+            }
+            first += len as u64;
+        }
+        session.finish().unwrap();
+        files
+    }
+
+    #[test]
+    fn test_split_accounting() {
+        let dir = std::env::temp_dir().join(format!("retune_split_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Recording starts partway into the stream; a retune lands 37
+        // samples into the second buffer, and arrives before either
+        let mut queue = RetuneQueue::default();
+        queue.push(retune(162_550_000, 5_037));
+        let files = record(&dir, &mut queue, 4_900, &[100, 100, 100]);
+        assert_eq!(files.len(), 2);
+        let before = std::fs::read(&files[0]).unwrap();
+        let after = std::fs::read(&files[1]).unwrap();
+        assert_eq!(before.len() / 2, 137);
+        assert_eq!(after.len() / 2, 163);
+
+        // Not a sample lost or doubled: the last before is the one just
+        // before the retune, the first after is the one it took effect at
+        assert_eq!(before[before.len() - 1], (5_036u64 % 256) as u8);
+        assert_eq!(after[0], (5_037u64 % 256) as u8);
+
+        // The new file has the new frequency in its sidecar
+        let sidecar: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(CaptureMetadata::sidecar_path(&files[1])).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(sidecar["center_frequency_hz"], 162_550_000);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_retunes_in_one_buffer() {
+        let dir = std::env::temp_dir().join(format!("retune_buffer_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Two retunes in one buffer: three files, samples in each as cut
+        let mut queue = RetuneQueue::default();
+        queue.push(retune(162_550_000, 10));
+        queue.push(retune(162_400_000, 75));
+        let files = record(&dir, &mut queue, 0, &[100]);
+        let lengths: Vec<usize> = files
+            .iter()
+            .map(|file| std::fs::read(file).unwrap().len() / 2)
+            .collect();
+        assert_eq!(lengths, [10, 65, 25]);
+
+        // A retune before the first sample leaves no empty file behind
+        queue.push(retune(162_550_000, 0));
+        let files = record(&dir, &mut queue, 0, &[100]);
+        assert!(!files[0].exists());
+        assert!(!CaptureMetadata::sidecar_path(&files[0]).exists());
+        assert_eq!(std::fs::read(&files[1]).unwrap().len(), 200);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+use super::split::part_path;
+use super::{CaptureMetadata, IqWriter, SampleFormat, SigMfMeta, SplitPolicy};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Capture parameters needed to open a recording
+#[derive(Debug, Clone)]
+pub struct CaptureSettings {
+    pub frequency: u32,
+    pub sample_rate: u32,
+    /// Metadata for the raw-format sidecar
+    pub metadata: CaptureMetadata,
+    /// Write SigMF instead of a raw file plus JSON sidecar
+    pub sigmf: bool,
+    /// Sample format asked for; SigMF may store it differently
+    pub format: SampleFormat,
+    /// Rotate to a new numbered part file per this policy
+    pub split: Option<SplitPolicy>,
+}
+
+impl CaptureSettings {
+    /// Format the sample file is written in
+    pub fn file_format(&self) -> SampleFormat {
+        if self.sigmf {
+            self.format.for_sigmf()
+        } else {
+            self.format
+        }
+    }
+}
+
+/// An open recording: the sample file plus whatever metadata goes with it
+pub struct RecordingSession {
+    writer: IqWriter,
+    /// SigMF metadata, rewritten on every retune and at close
+    sigmf: Option<SigMfMeta>,
+    /// Path the recording was requested at (parts are derived from it)
+    base_path: PathBuf,
+    /// Settings for the next part, kept current across retunes
+    settings: CaptureSettings,
+    /// Samples per part when splitting
+    samples_per_part: Option<u64>,
+    /// Current part number (1-based; 0 when not splitting)
+    part: u32,
+    /// Samples and bytes in parts already closed
+    earlier_samples: u64,
+    earlier_bytes: u64,
+}
+
+impl RecordingSession {
+    /// Open a recording at `path`, writing initial metadata
+    pub fn open(path: &Path, settings: &CaptureSettings) -> Result<Self> {
+        let format = settings.file_format();
+        let samples_per_part = settings
+            .split
+            .map(|split| split.samples_per_part(settings.sample_rate, format.bytes_per_sample()));
+        let (part, part_file) = match samples_per_part {
+            Some(_) => (1, part_path(path, 1)),
+            None => (0, path.to_path_buf()),
+        };
+
+        let (writer, sigmf) = open_file(&part_file, settings, 0)?;
+        Ok(Self {
+            writer,
+            sigmf,
+            base_path: path.to_path_buf(),
+            settings: settings.clone(),
+            samples_per_part,
+            part,
+            earlier_samples: 0,
+            earlier_bytes: 0,
+        })
+    }
+
+    /// Append raw u8 IQ bytes, the first captured at `captured`
+    ///
+    /// When splitting, a full part is closed before the chunk is written, so
+    /// parts always break between chunks. Each file is dated by its first
+    /// sample.
+    pub fn write(&mut self, raw: &[u8], captured: SystemTime) -> Result<()> {
+        if self
+            .samples_per_part
+            .is_some_and(|limit| self.writer.samples_written() >= limit)
+        {
+            self.next_part()?;
+        }
+        if self.writer.samples_written() == 0 {
+            self.started(captured);
+        }
+        self.writer.write(raw)
+    }
+
+    /// Date the current file by when its first sample was captured
+    fn started(&mut self, at: SystemTime) {
+        let path = self.writer.path();
+        let written = match self.sigmf.as_mut() {
+            Some(meta) => {
+                meta.started(at);
+                meta.write(&SigMfMeta::meta_path(path))
+            }
+            None => {
+                self.settings.metadata.start_time = chrono::DateTime::<chrono::Utc>::from(at)
+                    .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+                self.settings.metadata.write_sidecar(path).map(|_| ())
+            }
+        };
+        if let Err(e) = written {
+            log::warn!("{:#}", e);
+        }
+    }
+
+    /// Close the current part and continue in the next one
+    fn next_part(&mut self) -> Result<()> {
+        self.part += 1;
+        self.settings.metadata.start_time =
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+        let path = part_path(&self.base_path, self.part);
+        let offset = self.samples_written();
+        let (writer, sigmf) = open_file(&path, &self.settings, offset)?;
+        let previous = std::mem::replace(&mut self.writer, writer);
+        let previous_meta = std::mem::replace(&mut self.sigmf, sigmf);
+
+        self.earlier_samples = offset;
+        self.earlier_bytes += finish_file(previous, previous_meta)?;
+        log::info!("Recording continues in {}", path.display());
+        Ok(())
+    }
+
+    /// Finish this recording and continue in a new one at `path` tuned to
+    /// `frequency`; the bytes the finished one came to
+    ///
+    /// If the new file can't be created this one carries on. A recording
+    /// that never got a sample is removed rather than kept empty.
+    pub fn split(&mut self, path: &Path, frequency: u32) -> Result<u64> {
+        let mut settings = self.settings.clone();
+        settings.frequency = frequency;
+        settings.metadata.center_frequency_hz = frequency;
+        settings.metadata.start_time =
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let next = RecordingSession::open(path, &settings)?;
+
+        let finished = std::mem::replace(self, next);
+        let empty = finished.samples_written() == 0;
+        let file = finished.path().to_path_buf();
+        let meta = match finished.sigmf {
+            Some(_) => SigMfMeta::meta_path(&file),
+            None => CaptureMetadata::sidecar_path(&file),
+        };
+        let bytes = match finished.finish() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::error!("Failed to finalize recording {}: {:#}", file.display(), e);
+                0
+            }
+        };
+        if empty {
+            for leftover in [file, meta] {
+                if let Err(e) = std::fs::remove_file(&leftover) {
+                    log::warn!("Failed to remove {}: {}", leftover.display(), e);
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Whether the recording is SigMF, which can note a retune and go on
+    pub fn is_sigmf(&self) -> bool {
+        self.sigmf.is_some()
+    }
+
+    /// Note a retune at the current sample position, captured at `at`
+    pub fn retune(&mut self, frequency: u32, at: SystemTime) {
+        self.settings.frequency = frequency;
+        self.settings.metadata.center_frequency_hz = frequency;
+
+        if let Some(meta) = self.sigmf.as_mut() {
+            meta.retune(self.writer.samples_written(), frequency, at);
+            if let Err(e) = meta.write(&SigMfMeta::meta_path(self.writer.path())) {
+                log::warn!("{:#}", e);
+            }
+        }
+    }
+
+    /// Sample rate of the recording in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.settings.sample_rate
+    }
+
+    /// Complex samples written so far, across all parts
+    pub fn samples_written(&self) -> u64 {
+        self.earlier_samples + self.writer.samples_written()
+    }
+
+    /// Format the samples are written in
+    pub fn format(&self) -> SampleFormat {
+        self.writer.format()
+    }
+
+    /// Path of the sample file currently being written
+    pub fn path(&self) -> &Path {
+        self.writer.path()
+    }
+
+    /// Current part number, if the recording is being split
+    pub fn part(&self) -> Option<u32> {
+        (self.part > 0).then_some(self.part)
+    }
+
+    /// Flush, fsync, and write final metadata; returns bytes written
+    pub fn finish(self) -> Result<u64> {
+        Ok(self.earlier_bytes + finish_file(self.writer, self.sigmf)?)
+    }
+}
+
+/// Create one sample file with its initial metadata
+fn open_file(
+    path: &Path,
+    settings: &CaptureSettings,
+    global_offset: u64,
+) -> Result<(IqWriter, Option<SigMfMeta>)> {
+    if settings.sigmf {
+        let writer = IqWriter::create(path, settings.file_format())?;
+        let mut meta = SigMfMeta::new(
+            writer.format().sigmf_datatype(),
+            settings.sample_rate,
+            settings.frequency,
+            None,
+        );
+        if global_offset > 0 {
+            meta = meta.continued(global_offset);
+        }
+        meta.write(&SigMfMeta::meta_path(path))?;
+        Ok((writer, Some(meta)))
+    } else {
+        let writer = IqWriter::create(path, settings.file_format())?;
+        if let Err(e) = settings.metadata.write_sidecar(path) {
+            log::warn!("{:#}", e);
+        }
+        Ok((writer, None))
+    }
+}
+
+/// Finish one sample file and its metadata; returns bytes written
+fn finish_file(writer: IqWriter, sigmf: Option<SigMfMeta>) -> Result<u64> {
+    let path: PathBuf = writer.path().to_path_buf();
+    let bytes = writer.finish()?;
+    if let Some(meta) = sigmf {
+        meta.write(&SigMfMeta::meta_path(&path))?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(sigmf: bool) -> CaptureSettings {
+        CaptureSettings {
+            frequency: 144_390_000,
+            sample_rate: 2_048_000,
+            metadata: CaptureMetadata {
+                center_frequency_hz: 144_390_000,
+                sample_rate_hz: 2_048_000,
+                gain_db: None,
+                rtl_agc: false,
+                ppm_error: 0,
+                mode: "NFM".to_string(),
+                start_time: "2024-06-01T00:00:00Z".to_string(),
+                sample_format: "cu8".to_string(),
+            },
+            sigmf,
+            format: SampleFormat::Cu8,
+            split: None,
+        }
+    }
+
+    #[test]
+    fn test_sigmf_session_writes_segments() {
+        let dir = std::env::temp_dir().join(format!("sigmf_session_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.sigmf-data");
+
+        let start = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_717_200_000);
+        let mut session = RecordingSession::open(&path, &settings(true)).unwrap();
+        session.write(&[128; 200], start).unwrap();
+        let retuned = start + std::time::Duration::from_millis(250);
+        session.retune(162_550_000, retuned);
+        session.write(&[0; 100], retuned).unwrap();
+        assert_eq!(session.finish().unwrap(), 300);
+
+        let meta: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("capture.sigmf-meta")).unwrap())
+                .unwrap();
+        assert_eq!(meta["captures"][1]["core:sample_start"], 100);
+        assert_eq!(meta["captures"][1]["core:frequency"], 162_550_000.0);
+        // Dated by the samples, not by when the file was opened
+        assert_eq!(meta["captures"][0]["core:datetime"], "2024-06-01T00:00:00.000Z");
+        assert_eq!(meta["captures"][1]["core:datetime"], "2024-06-01T00:00:00.250Z");
+        assert_eq!(std::fs::read(&path).unwrap()[0], 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wide_format_session() {
+        let dir = std::env::temp_dir().join(format!("cf32_session_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.sigmf-data");
+
+        let mut settings = settings(true);
+        settings.format = SampleFormat::Cf32;
+        let mut session = RecordingSession::open(&path, &settings).unwrap();
+        session.write(&[128; 200], SystemTime::now()).unwrap();
+        assert_eq!(session.samples_written(), 100);
+        assert_eq!(session.finish().unwrap(), 800);
+
+        let meta: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("capture.sigmf-meta")).unwrap())
+                .unwrap();
+        assert_eq!(meta["global"]["core:datatype"], "cf32_le");
+        assert_eq!(std::fs::read(&path).unwrap().len(), 800);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_parts_are_contiguous() {
+        let dir = std::env::temp_dir().join(format!("split_session_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.sigmf-data");
+
+        // 100 samples (200 bytes) per part, written in 60-sample chunks
+        let mut settings = settings(true);
+        settings.split = Some(SplitPolicy::Size(200));
+        let mut session = RecordingSession::open(&path, &settings).unwrap();
+        assert_eq!(session.part(), Some(1));
+
+        let mut written = 0u8;
+        for _ in 0..6 {
+            let chunk: Vec<u8> = (0..120)
+                .map(|_| {
+                    written = written.wrapping_add(1);
+                    written
+                })
+                .collect();
+            session.write(&chunk, SystemTime::now()).unwrap();
+        }
+        assert_eq!(session.samples_written(), 360);
+        assert_eq!(session.part(), Some(3));
+        assert_eq!(session.finish().unwrap(), 720);
+
+        // Parts break between chunks: 120 + 120 + 120 samples
+        let mut joined = Vec::new();
+        for part in 1..=3 {
+            let data = std::fs::read(part_path(&path, part)).unwrap();
+            assert_eq!(data.len(), 240);
+            joined.extend(data);
+        }
+        assert!(!part_path(&path, 4).exists());
+
+        // The byte sequence continues across boundaries without gaps
+        let expected: Vec<u8> = (1..=720u32).map(|i| (i as u8).wrapping_sub(128)).collect();
+        assert_eq!(joined, expected);
+
+        // Later parts record where they sit in the whole capture
+        let meta: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(SigMfMeta::meta_path(&part_path(&path, 3))).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(meta["captures"][0]["core:global_index"], 240);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! SigMF metadata (<https://sigmf.org>) for IQ recordings
+//!
+//! A SigMF recording is a `.sigmf-data` file of raw samples plus a
+//! `.sigmf-meta` JSON file describing them. Retunes during a capture start a
+//! new capture segment at the sample index where they took effect.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// SigMF specification version written to `core:version`
+pub const SIGMF_VERSION: &str = "1.0.0";
+
+/// Top-level SigMF metadata document
+#[derive(Debug, Clone, Serialize)]
+pub struct SigMfMeta {
+    pub global: SigMfGlobal,
+    pub captures: Vec<SigMfCapture>,
+    pub annotations: Vec<SigMfAnnotation>,
+    /// Samples recorded in earlier parts of a split recording
+    #[serde(skip)]
+    pub global_offset: u64,
+}
+
+/// `global` object
+#[derive(Debug, Clone, Serialize)]
+pub struct SigMfGlobal {
+    #[serde(rename = "core:datatype")]
+    pub datatype: String,
+    #[serde(rename = "core:sample_rate")]
+    pub sample_rate: f64,
+    #[serde(rename = "core:version")]
+    pub version: String,
+    #[serde(rename = "core:recorder")]
+    pub recorder: String,
+    #[serde(rename = "core:hw", skip_serializing_if = "Option::is_none")]
+    pub hw: Option<String>,
+}
+
+/// Capture segment
+#[derive(Debug, Clone, Serialize)]
+pub struct SigMfCapture {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(rename = "core:frequency")]
+    pub frequency: f64,
+    #[serde(rename = "core:datetime")]
+    pub datetime: String,
+    /// Index of `sample_start` within the whole split recording
+    #[serde(rename = "core:global_index", skip_serializing_if = "Option::is_none")]
+    pub global_index: Option<u64>,
+}
+
+/// Annotation
+#[derive(Debug, Clone, Serialize)]
+pub struct SigMfAnnotation {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(rename = "core:comment")]
+    pub comment: String,
+}
+
+impl SigMfMeta {
+    /// Start a new metadata document with an initial capture segment
+    pub fn new(datatype: &str, sample_rate: u32, frequency: u32, hw: Option<String>) -> Self {
+        Self {
+            global: SigMfGlobal {
+                datatype: datatype.to_string(),
+                sample_rate: sample_rate as f64,
+                version: SIGMF_VERSION.to_string(),
+                recorder: format!("rtl-sdr-tui {}", env!("CARGO_PKG_VERSION")),
+                hw,
+            },
+            captures: vec![SigMfCapture {
+                sample_start: 0,
+                frequency: frequency as f64,
+                datetime: rfc3339(SystemTime::now()),
+                global_index: None,
+            }],
+            annotations: Vec::new(),
+            global_offset: 0,
+        }
+    }
+
+    /// Mark this document as a later part of a split recording whose first
+    /// sample is `global_offset` samples into the whole capture
+    pub fn continued(mut self, global_offset: u64) -> Self {
+        self.global_offset = global_offset;
+        for capture in &mut self.captures {
+            capture.global_index = Some(global_offset + capture.sample_start);
+        }
+        self
+    }
+
+    /// Date the current capture segment by when its first sample was
+    /// captured, rather than when the file was opened
+    pub fn started(&mut self, at: SystemTime) {
+        if let Some(last) = self.captures.last_mut() {
+            last.datetime = rfc3339(at);
+        }
+    }
+
+    /// Record a retune taking effect at `sample_index`, captured at `at`
+    pub fn retune(&mut self, sample_index: u64, frequency: u32, at: SystemTime) {
+        // A retune before any samples arrived just replaces the current segment
+        if let Some(last) = self.captures.last_mut() {
+            if last.sample_start == sample_index {
+                last.frequency = frequency as f64;
+                return;
+            }
+        }
+
+        self.captures.push(SigMfCapture {
+            sample_start: sample_index,
+            frequency: frequency as f64,
+            datetime: rfc3339(at),
+            global_index: (self.global_offset > 0).then_some(self.global_offset + sample_index),
+        });
+        self.annotations.push(SigMfAnnotation {
+            sample_start: sample_index,
+            comment: format!("Retuned to {} Hz", frequency),
+        });
+    }
+
+    /// Serialize as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Write the metadata file
+    pub fn write(&self, meta_path: &Path) -> Result<()> {
+        std::fs::write(meta_path, self.to_json())
+            .with_context(|| format!("Failed to write SigMF metadata {}", meta_path.display()))
+    }
+
+    /// Metadata path for a `.sigmf-data` file
+    pub fn meta_path(data_path: &Path) -> PathBuf {
+        data_path.with_extension("sigmf-meta")
+    }
+}
+
+/// A time in UTC, in the ISO-8601 form SigMF expects
+fn rfc3339(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time)
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    /// Check the structural rules of the SigMF core schema
+    fn assert_valid_sigmf(meta: &Value) {
+        let global = meta["global"].as_object().expect("global object");
+        let datatype = global["core:datatype"].as_str().unwrap();
+        assert!(
+            ["ci8", "cu8", "ci16_le", "cf32_le"].contains(&datatype),
+            "unexpected datatype {}",
+            datatype
+        );
+        assert!(global["core:sample_rate"].as_f64().unwrap() > 0.0);
+        assert_eq!(global["core:version"], SIGMF_VERSION);
+
+        let captures = meta["captures"].as_array().expect("captures array");
+        assert!(!captures.is_empty());
+        assert_eq!(captures[0]["core:sample_start"], 0);
+        let mut prev = 0;
+        for capture in captures {
+            let start = capture["core:sample_start"].as_u64().unwrap();
+            assert!(start >= prev, "capture segments must be in order");
+            prev = start;
+            assert!(capture["core:frequency"].is_number());
+            assert!(chrono::DateTime::parse_from_rfc3339(
+                capture["core:datetime"].as_str().unwrap()
+            )
+            .is_ok());
+        }
+
+        for annotation in meta["annotations"].as_array().expect("annotations array") {
+            assert!(annotation["core:sample_start"].is_u64());
+        }
+    }
+
+    #[test]
+    fn test_sigmf_meta_schema() {
+        let meta = SigMfMeta::new("ci8", 2_048_000, 162_550_000, None);
+        let value: Value = serde_json::from_str(&meta.to_json()).unwrap();
+        assert_valid_sigmf(&value);
+        assert_eq!(value["global"]["core:datatype"], "ci8");
+        assert_eq!(value["captures"][0]["core:frequency"], 162_550_000.0);
+    }
+
+    #[test]
+    fn test_sigmf_retune_segments() {
+        let mut meta = SigMfMeta::new("ci8", 2_048_000, 144_390_000, None);
+        let start = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_717_200_000);
+        meta.started(start);
+        meta.retune(0, 144_800_000, start);
+        assert_eq!(meta.captures.len(), 1);
+        assert_eq!(meta.captures[0].frequency, 144_800_000.0);
+        assert_eq!(meta.captures[0].datetime, "2024-06-01T00:00:00.000Z");
+
+        meta.retune(16_384, 162_550_000, start + std::time::Duration::from_millis(8));
+        meta.retune(32_768, 162_400_000, start + std::time::Duration::from_millis(16));
+
+        let value: Value = serde_json::from_str(&meta.to_json()).unwrap();
+        assert_valid_sigmf(&value);
+        assert_eq!(value["captures"].as_array().unwrap().len(), 3);
+        assert_eq!(value["captures"][1]["core:sample_start"], 16_384);
+        assert_eq!(value["captures"][2]["core:datetime"], "2024-06-01T00:00:00.016Z");
+        assert_eq!(value["annotations"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_meta_path() {
+        assert_eq!(
+            SigMfMeta::meta_path(Path::new("cap.sigmf-data")),
+            PathBuf::from("cap.sigmf-meta")
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Splitting long recordings into numbered parts
+//!
+//! With a split policy set, a recording is written as `<name>_part0001.<ext>`,
+//! `<name>_part0002.<ext>`, ... Parts are rotated between write chunks so no
+//! sample (or half of an I/Q pair) is ever lost at a boundary.
+
+use crate::util::units::parse_duration;
+use std::path::{Path, PathBuf};
+
+/// When to start a new part
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitPolicy {
+    /// Every N seconds of recorded signal
+    Duration(u64),
+    /// Every N bytes written
+    Size(u64),
+}
+
+impl SplitPolicy {
+    /// Complex samples per part for the given capture format
+    pub fn samples_per_part(&self, sample_rate: u32, bytes_per_sample: u64) -> u64 {
+        let samples = match *self {
+            SplitPolicy::Duration(secs) => secs * sample_rate as u64,
+            SplitPolicy::Size(bytes) => bytes / bytes_per_sample,
+        };
+        samples.max(1)
+    }
+}
+
+impl std::str::FromStr for SplitPolicy {
+    type Err = String;
+
+    /// Parse "30m", "1h" (duration) or "500M", "2G", "1.5GB" (size);
+    /// a lowercase "m" on its own means minutes
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spec = s.trim();
+        let upper = spec.to_ascii_uppercase();
+        let number = upper.trim_end_matches('B');
+
+        let size_unit = match number.chars().last() {
+            Some('K') => Some(1_000u64),
+            Some('M') if upper.ends_with('B') || !spec.ends_with('m') => Some(1_000_000),
+            Some('G') => Some(1_000_000_000),
+            Some('T') => Some(1_000_000_000_000),
+            _ => None,
+        };
+
+        if let Some(unit) = size_unit {
+            let value: f64 = number[..number.len() - 1]
+                .parse()
+                .map_err(|_| format!("invalid split size '{}'", s))?;
+            if value <= 0.0 {
+                return Err(format!("invalid split size '{}'", s));
+            }
+            return Ok(SplitPolicy::Size((value * unit as f64) as u64));
+        }
+
+        parse_duration(spec)
+            .map(|d| SplitPolicy::Duration(d.as_secs()))
+            .map_err(|_| format!("invalid split '{}' (expected e.g. 30m or 2G)", s))
+    }
+}
+
+/// Path of part `part` (1-based) of a recording at `path`
+///
+/// e.g. `capture.sigmf-data` -> `capture_part0003.sigmf-data`
+pub fn part_path(path: &Path, part: u32) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match file_name.rsplit_once('.') {
+        Some((stem, ext)) => format!("{}_part{:04}.{}", stem, part, ext),
+        None => format!("{}_part{:04}", file_name, part),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_split_policy() {
+        assert_eq!("30m".parse(), Ok(SplitPolicy::Duration(1800)));
+        assert_eq!("1h".parse(), Ok(SplitPolicy::Duration(3600)));
+        assert_eq!("500M".parse(), Ok(SplitPolicy::Size(500_000_000)));
+        assert_eq!("500MB".parse(), Ok(SplitPolicy::Size(500_000_000)));
+        assert_eq!("2G".parse(), Ok(SplitPolicy::Size(2_000_000_000)));
+        assert_eq!("1.5gb".parse(), Ok(SplitPolicy::Size(1_500_000_000)));
+        assert!("lots".parse::<SplitPolicy>().is_err());
+        assert!("0G".parse::<SplitPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_samples_per_part() {
+        assert_eq!(SplitPolicy::Duration(60).samples_per_part(2_048_000, 2), 122_880_000);
+        assert_eq!(SplitPolicy::Size(1_000_000).samples_per_part(2_048_000, 2), 500_000);
+    }
+
+    #[test]
+    fn test_part_path() {
+        assert_eq!(
+            part_path(Path::new("out/20240601_142503_162.550MHz_NFM_2048k.iq"), 1),
+            PathBuf::from("out/20240601_142503_162.550MHz_NFM_2048k_part0001.iq")
+        );
+        assert_eq!(
+            part_path(Path::new("capture.sigmf-data"), 12),
+            PathBuf::from("capture_part0012.sigmf-data")
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Squelch-triggered (VOX-style) audio recording
+//!
+//! Each transmission goes into its own WAV file: a segment opens when the
+//! squelch opens (including a short pre-roll of audio from before it opened)
+//! and closes after the squelch has stayed shut for the hang time. Segments
+//! whose open time is shorter than the minimum are discarded as blips.
+
+use super::{PostRecordHook, WavWriter};
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// Timing parameters for segmenting, in samples
+#[derive(Debug, Clone, Copy)]
+pub struct SegmenterConfig {
+    /// Audio kept from before the squelch opened
+    pub pre_roll: usize,
+    /// How long the squelch must stay closed before the segment ends
+    pub hang: usize,
+    /// Minimum squelch-open duration for a segment to be kept
+    pub min_length: usize,
+}
+
+impl SegmenterConfig {
+    /// Build a config from durations in seconds
+    pub fn from_secs(sample_rate: u32, pre_roll: f32, hang: f32, min_length: f32) -> Self {
+        let to_samples = |secs: f32| (secs.max(0.0) * sample_rate as f32) as usize;
+        Self {
+            pre_roll: to_samples(pre_roll),
+            hang: to_samples(hang),
+            min_length: to_samples(min_length),
+        }
+    }
+}
+
+/// Receives segment boundaries and audio from the segmenter
+pub trait SegmentSink {
+    /// A new segment begins with the given pre-roll audio
+    fn open_segment(&mut self, pre_roll: &[f32]) -> Result<()>;
+    /// Audio belonging to the current segment
+    fn write(&mut self, samples: &[f32]) -> Result<()>;
+    /// The current segment ended; `keep` is false for too-short blips
+    fn close_segment(&mut self, keep: bool) -> Result<()>;
+}
+
+/// Splits a continuous audio stream into squelch-open segments
+pub struct SquelchSegmenter {
+    config: SegmenterConfig,
+    /// Ring of the most recent audio while idle
+    pre_roll: VecDeque<f32>,
+    /// Whether a segment is currently open
+    active: bool,
+    /// Samples of hang time remaining after the squelch closed
+    hang_left: usize,
+    /// Samples received while the squelch was open in this segment
+    open_length: usize,
+}
+
+impl SquelchSegmenter {
+    /// Create a new segmenter
+    pub fn new(config: SegmenterConfig) -> Self {
+        Self {
+            config,
+            pre_roll: VecDeque::with_capacity(config.pre_roll),
+            active: false,
+            hang_left: 0,
+            open_length: 0,
+        }
+    }
+
+    /// Feed a buffer of audio along with the squelch state for that buffer
+    pub fn process<S: SegmentSink>(
+        &mut self,
+        audio: &[f32],
+        squelch_open: bool,
+        sink: &mut S,
+    ) -> Result<()> {
+        if !self.active {
+            if !squelch_open {
+                self.push_pre_roll(audio);
+                return Ok(());
+            }
+
+            let pre_roll: Vec<f32> = self.pre_roll.drain(..).collect();
+            sink.open_segment(&pre_roll)?;
+            self.active = true;
+            self.open_length = 0;
+        }
+
+        sink.write(audio)?;
+
+        if squelch_open {
+            self.hang_left = self.config.hang;
+            self.open_length += audio.len();
+        } else {
+            self.hang_left = self.hang_left.saturating_sub(audio.len());
+            if self.hang_left == 0 {
+                self.active = false;
+                sink.close_segment(self.open_length >= self.config.min_length)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Close any open segment (e.g. when recording is stopped)
+    pub fn flush<S: SegmentSink>(&mut self, sink: &mut S) -> Result<()> {
+        if self.active {
+            self.active = false;
+            sink.close_segment(self.open_length >= self.config.min_length)?;
+        }
+        self.pre_roll.clear();
+        Ok(())
+    }
+
+    fn push_pre_roll(&mut self, audio: &[f32]) {
+        if self.config.pre_roll == 0 {
+            return;
+        }
+        let keep = audio.len().min(self.config.pre_roll);
+        let overflow = (self.pre_roll.len() + keep).saturating_sub(self.config.pre_roll);
+        self.pre_roll.drain(..overflow);
+        self.pre_roll.extend(&audio[audio.len() - keep..]);
+    }
+}
+
+/// Segment sink writing each kept segment to its own WAV file
+pub struct WavSegmentSink {
+    sample_rate: u32,
+    /// Produces the path for each new segment
+    next_path: Box<dyn FnMut() -> PathBuf + Send>,
+    current: Option<WavWriter>,
+    /// Number of segments kept this session
+    pub files_written: usize,
+    /// Path of the most recently kept segment
+    pub last_file: Option<PathBuf>,
+    /// Run on each kept segment once it is finished
+    hook: Option<PostRecordHook>,
+}
+
+impl WavSegmentSink {
+    /// Create a sink; `next_path` is called once per segment
+    pub fn new(sample_rate: u32, next_path: Box<dyn FnMut() -> PathBuf + Send>) -> Self {
+        Self {
+            sample_rate,
+            next_path,
+            current: None,
+            files_written: 0,
+            last_file: None,
+            hook: None,
+        }
+    }
+
+    /// Run `hook` on each segment kept
+    pub fn with_hook(mut self, hook: Option<PostRecordHook>) -> Self {
+        self.hook = hook;
+        self
+    }
+
+    /// Path of the segment currently being written
+    pub fn current_file(&self) -> Option<&std::path::Path> {
+        self.current.as_ref().map(|w| w.path())
+    }
+}
+
+impl SegmentSink for WavSegmentSink {
+    fn open_segment(&mut self, pre_roll: &[f32]) -> Result<()> {
+        let mut writer = WavWriter::create(&(self.next_path)(), self.sample_rate)?;
+        writer.write(pre_roll)?;
+        self.current = Some(writer);
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        match self.current.as_mut() {
+            Some(writer) => writer.write(samples),
+            None => Ok(()),
+        }
+    }
+
+    fn close_segment(&mut self, keep: bool) -> Result<()> {
+        let Some(writer) = self.current.take() else {
+            return Ok(());
+        };
+        let path = writer.finish()?;
+        if keep {
+            log::info!("Squelch segment saved: {}", path.display());
+            self.files_written += 1;
+            if let Some(hook) = &self.hook {
+                hook.finished(&path);
+            }
+            self.last_file = Some(path);
+        } else {
+            log::debug!("Discarding short squelch segment {}", path.display());
+            std::fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// In-memory sink recording segment boundaries
+    #[derive(Default)]
+    struct MemorySink {
+        segments: Vec<(Vec<f32>, bool)>,
+        current: Option<Vec<f32>>,
+    }
+
+    impl SegmentSink for MemorySink {
+        fn open_segment(&mut self, pre_roll: &[f32]) -> Result<()> {
+            self.current = Some(pre_roll.to_vec());
+            Ok(())
+        }
+        fn write(&mut self, samples: &[f32]) -> Result<()> {
+            self.current.as_mut().unwrap().extend_from_slice(samples);
+            Ok(())
+        }
+        fn close_segment(&mut self, keep: bool) -> Result<()> {
+            self.segments.push((self.current.take().unwrap(), keep));
+            Ok(())
+        }
+    }
+
+    fn config() -> SegmenterConfig {
+        SegmenterConfig {
+            pre_roll: 4,
+            hang: 6,
+            min_length: 5,
+        }
+    }
+
+    #[test]
+    fn test_segment_boundaries_and_pre_roll() {
+        let mut seg = SquelchSegmenter::new(config());
+        let mut sink = MemorySink::default();
+
+        // Idle audio fills the pre-roll; only the last 4 samples are kept
+        seg.process(&[1.0, 2.0, 3.0], false, &mut sink).unwrap();
+        seg.process(&[4.0, 5.0, 6.0], false, &mut sink).unwrap();
+        assert!(sink.current.is_none());
+
+        // Squelch opens
+        seg.process(&[10.0, 11.0, 12.0], true, &mut sink).unwrap();
+        seg.process(&[13.0, 14.0, 15.0], true, &mut sink).unwrap();
+        assert!(sink.current.is_some());
+
+        // Closed for 3 samples: still inside the hang time
+        seg.process(&[0.1, 0.1, 0.1], false, &mut sink).unwrap();
+        assert!(sink.segments.is_empty());
+
+        // Hang time expires
+        seg.process(&[0.2, 0.2, 0.2], false, &mut sink).unwrap();
+        assert!(sink.current.is_none());
+
+        assert_eq!(sink.segments.len(), 1);
+        let (audio, keep) = &sink.segments[0];
+        assert!(keep);
+        assert_eq!(&audio[..4], &[3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(&audio[4..10], &[10.0, 11.0, 12.0, 13.0, 14.0, 15.0]);
+        assert_eq!(audio.len(), 4 + 6 + 6);
+    }
+
+    #[test]
+    fn test_short_blip_discarded() {
+        let mut seg = SquelchSegmenter::new(config());
+        let mut sink = MemorySink::default();
+
+        seg.process(&[1.0, 1.0], true, &mut sink).unwrap();
+        seg.process(&[0.0; 6], false, &mut sink).unwrap();
+
+        assert_eq!(sink.segments.len(), 1);
+        assert!(!sink.segments[0].1);
+    }
+
+    #[test]
+    fn test_reopen_within_hang_extends_segment() {
+        let mut seg = SquelchSegmenter::new(config());
+        let mut sink = MemorySink::default();
+
+        seg.process(&[1.0; 3], true, &mut sink).unwrap();
+        seg.process(&[0.0; 3], false, &mut sink).unwrap();
+        seg.process(&[1.0; 3], true, &mut sink).unwrap();
+        seg.process(&[0.0; 6], false, &mut sink).unwrap();
+
+        assert_eq!(sink.segments.len(), 1);
+        assert!(sink.segments[0].1);
+        assert_eq!(sink.segments[0].0.len(), 15);
+    }
+
+    #[test]
+    fn test_wav_sink_discards_blips() {
+        let dir = std::env::temp_dir().join(format!("vox_sink_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut counter = 0;
+        let name_dir = dir.clone();
+        let mut sink = WavSegmentSink::new(
+            8000,
+            Box::new(move || {
+                counter += 1;
+                name_dir.join(format!("seg{}.wav", counter))
+            }),
+        );
+        let mut seg = SquelchSegmenter::new(config());
+
+        seg.process(&[0.5; 8], true, &mut sink).unwrap();
+        seg.process(&[0.0; 6], false, &mut sink).unwrap();
+        seg.process(&[0.5; 2], true, &mut sink).unwrap();
+        seg.flush(&mut sink).unwrap();
+
+        assert_eq!(sink.files_written, 1);
+        assert!(dir.join("seg1.wav").exists());
+        assert!(!dir.join("seg2.wav").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
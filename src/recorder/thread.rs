@@ -0,0 +1,384 @@
+use super::naming::unused_recording_path;
+use super::retune::RetuneQueue;
+use super::{
+    free_space, recording_path, CaptureMetadata, CaptureSettings, RecorderEvent,
+    PostRecordHook, RecordingSession, Retune, RetunePolicy, SegmenterConfig, SquelchSegmenter,
+    WavSegmentSink,
+};
+use crate::sdr::buffer::span;
+use crate::state::SharedState;
+use crossbeam::channel::Receiver;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often to re-check free disk space while recording
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Squelch-triggered recording session: segmenter plus the WAV files it feeds
+struct SquelchRecording {
+    segmenter: SquelchSegmenter,
+    sink: WavSegmentSink,
+}
+
+impl SquelchRecording {
+    /// The file being written, or else the last one, once there is one
+    fn file(&self) -> Option<PathBuf> {
+        self.sink
+            .current_file()
+            .map(Path::to_path_buf)
+            .or_else(|| self.sink.last_file.clone())
+    }
+}
+
+/// Start the recorder thread
+///
+/// Owns the output file; IQ samples arrive from the SDR thread as raw bytes so
+/// writing never touches the DSP path. Squelch recordings take demodulated
+/// audio from the DSP thread instead, and `hook` is run on each file kept.
+pub fn start_recorder_thread(
+    state: SharedState,
+    events_rx: Receiver<RecorderEvent>,
+    hook: Option<PostRecordHook>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        log::info!("Recorder thread started");
+
+        let mut writer: Option<RecordingSession> = None;
+        // Retunes the IQ stream hasn't reached yet
+        let mut retunes = RetuneQueue::default();
+        // Active squelch session; the segmenter is built on the first audio
+        // buffer, once the audio sample rate is known
+        let mut squelch_active = false;
+        let mut squelch: Option<SquelchRecording> = None;
+        let mut last_disk_check = Instant::now();
+
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                log::info!("Recorder thread shutting down");
+                break;
+            }
+
+            match events_rx.recv_timeout(std::time::Duration::from_millis(100)) {
+                Ok(RecorderEvent::Start(path)) => {
+                    // Starting a new recording implicitly finalizes the previous one
+                    finish_recording(&state, writer.take());
+                    finish_squelch(&state, &mut squelch_active, squelch.take());
+                    retunes.clear();
+
+                    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                        if let Err(e) = std::fs::create_dir_all(dir) {
+                            log::error!("Failed to create {}: {}", dir.display(), e);
+                        }
+                    }
+
+                    match RecordingSession::open(&path, &capture_settings(&state)) {
+                        Ok(w) => {
+                            log::info!("Recording to {}", path.display());
+                            let free = free_space(&path);
+                            let mut state_guard = state.write();
+                            state_guard.recording.start(w.path().to_path_buf());
+                            state_guard.recording.part = w.part();
+                            state_guard.recording.bytes_per_sample = w.format().bytes_per_sample();
+                            state_guard.recording.free_space = free;
+                            writer = Some(w);
+                        }
+                        Err(e) => {
+                            log::error!("{:#}", e);
+                            state.write().ui.status_message = format!("Recording failed: {}", e);
+                        }
+                    }
+                }
+                Ok(RecorderEvent::StartSquelch) => {
+                    finish_recording(&state, writer.take());
+                    finish_squelch(&state, &mut squelch_active, squelch.take());
+
+                    let dir = state.read().recording.output_dir.clone();
+                    if let Err(e) = std::fs::create_dir_all(&dir) {
+                        log::error!("Failed to create {}: {}", dir.display(), e);
+                    }
+                    log::info!("Squelch recording into {}", dir.display());
+                    state.write().recording.start(dir);
+                    squelch_active = true;
+                }
+                Ok(RecorderEvent::Stop) => {
+                    finish_recording(&state, writer.take());
+                    finish_squelch(&state, &mut squelch_active, squelch.take());
+                }
+                Ok(RecorderEvent::Audio {
+                    samples,
+                    sample_rate,
+                    squelch_open,
+                }) => {
+                    if !squelch_active {
+                        continue;
+                    }
+                    let session = squelch
+                        .get_or_insert_with(|| new_squelch_recording(&state, sample_rate, &hook));
+                    if let Err(e) =
+                        session
+                            .segmenter
+                            .process(&samples, squelch_open, &mut session.sink)
+                    {
+                        log::error!("Squelch recording write failed: {:#}", e);
+                        finish_squelch(&state, &mut squelch_active, squelch.take());
+                        state.write().ui.status_message = format!("Recording failed: {}", e);
+                    } else {
+                        let mut state_guard = state.write();
+                        state_guard.recording.segments = session.sink.files_written;
+                        state_guard.recording.file_path = session.file();
+                    }
+                }
+                Ok(RecorderEvent::Retune(retune)) => {
+                    if writer.is_some() {
+                        retunes.push(retune);
+                    }
+                }
+                Ok(RecorderEvent::Samples {
+                    bytes,
+                    first,
+                    captured,
+                }) => {
+                    if let Some(rate) = writer.as_ref().map(RecordingSession::sample_rate) {
+                        let mut result = Ok(());
+                        for run in retunes.cut(first, bytes.len() / 2) {
+                            // When the run's first sample was captured
+                            let at = captured + span(run.samples.start as u64, rate);
+                            for retune in run.retunes {
+                                follow_retune(&state, &mut writer, retune, at);
+                            }
+                            let samples = &bytes[run.samples.start * 2..run.samples.end * 2];
+                            if let Some(w) = writer.as_mut() {
+                                result = w.write(samples, at);
+                            }
+                            if result.is_err() {
+                                break;
+                            }
+                        }
+                        if let Err(e) = result {
+                            log::error!("Recording write failed: {}", e);
+                            finish_recording(&state, writer.take());
+                        } else if let Some(w) = writer.as_ref() {
+                            let mut state_guard = state.write();
+                            state_guard.recording.samples_recorded = w.samples_written();
+                            if state_guard.recording.part != w.part() {
+                                state_guard.recording.part = w.part();
+                                state_guard.recording.file_path = Some(w.path().to_path_buf());
+                            }
+                        }
+                    }
+                }
+                Err(crossbeam::channel::RecvTimeoutError::Timeout) => {
+                    continue;
+                }
+                Err(crossbeam::channel::RecvTimeoutError::Disconnected) => {
+                    log::info!("Recorder channel disconnected");
+                    break;
+                }
+            }
+
+            // IQ or squelch, a recording stops before the disk fills
+            if last_disk_check.elapsed() >= DISK_CHECK_INTERVAL {
+                let file = writer
+                    .as_ref()
+                    .map(|w| w.path().to_path_buf())
+                    .or_else(|| squelch.as_ref().and_then(SquelchRecording::file));
+                if let Some(file) = file {
+                    last_disk_check = Instant::now();
+                    if !check_disk_space(&state, &file) {
+                        finish_recording(&state, writer.take());
+                        finish_squelch(&state, &mut squelch_active, squelch.take());
+                        state.write().ui.status_message =
+                            "Recording stopped: disk almost full".to_string();
+                    }
+                }
+            }
+        }
+
+        // Never leave a half-written file behind on exit
+        finish_recording(&state, writer.take());
+        finish_squelch(&state, &mut squelch_active, squelch.take());
+
+        log::info!("Recorder thread stopped");
+    })
+}
+
+/// Carry the IQ recording over a retune, its first sample captured at `at`:
+/// a new capture segment if it is SigMF and the policy says to annotate, or
+/// else a new file named after the new frequency; it stops if that can't be
+/// created. A priority check's trip away and back stays in the same file,
+/// noted as capture segments in SigMF.
+fn follow_retune(
+    state: &SharedState,
+    writer: &mut Option<RecordingSession>,
+    retune: Retune,
+    at: SystemTime,
+) {
+    let Some(session) = writer.as_mut() else {
+        return;
+    };
+    let policy = state.read().recording.retune;
+    if retune.priority {
+        log::debug!("Priority check at {} Hz: recording continues", retune.hardware);
+        if session.is_sigmf() {
+            session.retune(retune.hardware, at);
+        }
+        return;
+    }
+    if policy == RetunePolicy::Annotate && session.is_sigmf() {
+        session.retune(retune.hardware, at);
+        return;
+    }
+
+    let path = {
+        let state = state.read();
+        let extension = session
+            .path()
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        unused_recording_path(&recording_path(
+            &state.recording.output_dir,
+            &chrono::DateTime::<chrono::Local>::from(at),
+            retune.frequency,
+            state.decoder.mode,
+            state.sdr.sample_rate,
+            &extension,
+        ))
+    };
+    let finished = session.path().to_path_buf();
+    match session.split(&path, retune.hardware) {
+        Ok(bytes) => {
+            log::info!(
+                "Retuned to {} Hz: {} finished ({} bytes), recording continues in {}",
+                retune.hardware,
+                finished.display(),
+                bytes,
+                session.path().display()
+            );
+            let mut state_guard = state.write();
+            state_guard.recording.file_path = Some(session.path().to_path_buf());
+            state_guard.recording.part = session.part();
+            state_guard.recording.samples_recorded = 0;
+            state_guard.ui.status_message = format!(
+                "Retuned: recording continues in {}",
+                session.path().file_name().unwrap_or_default().to_string_lossy()
+            );
+        }
+        Err(e) => {
+            log::error!("{:#}", e);
+            finish_recording(state, writer.take());
+            state.write().ui.status_message = format!("Recording stopped on retune: {}", e);
+        }
+    }
+}
+
+/// Snapshot the capture parameters for a new recording
+fn capture_settings(state: &SharedState) -> CaptureSettings {
+    let state = state.read();
+    // The samples are as the hardware captured them, offset tuning or not
+    let metadata = CaptureMetadata {
+        center_frequency_hz: state.sdr.hardware_frequency(),
+        sample_rate_hz: state.sdr.sample_rate,
+        gain_db: (!state.sdr.tuner_agc && state.sdr.tuner_gain >= 0)
+            .then(|| state.sdr.tuner_gain as f32 / 10.0),
+        rtl_agc: state.sdr.rtl_agc,
+        ppm_error: state.sdr.ppm_error,
+        mode: state.decoder.mode.file_tag().to_string(),
+        start_time: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        sample_format: state.recording.format.sigmf_datatype().to_string(),
+    };
+
+    CaptureSettings {
+        frequency: state.sdr.hardware_frequency(),
+        sample_rate: state.sdr.sample_rate,
+        metadata,
+        sigmf: state.recording.sigmf,
+        format: state.recording.format,
+        split: state.recording.split,
+    }
+}
+
+/// Set up segmenting for a squelch recording at the given audio rate
+fn new_squelch_recording(
+    state: &SharedState,
+    sample_rate: u32,
+    hook: &Option<PostRecordHook>,
+) -> SquelchRecording {
+    let vox = state.read().recording.vox;
+    let config = SegmenterConfig::from_secs(sample_rate, vox.pre_roll, vox.hang, vox.min_length);
+
+    // Each transmission is named after the frequency and mode it was heard on
+    let name_state = state.clone();
+    let next_path = Box::new(move || {
+        let state = name_state.read();
+        recording_path(
+            &state.recording.output_dir,
+            &chrono::Local::now(),
+            state.sdr.frequency,
+            state.decoder.mode,
+            sample_rate,
+            "wav",
+        )
+    });
+
+    SquelchRecording {
+        segmenter: SquelchSegmenter::new(config),
+        sink: WavSegmentSink::new(sample_rate, next_path).with_hook(hook.clone()),
+    }
+}
+
+/// Close any open segment and end the squelch recording session
+fn finish_squelch(state: &SharedState, active: &mut bool, squelch: Option<SquelchRecording>) {
+    if !std::mem::take(active) {
+        return;
+    }
+
+    if let Some(mut squelch) = squelch {
+        if let Err(e) = squelch.segmenter.flush(&mut squelch.sink) {
+            log::error!("Failed to finalize squelch segment: {:#}", e);
+        }
+        log::info!(
+            "Squelch recording finished: {} file(s)",
+            squelch.sink.files_written
+        );
+    }
+    state.write().recording.stop();
+}
+
+/// Refresh the free-space estimate where `file` is being written; returns
+/// false once the hard floor is reached
+fn check_disk_space(state: &SharedState, file: &Path) -> bool {
+    let free = free_space(file);
+    let mut state_guard = state.write();
+    state_guard.recording.free_space = free;
+
+    match free {
+        Some(free) if free < state_guard.recording.min_free_space => {
+            log::warn!(
+                "Only {} bytes free for {}, stopping recording",
+                free,
+                file.display()
+            );
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Flush, fsync, and close the active recording (if any)
+fn finish_recording(state: &SharedState, writer: Option<RecordingSession>) {
+    let Some(writer) = writer else {
+        return;
+    };
+
+    let path = writer.path().to_path_buf();
+    match writer.finish() {
+        Ok(bytes) => log::info!("Recording finished: {} ({} bytes)", path.display(), bytes),
+        Err(e) => log::error!("Failed to finalize recording {}: {}", path.display(), e),
+    }
+    state.write().recording.stop();
+}
@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Size of the canonical PCM WAV header
+const HEADER_LEN: u32 = 44;
+
+/// Minimal 16-bit mono PCM WAV writer
+///
+/// The RIFF sizes are written as placeholders and patched in `finish`.
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    samples_written: u32,
+}
+
+impl WavWriter {
+    /// Create a new WAV file
+    pub fn create(path: &Path, sample_rate: u32) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        write_header(&mut writer, sample_rate, 0)?;
+
+        Ok(Self {
+            writer,
+            path: path.to_path_buf(),
+            samples_written: 0,
+        })
+    }
+
+    /// Append samples in [-1.0, 1.0]
+    pub fn write(&mut self, samples: &[f32]) -> Result<()> {
+        for &sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * 32767.0) as i16;
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        self.samples_written += samples.len() as u32;
+        Ok(())
+    }
+
+    /// Path of the file being written
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Patch the header sizes, flush, and fsync
+    pub fn finish(mut self) -> Result<PathBuf> {
+        let data_len = self.samples_written * 2;
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.seek(SeekFrom::Start(4))?;
+        file.write_all(&(HEADER_LEN - 8 + data_len).to_le_bytes())?;
+        file.seek(SeekFrom::Start(40))?;
+        file.write_all(&data_len.to_le_bytes())?;
+        file.sync_all()?;
+        Ok(self.path)
+    }
+}
+
+/// Header for a WAV stream of unknown length, its sizes at their largest
+pub fn stream_header(sample_rate: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN as usize);
+    write_header(&mut header, sample_rate, u32::MAX - (HEADER_LEN - 8))
+        .expect("writing to a Vec can't fail");
+    header
+}
+
+/// Write a canonical 16-bit mono PCM header
+fn write_header<W: Write>(w: &mut W, sample_rate: u32, data_len: u32) -> Result<()> {
+    w.write_all(b"RIFF")?;
+    w.write_all(&(HEADER_LEN - 8 + data_len).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+    w.write_all(&1u16.to_le_bytes())?; // PCM
+    w.write_all(&1u16.to_le_bytes())?; // mono
+    w.write_all(&sample_rate.to_le_bytes())?;
+    w.write_all(&(sample_rate * 2).to_le_bytes())?; // byte rate
+    w.write_all(&2u16.to_le_bytes())?; // block align
+    w.write_all(&16u16.to_le_bytes())?; // bits per sample
+    w.write_all(b"data")?;
+    w.write_all(&data_len.to_le_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav_header_sizes() {
+        let path = std::env::temp_dir().join(format!("wav_writer_{}.wav", std::process::id()));
+
+        let mut wav = WavWriter::create(&path, 48_000).unwrap();
+        wav.write(&[0.0, 0.5, -0.5, 1.0]).unwrap();
+        wav.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 48_000);
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 8);
+        assert_eq!(i16::from_le_bytes([bytes[50], bytes[51]]), 32767);
+
+        std::fs::remove_file(&path).unwrap();
+
+        let header = stream_header(48_000);
+        // As the file's, but for the sizes
+        assert_eq!(header[8..40], bytes[8..40]);
+        assert_eq!(&header[4..8], &u32::MAX.to_le_bytes());
+        assert_eq!(&header[40..44], &(u32::MAX - 36).to_le_bytes());
+    }
+}
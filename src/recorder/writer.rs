@@ -0,0 +1,367 @@
+use anyhow::{Context, Result};
+use num_complex::Complex;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Write buffer size per byte of a complex u8 sample (1 MiB keeps syscalls
+/// well below the callback rate)
+const WRITE_BUFFER_SIZE: usize = 1 << 20;
+
+/// On-disk IQ sample format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Interleaved unsigned 8-bit I/Q (native rtl_sdr format)
+    #[default]
+    Cu8,
+    /// Interleaved signed 8-bit I/Q (u8 shifted by 128)
+    Ci8,
+    /// Interleaved signed 16-bit little-endian I/Q, full scale ±32768
+    Ci16,
+    /// Interleaved 32-bit little-endian float I/Q, full scale ±1.0
+    Cf32,
+}
+
+impl SampleFormat {
+    /// Bytes per complex sample
+    pub fn bytes_per_sample(&self) -> u64 {
+        match self {
+            SampleFormat::Cu8 | SampleFormat::Ci8 => 2,
+            SampleFormat::Ci16 => 4,
+            SampleFormat::Cf32 => 8,
+        }
+    }
+
+    /// Name as given to `--record-format`
+    pub fn name(&self) -> &'static str {
+        match self {
+            SampleFormat::Cu8 => "u8",
+            SampleFormat::Ci8 => "i8",
+            SampleFormat::Ci16 => "i16",
+            SampleFormat::Cf32 => "f32",
+        }
+    }
+
+    /// The next format `--record-format` offers
+    pub fn next(&self) -> Self {
+        match self {
+            SampleFormat::Cu8 | SampleFormat::Ci8 => SampleFormat::Ci16,
+            SampleFormat::Ci16 => SampleFormat::Cf32,
+            SampleFormat::Cf32 => SampleFormat::Cu8,
+        }
+    }
+
+    /// The format a SigMF recording is written in; 8-bit captures are
+    /// signed there
+    pub fn for_sigmf(&self) -> Self {
+        match self {
+            SampleFormat::Cu8 => SampleFormat::Ci8,
+            other => *other,
+        }
+    }
+
+    /// Extension of a raw recording in this format: `.iq` for the 8-bit
+    /// formats, as rtl_sdr writes, and `.cs16` or `.cf32` otherwise
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SampleFormat::Cu8 | SampleFormat::Ci8 => "iq",
+            SampleFormat::Ci16 => "cs16",
+            SampleFormat::Cf32 => "cf32",
+        }
+    }
+
+    /// SigMF `core:datatype` string
+    pub fn sigmf_datatype(&self) -> &'static str {
+        match self {
+            SampleFormat::Cu8 => "cu8",
+            SampleFormat::Ci8 => "ci8",
+            SampleFormat::Ci16 => "ci16_le",
+            SampleFormat::Cf32 => "cf32_le",
+        }
+    }
+
+    /// The format a SigMF `core:datatype` (or sidecar `sample_format`)
+    /// names, if it is one of these
+    pub fn from_sigmf_datatype(datatype: &str) -> Option<Self> {
+        match datatype {
+            "cu8" => Some(SampleFormat::Cu8),
+            "ci8" => Some(SampleFormat::Ci8),
+            "ci16_le" => Some(SampleFormat::Ci16),
+            "cf32_le" => Some(SampleFormat::Cf32),
+            _ => None,
+        }
+    }
+
+    /// Samples in this format back to complex samples, as the receive path
+    /// makes them; a trailing partial sample is ignored
+    pub fn decode(&self, bytes: &[u8]) -> Vec<Complex<f32>> {
+        let sample_len = self.bytes_per_sample() as usize;
+        bytes
+            .chunks_exact(sample_len)
+            .map(|sample| {
+                let (i, q) = sample.split_at(sample_len / 2);
+                Complex::new(self.component(i), self.component(q))
+            })
+            .collect()
+    }
+
+    /// One I or Q value, scaled to ±1.0
+    fn component(&self, bytes: &[u8]) -> f32 {
+        match self {
+            SampleFormat::Cu8 => (bytes[0] as f32 - 127.5) / 128.0,
+            SampleFormat::Ci8 => (bytes[0] as i8 as f32 + 0.5) / 128.0,
+            SampleFormat::Ci16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            SampleFormat::Cf32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+
+    /// Convert raw u8 IQ bytes from librtlsdr into this format
+    ///
+    /// The wider formats take out the u8 offset of 127.5, so they have no DC
+    /// offset of their own, and scale as the receive path does: f32 is
+    /// `(b - 127.5) / 128` and i16 the same times 32768.
+    pub fn convert(&self, raw: &[u8], out: &mut Vec<u8>) {
+        out.clear();
+        match self {
+            SampleFormat::Cu8 => out.extend_from_slice(raw),
+            SampleFormat::Ci8 => out.extend(raw.iter().map(|&b| b.wrapping_sub(128))),
+            SampleFormat::Ci16 => {
+                out.reserve(raw.len() * 2);
+                for &b in raw {
+                    let value = (2 * b as i16 - 255) * 128;
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            SampleFormat::Cf32 => {
+                out.reserve(raw.len() * 4);
+                for &b in raw {
+                    let value = (b as f32 - 127.5) / 128.0;
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for SampleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "u8" | "cu8" => Ok(SampleFormat::Cu8),
+            "i16" | "cs16" | "ci16" => Ok(SampleFormat::Ci16),
+            "f32" | "cf32" => Ok(SampleFormat::Cf32),
+            _ => Err(format!("unknown sample format '{}' (expected u8, i16 or f32)", s)),
+        }
+    }
+}
+
+/// IQ file writer converting raw u8 samples to the chosen format
+pub struct IqWriter {
+    /// Buffered output file
+    writer: BufWriter<File>,
+    /// Path of the file being written
+    path: PathBuf,
+    /// Output sample format
+    format: SampleFormat,
+    /// Reusable conversion buffer
+    scratch: Vec<u8>,
+    /// Total complex samples written so far
+    samples_written: u64,
+}
+
+impl IqWriter {
+    /// Create a new IQ file, truncating any existing file at `path`
+    pub fn create(path: &Path, format: SampleFormat) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording {}", path.display()))?;
+
+        // Wider formats get a bigger buffer, so it holds as many samples
+        let capacity = WRITE_BUFFER_SIZE * format.bytes_per_sample() as usize / 2;
+        Ok(Self {
+            writer: BufWriter::with_capacity(capacity, file),
+            path: path.to_path_buf(),
+            format,
+            scratch: Vec::new(),
+            samples_written: 0,
+        })
+    }
+
+    /// Append raw u8 IQ bytes as delivered by librtlsdr
+    pub fn write(&mut self, raw: &[u8]) -> Result<()> {
+        // Never split an I/Q pair
+        let raw = &raw[..raw.len() & !1];
+        self.format.convert(raw, &mut self.scratch);
+        self.writer.write_all(&self.scratch)?;
+        self.samples_written += raw.len() as u64 / 2;
+        Ok(())
+    }
+
+    /// Number of complex samples written
+    pub fn samples_written(&self) -> u64 {
+        self.samples_written
+    }
+
+    /// Output sample format
+    pub fn format(&self) -> SampleFormat {
+        self.format
+    }
+
+    /// Path of the file being written
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flush buffered data and fsync so the file survives an abrupt exit
+    pub fn finish(mut self) -> Result<u64> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        Ok(self.samples_written * self.format.bytes_per_sample())
+    }
+}
+
+/// Free space available to unprivileged users on the filesystem holding `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    // statvfs needs an existing path; a bare file name lives in the CWD
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let c_path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+
+    unsafe {
+        let mut stats: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(c_path.as_ptr(), &mut stats) != 0 {
+            return None;
+        }
+        Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+    }
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iq_writer_counts_and_flushes() {
+        let path = std::env::temp_dir().join(format!("iq_writer_{}.iq", std::process::id()));
+
+        let mut writer = IqWriter::create(&path, SampleFormat::Cu8).unwrap();
+        writer.write(&[127, 128, 0, 255]).unwrap();
+        writer.write(&[10, 20]).unwrap();
+        assert_eq!(writer.samples_written(), 3);
+
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes, 6);
+        assert_eq!(std::fs::read(&path).unwrap(), vec![127, 128, 0, 255, 10, 20]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ci8_conversion() {
+        let mut out = Vec::new();
+        SampleFormat::Ci8.convert(&[0, 128, 255, 127], &mut out);
+        assert_eq!(out, vec![0x80, 0, 0x7f, 0xff]);
+        assert_eq!(
+            out.iter().map(|&b| b as i8).collect::<Vec<_>>(),
+            vec![-128, 0, 127, -1]
+        );
+    }
+
+    #[test]
+    fn test_wide_conversions() {
+        // Full scale, the two codes either side of the middle, and a step
+        let raw = [0, 255, 127, 128, 200];
+        let mut out = Vec::new();
+        SampleFormat::Ci16.convert(&raw, &mut out);
+        let i16s: Vec<i16> = out
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(i16s, vec![-32640, 32640, -128, 128, 18560]);
+
+        SampleFormat::Cf32.convert(&raw, &mut out);
+        let f32s: Vec<f32> = out
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(
+            f32s,
+            vec![-127.5 / 128.0, 127.5 / 128.0, -0.5 / 128.0, 0.5 / 128.0, 72.5 / 128.0]
+        );
+
+        // Both match what the receive path makes of the same bytes, so
+        // levels survive the round trip
+        let complex = crate::sdr::samples_u8_to_complex(&raw[..4]);
+        for (index, sample) in complex.iter().enumerate() {
+            assert_eq!(sample.re, f32s[index * 2]);
+            assert_eq!(sample.im, f32s[index * 2 + 1]);
+            assert_eq!(sample.re * 32768.0, i16s[index * 2] as f32);
+        }
+
+        // No DC offset: a signal symmetric about 127.5 averages to zero
+        let sweep: Vec<u8> = (0..=255).collect();
+        SampleFormat::Ci16.convert(&sweep, &mut out);
+        let sum: i64 = out
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as i64)
+            .sum();
+        assert_eq!(sum, 0);
+        SampleFormat::Cf32.convert(&sweep, &mut out);
+        let sum: f32 = out
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .sum();
+        assert_eq!(sum, 0.0);
+    }
+
+    #[test]
+    fn test_wide_writer_counts_samples() {
+        let path = std::env::temp_dir().join(format!("iq_writer_{}.cf32", std::process::id()));
+        let mut writer = IqWriter::create(&path, SampleFormat::Cf32).unwrap();
+        writer.write(&[0, 255, 128]).unwrap();
+        assert_eq!(writer.samples_written(), 1);
+        assert_eq!(writer.finish().unwrap(), 8);
+        assert_eq!(std::fs::read(&path).unwrap().len(), 8);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!("I16".parse(), Ok(SampleFormat::Ci16));
+        assert_eq!("cf32".parse(), Ok(SampleFormat::Cf32));
+        assert!("f64".parse::<SampleFormat>().is_err());
+        assert_eq!(SampleFormat::Cu8.for_sigmf().sigmf_datatype(), "ci8");
+        assert_eq!(SampleFormat::Cf32.for_sigmf().sigmf_datatype(), "cf32_le");
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        // Every format reads back what the receive path made of the bytes
+        let raw: Vec<u8> = (0..=255).collect();
+        let expected = crate::sdr::samples_u8_to_complex(&raw);
+        let mut out = Vec::new();
+        let formats =
+            [SampleFormat::Cu8, SampleFormat::Ci8, SampleFormat::Ci16, SampleFormat::Cf32];
+        for format in formats {
+            format.convert(&raw, &mut out);
+            assert_eq!(format.decode(&out), expected, "{:?}", format);
+            assert_eq!(SampleFormat::from_sigmf_datatype(format.sigmf_datatype()), Some(format));
+        }
+        // Half a sample at the end is left for the next read
+        assert_eq!(SampleFormat::Ci16.decode(&[0, 0, 0]).len(), 0);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_free_space() {
+        assert!(free_space(&std::env::temp_dir().join("missing.iq")).is_some());
+        assert!(free_space(Path::new("relative.iq")).is_some());
+    }
+}
@@ -0,0 +1,460 @@
+//! Band scan survey
+//!
+//! Sweeps a frequency range in fixed steps and writes the peak power seen on
+//! each step to a CSV file for plotting. Rather than retuning for every step,
+//! each retune ("hop") covers as many steps as fit in the middle of the
+//! captured band, and each step's power is read from the FFT bins around it.
+//! The bins nearest DC are left out while there are others, since the
+//! RTL-SDR's DC spike would otherwise show as a signal at every hop center.
+//!
+//! With `--scan-repeat` the sweep runs until quit, adding a timestamped
+//! column per sweep.
+
+use crate::state::SharedState;
+use crate::types::Command;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use crossbeam::channel::Sender;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Dwell per hop when the spec gives none
+const DEFAULT_DWELL: Duration = Duration::from_millis(100);
+/// Share of the captured band used per hop; the edges roll off
+const USABLE_BANDWIDTH: f64 = 0.75;
+/// Bins either side of DC left out of a step's peak
+const DC_BINS: usize = 2;
+/// Time for the tuner to settle after a retune before frames count
+const SETTLE: Duration = Duration::from_millis(30);
+/// Longest wait for a retune to show up in state
+const RETUNE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A range to sweep, parsed from `<start_mhz>:<end_mhz>:<step_khz>[:dwell_ms]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanSpec {
+    /// First step in Hz
+    pub start: u32,
+    /// Last step in Hz, inclusive
+    pub end: u32,
+    /// Step size in Hz
+    pub step: u32,
+    /// Time spent collecting FFT frames at each hop
+    pub dwell: Duration,
+}
+
+impl std::str::FromStr for ScanSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid scan '{}' (expected <start_mhz>:<end_mhz>:<step_khz>[:dwell_ms])",
+                s
+            )
+        };
+        let fields: Vec<&str> = s.split(':').collect();
+        if !(3..=4).contains(&fields.len()) {
+            return Err(invalid());
+        }
+        let number = |field: &str| field.trim().parse::<f64>().map_err(|_| invalid());
+        let start = (number(fields[0])? * 1_000_000.0).round();
+        let end = (number(fields[1])? * 1_000_000.0).round();
+        let step = (number(fields[2])? * 1_000.0).round();
+        let dwell = match fields.get(3) {
+            Some(ms) => Duration::from_millis(ms.trim().parse().map_err(|_| invalid())?),
+            None => DEFAULT_DWELL,
+        };
+
+        if start <= 0.0 || end > u32::MAX as f64 {
+            return Err(invalid());
+        }
+        if end <= start {
+            return Err(format!("scan end {} MHz is not above the start", fields[1]));
+        }
+        if step < 1.0 {
+            return Err(format!("scan step {} kHz is too small", fields[2]));
+        }
+        Ok(Self {
+            start: start as u32,
+            end: end as u32,
+            step: step as u32,
+            dwell,
+        })
+    }
+}
+
+impl ScanSpec {
+    /// Every step frequency, start to end inclusive
+    pub fn frequencies(&self) -> Vec<u32> {
+        (self.start..=self.end).step_by(self.step as usize).collect()
+    }
+}
+
+/// One retune of a sweep: the center frequency and the steps measured there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub center: u32,
+    /// Indices into the step frequencies
+    pub steps: Range<usize>,
+}
+
+/// Group `frequencies` (ascending, `step` apart) into hops that keep each
+/// step's whole width within the usable part of `sample_rate`
+pub fn plan_hops(frequencies: &[u32], step: u32, sample_rate: u32) -> Vec<Hop> {
+    let usable = (sample_rate as f64 * USABLE_BANDWIDTH) as i64;
+    let half_step = step as i64 / 2;
+    let mut hops = Vec::new();
+    let mut first = 0;
+
+    while first < frequencies.len() {
+        // A step too wide for the band gets a hop of its own
+        let low = frequencies[first] as i64 - half_step;
+        let center = (low + usable / 2).max(frequencies[first] as i64);
+        let high = center + usable / 2;
+        let count = frequencies[first..]
+            .iter()
+            .take_while(|&&f| f as i64 + half_step <= high)
+            .count()
+            .max(1);
+
+        hops.push(Hop {
+            center: center as u32,
+            steps: first..first + count,
+        });
+        first += count;
+    }
+    hops
+}
+
+/// Bins of a centered `fft_len`-point FFT across `sample_rate` whose
+/// centers fall within `width` Hz around `offset` Hz from the center; at
+/// least the nearest bin
+pub fn step_bins(offset: i64, width: u32, sample_rate: u32, fft_len: usize) -> Range<usize> {
+    let bin_hz = sample_rate as f64 / fft_len as f64;
+    let to_bin = |hz: f64| hz / bin_hz + (fft_len / 2) as f64;
+    let clamp = |bin: f64| (bin.max(0.0) as usize).min(fft_len);
+
+    let low = clamp(to_bin(offset as f64 - width as f64 / 2.0).ceil());
+    let high = clamp(to_bin(offset as f64 + width as f64 / 2.0).ceil());
+    if low < high {
+        low..high
+    } else {
+        let nearest = clamp(to_bin(offset as f64).round()).min(fft_len.saturating_sub(1));
+        nearest..nearest + 1
+    }
+}
+
+/// Offset from the center in Hz of `bin` of a centered `fft_len`-point FFT
+/// across `sample_rate`; the inverse of [`step_bins`] for a single bin
+pub fn bin_offset(bin: usize, sample_rate: u32, fft_len: usize) -> i64 {
+    let bin_hz = sample_rate as f64 / fft_len.max(1) as f64;
+    ((bin as f64 - (fft_len / 2) as f64) * bin_hz).round() as i64
+}
+
+/// Highest dB in `bins` of `fft`, leaving out the bins by DC while any
+/// others remain
+pub fn peak_in(fft: &[f32], bins: Range<usize>) -> Option<f32> {
+    peak_bin(fft, bins).map(|bin| fft[bin])
+}
+
+/// The bin of [`peak_in`]
+pub fn peak_bin(fft: &[f32], bins: Range<usize>) -> Option<usize> {
+    let dc = fft.len() / 2;
+    let near_dc = |bin: &usize| bin.abs_diff(dc) <= DC_BINS;
+    let peak = |keep: &dyn Fn(&usize) -> bool| {
+        bins.clone()
+            .filter(|bin| keep(bin) && *bin < fft.len())
+            .reduce(|best, bin| if fft[bin] > fft[best] { bin } else { best })
+    };
+    peak(&|bin| !near_dc(bin)).or_else(|| peak(&|_| true))
+}
+
+/// Peak power per step over every sweep so far
+pub struct Survey {
+    frequencies: Vec<u32>,
+    /// Start time and per-step peaks of each sweep
+    sweeps: Vec<(DateTime<Local>, Vec<Option<f32>>)>,
+}
+
+impl Survey {
+    pub fn new(frequencies: Vec<u32>) -> Self {
+        Self {
+            frequencies,
+            sweeps: Vec::new(),
+        }
+    }
+
+    pub fn start_sweep(&mut self, at: DateTime<Local>) {
+        self.sweeps.push((at, vec![None; self.frequencies.len()]));
+    }
+
+    /// Keep the higher of `db` and what step `index` has seen this sweep
+    pub fn record(&mut self, index: usize, db: f32) {
+        if let Some((_, peaks)) = self.sweeps.last_mut() {
+            let peak = &mut peaks[index];
+            *peak = Some(peak.map_or(db, |p| p.max(db)));
+        }
+    }
+
+    /// One row per step, one column per sweep; steps a sweep never reached
+    /// are left empty
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("frequency_mhz");
+        for (at, _) in &self.sweeps {
+            csv.push_str(&format!(",{}", at.format("%Y-%m-%d %H:%M:%S")));
+        }
+        csv.push('\n');
+
+        for (index, frequency) in self.frequencies.iter().enumerate() {
+            csv.push_str(&format!("{:.6}", *frequency as f64 / 1_000_000.0));
+            for (_, peaks) in &self.sweeps {
+                match peaks[index] {
+                    Some(db) => csv.push_str(&format!(",{:.1}", db)),
+                    None => csv.push(','),
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Write the CSV, replacing `path` only once the new file is complete
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let partial = path.with_extension("csv.part");
+        std::fs::write(&partial, self.to_csv())
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, path)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Where a scan started at `start` is written
+pub fn scan_path(dir: &Path, start: &DateTime<Local>) -> PathBuf {
+    dir.join(format!("scan_{}.csv", start.format("%Y%m%d_%H%M%S")))
+}
+
+/// Start the scan thread
+///
+/// Retunes through the normal command channel and reads the FFT frames the
+/// DSP thread publishes. The CSV is rewritten after every sweep, and after
+/// a partial one when the app shuts down mid-sweep. Progress goes to the
+/// status bar, or to stdout when `print_progress` is set (headless).
+pub fn start_scan_thread(
+    spec: ScanSpec,
+    repeat: bool,
+    path: PathBuf,
+    print_progress: bool,
+    state: SharedState,
+    command_tx: Sender<Command>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        log::info!("Scan thread started: {:?} to {}", spec, path.display());
+        let home = state.read().sdr.frequency;
+        let progress = |message: String| {
+            if print_progress {
+                println!("{}", message);
+            }
+            state.write().ui.scan_status = Some(message);
+        };
+
+        let mut survey = Survey::new(spec.frequencies());
+        let result = loop {
+            let completed = sweep(&spec, &mut survey, &state, &command_tx, &shutdown, &progress);
+            if let Err(e) = survey.write(&path) {
+                break Err(e);
+            }
+            if !completed || !repeat {
+                break Ok(completed);
+            }
+        };
+
+        let _ = command_tx.send(Command::SetFrequency(home));
+        let message = match result {
+            Ok(true) => format!("Scan saved to {}", path.display()),
+            Ok(false) => format!("Scan stopped, partial results in {}", path.display()),
+            Err(e) => {
+                log::error!("Scan: {:#}", e);
+                format!("Scan failed: {}", e)
+            }
+        };
+        log::info!("{}", message);
+        if print_progress {
+            println!("{}", message);
+        }
+        let mut state_guard = state.write();
+        state_guard.ui.scan_status = None;
+        state_guard.ui.status_message = message;
+    })
+}
+
+/// Run one sweep into `survey`; false if shutdown cut it short
+fn sweep(
+    spec: &ScanSpec,
+    survey: &mut Survey,
+    state: &SharedState,
+    command_tx: &Sender<Command>,
+    shutdown: &AtomicBool,
+    progress: &dyn Fn(String),
+) -> bool {
+    let frequencies = spec.frequencies();
+    let sample_rate = state.read().sdr.sample_rate;
+    let hops = plan_hops(&frequencies, spec.step, sample_rate);
+    survey.start_sweep(Local::now());
+
+    for (n, hop) in hops.iter().enumerate() {
+        progress(format!(
+            "Scanning {:.3} MHz ({}/{} steps)",
+            hop.center as f64 / 1_000_000.0,
+            hop.steps.end,
+            frequencies.len()
+        ));
+
+        if command_tx.send(Command::SetFrequency(hop.center)).is_err() {
+            return false;
+        }
+        // Wait for the retune, then for the tuner to settle
+        let asked = Instant::now();
+        while state.read().sdr.frequency != hop.center && asked.elapsed() < RETUNE_TIMEOUT {
+            if shutdown.load(Ordering::Relaxed) {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread::sleep(SETTLE);
+
+        let mut last_frame = state.read().spectrum.frames;
+        let until = Instant::now() + spec.dwell;
+        while Instant::now() < until {
+            if shutdown.load(Ordering::Relaxed) {
+                log::info!("Scan stopped at hop {} of {}", n + 1, hops.len());
+                return false;
+            }
+            thread::sleep(Duration::from_millis(5));
+
+            let state = state.read();
+            if state.spectrum.frames == last_frame {
+                continue;
+            }
+            last_frame = state.spectrum.frames;
+            let fft = &state.spectrum.fft_data;
+            for index in hop.steps.clone() {
+                let offset = frequencies[index] as i64 - hop.center as i64;
+                let bins = step_bins(offset, spec.step, sample_rate, fft.len());
+                if let Some(db) = peak_in(fft, bins) {
+                    survey.record(index, db);
+                }
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec: ScanSpec = "144:148:12.5".parse().unwrap();
+        assert_eq!(spec.start, 144_000_000);
+        assert_eq!(spec.end, 148_000_000);
+        assert_eq!(spec.step, 12_500);
+        assert_eq!(spec.dwell, DEFAULT_DWELL);
+        assert_eq!(spec.frequencies().len(), 321);
+        assert_eq!(spec.frequencies()[1], 144_012_500);
+        assert_eq!(*spec.frequencies().last().unwrap(), 148_000_000);
+
+        let spec: ScanSpec = "88.1:108:200:250".parse().unwrap();
+        assert_eq!(spec.dwell, Duration::from_millis(250));
+        // The end need not fall on a step
+        assert_eq!(*spec.frequencies().last().unwrap(), 107_900_000);
+
+        for bad in ["144:148", "148:144:12.5", "144:148:0", "a:148:12.5", "1:2:3:4:5"] {
+            assert!(bad.parse::<ScanSpec>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_plan_hops() {
+        let spec: ScanSpec = "144:148:12.5".parse().unwrap();
+        let frequencies = spec.frequencies();
+        let hops = plan_hops(&frequencies, spec.step, 2_048_000);
+
+        // Every step exactly once, in order
+        let mut next = 0;
+        for hop in &hops {
+            assert_eq!(hop.steps.start, next);
+            next = hop.steps.end;
+            // Each step's full width stays in the middle 75% of the band
+            for &f in &frequencies[hop.steps.clone()] {
+                let offset = (f as i64 - hop.center as i64).abs() + 6_250;
+                assert!(offset <= 768_000, "{} Hz from {}", offset, hop.center);
+            }
+        }
+        assert_eq!(next, frequencies.len());
+        // 1.536 MHz per hop covers 122 steps of 12.5 kHz
+        assert_eq!(hops.len(), 3);
+        assert_eq!(hops[0].steps, 0..122);
+        assert_eq!(hops[0].center, 144_761_750);
+
+        // Steps wider than the band get a hop each, centered on them
+        let wide = plan_hops(&[100_000_000, 103_000_000], 3_000_000, 2_048_000);
+        assert_eq!(wide[0], Hop { center: 100_000_000, steps: 0..1 });
+        assert_eq!(wide[1], Hop { center: 103_000_000, steps: 1..2 });
+    }
+
+    #[test]
+    fn test_step_bins() {
+        // 1 kHz bins, DC at bin 1024
+        assert_eq!(step_bins(0, 12_500, 2_048_000, 2048), 1018..1031);
+        assert_eq!(step_bins(100_000, 12_500, 2_048_000, 2048), 1118..1131);
+        // Narrower than a bin: the nearest one
+        assert_eq!(step_bins(2_400, 100, 2_048_000, 2048), 1026..1027);
+        // Clipped at the band edge
+        assert_eq!(step_bins(-1_024_000, 12_500, 2_048_000, 2048), 0..7);
+        // And back
+        assert_eq!(bin_offset(1124, 2_048_000, 2048), 100_000);
+        assert_eq!(bin_offset(0, 2_048_000, 2048), -1_024_000);
+    }
+
+    #[test]
+    fn test_peak_in_skips_dc() {
+        let mut fft = vec![-90.0; 2048];
+        fft[1024] = -10.0;
+        fft[1030] = -50.0;
+        assert_eq!(peak_in(&fft, 1018..1031), Some(-50.0));
+        // Only DC bins left: use them
+        assert_eq!(peak_in(&fft, 1023..1026), Some(-10.0));
+        assert_eq!(peak_in(&fft, 3000..3010), None);
+    }
+
+    #[test]
+    fn test_survey_csv() {
+        let at = |s: &str| {
+            chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+                .unwrap()
+                .and_local_timezone(Local)
+                .unwrap()
+        };
+        let mut survey = Survey::new(vec![144_000_000, 144_012_500]);
+        survey.start_sweep(at("2024-06-01 12:00:00"));
+        survey.record(0, -80.0);
+        survey.record(0, -70.3);
+        survey.record(0, -75.0);
+        survey.record(1, -60.0);
+        survey.start_sweep(at("2024-06-01 12:01:00"));
+        survey.record(0, -81.0);
+
+        assert_eq!(
+            survey.to_csv(),
+            "frequency_mhz,2024-06-01 12:00:00,2024-06-01 12:01:00\n\
+             144.000000,-70.3,-81.0\n\
+             144.012500,-60.0,\n"
+        );
+    }
+}
@@ -0,0 +1,574 @@
+//! Scheduled Recordings
+//!
+//! Runs timed recordings from the `[[schedule]]` entries of the config file:
+//! at each start time the scheduler tunes, switches mode, and starts
+//! recording, then stops again once the duration has elapsed.
+//!
+//! A schedule that fires while another recording is running (a manual one or
+//! an overlapping schedule) is skipped with a warning rather than interrupting
+//! it.
+
+use crate::recorder::{recording_path, SampleFormat};
+use crate::state::{RecordingMode, SharedState};
+use crate::types::{Command, DemodMode, ScheduleConfig};
+use crate::util::units::parse_duration;
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use crossbeam::channel::Sender;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// How long a started recording gets to show up in state before the
+/// scheduler assumes it was stopped by hand
+const START_GRACE: Duration = Duration::seconds(5);
+
+/// When a scheduled recording starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleTime {
+    /// Every day (or only on the listed weekdays) at a local time
+    Daily {
+        time: NaiveTime,
+        days: Option<Vec<Weekday>>,
+    },
+    /// Once, at a local date and time
+    Once(NaiveDateTime),
+}
+
+impl ScheduleTime {
+    /// Parse "14:00", "mon,wed,fri 14:00" or "2024-06-01 14:00"
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+
+        if let Ok(at) = NaiveDateTime::parse_from_str(spec, "%Y-%m-%d %H:%M") {
+            return Ok(ScheduleTime::Once(at));
+        }
+
+        let (days, time) = match spec.rsplit_once(char::is_whitespace) {
+            Some((days, time)) => (Some(parse_weekdays(days)?), time),
+            None => (None, spec),
+        };
+        let time = NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| anyhow!("invalid start time '{}' (expected HH:MM)", spec))?;
+
+        Ok(ScheduleTime::Daily { time, days })
+    }
+}
+
+/// Parse a comma-separated weekday list, e.g. "mon,wed,fri"
+fn parse_weekdays(spec: &str) -> Result<Vec<Weekday>> {
+    spec.split(',')
+        .map(|day| {
+            day.trim()
+                .parse::<Weekday>()
+                .map_err(|_| anyhow!("invalid weekday '{}'", day.trim()))
+        })
+        .collect()
+}
+
+/// How a scheduled recording is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleFormat {
+    Iq,
+    SigMf,
+    Squelch,
+}
+
+impl std::str::FromStr for ScheduleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "iq" | "raw" => Ok(ScheduleFormat::Iq),
+            "sigmf" => Ok(ScheduleFormat::SigMf),
+            "squelch" | "wav" => Ok(ScheduleFormat::Squelch),
+            _ => Err(format!("unknown format '{}' (expected iq, sigmf or squelch)", s)),
+        }
+    }
+}
+
+/// A parsed, validated schedule entry
+#[derive(Debug, Clone)]
+pub struct ScheduleEntry {
+    pub name: String,
+    pub when: ScheduleTime,
+    pub duration: Duration,
+    /// Frequency in Hz
+    pub frequency: u32,
+    pub mode: DemodMode,
+    pub format: ScheduleFormat,
+    /// IQ sample format, when not the one recordings are set to
+    pub sample_format: Option<SampleFormat>,
+}
+
+impl ScheduleEntry {
+    /// Validate a config file entry
+    pub fn from_config(config: &ScheduleConfig) -> Result<Self> {
+        let when = ScheduleTime::parse(&config.start)?;
+        let duration = Duration::from_std(parse_duration(&config.duration)?)?;
+        if matches!(when, ScheduleTime::Daily { .. }) && duration > Duration::days(1) {
+            bail!("daily schedule '{}' is longer than a day", config.start);
+        }
+
+        let mode = match &config.mode {
+            Some(mode) => mode.parse().map_err(|e: String| anyhow!(e))?,
+            None => DemodMode::default(),
+        };
+        let format = match &config.format {
+            Some(format) => format.parse().map_err(|e: String| anyhow!(e))?,
+            None => ScheduleFormat::Iq,
+        };
+        let sample_format = config
+            .sample_format
+            .as_ref()
+            .map(|format| format.parse().map_err(|e: String| anyhow!(e)))
+            .transpose()?;
+
+        Ok(Self {
+            name: config
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{:.3} MHz", config.frequency)),
+            when,
+            duration,
+            frequency: (config.frequency * 1_000_000.0).round() as u32,
+            mode,
+            format,
+            sample_format,
+        })
+    }
+
+    /// The earliest occurrence (start, end) that has not ended by `now`
+    pub fn next_window(&self, now: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        match &self.when {
+            ScheduleTime::Once(start) => {
+                let end = *start + self.duration;
+                (end > now).then_some((*start, end))
+            }
+            ScheduleTime::Daily { time, days } => {
+                // Start a day back so a window spanning midnight is found
+                (-1..=7)
+                    .filter_map(|offset| now.date().checked_add_signed(Duration::days(offset)))
+                    .filter(|date: &NaiveDate| {
+                        days.as_ref().is_none_or(|days| days.contains(&date.weekday()))
+                    })
+                    .map(|date| {
+                        let start = date.and_time(*time);
+                        (start, start + self.duration)
+                    })
+                    .find(|&(_, end)| end > now)
+            }
+        }
+    }
+}
+
+/// What the scheduler wants done on a tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleAction {
+    /// Tune and start recording entry `index`
+    Start(usize),
+    /// Stop the recording started for entry `index`
+    Stop(usize),
+    /// Entry `index` fired while something else was recording
+    Skip(usize),
+}
+
+/// The recording the scheduler started and still owns
+#[derive(Debug, Clone, Copy)]
+struct ActiveSchedule {
+    index: usize,
+    started: NaiveDateTime,
+    end: NaiveDateTime,
+}
+
+/// Schedule bookkeeping, driven by an external clock so it can be tested
+pub struct Scheduler {
+    entries: Vec<ScheduleEntry>,
+    /// Next window per entry (None once a one-shot has passed)
+    next: Vec<Option<(NaiveDateTime, NaiveDateTime)>>,
+    active: Option<ActiveSchedule>,
+}
+
+impl Scheduler {
+    /// Create a scheduler; windows already in progress at `now` fire on the first tick
+    pub fn new(entries: Vec<ScheduleEntry>, now: NaiveDateTime) -> Self {
+        let next = entries.iter().map(|e| e.next_window(now)).collect();
+        Self {
+            entries,
+            next,
+            active: None,
+        }
+    }
+
+    /// Scheduled entries
+    pub fn entries(&self) -> &[ScheduleEntry] {
+        &self.entries
+    }
+
+    /// Advance to `now`; `recording` is whether any recording is running
+    pub fn tick(&mut self, now: NaiveDateTime, recording: bool) -> Vec<ScheduleAction> {
+        let mut actions = Vec::new();
+
+        if let Some(active) = self.active {
+            if now >= active.end {
+                actions.push(ScheduleAction::Stop(active.index));
+                self.active = None;
+            } else if !recording && now >= active.started + START_GRACE {
+                // Stopped by hand (or the recorder failed); nothing left to stop
+                self.active = None;
+            }
+        }
+        let mut busy = self.active.is_some() || (recording && actions.is_empty());
+
+        for index in 0..self.entries.len() {
+            let Some((start, end)) = self.next[index] else {
+                continue;
+            };
+            if now < start {
+                continue;
+            }
+
+            if now >= end {
+                // The whole window passed between ticks (e.g. suspend)
+                log::warn!("Missed scheduled recording '{}'", self.entries[index].name);
+            } else if busy {
+                actions.push(ScheduleAction::Skip(index));
+            } else {
+                actions.push(ScheduleAction::Start(index));
+                self.active = Some(ActiveSchedule {
+                    index,
+                    started: now,
+                    end,
+                });
+                busy = true;
+            }
+            self.next[index] = self.entries[index].next_window(end);
+        }
+
+        actions
+    }
+
+    /// Entry and end time of the recording the scheduler is running
+    pub fn active(&self) -> Option<(&ScheduleEntry, NaiveDateTime)> {
+        self.active
+            .map(|active| (&self.entries[active.index], active.end))
+    }
+
+    /// The next entry due to start, with its start time
+    pub fn next_event(&self) -> Option<(&ScheduleEntry, NaiveDateTime)> {
+        self.next
+            .iter()
+            .enumerate()
+            .filter_map(|(index, window)| window.map(|(start, _)| (index, start)))
+            .min_by_key(|&(_, start)| start)
+            .map(|(index, start)| (&self.entries[index], start))
+    }
+
+    /// One-line description for the status bar
+    pub fn describe(&self) -> Option<String> {
+        if let Some((entry, end)) = self.active() {
+            return Some(format!("Scheduled: {} until {}", entry.name, end.format("%H:%M")));
+        }
+        self.next_event().map(|(entry, start)| {
+            format!(
+                "Next: {} {} ({:.3} MHz)",
+                entry.name,
+                start.format("%a %H:%M"),
+                entry.frequency as f64 / 1_000_000.0
+            )
+        })
+    }
+}
+
+/// Start the scheduler thread
+///
+/// Issues the same commands the UI would, so scheduled recordings go through
+/// the normal SDR and recorder paths.
+pub fn start_scheduler_thread(
+    entries: Vec<ScheduleEntry>,
+    state: SharedState,
+    command_tx: Sender<Command>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        log::info!("Scheduler thread started with {} entries", entries.len());
+
+        let mut scheduler = Scheduler::new(entries, chrono::Local::now().naive_local());
+        // Recording settings to put back after a scheduled recording
+        let mut saved_settings: Option<RecordingSettings> = None;
+
+        while !shutdown.load(Ordering::Relaxed) {
+            let now = chrono::Local::now().naive_local();
+            let recording = state.read().recording.is_recording;
+
+            for action in scheduler.tick(now, recording) {
+                let entry = &scheduler.entries()[match action {
+                    ScheduleAction::Start(i) | ScheduleAction::Stop(i) | ScheduleAction::Skip(i) => i,
+                }];
+
+                match action {
+                    ScheduleAction::Start(_) => {
+                        log::info!("Starting scheduled recording '{}'", entry.name);
+                        saved_settings = Some(apply_format(&state, entry));
+                        let _ = command_tx.send(Command::SetFrequency(entry.frequency));
+                        let _ = command_tx.send(Command::SetMode(entry.mode));
+                        let _ = command_tx.send(start_command(&state, entry));
+                        state.write().ui.status_message =
+                            format!("Scheduled recording started: {}", entry.name);
+                    }
+                    ScheduleAction::Stop(_) => {
+                        log::info!("Stopping scheduled recording '{}'", entry.name);
+                        let _ = command_tx.send(Command::StopRecording);
+                        state.write().ui.status_message =
+                            format!("Scheduled recording finished: {}", entry.name);
+                    }
+                    ScheduleAction::Skip(_) => {
+                        log::warn!(
+                            "Skipping scheduled recording '{}': another recording is running",
+                            entry.name
+                        );
+                        state.write().ui.status_message = format!(
+                            "Skipped scheduled recording '{}' (already recording)",
+                            entry.name
+                        );
+                    }
+                }
+            }
+
+            // Restore the user's recording settings once our recording is over
+            if scheduler.active().is_none() {
+                if let Some((mode, sigmf, format)) = saved_settings.take() {
+                    let mut state_guard = state.write();
+                    state_guard.recording.mode = mode;
+                    state_guard.recording.sigmf = sigmf;
+                    state_guard.recording.format = format;
+                }
+            }
+
+            state.write().recording.next_scheduled = scheduler.describe();
+            thread::sleep(std::time::Duration::from_millis(500));
+        }
+
+        log::info!("Scheduler thread stopped");
+    })
+}
+
+/// Recording mode, SigMF and sample format, as a schedule overrides them
+type RecordingSettings = (RecordingMode, bool, SampleFormat);
+
+/// Switch the recording settings to a schedule's format; returns the old ones
+fn apply_format(state: &SharedState, entry: &ScheduleEntry) -> RecordingSettings {
+    let mut state_guard = state.write();
+    let recording = &mut state_guard.recording;
+    let saved = (recording.mode, recording.sigmf, recording.format);
+
+    recording.mode = match entry.format {
+        ScheduleFormat::Squelch => RecordingMode::Squelch,
+        ScheduleFormat::Iq | ScheduleFormat::SigMf => RecordingMode::Iq,
+    };
+    recording.sigmf = entry.format == ScheduleFormat::SigMf;
+    recording.format = entry.sample_format.unwrap_or(recording.format);
+    saved
+}
+
+/// The command that starts recording for a schedule entry
+fn start_command(state: &SharedState, entry: &ScheduleEntry) -> Command {
+    let state = state.read();
+    match entry.format {
+        ScheduleFormat::Squelch => Command::StartSquelchRecording,
+        ScheduleFormat::Iq | ScheduleFormat::SigMf => Command::StartRecording(recording_path(
+            &state.recording.output_dir,
+            &chrono::Local::now(),
+            entry.frequency,
+            entry.mode,
+            state.sdr.sample_rate,
+            state.recording.extension(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(spec: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(spec, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn entry(start: &str, duration: &str) -> ScheduleEntry {
+        ScheduleEntry::from_config(&ScheduleConfig {
+            name: Some(start.to_string()),
+            start: start.to_string(),
+            duration: duration.to_string(),
+            frequency: 162.55,
+            mode: Some("nfm".to_string()),
+            format: None,
+            sample_format: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_schedule_time() {
+        assert_eq!(
+            ScheduleTime::parse("14:00").unwrap(),
+            ScheduleTime::Daily {
+                time: NaiveTime::from_hms_opt(14, 0, 0).unwrap(),
+                days: None
+            }
+        );
+        assert_eq!(
+            ScheduleTime::parse("mon,fri 06:30").unwrap(),
+            ScheduleTime::Daily {
+                time: NaiveTime::from_hms_opt(6, 30, 0).unwrap(),
+                days: Some(vec![Weekday::Mon, Weekday::Fri])
+            }
+        );
+        assert_eq!(
+            ScheduleTime::parse("2024-06-01 14:00").unwrap(),
+            ScheduleTime::Once(at("2024-06-01 14:00:00"))
+        );
+        assert!(ScheduleTime::parse("25:00").is_err());
+        assert!(ScheduleTime::parse("someday 14:00").is_err());
+    }
+
+    #[test]
+    fn test_entry_from_config() {
+        let e = entry("14:00", "10m");
+        assert_eq!(e.frequency, 162_550_000);
+        assert_eq!(e.mode, DemodMode::FmNarrow);
+        assert_eq!(e.format, ScheduleFormat::Iq);
+        assert_eq!(e.duration, Duration::minutes(10));
+    }
+
+    #[test]
+    fn test_sample_format_override() {
+        let mut e = entry("14:00", "10m");
+        assert_eq!(e.sample_format, None);
+        e.sample_format = Some(SampleFormat::Cf32);
+
+        let state = crate::state::AppState::new_shared();
+        let saved = apply_format(&state, &e);
+        assert_eq!(saved, (RecordingMode::Iq, false, SampleFormat::Cu8));
+        assert_eq!(state.read().recording.format, SampleFormat::Cf32);
+        let Command::StartRecording(path) = start_command(&state, &e) else {
+            panic!("expected an IQ recording");
+        };
+        assert_eq!(path.extension().unwrap(), "cf32");
+
+        let mut config = ScheduleConfig {
+            name: None,
+            start: "14:00".to_string(),
+            duration: "10m".to_string(),
+            frequency: 162.55,
+            mode: None,
+            format: None,
+            sample_format: Some("i16".to_string()),
+        };
+        let e = ScheduleEntry::from_config(&config).unwrap();
+        assert_eq!(e.sample_format, Some(SampleFormat::Ci16));
+        config.sample_format = Some("u4".to_string());
+        assert!(ScheduleEntry::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_next_window_daily() {
+        let e = entry("14:00", "10m");
+
+        // Before today's window
+        let (start, end) = e.next_window(at("2024-06-01 13:00:00")).unwrap();
+        assert_eq!(start, at("2024-06-01 14:00:00"));
+        assert_eq!(end, at("2024-06-01 14:10:00"));
+
+        // Inside today's window: still today's
+        let (start, _) = e.next_window(at("2024-06-01 14:05:00")).unwrap();
+        assert_eq!(start, at("2024-06-01 14:00:00"));
+
+        // After today's window: tomorrow
+        let (start, _) = e.next_window(at("2024-06-01 14:10:00")).unwrap();
+        assert_eq!(start, at("2024-06-02 14:00:00"));
+    }
+
+    #[test]
+    fn test_next_window_weekdays_and_midnight() {
+        // 2024-06-01 is a Saturday
+        let e = entry("mon,wed 23:50", "20m");
+        let (start, _) = e.next_window(at("2024-06-01 12:00:00")).unwrap();
+        assert_eq!(start, at("2024-06-03 23:50:00"));
+
+        // Just after midnight inside Monday's window
+        let (start, end) = e.next_window(at("2024-06-04 00:05:00")).unwrap();
+        assert_eq!(start, at("2024-06-03 23:50:00"));
+        assert_eq!(end, at("2024-06-04 00:10:00"));
+    }
+
+    #[test]
+    fn test_next_window_once() {
+        let e = entry("2024-06-01 14:00", "10m");
+        assert!(e.next_window(at("2024-06-01 13:00:00")).is_some());
+        assert!(e.next_window(at("2024-06-01 14:10:00")).is_none());
+    }
+
+    #[test]
+    fn test_scheduler_start_stop() {
+        let mut s = Scheduler::new(vec![entry("14:00", "10m")], at("2024-06-01 13:59:00"));
+
+        assert!(s.tick(at("2024-06-01 13:59:30"), false).is_empty());
+        assert_eq!(s.tick(at("2024-06-01 14:00:00"), false), vec![ScheduleAction::Start(0)]);
+        assert!(s.tick(at("2024-06-01 14:05:00"), true).is_empty());
+        assert_eq!(s.tick(at("2024-06-01 14:10:00"), true), vec![ScheduleAction::Stop(0)]);
+
+        // Rescheduled for tomorrow
+        let (_, start) = s.next_event().unwrap();
+        assert_eq!(start, at("2024-06-02 14:00:00"));
+    }
+
+    #[test]
+    fn test_scheduler_skips_during_manual_recording() {
+        let mut s = Scheduler::new(vec![entry("14:00", "10m")], at("2024-06-01 13:59:00"));
+
+        assert_eq!(s.tick(at("2024-06-01 14:00:00"), true), vec![ScheduleAction::Skip(0)]);
+        // The skipped window does not fire later once the manual recording ends
+        assert!(s.tick(at("2024-06-01 14:02:00"), false).is_empty());
+    }
+
+    #[test]
+    fn test_scheduler_skips_overlap() {
+        let mut s = Scheduler::new(
+            vec![entry("14:00", "10m"), entry("14:05", "10m")],
+            at("2024-06-01 13:59:00"),
+        );
+
+        assert_eq!(s.tick(at("2024-06-01 14:00:00"), false), vec![ScheduleAction::Start(0)]);
+        assert_eq!(s.tick(at("2024-06-01 14:05:00"), true), vec![ScheduleAction::Skip(1)]);
+        assert_eq!(s.tick(at("2024-06-01 14:10:00"), true), vec![ScheduleAction::Stop(0)]);
+    }
+
+    #[test]
+    fn test_scheduler_back_to_back() {
+        let mut s = Scheduler::new(
+            vec![entry("14:00", "10m"), entry("14:10", "10m")],
+            at("2024-06-01 13:59:00"),
+        );
+
+        s.tick(at("2024-06-01 14:00:00"), false);
+        assert_eq!(
+            s.tick(at("2024-06-01 14:10:00"), true),
+            vec![ScheduleAction::Stop(0), ScheduleAction::Start(1)]
+        );
+    }
+
+    #[test]
+    fn test_scheduler_manual_stop() {
+        let mut s = Scheduler::new(vec![entry("14:00", "10m")], at("2024-06-01 13:59:00"));
+
+        s.tick(at("2024-06-01 14:00:00"), false);
+        // Not yet visible in state: still within the start grace period
+        assert!(s.tick(at("2024-06-01 14:00:01"), false).is_empty());
+        assert!(s.active().is_some());
+        // User stopped the recording: nothing to stop at the end
+        assert!(s.tick(at("2024-06-01 14:03:00"), false).is_empty());
+        assert!(s.active().is_none());
+        assert!(s.tick(at("2024-06-01 14:10:00"), false).is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+//! How much of the 8-bit ADC's range the samples use
+//!
+//! The clipping warning only says when the gain is too high. To show
+//! under-driving too, every raw I and Q byte is counted into one of 256
+//! buckets, and once a second the counts give how far towards full scale
+//! the samples swing and their crest factor (peak to RMS). Backends that
+//! deliver floats are counted at the byte they would have been.
+//!
+//! The peak is the 99.9th percentile of the distance from the center, so a
+//! stray spike doesn't read as a well-driven ADC.
+
+use num_complex::Complex;
+use std::time::{Duration, Instant};
+
+/// ADC levels, one bucket each
+pub const LEVELS: usize = 256;
+/// How long counts are gathered before the figures are worked out
+pub const WINDOW: Duration = Duration::from_secs(1);
+/// Share of samples allowed beyond the peak
+const PEAK_QUANTILE: f64 = 0.999;
+/// The ADC's center, between levels 127 and 128
+const CENTER: f64 = 127.5;
+/// Bars for the histogram line, lowest to highest
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Counts of raw I and Q values by level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    counts: [u64; LEVELS],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; LEVELS],
+        }
+    }
+}
+
+/// Figures from a histogram
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdcUse {
+    /// How far towards full scale the samples reach, 0-1
+    pub range: f32,
+    /// Peak to RMS in dB
+    pub crest_db: f32,
+}
+
+impl Histogram {
+    /// Count interleaved 8-bit IQ
+    pub fn add_u8(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.counts[byte as usize] += 1;
+        }
+    }
+
+    /// Count IQ samples at the bytes they convert to
+    pub fn add(&mut self, samples: &[Complex<f32>]) {
+        let level = |v: f32| (v * 128.0 + 127.5).round().clamp(0.0, 255.0) as usize;
+        for sample in samples {
+            self.counts[level(sample.re)] += 1;
+            self.counts[level(sample.im)] += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+    }
+
+    /// Values counted
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Range used and crest factor; None if nothing was counted
+    pub fn usage(&self) -> Option<AdcUse> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        // Distance from the center: 0.5 for levels 127 and 128 up to 127.5
+        // at 0 and 255, bucketed by level pair
+        let mut distances = [0u64; LEVELS / 2];
+        let mut power = 0.0;
+        for (level, &count) in self.counts.iter().enumerate() {
+            let distance = (level as f64 - CENTER).abs();
+            distances[distance as usize] += count;
+            power += count as f64 * distance * distance;
+        }
+        let limit = (total as f64 * PEAK_QUANTILE).ceil() as u64;
+        let mut seen = 0;
+        let peak_bucket = distances
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen >= limit
+            })
+            .unwrap_or(LEVELS / 2 - 1);
+        let peak = peak_bucket as f64 + 0.5;
+        let rms = (power / total as f64).sqrt();
+        Some(AdcUse {
+            range: (peak / CENTER) as f32,
+            crest_db: (20.0 * (peak / rms).log10()) as f32,
+        })
+    }
+
+    /// The histogram as a line of `columns` bars over the ADC's range
+    ///
+    /// Any column with counts shows at least the lowest bar, so a narrow
+    /// distribution is still seen as one.
+    pub fn bars(&self, columns: usize) -> String {
+        let columns = columns.clamp(1, LEVELS);
+        let sums: Vec<u64> = (0..columns)
+            .map(|column| {
+                let start = column * LEVELS / columns;
+                let end = (column + 1) * LEVELS / columns;
+                self.counts[start..end].iter().sum()
+            })
+            .collect();
+        let highest = sums.iter().copied().max().unwrap_or(0);
+        sums.iter()
+            .map(|&sum| match sum {
+                0 => ' ',
+                _ => {
+                    let bar = (sum * BARS.len() as u64).div_ceil(highest) as usize;
+                    BARS[bar.clamp(1, BARS.len()) - 1]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Gathers a window's counts and keeps the last complete one
+#[derive(Debug, Clone, Default)]
+pub struct AdcMeter {
+    window: Histogram,
+    since: Option<Instant>,
+    /// The last complete window, and its figures
+    pub histogram: Option<Histogram>,
+    pub usage: Option<AdcUse>,
+}
+
+impl AdcMeter {
+    /// Add a buffer's counts at `now`, finishing the window once it has run
+    /// its length
+    pub fn add(&mut self, histogram: &Histogram, now: Instant) {
+        let since = *self.since.get_or_insert(now);
+        self.window.merge(histogram);
+        if now.saturating_duration_since(since) >= WINDOW {
+            let window = std::mem::take(&mut self.window);
+            self.usage = window.usage();
+            self.histogram = Some(window);
+            self.since = Some(now);
+        }
+    }
+
+    /// e.g. "43%"; "-" until a window is complete
+    pub fn percent(&self) -> String {
+        match self.usage {
+            Some(usage) => format!("{:.0}%", usage.range * 100.0),
+            None => "-".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A histogram with `count` of each level in `levels`
+    fn spread(levels: impl IntoIterator<Item = u8>, count: usize) -> Histogram {
+        let mut histogram = Histogram::default();
+        for level in levels {
+            histogram.add_u8(&vec![level; count]);
+        }
+        histogram
+    }
+
+    #[test]
+    fn test_full_range() {
+        // Uniform over the whole range: all of it used, and the crest
+        // factor of a uniform distribution, √3
+        let usage = spread(0..=255, 1000).usage().unwrap();
+        assert!((usage.range - 1.0).abs() < 0.01, "{:?}", usage);
+        assert!((usage.crest_db - 4.77).abs() < 0.05, "{:?}", usage);
+    }
+
+    #[test]
+    fn test_under_driven() {
+        // Swinging only 16 levels either side: 12% of the range
+        let usage = spread(112..=143, 1000).usage().unwrap();
+        assert!((usage.range - 15.5 / 127.5).abs() < 1e-3, "{:?}", usage);
+
+        // Sitting in the middle two levels
+        let usage = spread([127, 128], 500).usage().unwrap();
+        assert!((usage.range - 0.5 / 127.5).abs() < 1e-6);
+        assert!(usage.crest_db.abs() < 1e-3);
+        assert_eq!(Histogram::default().usage(), None);
+    }
+
+    #[test]
+    fn test_spikes_ignored() {
+        // Narrow noise with a few samples hitting the rails
+        let mut histogram = spread(120..=135, 10_000);
+        histogram.add_u8(&[0, 255, 0, 255, 0, 255]);
+        let usage = histogram.usage().unwrap();
+        assert!((usage.range - 7.5 / 127.5).abs() < 1e-3, "{:?}", usage);
+
+        // Once they are more than one in a thousand they count
+        histogram.add_u8(&[255; 400]);
+        assert_eq!(histogram.usage().unwrap().range, 1.0);
+    }
+
+    #[test]
+    fn test_gaussian_crest() {
+        // Gaussian noise at σ = 20 levels: the 99.9th percentile is about
+        // 3.3σ out, a crest factor of about 10.4 dB
+        let mut histogram = Histogram::default();
+        let sigma = 20.0f64;
+        for level in 0..=255u8 {
+            let x = level as f64 - CENTER;
+            let count = (1e6 * (-x * x / (2.0 * sigma * sigma)).exp()) as usize;
+            histogram.add_u8(&vec![level; count]);
+        }
+        let usage = histogram.usage().unwrap();
+        assert!((usage.range - 66.5 / 127.5).abs() < 0.02, "{:?}", usage);
+        assert!((usage.crest_db - 10.4).abs() < 0.3, "{:?}", usage);
+    }
+
+    #[test]
+    fn test_float_samples() {
+        // Floats land on the bytes they convert to
+        let mut floats = Histogram::default();
+        floats.add(&crate::sdr::samples_u8_to_complex(&[
+            0, 255, 127, 128, 140, 90,
+        ]));
+        let mut bytes = Histogram::default();
+        bytes.add_u8(&[0, 255, 127, 128, 140, 90]);
+        assert_eq!(floats, bytes);
+        floats.add(&[Complex::new(2.0, -2.0)]);
+        assert_eq!(floats.counts[255], 2);
+        assert_eq!(floats.counts[0], 2);
+    }
+
+    #[test]
+    fn test_meter_window() {
+        let start = Instant::now();
+        let mut meter = AdcMeter::default();
+        let buffer = spread(96..=159, 10);
+        for n in 0..10 {
+            meter.add(&buffer, start + Duration::from_millis(n * 100));
+        }
+        assert_eq!(meter.usage, None);
+        assert_eq!(meter.percent(), "-");
+
+        // A second's worth, then the next window starts empty
+        meter.add(&buffer, start + Duration::from_secs(1));
+        assert_eq!(meter.histogram.as_ref().unwrap().total(), 11 * 640);
+        assert_eq!(meter.percent(), "25%");
+        meter.add(&spread([127], 1), start + Duration::from_millis(2000));
+        assert_eq!(meter.histogram.as_ref().unwrap().total(), 1);
+    }
+
+    #[test]
+    fn test_bars() {
+        assert_eq!(Histogram::default().bars(8), "        ");
+        // Only the middle, evenly: two full bars in the middle columns
+        assert_eq!(spread(96..=159, 10).bars(8), "   ██   ");
+        // A few at one rail still show
+        let mut histogram = spread(96..=159, 1000);
+        histogram.add_u8(&[255]);
+        assert_eq!(histogram.bars(8), "   ██  ▁");
+        assert_eq!(histogram.bars(1000).chars().count(), LEVELS);
+    }
+}
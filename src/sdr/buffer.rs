@@ -0,0 +1,172 @@
+//! IQ buffers as they leave the receiver, stamped with where in the sample
+//! stream they fall and when they were captured
+//!
+//! The sample sink numbers every sample a source delivers, dropped buffers
+//! included, so a jump in [`SampleBuffer::sample_index`] shows exactly how
+//! much was lost. Capture times come from a [`CaptureClock`] that counts
+//! samples from an anchor rather than reading the wall clock per buffer, so
+//! consecutive buffers line up to the sample instead of carrying the jitter
+//! of USB transfers and thread wakeups.
+
+use num_complex::Complex;
+use std::time::{Duration, SystemTime};
+
+/// How far the clock may drift from when buffers actually arrive before it
+/// takes a fresh anchor (a stall, or the dongle's crystal being off)
+pub const MAX_WANDER: Duration = Duration::from_millis(50);
+
+/// A buffer of IQ samples from the receiver
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleBuffer {
+    pub samples: Vec<Complex<f32>>,
+    /// When the first sample was captured
+    pub capture_time: SystemTime,
+    /// Number of the first sample in the receiver's stream
+    pub sample_index: u64,
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+    /// Frequency the hardware was tuned to, which the samples are of
+    pub center_freq: u32,
+    /// Whether the source jumped in its stream (a seek in a file being
+    /// played) just before this buffer, so what came before doesn't belong
+    /// with it
+    pub seeked: bool,
+}
+
+impl SampleBuffer {
+    /// Samples from outside a receiver's stream: numbered from zero,
+    /// captured now, at no particular frequency
+    pub fn new(samples: Vec<Complex<f32>>, sample_rate: u32) -> Self {
+        Self {
+            samples,
+            capture_time: SystemTime::now(),
+            sample_index: 0,
+            sample_rate,
+            center_freq: 0,
+            seeked: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// When the sample `offset` into the buffer was captured
+    pub fn time_of(&self, offset: usize) -> SystemTime {
+        self.capture_time + span(offset as u64, self.sample_rate)
+    }
+
+    /// When the buffer's last sample had been captured
+    pub fn end_time(&self) -> SystemTime {
+        self.time_of(self.len())
+    }
+
+    /// Index of the sample after this buffer
+    pub fn next_index(&self) -> u64 {
+        self.sample_index + self.len() as u64
+    }
+}
+
+/// How long `samples` samples take at `rate`
+pub fn span(samples: u64, rate: u32) -> Duration {
+    let nanos = samples as u128 * 1_000_000_000 / rate.max(1) as u128;
+    Duration::from_nanos(nanos.min(u64::MAX as u128) as u64)
+}
+
+/// Turns sample indices into capture times
+///
+/// The first buffer is taken to have been captured just before it arrived;
+/// later ones are timed by their index from it. A buffer arriving more than
+/// [`MAX_WANDER`] away from that, or at another rate, starts over from its
+/// own arrival.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureClock {
+    /// Index and capture time of the sample timed from, and the rate then
+    anchor: Option<(u64, SystemTime, u32)>,
+}
+
+impl CaptureClock {
+    /// When sample `index` was captured, it being the first of `len`
+    /// samples at `rate` that arrived at `arrived`
+    pub fn time(&mut self, index: u64, len: usize, rate: u32, arrived: SystemTime) -> SystemTime {
+        let by_arrival = arrived
+            .checked_sub(span(len as u64, rate))
+            .unwrap_or(arrived);
+        if let Some((start, at, anchor_rate)) = self.anchor {
+            if anchor_rate == rate && index >= start {
+                let by_index = at + span(index - start, rate);
+                let wander = match by_index.duration_since(by_arrival) {
+                    Ok(ahead) => ahead,
+                    Err(behind) => behind.duration(),
+                };
+                if wander <= MAX_WANDER {
+                    return by_index;
+                }
+            }
+        }
+        self.anchor = Some((index, by_arrival, rate));
+        by_arrival
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 2_048_000;
+
+    fn at(millis: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_000 + millis)
+    }
+
+    #[test]
+    fn test_span() {
+        assert_eq!(span(2_048_000, RATE), Duration::from_secs(1));
+        assert_eq!(span(16_384, RATE), Duration::from_millis(8));
+        assert_eq!(span(48, 48_000), Duration::from_millis(1));
+        assert_eq!(span(10, 0), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_buffer_times() {
+        let buffer = SampleBuffer {
+            samples: vec![Complex::new(0.0, 0.0); 16_384],
+            capture_time: at(0),
+            sample_index: 32_768,
+            sample_rate: RATE,
+            center_freq: 162_550_000,
+            seeked: false,
+        };
+        assert_eq!(buffer.time_of(2_048), at(1));
+        assert_eq!(buffer.end_time(), at(8));
+        assert_eq!(buffer.next_index(), 49_152);
+    }
+
+    #[test]
+    fn test_clock_ignores_jitter() {
+        let mut clock = CaptureClock::default();
+        // 8 ms buffers arriving a few ms early or late
+        assert_eq!(clock.time(0, 16_384, RATE, at(8)), at(0));
+        assert_eq!(clock.time(16_384, 16_384, RATE, at(19)), at(8));
+        assert_eq!(clock.time(32_768, 16_384, RATE, at(22)), at(16));
+        // A dropped buffer leaves a gap in the index, not in the timing
+        assert_eq!(clock.time(65_536, 16_384, RATE, at(40)), at(32));
+    }
+
+    #[test]
+    fn test_clock_reanchors() {
+        let mut clock = CaptureClock::default();
+        clock.time(0, 16_384, RATE, at(8));
+        // Stalled for a second without losing count
+        assert_eq!(clock.time(16_384, 16_384, RATE, at(1_016)), at(1_008));
+        assert_eq!(clock.time(32_768, 16_384, RATE, at(1_024)), at(1_016));
+        // A new rate times from its first buffer
+        assert_eq!(clock.time(49_152, 8_192, 1_024_000, at(1_040)), at(1_032));
+        // As does a count that went backwards
+        assert_eq!(clock.time(0, 8_192, 1_024_000, at(1_050)), at(1_042));
+    }
+}
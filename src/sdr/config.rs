@@ -1,5 +1,9 @@
 /// RTL-SDR specific configuration constants and utilities
 
+use super::tuner::Tuner;
+use crate::types::DemodMode;
+use std::ops::RangeInclusive;
+
 /// Default RTL-SDR configuration values
 pub mod defaults {
     /// Default center frequency (144.390 MHz - APRS)
@@ -15,21 +19,6 @@ pub mod defaults {
     pub const PPM_ERROR: i32 = 0;
 }
 
-/// RTL-SDR hardware constraints
-pub mod constraints {
-    /// Minimum frequency supported by RTL-SDR (24 MHz)
-    pub const MIN_FREQUENCY: u32 = 24_000_000;
-
-    /// Maximum frequency supported by RTL-SDR (1.766 GHz)
-    pub const MAX_FREQUENCY: u32 = 1_766_000_000;
-
-    /// Minimum sample rate (225 kHz)
-    pub const MIN_SAMPLE_RATE: u32 = 225_000;
-
-    /// Maximum sample rate (3.2 MHz)
-    pub const MAX_SAMPLE_RATE: u32 = 3_200_000;
-}
-
 /// Common RTL-SDR sample rates that work well
 pub const COMMON_SAMPLE_RATES: &[u32] = &[
     225_000,    // 225 kHz
@@ -78,6 +67,56 @@ pub const FREQUENCY_PRESETS: &[FrequencyPreset] = &[
         frequency: 162_400_000,
         mode: "FM-WFM",
     },
+    FrequencyPreset {
+        name: "AIS (both channels)",
+        frequency: 162_000_000,
+        mode: "AIS",
+    },
+    FrequencyPreset {
+        name: "ISM 433.92 MHz sensors",
+        frequency: 433_920_000,
+        mode: "ISM",
+    },
+    FrequencyPreset {
+        name: "ACARS Primary (US/Canada)",
+        frequency: 131_550_000,
+        mode: "ACARS",
+    },
+    FrequencyPreset {
+        name: "ACARS Europe",
+        frequency: 131_725_000,
+        mode: "ACARS",
+    },
+    FrequencyPreset {
+        name: "ACARS US (130.025)",
+        frequency: 130_025_000,
+        mode: "ACARS",
+    },
+    FrequencyPreset {
+        name: "ACARS US (131.125)",
+        frequency: 131_125_000,
+        mode: "ACARS",
+    },
+    FrequencyPreset {
+        name: "NOAA 15 APT",
+        frequency: 137_620_000,
+        mode: "APT",
+    },
+    FrequencyPreset {
+        name: "NOAA 18 APT",
+        frequency: 137_912_500,
+        mode: "APT",
+    },
+    FrequencyPreset {
+        name: "NOAA 19 APT",
+        frequency: 137_100_000,
+        mode: "APT",
+    },
+    FrequencyPreset {
+        name: "Radiosonde RS41 (403.0)",
+        frequency: 403_000_000,
+        mode: "RS41",
+    },
     FrequencyPreset {
         name: "FM Broadcast",
         frequency: 98_500_000,
@@ -90,48 +129,296 @@ pub const FREQUENCY_PRESETS: &[FrequencyPreset] = &[
     },
 ];
 
-/// Validate frequency is within RTL-SDR range
-pub fn validate_frequency(freq: u32) -> anyhow::Result<()> {
-    if freq < constraints::MIN_FREQUENCY {
-        anyhow::bail!(
-            "Frequency {} Hz is below minimum {} Hz",
-            freq,
-            constraints::MIN_FREQUENCY
-        );
-    } else if freq > constraints::MAX_FREQUENCY {
-        anyhow::bail!(
-            "Frequency {} Hz is above maximum {} Hz",
-            freq,
-            constraints::MAX_FREQUENCY
-        );
-    }
-    Ok(())
+/// The sample rates a mode works at, for picking one automatically when the
+/// mode changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeRate {
+    pub mode: DemodMode,
+    /// Rates from `min` to `max` are left alone
+    pub min: u32,
+    pub max: u32,
+    /// Rate switched to from outside that range
+    pub rate: u32,
+    /// Tuner bandwidth in Hz, or 0 to follow the sample rate
+    pub bandwidth: u32,
+    /// Why, for the status line
+    pub reason: &'static str,
 }
 
-/// Validate sample rate is within RTL-SDR range
-pub fn validate_sample_rate(rate: u32) -> anyhow::Result<()> {
-    if rate < constraints::MIN_SAMPLE_RATE {
-        anyhow::bail!(
-            "Sample rate {} Hz is below minimum {} Hz",
-            rate,
-            constraints::MIN_SAMPLE_RATE
-        );
-    } else if rate > constraints::MAX_SAMPLE_RATE {
-        anyhow::bail!(
-            "Sample rate {} Hz is above maximum {} Hz",
-            rate,
-            constraints::MAX_SAMPLE_RATE
-        );
+/// Sample rates by mode; modes not listed keep whatever rate they are given
+pub const MODE_RATES: &[ModeRate] = &[
+    ModeRate {
+        mode: DemodMode::Adsb,
+        min: 2_400_000,
+        max: 2_400_000,
+        rate: 2_400_000,
+        bandwidth: 0,
+        reason: "Mode S needs 2.4 MS/s",
+    },
+    ModeRate {
+        mode: DemodMode::FmWide,
+        min: 1_800_000,
+        max: u32::MAX,
+        rate: 2_048_000,
+        bandwidth: 0,
+        reason: "broadcast FM needs at least 1.8 MS/s",
+    },
+    ModeRate {
+        mode: DemodMode::FmNarrow,
+        min: 1_024_000,
+        max: 1_024_000,
+        rate: 1_024_000,
+        bandwidth: 1_000_000,
+        reason: "1.024 MS/s is plenty and saves CPU",
+    },
+    ModeRate {
+        mode: DemodMode::Am,
+        min: 1_024_000,
+        max: 1_024_000,
+        rate: 1_024_000,
+        bandwidth: 1_000_000,
+        reason: "1.024 MS/s is plenty and saves CPU",
+    },
+    ModeRate {
+        mode: DemodMode::Usb,
+        min: 1_024_000,
+        max: 1_024_000,
+        rate: 1_024_000,
+        bandwidth: 1_000_000,
+        reason: "1.024 MS/s is plenty and saves CPU",
+    },
+    ModeRate {
+        mode: DemodMode::Lsb,
+        min: 1_024_000,
+        max: 1_024_000,
+        rate: 1_024_000,
+        bandwidth: 1_000_000,
+        reason: "1.024 MS/s is plenty and saves CPU",
+    },
+    ModeRate {
+        mode: DemodMode::Cw,
+        min: 1_024_000,
+        max: 1_024_000,
+        rate: 1_024_000,
+        bandwidth: 1_000_000,
+        reason: "1.024 MS/s is plenty and saves CPU",
+    },
+    ModeRate {
+        mode: DemodMode::Apt,
+        min: 1_024_000,
+        max: 1_024_000,
+        rate: 1_024_000,
+        bandwidth: 1_000_000,
+        reason: "1.024 MS/s is plenty and saves CPU",
+    },
+    ModeRate {
+        mode: DemodMode::Rs41,
+        min: 1_024_000,
+        max: 1_024_000,
+        rate: 1_024_000,
+        bandwidth: 1_000_000,
+        reason: "1.024 MS/s is plenty and saves CPU",
+    },
+];
+
+/// Gain steps of the R820T/R820T2, the usual RTL-SDR tuner, in tenths of dB
+pub const R820T_GAINS: [i32; 29] = [
+    0, 9, 14, 27, 37, 77, 87, 125, 144, 157, 166, 197, 207, 229, 254, 280, 297, 328, 338, 364,
+    372, 386, 402, 421, 434, 439, 445, 480, 496,
+];
+
+/// Spacing of the gains tried on receivers that don't list steps, in tenths
+/// of dB
+const GAIN_SWEEP_STEP: i32 = 30;
+
+/// Tuning and gain limits of a receiver, reported by its backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    /// Tunable center frequencies in Hz
+    pub frequency: RangeInclusive<u32>,
+    /// Supported sample rates in Hz
+    pub sample_rate: RangeInclusive<u32>,
+    /// Sample rates offered by the sample rate control, ascending
+    pub sample_rates: Vec<u32>,
+    /// Manual gain in tenths of dB
+    pub gain: RangeInclusive<i32>,
+    /// Gain steps the tuner offers in tenths of dB, ascending; empty where
+    /// any gain in the range can be set
+    pub gains: Vec<i32>,
+    /// The tuner chip, where the receiver says
+    pub tuner: Option<Tuner>,
+    /// Frequencies within `frequency` that can be tuned but not received
+    pub gaps: Vec<RangeInclusive<u32>>,
+}
+
+impl Capabilities {
+    /// RTL-SDR limits: 24 MHz to 1.766 GHz (R820T), 225 kHz to 3.2 MHz
+    pub fn rtl() -> Self {
+        Self {
+            frequency: 24_000_000..=1_766_000_000,
+            sample_rate: 225_000..=3_200_000,
+            sample_rates: COMMON_SAMPLE_RATES.to_vec(),
+            gain: 0..=500,
+            gains: R820T_GAINS.to_vec(),
+            tuner: None,
+            gaps: Vec::new(),
+        }
     }
 
-    // Warn if not a common sample rate
-    if !COMMON_SAMPLE_RATES.contains(&rate) {
-        log::warn!(
-            "Sample rate {} Hz is not a common RTL-SDR rate, may cause issues",
-            rate
-        );
+    /// RTL-SDR limits for `tuner`, or the R820T's where it isn't known
+    pub fn rtl_tuner(tuner: Option<Tuner>) -> Self {
+        let Some(tuner) = tuner else {
+            return Self::rtl();
+        };
+        let gains = tuner.gains();
+        Self {
+            frequency: tuner.frequency(),
+            gain: gains[0]..=gains[gains.len() - 1],
+            gains: gains.to_vec(),
+            tuner: Some(tuner),
+            gaps: tuner.gaps(),
+            ..Self::rtl()
+        }
+    }
+
+    /// The gap `freq` falls in, if any
+    pub fn gap_at(&self, freq: u32) -> Option<&RangeInclusive<u32>> {
+        self.gaps.iter().find(|gap| gap.contains(&freq))
+    }
+
+    /// What the status bar says while tuned to `freq`, if it's in a gap
+    pub fn gap_warning(&self, freq: u32) -> Option<String> {
+        let gap = self.gap_at(freq)?;
+        let tuner = self.tuner.map_or("the tuner", |tuner| tuner.name());
+        Some(format!(
+            "TUNER GAP — {} can't receive {:.0}-{:.0} MHz",
+            tuner,
+            *gap.start() as f64 / 1e6,
+            *gap.end() as f64 / 1e6
+        ))
+    }
+
+    /// The nearest tunable frequency
+    pub fn clamp_frequency(&self, freq: u32) -> u32 {
+        freq.clamp(*self.frequency.start(), *self.frequency.end())
+    }
+
+    /// Validate frequency is within the receiver's range
+    pub fn validate_frequency(&self, freq: u32) -> anyhow::Result<()> {
+        if freq < *self.frequency.start() {
+            anyhow::bail!(
+                "Frequency {} Hz is below minimum {} Hz",
+                freq,
+                self.frequency.start()
+            );
+        } else if freq > *self.frequency.end() {
+            anyhow::bail!(
+                "Frequency {} Hz is above maximum {} Hz",
+                freq,
+                self.frequency.end()
+            );
+        }
+        Ok(())
+    }
+
+    /// Validate sample rate is within the receiver's range
+    pub fn validate_sample_rate(&self, rate: u32) -> anyhow::Result<()> {
+        if rate < *self.sample_rate.start() {
+            anyhow::bail!(
+                "Sample rate {} Hz is below minimum {} Hz",
+                rate,
+                self.sample_rate.start()
+            );
+        } else if rate > *self.sample_rate.end() {
+            anyhow::bail!(
+                "Sample rate {} Hz is above maximum {} Hz",
+                rate,
+                self.sample_rate.end()
+            );
+        }
+
+        // Warn if not a rate the receiver lists
+        if !self.sample_rates.contains(&rate) {
+            log::warn!(
+                "Sample rate {} Hz is not a common rate for this receiver, may cause issues",
+                rate
+            );
+        }
+        Ok(())
+    }
+
+    /// The rate (with tuner bandwidth and reason) to switch to for `mode`
+    /// from `current`, or None if `current` suits it or nothing this
+    /// receiver offers does
+    pub fn rate_for_mode(&self, mode: DemodMode, current: u32) -> Option<ModeRate> {
+        let wanted = MODE_RATES.iter().find(|entry| entry.mode == mode)?;
+        let suits = |rate: &u32| (wanted.min..=wanted.max).contains(rate);
+        if suits(&current) {
+            return None;
+        }
+        let rate = if self.sample_rates.contains(&wanted.rate) {
+            wanted.rate
+        } else {
+            *self.sample_rates.iter().find(|rate| suits(rate))?
+        };
+        Some(ModeRate { rate, ..*wanted })
+    }
+
+    /// Gains to try in turn, ascending: the tuner's steps, or every 3 dB
+    /// across the range where it doesn't list any
+    pub fn gain_steps(&self) -> Vec<i32> {
+        if self.gains.is_empty() {
+            let (min, max) = (*self.gain.start(), *self.gain.end());
+            let mut steps: Vec<i32> = (min..max).step_by(GAIN_SWEEP_STEP as usize).collect();
+            steps.push(max);
+            steps
+        } else {
+            self.gains.clone()
+        }
+    }
+
+    /// The gain the tuner sets when asked for `gain` tenths of dB: its
+    /// nearest step, or `gain` itself where it lists none
+    pub fn nearest_gain(&self, gain: i32) -> i32 {
+        self.gains
+            .iter()
+            .copied()
+            .min_by_key(|step| step.abs_diff(gain))
+            .unwrap_or(gain)
+    }
+
+    /// Where the sample rate control starts from `rate`: its index in
+    /// `sample_rates`, or the closest one
+    pub fn sample_rate_index(&self, rate: u32) -> usize {
+        self.sample_rates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, r)| r.abs_diff(rate))
+            .map_or(0, |(i, _)| i)
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::rtl()
     }
-    Ok(())
+}
+
+/// NOAA Weather Radio channels (162.400-162.550 MHz, 25 kHz spacing)
+pub const WEATHER_CHANNELS: [u32; 7] = [
+    162_400_000,
+    162_425_000,
+    162_450_000,
+    162_475_000,
+    162_500_000,
+    162_525_000,
+    162_550_000,
+];
+
+/// Whether a frequency is tuned to a NOAA Weather Radio channel
+pub fn is_weather_channel(freq: u32) -> bool {
+    // Allow for a slightly off-channel tuning step
+    WEATHER_CHANNELS.iter().any(|&ch| freq.abs_diff(ch) <= 5_000)
 }
 
 #[cfg(test)]
@@ -140,16 +427,145 @@ mod tests {
 
     #[test]
     fn test_validate_frequency() {
-        assert!(validate_frequency(144_390_000).is_ok());
-        assert!(validate_frequency(1_090_000_000).is_ok());
-        assert!(validate_frequency(1_000_000).is_err());
-        assert!(validate_frequency(2_000_000_000).is_err());
+        let rtl = Capabilities::rtl();
+        assert!(rtl.validate_frequency(144_390_000).is_ok());
+        assert!(rtl.validate_frequency(1_090_000_000).is_ok());
+        assert!(rtl.validate_frequency(1_000_000).is_err());
+        assert!(rtl.validate_frequency(2_000_000_000).is_err());
+
+        // Another backend's range
+        let wide = Capabilities {
+            frequency: 1_000_000..=4_000_000_000,
+            ..Capabilities::rtl()
+        };
+        assert!(wide.validate_frequency(1_000_000).is_ok());
+        assert!(wide.validate_frequency(2_000_000_000).is_ok());
+        assert_eq!(wide.clamp_frequency(500_000), 1_000_000);
+        assert_eq!(rtl.clamp_frequency(2_000_000_000), 1_766_000_000);
+    }
+
+    #[test]
+    fn test_rtl_tuner() {
+        assert_eq!(Capabilities::rtl_tuner(None), Capabilities::rtl());
+        let r820t = Capabilities::rtl_tuner(Some(Tuner::R820t));
+        assert_eq!(r820t.frequency, Capabilities::rtl().frequency);
+        assert_eq!(r820t.gains, R820T_GAINS);
+
+        let e4000 = Capabilities::rtl_tuner(Some(Tuner::E4000));
+        assert_eq!(e4000.frequency, 52_000_000..=2_200_000_000);
+        assert_eq!(e4000.gain, -10..=420);
+        assert!(e4000.validate_frequency(2_000_000_000).is_ok());
+        assert!(e4000.validate_frequency(30_000_000).is_err());
+        // The sample rates are the RTL2832's, whatever the tuner
+        assert_eq!(e4000.sample_rates, COMMON_SAMPLE_RATES);
+    }
+
+    #[test]
+    fn test_gap_warning() {
+        let e4000 = Capabilities::rtl_tuner(Some(Tuner::E4000));
+        // Tunable, so not refused, but warned about
+        assert!(e4000.validate_frequency(1_200_000_000).is_ok());
+        assert_eq!(e4000.gap_at(1_200_000_000), Some(&(1_100_000_000..=1_250_000_000)));
+        assert_eq!(
+            e4000.gap_warning(1_200_000_000).as_deref(),
+            Some("TUNER GAP — E4000 can't receive 1100-1250 MHz")
+        );
+        assert!(e4000.gap_warning(1_100_000_000).is_some());
+        assert_eq!(e4000.gap_warning(1_090_000_000), None);
+        assert_eq!(e4000.gap_warning(1_250_000_001), None);
+
+        // The R820T covers the whole of its range
+        let r820t = Capabilities::rtl_tuner(Some(Tuner::R820t));
+        assert_eq!(r820t.gap_warning(1_200_000_000), None);
+        let fc2580 = Capabilities::rtl_tuner(Some(Tuner::Fc2580));
+        assert!(fc2580.gap_warning(400_000_000).is_some());
+        assert_eq!(fc2580.gap_warning(440_000_000), None);
     }
 
     #[test]
     fn test_validate_sample_rate() {
-        assert!(validate_sample_rate(2_048_000).is_ok());
-        assert!(validate_sample_rate(100_000).is_err());
-        assert!(validate_sample_rate(5_000_000).is_err());
+        let rtl = Capabilities::rtl();
+        assert!(rtl.validate_sample_rate(2_048_000).is_ok());
+        assert!(rtl.validate_sample_rate(100_000).is_err());
+        assert!(rtl.validate_sample_rate(5_000_000).is_err());
+    }
+
+    #[test]
+    fn test_sample_rate_index() {
+        let rtl = Capabilities::rtl();
+        assert_eq!(rtl.sample_rates[rtl.sample_rate_index(2_048_000)], 2_048_000);
+        // An unlisted rate starts from the closest listed one
+        assert_eq!(rtl.sample_rates[rtl.sample_rate_index(2_000_000)], 2_048_000);
+        assert_eq!(rtl.sample_rates[rtl.sample_rate_index(10_000_000)], 3_200_000);
+    }
+
+    #[test]
+    fn test_gain_steps() {
+        assert_eq!(Capabilities::rtl().gain_steps().len(), 29);
+        let continuous = Capabilities {
+            gain: 0..=100,
+            gains: Vec::new(),
+            ..Capabilities::rtl()
+        };
+        assert_eq!(continuous.gain_steps(), [0, 30, 60, 90, 100]);
+    }
+
+    #[test]
+    fn test_nearest_gain() {
+        let r820t = Capabilities::rtl();
+        assert_eq!(r820t.nearest_gain(300), 297);
+        assert_eq!(r820t.nearest_gain(297), 297);
+        assert_eq!(r820t.nearest_gain(0), 0);
+        let e4000 = Capabilities::rtl_tuner(Some(Tuner::E4000));
+        assert_eq!(e4000.nearest_gain(400), 420);
+        let continuous = Capabilities {
+            gains: Vec::new(),
+            ..Capabilities::rtl()
+        };
+        assert_eq!(continuous.nearest_gain(123), 123);
+    }
+
+    #[test]
+    fn test_rate_for_mode() {
+        let rtl = Capabilities::rtl();
+        let adsb = rtl.rate_for_mode(DemodMode::Adsb, 2_048_000).unwrap();
+        assert_eq!(adsb.rate, 2_400_000);
+        assert_eq!(adsb.bandwidth, 0);
+        assert_eq!(rtl.rate_for_mode(DemodMode::Adsb, 2_400_000), None);
+
+        // Wideband FM keeps any rate that holds it
+        assert_eq!(rtl.rate_for_mode(DemodMode::FmWide, 1_024_000).unwrap().rate, 2_048_000);
+        assert_eq!(rtl.rate_for_mode(DemodMode::FmWide, 2_560_000), None);
+
+        // Narrow modes come down to save CPU
+        for mode in [DemodMode::FmNarrow, DemodMode::Am, DemodMode::Usb, DemodMode::Cw] {
+            let narrow = rtl.rate_for_mode(mode, 2_400_000).unwrap();
+            assert_eq!((narrow.rate, narrow.bandwidth), (1_024_000, 1_000_000));
+        }
+
+        // Decoders with their own needs are left alone
+        assert_eq!(rtl.rate_for_mode(DemodMode::Ais, 1_024_000), None);
+        assert_eq!(rtl.rate_for_mode(DemodMode::Raw, 225_000), None);
+    }
+
+    #[test]
+    fn test_rate_for_mode_within_capabilities() {
+        // A receiver without 2.048 MS/s gets its first rate that works
+        let other = Capabilities {
+            sample_rates: vec![1_000_000, 2_000_000, 2_400_000, 3_000_000],
+            ..Capabilities::rtl()
+        };
+        assert_eq!(other.rate_for_mode(DemodMode::FmWide, 1_000_000).unwrap().rate, 2_000_000);
+        assert_eq!(other.rate_for_mode(DemodMode::Adsb, 1_000_000).unwrap().rate, 2_400_000);
+        // and leaves the rate be when none does
+        assert_eq!(other.rate_for_mode(DemodMode::Am, 2_000_000), None);
+    }
+
+    #[test]
+    fn test_is_weather_channel() {
+        assert!(is_weather_channel(162_550_000));
+        assert!(is_weather_channel(162_427_500));
+        assert!(!is_weather_channel(162_412_500));
+        assert!(!is_weather_channel(144_390_000));
     }
 }
@@ -1,10 +1,17 @@
+use super::librtlsdr::{self, Controller, Reader};
+use super::tuner::{self, Tuner};
+use super::{Capabilities, SampleSink, SdrSource};
 use anyhow::{anyhow, Result};
 use num_complex::Complex;
-use rtlsdr_mt::Controller;
+use std::thread;
 
 /// Wrapper around RTL-SDR device for easier management
 pub struct RtlSdrDevice {
+    index: usize,
     controller: Controller,
+    /// Taken by the acquisition thread when streaming starts
+    reader: Option<Reader>,
+    capabilities: Capabilities,
     sample_rate: u32,
     center_freq: u32,
 }
@@ -14,14 +21,32 @@ impl RtlSdrDevice {
     pub fn open(device_index: usize) -> Result<Self> {
         log::info!("Opening RTL-SDR device {}", device_index);
 
-        // Open the device - rtlsdr_mt::open returns (Controller, Reader)
-        let (controller, _reader) = rtlsdr_mt::open(device_index as u32)
-            .map_err(|_| anyhow!("Failed to open RTL-SDR device {}", device_index))?;
+        let (controller, reader) = librtlsdr::open(device_index as u32).map_err(|_| OpenError {
+            index: device_index,
+            devices: list_devices(),
+        })?;
 
         log::info!("RTL-SDR device opened successfully");
+        let tuner = Tuner::from_code(controller.tuner_type());
+        match tuner {
+            Some(tuner) => log::info!("RTL-SDR device {} has an {} tuner", device_index, tuner),
+            None => log::warn!("Can't tell RTL-SDR device {}'s tuner", device_index),
+        }
+
+        // The tuner knows its own gain steps
+        let mut capabilities = Capabilities::rtl_tuner(tuner);
+        let mut gains = controller.tuner_gains();
+        if let (Some(&min), Some(&max)) = (gains.iter().min(), gains.iter().max()) {
+            capabilities.gain = min..=max;
+            gains.sort_unstable();
+            capabilities.gains = gains;
+        }
 
         Ok(Self {
+            index: device_index,
             controller,
+            reader: Some(reader),
+            capabilities,
             sample_rate: 0,
             center_freq: 0,
         })
@@ -34,7 +59,7 @@ impl RtlSdrDevice {
 
     /// Set the center frequency in Hz
     pub fn set_center_freq(&mut self, freq: u32) -> Result<()> {
-        super::config::validate_frequency(freq)?;
+        self.capabilities.validate_frequency(freq)?;
 
         self.controller
             .set_center_freq(freq)
@@ -53,7 +78,7 @@ impl RtlSdrDevice {
 
     /// Set the sample rate in Hz
     pub fn set_sample_rate(&mut self, rate: u32) -> Result<()> {
-        super::config::validate_sample_rate(rate)?;
+        self.capabilities.validate_sample_rate(rate)?;
 
         self.controller
             .set_sample_rate(rate)
@@ -65,25 +90,30 @@ impl RtlSdrDevice {
         Ok(())
     }
 
+    /// Set the tuner IF bandwidth in Hz, 0 for automatic
+    pub fn set_bandwidth(&mut self, bandwidth: u32) -> Result<()> {
+        self.controller
+            .set_bandwidth(bandwidth)
+            .map_err(|_| anyhow!("Failed to set tuner bandwidth to {} Hz", bandwidth))?;
+
+        log::info!("Set tuner bandwidth to {} Hz", bandwidth);
+
+        Ok(())
+    }
+
     /// Get the current sample rate
     pub fn get_sample_rate(&self) -> u32 {
         self.sample_rate
     }
 
     /// Set tuner gain in tenths of dB (e.g., 421 = 42.1 dB)
-    /// Use -1 for automatic gain
+    /// Use -1 for tuner automatic gain
     pub fn set_tuner_gain(&mut self, gain: i32) -> Result<()> {
         if gain == -1 {
-            // Enable automatic gain
-            self.controller
-                .enable_agc()
-                .map_err(|_| anyhow!("Failed to enable automatic gain"))?;
-            log::info!("Enabled automatic gain control");
+            set_tuner_agc(&mut self.controller, true)?;
         } else {
-            // Disable AGC and set manual gain
-            self.controller
-                .disable_agc()
-                .map_err(|_| anyhow!("Failed to disable automatic gain"))?;
+            // Tuner must be in manual mode before a fixed gain is accepted
+            set_tuner_agc(&mut self.controller, false)?;
             self.controller
                 .set_tuner_gain(gain)
                 .map_err(|_| anyhow!("Failed to set tuner gain to {} ({}dB)", gain, gain / 10))?;
@@ -93,6 +123,11 @@ impl RtlSdrDevice {
         Ok(())
     }
 
+    /// Enable or disable the RTL2832 digital AGC
+    pub fn set_rtl_agc(&mut self, enabled: bool) -> Result<()> {
+        set_rtl_agc(&mut self.controller, enabled)
+    }
+
     /// Set PPM (parts per million) frequency correction
     pub fn set_ppm(&mut self, ppm: i32) -> Result<()> {
         self.controller
@@ -116,6 +151,90 @@ impl RtlSdrDevice {
     }
 }
 
+impl SdrSource for RtlSdrDevice {
+    fn describe(&self) -> String {
+        format!("RTL-SDR #{}", self.index)
+    }
+
+    fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    fn set_frequency(&mut self, freq: u32) -> Result<()> {
+        self.set_center_freq(freq)
+    }
+
+    fn set_sample_rate(&mut self, rate: u32) -> Result<()> {
+        RtlSdrDevice::set_sample_rate(self, rate)
+    }
+
+    fn set_bandwidth(&mut self, bandwidth: u32) -> Result<()> {
+        RtlSdrDevice::set_bandwidth(self, bandwidth)
+    }
+
+    fn set_gain(&mut self, gain: Option<i32>) -> Result<()> {
+        self.set_tuner_gain(gain.unwrap_or(-1))
+    }
+
+    fn set_rtl_agc(&mut self, enabled: bool) -> Result<()> {
+        RtlSdrDevice::set_rtl_agc(self, enabled)
+    }
+
+    fn set_ppm(&mut self, ppm: i32) -> Result<()> {
+        RtlSdrDevice::set_ppm(self, ppm)
+    }
+
+    fn stop(&mut self) {
+        // read_async returns once cancelled, ending the acquisition thread
+        self.controller.cancel_async_read();
+    }
+
+    fn start(&mut self, sink: SampleSink) -> Result<thread::JoinHandle<()>> {
+        let mut reader = self
+            .reader
+            .take()
+            .ok_or_else(|| anyhow!("RTL-SDR device {} is already streaming", self.index))?;
+
+        Ok(thread::spawn(move || {
+            log::info!("SDR acquisition thread started");
+
+            // Buffer params: 32 buffers of 16384 samples each (must be multiple of 512)
+            let result = reader.read_async(32, 16384, |bytes| sink.push_u8(bytes));
+            if let Err(e) = result {
+                log::error!("SDR read_async error: {:?}", e);
+            }
+
+            log::info!("SDR acquisition thread stopped");
+        }))
+    }
+}
+
+/// Switch the tuner between automatic and manual gain mode
+///
+/// This is librtlsdr's `rtlsdr_set_tuner_gain_mode` and is independent of the
+/// RTL2832 digital AGC set by [`set_rtl_agc`].
+pub fn set_tuner_agc(controller: &mut Controller, auto: bool) -> Result<()> {
+    controller
+        .set_tuner_gain_mode(!auto)
+        .map_err(|e| anyhow!("Failed to set tuner gain mode: {:?}", e))?;
+    log::info!("Tuner gain mode: {}", if auto { "auto" } else { "manual" });
+    Ok(())
+}
+
+/// Enable or disable the RTL2832 digital AGC
+///
+/// This is librtlsdr's `rtlsdr_set_agc_mode`, which acts on the demodulator
+/// rather than the tuner. Leaving it on tends to make the noise floor pump,
+/// so it defaults to off.
+pub fn set_rtl_agc(controller: &mut Controller, enabled: bool) -> Result<()> {
+    controller.set_agc_mode(enabled).map_err(|e| {
+        let action = if enabled { "enable" } else { "disable" };
+        anyhow!("Failed to {} RTL AGC: {:?}", action, e)
+    })?;
+    log::info!("RTL AGC {}", if enabled { "enabled" } else { "disabled" });
+    Ok(())
+}
+
 /// Device information
 #[derive(Debug, Clone)]
 pub struct DeviceInfo {
@@ -135,26 +254,87 @@ impl std::fmt::Display for DeviceInfo {
 }
 
 /// Get the number of available RTL-SDR devices
-/// Note: In rtlsdr_mt v2, we attempt to open devices to count them
 pub fn get_device_count() -> usize {
-    // Try opening devices 0-10 to see how many exist
-    for i in 0..10u32 {
-        if rtlsdr_mt::open(i).is_err() {
-            return i as usize;
-        }
-    }
-    10 // Maximum we checked
+    rtlsdr_mt::devices().count()
 }
 
-/// List all available RTL-SDR devices
+/// List all available RTL-SDR devices, by index and USB product name
+///
+/// This only enumerates, so devices in use by another program are listed too.
 pub fn list_devices() -> Vec<String> {
-    let count = get_device_count();
-    (0..count)
-        .map(|i| format!("RTL-SDR Device #{}", i))
+    rtlsdr_mt::devices()
+        .enumerate()
+        .map(|(i, name)| format!("#{}: {}", i, name.to_string_lossy()))
         .collect()
 }
 
-/// Convert raw IQ samples (u8) to Complex<f32> and normalize to [-1.0, 1.0]
+/// [`list_devices`] with each one's tuner, for `--list-devices`
+///
+/// Each device is opened briefly to ask, so one in use by another program
+/// is listed without it.
+pub fn list_devices_with_tuners() -> Vec<String> {
+    list_devices()
+        .into_iter()
+        .enumerate()
+        .map(|(index, device)| match tuner::probe(index) {
+            Some(tuner) => format!("{}, {} tuner", device, tuner),
+            None => format!("{}, tuner unknown (in use?)", device),
+        })
+        .collect()
+}
+
+/// What usually frees a dongle that is there but can't be opened
+#[cfg(windows)]
+const BUSY_HINT: &str = "close other programs using it (rtl_tcp, dump1090, SDR#...), or \
+                         install the WinUSB driver for it with Zadig";
+#[cfg(not(windows))]
+const BUSY_HINT: &str = "close other programs using it (rtl_tcp, dump1090, gqrx...), check USB \
+                         permissions, or unload the dvb_usb_rtl28xxu kernel module";
+
+/// An RTL-SDR that couldn't be opened, and what is plugged in instead
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenError {
+    pub index: usize,
+    /// From [`list_devices`] at the time
+    pub devices: Vec<String>,
+}
+
+impl OpenError {
+    /// Whether the device is there but couldn't be opened, usually because
+    /// another program has it
+    pub fn is_busy(&self) -> bool {
+        self.index < self.devices.len()
+    }
+}
+
+impl std::fmt::Display for OpenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_busy() {
+            writeln!(f, "RTL-SDR device {} is busy or inaccessible", self.index)?;
+        } else {
+            writeln!(f, "RTL-SDR device {} not found", self.index)?;
+        }
+        if self.devices.is_empty() {
+            writeln!(f, "No RTL-SDR devices are connected")?;
+        } else {
+            writeln!(f, "Connected devices:")?;
+            for device in &self.devices {
+                writeln!(f, "  {}", device)?;
+            }
+        }
+        if self.is_busy() {
+            write!(f, "Hint: {}", BUSY_HINT)
+        } else if self.devices.is_empty() {
+            write!(f, "Hint: plug the dongle in, or use --wait-for-device to wait for it")
+        } else {
+            write!(f, "Hint: choose one of the devices above with --device")
+        }
+    }
+}
+
+impl std::error::Error for OpenError {}
+
+/// Convert raw IQ samples (u8) to `Complex<f32>` and normalize to [-1.0, 1.0]
 pub fn samples_u8_to_complex(samples: &[u8]) -> Vec<Complex<f32>> {
     samples
         .chunks_exact(2)
@@ -168,10 +348,118 @@ pub fn samples_u8_to_complex(samples: &[u8]) -> Vec<Complex<f32>> {
         .collect()
 }
 
+/// Components at or beyond this magnitude are at the ADC's limits
+const CLIP_LEVEL: f32 = 0.99;
+
+/// Share of samples with I or Q at full scale
+pub fn clipped_fraction(samples: &[Complex<f32>]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let clipped = samples
+        .iter()
+        .filter(|s| s.re.abs() >= CLIP_LEVEL || s.im.abs() >= CLIP_LEVEL)
+        .count();
+    clipped as f32 / samples.len() as f32
+}
+
+/// Share of raw 8-bit IQ pairs with I or Q at 0 or 255, counted before
+/// conversion so it costs a compare per byte
+pub fn clipped_fraction_u8(bytes: &[u8]) -> f32 {
+    let pairs = bytes.len() / 2;
+    if pairs == 0 {
+        return 0.0;
+    }
+    let clipped = bytes
+        .chunks_exact(2)
+        .filter(|iq| matches!(iq[0], 0 | 255) || matches!(iq[1], 0 | 255))
+        .count();
+    clipped as f32 / pairs as f32
+}
+
+/// Convert IQ samples back to unsigned 8-bit pairs, as the RTL-SDR
+/// produces them, so every backend records the same format
+pub fn samples_complex_to_u8(samples: &[Complex<f32>]) -> Vec<u8> {
+    let to_u8 = |v: f32| (v * 128.0 + 127.5).round().clamp(0.0, 255.0) as u8;
+    samples.iter().flat_map(|s| [to_u8(s.re), to_u8(s.im)]).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_clipped_fraction() {
+        let mut samples = vec![Complex::new(0.3, -0.2); 98];
+        samples.push(Complex::new(0.996, 0.1));
+        samples.push(Complex::new(0.2, -0.996));
+        assert!((clipped_fraction(&samples) - 0.02).abs() < 1e-6);
+        assert_eq!(clipped_fraction(&[]), 0.0);
+        // The 8-bit extremes count
+        assert_eq!(clipped_fraction(&samples_u8_to_complex(&[0, 128, 255, 128])), 1.0);
+    }
+
+    #[test]
+    fn test_clipped_fraction_u8() {
+        let buffer = |clipped: usize, total: usize| {
+            let mut bytes = [140u8, 110].repeat(total - clipped);
+            // Alternate which component hits which rail
+            for n in 0..clipped {
+                bytes.extend_from_slice(match n % 4 {
+                    0 => &[255, 128],
+                    1 => &[128, 0],
+                    2 => &[0, 255],
+                    _ => &[128, 255],
+                });
+            }
+            bytes
+        };
+        assert_eq!(clipped_fraction_u8(&buffer(0, 1000)), 0.0);
+        assert!((clipped_fraction_u8(&buffer(1, 1000)) - 0.001).abs() < 1e-6);
+        assert!((clipped_fraction_u8(&buffer(50, 1000)) - 0.05).abs() < 1e-6);
+        assert!((clipped_fraction_u8(&buffer(500, 1000)) - 0.5).abs() < 1e-6);
+        assert_eq!(clipped_fraction_u8(&buffer(1000, 1000)), 1.0);
+        // Near the rails isn't clipping
+        assert_eq!(clipped_fraction_u8(&[1, 254, 254, 1]), 0.0);
+        // Empty and odd trailing bytes
+        assert_eq!(clipped_fraction_u8(&[]), 0.0);
+        assert_eq!(clipped_fraction_u8(&[255]), 0.0);
+        // Agrees with the count on converted samples
+        let bytes = buffer(30, 400);
+        assert_eq!(
+            clipped_fraction_u8(&bytes),
+            clipped_fraction(&samples_u8_to_complex(&bytes))
+        );
+    }
+
+    #[test]
+    fn test_open_error_tells_missing_from_busy() {
+        let missing = OpenError {
+            index: 1,
+            devices: vec!["#0: Generic RTL2832U OEM".to_string()],
+        };
+        assert!(!missing.is_busy());
+        let message = missing.to_string();
+        assert!(message.starts_with("RTL-SDR device 1 not found\n"));
+        assert!(message.contains("  #0: Generic RTL2832U OEM\n"));
+        assert!(message.contains("--device"));
+
+        let busy = OpenError {
+            index: 0,
+            ..missing
+        };
+        assert!(busy.is_busy());
+        assert!(busy.to_string().starts_with("RTL-SDR device 0 is busy"));
+
+        let none = OpenError {
+            index: 0,
+            devices: Vec::new(),
+        };
+        assert!(!none.is_busy());
+        assert!(none.to_string().contains("No RTL-SDR devices are connected"));
+        assert!(none.to_string().contains("--wait-for-device"));
+    }
+
     #[test]
     fn test_samples_u8_to_complex() {
         // Test center value (127)
@@ -193,4 +481,14 @@ mod tests {
         assert!(complex[0].re < -0.99);
         assert!(complex[0].im < -0.99);
     }
+
+    #[test]
+    fn test_samples_complex_to_u8_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(samples_complex_to_u8(&samples_u8_to_complex(&bytes)), bytes);
+
+        // Out of range values saturate
+        let loud = [Complex::new(2.0, -2.0)];
+        assert_eq!(samples_complex_to_u8(&loud), [255, 0]);
+    }
 }
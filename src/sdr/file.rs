@@ -0,0 +1,421 @@
+//! IQ file playback, in place of a receiver
+//!
+//! Plays back a recording as if it were arriving live: paced at its sample
+//! rate, or 2×, 5× or as fast as the DSP keeps up. The format, sample rate
+//! and center frequency come from the SigMF metadata for a `.sigmf-data`
+//! file, else from the `.json` sidecar written with it, else from the file
+//! name the recorder gave it and its extension (`.iq` u8, `.cs16`, `.cf32`).
+//!
+//! Pause and seeks are asked for through [`PlaybackState`] and carried out
+//! here between blocks. Seeks land on whole samples, and the first buffer
+//! after each is marked so the DSP thread resets its filters rather than
+//! run them across the jump.
+
+use super::{Capabilities, SampleSink, SdrSource};
+use crate::recorder::{parse_recording_file_name, CaptureMetadata, SampleFormat, SigMfMeta};
+use crate::state::{PlaybackState, SharedState};
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Complex samples read at a time, as a dongle delivers them
+const BLOCK_SAMPLES: u64 = 16384;
+/// Sample buffers left waiting for the DSP before unpaced playback waits
+const MAX_BACKLOG: usize = 8;
+/// How often a paused or finished playback looks for something to do
+const IDLE_POLL: Duration = Duration::from_millis(50);
+
+/// An IQ recording and what is known about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackFile {
+    pub path: PathBuf,
+    pub format: SampleFormat,
+    pub sample_rate: u32,
+    /// Center frequency in Hz
+    pub frequency: u32,
+    /// Bytes of samples in the file
+    pub data_len: u64,
+}
+
+impl PlaybackFile {
+    /// Find out the format, rate and frequency of the recording at `path`
+    pub fn probe(path: &Path) -> Result<Self> {
+        let data_len = std::fs::metadata(path)
+            .with_context(|| format!("Can't play {}", path.display()))?
+            .len();
+        let (format, sample_rate, frequency) = Self::describe(path)?;
+        if sample_rate == 0 {
+            anyhow::bail!("{} gives no sample rate", path.display());
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            sample_rate,
+            frequency,
+            data_len,
+        })
+    }
+
+    /// Format, sample rate and frequency, from the best source there is
+    fn describe(path: &Path) -> Result<(SampleFormat, u32, u32)> {
+        let read_json = |path: &Path| -> Option<serde_json::Value> {
+            serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+        };
+        let format = |datatype: &serde_json::Value| {
+            let datatype = datatype.as_str().unwrap_or_default();
+            SampleFormat::from_sigmf_datatype(datatype)
+                .ok_or_else(|| anyhow!("Can't play {} samples", datatype))
+        };
+
+        if path.extension().is_some_and(|ext| ext == "sigmf-data") {
+            let meta_path = SigMfMeta::meta_path(path);
+            let meta = read_json(&meta_path)
+                .ok_or_else(|| anyhow!("Can't read {}", meta_path.display()))?;
+            let rate = meta["global"]["core:sample_rate"]
+                .as_f64()
+                .unwrap_or_default();
+            let frequency = meta["captures"][0]["core:frequency"]
+                .as_f64()
+                .unwrap_or_default();
+            return Ok((
+                format(&meta["global"]["core:datatype"])?,
+                rate as u32,
+                frequency as u32,
+            ));
+        }
+
+        if let Some(sidecar) = read_json(&CaptureMetadata::sidecar_path(path)) {
+            let field = |name: &str| sidecar[name].as_u64().unwrap_or_default() as u32;
+            return Ok((
+                format(&sidecar["sample_format"])?,
+                field("sample_rate_hz"),
+                field("center_frequency_hz"),
+            ));
+        }
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let format = match extension.to_ascii_lowercase().as_str() {
+            "iq" | "cu8" | "bin" => SampleFormat::Cu8,
+            other => other.parse().map_err(anyhow::Error::msg)?,
+        };
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let (frequency, sample_rate) = parse_recording_file_name(&name).ok_or_else(|| {
+            anyhow!(
+                "Can't tell the sample rate of {}: keep its .json sidecar, or its name \
+                 as recorded (e.g. ..._162.550MHz_NFM_2048k.iq)",
+                path.display()
+            )
+        })?;
+        Ok((format, sample_rate, frequency))
+    }
+
+    /// Whole complex samples in the file
+    pub fn total_samples(&self) -> u64 {
+        self.data_len / self.format.bytes_per_sample()
+    }
+
+    /// Where sample `sample` starts, clamped to the end of the file
+    pub fn byte_offset(&self, sample: u64) -> u64 {
+        sample.min(self.total_samples()) * self.format.bytes_per_sample()
+    }
+
+    /// Playback state for the status bar and controls
+    pub fn playback_state(&self) -> PlaybackState {
+        let name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        PlaybackState::new(
+            name.unwrap_or_default(),
+            self.sample_rate,
+            self.total_samples(),
+        )
+    }
+}
+
+/// An IQ recording played as a receiver
+pub struct FileSource {
+    file: PlaybackFile,
+    state: SharedState,
+    capabilities: Capabilities,
+}
+
+impl FileSource {
+    /// Play `file`, taking pause, speed and seeks from `state.playback`
+    pub fn open(file: PlaybackFile, state: SharedState) -> Self {
+        // The recording can't be retuned or resampled
+        let capabilities = Capabilities {
+            frequency: file.frequency..=file.frequency,
+            sample_rate: file.sample_rate..=file.sample_rate,
+            sample_rates: vec![file.sample_rate],
+            gain: 0..=0,
+            gains: Vec::new(),
+            tuner: None,
+            gaps: Vec::new(),
+        };
+        Self {
+            file,
+            state,
+            capabilities,
+        }
+    }
+}
+
+impl SdrSource for FileSource {
+    fn describe(&self) -> String {
+        format!("IQ file {}", self.file.path.display())
+    }
+
+    fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    fn set_frequency(&mut self, freq: u32) -> Result<()> {
+        self.capabilities.validate_frequency(freq)
+    }
+
+    fn set_sample_rate(&mut self, rate: u32) -> Result<()> {
+        self.capabilities.validate_sample_rate(rate)
+    }
+
+    fn set_gain(&mut self, _gain: Option<i32>) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_ppm(&mut self, _ppm: i32) -> Result<()> {
+        Ok(())
+    }
+
+    fn start(&mut self, sink: SampleSink) -> Result<thread::JoinHandle<()>> {
+        let mut reader = File::open(&self.file.path)
+            .with_context(|| format!("Can't play {}", self.file.path.display()))?;
+        let file = self.file.clone();
+        let state = self.state.clone();
+
+        Ok(thread::spawn(move || {
+            log::info!("Playing {}", file.path.display());
+            let block_len = (BLOCK_SAMPLES * file.format.bytes_per_sample()) as usize;
+            let mut block = vec![0u8; block_len];
+            // When the next block is due, at the current speed
+            let mut due = Instant::now();
+            let mut finished = false;
+
+            while !sink.is_shutdown() {
+                let (paused, speed, seek) = {
+                    let mut state = state.write();
+                    let Some(playback) = state.playback.as_mut() else {
+                        break;
+                    };
+                    (playback.paused, playback.speed, playback.seek.take())
+                };
+
+                if let Some(target) = seek {
+                    match reader.seek(SeekFrom::Start(file.byte_offset(target))) {
+                        Ok(_) => {
+                            sink.mark_seek();
+                            if let Some(playback) = state.write().playback.as_mut() {
+                                playback.seeked(target.min(file.total_samples()));
+                            }
+                            finished = false;
+                            due = Instant::now();
+                        }
+                        Err(e) => log::warn!("Seek in {} failed: {}", file.path.display(), e),
+                    }
+                }
+                if paused || finished {
+                    thread::sleep(IDLE_POLL);
+                    due = Instant::now();
+                    continue;
+                }
+
+                let len = match read_block(&mut reader, &mut block) {
+                    Ok(len) => len - len % file.format.bytes_per_sample() as usize,
+                    Err(e) => {
+                        log::error!("Reading {} failed: {}", file.path.display(), e);
+                        0
+                    }
+                };
+                if len == 0 {
+                    log::info!("Playback of {} finished", file.path.display());
+                    let mut state = state.write();
+                    state.ui.status_message = "Playback finished".to_string();
+                    if let Some(playback) = state.playback.as_mut() {
+                        playback.paused = true;
+                    }
+                    finished = true;
+                    continue;
+                }
+
+                let bytes = &block[..len];
+                match file.format {
+                    SampleFormat::Cu8 => sink.push_u8(bytes),
+                    format => sink.push(format.decode(bytes)),
+                }
+                let samples = len as u64 / file.format.bytes_per_sample();
+                if let Some(playback) = state.write().playback.as_mut() {
+                    playback.position += samples;
+                }
+
+                // Pace to the speed, or to what the DSP keeps up with
+                match speed.factor() {
+                    Some(factor) => {
+                        let secs = samples as f64 / file.sample_rate.max(1) as f64 / factor;
+                        due += Duration::from_secs_f64(secs);
+                        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                            thread::sleep(wait);
+                        }
+                    }
+                    None => {
+                        while sink.backlog() >= MAX_BACKLOG && !sink.is_shutdown() {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                        due = Instant::now();
+                    }
+                }
+            }
+
+            log::info!("File playback thread stopped");
+        }))
+    }
+}
+
+/// Fill `block` from `reader` as far as the file goes; returns the bytes read
+fn read_block(reader: &mut impl Read, block: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match reader.read(&mut block[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [SampleFormat; 4] = [
+        SampleFormat::Cu8,
+        SampleFormat::Ci8,
+        SampleFormat::Ci16,
+        SampleFormat::Cf32,
+    ];
+
+    fn playback_file(format: SampleFormat, data_len: u64) -> PlaybackFile {
+        PlaybackFile {
+            path: PathBuf::from("capture"),
+            format,
+            sample_rate: 2_048_000,
+            frequency: 162_550_000,
+            data_len,
+        }
+    }
+
+    #[test]
+    fn test_offsets_are_sample_aligned() {
+        for format in FORMATS {
+            let size = format.bytes_per_sample();
+            // Ten seconds and half a sample
+            let file = playback_file(format, 2_048_000 * 10 * size + size / 2);
+            assert_eq!(file.total_samples(), 20_480_000, "{:?}", format);
+
+            // Ten seconds in lands on the start of a sample
+            let mut playback = file.playback_state();
+            let target = playback.seek_by(10);
+            assert_eq!(file.byte_offset(target), 20_480_000 * size);
+            let target = playback.seek_by(-3);
+            assert_eq!(file.byte_offset(target) % size, 0);
+            assert_eq!(file.byte_offset(target), 7 * 2_048_000 * size);
+
+            // Past the end stops before the partial sample
+            assert_eq!(file.byte_offset(u64::MAX), 20_480_000 * size);
+            assert_eq!(file.byte_offset(playback.seek_tenths(5)), 10_240_000 * size);
+        }
+    }
+
+    #[test]
+    fn test_probe() {
+        let dir = std::env::temp_dir().join(format!("file_source_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // By name, as the recorder names files
+        let path = dir.join("20240601_142503_162.550MHz_NFM_2048k.cs16");
+        std::fs::write(&path, [0u8; 40]).unwrap();
+        let file = PlaybackFile::probe(&path).unwrap();
+        assert_eq!(file.format, SampleFormat::Ci16);
+        assert_eq!((file.frequency, file.sample_rate), (162_550_000, 2_048_000));
+        assert_eq!(file.total_samples(), 10);
+
+        // The sidecar wins over the name
+        std::fs::write(
+            CaptureMetadata::sidecar_path(&path),
+            r#"{"center_frequency_hz": 144390000, "sample_rate_hz": 1024000,
+                "sample_format": "cf32_le"}"#,
+        )
+        .unwrap();
+        let file = PlaybackFile::probe(&path).unwrap();
+        assert_eq!(file.format, SampleFormat::Cf32);
+        assert_eq!((file.frequency, file.sample_rate), (144_390_000, 1_024_000));
+        assert_eq!(file.total_samples(), 5);
+
+        // SigMF
+        let path = dir.join("capture.sigmf-data");
+        std::fs::write(&path, [0u8; 40]).unwrap();
+        SigMfMeta::new("ci8", 2_400_000, 1_090_000_000, None)
+            .write(&SigMfMeta::meta_path(&path))
+            .unwrap();
+        let file = PlaybackFile::probe(&path).unwrap();
+        assert_eq!(file.format, SampleFormat::Ci8);
+        assert_eq!(
+            (file.frequency, file.sample_rate),
+            (1_090_000_000, 2_400_000)
+        );
+
+        // Nothing to go on
+        let path = dir.join("mystery.iq");
+        std::fs::write(&path, [0u8; 4]).unwrap();
+        assert!(PlaybackFile::probe(&path).is_err());
+        assert!(PlaybackFile::probe(&dir.join("missing.iq")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_after_seek() {
+        // Every sample of a cf32 file is its own index, so a read after a
+        // seek shows where it landed
+        let dir = std::env::temp_dir().join(format!("file_seek_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("20240601_142503_100.000MHz_NFM_1k.cf32");
+        let bytes: Vec<u8> = (0..1000)
+            .flat_map(|i| [i as f32, -(i as f32)])
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        std::fs::write(&path, bytes).unwrap();
+
+        let file = PlaybackFile::probe(&path).unwrap();
+        let mut playback = file.playback_state();
+        let target = playback.seek_tenths(3);
+        let mut reader = File::open(&path).unwrap();
+        reader
+            .seek(SeekFrom::Start(file.byte_offset(target)))
+            .unwrap();
+        let mut block = vec![0u8; 16];
+        assert_eq!(read_block(&mut reader, &mut block).unwrap(), 16);
+        let samples = file.format.decode(&block);
+        assert_eq!(samples[0].re, 300.0);
+        assert_eq!(samples[1].im, -301.0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,365 @@
+//! Tuner gain by frequency range
+//!
+//! A gain that suits 162 MHz can overload the front end at 1090 MHz behind
+//! an LNA. With gain profiles on, each retune of the center frequency is
+//! looked up in a table of ranges, and crossing into another range sets
+//! the gain that range wants. A gain set by hand since the last crossing is
+//! left alone, once; the next crossing applies its range's gain again.
+//!
+//! A range is only left once the frequency is more than the hysteresis
+//! past its edge, so a scan stepping back and forth over a boundary
+//! doesn't flip the gain at every step. Outside every range the gain stays
+//! as it was.
+
+use crate::types::config::{GainProfilesConfig, GainSetting};
+use anyhow::{bail, Result};
+
+/// Highest gain a range can ask for, in tenths of dB
+const MAX_GAIN: i32 = 600;
+/// Highest a range can reach, in MHz: what a u32 of Hz holds
+const MAX_MHZ: f64 = u32::MAX as f64 / 1e6;
+
+/// A frequency range and the gain it wants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GainRange {
+    /// Hz, inclusive
+    pub start: u32,
+    pub end: u32,
+    /// Tenths of dB, None for auto gain
+    pub gain: Option<i32>,
+}
+
+impl GainRange {
+    fn contains(&self, frequency: u32, margin: u32) -> bool {
+        frequency >= self.start.saturating_sub(margin)
+            && frequency <= self.end.saturating_add(margin)
+    }
+
+    /// e.g. "108.000-174.000 MHz: 40.2 dB"
+    pub fn describe(&self) -> String {
+        let gain = match self.gain {
+            Some(gain) => format!("{}.{} dB", gain / 10, gain % 10),
+            None => "auto gain".to_string(),
+        };
+        format!(
+            "{:.3}-{:.3} MHz: {}",
+            self.start as f64 / 1e6,
+            self.end as f64 / 1e6,
+            gain
+        )
+    }
+}
+
+/// What a retune did to the gain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crossing {
+    /// Into `range`, whose gain is to be set
+    Apply(GainRange),
+    /// Into `range`, keeping the gain set by hand
+    Kept(GainRange),
+}
+
+/// The ranges, the one the receiver is in and whether the gain has been
+/// set by hand since it got there
+#[derive(Debug, Clone, PartialEq)]
+pub struct GainProfiles {
+    ranges: Vec<GainRange>,
+    /// Hz past a range's edge before it is left
+    hysteresis: u32,
+    active: Option<usize>,
+    overridden: bool,
+}
+
+impl GainProfiles {
+    /// Profiles over `ranges`, the receiver starting out on `frequency`
+    /// with the gain it was given
+    pub fn new(ranges: Vec<GainRange>, hysteresis: u32, frequency: u32) -> Self {
+        let mut profiles = Self {
+            ranges,
+            hysteresis,
+            active: None,
+            overridden: false,
+        };
+        profiles.active = profiles.lookup(frequency);
+        profiles
+    }
+
+    /// The profiles in the config file, checked; None when they are off
+    pub fn from_config(config: &GainProfilesConfig, frequency: u32) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        if config.hysteresis.is_nan() || config.hysteresis < 0.0 {
+            bail!(
+                "gain_profiles: hysteresis {} is negative",
+                config.hysteresis
+            );
+        }
+        let mut ranges: Vec<GainRange> = Vec::new();
+        for (index, range) in config.ranges.iter().enumerate() {
+            let place = format!(
+                "gain_profiles: range {} ({}-{} MHz)",
+                index + 1,
+                range.start,
+                range.end
+            );
+            let valid = range.start > 0.0 && range.start < range.end && range.end <= MAX_MHZ;
+            if !valid {
+                bail!(
+                    "{}: start and end must be in MHz, start below end and end no higher \
+                     than {:.3} MHz",
+                    place,
+                    MAX_MHZ
+                );
+            }
+            let gain = match &range.gain {
+                GainSetting::Word(word) if word.eq_ignore_ascii_case("auto") => None,
+                GainSetting::Db(db) if (0.0..=MAX_GAIN as f64 / 10.0).contains(db) => {
+                    Some((db * 10.0).round() as i32)
+                }
+                GainSetting::Db(db) => bail!("{}: gain {} dB is out of range", place, db),
+                GainSetting::Word(word) => {
+                    bail!(
+                        "{}: gain '{}' is neither a number of dB nor \"auto\"",
+                        place,
+                        word
+                    )
+                }
+            };
+            let range = GainRange {
+                start: (range.start * 1e6).round() as u32,
+                end: (range.end * 1e6).round() as u32,
+                gain,
+            };
+            if let Some(other) = ranges
+                .iter()
+                .position(|r| r.start <= range.end && range.start <= r.end)
+            {
+                bail!("{}: overlaps range {}", place, other + 1);
+            }
+            ranges.push(range);
+        }
+        Ok(Some(Self::new(
+            ranges,
+            (config.hysteresis * 1e6).round() as u32,
+            frequency,
+        )))
+    }
+
+    pub fn ranges(&self) -> &[GainRange] {
+        &self.ranges
+    }
+
+    /// The range for `frequency`: the one the receiver is in while it is
+    /// within the hysteresis of it, otherwise the one it falls in
+    fn lookup(&self, frequency: u32) -> Option<usize> {
+        if let Some(active) = self.active {
+            if self.ranges[active].contains(frequency, self.hysteresis) {
+                return Some(active);
+            }
+        }
+        self.ranges
+            .iter()
+            .position(|range| range.contains(frequency, 0))
+    }
+
+    /// Note a retune to `frequency`; what to do about the gain if it
+    /// crossed into another range
+    pub fn retune(&mut self, frequency: u32) -> Option<Crossing> {
+        let range = self.lookup(frequency);
+        if range == self.active {
+            return None;
+        }
+        self.active = range;
+        let range = self.ranges[range?];
+        let overridden = std::mem::take(&mut self.overridden);
+        Some(if overridden {
+            Crossing::Kept(range)
+        } else {
+            Crossing::Apply(range)
+        })
+    }
+
+    /// Note a gain set by hand
+    pub fn manual(&mut self) {
+        self.overridden = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::config::GainRangeConfig;
+
+    const MHZ: u32 = 1_000_000;
+
+    fn profiles(frequency: u32) -> GainProfiles {
+        GainProfiles::new(
+            vec![
+                GainRange {
+                    start: 108 * MHZ,
+                    end: 174 * MHZ,
+                    gain: Some(402),
+                },
+                GainRange {
+                    start: 174 * MHZ + 1,
+                    end: 230 * MHZ,
+                    gain: Some(297),
+                },
+                GainRange {
+                    start: 1085 * MHZ,
+                    end: 1095 * MHZ,
+                    gain: None,
+                },
+            ],
+            MHZ / 2,
+            frequency,
+        )
+    }
+
+    #[test]
+    fn test_crossings() {
+        let mut profiles = profiles(100 * MHZ);
+        // Within the starting range: nothing to do
+        let mut start = GainProfiles::new(profiles.ranges.clone(), MHZ / 2, 162 * MHZ);
+        assert_eq!(start.retune(146 * MHZ), None);
+
+        // Into a range, around in it, and into the next
+        assert_eq!(
+            profiles.retune(162_550_000),
+            Some(Crossing::Apply(profiles.ranges[0]))
+        );
+        assert_eq!(profiles.retune(118 * MHZ), None);
+        assert_eq!(
+            profiles.retune(1090 * MHZ),
+            Some(Crossing::Apply(profiles.ranges[2]))
+        );
+
+        // Out of every range the gain stays; back in applies again
+        assert_eq!(profiles.retune(433 * MHZ), None);
+        assert_eq!(
+            profiles.retune(1090 * MHZ),
+            Some(Crossing::Apply(profiles.ranges[2]))
+        );
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let mut profiles = profiles(170 * MHZ);
+        // A scan stepping back and forth over 174 MHz stays in the first
+        // range until it is half a MHz past
+        for frequency in [173_900_000, 174_100_000, 173_950_000, 174_400_000] {
+            assert_eq!(profiles.retune(frequency), None, "{}", frequency);
+        }
+        assert_eq!(
+            profiles.retune(174_600_000),
+            Some(Crossing::Apply(profiles.ranges[1]))
+        );
+        // And now in the second, it takes half a MHz the other way
+        assert_eq!(profiles.retune(173_800_000), None);
+        assert_eq!(
+            profiles.retune(173_400_000),
+            Some(Crossing::Apply(profiles.ranges[0]))
+        );
+    }
+
+    #[test]
+    fn test_manual_override() {
+        let mut profiles = profiles(162 * MHZ);
+        profiles.manual();
+        // The crossing after a gain set by hand keeps it...
+        assert_eq!(
+            profiles.retune(1090 * MHZ),
+            Some(Crossing::Kept(profiles.ranges[2]))
+        );
+        // ...and the one after that applies its own
+        assert_eq!(
+            profiles.retune(162 * MHZ),
+            Some(Crossing::Apply(profiles.ranges[0]))
+        );
+        assert_eq!(
+            profiles.ranges[0].describe(),
+            "108.000-174.000 MHz: 40.2 dB"
+        );
+        assert_eq!(
+            profiles.ranges[2].describe(),
+            "1085.000-1095.000 MHz: auto gain"
+        );
+    }
+
+    fn config(ranges: Vec<(f64, f64, GainSetting)>) -> GainProfilesConfig {
+        GainProfilesConfig {
+            enabled: true,
+            hysteresis: 0.5,
+            ranges: ranges
+                .into_iter()
+                .map(|(start, end, gain)| GainRangeConfig { start, end, gain })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_from_config() {
+        let loaded = GainProfiles::from_config(
+            &config(vec![
+                (108.0, 174.0, GainSetting::Db(40.2)),
+                (1085.0, 1095.0, GainSetting::Word("Auto".into())),
+            ]),
+            1090 * MHZ,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(loaded.ranges()[0].gain, Some(402));
+        assert_eq!(loaded.ranges()[1].gain, None);
+        assert_eq!(loaded.active, Some(1));
+
+        // Off: nothing, however the ranges look
+        let mut off = config(vec![(200.0, 100.0, GainSetting::Db(99.0))]);
+        off.enabled = false;
+        assert_eq!(GainProfiles::from_config(&off, 0).unwrap(), None);
+
+        let error = |ranges| {
+            GainProfiles::from_config(&config(ranges), 0)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error(vec![(174.0, 108.0, GainSetting::Db(40.2))]).contains("start below end"));
+        // Past what a u32 of Hz holds
+        assert!(error(vec![(4000.0, 5000.0, GainSetting::Db(40.2))]).contains("4294.967 MHz"));
+        assert!(GainProfiles::from_config(
+            &config(vec![(4000.0, MAX_MHZ, GainSetting::Db(40.2))]),
+            0
+        )
+        .is_ok());
+        assert!(error(vec![(108.0, 174.0, GainSetting::Db(99.0))]).contains("out of range"));
+        assert!(
+            error(vec![(108.0, 174.0, GainSetting::Word("high".into()))])
+                .contains("neither a number of dB nor \"auto\"")
+        );
+        assert_eq!(
+            error(vec![
+                (108.0, 174.0, GainSetting::Db(40.2)),
+                (150.0, 200.0, GainSetting::Db(30.0)),
+            ]),
+            "gain_profiles: range 2 (150-200 MHz): overlaps range 1"
+        );
+    }
+
+    #[test]
+    fn test_config_toml() {
+        let config: crate::types::AppConfig = toml::from_str(
+            r#"
+            [gain_profiles]
+            enabled = true
+            ranges = [{ start = 108.0, end = 174.0, gain = 40 },
+                      { start = 1085.0, end = 1095.0, gain = "auto" }]
+            "#,
+        )
+        .unwrap();
+        let profiles = GainProfiles::from_config(&config.gain_profiles, 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(profiles.ranges()[0].gain, Some(400));
+        assert_eq!(profiles.hysteresis, MHZ / 2);
+    }
+}
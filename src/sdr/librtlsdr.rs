@@ -0,0 +1,168 @@
+//! The librtlsdr calls an RTL-SDR is driven with, bound directly
+//!
+//! rtlsdr_mt keeps its device handle to itself, which leaves out the tuner's
+//! gain mode, the RTL2832's digital AGC and the tuner type. The device is
+//! opened here instead, split as rtlsdr_mt splits it: a [`Controller`] for
+//! the command thread and a [`Reader`] for the acquisition thread, sharing
+//! the handle, which closes once both are gone. librtlsdr itself is linked
+//! for rtlsdr_mt, which still lists the devices.
+
+use std::ffi::c_void;
+use std::sync::Arc;
+
+/// librtlsdr's device handle, only ever behind a pointer
+#[repr(C)]
+struct RtlSdrDev {
+    _private: [u8; 0],
+}
+
+/// What librtlsdr calls with each buffer read
+type ReadAsyncCallback = unsafe extern "C" fn(buf: *mut u8, len: u32, ctx: *mut c_void);
+
+extern "C" {
+    fn rtlsdr_open(dev: *mut *mut RtlSdrDev, index: u32) -> libc::c_int;
+    fn rtlsdr_close(dev: *mut RtlSdrDev) -> libc::c_int;
+    fn rtlsdr_get_tuner_type(dev: *mut RtlSdrDev) -> libc::c_int;
+    fn rtlsdr_get_tuner_gains(dev: *mut RtlSdrDev, gains: *mut libc::c_int) -> libc::c_int;
+    fn rtlsdr_set_center_freq(dev: *mut RtlSdrDev, freq: u32) -> libc::c_int;
+    fn rtlsdr_set_sample_rate(dev: *mut RtlSdrDev, rate: u32) -> libc::c_int;
+    fn rtlsdr_set_tuner_bandwidth(dev: *mut RtlSdrDev, bw: u32) -> libc::c_int;
+    fn rtlsdr_set_tuner_gain_mode(dev: *mut RtlSdrDev, manual: libc::c_int) -> libc::c_int;
+    fn rtlsdr_set_tuner_gain(dev: *mut RtlSdrDev, gain: libc::c_int) -> libc::c_int;
+    fn rtlsdr_set_agc_mode(dev: *mut RtlSdrDev, on: libc::c_int) -> libc::c_int;
+    fn rtlsdr_set_freq_correction(dev: *mut RtlSdrDev, ppm: libc::c_int) -> libc::c_int;
+    fn rtlsdr_reset_buffer(dev: *mut RtlSdrDev) -> libc::c_int;
+    fn rtlsdr_read_async(
+        dev: *mut RtlSdrDev,
+        cb: ReadAsyncCallback,
+        ctx: *mut c_void,
+        buf_num: u32,
+        buf_len: u32,
+    ) -> libc::c_int;
+    fn rtlsdr_cancel_async(dev: *mut RtlSdrDev) -> libc::c_int;
+}
+
+/// A librtlsdr call that failed, with the code it returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error(pub i32);
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// librtlsdr's 0 for success, or the error it returned
+fn check(code: libc::c_int) -> Result<()> {
+    match code {
+        0 => Ok(()),
+        code => Err(Error(code)),
+    }
+}
+
+/// An open device, closed when dropped
+struct Handle(*mut RtlSdrDev);
+
+// librtlsdr takes control calls from one thread while another reads, and
+// cancels the read from a thread other than the reader's
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            rtlsdr_close(self.0);
+        }
+    }
+}
+
+/// Open device `index`, split into its controls and its sample stream
+pub fn open(index: u32) -> Result<(Controller, Reader)> {
+    let mut dev = std::ptr::null_mut();
+    check(unsafe { rtlsdr_open(&mut dev, index) })?;
+    if dev.is_null() {
+        return Err(Error(-1));
+    }
+    let handle = Arc::new(Handle(dev));
+    Ok((Controller(handle.clone()), Reader(handle)))
+}
+
+/// Tuning and gain for an open device
+pub struct Controller(Arc<Handle>);
+
+impl Controller {
+    /// librtlsdr's code for the tuner chip, 0 if it doesn't know it
+    pub fn tuner_type(&self) -> u32 {
+        u32::try_from(unsafe { rtlsdr_get_tuner_type(self.0 .0) }).unwrap_or(0)
+    }
+
+    /// The tuner's gain steps in tenths of dB, as librtlsdr lists them
+    pub fn tuner_gains(&self) -> Vec<i32> {
+        let count = unsafe { rtlsdr_get_tuner_gains(self.0 .0, std::ptr::null_mut()) };
+        let Ok(count) = usize::try_from(count) else {
+            return Vec::new();
+        };
+        let mut gains = vec![0; count];
+        let written = unsafe { rtlsdr_get_tuner_gains(self.0 .0, gains.as_mut_ptr()) };
+        gains.truncate(usize::try_from(written).unwrap_or(0));
+        gains
+    }
+
+    pub fn set_center_freq(&mut self, freq: u32) -> Result<()> {
+        check(unsafe { rtlsdr_set_center_freq(self.0 .0, freq) })
+    }
+
+    pub fn set_sample_rate(&mut self, rate: u32) -> Result<()> {
+        check(unsafe { rtlsdr_set_sample_rate(self.0 .0, rate) })
+    }
+
+    /// Set the tuner's IF bandwidth in Hz, 0 for automatic
+    pub fn set_bandwidth(&mut self, bandwidth: u32) -> Result<()> {
+        check(unsafe { rtlsdr_set_tuner_bandwidth(self.0 .0, bandwidth) })
+    }
+
+    /// Put the tuner in manual gain mode, or back in automatic
+    pub fn set_tuner_gain_mode(&mut self, manual: bool) -> Result<()> {
+        check(unsafe { rtlsdr_set_tuner_gain_mode(self.0 .0, manual as libc::c_int) })
+    }
+
+    /// Set the tuner gain in tenths of dB, which needs manual gain mode
+    pub fn set_tuner_gain(&mut self, gain: i32) -> Result<()> {
+        check(unsafe { rtlsdr_set_tuner_gain(self.0 .0, gain) })
+    }
+
+    /// Turn the RTL2832's digital AGC on or off
+    pub fn set_agc_mode(&mut self, on: bool) -> Result<()> {
+        check(unsafe { rtlsdr_set_agc_mode(self.0 .0, on as libc::c_int) })
+    }
+
+    pub fn set_ppm(&mut self, ppm: i32) -> Result<()> {
+        check(unsafe { rtlsdr_set_freq_correction(self.0 .0, ppm) })
+    }
+
+    /// End a [`Reader::read_async`] running on another thread
+    pub fn cancel_async_read(&mut self) {
+        unsafe {
+            rtlsdr_cancel_async(self.0 .0);
+        }
+    }
+}
+
+/// The sample stream of an open device
+pub struct Reader(Arc<Handle>);
+
+impl Reader {
+    /// Read `buffers` buffers of `length` bytes at a time, handing each to
+    /// `callback`, until cancelled
+    pub fn read_async<F: FnMut(&[u8])>(
+        &mut self,
+        buffers: u32,
+        length: u32,
+        mut callback: F,
+    ) -> Result<()> {
+        unsafe extern "C" fn forward<F: FnMut(&[u8])>(buf: *mut u8, len: u32, ctx: *mut c_void) {
+            let callback = &mut *(ctx as *mut F);
+            callback(std::slice::from_raw_parts(buf, len as usize));
+        }
+
+        check(unsafe { rtlsdr_reset_buffer(self.0 .0) })?;
+        let ctx = &mut callback as *mut F as *mut c_void;
+        check(unsafe { rtlsdr_read_async(self.0 .0, forward::<F>, ctx, buffers, length) })
+    }
+}
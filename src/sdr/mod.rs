@@ -1,7 +1,408 @@
+pub mod adc;
+pub mod buffer;
 pub mod config;
 pub mod device;
+pub mod file;
+pub mod gain_profile;
+pub mod librtlsdr;
+pub mod rtl_tcp;
+#[cfg(feature = "soapy")]
+pub mod soapy;
+pub mod stderr;
 pub mod thread;
+pub mod tuner;
+pub mod wait;
+pub mod watchdog;
 
 // Re-export commonly used types
-pub use device::{get_device_count, list_devices, samples_u8_to_complex, DeviceInfo, RtlSdrDevice};
-pub use thread::start_sdr_thread;
+pub use buffer::{CaptureClock, SampleBuffer};
+pub use config::Capabilities;
+pub use file::{FileSource, PlaybackFile};
+pub use rtl_tcp::RtlTcpSource;
+pub use device::{
+    clipped_fraction, clipped_fraction_u8, get_device_count, list_devices, samples_complex_to_u8,
+    samples_u8_to_complex, DeviceInfo, RtlSdrDevice,
+};
+pub use tuner::Tuner;
+pub use thread::{start_sdr_thread, Reopen};
+pub use wait::start_sdr_thread_when_available;
+
+use crate::recorder::RecorderEvent;
+use crate::state::{RecordingMode, SharedState};
+use adc::Histogram;
+use anyhow::Result;
+use crossbeam::channel::Sender;
+use num_complex::Complex;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tracing::field::Empty;
+
+/// A receiver backend the SDR thread tunes and streams from
+///
+/// Gains are in tenths of dB throughout, as the RTL-SDR reports them.
+pub trait SdrSource: Send {
+    /// Which device this is, for the log
+    fn describe(&self) -> String;
+
+    /// Tuning and gain limits of the device
+    fn capabilities(&self) -> &Capabilities;
+
+    /// Set the center frequency in Hz
+    fn set_frequency(&mut self, freq: u32) -> Result<()>;
+
+    /// Set the sample rate in Hz
+    fn set_sample_rate(&mut self, rate: u32) -> Result<()>;
+
+    /// Set the tuner's IF bandwidth in Hz, or 0 to follow the sample rate;
+    /// ignored by backends that can't
+    fn set_bandwidth(&mut self, _bandwidth: u32) -> Result<()> {
+        Ok(())
+    }
+
+    /// Set a fixed gain, or None for the tuner's automatic gain
+    fn set_gain(&mut self, gain: Option<i32>) -> Result<()>;
+
+    /// Enable or disable the RTL2832 digital AGC, which only RTL-SDRs have
+    fn set_rtl_agc(&mut self, enabled: bool) -> Result<()> {
+        if enabled {
+            anyhow::bail!("{} has no RTL AGC", self.describe());
+        }
+        Ok(())
+    }
+
+    /// Set PPM frequency correction
+    fn set_ppm(&mut self, ppm: i32) -> Result<()>;
+
+    /// Start streaming into `sink` on a thread of its own, which runs until
+    /// shutdown or [`stop`](Self::stop); called once
+    fn start(&mut self, sink: SampleSink) -> Result<std::thread::JoinHandle<()>>;
+
+    /// Stop streaming, so the device can be closed and opened again after
+    /// a stall
+    fn stop(&mut self) {}
+}
+
+/// Which backend opens the receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Driver {
+    /// librtlsdr, by device index
+    #[default]
+    Rtl,
+    /// SoapySDR, by device arguments such as "driver=airspy"
+    Soapy,
+}
+
+impl std::str::FromStr for Driver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rtl" | "rtlsdr" => Ok(Driver::Rtl),
+            "soapy" | "soapysdr" => Ok(Driver::Soapy),
+            _ => Err(format!("unknown driver '{}' (expected rtl or soapy)", s)),
+        }
+    }
+}
+
+/// Open the receiver: RTL-SDRs by `device_index`, SoapySDR devices by
+/// `device_args`
+pub fn open_source(
+    driver: Driver,
+    device_index: usize,
+    device_args: &str,
+) -> Result<Box<dyn SdrSource>> {
+    // librtlsdr and SoapySDR print directly to stderr, which would corrupt
+    // the TUI, so it goes to the log instead
+    stderr::capture();
+
+    match driver {
+        Driver::Rtl => Ok(Box::new(RtlSdrDevice::open(device_index)?)),
+        #[cfg(feature = "soapy")]
+        Driver::Soapy => Ok(Box::new(soapy::SoapySource::open(device_args)?)),
+        #[cfg(not(feature = "soapy"))]
+        Driver::Soapy => {
+            let _ = device_args;
+            anyhow::bail!("Built without SoapySDR support; rebuild with --features soapy")
+        }
+    }
+}
+
+/// Where a source delivers its samples: the DSP thread, and the recorder
+/// while recording raw IQ
+///
+/// Each buffer is stamped as it arrives, so what goes downstream is a
+/// [`SampleBuffer`] numbered and timed within the receiver's stream.
+pub struct SampleSink {
+    state: SharedState,
+    samples_tx: Sender<SampleBuffer>,
+    recorder_tx: Sender<RecorderEvent>,
+    shutdown: Arc<AtomicBool>,
+    clock: Mutex<CaptureClock>,
+    /// Set by a seek, for the next buffer to carry
+    seeked: AtomicBool,
+}
+
+/// Where a buffer falls in the stream, as the sink numbered it
+struct Stamp {
+    first: u64,
+    capture_time: SystemTime,
+    sample_rate: u32,
+    center_freq: u32,
+    seeked: bool,
+    /// Whether raw IQ is being recorded
+    recording: bool,
+}
+
+impl Stamp {
+    fn buffer(self, samples: Vec<Complex<f32>>) -> SampleBuffer {
+        SampleBuffer {
+            samples,
+            capture_time: self.capture_time,
+            sample_index: self.first,
+            sample_rate: self.sample_rate,
+            center_freq: self.center_freq,
+            seeked: self.seeked,
+        }
+    }
+}
+
+impl SampleSink {
+    pub fn new(
+        state: SharedState,
+        samples_tx: Sender<SampleBuffer>,
+        recorder_tx: Sender<RecorderEvent>,
+        shutdown: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            state,
+            samples_tx,
+            recorder_tx,
+            shutdown,
+            clock: Mutex::new(CaptureClock::default()),
+            seeked: AtomicBool::new(false),
+        }
+    }
+
+    /// Note that the source jumped in its stream; the next buffer it
+    /// delivers is marked [`SampleBuffer::seeked`]
+    pub fn mark_seek(&self) {
+        self.seeked.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the source should stop streaming
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    /// Deliver interleaved unsigned 8-bit IQ, as the RTL2832 produces it
+    pub fn push_u8(&self, bytes: &[u8]) {
+        // A callback can't return early, so buffers arriving during
+        // shutdown are just skipped
+        if self.is_shutdown() {
+            return;
+        }
+        let arrived = SystemTime::now();
+        let _span = read_span(bytes.len() / 2).entered();
+        let stamp = self.advance(bytes.len() / 2, arrived);
+        // Tee raw bytes to the recorder before conversion
+        if stamp.recording {
+            self.record(&stamp, bytes.to_vec());
+        }
+        self.count_clipping(clipped_fraction_u8(bytes));
+        let mut histogram = Histogram::default();
+        histogram.add_u8(bytes);
+        self.count_levels(&histogram);
+        self.send(stamp.buffer(samples_u8_to_complex(bytes)));
+    }
+
+    /// Deliver IQ samples; recordings get them as 8-bit IQ
+    #[cfg_attr(not(feature = "soapy"), allow(dead_code))]
+    pub fn push(&self, samples: Vec<Complex<f32>>) {
+        if self.is_shutdown() {
+            return;
+        }
+        let arrived = SystemTime::now();
+        let _span = read_span(samples.len()).entered();
+        let stamp = self.advance(samples.len(), arrived);
+        if stamp.recording {
+            self.record(&stamp, samples_complex_to_u8(&samples));
+        }
+        self.count_clipping(clipped_fraction(&samples));
+        let mut histogram = Histogram::default();
+        histogram.add(&samples);
+        self.count_levels(&histogram);
+        self.send(stamp.buffer(samples));
+    }
+
+    /// Sample buffers waiting for the DSP thread
+    pub fn backlog(&self) -> usize {
+        self.samples_tx.len()
+    }
+
+    /// Show a warning about the connection to the receiver, or clear it
+    pub fn set_link_warning(&self, warning: Option<String>) {
+        let mut state = self.state.write();
+        state.sdr.link_warning = warning;
+        state.changed.notify();
+    }
+
+    /// Number and time the next `samples` samples, which arrived at
+    /// `arrived`
+    ///
+    /// Every buffer delivered counts, including those dropped afterwards
+    /// for want of room downstream.
+    fn advance(&self, samples: usize, arrived: SystemTime) -> Stamp {
+        let mut state = self.state.write();
+        let first = state.sdr.samples_delivered;
+        state.sdr.samples_delivered += samples as u64;
+        let sample_rate = state.sdr.sample_rate;
+        let recording = &state.recording;
+        Stamp {
+            first,
+            capture_time: self.clock.lock().time(first, samples, sample_rate, arrived),
+            sample_rate,
+            center_freq: state.sdr.hardware_frequency(),
+            seeked: self.seeked.swap(false, Ordering::Relaxed),
+            recording: recording.is_recording && recording.mode == RecordingMode::Iq,
+        }
+    }
+
+    fn count_clipping(&self, fraction: f32) {
+        match self.state.write().stats.add_clipping(fraction) {
+            Some(true) => log::warn!("Overload: raw IQ is clipping, reduce gain"),
+            Some(false) => log::info!("Overload cleared"),
+            None => {}
+        }
+    }
+
+    /// Add a buffer's ADC levels, counted before taking the lock
+    fn count_levels(&self, histogram: &Histogram) {
+        self.state.write().stats.adc.add(histogram, Instant::now());
+    }
+
+    fn record(&self, stamp: &Stamp, bytes: Vec<u8>) {
+        let event = RecorderEvent::Samples {
+            bytes,
+            first: stamp.first,
+            captured: stamp.capture_time,
+        };
+        if self.recorder_tx.try_send(event).is_err() {
+            log::warn!("Recorder is falling behind, dropping IQ buffer");
+            self.state.write().stats.recorder_dropped += 1;
+        }
+    }
+
+    /// Send to the DSP thread, within the buffer's read span
+    fn send(&self, buffer: SampleBuffer) {
+        // Send to DSP thread (non-blocking)
+        let dropped = if self.samples_tx.try_send(buffer).is_err() {
+            // DSP thread is slow, drop this buffer
+            log::warn!("Dropping samples due to backpressure");
+            let mut state = self.state.write();
+            state.stats.sdr_dropped += 1;
+            state.stats.sdr_dropped
+        } else {
+            self.state.read().stats.sdr_dropped
+        };
+        let span = tracing::Span::current();
+        span.record("backlog", self.samples_tx.len());
+        span.record("dropped", dropped);
+    }
+}
+
+/// A buffer of `len` samples on its way from the source to the DSP thread;
+/// `backlog` is the buffers then waiting for it, `dropped` those dropped so
+/// far
+fn read_span(len: usize) -> tracing::Span {
+    tracing::debug_span!("sdr.read", len, backlog = Empty, dropped = Empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+    use crate::trace_capture::capture;
+
+    #[test]
+    fn test_read_span() {
+        let state = AppState::new_shared();
+        let (samples_tx, _samples_rx) = crossbeam::channel::bounded(1);
+        let (recorder_tx, _recorder_rx) = crossbeam::channel::unbounded();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let sink = SampleSink::new(state, samples_tx, recorder_tx, shutdown);
+
+        let ((), spans) = capture(|| {
+            sink.push_u8(&[127; 8]);
+            // No room for this one
+            sink.push(vec![Complex::new(0.0, 0.0); 6]);
+        });
+        let reads: Vec<_> = spans.iter().filter(|span| span.name == "sdr.read").collect();
+        assert_eq!(reads.len(), 2);
+        assert_eq!(reads[0].field("len"), Some("4"));
+        assert_eq!(reads[0].field("backlog"), Some("1"));
+        assert_eq!(reads[0].field("dropped"), Some("0"));
+        assert_eq!(reads[1].field("len"), Some("6"));
+        assert_eq!(reads[1].field("dropped"), Some("1"));
+    }
+
+    #[test]
+    fn test_buffers_stamped() {
+        let state = AppState::new_shared();
+        {
+            let mut state = state.write();
+            state.sdr.frequency = 162_550_000;
+            state.sdr.hardware_offset = 250_000;
+            state.sdr.sample_rate = 1_000_000;
+            state.recording.is_recording = true;
+            state.recording.mode = RecordingMode::Iq;
+        }
+        let (samples_tx, samples_rx) = crossbeam::channel::bounded(1);
+        let (recorder_tx, recorder_rx) = crossbeam::channel::unbounded();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let sink = SampleSink::new(state, samples_tx, recorder_tx, shutdown);
+
+        sink.push_u8(&[127; 2_000]);
+        // Dropped on the way to the DSP thread, but still counted
+        sink.push_u8(&[127; 2_000]);
+        let first = samples_rx.try_recv().unwrap();
+        sink.push(vec![Complex::new(0.0, 0.0); 500]);
+        let third = samples_rx.try_recv().unwrap();
+
+        assert_eq!(first.len(), 1_000);
+        assert_eq!(first.sample_index, 0);
+        assert_eq!(first.sample_rate, 1_000_000);
+        assert_eq!(first.center_freq, 162_800_000);
+        assert_eq!(third.sample_index, 2_000);
+        // Timed by the count, a millisecond a buffer
+        assert_eq!(third.time_of(0), first.time_of(2_000));
+
+        // The recorder gets all three, stamped alike
+        let recorded: Vec<_> = recorder_rx
+            .try_iter()
+            .map(|event| match event {
+                RecorderEvent::Samples { first, captured, .. } => (first, captured),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(recorded.len(), 3);
+        assert_eq!(recorded[0], (0, first.capture_time));
+        assert_eq!(recorded[2], (2_000, third.capture_time));
+    }
+
+    #[test]
+    fn test_seek_marks_next_buffer() {
+        let (samples_tx, samples_rx) = crossbeam::channel::unbounded();
+        let (recorder_tx, _recorder_rx) = crossbeam::channel::unbounded();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let sink = SampleSink::new(AppState::new_shared(), samples_tx, recorder_tx, shutdown);
+
+        sink.push_u8(&[127; 200]);
+        sink.mark_seek();
+        sink.push_u8(&[127; 200]);
+        sink.push_u8(&[127; 200]);
+        let seeked: Vec<bool> = samples_rx.try_iter().map(|buffer| buffer.seeked).collect();
+        assert_eq!(seeked, [false, true, false]);
+    }
+}
@@ -0,0 +1,431 @@
+//! rtl_tcp client, for a dongle shared over the network by `rtl_tcp`
+//!
+//! The server opens with a 12-byte header ("RTL0", tuner type, gain count)
+//! and then streams unsigned 8-bit IQ exactly as a local dongle would.
+//! Settings go the other way as 5-byte commands: a command byte and a
+//! big-endian parameter.
+//!
+//! When the connection drops the acquisition thread keeps reconnecting,
+//! with a warning in the status bar meanwhile, and replays the last value of
+//! every setting on the new connection. A server that sends nothing for
+//! [`IDLE_TIMEOUT`] counts as dropped too, since a link that stalls (a
+//! cable pulled, a laptop asleep) may never close. Samples are passed on in
+//! fixed blocks as they arrive; TCP's own buffering plus the bounded sample
+//! channel absorb network jitter, so nothing here adds latency beyond one
+//! block.
+
+use super::{Capabilities, SampleSink, SdrSource, Tuner};
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// rtl_tcp command bytes
+const SET_FREQUENCY: u8 = 0x01;
+const SET_SAMPLE_RATE: u8 = 0x02;
+const SET_GAIN_MODE: u8 = 0x03;
+const SET_GAIN: u8 = 0x04;
+const SET_PPM: u8 = 0x05;
+const SET_AGC_MODE: u8 = 0x08;
+
+/// Bytes passed on at a time: 16384 samples, as a local dongle delivers
+const BLOCK_LEN: usize = 32768;
+/// How long connecting and the header may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Read timeout, so shutdown is noticed
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+/// How long the server may send nothing before the link counts as lost
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Wait between reconnection attempts
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+/// The connection the commands go out on, and every setting's last value
+#[derive(Default)]
+struct Link {
+    control: Option<TcpStream>,
+    /// Replayed in command order on reconnection, so the gain mode comes
+    /// before the gain
+    settings: BTreeMap<u8, u32>,
+}
+
+impl Link {
+    /// Remember a setting and send it if connected; a failed send drops
+    /// the connection for the reader to re-establish
+    fn command(&mut self, command: u8, param: u32) {
+        self.settings.insert(command, param);
+        if let Some(control) = &mut self.control {
+            if let Err(e) = control.write_all(&packet(command, param)) {
+                log::warn!("rtl_tcp command {:#04x} failed: {}", command, e);
+                self.control = None;
+            }
+        }
+    }
+
+    /// Adopt a new connection and bring it up to date
+    fn connected(&mut self, stream: &TcpStream) -> std::io::Result<()> {
+        let mut control = stream.try_clone()?;
+        for (&command, &param) in &self.settings {
+            control.write_all(&packet(command, param))?;
+        }
+        self.control = Some(control);
+        Ok(())
+    }
+}
+
+/// One 5-byte rtl_tcp command
+fn packet(command: u8, param: u32) -> [u8; 5] {
+    let [a, b, c, d] = param.to_be_bytes();
+    [command, a, b, c, d]
+}
+
+/// Connect and read the server's header, returning the tuner type
+fn connect(addr: &str) -> Result<(TcpStream, u32)> {
+    let socket = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("{} doesn't resolve to an address", addr))?;
+    let mut stream = TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+
+    let mut header = [0u8; 12];
+    stream.read_exact(&mut header)?;
+    if &header[..4] != b"RTL0" {
+        anyhow::bail!("{} is not an rtl_tcp server", addr);
+    }
+    let tuner = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    Ok((stream, tuner))
+}
+
+/// A dongle on an rtl_tcp server
+pub struct RtlTcpSource {
+    addr: String,
+    link: Arc<Mutex<Link>>,
+    /// The first connection, until streaming starts
+    stream: Option<TcpStream>,
+    capabilities: Capabilities,
+    /// How long the server may go quiet before reconnecting
+    idle_timeout: Duration,
+}
+
+impl RtlTcpSource {
+    /// Connect to the rtl_tcp server at `addr` ("host:port")
+    pub fn open(addr: &str) -> Result<Self> {
+        log::info!("Connecting to rtl_tcp server {}", addr);
+        let (stream, tuner) =
+            connect(addr).map_err(|e| anyhow!("Failed to connect to rtl_tcp {}: {}", addr, e))?;
+        let tuner = Tuner::from_code(tuner);
+        match tuner {
+            Some(tuner) => log::info!("Connected to rtl_tcp server {} ({} tuner)", addr, tuner),
+            None => log::info!("Connected to rtl_tcp server {} (tuner unknown)", addr),
+        }
+
+        let link = Link {
+            control: Some(stream.try_clone()?),
+            settings: BTreeMap::new(),
+        };
+        Ok(Self {
+            addr: addr.to_string(),
+            link: Arc::new(Mutex::new(link)),
+            stream: Some(stream),
+            capabilities: Capabilities::rtl_tuner(tuner),
+            idle_timeout: IDLE_TIMEOUT,
+        })
+    }
+}
+
+impl SdrSource for RtlTcpSource {
+    fn describe(&self) -> String {
+        format!("rtl_tcp {}", self.addr)
+    }
+
+    fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    fn set_frequency(&mut self, freq: u32) -> Result<()> {
+        self.capabilities.validate_frequency(freq)?;
+        self.link.lock().command(SET_FREQUENCY, freq);
+        Ok(())
+    }
+
+    fn set_sample_rate(&mut self, rate: u32) -> Result<()> {
+        self.capabilities.validate_sample_rate(rate)?;
+        self.link.lock().command(SET_SAMPLE_RATE, rate);
+        Ok(())
+    }
+
+    fn set_gain(&mut self, gain: Option<i32>) -> Result<()> {
+        let mut link = self.link.lock();
+        match gain {
+            None => {
+                link.settings.remove(&SET_GAIN);
+                link.command(SET_GAIN_MODE, 0);
+            }
+            Some(gain) => {
+                link.command(SET_GAIN_MODE, 1);
+                link.command(SET_GAIN, gain as u32);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_rtl_agc(&mut self, enabled: bool) -> Result<()> {
+        self.link.lock().command(SET_AGC_MODE, enabled as u32);
+        Ok(())
+    }
+
+    fn set_ppm(&mut self, ppm: i32) -> Result<()> {
+        self.link.lock().command(SET_PPM, ppm as u32);
+        Ok(())
+    }
+
+    fn start(&mut self, sink: SampleSink) -> Result<thread::JoinHandle<()>> {
+        let mut stream = self
+            .stream
+            .take()
+            .ok_or_else(|| anyhow!("{} is already streaming", self.describe()))?;
+        let addr = self.addr.clone();
+        let link = self.link.clone();
+        let idle_timeout = self.idle_timeout;
+
+        Ok(thread::spawn(move || {
+            log::info!("rtl_tcp acquisition thread started");
+
+            let mut block = vec![0u8; BLOCK_LEN];
+            while !sink.is_shutdown() {
+                stream_until_dropped(&mut stream, &mut block, &sink, idle_timeout);
+                if sink.is_shutdown() {
+                    break;
+                }
+
+                log::warn!("rtl_tcp connection to {} lost, reconnecting", addr);
+                // Hang up on a server that went quiet rather than leave it
+                // holding the old connection
+                let _ = stream.shutdown(Shutdown::Both);
+                link.lock().control = None;
+                sink.set_link_warning(Some(format!("rtl_tcp {} disconnected", addr)));
+                match reconnect(&addr, &link, &sink) {
+                    Some(reconnected) => stream = reconnected,
+                    None => break,
+                }
+                log::info!("Reconnected to rtl_tcp server {}", addr);
+                sink.set_link_warning(None);
+            }
+
+            log::info!("rtl_tcp acquisition thread stopped");
+        }))
+    }
+}
+
+/// Pass on whole blocks from `stream` until it closes, fails, sends nothing
+/// for `idle_timeout` or shutdown
+fn stream_until_dropped(
+    stream: &mut TcpStream,
+    block: &mut [u8],
+    sink: &SampleSink,
+    idle_timeout: Duration,
+) {
+    let mut filled = 0;
+    let mut heard = Instant::now();
+    while !sink.is_shutdown() {
+        match stream.read(&mut block[filled..]) {
+            Ok(0) => return,
+            Ok(read) => {
+                heard = Instant::now();
+                filled += read;
+                if filled == block.len() {
+                    sink.push_u8(block);
+                    filled = 0;
+                }
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                if heard.elapsed() >= idle_timeout {
+                    log::warn!("rtl_tcp server sent nothing for {:?}", idle_timeout);
+                    return;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => {
+                log::warn!("rtl_tcp read error: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Keep connecting until it works, replaying the settings; None at shutdown
+fn reconnect(addr: &str, link: &Mutex<Link>, sink: &SampleSink) -> Option<TcpStream> {
+    while !sink.is_shutdown() {
+        match connect(addr) {
+            Ok((stream, _)) => match link.lock().connected(&stream) {
+                Ok(()) => return Some(stream),
+                Err(e) => log::warn!("rtl_tcp {}: {}", addr, e),
+            },
+            Err(e) => log::debug!("rtl_tcp {}: {}", addr, e),
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+    use crossbeam::channel::{self, Receiver, Sender};
+    use num_complex::Complex;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// A tiny rtl_tcp server: each accepted connection gets the header and
+    /// `blocks` blocks of the byte pattern, then stays open reading
+    /// commands into the returned channel until told to drop it or the
+    /// client hangs up
+    fn fake_server(blocks: usize) -> (String, Receiver<[u8; 5]>, Sender<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (commands_tx, commands_rx) = channel::unbounded();
+        let (drop_tx, drop_rx) = channel::unbounded::<()>();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut header = b"RTL0".to_vec();
+                header.extend(5u32.to_be_bytes());
+                header.extend(29u32.to_be_bytes());
+                stream.write_all(&header).unwrap();
+                for _ in 0..blocks {
+                    let block: Vec<u8> = (0..BLOCK_LEN).map(|i| (i % 256) as u8).collect();
+                    stream.write_all(&block).unwrap();
+                }
+
+                stream.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+                let mut command = [0u8; 5];
+                loop {
+                    if drop_rx.try_recv().is_ok() {
+                        break;
+                    }
+                    match stream.read_exact(&mut command) {
+                        Ok(()) => commands_tx.send(command).unwrap(),
+                        Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                        Err(_) => {}
+                    }
+                }
+                // Down for a moment before taking the next connection
+                drop(stream);
+                thread::sleep(Duration::from_millis(300));
+            }
+        });
+        (addr, commands_rx, drop_tx)
+    }
+
+    fn next_command(commands: &Receiver<[u8; 5]>) -> [u8; 5] {
+        commands.recv_timeout(Duration::from_secs(2)).expect("no command")
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !done() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_commands_and_samples() {
+        let (addr, commands, _drop) = fake_server(2);
+        let state = AppState::new_shared();
+        let (samples_tx, samples_rx) = channel::bounded(8);
+        let (recorder_tx, _recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let mut source = RtlTcpSource::open(&addr).unwrap();
+        // Tuner type 5 in the header
+        assert_eq!(source.capabilities().tuner, Some(Tuner::R820t));
+        let sink = SampleSink::new(state, samples_tx, recorder_tx, shutdown.clone());
+        let handle = source.start(sink).unwrap();
+
+        source.set_frequency(144_390_000).unwrap();
+        assert_eq!(next_command(&commands), [0x01, 0x08, 0x9B, 0x37, 0x70]);
+        source.set_sample_rate(2_048_000).unwrap();
+        assert_eq!(next_command(&commands), packet(SET_SAMPLE_RATE, 2_048_000));
+        source.set_gain(Some(297)).unwrap();
+        assert_eq!(next_command(&commands), packet(SET_GAIN_MODE, 1));
+        assert_eq!(next_command(&commands), packet(SET_GAIN, 297));
+        source.set_gain(None).unwrap();
+        assert_eq!(next_command(&commands), packet(SET_GAIN_MODE, 0));
+        source.set_ppm(-3).unwrap();
+        assert_eq!(next_command(&commands), [0x05, 0xFF, 0xFF, 0xFF, 0xFD]);
+        source.set_rtl_agc(true).unwrap();
+        assert_eq!(next_command(&commands), packet(SET_AGC_MODE, 1));
+
+        // Whole blocks of samples, converted as from a local dongle
+        for _ in 0..2 {
+            let buffer = samples_rx.recv_timeout(Duration::from_secs(2)).unwrap();
+            assert_eq!(buffer.len(), BLOCK_LEN / 2);
+            assert_eq!(buffer.samples[0], Complex::new(-127.5 / 128.0, -126.5 / 128.0));
+        }
+
+        shutdown.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_reconnects_and_replays_settings() {
+        let (addr, commands, drop_connection) = fake_server(0);
+        let state = AppState::new_shared();
+        let (samples_tx, _samples_rx) = channel::bounded(8);
+        let (recorder_tx, _recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let mut source = RtlTcpSource::open(&addr).unwrap();
+        let sink = SampleSink::new(state.clone(), samples_tx, recorder_tx, shutdown.clone());
+        let handle = source.start(sink).unwrap();
+        source.set_frequency(433_920_000).unwrap();
+        source.set_gain(Some(100)).unwrap();
+        for _ in 0..3 {
+            next_command(&commands);
+        }
+
+        // The server drops the dongle's connection and takes a new one
+        drop_connection.send(()).unwrap();
+        wait_for(|| state.read().sdr.link_warning.is_some());
+        assert_eq!(next_command(&commands), packet(SET_FREQUENCY, 433_920_000));
+        assert_eq!(next_command(&commands), packet(SET_GAIN_MODE, 1));
+        assert_eq!(next_command(&commands), packet(SET_GAIN, 100));
+        wait_for(|| state.read().sdr.link_warning.is_none());
+
+        shutdown.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_reconnects_when_idle() {
+        let (addr, commands, _drop) = fake_server(0);
+        let state = AppState::new_shared();
+        let (samples_tx, _samples_rx) = channel::bounded(8);
+        let (recorder_tx, _recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let mut source = RtlTcpSource::open(&addr).unwrap();
+        source.idle_timeout = Duration::from_millis(300);
+        let sink = SampleSink::new(state.clone(), samples_tx, recorder_tx, shutdown.clone());
+        let handle = source.start(sink).unwrap();
+        source.set_frequency(433_920_000).unwrap();
+        next_command(&commands);
+
+        // The server stays connected but goes quiet, and is given up on
+        wait_for(|| state.read().sdr.link_warning.is_some());
+        assert_eq!(next_command(&commands), packet(SET_FREQUENCY, 433_920_000));
+
+        shutdown.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+}
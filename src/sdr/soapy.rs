@@ -0,0 +1,201 @@
+//! SoapySDR backend, for Airspy, HackRF, SDRplay and anything else with a
+//! Soapy module
+//!
+//! Samples are read as complex floats and handed on as they are; IQ
+//! recordings get them converted to the RTL-SDR's 8-bit format.
+
+use super::config::COMMON_SAMPLE_RATES;
+use super::{Capabilities, SampleSink, SdrSource};
+use anyhow::{anyhow, Result};
+use num_complex::Complex;
+use soapysdr::{Device, Direction, ErrorCode, Range};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Receive channel used on multi-channel devices
+const CHANNEL: usize = 0;
+/// Samples read per buffer
+const BUFFER_LEN: usize = 16384;
+/// Read timeout in microseconds, so shutdown is noticed
+const READ_TIMEOUT_US: i64 = 100_000;
+
+/// A SoapySDR device opened by its device arguments
+pub struct SoapySource {
+    device: Device,
+    args: String,
+    capabilities: Capabilities,
+    /// Set to end the acquisition thread
+    stop: Arc<AtomicBool>,
+}
+
+impl SoapySource {
+    /// Open the device matching `args`, e.g. "driver=airspy"
+    pub fn open(args: &str) -> Result<Self> {
+        log::info!("Opening SoapySDR device \"{}\"", args);
+
+        let device = Device::new(args)
+            .map_err(|e| anyhow!("Failed to open SoapySDR device \"{}\": {}", args, e))?;
+        let capabilities = capabilities(&device)
+            .map_err(|e| anyhow!("Failed to query SoapySDR device \"{}\": {}", args, e))?;
+
+        log::info!(
+            "SoapySDR device opened: {} ({:?} Hz, gain {:?} tenths dB)",
+            device.hardware_key().unwrap_or_default(),
+            capabilities.frequency,
+            capabilities.gain
+        );
+
+        Ok(Self {
+            device,
+            args: args.to_string(),
+            capabilities,
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+}
+
+/// Limits the device reports for its receive channel
+fn capabilities(device: &Device) -> Result<Capabilities, soapysdr::Error> {
+    let frequency = span(&device.frequency_range(Direction::Rx, CHANNEL)?);
+    let rate_ranges = device.get_sample_rate_range(Direction::Rx, CHANNEL)?;
+
+    // Discrete rates are offered as they are, continuous ranges through the
+    // usual RTL-SDR steps that fit
+    let mut sample_rates: Vec<u32> = rate_ranges
+        .iter()
+        .flat_map(|range| {
+            if range.maximum - range.minimum < 1.0 {
+                vec![range.minimum as u32]
+            } else {
+                COMMON_SAMPLE_RATES
+                    .iter()
+                    .copied()
+                    .filter(|&rate| (range.minimum..=range.maximum).contains(&(rate as f64)))
+                    .collect()
+            }
+        })
+        .collect();
+    sample_rates.sort_unstable();
+    sample_rates.dedup();
+
+    let gain = device.gain_range(Direction::Rx, CHANNEL)?;
+    Ok(Capabilities {
+        frequency,
+        sample_rate: span(&rate_ranges),
+        sample_rates,
+        gain: (gain.minimum * 10.0).round() as i32..=(gain.maximum * 10.0).round() as i32,
+        gains: Vec::new(),
+        tuner: None,
+        gaps: Vec::new(),
+    })
+}
+
+/// The lowest to the highest of `ranges`, in whole Hz
+fn span(ranges: &[Range]) -> std::ops::RangeInclusive<u32> {
+    let to_hz = |v: f64| v.clamp(0.0, u32::MAX as f64) as u32;
+    let min = ranges.iter().map(|r| r.minimum).fold(f64::INFINITY, f64::min);
+    let max = ranges.iter().map(|r| r.maximum).fold(0.0, f64::max);
+    to_hz(min)..=to_hz(max)
+}
+
+impl SdrSource for SoapySource {
+    fn describe(&self) -> String {
+        format!("SoapySDR \"{}\"", self.args)
+    }
+
+    fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    fn set_frequency(&mut self, freq: u32) -> Result<()> {
+        self.capabilities.validate_frequency(freq)?;
+        self.device
+            .set_frequency(Direction::Rx, CHANNEL, freq as f64, "")
+            .map_err(|e| anyhow!("Failed to set center frequency to {} Hz: {}", freq, e))?;
+        log::info!("Set center frequency to {} Hz ({} MHz)", freq, freq / 1_000_000);
+        Ok(())
+    }
+
+    fn set_sample_rate(&mut self, rate: u32) -> Result<()> {
+        self.capabilities.validate_sample_rate(rate)?;
+        self.device
+            .set_sample_rate(Direction::Rx, CHANNEL, rate as f64)
+            .map_err(|e| anyhow!("Failed to set sample rate to {} Hz: {}", rate, e))?;
+        log::info!("Set sample rate to {} Hz ({} kHz)", rate, rate / 1000);
+        Ok(())
+    }
+
+    fn set_bandwidth(&mut self, bandwidth: u32) -> Result<()> {
+        // Soapy has no "automatic"; the driver picks one with the rate
+        if bandwidth > 0 {
+            self.device
+                .set_bandwidth(Direction::Rx, CHANNEL, bandwidth as f64)
+                .map_err(|e| anyhow!("Failed to set bandwidth to {} Hz: {}", bandwidth, e))?;
+            log::info!("Set bandwidth to {} Hz", bandwidth);
+        }
+        Ok(())
+    }
+
+    fn set_gain(&mut self, gain: Option<i32>) -> Result<()> {
+        self.device
+            .set_gain_mode(Direction::Rx, CHANNEL, gain.is_none())
+            .map_err(|e| anyhow!("Failed to set gain mode: {}", e))?;
+        if let Some(gain) = gain {
+            self.device
+                .set_gain(Direction::Rx, CHANNEL, gain as f64 / 10.0)
+                .map_err(|e| {
+                    anyhow!("Failed to set gain to {}.{} dB: {}", gain / 10, gain % 10, e)
+                })?;
+            log::info!("Set gain to {}.{} dB", gain / 10, gain % 10);
+        }
+        Ok(())
+    }
+
+    fn set_ppm(&mut self, ppm: i32) -> Result<()> {
+        self.device
+            .set_frequency_correction(Direction::Rx, CHANNEL, ppm as f64)
+            .map_err(|e| anyhow!("Failed to set PPM correction to {}: {}", ppm, e))?;
+        if ppm != 0 {
+            log::info!("Set PPM correction to {}", ppm);
+        }
+        Ok(())
+    }
+
+    fn start(&mut self, sink: SampleSink) -> Result<thread::JoinHandle<()>> {
+        let mut stream = self
+            .device
+            .rx_stream::<Complex<f32>>(&[CHANNEL])
+            .map_err(|e| anyhow!("Failed to open receive stream: {}", e))?;
+        stream
+            .activate(None)
+            .map_err(|e| anyhow!("Failed to start receive stream: {}", e))?;
+
+        let stop = self.stop.clone();
+        Ok(thread::spawn(move || {
+            log::info!("SoapySDR acquisition thread started");
+
+            let mut buffer = vec![Complex::new(0.0, 0.0); BUFFER_LEN];
+            while !sink.is_shutdown() && !stop.load(Ordering::Relaxed) {
+                match stream.read(&mut [&mut buffer[..]], READ_TIMEOUT_US) {
+                    Ok(len) => sink.push(buffer[..len].to_vec()),
+                    // Nothing yet, or samples lost to a slow reader
+                    Err(e) if matches!(e.code, ErrorCode::Timeout | ErrorCode::Overflow) => {}
+                    Err(e) => {
+                        log::error!("SoapySDR read error: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            if let Err(e) = stream.deactivate(None) {
+                log::warn!("Failed to stop receive stream: {}", e);
+            }
+            log::info!("SoapySDR acquisition thread stopped");
+        }))
+    }
+
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
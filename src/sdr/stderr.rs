@@ -0,0 +1,260 @@
+//! Driver library stderr, routed into the log
+//!
+//! librtlsdr and SoapySDR print straight to stderr, which would scribble over
+//! the TUI. Instead of throwing that away, stderr is pointed at a pipe whose
+//! lines a thread forwards to the log, where they can explain a failed tune.
+//! The original stderr comes back on shutdown, so errors after the TUI has
+//! gone still reach the console.
+//!
+//! On Windows the C runtime's stderr is redirected, along with the standard
+//! error handle Windows hands out. A driver DLL built against a C runtime of
+//! its own keeps the stderr it started with, and still writes to the console.
+
+use parking_lot::Mutex;
+use std::io::{self, BufRead, BufReader};
+use std::thread;
+
+/// The capture in effect while the drivers are open
+static CAPTURE: Mutex<Option<StderrCapture>> = Mutex::new(None);
+
+/// Send stderr to the log until [`restore`]; does nothing if it already is
+pub fn capture() {
+    let mut capture = CAPTURE.lock();
+    if capture.is_some() {
+        return;
+    }
+    match StderrCapture::start(|line| log::warn!("stderr: {}", line)) {
+        Ok(started) => *capture = Some(started),
+        Err(e) => log::warn!("Leaving stderr as it is: {}", e),
+    }
+}
+
+/// Put stderr back where it was, once everything written so far is logged
+pub fn restore() {
+    if let Some(capture) = CAPTURE.lock().take() {
+        capture.restore();
+    }
+}
+
+/// Stderr redirected into a pipe, and the thread reading it
+pub struct StderrCapture {
+    original: sys::Saved,
+    reader: thread::JoinHandle<()>,
+}
+
+impl StderrCapture {
+    /// Redirect stderr, handing each line written to it to `sink`
+    pub fn start(mut sink: impl FnMut(&str) + Send + 'static) -> io::Result<Self> {
+        let (original, pipe) = sys::redirect()?;
+        let reader = thread::spawn(move || {
+            let mut reader = BufReader::new(pipe);
+            let mut line = Vec::new();
+            // Ends when the last write end closes, at restore
+            while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                let text = String::from_utf8_lossy(&line);
+                let text = text.trim_end();
+                if !text.is_empty() {
+                    sink(text);
+                }
+                line.clear();
+            }
+        });
+        Ok(Self { original, reader })
+    }
+
+    /// Put stderr back and wait for the rest of the pipe to be forwarded
+    pub fn restore(self) {
+        sys::put_back(self.original);
+        let _ = self.reader.join();
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::{FromRawFd, RawFd};
+
+    /// Duplicate of the original stderr
+    pub type Saved = RawFd;
+
+    /// Point stderr at a new pipe, returning the original and the read end
+    pub fn redirect() -> io::Result<(Saved, File)> {
+        let mut fds = [0; 2];
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let original = libc::dup(libc::STDERR_FILENO);
+            if original < 0 || libc::dup2(fds[1], libc::STDERR_FILENO) < 0 {
+                let error = io::Error::last_os_error();
+                if original >= 0 {
+                    libc::close(original);
+                }
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+                return Err(error);
+            }
+            // Stderr is the pipe's only write end from here on
+            libc::close(fds[1]);
+            Ok((original, File::from_raw_fd(fds[0])))
+        }
+    }
+
+    /// Point stderr back at `original`, closing the pipe's write end
+    pub fn put_back(original: Saved) {
+        unsafe {
+            libc::dup2(original, libc::STDERR_FILENO);
+            libc::close(original);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::mem::ManuallyDrop;
+    use std::os::windows::io::{FromRawHandle, RawHandle};
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::System::Console::{GetStdHandle, SetStdHandle, STD_ERROR_HANDLE};
+
+    /// The C runtime's stderr descriptor
+    pub const STDERR_FILENO: libc::c_int = 2;
+    /// Bytes the pipe holds before writers wait for the reader
+    const PIPE_SIZE: libc::c_uint = 64 * 1024;
+
+    /// Duplicate of the runtime's original stderr, and the standard error
+    /// handle from before (as an integer, so it can move between threads)
+    pub struct Saved {
+        fd: libc::c_int,
+        handle: isize,
+    }
+
+    /// Point stderr at a new pipe, returning the original and the read end
+    pub fn redirect() -> io::Result<(Saved, File)> {
+        let mut fds = [0; 2];
+        unsafe {
+            // Binary, so lines come through as the drivers wrote them
+            if libc::pipe(fds.as_mut_ptr(), PIPE_SIZE, libc::O_BINARY) != 0 {
+                return Err(io::Error::other("can't make a pipe for stderr"));
+            }
+            let original = libc::dup(STDERR_FILENO);
+            if original < 0 || libc::dup2(fds[1], STDERR_FILENO) < 0 {
+                if original >= 0 {
+                    libc::close(original);
+                }
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+                return Err(io::Error::other("stderr isn't open, or can't be redirected"));
+            }
+            // Stderr is the pipe's only write end from here on
+            libc::close(fds[1]);
+
+            // Whatever asks Windows for standard error, Rust's own stderr
+            // included, gets the pipe too
+            let saved = Saved {
+                fd: original,
+                handle: GetStdHandle(STD_ERROR_HANDLE) as isize,
+            };
+            SetStdHandle(STD_ERROR_HANDLE, libc::get_osfhandle(STDERR_FILENO) as HANDLE);
+
+            // The read end as a handle of its own, the runtime's descriptor
+            // closing its copy
+            let read = libc::get_osfhandle(fds[0]) as RawHandle;
+            let pipe = ManuallyDrop::new(File::from_raw_handle(read)).try_clone();
+            libc::close(fds[0]);
+            match pipe {
+                Ok(pipe) => Ok((saved, pipe)),
+                Err(e) => {
+                    put_back(saved);
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Point stderr back at `original`, closing the pipe's write end
+    pub fn put_back(original: Saved) {
+        unsafe {
+            SetStdHandle(STD_ERROR_HANDLE, original.handle as HANDLE);
+            libc::dup2(original.fd, STDERR_FILENO);
+            libc::close(original.fd);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    use std::fs::File;
+    use std::io;
+
+    pub type Saved = ();
+
+    /// Not done here, so driver output still reaches the console
+    pub fn redirect() -> io::Result<(Saved, File)> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "stderr redirection is only supported on Unix and Windows",
+        ))
+    }
+
+    pub fn put_back(_original: Saved) {}
+}
+
+#[cfg(all(test, any(unix, windows)))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Write to stderr as the C library does, past Rust's stderr handle
+    fn write_c_stderr(bytes: &[u8]) -> usize {
+        #[cfg(unix)]
+        let written =
+            unsafe { libc::write(libc::STDERR_FILENO, bytes.as_ptr().cast(), bytes.len()) };
+        #[cfg(windows)]
+        let written = unsafe {
+            libc::write(sys::STDERR_FILENO, bytes.as_ptr().cast(), bytes.len() as libc::c_uint)
+        };
+        written as usize
+    }
+
+    /// Held by each test while it has stderr
+    static STDERR: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_stderr_lines_reach_sink() {
+        let _stderr = STDERR.lock();
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        let capture = StderrCapture::start(move |line| sink.lock().push(line.to_string())).unwrap();
+
+        let message = b"usb_claim_interface error -6\r\n\nFound Rafael Micro R820T tuner";
+        assert_eq!(write_c_stderr(message), message.len());
+
+        capture.restore();
+        assert_eq!(
+            *lines.lock(),
+            [
+                "usb_claim_interface error -6",
+                "Found Rafael Micro R820T tuner"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_standard_error_handle_follows() {
+        use std::io::Write;
+
+        let _stderr = STDERR.lock();
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        let capture = StderrCapture::start(move |line| sink.lock().push(line.to_string())).unwrap();
+
+        // Rust's stderr asks Windows for the handle at each write
+        writeln!(io::stderr(), "rtlsdr_demod_write_reg failed with -9").unwrap();
+        capture.restore();
+        assert_eq!(*lines.lock(), ["rtlsdr_demod_write_reg failed with -9"]);
+    }
+}
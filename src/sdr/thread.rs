@@ -1,62 +1,65 @@
-use super::samples_u8_to_complex;
-use crate::state::SharedState;
-use crate::types::Command;
-use anyhow::Result;
+use super::gain_profile::Crossing;
+use super::watchdog::{Watch, Watchdog, CHECK_INTERVAL};
+use super::{Capabilities, SampleBuffer, SampleSink, SdrSource};
+use crate::dsp::channelizer;
+use crate::recorder::{RecorderEvent, Retune, RetunePolicy};
+use crate::state::{RecordingMode, SharedState, VfoConfig};
+use crate::types::{Chain, Command, DemodMode};
+use anyhow::{bail, Result};
 use crossbeam::channel::{Receiver, Sender};
-use num_complex::Complex;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
-/// Start the SDR acquisition thread with real RTL-SDR hardware
+/// How long a stalled source's acquisition thread gets to end once stopped
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Opens the receiver again after its stream has stalled
+pub type Reopen = Box<dyn FnMut() -> Result<Box<dyn SdrSource>> + Send>;
+
+/// Start the SDR acquisition thread streaming from `source`, and the command
+/// thread that drives it; the handle is the command thread's, which joins
+/// the acquisition thread on its way out
+///
+/// The command thread also watches the stream; once it stalls the source is
+/// closed and opened again with `reopen`, if given, or only reported if not.
 pub fn start_sdr_thread(
-    device_index: usize,
+    mut source: Box<dyn SdrSource>,
+    reopen: Option<Reopen>,
     state: SharedState,
-    samples_tx: Sender<Vec<Complex<f32>>>,
+    samples_tx: Sender<SampleBuffer>,
     command_rx: Receiver<Command>,
+    recorder_tx: Sender<RecorderEvent>,
     shutdown: Arc<AtomicBool>,
 ) -> Result<thread::JoinHandle<()>> {
-    log::info!("Opening RTL-SDR device {}...", device_index);
-
-    // Suppress librtlsdr stderr output to prevent TUI corruption
-    // The RTL-SDR library prints tuner errors directly to stderr which we cannot control
-    suppress_stderr();
-
-    // Open RTL-SDR device
-    let (mut controller, mut reader) = rtlsdr_mt::open(device_index as u32)
-        .map_err(|e| anyhow::anyhow!("Failed to open RTL-SDR device {}: {:?}", device_index, e))?;
-
-    // Get initial configuration from state
-    let initial_freq = state.read().sdr.frequency;
-    let initial_rate = state.read().sdr.sample_rate;
-    let initial_gain = state.read().sdr.tuner_gain;
-
-    // Configure device
-    log::info!("Configuring RTL-SDR...");
-    controller.set_center_freq(initial_freq)
-        .map_err(|e| anyhow::anyhow!("Failed to set frequency: {:?}", e))?;
-    controller.set_sample_rate(initial_rate)
-        .map_err(|e| anyhow::anyhow!("Failed to set sample rate: {:?}", e))?;
-
-    if initial_gain == -1 {
-        controller.enable_agc()
-            .map_err(|e| anyhow::anyhow!("Failed to enable AGC: {:?}", e))?;
-        log::info!("AGC enabled");
-    } else {
-        controller.disable_agc()
-            .map_err(|e| anyhow::anyhow!("Failed to disable AGC: {:?}", e))?;
-        controller.set_tuner_gain(initial_gain)
-            .map_err(|e| anyhow::anyhow!("Failed to set gain: {:?}", e))?;
-        log::info!("Gain set to {}.{} dB", initial_gain / 10, initial_gain % 10);
-    }
+    configure(source.as_mut(), &state)?;
 
-    log::info!("RTL-SDR configured: {} Hz, {} S/s", initial_freq, initial_rate);
+    // Start streaming before the command thread takes the source
+    let sink_state = state.clone();
+    let sink_recorder_tx = recorder_tx.clone();
+    let sink_shutdown = shutdown.clone();
+    let new_sink = move || {
+        SampleSink::new(
+            sink_state.clone(),
+            samples_tx.clone(),
+            sink_recorder_tx.clone(),
+            sink_shutdown.clone(),
+        )
+    };
+    let mut acquisition = Some(source.start(new_sink())?);
 
     // Spawn command processing thread
     let cmd_shutdown = shutdown.clone();
     let cmd_state = state.clone();
-    thread::spawn(move || {
+    let cmd_recorder_tx = recorder_tx.clone();
+    let mut reopen = reopen;
+    let stall_timeout = state.read().sdr.stall_timeout;
+    let handle = thread::spawn(move || {
         log::info!("SDR command processing thread started");
+        let mut watchdog = stall_timeout.map(|timeout| Watchdog::new(timeout, Instant::now()));
+        let check_interval = stall_timeout.map_or(CHECK_INTERVAL, |t| t.min(CHECK_INTERVAL));
+        let mut next_check = Instant::now() + check_interval;
 
         loop {
             // Check for shutdown
@@ -65,99 +68,190 @@ pub fn start_sdr_thread(
                 break;
             }
 
+            if let Some(watchdog) = watchdog.as_mut().filter(|_| Instant::now() >= next_check) {
+                next_check = Instant::now() + check_interval;
+                watch_stream(
+                    watchdog,
+                    &mut source,
+                    &mut acquisition,
+                    reopen.as_mut(),
+                    &cmd_state,
+                    &new_sink,
+                );
+            }
+
             // Process commands (blocking with timeout)
             match command_rx.recv_timeout(std::time::Duration::from_millis(100)) {
                 Ok(command) => {
                     match command {
                         Command::SetFrequency(freq) => {
-                            use crate::sdr::config::constraints;
-                            let clamped_freq = freq.clamp(constraints::MIN_FREQUENCY, constraints::MAX_FREQUENCY);
-                            if let Err(e) = controller.set_center_freq(clamped_freq) {
-                                log::error!("Failed to set frequency to {} Hz: {:?}", clamped_freq, e);
-                            } else {
-                                cmd_state.write().sdr.frequency = clamped_freq;
+                            let clamped_freq = source.capabilities().clamp_frequency(freq);
+                            if tune(source.as_mut(), &cmd_state, &cmd_recorder_tx, clamped_freq) {
                                 log::info!("Frequency changed to {} Hz ({:.3} MHz)", clamped_freq, clamped_freq as f64 / 1_000_000.0);
                             }
                         }
                         Command::IncreaseFrequency(delta) => {
-                            use crate::sdr::config::constraints;
                             let state_guard = cmd_state.write();
-                            let new_freq = state_guard.sdr.frequency
-                                .saturating_add(delta as u32)
-                                .clamp(constraints::MIN_FREQUENCY, constraints::MAX_FREQUENCY);
+                            let new_freq = source.capabilities().clamp_frequency(
+                                state_guard.sdr.frequency.saturating_add(delta as u32),
+                            );
                             drop(state_guard); // Release lock before device call
 
-                            if let Err(e) = controller.set_center_freq(new_freq) {
-                                log::error!("Failed to set frequency to {} Hz: {:?}", new_freq, e);
-                            } else {
-                                cmd_state.write().sdr.frequency = new_freq;
+                            if tune(source.as_mut(), &cmd_state, &cmd_recorder_tx, new_freq) {
                                 log::info!("Frequency increased to {} Hz ({:.3} MHz)", new_freq, new_freq as f64 / 1_000_000.0);
                             }
                         }
                         Command::DecreaseFrequency(delta) => {
-                            use crate::sdr::config::constraints;
                             let state_guard = cmd_state.write();
-                            let new_freq = state_guard.sdr.frequency
-                                .saturating_sub(delta as u32)
-                                .clamp(constraints::MIN_FREQUENCY, constraints::MAX_FREQUENCY);
+                            let new_freq = source.capabilities().clamp_frequency(
+                                state_guard.sdr.frequency.saturating_sub(delta as u32),
+                            );
                             drop(state_guard); // Release lock before device call
 
-                            if let Err(e) = controller.set_center_freq(new_freq) {
-                                log::error!("Failed to set frequency to {} Hz: {:?}", new_freq, e);
-                            } else {
-                                cmd_state.write().sdr.frequency = new_freq;
+                            if tune(source.as_mut(), &cmd_state, &cmd_recorder_tx, new_freq) {
                                 log::info!("Frequency decreased to {} Hz ({:.3} MHz)", new_freq, new_freq as f64 / 1_000_000.0);
                             }
                         }
                         Command::SetSampleRate(rate) => {
-                            if let Err(e) = controller.set_sample_rate(rate) {
-                                log::error!("Failed to set sample rate: {:?}", e);
+                            if let Err(e) = source.set_sample_rate(rate) {
+                                log::error!("Failed to set sample rate: {}", e);
                             } else {
                                 cmd_state.write().sdr.sample_rate = rate;
                                 log::info!("Sample rate changed to {} Hz", rate);
                             }
                         }
                         Command::SetTunerGain(gain) => {
-                            if let Err(e) = controller.set_tuner_gain(gain) {
-                                log::error!("Failed to set gain: {:?}", e);
+                            if let Err(e) = source.set_gain(Some(gain)) {
+                                log::error!("Failed to set gain: {}", e);
                             } else {
-                                cmd_state.write().sdr.tuner_gain = gain;
-                                cmd_state.write().sdr.auto_gain = false;
+                                let mut state_guard = cmd_state.write();
+                                state_guard.sdr.tuner_gain = gain;
+                                state_guard.sdr.tuner_agc = false;
+                                if let Some(profiles) = &mut state_guard.sdr.gain_profiles {
+                                    profiles.manual();
+                                }
                                 log::info!("Gain set to {}.{} dB", gain / 10, gain % 10);
                             }
                         }
-                        Command::SetAutoGain(auto) => {
-                            if auto {
-                                if let Err(e) = controller.enable_agc() {
-                                    log::error!("Failed to enable AGC: {:?}", e);
-                                } else {
-                                    cmd_state.write().sdr.tuner_gain = -1;
-                                    cmd_state.write().sdr.auto_gain = true;
-                                    log::info!("AGC enabled");
-                                }
+                        Command::SetTunerAgc(auto) => {
+                            let result = if auto {
+                                source.set_gain(None)
                             } else {
-                                if let Err(e) = controller.disable_agc() {
-                                    log::error!("Failed to disable AGC: {:?}", e);
-                                } else {
-                                    cmd_state.write().sdr.auto_gain = false;
-                                    log::info!("AGC disabled");
+                                // Restore the last manual gain, or a sane default if there was none
+                                let range = source.capabilities().gain.clone();
+                                let gain = match cmd_state.read().sdr.tuner_gain {
+                                    -1 => 200.clamp(*range.start(), *range.end()),
+                                    gain => gain,
+                                };
+                                source.set_gain(Some(gain))
+                                    .map(|_| cmd_state.write().sdr.tuner_gain = gain)
+                            };
+                            if let Err(e) = result {
+                                log::error!("Failed to change tuner gain mode: {}", e);
+                            } else {
+                                let mut state_guard = cmd_state.write();
+                                state_guard.sdr.tuner_agc = auto;
+                                if let Some(profiles) = &mut state_guard.sdr.gain_profiles {
+                                    profiles.manual();
                                 }
                             }
                         }
+                        Command::SetRtlAgc(enabled) => {
+                            if let Err(e) = source.set_rtl_agc(enabled) {
+                                log::error!("{}", e);
+                            } else {
+                                cmd_state.write().sdr.rtl_agc = enabled;
+                            }
+                        }
                         Command::SetPpmError(ppm) => {
-                            if let Err(e) = controller.set_ppm(ppm) {
-                                log::error!("Failed to set PPM: {:?}", e);
+                            if let Err(e) = source.set_ppm(ppm) {
+                                log::error!("Failed to set PPM: {}", e);
                             } else {
                                 cmd_state.write().sdr.ppm_error = ppm;
                                 log::info!("PPM set to {}", ppm);
                             }
                         }
+                        Command::SetMode(mode) => {
+                            cmd_state.write().decoder.switch_mode(mode);
+                            log::info!("Mode set to {}", mode.name());
+                            set_rate_for_mode(source.as_mut(), &cmd_state, mode);
+                        }
+                        Command::SetChannelOffset(chain, offset) => {
+                            let mut state_guard = cmd_state.write();
+                            // Stay within the captured bandwidth
+                            let half = (state_guard.sdr.sample_rate / 2) as i32;
+                            let offset = offset.clamp(-half, half);
+                            match chain {
+                                Chain::A => state_guard.channels.offset_a = offset,
+                                Chain::B => state_guard.channels.offset_b = offset,
+                            }
+                            log::info!("Channel {} offset set to {} Hz", chain.name(), offset);
+                        }
+                        Command::SetChannelMode(chain, mode) => {
+                            match chain {
+                                Chain::A => cmd_state.write().decoder.switch_mode(mode),
+                                Chain::B => cmd_state.write().channels.mode_b = mode,
+                            }
+                            log::info!("Channel {} mode set to {}", chain.name(), mode.name());
+                        }
+                        Command::SetFilterWidth(width) => {
+                            let mut state_guard = cmd_state.write();
+                            let mode = state_guard.decoder.mode;
+                            match channelizer::clamp_width(mode, width) {
+                                Some(width) => {
+                                    state_guard.decoder.filter_width = Some(width);
+                                    log::info!("Filter width set to {} Hz", width);
+                                }
+                                None => log::warn!("{} has a fixed filter width", mode.name()),
+                            }
+                        }
+                        Command::SwapVfo | Command::CopyVfo => {
+                            let (target, active) = {
+                                let mut state_guard = cmd_state.write();
+                                let target = if command == Command::SwapVfo {
+                                    Some(state_guard.swap_vfo())
+                                } else {
+                                    state_guard.copy_vfo()
+                                };
+                                (target, state_guard.sdr.active_vfo)
+                            };
+                            log::info!("VFO {} active", VfoConfig::name(active));
+                            if let Some(vfo) = target {
+                                let tuned =
+                                    tune_to_vfo(source.as_mut(), &cmd_state, &cmd_recorder_tx, vfo);
+                                // The VFO that was active stays so if the receiver didn't retune
+                                if !tuned && command == Command::SwapVfo {
+                                    cmd_state.write().sdr.active_vfo ^= 1;
+                                }
+                            }
+                        }
+                        Command::StartRecording(path) => {
+                            let _ = cmd_recorder_tx.send(RecorderEvent::Start(path));
+                        }
+                        Command::StartSquelchRecording => {
+                            let _ = cmd_recorder_tx.send(RecorderEvent::StartSquelch);
+                        }
+                        Command::StopRecording => {
+                            let _ = cmd_recorder_tx.send(RecorderEvent::Stop);
+                        }
                         Command::Quit => {
                             log::info!("SDR command thread received quit command");
                             break;
                         }
-                        _ => {} // Ignore other commands
                     }
+                    // A new mode, width or offset can bring the DC spike
+                    // into the channel or take it out
+                    let (frequency, offset, wanted) = {
+                        let state_guard = cmd_state.read();
+                        let tunable = &source.capabilities().frequency;
+                        let frequency = state_guard.sdr.frequency;
+                        let wanted = state_guard.hardware_offset_for(frequency, tunable);
+                        (frequency, state_guard.sdr.hardware_offset, wanted)
+                    };
+                    if wanted != offset {
+                        tune(source.as_mut(), &cmd_state, &cmd_recorder_tx, frequency);
+                    }
+                    cmd_state.write().sync_vfo();
                 }
                 Err(crossbeam::channel::RecvTimeoutError::Timeout) => {
                     // No command, continue
@@ -169,61 +263,922 @@ pub fn start_sdr_thread(
             }
         }
 
+        source.stop();
+        if let Some(acquisition) = acquisition {
+            let _ = acquisition.join();
+        }
         log::info!("SDR command processing thread stopped");
     });
 
-    // Spawn the sample reading thread
-    let handle = thread::spawn(move || {
-        log::info!("SDR acquisition thread started");
-
-        // Read samples asynchronously
-        // Buffer params: 32 buffers of 16384 samples each (must be multiple of 512)
-        let result = reader.read_async(32, 16384, |bytes| {
-            // Check for shutdown (note: we can't early return from this callback,
-            // so we just skip processing when shutting down)
-            if !shutdown.load(Ordering::Relaxed) {
-                // Convert u8 I/Q samples to Complex<f32>
-                let samples = samples_u8_to_complex(bytes);
-
-                // Send to DSP thread (non-blocking)
-                if samples_tx.try_send(samples).is_err() {
-                    // DSP thread is slow, drop this buffer
-                    log::warn!("Dropping samples due to backpressure");
-                }
-            }
-        });
+    Ok(handle)
+}
+
+/// Configure `source` as the state has it, and take its limits into the state
+fn configure(source: &mut dyn SdrSource, state: &SharedState) -> Result<()> {
+    let frequency = state.read().sdr.frequency;
+    let rate = state.read().sdr.sample_rate;
+    let gain = state.read().sdr.tuner_gain;
+    let tuner_agc = state.read().sdr.tuner_agc;
+    let rtl_agc = state.read().sdr.rtl_agc;
+    let ppm = state.read().sdr.ppm_error;
+
+    log::info!("Configuring {}...", source.describe());
+    let frequency = source.capabilities().clamp_frequency(frequency);
+    let offset = state
+        .read()
+        .hardware_offset_for(frequency, &source.capabilities().frequency);
+    source.set_frequency(frequency.saturating_add_signed(offset))?;
+    source.set_sample_rate(rate)?;
+
+    if tuner_agc || gain == -1 {
+        source.set_gain(None)?;
+    } else {
+        source.set_gain(Some(gain))?;
+        log::info!("Gain set to {}.{} dB", gain / 10, gain % 10);
+    }
+    // Only RTL-SDRs have one; anything else runs without it
+    if let Err(e) = source.set_rtl_agc(rtl_agc) {
+        log::warn!("{:#}", e);
+        state.write().sdr.rtl_agc = false;
+    }
+    if ppm != 0 {
+        source.set_ppm(ppm)?;
+    }
 
-        if let Err(e) = result {
-            log::error!("SDR read_async error: {:?}", e);
+    {
+        let mut state_guard = state.write();
+        state_guard.sdr.frequency = frequency;
+        state_guard.sdr.hardware_offset = offset;
+        state_guard.sdr.capabilities = source.capabilities().clone();
+        state_guard.sync_vfo();
+    }
+    log::info!("{} configured: {} Hz, {} S/s", source.describe(), frequency, rate);
+    Ok(())
+}
+
+/// Check on the sample stream, reopening the receiver with `reopen` once it
+/// has stalled
+fn watch_stream(
+    watchdog: &mut Watchdog,
+    source: &mut Box<dyn SdrSource>,
+    acquisition: &mut Option<thread::JoinHandle<()>>,
+    reopen: Option<&mut Reopen>,
+    state: &SharedState,
+    new_sink: &dyn Fn() -> SampleSink,
+) {
+    let (last_samples, paused) = {
+        let state_guard = state.read();
+        let paused = state_guard.playback.as_ref().is_some_and(|p| p.paused);
+        (state_guard.sdr.last_samples, paused)
+    };
+    match watchdog.check(Instant::now(), last_samples, paused) {
+        Watch::Running => {}
+        Watch::Resumed => {
+            log::info!("Samples are arriving from {} again", source.describe());
+            let mut state_guard = state.write();
+            state_guard.sdr.stall_warning = None;
+            state_guard.ui.status_message = "Receiver recovered".to_string();
+            state_guard.changed.notify();
         }
+        Watch::Stalled { quiet } => {
+            let quiet = quiet.as_secs();
+            log::warn!("No samples from {} for {} s", source.describe(), quiet);
+            let warning = match reopen {
+                Some(reopen) => match reconnect(source, acquisition, reopen, state, new_sink) {
+                    Ok(()) => format!("No samples for {}s - receiver reopened", quiet),
+                    Err(e) => {
+                        log::error!("Failed to reopen the receiver: {:#}", e);
+                        format!("No samples for {}s - reopening failed: {}", quiet, e)
+                    }
+                },
+                None => format!("No samples for {}s", quiet),
+            };
+            let mut state_guard = state.write();
+            state_guard.sdr.stall_warning = Some(warning.clone());
+            state_guard.ui.status_message = warning;
+            state_guard.changed.notify();
+        }
+    }
+}
 
-        log::info!("SDR acquisition thread stopped");
-    });
+/// Close `source` once its acquisition thread has ended, open it again with
+/// `reopen`, configure it as it was and stream into a new sink; left closed
+/// if that fails
+fn reconnect(
+    source: &mut Box<dyn SdrSource>,
+    acquisition: &mut Option<thread::JoinHandle<()>>,
+    reopen: &mut Reopen,
+    state: &SharedState,
+    new_sink: &dyn Fn() -> SampleSink,
+) -> Result<()> {
+    source.stop();
+    if let Some(stopping) = acquisition.take() {
+        join_stopped(stopping, &source.describe());
+    }
+    // The device has to be closed before it will open again
+    let closed = Closed {
+        name: source.describe(),
+        capabilities: source.capabilities().clone(),
+    };
+    drop(std::mem::replace(source, Box::new(closed)));
 
-    Ok(handle)
+    let mut reopened = reopen()?;
+    configure(reopened.as_mut(), state)?;
+    // The new acquisition thread ends with shutdown or the next stall
+    *acquisition = Some(reopened.start(new_sink())?);
+    log::info!("{} reopened", reopened.describe());
+    *source = reopened;
+    Ok(())
+}
+
+/// Wait for a stopped source's acquisition thread to end, up to
+/// [`STOP_TIMEOUT`]; a driver stuck in a read is left behind rather than
+/// holding up the reopen
+fn join_stopped(acquisition: thread::JoinHandle<()>, name: &str) {
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while !acquisition.is_finished() {
+        if Instant::now() >= deadline {
+            log::warn!("{} didn't stop within {:?}; reopening anyway", name, STOP_TIMEOUT);
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let _ = acquisition.join();
+}
+
+/// Stands in for a receiver closed to be reopened, until it is
+struct Closed {
+    name: String,
+    capabilities: Capabilities,
+}
+
+impl SdrSource for Closed {
+    fn describe(&self) -> String {
+        format!("{} (closed)", self.name)
+    }
+
+    fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    fn set_frequency(&mut self, _freq: u32) -> Result<()> {
+        bail!("{} is being reopened", self.name)
+    }
+
+    fn set_sample_rate(&mut self, _rate: u32) -> Result<()> {
+        bail!("{} is being reopened", self.name)
+    }
+
+    fn set_gain(&mut self, _gain: Option<i32>) -> Result<()> {
+        bail!("{} is being reopened", self.name)
+    }
+
+    fn set_ppm(&mut self, _ppm: i32) -> Result<()> {
+        bail!("{} is being reopened", self.name)
+    }
+
+    fn start(&mut self, _sink: SampleSink) -> Result<thread::JoinHandle<()>> {
+        bail!("{} is being reopened", self.name)
+    }
 }
 
-/// Suppress stderr to prevent librtlsdr from corrupting the TUI
-/// The RTL-SDR C library prints tuner errors directly to stderr which we cannot intercept
-#[cfg(unix)]
-fn suppress_stderr() {
-    use std::os::unix::io::AsRawFd;
+/// Switch the sample rate and tuner bandwidth to suit `mode`, when the
+/// receiver picks them automatically
+fn set_rate_for_mode(source: &mut dyn SdrSource, state: &SharedState, mode: DemodMode) {
+    let (auto_rate, current) = {
+        let state_guard = state.read();
+        (state_guard.sdr.auto_rate, state_guard.sdr.sample_rate)
+    };
+    let change = auto_rate
+        .then(|| source.capabilities().rate_for_mode(mode, current))
+        .flatten();
+    let Some(change) = change else {
+        return;
+    };
+    if let Err(e) = source.set_sample_rate(change.rate) {
+        log::error!("Failed to set sample rate: {}", e);
+        return;
+    }
+    if let Err(e) = source.set_bandwidth(change.bandwidth) {
+        log::warn!("Failed to set tuner bandwidth: {}", e);
+    }
+    let mut state_guard = state.write();
+    state_guard.sdr.sample_rate = change.rate;
+    state_guard.ui.status_message = format!(
+        "{}: sample rate {:.3} MS/s, {} (override on Sample Rate)",
+        mode.name(),
+        change.rate as f64 / 1_000_000.0,
+        change.reason
+    );
+    log::info!(
+        "Sample rate changed to {} Hz for {}: {}",
+        change.rate,
+        mode.name(),
+        change.reason
+    );
+}
 
-    unsafe {
-        // Open /dev/null
-        let null_path = std::ffi::CString::new("/dev/null").unwrap();
-        let null_fd = libc::open(null_path.as_ptr(), libc::O_WRONLY);
+/// Retune the center to `frequency`, the hardware to one side of it if the
+/// DC spike would otherwise be heard; false if the receiver couldn't or
+/// mustn't retune
+fn tune(
+    source: &mut dyn SdrSource,
+    state: &SharedState,
+    recorder_tx: &Sender<RecorderEvent>,
+    frequency: u32,
+) -> bool {
+    let (offset, current) = {
+        let state_guard = state.read();
+        let offset = state_guard.hardware_offset_for(frequency, &source.capabilities().frequency);
+        let current = state_guard.sdr.hardware_frequency();
+        let recording = &state_guard.recording;
+        let blocked = recording.is_recording
+            && recording.mode == RecordingMode::Iq
+            && recording.retune == RetunePolicy::Block;
+        if blocked && frequency.saturating_add_signed(offset) != current {
+            drop(state_guard);
+            log::warn!("Not retuning to {} Hz during an IQ recording", frequency);
+            state.write().ui.status_message =
+                "Retuning is blocked while recording IQ; stop the recording first".to_string();
+            return false;
+        }
+        (offset, current)
+    };
+    let hardware = frequency.saturating_add_signed(offset);
+    if let Err(e) = source.set_frequency(hardware) {
+        log::error!("Failed to set frequency to {} Hz: {}", hardware, e);
+        return false;
+    }
+    let capabilities = source.capabilities();
+    if capabilities.gap_at(current).is_none() {
+        if let Some(warning) = capabilities.gap_warning(hardware) {
+            log::warn!("Tuned to {} Hz: {}", hardware, warning);
+        }
+    }
+    // The new frequency counts from the next sample delivered
+    let (sample_index, crossing, priority) = {
+        let mut state_guard = state.write();
+        state_guard.sdr.frequency = frequency;
+        state_guard.sdr.hardware_offset = offset;
+        let crossing = state_guard
+            .sdr
+            .gain_profiles
+            .as_mut()
+            .and_then(|profiles| profiles.retune(frequency));
+        (
+            state_guard.sdr.samples_delivered,
+            crossing,
+            state_guard.priority.away(),
+        )
+    };
+    if let Some(crossing) = crossing {
+        follow_gain_profile(source, state, crossing);
+    }
+    if offset != 0 {
+        log::info!("Hardware tuned to {} Hz to keep the DC spike out", hardware);
+    }
+    // IQ recordings are of what the hardware is tuned to
+    if hardware != current {
+        let retune = Retune {
+            frequency,
+            hardware,
+            sample_index,
+            priority,
+        };
+        let _ = recorder_tx.send(RecorderEvent::Retune(retune));
+    }
+    true
+}
 
-        if null_fd >= 0 {
-            // Redirect stderr (fd 2) to /dev/null
-            libc::dup2(null_fd, libc::STDERR_FILENO);
-            libc::close(null_fd);
+/// Set the gain of a range the receiver has crossed into, or say why not
+fn follow_gain_profile(source: &mut dyn SdrSource, state: &SharedState, crossing: Crossing) {
+    let status = match crossing {
+        Crossing::Kept(range) => {
+            format!("Gain profile {} (kept the gain set by hand)", range.describe())
         }
+        Crossing::Apply(range) => match source.set_gain(range.gain) {
+            Ok(()) => {
+                let mut state_guard = state.write();
+                state_guard.sdr.tuner_agc = range.gain.is_none();
+                if let Some(gain) = range.gain {
+                    state_guard.sdr.tuner_gain = gain;
+                }
+                format!("Gain profile {}", range.describe())
+            }
+            Err(e) => {
+                log::error!("Failed to set the gain profile's gain: {}", e);
+                format!("Gain profile {} failed: {}", range.describe(), e)
+            }
+        },
+    };
+    log::info!("{}", status);
+    state.write().ui.status_message = status;
+}
+
+/// Retune the center onto `vfo` and give chain A its mode and filter width;
+/// false if the receiver couldn't retune
+fn tune_to_vfo(
+    source: &mut dyn SdrSource,
+    state: &SharedState,
+    recorder_tx: &Sender<RecorderEvent>,
+    vfo: VfoConfig,
+) -> bool {
+    let frequency = source.capabilities().clamp_frequency(vfo.frequency);
+    if !tune(source, state, recorder_tx, frequency) {
+        return false;
+    }
+    let mode_changed = {
+        let mut state_guard = state.write();
+        let changed = state_guard.decoder.mode != vfo.mode;
+        state_guard.tuned_to_vfo(vfo, frequency);
+        changed
+    };
+    log::info!("Tuned to {} Hz, {}", frequency, vfo.mode.name());
+    if mode_changed {
+        set_rate_for_mode(source, state, vfo.mode);
     }
+    true
 }
 
-#[cfg(not(unix))]
-fn suppress_stderr() {
-    // On non-Unix systems, we can't easily suppress stderr
-    // The TUI corruption will remain on Windows
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr::{samples_complex_to_u8, Capabilities};
+    use crate::state::{AppState, PlaybackState, RecordingMode};
+    use crate::types::DemodMode;
+    use crossbeam::channel;
+    use num_complex::Complex;
+    use parking_lot::Mutex;
+    use std::time::{Duration, Instant};
+
+    /// A receiver that logs what it is asked and streams `buffers` once
+    struct MockSource {
+        capabilities: Capabilities,
+        calls: Arc<Mutex<Vec<String>>>,
+        buffers: Vec<Vec<Complex<f32>>>,
+    }
+
+    impl MockSource {
+        /// Wider tuning than an RTL-SDR and a 0-15 dB gain range
+        fn new(buffers: Vec<Vec<Complex<f32>>>) -> (Self, Arc<Mutex<Vec<String>>>) {
+            let calls = Arc::new(Mutex::new(Vec::new()));
+            let source = Self {
+                capabilities: Capabilities {
+                    frequency: 1_000_000..=3_000_000_000,
+                    gain: 0..=150,
+                    ..Capabilities::rtl()
+                },
+                calls: calls.clone(),
+                buffers,
+            };
+            (source, calls)
+        }
+    }
+
+    impl SdrSource for MockSource {
+        fn describe(&self) -> String {
+            "mock".to_string()
+        }
+
+        fn capabilities(&self) -> &Capabilities {
+            &self.capabilities
+        }
+
+        fn set_frequency(&mut self, freq: u32) -> Result<()> {
+            self.calls.lock().push(format!("frequency {}", freq));
+            Ok(())
+        }
+
+        fn set_sample_rate(&mut self, rate: u32) -> Result<()> {
+            self.calls.lock().push(format!("rate {}", rate));
+            Ok(())
+        }
+
+        fn set_bandwidth(&mut self, bandwidth: u32) -> Result<()> {
+            self.calls.lock().push(format!("bandwidth {}", bandwidth));
+            Ok(())
+        }
+
+        fn set_gain(&mut self, gain: Option<i32>) -> Result<()> {
+            self.calls.lock().push(format!("gain {:?}", gain));
+            Ok(())
+        }
+
+        fn set_ppm(&mut self, ppm: i32) -> Result<()> {
+            self.calls.lock().push(format!("ppm {}", ppm));
+            Ok(())
+        }
+
+        fn start(&mut self, sink: SampleSink) -> Result<thread::JoinHandle<()>> {
+            let buffers = std::mem::take(&mut self.buffers);
+            Ok(thread::spawn(move || {
+                for buffer in buffers {
+                    sink.push(buffer);
+                }
+            }))
+        }
+
+        fn stop(&mut self) {
+            self.calls.lock().push("stop".to_string());
+        }
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !done() {
+            assert!(start.elapsed() < Duration::from_secs(2), "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn buffer(value: f32) -> Vec<Complex<f32>> {
+        vec![Complex::new(value, -value); 256]
+    }
+
+    #[test]
+    fn test_commands_use_source_capabilities() {
+        let state = AppState::new_shared();
+        let (source, calls) = MockSource::new(vec![buffer(0.25), buffer(0.5)]);
+        let (samples_tx, samples_rx) = channel::bounded(8);
+        let (command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, _recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        start_sdr_thread(
+            Box::new(source),
+            None,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+
+        // Configured from state, then streamed
+        assert_eq!(
+            calls.lock()[..3],
+            ["frequency 144390000", "rate 2048000", "gain None"]
+        );
+        assert_eq!(state.read().sdr.capabilities.gain, 0..=150);
+        let timeout = Duration::from_secs(2);
+        assert_eq!(samples_rx.recv_timeout(timeout).unwrap().samples, buffer(0.25));
+        let second = samples_rx.recv_timeout(timeout).unwrap();
+        assert_eq!(second.samples, buffer(0.5));
+        assert_eq!(second.sample_index, 256);
+        assert_eq!(second.center_freq, 144_390_000);
+
+        // Beyond an RTL-SDR but within this receiver, then beyond it too
+        command_tx.send(Command::SetFrequency(2_500_000_000)).unwrap();
+        wait_for(|| state.read().sdr.frequency == 2_500_000_000);
+        command_tx.send(Command::SetFrequency(4_000_000_000)).unwrap();
+        wait_for(|| state.read().sdr.frequency == 3_000_000_000);
+
+        // Leaving auto gain starts within the receiver's range
+        command_tx.send(Command::SetTunerAgc(false)).unwrap();
+        wait_for(|| !state.read().sdr.tuner_agc);
+        assert_eq!(state.read().sdr.tuner_gain, 150);
+        assert!(calls.lock().contains(&"gain Some(150)".to_string()));
+
+        // There is no RTL AGC to turn on
+        command_tx.send(Command::SetRtlAgc(true)).unwrap();
+        command_tx.send(Command::SetPpmError(3)).unwrap();
+        wait_for(|| state.read().sdr.ppm_error == 3);
+        assert!(!state.read().sdr.rtl_agc);
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_mode_change_picks_sample_rate() {
+        let state = AppState::new_shared();
+        let (source, calls) = MockSource::new(vec![]);
+        let (samples_tx, _samples_rx) = channel::bounded(8);
+        let (command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, _recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        start_sdr_thread(
+            Box::new(source),
+            None,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+
+        command_tx.send(Command::SetMode(DemodMode::Adsb)).unwrap();
+        wait_for(|| state.read().sdr.sample_rate == 2_400_000);
+        assert!(calls.lock().ends_with(&["rate 2400000".to_string(), "bandwidth 0".to_string()]));
+        assert!(state.read().ui.status_message.contains("Mode S needs 2.4 MS/s"));
+
+        // A rate set by hand holds until the mode changes again
+        command_tx.send(Command::SetSampleRate(2_048_000)).unwrap();
+        wait_for(|| state.read().sdr.sample_rate == 2_048_000);
+        command_tx.send(Command::SetMode(DemodMode::Am)).unwrap();
+        wait_for(|| state.read().sdr.sample_rate == 1_024_000);
+        assert!(calls.lock().contains(&"bandwidth 1000000".to_string()));
+
+        // Turned off, the mode changes alone
+        state.write().sdr.auto_rate = false;
+        command_tx.send(Command::SetMode(DemodMode::FmWide)).unwrap();
+        wait_for(|| state.read().decoder.mode == DemodMode::FmWide);
+        assert_eq!(state.read().sdr.sample_rate, 1_024_000);
+
+        // Filter widths stay within the mode's limits and reset with it
+        command_tx.send(Command::SetFilterWidth(500_000)).unwrap();
+        wait_for(|| state.read().decoder.filter_width == Some(250_000));
+        command_tx.send(Command::SetMode(DemodMode::Ais)).unwrap();
+        command_tx.send(Command::SetFilterWidth(10_000)).unwrap();
+        wait_for(|| state.read().decoder.mode == DemodMode::Ais);
+        command_tx.send(Command::SetSampleRate(2_048_000)).unwrap();
+        wait_for(|| state.read().sdr.sample_rate == 2_048_000);
+        assert_eq!(state.read().decoder.filter_width, None);
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_starts_without_rtl_agc() {
+        let state = AppState::new_shared();
+        state.write().sdr.rtl_agc = true;
+        let (source, _) = MockSource::new(vec![]);
+        let (samples_tx, _samples_rx) = channel::bounded(8);
+        let (_command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, _recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        // A receiver with no RTL AGC still starts, with it shown off
+        start_sdr_thread(
+            Box::new(source),
+            None,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+        assert!(!state.read().sdr.rtl_agc);
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_iq_recording_gets_8_bit_samples() {
+        let state = AppState::new_shared();
+        {
+            let mut state = state.write();
+            state.recording.is_recording = true;
+            state.recording.mode = RecordingMode::Iq;
+        }
+        let (source, _) = MockSource::new(vec![buffer(0.5)]);
+        let (samples_tx, samples_rx) = channel::bounded(8);
+        let (_command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        start_sdr_thread(
+            Box::new(source),
+            None,
+            state,
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+        // Sent to the recorder before the DSP thread
+        let timeout = Duration::from_secs(2);
+        assert_eq!(samples_rx.recv_timeout(timeout).unwrap().samples, buffer(0.5));
+
+        match recorder_rx.try_recv() {
+            Ok(RecorderEvent::Samples { bytes, first, .. }) => {
+                assert_eq!(first, 0);
+                assert_eq!(bytes, samples_complex_to_u8(&buffer(0.5)));
+                assert_eq!(&bytes[..2], [192, 64]);
+            }
+            _ => panic!("expected an IQ buffer for the recorder"),
+        }
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_retune_during_iq_recording() {
+        let state = AppState::new_shared();
+        {
+            let mut state = state.write();
+            state.sdr.auto_rate = false;
+            state.recording.is_recording = true;
+            state.recording.mode = RecordingMode::Iq;
+        }
+        let (source, calls) = MockSource::new(vec![buffer(0.25), buffer(0.5)]);
+        let (samples_tx, samples_rx) = channel::bounded(8);
+        let (command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        start_sdr_thread(
+            Box::new(source),
+            None,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+        for _ in 0..2 {
+            samples_rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        }
+
+        // The buffers are numbered through the stream, and the retune takes
+        // effect at the first sample after them
+        let firsts: Vec<u64> = recorder_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                RecorderEvent::Samples { first, .. } => Some(first),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(firsts, [0, 256]);
+        command_tx.send(Command::SetFrequency(162_550_000)).unwrap();
+        wait_for(|| state.read().sdr.frequency == 162_550_000);
+        match recorder_rx.try_recv() {
+            Ok(RecorderEvent::Retune(retune)) => assert_eq!(retune.sample_index, 512),
+            _ => panic!("expected a retune for the recorder"),
+        }
+
+        // Blocked, the receiver stays where it is until the recording stops
+        state.write().recording.retune = RetunePolicy::Block;
+        calls.lock().clear();
+        command_tx.send(Command::SetFrequency(146_520_000)).unwrap();
+        wait_for(|| state.read().ui.status_message.contains("blocked"));
+        assert_eq!(state.read().sdr.frequency, 162_550_000);
+        assert!(calls.lock().is_empty());
+        assert!(recorder_rx.try_recv().is_err());
+
+        state.write().recording.is_recording = false;
+        command_tx.send(Command::SetFrequency(146_520_000)).unwrap();
+        wait_for(|| state.read().sdr.frequency == 146_520_000);
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_gain_profiles() {
+        use crate::sdr::gain_profile::{GainProfiles, GainRange};
+
+        let state = AppState::new_shared();
+        state.write().sdr.auto_rate = false;
+        let ranges = vec![
+            GainRange {
+                start: 108_000_000,
+                end: 174_000_000,
+                gain: Some(402),
+            },
+            GainRange {
+                start: 1_085_000_000,
+                end: 1_095_000_000,
+                gain: None,
+            },
+        ];
+        state.write().sdr.gain_profiles = Some(GainProfiles::new(ranges, 500_000, 144_390_000));
+        let (source, calls) = MockSource::new(vec![]);
+        let (samples_tx, _samples_rx) = channel::bounded(8);
+        let (command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, _recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        start_sdr_thread(
+            Box::new(source),
+            None,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+
+        // Crossing into a range sets its gain
+        command_tx.send(Command::SetFrequency(1_090_000_000)).unwrap();
+        wait_for(|| state.read().ui.status_message.contains("auto gain"));
+        assert!(calls.lock().contains(&"gain None".to_string()));
+        assert!(state.read().sdr.tuner_agc);
+
+        // A gain set by hand is kept over the next crossing, then not
+        command_tx.send(Command::SetTunerGain(150)).unwrap();
+        command_tx.send(Command::SetFrequency(162_550_000)).unwrap();
+        wait_for(|| state.read().ui.status_message.contains("kept"));
+        assert_eq!(state.read().sdr.tuner_gain, 150);
+        command_tx.send(Command::SetFrequency(1_090_000_000)).unwrap();
+        command_tx.send(Command::SetFrequency(146_520_000)).unwrap();
+        wait_for(|| state.read().sdr.frequency == 146_520_000);
+        assert_eq!(state.read().sdr.tuner_gain, 402);
+        assert!(!state.read().sdr.tuner_agc);
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_vfo_swap_retunes_in_one_go() {
+        let state = AppState::new_shared();
+        state.write().sdr.frequency = 162_550_000;
+        let (source, calls) = MockSource::new(vec![]);
+        let (samples_tx, _samples_rx) = channel::bounded(8);
+        let (command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        start_sdr_thread(
+            Box::new(source),
+            None,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+        assert_eq!(state.read().sdr.vfo[0].frequency, 162_550_000);
+
+        // Give B a frequency, mode and width of its own
+        command_tx.send(Command::SwapVfo).unwrap();
+        command_tx.send(Command::SetFrequency(118_100_000)).unwrap();
+        command_tx.send(Command::SetMode(DemodMode::Am)).unwrap();
+        command_tx.send(Command::SetFilterWidth(6_000)).unwrap();
+        wait_for(|| state.read().sdr.vfo[1].filter_width == Some(6_000));
+        assert_eq!(state.read().sdr.active_vfo, 1);
+        assert_eq!(state.read().sdr.vfo[0].frequency, 162_550_000);
+
+        // Back to A: center, mode, width and rate all at once
+        calls.lock().clear();
+        command_tx.send(Command::SwapVfo).unwrap();
+        wait_for(|| state.read().decoder.mode == DemodMode::FmNarrow);
+        {
+            let state = state.read();
+            assert_eq!(state.sdr.frequency, 162_550_000);
+            assert_eq!(state.sdr.active_vfo, 0);
+            assert_eq!(state.decoder.filter_width, None);
+            assert_eq!(state.sdr.vfo[1].mode, DemodMode::Am);
+        }
+        assert_eq!(calls.lock()[0], "frequency 162550000");
+        assert!(recorder_rx.try_iter().any(|e| matches!(
+            e,
+            RecorderEvent::Retune(Retune { hardware: 162_550_000, .. })
+        )));
+
+        // Copying A to B while A is active leaves the receiver alone
+        calls.lock().clear();
+        command_tx.send(Command::CopyVfo).unwrap();
+        wait_for(|| state.read().sdr.vfo[1].mode == DemodMode::FmNarrow);
+        assert!(calls.lock().is_empty());
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_offset_tuning_keeps_the_frequency_shown() {
+        let state = AppState::new_shared();
+        {
+            let mut state = state.write();
+            state.sdr.frequency = 145_500_000;
+            state.sdr.auto_rate = false;
+            state.sdr.dc_avoidance = crate::dsp::dc::DcAvoidance::Offset;
+        }
+        let (source, calls) = MockSource::new(vec![]);
+        let (samples_tx, _samples_rx) = channel::bounded(8);
+        let (command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        start_sdr_thread(
+            Box::new(source),
+            None,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+        // NFM on the center: the hardware sits 250 kHz up from the start
+        assert_eq!(calls.lock()[0], "frequency 145750000");
+        assert_eq!(state.read().sdr.frequency, 145_500_000);
+
+        calls.lock().clear();
+        command_tx.send(Command::SetFrequency(146_520_000)).unwrap();
+        wait_for(|| state.read().sdr.frequency == 146_520_000);
+        assert_eq!(calls.lock()[0], "frequency 146770000");
+        assert_eq!(state.read().sdr.hardware_frequency(), 146_770_000);
+        assert_eq!(state.read().live_vfo().frequency, 146_520_000);
+        assert!(recorder_rx.try_iter().any(|e| matches!(
+            e,
+            RecorderEvent::Retune(Retune { frequency: 146_520_000, hardware: 146_770_000, .. })
+        )));
+
+        // Listening off the center the spike is out of the way, so the
+        // hardware comes back onto the frequency shown
+        calls.lock().clear();
+        command_tx.send(Command::SetChannelOffset(Chain::A, 300_000)).unwrap();
+        wait_for(|| state.read().sdr.hardware_offset == 0);
+        assert_eq!(*calls.lock(), ["frequency 146520000"]);
+        assert_eq!(state.read().sdr.frequency, 146_520_000);
+        assert_eq!(state.read().live_vfo().frequency, 146_820_000);
+
+        // A priority check's retune says so, for the recorder to keep the file
+        recorder_rx.try_iter().count();
+        state.write().priority.muted = true;
+        command_tx.send(Command::SetFrequency(162_550_000)).unwrap();
+        wait_for(|| state.read().sdr.frequency == 162_550_000);
+        assert!(recorder_rx.try_iter().any(|e| matches!(
+            e,
+            RecorderEvent::Retune(Retune { hardware: 162_550_000, priority: true, .. })
+        )));
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_stalled_receiver_is_reopened() {
+        let state = AppState::new_shared();
+        state.write().sdr.stall_timeout = Some(Duration::from_millis(300));
+        // Never streams, and its replacement streams once
+        let (source, calls) = MockSource::new(vec![]);
+        let (replacement, reopened_calls) = MockSource::new(vec![buffer(0.25)]);
+        let mut replacement = Some(replacement);
+        let reopen: Reopen = Box::new(move || match replacement.take() {
+            Some(source) => Ok(Box::new(source) as Box<dyn SdrSource>),
+            None => anyhow::bail!("RTL-SDR device 0 not found"),
+        });
+        let (samples_tx, samples_rx) = channel::bounded(8);
+        let (command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, _recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        start_sdr_thread(
+            Box::new(source),
+            Some(reopen),
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+        command_tx.send(Command::SetFrequency(146_520_000)).unwrap();
+        command_tx.send(Command::SetPpmError(3)).unwrap();
+        state.write().sdr.link_warning = Some("link trouble".to_string());
+
+        // Stopped, reopened and set up as it was left
+        wait_for(|| state.read().sdr.stall_warning.is_some());
+        let warning = state.read().sdr.warning().unwrap();
+        assert!(warning.starts_with("link trouble; No samples"), "{}", warning);
+        assert!(warning.ends_with("receiver reopened"), "{}", warning);
+        assert_eq!(calls.lock().last().unwrap(), "stop");
+        assert_eq!(
+            reopened_calls.lock()[..],
+            ["frequency 146520000", "rate 2048000", "gain None", "ppm 3"]
+        );
+
+        // The DSP thread notes the first buffer and the warning goes
+        assert_eq!(samples_rx.recv().unwrap().samples, buffer(0.25));
+        state.write().sdr.last_samples = Some(Instant::now());
+        wait_for(|| state.read().sdr.stall_warning.is_none());
+        assert_eq!(state.read().ui.status_message, "Receiver recovered");
+        // The link's own warning is the link's to clear
+        assert_eq!(state.read().sdr.warning().as_deref(), Some("link trouble"));
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_paused_playback_is_not_a_stall() {
+        let state = AppState::new_shared();
+        state.write().sdr.stall_timeout = Some(Duration::from_millis(100));
+        let mut playback = PlaybackState::new("pass.cu8", 2_048_000, 2_048_000 * 60);
+        playback.paused = true;
+        state.write().playback = Some(playback);
+        let (source, calls) = MockSource::new(vec![]);
+        let reopen: Reopen = Box::new(|| anyhow::bail!("a file can't be reopened"));
+        let (samples_tx, _samples_rx) = channel::bounded(8);
+        let (_command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, _recorder_rx) = channel::bounded(8);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        start_sdr_thread(
+            Box::new(source),
+            Some(reopen),
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        )
+        .unwrap();
+
+        // Several timeouts go by without a buffer
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(state.read().sdr.stall_warning, None);
+        assert!(!calls.lock().contains(&"stop".to_string()));
+
+        shutdown.store(true, Ordering::Relaxed);
+    }
 }
@@ -0,0 +1,147 @@
+//! Which tuner chip a dongle has, and what it can tune
+//!
+//! RTL-SDRs share the RTL2832 demodulator but not the tuner in front of it,
+//! and the tuner decides the frequency coverage and gain steps. librtlsdr
+//! reports it once the device is open, and an rtl_tcp server passes the same
+//! code on in its header. To list a device's tuner without using it, the
+//! device is opened briefly to ask.
+
+use std::ops::RangeInclusive;
+
+/// A tuner chip, as librtlsdr's `enum rtlsdr_tuner` numbers them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tuner {
+    E4000,
+    Fc0012,
+    Fc0013,
+    Fc2580,
+    R820t,
+    R828d,
+}
+
+/// Gain steps in tenths of dB, as librtlsdr lists them per tuner
+const E4000_GAINS: &[i32] = &[
+    -10, 15, 40, 65, 90, 115, 140, 165, 190, 215, 240, 290, 340, 420,
+];
+const FC0012_GAINS: &[i32] = &[-99, -40, 71, 179, 192];
+const FC0013_GAINS: &[i32] = &[
+    -99, -73, -65, -63, -60, -58, -54, 58, 61, 63, 65, 67, 68, 70, 71, 179, 181, 182, 184, 186,
+    188, 191, 197,
+];
+const FC2580_GAINS: &[i32] = &[0];
+
+impl Tuner {
+    /// The tuner for a librtlsdr tuner code, None for 0 (unknown) or a code
+    /// newer than this list
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1 => Some(Tuner::E4000),
+            2 => Some(Tuner::Fc0012),
+            3 => Some(Tuner::Fc0013),
+            4 => Some(Tuner::Fc2580),
+            5 => Some(Tuner::R820t),
+            6 => Some(Tuner::R828d),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tuner::E4000 => "E4000",
+            Tuner::Fc0012 => "FC0012",
+            Tuner::Fc0013 => "FC0013",
+            Tuner::Fc2580 => "FC2580",
+            Tuner::R820t => "R820T",
+            Tuner::R828d => "R828D",
+        }
+    }
+
+    /// Lowest to highest frequency it tunes, in Hz
+    pub fn frequency(&self) -> RangeInclusive<u32> {
+        match self {
+            Tuner::E4000 => 52_000_000..=2_200_000_000,
+            Tuner::Fc0012 => 22_000_000..=948_600_000,
+            Tuner::Fc0013 => 22_000_000..=1_100_000_000,
+            Tuner::Fc2580 => 146_000_000..=924_000_000,
+            Tuner::R820t | Tuner::R828d => 24_000_000..=1_766_000_000,
+        }
+    }
+
+    /// Stretches within [`frequency`](Self::frequency) the tuner can't
+    /// receive: its PLL doesn't lock there
+    pub fn gaps(&self) -> Vec<RangeInclusive<u32>> {
+        match self {
+            Tuner::E4000 => vec![1_100_000_000..=1_250_000_000],
+            Tuner::Fc2580 => vec![308_000_000..=438_000_000],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gain steps in tenths of dB, ascending
+    pub fn gains(&self) -> &'static [i32] {
+        match self {
+            Tuner::E4000 => E4000_GAINS,
+            Tuner::Fc0012 => FC0012_GAINS,
+            Tuner::Fc0013 => FC0013_GAINS,
+            Tuner::Fc2580 => FC2580_GAINS,
+            Tuner::R820t | Tuner::R828d => &super::config::R820T_GAINS,
+        }
+    }
+}
+
+impl std::fmt::Display for Tuner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The tuner of RTL-SDR `index`, opening and closing it to ask; None if it
+/// can't be opened (in use, or not there) or librtlsdr doesn't know it
+pub fn probe(index: usize) -> Option<Tuner> {
+    let (controller, _reader) = super::librtlsdr::open(index as u32).ok()?;
+    Tuner::from_code(controller.tuner_type())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuner_codes() {
+        assert_eq!(Tuner::from_code(0), None);
+        assert_eq!(Tuner::from_code(1), Some(Tuner::E4000));
+        assert_eq!(Tuner::from_code(5), Some(Tuner::R820t));
+        assert_eq!(Tuner::from_code(6), Some(Tuner::R828d));
+        assert_eq!(Tuner::from_code(7), None);
+        assert_eq!(Tuner::R828d.to_string(), "R828D");
+    }
+
+    #[test]
+    fn test_capability_table() {
+        let all = [
+            Tuner::E4000,
+            Tuner::Fc0012,
+            Tuner::Fc0013,
+            Tuner::Fc2580,
+            Tuner::R820t,
+            Tuner::R828d,
+        ];
+        for tuner in all {
+            let frequency = tuner.frequency();
+            // Gaps lie inside the range, and gains ascend
+            for gap in tuner.gaps() {
+                assert!(frequency.contains(gap.start()) && frequency.contains(gap.end()));
+            }
+            assert!(
+                tuner.gains().windows(2).all(|pair| pair[0] < pair[1]),
+                "{}",
+                tuner
+            );
+        }
+        assert!(Tuner::E4000.frequency().contains(&2_000_000_000));
+        assert!(!Tuner::R820t.frequency().contains(&2_000_000_000));
+        assert!(!Tuner::Fc0012.frequency().contains(&1_090_000_000));
+        assert_eq!(Tuner::R820t.gains().len(), 29);
+        assert_eq!(Tuner::E4000.gains().last(), Some(&420));
+    }
+}
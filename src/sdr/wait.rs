@@ -0,0 +1,221 @@
+//! Waiting for a receiver that isn't there yet
+//!
+//! With `--wait-for-device` the UI starts straight away and the receiver is
+//! opened in the background, retrying until it appears. Commands sent in the
+//! meantime stay queued on the command channel and are applied once it is
+//! attached.
+
+use super::{start_sdr_thread, Reopen, SampleBuffer, SdrSource};
+use crate::recorder::RecorderEvent;
+use crate::state::SharedState;
+use crate::types::Command;
+use anyhow::Result;
+use crossbeam::channel::{Receiver, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time between attempts to open the receiver
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+/// How often the countdown is updated and shutdown checked
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where an attempt to open the receiver stands
+#[derive(Debug, PartialEq)]
+pub enum WaitStatus<T> {
+    /// Opened
+    Attached(T),
+    /// Not yet; the next attempt is `retry_in` from now
+    Retrying {
+        attempts: u32,
+        retry_in: Duration,
+        error: String,
+    },
+}
+
+/// Opens the receiver with `open`, every [`RETRY_INTERVAL`] until it works
+pub struct DeviceWait<F> {
+    open: F,
+    interval: Duration,
+    next_attempt: Instant,
+    attempts: u32,
+    error: String,
+}
+
+impl<T, F: FnMut() -> Result<T>> DeviceWait<F> {
+    /// Wait with the first attempt at `now`
+    pub fn new(open: F, interval: Duration, now: Instant) -> Self {
+        Self {
+            open,
+            interval,
+            next_attempt: now,
+            attempts: 0,
+            error: String::new(),
+        }
+    }
+
+    /// Try to open the receiver if the next attempt is due
+    pub fn poll(&mut self, now: Instant) -> WaitStatus<T> {
+        if now >= self.next_attempt {
+            self.attempts += 1;
+            match (self.open)() {
+                Ok(source) => return WaitStatus::Attached(source),
+                Err(e) => {
+                    // Just the first line; the device list follows it
+                    let error = e.to_string();
+                    let error = error.lines().next().unwrap_or_default();
+                    if error != self.error {
+                        log::warn!("Waiting for the receiver: {:#}", e);
+                        self.error = error.to_string();
+                    }
+                    self.next_attempt = now + self.interval;
+                }
+            }
+        }
+        WaitStatus::Retrying {
+            attempts: self.attempts,
+            retry_in: self.next_attempt.saturating_duration_since(now),
+            error: self.error.clone(),
+        }
+    }
+
+    /// The open function, to open the receiver again later
+    pub fn into_open(self) -> F {
+        self.open
+    }
+}
+
+/// Start the SDR thread once `open` succeeds, showing the wait in the state
+/// until then; gives up at shutdown. With `reopen`, `open` also opens the
+/// receiver again if its stream stalls.
+pub fn start_sdr_thread_when_available(
+    open: impl FnMut() -> Result<Box<dyn SdrSource>> + Send + 'static,
+    reopen: bool,
+    state: SharedState,
+    samples_tx: Sender<SampleBuffer>,
+    command_rx: Receiver<Command>,
+    recorder_tx: Sender<RecorderEvent>,
+    shutdown: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut wait = DeviceWait::new(open, RETRY_INTERVAL, Instant::now());
+        let source = loop {
+            if shutdown.load(Ordering::Relaxed) {
+                log::info!("Stopped waiting for the receiver");
+                return;
+            }
+            match wait.poll(Instant::now()) {
+                WaitStatus::Attached(source) => break source,
+                WaitStatus::Retrying {
+                    attempts,
+                    retry_in,
+                    error,
+                } => {
+                    state.write().sdr.waiting_for_device = Some(format!(
+                        "{} - retrying in {}s (attempt {})",
+                        error,
+                        retry_in.as_secs_f32().ceil(),
+                        attempts
+                    ));
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+        };
+
+        log::info!("{} attached", source.describe());
+        state.write().sdr.waiting_for_device = None;
+        let reopen = reopen.then(|| Box::new(wait.into_open()) as Reopen);
+        match start_sdr_thread(
+            source,
+            reopen,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown,
+        ) {
+            Ok(handle) => {
+                let _ = handle.join();
+            }
+            Err(e) => {
+                log::error!("Failed to start the receiver: {:#}", e);
+                state.write().sdr.waiting_for_device = Some(format!("Receiver failed: {}", e));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+    use crossbeam::channel;
+
+    /// An open function that fails `failures` times, then returns the attempt
+    fn flaky(failures: u32) -> impl FnMut() -> Result<u32> {
+        let mut attempt = 0;
+        move || {
+            attempt += 1;
+            if attempt <= failures {
+                anyhow::bail!("RTL-SDR device 0 not found\nNo RTL-SDR devices are connected")
+            }
+            Ok(attempt)
+        }
+    }
+
+    #[test]
+    fn test_retries_until_open_succeeds() {
+        let start = Instant::now();
+        let at = |secs: f32| start + Duration::from_secs_f32(secs);
+        let mut wait = DeviceWait::new(flaky(2), RETRY_INTERVAL, start);
+
+        let retrying = |attempts, retry_in: f32| WaitStatus::Retrying {
+            attempts,
+            retry_in: Duration::from_secs_f32(retry_in),
+            error: "RTL-SDR device 0 not found".to_string(),
+        };
+        assert_eq!(wait.poll(at(0.0)), retrying(1, 2.0));
+        // Counting down between attempts
+        assert_eq!(wait.poll(at(1.5)), retrying(1, 0.5));
+        assert_eq!(wait.poll(at(2.0)), retrying(2, 2.0));
+        assert_eq!(wait.poll(at(4.0)), WaitStatus::Attached(3));
+    }
+
+    #[test]
+    fn test_immediate_success_attaches() {
+        let mut wait = DeviceWait::new(flaky(0), RETRY_INTERVAL, Instant::now());
+        assert_eq!(wait.poll(Instant::now()), WaitStatus::Attached(1));
+    }
+
+    #[test]
+    fn test_waiting_stops_at_shutdown() {
+        let state = AppState::new_shared();
+        let (samples_tx, _samples_rx) = channel::bounded(1);
+        let (_command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, _recorder_rx) = channel::bounded(1);
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handle = start_sdr_thread_when_available(
+            || anyhow::bail!("RTL-SDR device 0 not found"),
+            false,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx,
+            shutdown.clone(),
+        );
+        let begun = Instant::now();
+        while state.read().sdr.waiting_for_device.is_none() {
+            assert!(begun.elapsed() < Duration::from_secs(2), "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(
+            state.read().sdr.waiting_for_device.as_deref(),
+            Some("RTL-SDR device 0 not found - retrying in 2s (attempt 1)")
+        );
+
+        shutdown.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+}
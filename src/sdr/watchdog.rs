@@ -0,0 +1,167 @@
+//! Noticing when the receiver stops delivering samples
+//!
+//! After a USB hiccup librtlsdr can stop calling back without ever returning
+//! an error, leaving the spectrum frozen and the audio silent. The DSP thread
+//! notes when each buffer arrives, and the SDR thread checks every second how
+//! long it has been. A stall is reported once the stream has been quiet for
+//! the timeout, and again each timeout after that until samples come back,
+//! so a reconnect that doesn't take is tried again.
+//!
+//! A paused file playback is quiet on purpose and never counts; the clock
+//! starts afresh when it resumes.
+
+use std::time::{Duration, Instant};
+
+/// Silence after which the stream counts as stalled by default
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the SDR thread checks on the stream
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a check found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    /// Samples are arriving, or haven't been missed for long yet
+    Running,
+    /// Nothing for `quiet`; time to reconnect
+    Stalled { quiet: Duration },
+    /// Samples are arriving again after a stall
+    Resumed,
+}
+
+/// Tells a stalled sample stream from a quiet spell
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    timeout: Duration,
+    /// Samples are expected from here on: the start, the end of a pause or
+    /// the last stall reported
+    since: Instant,
+    /// When the stream went quiet, while it is stalled
+    quiet_since: Option<Instant>,
+}
+
+impl Watchdog {
+    /// Watch with samples expected from `now`
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            since: now,
+            quiet_since: None,
+        }
+    }
+
+    /// Check at `now`, the last buffer having arrived at `last_buffer`;
+    /// `paused` while the stream is stopped on purpose
+    pub fn check(&mut self, now: Instant, last_buffer: Option<Instant>, paused: bool) -> Watch {
+        if paused {
+            self.since = now;
+            return Watch::Running;
+        }
+        if let Some(quiet_since) = self.quiet_since {
+            if last_buffer.is_some_and(|buffer| buffer > quiet_since) {
+                self.quiet_since = None;
+                self.since = now;
+                return Watch::Resumed;
+            }
+        }
+        let latest = last_buffer.map_or(self.since, |buffer| buffer.max(self.since));
+        if now.saturating_duration_since(latest) < self.timeout {
+            return Watch::Running;
+        }
+        self.since = now;
+        let quiet_since = *self.quiet_since.get_or_insert(latest);
+        Watch::Stalled {
+            quiet: now.saturating_duration_since(quiet_since),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = DEFAULT_STALL_TIMEOUT;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn test_steady_stream() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(TIMEOUT, start);
+        for n in 1..30 {
+            let now = start + secs(n);
+            // A buffer every second, the last one just before the check
+            let last = Some(now - Duration::from_millis(50));
+            assert_eq!(watchdog.check(now, last, false), Watch::Running);
+        }
+    }
+
+    #[test]
+    fn test_stall_and_recovery() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(TIMEOUT, start);
+        let last = Some(start + secs(2));
+        assert_eq!(watchdog.check(start + secs(6), last, false), Watch::Running);
+        assert_eq!(
+            watchdog.check(start + secs(7), last, false),
+            Watch::Stalled { quiet: secs(5) }
+        );
+
+        // Not again straight away, but again a timeout later if the
+        // reconnect didn't bring samples back
+        for n in 8..12 {
+            assert_eq!(watchdog.check(start + secs(n), last, false), Watch::Running);
+        }
+        assert_eq!(
+            watchdog.check(start + secs(12), last, false),
+            Watch::Stalled { quiet: secs(10) }
+        );
+
+        // The first buffer after the stall ends it, once
+        let last = Some(start + secs(13));
+        assert_eq!(
+            watchdog.check(start + secs(14), last, false),
+            Watch::Resumed
+        );
+        assert_eq!(
+            watchdog.check(start + secs(15), last, false),
+            Watch::Running
+        );
+    }
+
+    #[test]
+    fn test_nothing_from_the_start() {
+        // A receiver that never delivers a buffer is timed from the start
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(TIMEOUT, start);
+        assert_eq!(watchdog.check(start + secs(4), None, false), Watch::Running);
+        assert_eq!(
+            watchdog.check(start + secs(5), None, false),
+            Watch::Stalled { quiet: secs(5) }
+        );
+    }
+
+    #[test]
+    fn test_pause_never_trips() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(TIMEOUT, start);
+        let last = Some(start + secs(1));
+        for n in 2..60 {
+            assert_eq!(watchdog.check(start + secs(n), last, true), Watch::Running);
+        }
+        // Resumed after 59 s: the old buffer doesn't count against it
+        assert_eq!(
+            watchdog.check(start + secs(61), last, false),
+            Watch::Running
+        );
+        assert_eq!(
+            watchdog.check(start + secs(63), last, false),
+            Watch::Running
+        );
+        assert!(matches!(
+            watchdog.check(start + secs(64), last, false),
+            Watch::Stalled { .. }
+        ));
+    }
+}
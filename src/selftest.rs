@@ -0,0 +1,498 @@
+//! `--self-test`: checks each stage from the dongle to the speaker
+//!
+//! Every check runs on its own and prints pass or fail, so one broken stage
+//! doesn't hide the state of the others. Checks that need an earlier one,
+//! like reading samples from a device that didn't open, are skipped.
+
+use crate::audio::AudioOutput;
+use crate::dsp::FftProcessor;
+use crate::sdr::{self, Capabilities, SampleSink, SdrSource};
+use crate::state::AppState;
+use anyhow::Result;
+use crossbeam::channel;
+use num_complex::Complex;
+use ringbuf::traits::{Producer, Split};
+use ringbuf::HeapRb;
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How much signal is read and analyzed
+const CAPTURE_TIME: Duration = Duration::from_secs(1);
+/// How long to wait for the capture before giving up
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(3);
+/// FFT size for the spectrum check
+const FFT_SIZE: usize = 2048;
+/// Below this spread, in 8-bit steps, the samples aren't changing
+const MIN_STD_DEV: f32 = 0.5;
+/// Rate the audio output is fed at
+const AUDIO_RATE: u32 = 48_000;
+/// Test tone pitch and length
+const TONE_FREQ: f32 = 1_000.0;
+const TONE_TIME: Duration = Duration::from_millis(500);
+
+/// What a check found
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Pass(String),
+    Fail(String),
+    /// Not run, because of an earlier failure or because it doesn't apply
+    Skip(String),
+}
+
+impl Outcome {
+    /// The line printed for the check called `name`
+    pub fn line(&self, name: &str) -> String {
+        let (tag, detail) = match self {
+            Outcome::Pass(detail) => ("PASS", detail),
+            Outcome::Fail(detail) => ("FAIL", detail),
+            Outcome::Skip(detail) => ("SKIP", detail),
+        };
+        format!("[{}] {}: {}", tag, name, detail)
+    }
+}
+
+impl<T: std::fmt::Display> From<Result<T>> for Outcome {
+    fn from(result: Result<T>) -> Self {
+        match result {
+            Ok(detail) => Outcome::Pass(detail.to_string()),
+            // Only the first line; the rest goes to the log
+            Err(e) => {
+                log::error!("Self-test: {:#}", e);
+                Outcome::Fail(e.to_string().lines().next().unwrap_or_default().to_string())
+            }
+        }
+    }
+}
+
+/// The outcomes so far, printed as they come in
+#[derive(Default)]
+pub struct Report {
+    outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    fn record(&mut self, name: &str, outcome: Outcome) {
+        println!("  {}", outcome.line(name));
+        self.outcomes.push(outcome);
+    }
+
+    /// Whether nothing failed
+    pub fn passed(&self) -> bool {
+        !self
+            .outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, Outcome::Fail(_)))
+    }
+
+    /// How many checks failed
+    pub fn failures(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| matches!(outcome, Outcome::Fail(_)))
+            .count()
+    }
+}
+
+/// What the receiver is set to for the test
+pub struct Settings {
+    pub frequency: u32,
+    pub sample_rate: u32,
+    /// Tenths of dB, or None for automatic gain
+    pub gain: Option<i32>,
+    /// List the local RTL-SDRs first
+    pub enumerate: bool,
+    /// Streaming port to try binding
+    pub audio_port: Option<u16>,
+}
+
+/// Run every check and print the results; true if none failed
+pub fn run(open: impl FnOnce() -> Result<Box<dyn SdrSource>>, settings: &Settings) -> bool {
+    println!("RTL-SDR TUI self-test");
+    let mut report = Report::default();
+
+    if settings.enumerate {
+        report.record(
+            "Device enumeration",
+            check_enumeration(sdr::list_devices()).into(),
+        );
+    }
+
+    let mut source = match open() {
+        Ok(source) => {
+            report.record("Open device", Outcome::Pass(source.describe()));
+            report.record("Tuner", check_tuner(source.capabilities()));
+            Some(source)
+        }
+        Err(e) => {
+            report.record("Open device", Outcome::from(Err::<String, _>(e)));
+            report.record("Tuner", Outcome::Skip("no device".into()));
+            None
+        }
+    };
+
+    let samples = match source.as_mut() {
+        Some(source) => {
+            let configured = configure(source.as_mut(), settings);
+            let configured_ok = configured.is_ok();
+            report.record("Configure", configured.into());
+            if configured_ok {
+                let samples = read_samples(source.as_mut(), settings.sample_rate);
+                let outcome = match &samples {
+                    Ok(samples) => check_samples(samples),
+                    Err(e) => Outcome::Fail(e.to_string()),
+                };
+                report.record("Read samples", outcome);
+                samples.ok()
+            } else {
+                report.record(
+                    "Read samples",
+                    Outcome::Skip("receiver not configured".into()),
+                );
+                None
+            }
+        }
+        None => {
+            report.record("Configure", Outcome::Skip("no device".into()));
+            report.record("Read samples", Outcome::Skip("no device".into()));
+            None
+        }
+    };
+
+    match samples {
+        Some(samples) => report.record(
+            "Spectrum",
+            check_spectrum(&samples, settings.sample_rate).into(),
+        ),
+        None => report.record("Spectrum", Outcome::Skip("no samples".into())),
+    }
+
+    report.record("Audio output", check_audio().into());
+
+    match settings.audio_port {
+        Some(port) => report.record("Streaming port", check_port(port).into()),
+        None => report.record("Streaming port", Outcome::Skip("no --audio-port".into())),
+    }
+
+    if report.passed() {
+        println!("All checks passed");
+    } else {
+        println!(
+            "{} check(s) failed; details are in the log",
+            report.failures()
+        );
+    }
+    report.passed()
+}
+
+/// Whether any RTL-SDR is connected
+fn check_enumeration(devices: Vec<String>) -> Result<String> {
+    if devices.is_empty() {
+        anyhow::bail!("No RTL-SDR devices are connected");
+    }
+    Ok(format!("{} found ({})", devices.len(), devices.join(", ")))
+}
+
+/// Which tuner the receiver has and what it covers; not knowing isn't a
+/// failure, as only RTL-SDRs say
+fn check_tuner(capabilities: &Capabilities) -> Outcome {
+    let Some(tuner) = capabilities.tuner else {
+        return Outcome::Skip("not reported".into());
+    };
+    let mhz = |hz: &u32| *hz as f64 / 1e6;
+    let mut line = format!(
+        "{}, {:.0}-{:.0} MHz",
+        tuner,
+        mhz(capabilities.frequency.start()),
+        mhz(capabilities.frequency.end())
+    );
+    for gap in &capabilities.gaps {
+        line.push_str(&format!(" except {:.0}-{:.0} MHz", mhz(gap.start()), mhz(gap.end())));
+    }
+    Outcome::Pass(line)
+}
+
+/// Tune, set the rate and set the gain
+fn configure(source: &mut dyn SdrSource, settings: &Settings) -> Result<String> {
+    source.set_frequency(settings.frequency)?;
+    source.set_sample_rate(settings.sample_rate)?;
+    source.set_gain(settings.gain)?;
+    let gain = match settings.gain {
+        Some(gain) => format!("{}.{} dB", gain / 10, gain % 10),
+        None => "auto".to_string(),
+    };
+    Ok(format!(
+        "{:.3} MHz, {:.3} MS/s, gain {}",
+        settings.frequency as f64 / 1e6,
+        settings.sample_rate as f64 / 1e6,
+        gain
+    ))
+}
+
+/// Stream [`CAPTURE_TIME`] of samples from `source`
+fn read_samples(source: &mut dyn SdrSource, sample_rate: u32) -> Result<Vec<Complex<f32>>> {
+    let wanted = (sample_rate as f64 * CAPTURE_TIME.as_secs_f64()) as usize;
+    let (samples_tx, samples_rx) = channel::bounded(256);
+    let (recorder_tx, _recorder_rx) = channel::bounded(1);
+    let shutdown = Arc::new(AtomicBool::new(false));
+    // The streaming thread is left to end with the process, since not
+    // every backend stops reading on shutdown
+    let _ = source.start(SampleSink::new(
+        AppState::new_shared(),
+        samples_tx,
+        recorder_tx,
+        shutdown.clone(),
+    ))?;
+
+    let started = Instant::now();
+    let mut samples = Vec::with_capacity(wanted);
+    while samples.len() < wanted {
+        let left = CAPTURE_TIMEOUT.saturating_sub(started.elapsed());
+        match samples_rx.recv_timeout(left) {
+            Ok(buffer) => samples.extend(buffer.samples),
+            Err(_) => break,
+        }
+    }
+    shutdown.store(true, Ordering::Relaxed);
+
+    if samples.len() < wanted {
+        anyhow::bail!(
+            "only {} of {} samples arrived within {}s",
+            samples.len(),
+            wanted,
+            CAPTURE_TIMEOUT.as_secs()
+        );
+    }
+    samples.truncate(wanted);
+    Ok(samples)
+}
+
+/// Mean and spread of I and Q, in the RTL-SDR's 8-bit steps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleStats {
+    pub mean_i: f32,
+    pub mean_q: f32,
+    /// Standard deviation, of I and Q together
+    pub std_dev: f32,
+}
+
+impl SampleStats {
+    pub fn of(samples: &[Complex<f32>]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let to_steps = |v: f32| v * 128.0 + 127.5;
+        let n = samples.len() as f32;
+        let mean_i = samples.iter().map(|s| to_steps(s.re)).sum::<f32>() / n;
+        let mean_q = samples.iter().map(|s| to_steps(s.im)).sum::<f32>() / n;
+        let variance = samples
+            .iter()
+            .map(|s| (to_steps(s.re) - mean_i).powi(2) + (to_steps(s.im) - mean_q).powi(2))
+            .sum::<f32>()
+            / (2.0 * n);
+        Some(Self {
+            mean_i,
+            mean_q,
+            std_dev: variance.sqrt(),
+        })
+    }
+}
+
+/// Whether the samples look like a live receiver rather than a constant
+fn check_samples(samples: &[Complex<f32>]) -> Outcome {
+    let Some(stats) = SampleStats::of(samples) else {
+        return Outcome::Fail("no samples".into());
+    };
+    let summary = format!(
+        "{} samples, mean I {:.1} Q {:.1}, \u{3c3} {:.2}",
+        samples.len(),
+        stats.mean_i,
+        stats.mean_q,
+        stats.std_dev
+    );
+    if stats.std_dev < MIN_STD_DEV {
+        Outcome::Fail(format!(
+            "{}: stuck at a constant value, the dongle isn't sampling",
+            summary
+        ))
+    } else {
+        Outcome::Pass(summary)
+    }
+}
+
+/// The strongest signal in the averaged spectrum, away from the DC spike,
+/// as its offset in Hz and level in dB
+pub fn spectrum_peak(samples: &[Complex<f32>], sample_rate: u32) -> Option<(i32, f32)> {
+    let mut fft = FftProcessor::new(FFT_SIZE);
+    let mut power = vec![0.0f32; FFT_SIZE];
+    let mut frames = 0;
+    for frame in samples.chunks_exact(FFT_SIZE) {
+        for (total, db) in power.iter_mut().zip(fft.process(frame)) {
+            *total += 10f32.powf(db / 10.0);
+        }
+        frames += 1;
+    }
+    if frames == 0 {
+        return None;
+    }
+
+    let center = FFT_SIZE / 2;
+    let (bin, peak) = power
+        .iter()
+        .enumerate()
+        .filter(|&(bin, _)| bin.abs_diff(center) > 1)
+        .max_by(|a, b| a.1.total_cmp(b.1))?;
+    let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+    let offset = (bin as f32 - center as f32) * bin_width;
+    Some((offset.round() as i32, 10.0 * (peak / frames as f32).log10()))
+}
+
+/// Run the FFT over the samples and report the peak
+fn check_spectrum(samples: &[Complex<f32>], sample_rate: u32) -> Result<String> {
+    let (offset, db) = spectrum_peak(samples, sample_rate)
+        .ok_or_else(|| anyhow::anyhow!("fewer than {} samples", FFT_SIZE))?;
+    Ok(format!(
+        "peak {:.1} dB at {:+.1} kHz",
+        db,
+        offset as f32 / 1000.0
+    ))
+}
+
+/// A sine at `freq` Hz, `duration` long at `rate`, faded in and out so it
+/// doesn't click
+pub fn test_tone(rate: u32, freq: f32, duration: Duration) -> Vec<f32> {
+    let len = (rate as f64 * duration.as_secs_f64()) as usize;
+    let fade = (rate / 100) as usize;
+    (0..len)
+        .map(|i| {
+            let envelope = (i.min(len - 1 - i) as f32 / fade as f32).min(1.0);
+            let phase = 2.0 * std::f32::consts::PI * freq * i as f32 / rate as f32;
+            0.3 * envelope * phase.sin()
+        })
+        .collect()
+}
+
+/// Open the default output and play the test tone
+fn check_audio() -> Result<String> {
+    let tone = test_tone(AUDIO_RATE, TONE_FREQ, TONE_TIME);
+    let (mut producer, consumer) = HeapRb::<f32>::new(tone.len()).split();
+    producer.push_slice(&tone);
+    let output = AudioOutput::new(consumer)?;
+    std::thread::sleep(TONE_TIME + Duration::from_millis(100));
+    drop(output);
+    Ok(format!(
+        "played a {} ms {:.0} Hz tone",
+        TONE_TIME.as_millis(),
+        TONE_FREQ
+    ))
+}
+
+/// Whether the streaming server could listen on `port`
+fn check_port(port: u16) -> Result<String> {
+    TcpListener::bind(("0.0.0.0", port))
+        .map_err(|e| anyhow::anyhow!("can't listen on port {}: {}", port, e))?;
+    Ok(format!("port {} is free", port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdr::Tuner;
+
+    fn tone(offset: f32, rate: u32, len: usize, amplitude: f32) -> Vec<Complex<f32>> {
+        (0..len)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * offset * i as f32 / rate as f32;
+                Complex::from_polar(amplitude, phase)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stuck_dongle_fails() {
+        // Raw 127 everywhere, as a dead dongle delivers
+        let stuck = sdr::samples_u8_to_complex(&[127; 4096]);
+        let stats = SampleStats::of(&stuck).unwrap();
+        assert!((stats.mean_i - 127.0).abs() < 1e-3);
+        assert!(stats.std_dev < 1e-3);
+        assert!(matches!(check_samples(&stuck), Outcome::Fail(detail) if detail.contains("stuck")));
+
+        let live = tone(10_000.0, 2_048_000, 4096, 0.2);
+        let stats = SampleStats::of(&live).unwrap();
+        assert!((stats.mean_i - 127.5).abs() < 0.5);
+        assert!((stats.std_dev - 0.2 * 128.0 / 2f32.sqrt()).abs() < 0.5);
+        assert!(matches!(check_samples(&live), Outcome::Pass(_)));
+
+        assert_eq!(check_samples(&[]), Outcome::Fail("no samples".into()));
+    }
+
+    #[test]
+    fn test_spectrum_peak_finds_tone() {
+        let rate = 2_048_000;
+        let bin_width = rate as f32 / FFT_SIZE as f32;
+        let mut samples = tone(100.0 * bin_width, rate, FFT_SIZE * 4, 0.5);
+        // A bigger DC offset is ignored
+        for sample in &mut samples {
+            sample.re += 0.9;
+        }
+        let (offset, db) = spectrum_peak(&samples, rate).unwrap();
+        assert_eq!(offset, 100_000);
+        assert!(db > 40.0);
+
+        assert_eq!(spectrum_peak(&samples[..FFT_SIZE - 1], rate), None);
+    }
+
+    #[test]
+    fn test_tone_shape() {
+        let tone = test_tone(AUDIO_RATE, TONE_FREQ, TONE_TIME);
+        assert_eq!(tone.len(), 24_000);
+        assert_eq!(tone[0], 0.0);
+        assert!(tone.iter().all(|s| s.abs() <= 0.3));
+        // Full level away from the fades
+        let middle = &tone[10_000..10_048];
+        assert!(middle.iter().any(|s| s.abs() > 0.29));
+    }
+
+    #[test]
+    fn test_tuner_check() {
+        let e4000 = Capabilities::rtl_tuner(Some(Tuner::E4000));
+        assert_eq!(
+            check_tuner(&e4000).line("Tuner"),
+            "[PASS] Tuner: E4000, 52-2200 MHz except 1100-1250 MHz"
+        );
+        let r820t = Capabilities::rtl_tuner(Some(Tuner::R820t));
+        assert_eq!(check_tuner(&r820t), Outcome::Pass("R820T, 24-1766 MHz".into()));
+        assert_eq!(check_tuner(&Capabilities::rtl()), Outcome::Skip("not reported".into()));
+    }
+
+    #[test]
+    fn test_port_check() {
+        let taken = TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+        assert!(check_port(port).is_err());
+        drop(taken);
+        assert_eq!(check_port(port).unwrap(), format!("port {} is free", port));
+    }
+
+    #[test]
+    fn test_outcome_lines() {
+        assert!(check_enumeration(Vec::new()).is_err());
+        let found = check_enumeration(vec!["#0: Generic RTL2832U OEM".into()]);
+        assert_eq!(
+            Outcome::from(found).line("Device enumeration"),
+            "[PASS] Device enumeration: 1 found (#0: Generic RTL2832U OEM)"
+        );
+        let failed: Outcome = Err::<String, _>(anyhow::anyhow!("first\nsecond")).into();
+        assert_eq!(failed.line("Open device"), "[FAIL] Open device: first");
+
+        let mut report = Report::default();
+        report
+            .outcomes
+            .push(Outcome::Skip("no --audio-port".into()));
+        assert!(report.passed());
+        report.outcomes.push(failed);
+        assert!(!report.passed());
+        assert_eq!(report.failures(), 1);
+    }
+}
@@ -0,0 +1,437 @@
+//! Running as a system service: listening sockets handed over by systemd,
+//! and giving up root once the ports are open
+//!
+//! Under socket activation systemd binds the ports and passes them in as
+//! file descriptors from 3 up, saying how many in `LISTEN_FDS` and what each
+//! is called in `LISTEN_FDNAMES` (`FileDescriptorName=`, or the socket
+//! unit's name). A socket is taken for a server when its name is the
+//! server's role or ends in `-<role>`, with or without `.socket`:
+//!
+//! - `audio`: the audio stream (`--audio-port`)
+//! - `nmea`: AIS sentences (`--ais-port`)
+//! - `sonde`: radiosonde telemetry (`--sonde-port`)
+//! - `http`: the status server (`--http-port`)
+//! - `control`: the control socket (`--control-port`), on loopback only
+//!
+//! A server with a socket passed starts even without its port option, and
+//! one without binds its port as usual. Because systemd keeps the sockets
+//! across restarts, clients can reconnect to a restarted service without it
+//! ever running as root for a port below 1024.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io;
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+
+/// What each server's socket is called
+pub const ROLES: [&str; 5] = ["audio", "nmea", "sonde", "http", "control"];
+
+/// The first descriptor systemd passes
+const LISTEN_FDS_START: i32 = 3;
+
+/// The role a socket called `name` is for, if any
+pub fn role_of(name: &str) -> Option<&'static str> {
+    let name = name.strip_suffix(".socket").unwrap_or(name);
+    ROLES.iter().copied().find(|role| {
+        name == *role
+            || name
+                .strip_suffix(role)
+                .is_some_and(|prefix| prefix.ends_with('-'))
+    })
+}
+
+/// The descriptors passed to process `pid` and their names, from the
+/// values of `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES`
+///
+/// Nothing was passed to this process unless `LISTEN_PID` is its own.
+/// Descriptors without a name are called "unknown", as systemd calls them.
+pub fn passed_fds(
+    pid: u32,
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    names: Option<&str>,
+) -> Vec<(i32, String)> {
+    if listen_pid.and_then(|value| value.parse::<u32>().ok()) != Some(pid) {
+        return Vec::new();
+    }
+    let Some(count) = listen_fds.and_then(|value| value.parse::<i32>().ok()) else {
+        return Vec::new();
+    };
+    let mut names = names.unwrap_or_default().split(':');
+    (0..count.max(0))
+        .map(|n| {
+            let name = names.next().filter(|name| !name.is_empty());
+            (LISTEN_FDS_START + n, name.unwrap_or("unknown").to_string())
+        })
+        .collect()
+}
+
+/// Listening sockets passed in by systemd, by role, until a server takes
+/// them
+#[derive(Debug, Default)]
+pub struct Sockets {
+    passed: HashMap<&'static str, TcpListener>,
+}
+
+impl Sockets {
+    /// The sockets systemd passed this process, if it did; the variables
+    /// are cleared so nothing started from here thinks it got them too
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok();
+        let passed = passed_fds(
+            std::process::id(),
+            var("LISTEN_PID").as_deref(),
+            var("LISTEN_FDS").as_deref(),
+            var("LISTEN_FDNAMES").as_deref(),
+        );
+        for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            std::env::remove_var(name);
+        }
+        Self::adopt(passed)
+    }
+
+    /// Take ownership of the listening sockets `passed` as descriptors and
+    /// names; ones for no role, for a role already given one, or that
+    /// aren't TCP listeners are closed, as is a control socket anyone but
+    /// this machine could reach
+    #[cfg(unix)]
+    pub fn adopt(passed: Vec<(i32, String)>) -> Self {
+        use std::os::unix::io::FromRawFd;
+
+        let mut sockets = Self::default();
+        for (fd, name) in passed {
+            // Passed without close-on-exec, which nothing started should get
+            unsafe {
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            }
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            let addr = match listener.local_addr() {
+                Ok(addr) => addr,
+                Err(e) => {
+                    log::warn!("Closing passed socket {} ({}): not TCP: {}", fd, name, e);
+                    continue;
+                }
+            };
+            match role_of(&name) {
+                // The control socket takes commands from anyone who
+                // connects, so it's never bound beyond loopback
+                Some("control") if !addr.ip().is_loopback() => log::warn!(
+                    "Closing socket {} on {}: the control socket must be on loopback",
+                    name,
+                    addr
+                ),
+                Some(role) if !sockets.passed.contains_key(role) => {
+                    log::info!("Socket {} on {} passed for {}", name, addr, role);
+                    sockets.passed.insert(role, listener);
+                }
+                Some(role) => log::warn!("Closing socket {}: {} already has one", name, role),
+                None => log::warn!(
+                    "Closing socket {} on {}: its name is none of {}",
+                    name,
+                    addr,
+                    ROLES.join(", ")
+                ),
+            }
+        }
+        sockets
+    }
+
+    #[cfg(not(unix))]
+    pub fn adopt(passed: Vec<(i32, String)>) -> Self {
+        if !passed.is_empty() {
+            log::warn!("Socket activation is only supported on Unix");
+        }
+        Self::default()
+    }
+
+    /// Whether any sockets were passed
+    pub fn is_empty(&self) -> bool {
+        self.passed.is_empty()
+    }
+
+    /// The port of the socket passed for `role`, if one was
+    pub fn port(&self, role: &str) -> Option<u16> {
+        let listener = self.passed.get(role)?;
+        listener.local_addr().ok().map(|addr| addr.port())
+    }
+
+    /// The socket passed for `role`, or else a new one bound to `addr`
+    pub fn listen(&mut self, role: &str, addr: impl ToSocketAddrs) -> io::Result<TcpListener> {
+        match self.passed.remove(role) {
+            Some(listener) => Ok(listener),
+            None => TcpListener::bind(addr),
+        }
+    }
+
+    /// Close the sockets no server took, saying so
+    pub fn finish(self) {
+        for role in self.passed.keys() {
+            log::warn!(
+                "Closing the {} socket passed in: its server isn't running",
+                role
+            );
+        }
+    }
+}
+
+/// The user `--user` switches to, looked up before anything is opened
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+}
+
+impl Account {
+    /// Look up `user` in the password database
+    #[cfg(unix)]
+    pub fn lookup(user: &str) -> Result<Self> {
+        use anyhow::{bail, Context};
+        use std::ffi::{CStr, CString};
+
+        let name = CString::new(user).context("bad user name")?;
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let mut buffer = vec![0 as libc::c_char; 16_384];
+        let status = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut found,
+            )
+        };
+        if status != 0 {
+            bail!(
+                "can't look up user {}: {}",
+                user,
+                io::Error::from_raw_os_error(status)
+            );
+        }
+        if found.is_null() {
+            bail!("no such user {}", user);
+        }
+        let text = |field: *const libc::c_char| unsafe {
+            CStr::from_ptr(field).to_string_lossy().into_owned()
+        };
+        Ok(Self {
+            name: text(entry.pw_name),
+            uid: entry.pw_uid,
+            gid: entry.pw_gid,
+            home: PathBuf::from(text(entry.pw_dir)),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn lookup(_user: &str) -> Result<Self> {
+        anyhow::bail!("--user is only supported on Unix")
+    }
+
+    /// Make the user's home this process's, so the config, the log and
+    /// the recordings go where theirs would rather than into root's
+    ///
+    /// Called first thing, while nothing else is running to read the
+    /// environment.
+    pub fn take_environment(&self) {
+        std::env::set_var("HOME", &self.home);
+        std::env::set_var("USER", &self.name);
+        std::env::set_var("LOGNAME", &self.name);
+        // Root's, which would otherwise win over the home directory
+        for var in ["XDG_CONFIG_HOME", "XDG_DATA_HOME", "XDG_STATE_HOME"] {
+            std::env::remove_var(var);
+        }
+    }
+
+    /// Hand `path`, made while still root, over to the user
+    #[cfg(unix)]
+    pub fn give(&self, path: &Path) -> io::Result<()> {
+        if unsafe { libc::geteuid() } != 0 {
+            return Ok(());
+        }
+        std::os::unix::fs::chown(path, Some(self.uid), Some(self.gid))
+    }
+
+    #[cfg(not(unix))]
+    pub fn give(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Switch to `account`, with its primary and supplementary groups, for good
+///
+/// Called once the ports are open, so a service started as root to bind a
+/// port below 1024 doesn't go on to run as root. Fails unless running as
+/// root or already as that user.
+#[cfg(unix)]
+pub fn drop_privileges(account: &Account) -> Result<()> {
+    use anyhow::{bail, Context};
+    use std::ffi::CString;
+
+    let (user, uid, gid) = (&account.name, account.uid, account.gid);
+    if unsafe { libc::geteuid() } == uid {
+        log::info!("Already running as {}", user);
+        return Ok(());
+    }
+    if unsafe { libc::geteuid() } != 0 {
+        bail!("switching to user {} needs starting as root", user);
+    }
+    let login = CString::new(user.as_str()).context("bad user name")?;
+    // Groups first, while still allowed to set them
+    unsafe {
+        if libc::initgroups(login.as_ptr(), gid as _) != 0 {
+            bail!(
+                "can't set {}'s groups: {}",
+                user,
+                io::Error::last_os_error()
+            );
+        }
+        if libc::setgid(gid) != 0 {
+            bail!(
+                "can't switch to group {}: {}",
+                gid,
+                io::Error::last_os_error()
+            );
+        }
+        if libc::setuid(uid) != 0 {
+            bail!(
+                "can't switch to user {}: {}",
+                user,
+                io::Error::last_os_error()
+            );
+        }
+        // Make sure there's no way back
+        if uid != 0 && libc::setuid(0) == 0 {
+            bail!("still able to regain root after switching to {}", user);
+        }
+    }
+    log::info!("Switched to user {} (uid {}, gid {})", user, uid, gid);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn drop_privileges(_account: &Account) -> Result<()> {
+    anyhow::bail!("--user is only supported on Unix")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_of() {
+        assert_eq!(role_of("audio"), Some("audio"));
+        assert_eq!(role_of("control.socket"), Some("control"));
+        assert_eq!(role_of("rtl-sdr-tui-audio.socket"), Some("audio"));
+        assert_eq!(role_of("rtl-sdr-tui-http"), Some("http"));
+        assert_eq!(role_of("sdr-nmea.socket"), Some("nmea"));
+        // Only whole words
+        assert_eq!(role_of("studio"), None);
+        assert_eq!(role_of("audio-in"), None);
+        assert_eq!(role_of("unknown"), None);
+        assert_eq!(role_of(""), None);
+    }
+
+    #[test]
+    fn test_passed_fds() {
+        let passed = passed_fds(
+            42,
+            Some("42"),
+            Some("3"),
+            Some("audio:rtl-sdr-tui-http.socket"),
+        );
+        assert_eq!(
+            passed,
+            [
+                (3, "audio".to_string()),
+                (4, "rtl-sdr-tui-http.socket".to_string()),
+                (5, "unknown".to_string()),
+            ]
+        );
+        // Empty names are unknown too
+        assert_eq!(
+            passed_fds(42, Some("42"), Some("1"), Some("")),
+            [(3, "unknown".into())]
+        );
+        assert_eq!(
+            passed_fds(42, Some("42"), Some("1"), None),
+            [(3, "unknown".into())]
+        );
+
+        // Meant for another process, such as the shell that started this one
+        assert!(passed_fds(42, Some("41"), Some("2"), Some("audio:http")).is_empty());
+        assert!(passed_fds(42, None, Some("2"), None).is_empty());
+        assert!(passed_fds(42, Some("42"), None, None).is_empty());
+        assert!(passed_fds(42, Some("42"), Some("lots"), None).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_adopts_passed_listener() {
+        use crate::state::AppState;
+        use crate::streaming::{codec::StreamCodec, start_streaming_server};
+        use std::io::Read;
+        use std::net::TcpStream;
+        use std::os::unix::io::IntoRawFd;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        // Bound here as systemd would have, and passed by descriptor
+        let bound = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = bound.local_addr().unwrap().port();
+        let fd = bound.into_raw_fd();
+        let mut sockets = Sockets::adopt(vec![(fd, "rtl-sdr-tui-audio.socket".to_string())]);
+        assert_eq!(sockets.port("audio"), Some(port));
+        assert_eq!(sockets.port("http"), None);
+
+        // Taken in place of binding the address given
+        let listener = sockets.listen("audio", ("127.0.0.1", 1)).unwrap();
+        assert_eq!(listener.local_addr().unwrap().port(), port);
+        assert!(sockets.is_empty());
+
+        let state = AppState::new_shared();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        start_streaming_server(
+            listener,
+            StreamCodec::Wav,
+            0,
+            8,
+            state.clone(),
+            shutdown.clone(),
+        )
+        .unwrap();
+        assert_eq!(state.read().streaming.port, Some(port));
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let mut header = [0u8; 4];
+        client.read_exact(&mut header).unwrap();
+        assert_eq!(&header, b"RIFF");
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_refuses_control_beyond_loopback() {
+        use std::os::unix::io::IntoRawFd;
+
+        let open = TcpListener::bind("0.0.0.0:0").unwrap().into_raw_fd();
+        let local = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = local.local_addr().unwrap().port();
+        let sockets = Sockets::adopt(vec![(open, "control".to_string())]);
+        assert_eq!(sockets.port("control"), None);
+        let sockets = Sockets::adopt(vec![(local.into_raw_fd(), "control".to_string())]);
+        assert_eq!(sockets.port("control"), Some(port));
+    }
+
+    #[test]
+    fn test_binds_without_passed_socket() {
+        let mut sockets = Sockets::default();
+        let listener = sockets.listen("control", ("127.0.0.1", 0)).unwrap();
+        assert_ne!(listener.local_addr().unwrap().port(), 0);
+    }
+}
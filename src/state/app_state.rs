@@ -1,19 +1,82 @@
-use crate::types::{DecodedMessage, DemodMode};
+use super::bookmarks::{Bookmarks, LevelOverride};
+use super::calibration::{Calibration, LevelScale};
+use super::history::FrequencyHistory;
+use super::markers::Markers;
+use super::message_view::MessageView;
+use super::mode_settings::{ModeMemory, ModeSettings};
+use super::playback::PlaybackState;
+use super::stats::StatsState;
+use super::undo::UndoHistory;
+use super::waterfall::{frames_per_line, Integration, RowIntegrator, DEFAULT_LINE_RATE};
+use crate::dsp::dc::{self, DcAvoidance, DEFAULT_DC_OFFSET};
+use crate::dsp::filters::{AgcSpeed, SoftLimiter};
+use crate::dsp::noise::NoiseReduction;
+use crate::dsp::ActivityTable;
+use crate::recorder::{recording_path, RetunePolicy, SampleFormat, SplitPolicy};
+use crate::sdr::gain_profile::GainProfiles;
+use crate::sdr::watchdog::DEFAULT_STALL_TIMEOUT;
+use crate::sdr::Capabilities;
+use crate::types::config::SavedVfoConfig;
+use crate::types::{Aircraft, AircraftSort, Chain, Command, DecodedMessage, DemodMode};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use crossbeam::channel::{self, Receiver, Sender};
+use num_complex::Complex;
 use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Shared application state accessible from all threads
 pub type SharedState = Arc<RwLock<AppState>>;
 
+/// Wakes the UI when another thread changes something worth showing at
+/// once, rather than on the next render tick
+///
+/// Changes made before the UI gets round to them wake it once. Until the
+/// UI is listening they go nowhere.
+#[derive(Debug, Clone)]
+pub struct StateChanged(Sender<()>);
+
+impl StateChanged {
+    /// A notifier, and what the UI listens on
+    pub fn channel() -> (Self, Receiver<()>) {
+        let (tx, rx) = channel::bounded(1);
+        (Self(tx), rx)
+    }
+
+    /// Tell the UI something changed
+    pub fn notify(&self) {
+        let _ = self.0.try_send(());
+    }
+}
+
+impl Default for StateChanged {
+    fn default() -> Self {
+        Self::channel().0
+    }
+}
+
 /// Main application state
 #[derive(Debug)]
 pub struct AppState {
     pub sdr: SdrState,
     pub spectrum: SpectrumState,
+    pub channels: ChannelState,
+    pub priority: PriorityState,
     pub decoder: DecoderState,
+    /// Live status of chain B's decoders; their messages go to `decoder`
+    /// with chain A's
+    pub decoder_b: DecoderState,
     pub recording: RecordingState,
+    pub streaming: StreamingState,
     pub ui: UiState,
+    pub stats: StatsState,
+    /// The IQ file being played, when there is no receiver
+    pub playback: Option<PlaybackState>,
+    /// Wakes the UI for decoded messages and receiver warnings
+    pub changed: StateChanged,
 }
 
 impl Default for AppState {
@@ -21,113 +84,591 @@ impl Default for AppState {
         Self {
             sdr: SdrState::default(),
             spectrum: SpectrumState::default(),
+            channels: ChannelState::default(),
+            priority: PriorityState::default(),
             decoder: DecoderState::default(),
+            decoder_b: DecoderState::default(),
             recording: RecordingState::default(),
+            streaming: StreamingState::default(),
             ui: UiState::default(),
+            stats: StatsState::default(),
+            playback: None,
+            changed: StateChanged::default(),
         }
     }
 }
 
 impl AppState {
-    /// Create a new shared state wrapped in Arc<RwLock>
+    /// Create a new shared state wrapped in `Arc<RwLock>`
     pub fn new_shared() -> SharedState {
         Arc::new(RwLock::new(Self::default()))
     }
+
+    /// Where `chain`'s decoders publish their live status
+    pub fn decoder_status(&mut self, chain: Chain) -> &mut DecoderState {
+        match chain {
+            Chain::A => &mut self.decoder,
+            Chain::B => &mut self.decoder_b,
+        }
+    }
+
+    /// The SAME alert in force, chain A's before chain B's
+    pub fn same_alert(&self) -> Option<&String> {
+        self.decoder.same_alert.as_ref().or(self.decoder_b.same_alert.as_ref())
+    }
+
+    /// What chain A is tuned to right now
+    pub fn live_vfo(&self) -> VfoConfig {
+        VfoConfig {
+            frequency: self.sdr.frequency.saturating_add_signed(self.channels.offset_a),
+            mode: self.decoder.mode,
+            filter_width: self.decoder.filter_width,
+        }
+    }
+
+    /// Hz to tune the hardware above `frequency` to keep the DC spike out
+    /// of chain A, for a receiver tunable over `tunable`
+    pub fn hardware_offset_for(&self, frequency: u32, tunable: &RangeInclusive<u32>) -> i32 {
+        let heard = dc::dc_in_passband(
+            self.channels.offset_a,
+            self.decoder.mode,
+            self.decoder.filter_width,
+        );
+        if self.sdr.dc_avoidance != DcAvoidance::Offset || !heard {
+            return 0;
+        }
+        dc::nudge(frequency, self.sdr.dc_offset, self.sdr.sample_rate, tunable)
+    }
+
+    /// Keep the active VFO following chain A
+    pub fn sync_vfo(&mut self) {
+        self.sdr.vfo[self.sdr.active_vfo] = self.live_vfo();
+    }
+
+    /// Make the other VFO the active one, returning what chain A is to be
+    /// tuned to
+    pub fn swap_vfo(&mut self) -> VfoConfig {
+        self.sync_vfo();
+        self.sdr.active_vfo ^= 1;
+        self.sdr.vfo[self.sdr.active_vfo]
+    }
+
+    /// Copy VFO A to B, returning what chain A is to be tuned to if B is
+    /// the active one
+    pub fn copy_vfo(&mut self) -> Option<VfoConfig> {
+        self.sync_vfo();
+        self.sdr.vfo[1] = self.sdr.vfo[0];
+        (self.sdr.active_vfo == 1).then_some(self.sdr.vfo[1])
+    }
+
+    /// Chain A now tuned to `vfo`, with the center on it at `frequency`
+    /// (`vfo`'s, as far as the receiver reaches)
+    pub fn tuned_to_vfo(&mut self, vfo: VfoConfig, frequency: u32) {
+        self.sdr.frequency = frequency;
+        self.channels.offset_a = 0;
+        self.decoder.mode = vfo.mode;
+        self.decoder.filter_width = vfo.filter_width;
+    }
+
+    /// The command that starts recording in the recording mode, to a new
+    /// file named for now and what is tuned
+    pub fn start_recording_command(&self) -> Command {
+        match self.recording.mode {
+            RecordingMode::Squelch => Command::StartSquelchRecording,
+            RecordingMode::Iq => Command::StartRecording(recording_path(
+                &self.recording.output_dir,
+                &Local::now(),
+                self.sdr.frequency,
+                self.decoder.mode,
+                self.sdr.sample_rate,
+                self.recording.extension(),
+            )),
+        }
+    }
+}
+
+/// What chain A is tuned to while a VFO is the active one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VfoConfig {
+    /// Hz
+    pub frequency: u32,
+    pub mode: DemodMode,
+    /// None for the mode's default
+    pub filter_width: Option<u32>,
+}
+
+impl VfoConfig {
+    /// "A" or "B"
+    pub fn name(index: usize) -> &'static str {
+        if index == 0 {
+            "A"
+        } else {
+            "B"
+        }
+    }
+
+    /// A VFO as saved in the config file
+    pub fn from_config(saved: &SavedVfoConfig) -> Result<Self, String> {
+        if saved.frequency <= 0.0 {
+            return Err(format!("bad frequency {}", saved.frequency));
+        }
+        Ok(Self {
+            frequency: (saved.frequency * 1e6).round() as u32,
+            mode: saved.mode.parse()?,
+            filter_width: saved.filter_width,
+        })
+    }
+
+    /// The VFO as a TOML inline table for the config file
+    pub fn to_toml(self) -> String {
+        let width = match self.filter_width {
+            Some(width) => format!(", filter_width = {}", width),
+            None => String::new(),
+        };
+        format!(
+            "{{ frequency = {:.6}, mode = \"{}\"{} }}",
+            self.frequency as f64 / 1e6,
+            self.mode.file_tag().to_ascii_lowercase(),
+            width
+        )
+    }
 }
 
 /// SDR device state
 #[derive(Debug)]
 pub struct SdrState {
-    /// Center frequency in Hz
+    /// Center frequency in Hz, as shown and as the DSP thread sees the
+    /// capture; see `hardware_offset`
     pub frequency: u32,
+    /// Hz the hardware is tuned above `frequency` to keep the DC spike out
+    /// of the audio; the DSP thread shifts the capture back down
+    pub hardware_offset: i32,
+    /// How the DC spike is kept out of chain A
+    pub dc_avoidance: DcAvoidance,
+    /// Hardware offset for `DcAvoidance::Offset` in Hz
+    pub dc_offset: u32,
+    /// Whether the DSP thread is notching out the DC spike
+    pub dc_notch_active: bool,
+    /// Offsets from dBFS to dBm by tuner gain
+    pub calibration: Calibration,
     /// Sample rate in Hz
     pub sample_rate: u32,
-    /// Tuner gain in tenths of dB (-1 = auto)
+    /// Tuner gain in tenths of dB (-1 = never set manually)
     pub tuner_gain: i32,
-    /// Automatic gain control enabled
-    pub auto_gain: bool,
+    /// Tuner automatic gain mode enabled
+    pub tuner_agc: bool,
+    /// RTL2832 digital AGC enabled
+    pub rtl_agc: bool,
     /// PPM frequency correction
     pub ppm_error: i32,
     /// Whether the SDR is currently running
     pub is_running: bool,
     /// Device serial number
     pub device_serial: Option<String>,
+    /// Tuning and gain limits of the open receiver
+    pub capabilities: Capabilities,
+    /// Trouble with a remote receiver's connection, until it recovers
+    pub link_warning: Option<String>,
+    /// The sample stream stalled, until samples arrive again
+    pub stall_warning: Option<String>,
+    /// Why there is no receiver yet, while waiting for it to appear
+    pub waiting_for_device: Option<String>,
+    /// When the DSP thread last got a sample buffer
+    pub last_samples: Option<Instant>,
+    /// Samples the receiver has delivered, which numbers them for the
+    /// recorder
+    pub samples_delivered: u64,
+    /// Silence after which the receiver is reopened, None to never
+    pub stall_timeout: Option<Duration>,
+    /// Switch sample rate and tuner bandwidth to suit each new mode
+    pub auto_rate: bool,
+    /// Gain by frequency range, when on
+    pub gain_profiles: Option<GainProfiles>,
+    /// Progress of a running gain sweep
+    pub gain_sweep: Option<String>,
+    /// Set to stop the running gain sweep
+    pub gain_sweep_cancel: bool,
+    /// Progress of a running PPM calibration
+    pub ppm_calibration: Option<String>,
+    /// VFOs A and B; the active one follows chain A, the other keeps its
+    /// settings for a swap
+    pub vfo: [VfoConfig; 2],
+    pub active_vfo: usize,
 }
 
 impl Default for SdrState {
     fn default() -> Self {
         Self {
             frequency: 144_390_000,  // 144.390 MHz (APRS)
+            hardware_offset: 0,
+            dc_avoidance: DcAvoidance::default(),
+            dc_offset: DEFAULT_DC_OFFSET,
+            dc_notch_active: false,
+            calibration: Calibration::default(),
             sample_rate: 2_048_000,  // 2.048 MHz
             tuner_gain: -1,          // Auto gain
-            auto_gain: true,
+            tuner_agc: true,
+            rtl_agc: false,
             ppm_error: 0,
             is_running: false,
             device_serial: None,
+            capabilities: Capabilities::default(),
+            link_warning: None,
+            stall_warning: None,
+            waiting_for_device: None,
+            last_samples: None,
+            samples_delivered: 0,
+            stall_timeout: Some(DEFAULT_STALL_TIMEOUT),
+            auto_rate: true,
+            gain_profiles: None,
+            gain_sweep: None,
+            gain_sweep_cancel: false,
+            ppm_calibration: None,
+            vfo: [VfoConfig {
+                frequency: 144_390_000,
+                mode: DemodMode::default(),
+                filter_width: None,
+            }; 2],
+            active_vfo: 0,
         }
     }
 }
 
+impl SdrState {
+    /// Whatever is wrong with the receiver's link and its stream
+    pub fn warning(&self) -> Option<String> {
+        match (&self.link_warning, &self.stall_warning) {
+            (Some(link), Some(stall)) => Some(format!("{}; {}", link, stall)),
+            (link, stall) => link.clone().or(stall.clone()),
+        }
+    }
+
+    /// The manual tuner gain in tenths of dB, None on auto gain
+    pub fn manual_gain(&self) -> Option<i32> {
+        (!self.tuner_agc && self.tuner_gain >= 0).then_some(self.tuner_gain)
+    }
+
+    /// How levels are shown at the current gain
+    pub fn level_scale(&self) -> LevelScale {
+        LevelScale::new(self.calibration.offset(self.manual_gain()))
+    }
+
+    /// What the receiver itself is tuned to
+    pub fn hardware_frequency(&self) -> u32 {
+        self.frequency.saturating_add_signed(self.hardware_offset)
+    }
+
+    /// Describe the gain setting, e.g. "29.7 dB (tuner auto, RTL AGC off)"
+    pub fn gain_description(&self) -> String {
+        let gain = if self.tuner_gain < 0 {
+            "Auto".to_string()
+        } else {
+            format!("{}.{} dB", self.tuner_gain / 10, self.tuner_gain % 10)
+        };
+        format!(
+            "{} (tuner {}, RTL AGC {})",
+            gain,
+            if self.tuner_agc { "auto" } else { "manual" },
+            if self.rtl_agc { "on" } else { "off" }
+        )
+    }
+}
+
+/// Columns every waterfall row is resampled to on insert, so history
+/// survives FFT size changes
+pub const WATERFALL_WIDTH: usize = 1024;
+
+/// One line of waterfall history
+#[derive(Debug, Clone)]
+pub struct WaterfallRow {
+    /// When it was added, in Unix milliseconds
+    pub time: i64,
+    /// `WATERFALL_WIDTH` bins in dB
+    pub bins: Vec<f32>,
+}
+
+impl WaterfallRow {
+    pub fn time(&self) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(self.time).single().unwrap_or_default()
+    }
+}
+
 /// Spectrum analyzer and waterfall state
 #[derive(Debug)]
 pub struct SpectrumState {
     /// Current FFT magnitude data (in dB)
     pub fft_data: Vec<f32>,
-    /// Waterfall history (ring buffer of FFT data)
-    pub waterfall: Vec<Vec<f32>>,
-    /// Current index in waterfall ring buffer
+    /// Frames ever shown; a new one means fresh `fft_data`
+    pub frames: u64,
+    /// Waterfall history (ring buffer of `WATERFALL_WIDTH` rows), growing
+    /// up to `max_waterfall_history` rows before it wraps
+    pub waterfall: Vec<WaterfallRow>,
+    /// Oldest row in the waterfall ring buffer, and the next to overwrite
     pub waterfall_index: usize,
-    /// Maximum waterfall history size
+    /// Maximum waterfall history size; change with `set_waterfall_history`
     pub max_waterfall_history: usize,
+    /// Rows ever added to the waterfall
+    pub waterfall_rows: u64,
+    /// Waterfall rows wanted per second; each is made of as many frames as
+    /// arrive in that time
+    pub line_rate: f32,
+    /// How the frames in a waterfall row are combined
+    pub integration: Integration,
+    /// Spectrum frames arriving per second; 0 until known, and then every
+    /// frame is a row
+    pub frame_rate: f32,
+    /// The waterfall row being gathered, and when its first frame was from
+    integrator: RowIntegrator,
+    row_time: i64,
+    /// Demodulated audio spectrum from 0 Hz to `AF_MAX_FREQ` (in dB),
+    /// computed only while the AF pane is shown
+    pub af_fft: Vec<f32>,
+    /// Evenly spaced samples from the latest raw IQ buffer, kept only
+    /// while the constellation is shown
+    pub iq_snapshot: Vec<Complex<f32>>,
+    /// Recent demodulated audio for the oscilloscope, fed only while it is shown
+    pub scope: ScopeBuffer,
+    /// Signals the activity detector has seen recently
+    pub activity: ActivityTable,
+    /// Measurement markers, at absolute frequencies
+    pub markers: Markers,
 }
 
 impl Default for SpectrumState {
     fn default() -> Self {
         Self {
             fft_data: vec![],
+            frames: 0,
             waterfall: vec![],
             waterfall_index: 0,
             max_waterfall_history: 500,
+            waterfall_rows: 0,
+            line_rate: DEFAULT_LINE_RATE,
+            integration: Integration::default(),
+            frame_rate: 0.0,
+            integrator: RowIntegrator::default(),
+            row_time: 0,
+            af_fft: vec![],
+            iq_snapshot: vec![],
+            scope: ScopeBuffer::default(),
+            activity: ActivityTable::default(),
+            markers: Markers::default(),
         }
     }
 }
 
 impl SpectrumState {
-    /// Add new FFT data to waterfall
-    pub fn add_fft_data(&mut self, data: Vec<f32>) {
-        self.fft_data = data.clone();
-
-        // Initialize waterfall if empty
-        if self.waterfall.is_empty() {
-            self.waterfall = vec![vec![0.0; data.len()]; self.max_waterfall_history];
+    /// Show a new spectrum frame, as of `time`, and gather it into the
+    /// waterfall, which gets a row once the frames for one are in
+    pub fn add_fft_data(&mut self, data: Vec<f32>, time: DateTime<Utc>) {
+        if self.integrator.frames() == 0 {
+            self.row_time = time.timestamp_millis();
+        }
+        let per_row = frames_per_line(self.line_rate, self.frame_rate);
+        let integrated = self.integrator.add(&data, self.integration, per_row);
+        self.fft_data = data;
+        self.frames += 1;
+        if let Some(bins) = integrated {
+            self.push_row(WaterfallRow {
+                time: self.row_time,
+                bins: resample_row(&bins, WATERFALL_WIDTH),
+            });
         }
+    }
+
+    /// Drop the waterfall row being gathered, so it doesn't mix frames
+    /// from before a retune with those after
+    pub fn restart_row(&mut self) {
+        self.integrator.reset();
+    }
+
+    fn push_row(&mut self, row: WaterfallRow) {
+        self.waterfall_rows += 1;
 
         // Add to ring buffer
-        if self.waterfall_index < self.waterfall.len() {
-            self.waterfall[self.waterfall_index] = data;
+        if self.waterfall.len() < self.max_waterfall_history {
+            self.waterfall.push(row);
+            self.waterfall_index = self.waterfall.len() % self.max_waterfall_history;
+        } else {
+            self.waterfall[self.waterfall_index] = row;
             self.waterfall_index = (self.waterfall_index + 1) % self.waterfall.len();
         }
     }
 
-    /// Get waterfall data in display order (oldest to newest)
-    pub fn get_waterfall_display(&self) -> Vec<&Vec<f32>> {
-        if self.waterfall.is_empty() {
-            return vec![];
+    /// Keep at most `rows` rows of waterfall history, dropping the oldest
+    pub fn set_waterfall_history(&mut self, rows: usize) {
+        let rows = rows.max(1);
+        let mut history: Vec<WaterfallRow> =
+            self.waterfall.drain(self.waterfall_index..).collect();
+        history.append(&mut self.waterfall);
+        history.drain(..history.len().saturating_sub(rows));
+
+        self.waterfall = history;
+        self.waterfall_index = self.waterfall.len() % rows;
+        self.max_waterfall_history = rows;
+    }
+
+    /// Get up to `rows` of waterfall data ending `back` rows before the
+    /// newest, in display order (oldest to newest)
+    pub fn get_waterfall_display(&self, back: usize, rows: usize) -> Vec<&WaterfallRow> {
+        let len = self.waterfall.len();
+        // Positions in display order, 0 being the oldest row
+        let end = len.saturating_sub(back);
+        let start = end.saturating_sub(rows);
+        (start..end)
+            .map(|i| &self.waterfall[(self.waterfall_index + i) % len])
+            .collect()
+    }
+}
+
+/// Resample an FFT row to `width` columns: shrinking keeps each column's
+/// strongest bin so narrow signals survive, growing interpolates
+fn resample_row(data: &[f32], width: usize) -> Vec<f32> {
+    if data.is_empty() {
+        return vec![-100.0; width];
+    }
+    if data.len() == width {
+        return data.to_vec();
+    }
+
+    if data.len() > width {
+        (0..width)
+            .map(|i| {
+                let start = i * data.len() / width;
+                let end = ((i + 1) * data.len() / width).max(start + 1);
+                data[start..end].iter().copied().fold(f32::NEG_INFINITY, f32::max)
+            })
+            .collect()
+    } else {
+        let ratio = (data.len() - 1) as f32 / (width - 1).max(1) as f32;
+        (0..width)
+            .map(|i| {
+                let position = i as f32 * ratio;
+                let index = (position as usize).min(data.len() - 1);
+                let next = (index + 1).min(data.len() - 1);
+                let frac = position - index as f32;
+                data[index] * (1.0 - frac) + data[next] * frac
+            })
+            .collect()
+    }
+}
+
+/// Highest rate audio is kept at for the oscilloscope
+pub const SCOPE_MAX_RATE: u32 = 48_000;
+/// Length of audio the oscilloscope keeps, in milliseconds
+const SCOPE_HISTORY_MS: u32 = 50;
+
+/// Ring of the most recent audio, averaged down to at most `SCOPE_MAX_RATE`
+#[derive(Debug, Clone, Default)]
+pub struct ScopeBuffer {
+    ring: Vec<f32>,
+    /// Next slot to overwrite
+    index: usize,
+    /// Input rate the ring was sized for
+    input_rate: u32,
+    decimation: u32,
+    acc: f32,
+    acc_count: u32,
+}
+
+impl ScopeBuffer {
+    /// Append audio at `sample_rate`; a rate change starts a fresh ring
+    pub fn push(&mut self, sample_rate: u32, audio: &[f32]) {
+        if sample_rate != self.input_rate {
+            self.input_rate = sample_rate;
+            self.decimation = sample_rate.div_ceil(SCOPE_MAX_RATE).max(1);
+            let len = (self.sample_rate() * SCOPE_HISTORY_MS / 1000).max(1);
+            self.ring = vec![0.0; len as usize];
+            self.index = 0;
+            self.acc = 0.0;
+            self.acc_count = 0;
         }
 
-        let mut result = Vec::with_capacity(self.waterfall.len());
+        for &sample in audio {
+            self.acc += sample;
+            self.acc_count += 1;
+            if self.acc_count == self.decimation {
+                self.ring[self.index] = self.acc / self.decimation as f32;
+                self.index = (self.index + 1) % self.ring.len();
+                self.acc = 0.0;
+                self.acc_count = 0;
+            }
+        }
+    }
 
-        // Add from current index to end (oldest data)
-        for i in self.waterfall_index..self.waterfall.len() {
-            result.push(&self.waterfall[i]);
+    /// Rate of the kept audio
+    pub fn sample_rate(&self) -> u32 {
+        self.input_rate / self.decimation.max(1)
+    }
+
+    /// The kept audio, oldest first
+    pub fn snapshot(&self) -> Vec<f32> {
+        let mut samples = self.ring[self.index..].to_vec();
+        samples.extend_from_slice(&self.ring[..self.index]);
+        samples
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Where the receive chains listen within the captured bandwidth
+#[derive(Debug, Clone)]
+pub struct ChannelState {
+    /// Chain A's offset from the center frequency in Hz; its mode is
+    /// `DecoderState::mode`
+    pub offset_a: i32,
+    /// Chain B's offset from the center frequency in Hz
+    pub offset_b: i32,
+    /// Chain B's mode, `Raw` while it is off
+    pub mode_b: DemodMode,
+}
+
+impl Default for ChannelState {
+    fn default() -> Self {
+        Self {
+            offset_a: 0,
+            offset_b: 0,
+            mode_b: DemodMode::Raw,
         }
+    }
+}
 
-        // Add from start to current index (newest data)
-        for i in 0..self.waterfall_index {
-            result.push(&self.waterfall[i]);
+impl ChannelState {
+    pub fn offset(&self, chain: Chain) -> i32 {
+        match chain {
+            Chain::A => self.offset_a,
+            Chain::B => self.offset_b,
         }
+    }
 
-        result
+    pub fn b_enabled(&self) -> bool {
+        self.mode_b != DemodMode::Raw
+    }
+}
+
+/// Priority channel watch, shared between its thread and the DSP thread
+#[derive(Debug, Default)]
+pub struct PriorityState {
+    /// Frequency watched in Hz, None if none is configured
+    pub frequency: Option<u32>,
+    pub enabled: bool,
+    /// Audio has switched to the priority channel
+    pub active: bool,
+    /// Audio is muted while a check has the tuner away
+    pub muted: bool,
+    /// Offset from the center the DSP thread measures while checking
+    pub probe_offset: Option<i32>,
+    /// Power at the probe offset in dBFS, from the latest buffer
+    pub probe_level: Option<f32>,
+}
+
+impl PriorityState {
+    /// Whether a check has chain A or the tuner away from where the user
+    /// left them, so a retune now isn't theirs
+    pub fn away(&self) -> bool {
+        self.muted || self.probe_offset.is_some()
     }
 }
 
@@ -136,26 +677,119 @@ impl SpectrumState {
 pub struct DecoderState {
     /// Current demodulation mode
     pub mode: DemodMode,
+    /// Channel filter width in Hz, None for the mode's default; kept for
+    /// each mode in `mode_memory`
+    pub filter_width: Option<u32>,
+    /// Filter width, squelch and demodulator settings as each mode was left
+    pub mode_memory: ModeMemory,
     /// Recent decoded messages
     pub messages: Vec<DecodedMessage>,
     /// Maximum number of messages to keep
     pub max_messages: usize,
+    /// Messages received in all, including those no longer kept
+    pub received: u64,
+    /// Squelch threshold in dBFS (None = squelch off)
+    pub squelch_level: Option<f32>,
+    /// Most recent channel power in dBFS
+    pub signal_level: f32,
+    /// Whether the squelch is currently open
+    pub squelch_open: bool,
+    /// Apply the per-mode audio high-pass/bandpass filters
+    pub audio_filters: bool,
+    /// Soft limiter for everything heard, streamed or recorded; None clips
+    pub limiter: Option<SoftLimiter>,
+    /// Background noise reduction in the voice modes
+    pub noise_reduction: NoiseReduction,
+    /// FM de-emphasis time constant in µs, None for a flat response; kept
+    /// for each mode in `mode_memory`, as are the two below
+    pub deemphasis: Option<u32>,
+    /// Where the SSB and CW beat oscillator sits from the carrier, in Hz
+    pub bfo_offset: i32,
+    /// How quickly the audio AGC follows the level
+    pub agc_speed: AgcSpeed,
+    /// Decode DTMF digits while in NFM mode
+    pub dtmf_enabled: bool,
+    /// Estimated Morse speed while the CW decoder is running
+    pub cw_wpm: Option<f32>,
+    /// Active SAME alert banner, cleared by the end-of-message burst
+    pub same_alert: Option<String>,
+    /// Lines of the current APT pass (0 while searching) while the APT
+    /// decoder is running
+    pub apt_lines: Option<usize>,
+    /// Append decoded messages to the decode log
+    pub log_enabled: bool,
+    /// Where the decode log is written
+    pub log_path: PathBuf,
+    /// Aircraft heard in ADS-B mode, by ICAO address
+    pub aircraft: HashMap<u32, Aircraft>,
 }
 
 impl Default for DecoderState {
     fn default() -> Self {
+        let defaults = ModeSettings::default_for(DemodMode::default());
         Self {
             mode: DemodMode::default(),
+            filter_width: None,
+            mode_memory: ModeMemory::default(),
             messages: Vec::new(),
             max_messages: 100,
+            received: 0,
+            squelch_level: None,
+            signal_level: -100.0,
+            squelch_open: true,
+            audio_filters: true,
+            limiter: Some(SoftLimiter::new(0.8)),
+            noise_reduction: NoiseReduction::Off,
+            deemphasis: defaults.deemphasis,
+            bfo_offset: defaults.bfo_offset,
+            agc_speed: defaults.agc_speed,
+            dtmf_enabled: false,
+            cw_wpm: None,
+            same_alert: None,
+            apt_lines: None,
+            log_enabled: false,
+            log_path: PathBuf::from("decodes.jsonl"),
+            aircraft: HashMap::new(),
         }
     }
 }
 
 impl DecoderState {
+    /// The filter width, squelch and demodulator settings as they are now
+    pub fn mode_settings(&self) -> ModeSettings {
+        ModeSettings {
+            filter_width: self.filter_width,
+            squelch_level: self.squelch_level,
+            deemphasis: self.deemphasis,
+            bfo_offset: self.bfo_offset,
+            agc_speed: self.agc_speed,
+        }
+    }
+
+    /// Take up `settings` as the current mode's
+    pub fn restore(&mut self, settings: ModeSettings) {
+        self.filter_width = settings.filter_width;
+        self.squelch_level = settings.squelch_level;
+        self.deemphasis = settings.deemphasis;
+        self.bfo_offset = settings.bfo_offset;
+        self.agc_speed = settings.agc_speed;
+    }
+
+    /// Change to `mode`, storing the outgoing mode's settings and putting
+    /// back the incoming one's
+    pub fn switch_mode(&mut self, mode: DemodMode) {
+        if mode == self.mode {
+            return;
+        }
+        self.mode_memory.store(self.mode, self.mode_settings());
+        self.mode = mode;
+        self.restore(self.mode_memory.get(mode));
+    }
+
     /// Add a new decoded message
     pub fn add_message(&mut self, message: DecodedMessage) {
         self.messages.push(message);
+        self.received += 1;
 
         // Keep only the most recent messages
         if self.messages.len() > self.max_messages {
@@ -167,6 +801,11 @@ impl DecoderState {
     pub fn clear_messages(&mut self) {
         self.messages.clear();
     }
+
+    /// Forget aircraft that have not been heard from for a while
+    pub fn prune_aircraft(&mut self, now: Instant) {
+        self.aircraft.retain(|_, aircraft| !aircraft.is_expired(now));
+    }
 }
 
 /// Recording state
@@ -180,6 +819,34 @@ pub struct RecordingState {
     pub samples_recorded: u64,
     /// Recording start time
     pub start_time: Option<chrono::DateTime<chrono::Utc>>,
+    /// Directory new recordings are written to
+    pub output_dir: PathBuf,
+    /// Write SigMF (.sigmf-data + .sigmf-meta) instead of raw .iq
+    pub sigmf: bool,
+    /// Sample format IQ recordings are written in
+    pub format: SampleFormat,
+    /// Bytes per complex sample in the output format (2 for raw u8 IQ)
+    pub bytes_per_sample: u64,
+    /// Free space on the recording's filesystem in bytes, if known
+    pub free_space: Option<u64>,
+    /// Free space in bytes below which the indicator turns yellow
+    pub low_space_warning: u64,
+    /// Free space in bytes below which recording stops automatically
+    pub min_free_space: u64,
+    /// What gets recorded when recording is started
+    pub mode: RecordingMode,
+    /// Segmenting parameters for squelch-triggered recording
+    pub vox: VoxSettings,
+    /// Squelch segments kept in the current session
+    pub segments: usize,
+    /// Upcoming or running scheduled recording, for display
+    pub next_scheduled: Option<String>,
+    /// Split IQ recordings into parts per this policy
+    pub split: Option<SplitPolicy>,
+    /// Part currently being written, when splitting
+    pub part: Option<u32>,
+    /// What a retune does to an IQ recording
+    pub retune: RetunePolicy,
 }
 
 impl Default for RecordingState {
@@ -189,6 +856,20 @@ impl Default for RecordingState {
             file_path: None,
             samples_recorded: 0,
             start_time: None,
+            output_dir: PathBuf::from("."),
+            sigmf: false,
+            format: SampleFormat::Cu8,
+            bytes_per_sample: 2,
+            free_space: None,
+            low_space_warning: 2048 * 1024 * 1024, // 2 GiB
+            min_free_space: 256 * 1024 * 1024,     // 256 MiB
+            mode: RecordingMode::Iq,
+            vox: VoxSettings::default(),
+            segments: 0,
+            next_scheduled: None,
+            split: None,
+            part: None,
+            retune: RetunePolicy::default(),
         }
     }
 }
@@ -199,6 +880,8 @@ impl RecordingState {
         self.is_recording = true;
         self.file_path = Some(path);
         self.samples_recorded = 0;
+        self.segments = 0;
+        self.part = None;
         self.start_time = Some(chrono::Utc::now());
     }
 
@@ -207,9 +890,212 @@ impl RecordingState {
         self.is_recording = false;
         self.file_path = None;
         self.start_time = None;
+        self.free_space = None;
+    }
+
+    /// Extension of the next IQ recording, which says what is in it
+    pub fn extension(&self) -> &'static str {
+        if self.sigmf {
+            "sigmf-data"
+        } else {
+            self.format.extension()
+        }
+    }
+
+    /// Bytes written to the current recording
+    pub fn bytes_written(&self) -> u64 {
+        self.samples_recorded * self.bytes_per_sample
+    }
+
+    /// Seconds elapsed since the recording started
+    pub fn elapsed_secs(&self) -> i64 {
+        self.start_time
+            .map(|start| (chrono::Utc::now() - start).num_seconds().max(0))
+            .unwrap_or(0)
+    }
+
+    /// Whether free space has dropped under the warning threshold
+    pub fn is_low_on_space(&self) -> bool {
+        self.free_space
+            .is_some_and(|free| free < self.low_space_warning)
+    }
+
+    /// One-line summary, e.g. "00:01:23  12.3 MB  (41.2 GB free)"
+    pub fn summary(&self) -> String {
+        let mut summary = match self.mode {
+            RecordingMode::Iq => format!(
+                "{}  {:.1} MB",
+                format_elapsed(self.elapsed_secs()),
+                self.bytes_written() as f64 / 1_000_000.0
+            ),
+            RecordingMode::Squelch => format!(
+                "(squelch) {}  {} files",
+                format_elapsed(self.elapsed_secs()),
+                self.segments
+            ),
+        };
+        if let Some(part) = self.part {
+            summary.push_str(&format!("  part {}", part));
+        }
+        if let Some(free) = self.free_space {
+            summary.push_str(&format!("  ({:.1} GB free)", free as f64 / 1e9));
+        }
+        summary
     }
 }
 
+/// What a recording captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingMode {
+    /// Continuous raw IQ
+    Iq,
+    /// Demodulated audio, one WAV file per squelch opening
+    Squelch,
+}
+
+impl RecordingMode {
+    /// Get display name
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordingMode::Iq => "IQ",
+            RecordingMode::Squelch => "Squelch (WAV)",
+        }
+    }
+
+    /// Switch to the other mode
+    pub fn toggle(&self) -> Self {
+        match self {
+            RecordingMode::Iq => RecordingMode::Squelch,
+            RecordingMode::Squelch => RecordingMode::Iq,
+        }
+    }
+}
+
+impl std::str::FromStr for RecordingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "iq" => Ok(RecordingMode::Iq),
+            "squelch" | "vox" => Ok(RecordingMode::Squelch),
+            _ => Err(format!("unknown recording mode '{}' (expected iq or squelch)", s)),
+        }
+    }
+}
+
+/// Timing for squelch-triggered recording, in seconds
+#[derive(Debug, Clone, Copy)]
+pub struct VoxSettings {
+    /// Audio kept from before the squelch opened
+    pub pre_roll: f32,
+    /// How long the squelch must stay closed before a file is finalized
+    pub hang: f32,
+    /// Transmissions shorter than this are discarded
+    pub min_length: f32,
+}
+
+impl Default for VoxSettings {
+    fn default() -> Self {
+        Self {
+            pre_roll: 0.5,
+            hang: 1.0,
+            min_length: 0.3,
+        }
+    }
+}
+
+/// Format a duration in seconds as HH:MM:SS
+pub fn format_elapsed(secs: i64) -> String {
+    let secs = secs.max(0);
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+/// Which time zone clocks and message times are shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClockZone {
+    #[default]
+    Local,
+    /// As APRS, ADS-B and most logs use
+    Utc,
+}
+
+impl ClockZone {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClockZone::Local => "local",
+            ClockZone::Utc => "utc",
+        }
+    }
+
+    pub fn toggle(self) -> Self {
+        match self {
+            ClockZone::Local => ClockZone::Utc,
+            ClockZone::Utc => ClockZone::Local,
+        }
+    }
+
+    /// Wall clock for the status bar, e.g. "14:03:22 UTC" or "16:03:22 +02:00"
+    pub fn clock(&self, time: DateTime<Utc>) -> String {
+        match self {
+            ClockZone::Local => format_clock(&time.with_timezone(&Local)),
+            ClockZone::Utc => time.format("%H:%M:%S UTC").to_string(),
+        }
+    }
+
+    /// Date and time of a decoded message, e.g. "2024-06-01 14:03:22Z"
+    pub fn date_time(&self, time: DateTime<Utc>) -> String {
+        match self {
+            ClockZone::Local => time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string(),
+            ClockZone::Utc => time.format("%Y-%m-%d %H:%M:%SZ").to_string(),
+        }
+    }
+
+    /// Time of a decoded message, e.g. "14:03:22Z" or "16:03:22"
+    pub fn timestamp(&self, time: DateTime<Utc>) -> String {
+        match self {
+            ClockZone::Local => time.with_timezone(&Local).format("%H:%M:%S").to_string(),
+            ClockZone::Utc => time.format("%H:%M:%SZ").to_string(),
+        }
+    }
+
+    /// Time of a waterfall row to the millisecond, e.g. "14:03:22.250Z"
+    pub fn timestamp_millis(&self, time: DateTime<Utc>) -> String {
+        match self {
+            ClockZone::Local => time.with_timezone(&Local).format("%H:%M:%S%.3f").to_string(),
+            ClockZone::Utc => time.format("%H:%M:%S%.3fZ").to_string(),
+        }
+    }
+}
+
+impl std::str::FromStr for ClockZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "local" => Ok(ClockZone::Local),
+            "utc" => Ok(ClockZone::Utc),
+            _ => Err(format!("unknown clock '{}' (expected local or utc)", s)),
+        }
+    }
+}
+
+/// A zoned time with its offset, so a daylight saving change shows
+pub fn format_clock<Tz: TimeZone>(time: &DateTime<Tz>) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    time.format("%H:%M:%S %:z").to_string()
+}
+
+/// TCP audio streaming state
+#[derive(Debug, Default)]
+pub struct StreamingState {
+    /// Listening port, if the streaming server is running
+    pub port: Option<u16>,
+    /// Number of currently connected clients
+    pub clients: usize,
+}
+
 /// UI state
 #[derive(Debug)]
 pub struct UiState {
@@ -219,6 +1105,56 @@ pub struct UiState {
     pub status_message: String,
     /// Whether the application should quit
     pub should_quit: bool,
+    /// Modal dialog currently capturing input, if any
+    pub modal: Option<Modal>,
+    /// Aircraft table sort column and first visible row
+    pub aircraft_sort: AircraftSort,
+    pub aircraft_scroll: usize,
+    /// Whether the AF spectrum pane is shown
+    pub show_af_spectrum: bool,
+    /// Whether the IQ constellation pane is shown
+    pub show_constellation: bool,
+    /// Whether the audio oscilloscope is shown, its rising-edge trigger,
+    /// and the amplitude at its top and bottom edges
+    pub show_scope: bool,
+    pub scope_trigger: bool,
+    pub scope_range: f32,
+    /// Whether the processing stats overlay is shown
+    pub show_stats: bool,
+    /// `SpectrumState::waterfall_rows` when the waterfall was paused, None
+    /// while live
+    pub waterfall_paused_at: Option<u64>,
+    /// Rows scrolled back from where the waterfall was paused
+    pub waterfall_scroll: usize,
+    /// Receive chain the frequency and mode controls adjust
+    pub selected_chain: Chain,
+    /// Progress of a running band scan
+    pub scan_status: Option<String>,
+    /// Whether the activity table replaces the decoder pane, and its
+    /// highlighted row
+    pub show_activity: bool,
+    pub activity_selected: usize,
+    /// Zone of the status bar clock and message times
+    pub clock: ClockZone,
+    /// Frequencies recently tuned by hand
+    pub frequency_history: FrequencyHistory,
+    pub bookmarks: Bookmarks,
+    /// Levels of the bookmark recalled last, while they apply
+    pub level_override: LevelOverride,
+    /// Steps of the user's own tuning and settings changes
+    pub undo: UndoHistory,
+    /// Whether the speaker plays the selected receiver; decoders, the
+    /// network stream and recordings carry on either way
+    pub monitor: bool,
+    /// Pane the pane-specific keys apply to
+    pub focused_pane: PaneId,
+    /// Whether the decoder pane follows new messages
+    pub message_view: MessageView,
+    /// When the test tone stops, while it plays over the audio at the
+    /// speaker and streams
+    pub test_tone: Option<Instant>,
+    /// Trouble with the speaker, until it plays again
+    pub audio_warning: Option<String>,
 }
 
 impl Default for UiState {
@@ -227,16 +1163,123 @@ impl Default for UiState {
             selected_control: ControlId::Frequency,
             status_message: String::from("Ready"),
             should_quit: false,
+            modal: None,
+            aircraft_sort: AircraftSort::default(),
+            aircraft_scroll: 0,
+            show_af_spectrum: false,
+            show_constellation: false,
+            show_scope: false,
+            scope_trigger: true,
+            scope_range: 1.0,
+            show_stats: false,
+            waterfall_paused_at: None,
+            waterfall_scroll: 0,
+            selected_chain: Chain::A,
+            scan_status: None,
+            show_activity: false,
+            activity_selected: 0,
+            clock: ClockZone::default(),
+            frequency_history: FrequencyHistory::default(),
+            bookmarks: Bookmarks::default(),
+            level_override: LevelOverride::default(),
+            undo: UndoHistory::default(),
+            monitor: true,
+            focused_pane: PaneId::Controls,
+            message_view: MessageView::default(),
+            test_tone: None,
+            audio_warning: None,
         }
     }
 }
 
+impl UiState {
+    /// Hand what belongs to the screen rather than to a receiver over to
+    /// `to`, the receiver being selected instead of this one
+    ///
+    /// Layout, focus, the clock and the user's bookmarks and history go
+    /// across; the panes this one had open are closed so its DSP thread
+    /// stops computing them. Its status, scan progress, message view,
+    /// levels and undo steps stay, for when it is selected again.
+    pub fn hand_over(&mut self, to: &mut UiState) {
+        to.selected_control = self.selected_control;
+        to.focused_pane = self.focused_pane;
+        to.aircraft_sort = self.aircraft_sort;
+        to.show_af_spectrum = std::mem::take(&mut self.show_af_spectrum);
+        to.show_constellation = std::mem::take(&mut self.show_constellation);
+        to.show_scope = std::mem::take(&mut self.show_scope);
+        to.scope_trigger = self.scope_trigger;
+        to.scope_range = self.scope_range;
+        to.show_stats = self.show_stats;
+        to.show_activity = self.show_activity;
+        to.clock = self.clock;
+        to.monitor = self.monitor;
+        std::mem::swap(&mut to.frequency_history, &mut self.frequency_history);
+        std::mem::swap(&mut to.bookmarks, &mut self.bookmarks);
+    }
+}
+
+/// Modal dialogs that suspend the normal key bindings while open
+#[derive(Debug, Clone, PartialEq)]
+pub enum Modal {
+    /// Quit confirmation, with the reason quitting is risky
+    ConfirmQuit(String),
+    /// Offer a measured PPM correction, explained by `message`
+    ApplyPpm { ppm: i32, message: String },
+    /// Recently tuned frequencies to pick from, with the highlighted row
+    History { selected: usize },
+    /// Profiles to switch to, with the highlighted row
+    Profiles { selected: usize },
+    /// Bookmarks to recall, with the highlighted row
+    Bookmarks { selected: usize },
+    /// Clearing the decoded messages, with the question asked
+    ConfirmClearMessages(String),
+    /// The known level in dBm of the spectrum's peak, `peak` dBFS, as typed
+    /// so far
+    CalibrateLevel { peak: f32, input: String },
+}
+
+/// Panes that can have focus, in the order focus cycles through them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaneId {
+    Spectrum,
+    Waterfall,
+    Controls,
+    Decoder,
+}
+
+impl PaneId {
+    const ALL: [PaneId; 4] =
+        [PaneId::Spectrum, PaneId::Waterfall, PaneId::Controls, PaneId::Decoder];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PaneId::Spectrum => "spectrum",
+            PaneId::Waterfall => "waterfall",
+            PaneId::Controls => "controls",
+            PaneId::Decoder => "decoder",
+        }
+    }
+
+    /// The pane focus moves to next
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&p| p == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// The pane focus moves back to
+    pub fn prev(self) -> Self {
+        let index = Self::ALL.iter().position(|&p| p == self).unwrap_or(0);
+        Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
 /// Control element identifiers for UI navigation
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ControlId {
     Frequency,
     Mode,
     Gain,
+    Squelch,
     SampleRate,
     Record,
 }
@@ -248,6 +1291,7 @@ impl ControlId {
             ControlId::Frequency,
             ControlId::Mode,
             ControlId::Gain,
+            ControlId::Squelch,
             ControlId::SampleRate,
             ControlId::Record,
         ]
@@ -272,3 +1316,335 @@ impl ControlId {
         all[prev_idx]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A row of `len` bins at -100 dB with a 0 dB bin `position` of the way across
+    fn row_with_peak(len: usize, position: f32) -> Vec<f32> {
+        let mut row = vec![-100.0; len];
+        row[(position * len as f32) as usize] = 0.0;
+        row
+    }
+
+    #[test]
+    fn test_waterfall_rows_of_any_length() {
+        let mut spectrum = SpectrumState::default();
+        for len in [2048, 1024, 4096, 512, 7] {
+            spectrum.add_fft_data(row_with_peak(len, 0.5), Utc::now());
+        }
+        spectrum.add_fft_data(vec![], Utc::now());
+        assert_eq!(spectrum.fft_data.len(), 0);
+
+        let rows: Vec<&Vec<f32>> =
+            spectrum.get_waterfall_display(0, usize::MAX).iter().map(|r| &r.bins).collect();
+        assert_eq!(rows.len(), 6);
+        assert!(rows.iter().all(|row| row.len() == WATERFALL_WIDTH));
+        // Single-bin peaks survive shrinking, and land in the same column
+        for row in &rows[..3] {
+            assert_eq!(row[WATERFALL_WIDTH / 2], 0.0);
+            assert_eq!(row.iter().filter(|&&db| db == 0.0).count(), 1);
+        }
+        // Growing interpolates
+        for row in &rows[3..5] {
+            assert!(row[WATERFALL_WIDTH / 2] > -100.0);
+            assert!(row.iter().any(|&db| db > -100.0 && db < 0.0));
+        }
+        assert!(rows[5].iter().all(|&db| db == -100.0));
+    }
+
+    #[test]
+    fn test_waterfall_history_resize() {
+        let mut spectrum = SpectrumState::default();
+        spectrum.set_waterfall_history(4);
+        // Rows tagged by their first value, wrapping the ring
+        for i in 0..6 {
+            spectrum.add_fft_data(vec![i as f32; WATERFALL_WIDTH], Utc::now());
+        }
+        let firsts = |spectrum: &SpectrumState| -> Vec<f32> {
+            spectrum.get_waterfall_display(0, usize::MAX).iter().map(|row| row.bins[0]).collect()
+        };
+        assert_eq!(firsts(&spectrum), [2.0, 3.0, 4.0, 5.0]);
+
+        // Shrinking keeps the newest rows
+        spectrum.set_waterfall_history(3);
+        assert_eq!(firsts(&spectrum), [3.0, 4.0, 5.0]);
+        spectrum.add_fft_data(vec![6.0; WATERFALL_WIDTH], Utc::now());
+        assert_eq!(firsts(&spectrum), [4.0, 5.0, 6.0]);
+
+        // Growing keeps everything and fills before wrapping
+        spectrum.set_waterfall_history(5);
+        for i in 7..10 {
+            spectrum.add_fft_data(vec![i as f32; WATERFALL_WIDTH], Utc::now());
+        }
+        assert_eq!(firsts(&spectrum), [5.0, 6.0, 7.0, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn test_waterfall_row_times() {
+        let mut spectrum = SpectrumState::default();
+        spectrum.set_waterfall_history(2);
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 14, 25, 3).unwrap();
+        for i in 0..3 {
+            let time = start + chrono::Duration::milliseconds(250 * i);
+            spectrum.add_fft_data(vec![-100.0; 16], time);
+        }
+        let rows = spectrum.get_waterfall_display(0, 2);
+        assert_eq!(rows[0].time - rows[1].time, -250);
+        assert_eq!(rows[1].time(), start + chrono::Duration::milliseconds(500));
+    }
+
+    #[test]
+    fn test_waterfall_line_rate() {
+        let mut spectrum = SpectrumState {
+            frame_rate: 100.0,
+            line_rate: 25.0,
+            integration: Integration::Peak,
+            ..Default::default()
+        };
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 14, 25, 3).unwrap();
+        for i in 0..10 {
+            let time = start + chrono::Duration::milliseconds(10 * i);
+            spectrum.add_fft_data(vec![i as f32; WATERFALL_WIDTH], time);
+            // The trace follows every frame
+            assert_eq!(spectrum.fft_data[0], i as f32);
+        }
+        assert_eq!(spectrum.frames, 10);
+
+        // Four frames a row, dated by the first; the last two still gathering
+        let rows = spectrum.get_waterfall_display(0, usize::MAX);
+        assert_eq!(spectrum.waterfall_rows, 2);
+        assert_eq!(rows.iter().map(|row| row.bins[0]).collect::<Vec<_>>(), [3.0, 7.0]);
+        assert_eq!(rows[1].time(), start + chrono::Duration::milliseconds(40));
+
+        // A retune drops them
+        spectrum.restart_row();
+        for _ in 0..4 {
+            spectrum.add_fft_data(vec![-100.0; WATERFALL_WIDTH], Utc::now());
+        }
+        assert_eq!(spectrum.get_waterfall_display(0, 1)[0].bins[0], -100.0);
+    }
+
+    #[test]
+    fn test_waterfall_window_across_wrap() {
+        let mut spectrum = SpectrumState::default();
+        spectrum.set_waterfall_history(5);
+        let firsts =
+            |rows: Vec<&WaterfallRow>| -> Vec<f32> { rows.iter().map(|r| r.bins[0]).collect() };
+
+        // Before the ring fills
+        for i in 0..3 {
+            spectrum.add_fft_data(vec![i as f32; WATERFALL_WIDTH], Utc::now());
+        }
+        assert_eq!(firsts(spectrum.get_waterfall_display(0, 2)), [1.0, 2.0]);
+        assert_eq!(firsts(spectrum.get_waterfall_display(1, 5)), [0.0, 1.0]);
+
+        // Rows 0-7 through a 5-row ring: 3-7 are kept, 5-7 in slots 0-2
+        for i in 3..8 {
+            spectrum.add_fft_data(vec![i as f32; WATERFALL_WIDTH], Utc::now());
+        }
+        assert_eq!(spectrum.waterfall_index, 3);
+        assert_eq!(spectrum.waterfall_rows, 8);
+        assert_eq!(firsts(spectrum.get_waterfall_display(0, 3)), [5.0, 6.0, 7.0]);
+        // Straddling the wrap point
+        assert_eq!(firsts(spectrum.get_waterfall_display(2, 2)), [4.0, 5.0]);
+        assert_eq!(firsts(spectrum.get_waterfall_display(1, 10)), [3.0, 4.0, 5.0, 6.0]);
+        // Entirely before it
+        assert_eq!(firsts(spectrum.get_waterfall_display(3, 2)), [3.0, 4.0]);
+        // Past the oldest row
+        assert!(spectrum.get_waterfall_display(5, 3).is_empty());
+        assert!(SpectrumState::default().get_waterfall_display(0, 3).is_empty());
+    }
+
+    #[test]
+    fn test_scope_buffer() {
+        let mut scope = ScopeBuffer::default();
+        assert!(scope.snapshot().is_empty());
+
+        // 250 kHz averages 6:1 down to 41.7 kHz; 50 ms is 2083 samples
+        let audio: Vec<f32> = (0..3000 * 6).map(|i| (i / 6) as f32).collect();
+        scope.push(250_000, &audio);
+        assert_eq!(scope.sample_rate(), 41_666);
+        let snapshot = scope.snapshot();
+        assert_eq!(snapshot.len(), 2083);
+        assert_eq!(snapshot[0], 917.0);
+        assert_eq!(*snapshot.last().unwrap(), 2999.0);
+
+        // A new rate starts over
+        scope.push(48_000, &[0.5; 10]);
+        assert_eq!(scope.snapshot().len(), 2400);
+        assert_eq!(scope.snapshot()[2399], 0.5);
+    }
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(0), "00:00:00");
+        assert_eq!(format_elapsed(83), "00:01:23");
+        assert_eq!(format_elapsed(36_000 + 61), "10:01:01");
+        assert_eq!(format_elapsed(-5), "00:00:00");
+    }
+
+    #[test]
+    fn test_clock_across_dst() {
+        use chrono::FixedOffset;
+        let cet = FixedOffset::east_opt(3600).unwrap();
+        let cest = FixedOffset::east_opt(7200).unwrap();
+
+        // Central Europe springs forward at 01:00 UTC on 29 March 2026
+        let before = Utc.with_ymd_and_hms(2026, 3, 29, 0, 59, 59).unwrap();
+        let after = before + chrono::Duration::seconds(2);
+        assert_eq!(format_clock(&before.with_timezone(&cet)), "01:59:59 +01:00");
+        assert_eq!(format_clock(&after.with_timezone(&cest)), "03:00:01 +02:00");
+        // Elapsed time counts real seconds, not the jump on the wall clock
+        assert_eq!(format_elapsed((after - before).num_seconds()), "00:00:02");
+        assert_eq!(ClockZone::Utc.clock(after), "01:00:01 UTC");
+        assert_eq!(ClockZone::Utc.timestamp(after), "01:00:01Z");
+
+        // Falling back on 25 October, 02:30 happens twice and the offset
+        // tells them apart
+        let first = Utc.with_ymd_and_hms(2026, 10, 25, 0, 30, 0).unwrap();
+        let second = first + chrono::Duration::hours(1);
+        assert_eq!(format_clock(&first.with_timezone(&cest)), "02:30:00 +02:00");
+        assert_eq!(format_clock(&second.with_timezone(&cet)), "02:30:00 +01:00");
+        assert_eq!(format_elapsed((second - first).num_seconds()), "01:00:00");
+    }
+
+    #[test]
+    fn test_clock_zone_parse_and_toggle() {
+        assert_eq!("UTC".parse::<ClockZone>(), Ok(ClockZone::Utc));
+        assert_eq!("local".parse::<ClockZone>(), Ok(ClockZone::Local));
+        assert!("gmt".parse::<ClockZone>().is_err());
+        assert_eq!(ClockZone::Local.toggle(), ClockZone::Utc);
+        assert_eq!(ClockZone::Utc.toggle().name(), "local");
+    }
+
+    #[test]
+    fn test_recording_size_and_space() {
+        let mut recording = RecordingState::default();
+        recording.start(PathBuf::from("test.iq"));
+        recording.samples_recorded = 1_000_000;
+        assert_eq!(recording.bytes_written(), 2_000_000);
+
+        assert!(!recording.is_low_on_space());
+        recording.free_space = Some(recording.low_space_warning - 1);
+        assert!(recording.is_low_on_space());
+        assert!(recording.summary().contains("2.0 MB"));
+
+        recording.part = Some(3);
+        assert!(recording.summary().contains("part 3"));
+    }
+
+    #[test]
+    fn test_squelch_recording_summary() {
+        let mut recording = RecordingState {
+            mode: "squelch".parse().unwrap(),
+            ..Default::default()
+        };
+        recording.start(PathBuf::from("."));
+        recording.segments = 3;
+        assert!(recording.summary().starts_with("(squelch)"));
+        assert!(recording.summary().ends_with("3 files"));
+        assert!("tape".parse::<RecordingMode>().is_err());
+    }
+
+    #[test]
+    fn test_pane_focus_cycles() {
+        let mut pane = UiState::default().focused_pane;
+        assert_eq!(pane, PaneId::Controls);
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            pane = pane.next();
+            seen.push(pane);
+        }
+        assert_eq!(
+            seen,
+            [PaneId::Decoder, PaneId::Spectrum, PaneId::Waterfall, PaneId::Controls]
+        );
+        assert_eq!(PaneId::Spectrum.prev(), PaneId::Decoder);
+        assert_eq!(PaneId::Decoder.prev(), PaneId::Controls);
+    }
+
+    #[test]
+    fn test_vfo_swap_and_copy() {
+        let mut state = AppState::default();
+        state.sdr.frequency = 162_500_000;
+        state.channels.offset_a = 50_000;
+        state.decoder.filter_width = Some(10_000);
+        state.sync_vfo();
+        let a = VfoConfig {
+            frequency: 162_550_000,
+            mode: DemodMode::FmNarrow,
+            filter_width: Some(10_000),
+        };
+        assert_eq!(state.sdr.vfo[0], a);
+
+        // B starts out where the receiver started
+        let b = state.swap_vfo();
+        assert_eq!(state.sdr.active_vfo, 1);
+        assert_eq!(b.frequency, 144_390_000);
+        state.tuned_to_vfo(b, b.frequency);
+        assert_eq!(state.live_vfo(), b);
+
+        // Tuning moves B only
+        state.decoder.mode = DemodMode::Am;
+        state.decoder.filter_width = None;
+        state.sdr.frequency = 118_100_000;
+        state.sync_vfo();
+        let b = VfoConfig {
+            frequency: 118_100_000,
+            mode: DemodMode::Am,
+            filter_width: None,
+        };
+        assert_eq!(state.sdr.vfo, [a, b]);
+
+        // Swapping back brings A's mode and width back
+        assert_eq!(state.swap_vfo(), a);
+        assert_eq!(state.sdr.active_vfo, 0);
+        state.tuned_to_vfo(a, a.frequency);
+        assert_eq!(state.live_vfo(), a);
+        assert_eq!(state.channels.offset_a, 0);
+        assert_eq!(state.sdr.vfo, [a, b]);
+
+        // Copying with A active leaves chain A alone
+        assert_eq!(state.copy_vfo(), None);
+        assert_eq!(state.sdr.vfo, [a, a]);
+        assert_eq!(state.swap_vfo(), a);
+        // With B active, B takes A's place on the air
+        state.sdr.vfo[0] = b;
+        assert_eq!(state.copy_vfo(), Some(b));
+        assert_eq!(state.sdr.vfo, [b, b]);
+    }
+
+    #[test]
+    fn test_vfo_config_round_trip() {
+        use crate::types::config::set_value;
+        use crate::types::AppConfig;
+
+        let a = VfoConfig {
+            frequency: 162_550_000,
+            mode: DemodMode::FmNarrow,
+            filter_width: None,
+        };
+        let b = VfoConfig {
+            frequency: 118_100_000,
+            mode: DemodMode::Am,
+            filter_width: Some(6_000),
+        };
+        assert_eq!(b.to_toml(), "{ frequency = 118.100000, mode = \"am\", filter_width = 6000 }");
+
+        let text = set_value("", "vfo", "a", &a.to_toml());
+        let text = set_value(&text, "vfo", "b", &b.to_toml());
+        let config = AppConfig::parse(&text).unwrap();
+        assert_eq!(config.vfo.active, "a");
+        assert_eq!(VfoConfig::from_config(config.vfo.a.as_ref().unwrap()), Ok(a));
+        assert_eq!(VfoConfig::from_config(config.vfo.b.as_ref().unwrap()), Ok(b));
+
+        let bad = SavedVfoConfig {
+            frequency: 7.1,
+            mode: "morse".to_string(),
+            filter_width: None,
+        };
+        assert_eq!(VfoConfig::from_config(&bad), Err("unknown mode 'morse'".to_string()));
+    }
+}
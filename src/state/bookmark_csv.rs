@@ -0,0 +1,458 @@
+//! Bookmark lists as CSV, to trade with other SDR software
+//!
+//! The export is a plain CSV file with a header line:
+//!
+//! ```text
+//! frequency_hz,name,mode,bandwidth,tags
+//! 162550000,NOAA 7,nfm,12500,weather;noaa
+//! ```
+//!
+//! The bandwidth in Hz may be left empty, tags are separated by `;`, and a
+//! name holding a comma or a quote is quoted the usual CSV way. The import
+//! reads that back, and GQRX's `bookmarks.csv` too: a `# Tag name` section
+//! of tags and their colours, then a `# Frequency ; Name ; Modulation ;
+//! Bandwidth ; Tags` section with one bookmark a line, separated by `;`.
+//!
+//! Files from elsewhere come in whatever encoding they were saved in: a
+//! byte order mark is dropped, UTF-16 with one is decoded, and anything
+//! else that isn't UTF-8 is read as Latin-1. A mode that isn't known is
+//! taken as narrow FM, with a warning, and bookmarks within
+//! [`PARK_TOLERANCE`](super::bookmarks::PARK_TOLERANCE) of each other are
+//! merged.
+
+use super::bookmarks::{Bookmark, Bookmarks, LevelOverrides};
+use crate::types::DemodMode;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// The export's header line
+pub const HEADER: &str = "frequency_hz,name,mode,bandwidth,tags";
+
+/// What GQRX calls a bookmark without tags
+const GQRX_UNTAGGED: &str = "Untagged";
+
+/// The bookmarks read from a file, and what was wrong with it
+#[derive(Debug, Clone, Default)]
+pub struct Import {
+    /// In frequency order, duplicates merged
+    pub bookmarks: Vec<Bookmark>,
+    /// A line skipped or a mode guessed, e.g. "line 4: unknown mode 'DSD'"
+    pub warnings: Vec<String>,
+}
+
+impl Import {
+    /// Merge the bookmarks into `list`; e.g. "12 bookmarks imported (3
+    /// merged), 1 warning"
+    pub fn merge_into(self, list: &mut Bookmarks) -> String {
+        let count = self.bookmarks.len();
+        let merged = self
+            .bookmarks
+            .into_iter()
+            .filter(|bookmark| !list.merge(bookmark.clone()))
+            .count();
+        let mut summary = format!("{} bookmarks imported", count);
+        if merged > 0 {
+            summary.push_str(&format!(" ({} merged)", merged));
+        }
+        match self.warnings.len() {
+            0 => {}
+            1 => summary.push_str(", 1 warning"),
+            n => summary.push_str(&format!(", {} warnings", n)),
+        }
+        summary
+    }
+}
+
+/// Where GQRX keeps its bookmarks
+pub fn gqrx_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("gqrx").join("bookmarks.csv"))
+}
+
+/// The bookmarks in the file at `path`, in either format
+pub fn read(path: &Path) -> Result<Import> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(parse(&decode(&bytes)))
+}
+
+/// Write `bookmarks` to `path` as CSV
+pub fn write(path: &Path, bookmarks: &[Bookmark]) -> Result<()> {
+    std::fs::write(path, to_csv(bookmarks))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// `bookmarks` as CSV, header first
+pub fn to_csv(bookmarks: &[Bookmark]) -> String {
+    let mut text = format!("{}\n", HEADER);
+    for bookmark in bookmarks {
+        let fields = [
+            bookmark.frequency.to_string(),
+            quote(&bookmark.name),
+            bookmark.mode.file_tag().to_ascii_lowercase(),
+            bookmark
+                .bandwidth
+                .map(|hz| hz.to_string())
+                .unwrap_or_default(),
+            quote(&bookmark.tags.join(";")),
+        ];
+        text.push_str(&fields.join(","));
+        text.push('\n');
+    }
+    text
+}
+
+/// `field` quoted if it has to be to read back the same
+fn quote(field: &str) -> String {
+    if field.contains([',', '"']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The text of a file in whatever encoding it was saved in
+pub fn decode(bytes: &[u8]) -> String {
+    match bytes {
+        [0xef, 0xbb, 0xbf, rest @ ..] => utf8_or_latin1(rest),
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ => utf8_or_latin1(bytes),
+    }
+}
+
+fn utf8_or_latin1(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        // Latin-1 is the first 256 code points
+        Err(_) => bytes.iter().map(|&byte| char::from(byte)).collect(),
+    }
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| unit([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// The bookmarks in `text`, GQRX's format or the export's
+pub fn parse(text: &str) -> Import {
+    // Both of GQRX's section headers are comments with `;` in them
+    let gqrx = text.lines().any(|line| {
+        let line = line.trim_start();
+        line.starts_with('#') && line.contains(';')
+    });
+    let mut list = Bookmarks::default();
+    let mut warnings = Vec::new();
+    let mut in_bookmarks = false;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let entry = if gqrx {
+            if let Some(comment) = line.strip_prefix('#') {
+                let comment = comment.trim_start().to_ascii_lowercase();
+                in_bookmarks = comment.starts_with("frequency");
+                continue;
+            }
+            // Outside the bookmarks, a line is a tag and its colour
+            if !in_bookmarks {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(';').map(str::trim).collect();
+            let tags = fields.get(4).map_or(Vec::new(), |tags| {
+                tags.split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty() && *tag != GQRX_UNTAGGED)
+                    .map(str::to_string)
+                    .collect()
+            });
+            bookmark(&fields, tags, number, &mut warnings)
+        } else {
+            if line.starts_with('#') {
+                continue;
+            }
+            let fields = split_csv(line);
+            if fields[0].trim().eq_ignore_ascii_case("frequency_hz") {
+                continue;
+            }
+            let fields: Vec<&str> = fields.iter().map(|field| field.trim()).collect();
+            let tags = fields.get(4).map_or(Vec::new(), |tags| {
+                tags.split(';')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            });
+            bookmark(&fields, tags, number, &mut warnings)
+        };
+        if let Some(entry) = entry {
+            list.merge(entry);
+        }
+    }
+    Import {
+        bookmarks: list.entries().to_vec(),
+        warnings,
+    }
+}
+
+/// The fields of a CSV line, unquoted
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// The bookmark on line `number`, its `fields` being the frequency in Hz,
+/// name, mode and bandwidth in Hz; None if it can't be read. What was
+/// wrong goes in `warnings`.
+fn bookmark(
+    fields: &[&str],
+    tags: Vec<String>,
+    number: usize,
+    warnings: &mut Vec<String>,
+) -> Option<Bookmark> {
+    let field = |index: usize| fields.get(index).copied().unwrap_or("");
+    let frequency = match field(0).parse::<f64>() {
+        Ok(hz) if hz >= 1.0 && hz <= u32::MAX as f64 => hz.round() as u32,
+        _ => {
+            warnings.push(format!("line {}: bad frequency '{}'", number, field(0)));
+            return None;
+        }
+    };
+    let name = match field(1) {
+        "" => format!("{:.4} MHz", frequency as f64 / 1_000_000.0),
+        name => name.to_string(),
+    };
+    let mode = mode(field(2)).unwrap_or_else(|| {
+        warnings.push(format!(
+            "line {}: unknown mode '{}', taken as {}",
+            number,
+            field(2),
+            DemodMode::FmNarrow.name()
+        ));
+        DemodMode::FmNarrow
+    });
+    let bandwidth = match field(3) {
+        "" => None,
+        width => match width.parse::<f64>() {
+            Ok(hz) if hz >= 1.0 && hz <= u32::MAX as f64 => Some(hz.round() as u32),
+            Ok(_) => None,
+            Err(_) => {
+                warnings.push(format!("line {}: bad bandwidth '{}'", number, width));
+                None
+            }
+        },
+    };
+    Some(Bookmark {
+        frequency,
+        name,
+        mode,
+        bandwidth,
+        tags,
+        overrides: LevelOverrides::default(),
+    })
+}
+
+/// The mode GQRX or the export calls `name`
+fn mode(name: &str) -> Option<DemodMode> {
+    let gqrx = match name.to_ascii_lowercase().as_str() {
+        "narrow fm" => Some(DemodMode::FmNarrow),
+        "wfm (mono)" | "wfm (stereo)" | "wfm (oirt)" => Some(DemodMode::FmWide),
+        "am-sync" => Some(DemodMode::Am),
+        "cw-l" | "cw-u" => Some(DemodMode::Cw),
+        "raw i/q" => Some(DemodMode::Raw),
+        _ => None,
+    };
+    gqrx.or_else(|| name.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// As GQRX 2.15 writes it, padding and all
+    const GQRX: &str = "\
+# Tag name          ;  color
+Untagged            ; #c0c0c0
+Marine              ; #0000ff
+Air, civil          ; #00ff00
+
+# Frequency ; Name                     ; Modulation          ;  Bandwidth; Tags
+     118100000; Tower                    ; AM                  ;       8000; Air
+     144800000; APRS                     ; Narrow FM           ;      10000; Untagged
+     156800000; Ch 16                    ; Narrow FM           ;      12500; Marine
+      98500000; Local FM                 ; WFM (stereo)        ;     160000; Untagged
+      14074000; FT8 20m                  ; USB                 ;       2800; Untagged
+       7030000; CW 40m                   ; CW-U                ;        500; Untagged
+     433920000; Remote                   ; Demod Off           ;     200000; Untagged
+     156800400; Channel 16               ; Narrow FM           ;      12500; Marine,Distress
+";
+
+    fn names(import: &Import) -> Vec<&str> {
+        import.bookmarks.iter().map(|b| b.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_gqrx() {
+        let import = parse(GQRX);
+        assert_eq!(
+            names(&import),
+            [
+                "CW 40m",
+                "FT8 20m",
+                "Local FM",
+                "Tower",
+                "APRS",
+                "Channel 16",
+                "Remote"
+            ]
+        );
+        let modes: Vec<DemodMode> = import.bookmarks.iter().map(|b| b.mode).collect();
+        assert_eq!(
+            modes,
+            [
+                DemodMode::Cw,
+                DemodMode::Usb,
+                DemodMode::FmWide,
+                DemodMode::Am,
+                DemodMode::FmNarrow,
+                DemodMode::FmNarrow,
+                DemodMode::FmNarrow,
+            ]
+        );
+        let tower = &import.bookmarks[3];
+        assert_eq!(tower.frequency, 118_100_000);
+        assert_eq!(tower.bandwidth, Some(8000));
+        assert_eq!(tower.tags, ["Air"]);
+        // Untagged is no tag at all
+        assert!(import.bookmarks[4].tags.is_empty());
+
+        // Channel 16 twice within 1 kHz: one bookmark, the later name and
+        // every tag
+        let ch16 = &import.bookmarks[5];
+        assert_eq!(ch16.frequency, 156_800_000);
+        assert_eq!(ch16.tags, ["Marine", "Distress"]);
+
+        // Demod Off has no counterpart
+        assert_eq!(
+            import.warnings,
+            ["line 13: unknown mode 'Demod Off', taken as FM-NFM"]
+        );
+    }
+
+    #[test]
+    fn test_gqrx_odd_files() {
+        // Saved on Windows in Latin-1 with CRLFs and stray blank lines, the
+        // tag section missing
+        let mut bytes = b"\r\n# Frequency ; Name ; Modulation ; Bandwidth ; Tags\r\n\r\n".to_vec();
+        bytes.extend(b"162550000; M\xe9t\xe9o ; Narrow FM ; 12500 ; \r\n");
+        bytes.extend(b"not a number; Junk ; AM ; 8000 ; \r\n");
+        bytes.extend(b"   \r\n121500000; Guard ; AM\r\n");
+        let import = parse(&decode(&bytes));
+        assert_eq!(names(&import), ["Guard", "Météo"]);
+        assert_eq!(import.bookmarks[0].bandwidth, None);
+        assert_eq!(import.warnings, ["line 5: bad frequency 'not a number'"]);
+
+        // UTF-8 with a byte order mark, and UTF-16 as Windows Notepad saves
+        let text = "# Frequency ; Name ; Modulation ; Bandwidth ; Tags\n\
+                    162550000 ; Météo ; Narrow FM ; 12500 ; Untagged\n";
+        let mut utf8 = b"\xef\xbb\xbf".to_vec();
+        utf8.extend(text.as_bytes());
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        for bytes in [utf8, utf16] {
+            let import = parse(&decode(&bytes));
+            assert_eq!(names(&import), ["Météo"]);
+            assert!(import.warnings.is_empty(), "{:?}", import.warnings);
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        let text = "\u{feff}frequency_hz,name,mode,bandwidth,tags\r\n\
+                    \r\n\
+                    162550000,NOAA 7,nfm,12500,weather;noaa\r\n\
+                    146940000,\"Repeater, \"\"main\"\"\",FM-NFM,,\r\n\
+                    131550000,ACARS,acars\r\n\
+                    7030000,CW,morse,500,\r\n\
+                    \r\n";
+        let import = parse(&decode(text.as_bytes()));
+        assert_eq!(
+            names(&import),
+            ["CW", "ACARS", "Repeater, \"main\"", "NOAA 7"]
+        );
+        assert_eq!(import.bookmarks[0].mode, DemodMode::FmNarrow);
+        assert_eq!(import.bookmarks[1].mode, DemodMode::Acars);
+        assert_eq!(import.bookmarks[2].bandwidth, None);
+        assert_eq!(import.bookmarks[3].tags, ["weather", "noaa"]);
+        assert_eq!(
+            import.warnings,
+            ["line 6: unknown mode 'morse', taken as FM-NFM"]
+        );
+
+        // What is exported reads back the same
+        let exported = to_csv(&import.bookmarks);
+        assert!(
+            exported.starts_with("frequency_hz,name,mode,bandwidth,tags\n7030000,CW,nfm,500,\n")
+        );
+        assert!(exported.contains("146940000,\"Repeater, \"\"main\"\"\",nfm,,\n"));
+        let again = parse(&exported);
+        assert_eq!(again.bookmarks, import.bookmarks);
+        assert!(again.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_merge_into() {
+        let mut list = Bookmarks::default();
+        list.add(Bookmark {
+            frequency: 162_550_000,
+            name: "Weather".to_string(),
+            mode: DemodMode::Am,
+            bandwidth: None,
+            tags: vec!["wx".to_string()],
+            overrides: LevelOverrides {
+                squelch: Some(-45.0),
+                gain: None,
+            },
+        });
+        let import = parse("162550500,NOAA 7,nfm,12500,noaa\n144390000,APRS,aprs,,\n");
+        assert_eq!(
+            import.merge_into(&mut list),
+            "2 bookmarks imported (1 merged)"
+        );
+        let entries = list.entries();
+        assert_eq!(entries.len(), 2);
+        // The imported name, mode and width, and the levels already there
+        let noaa = &entries[1];
+        assert_eq!(noaa.frequency, 162_550_000);
+        assert_eq!(noaa.name, "NOAA 7");
+        assert_eq!(noaa.mode, DemodMode::FmNarrow);
+        assert_eq!(noaa.bandwidth, Some(12_500));
+        assert_eq!(noaa.tags, ["wx", "noaa"]);
+        assert_eq!(noaa.overrides.squelch, Some(-45.0));
+
+        let import = parse("x,Bad,nfm,,\n");
+        assert_eq!(
+            import.merge_into(&mut list),
+            "0 bookmarks imported, 1 warning"
+        );
+    }
+}
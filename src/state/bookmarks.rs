@@ -0,0 +1,599 @@
+//! Bookmarked channels and the squelch and gain each one wants
+//!
+//! A bookmark is a frequency and mode with a name, and optionally the
+//! squelch level and tuner gain that suit that channel: a strong local
+//! repeater wants a higher squelch than a weak weather broadcast. Once the
+//! receiver is on a recalled bookmark its levels are applied, and tuning
+//! away puts back the ones from before. A level changed by hand while
+//! parked there wins: it is kept on leaving, or, with `write_back`, saved to
+//! the bookmark and the earlier level put back.
+
+use crate::types::config::BookmarkConfig;
+use crate::types::DemodMode;
+
+/// How far chain A can be from a bookmark and still be on it, in Hz
+pub const PARK_TOLERANCE: u32 = 1_000;
+
+/// Squelch and tuner gain as set on the receiver
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Levels {
+    /// Threshold in dBFS, None for squelch off
+    pub squelch: Option<f32>,
+    /// Gain in tenths of dB, None for auto gain
+    pub gain: Option<i32>,
+}
+
+impl Levels {
+    /// e.g. "squelch -45 dBFS, auto gain"
+    pub fn summary(&self) -> String {
+        let squelch = match self.squelch {
+            Some(level) => format!("squelch {:.0} dBFS", level),
+            None => "squelch off".to_string(),
+        };
+        match self.gain {
+            Some(gain) => format!("{}, gain {}.{} dB", squelch, gain / 10, gain % 10),
+            None => format!("{}, auto gain", squelch),
+        }
+    }
+}
+
+/// Squelch and gain a bookmark sets, None where it leaves the global one
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LevelOverrides {
+    /// Threshold in dBFS
+    pub squelch: Option<f32>,
+    /// Gain in tenths of dB
+    pub gain: Option<i32>,
+}
+
+impl LevelOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.squelch.is_none() && self.gain.is_none()
+    }
+
+    /// e.g. "squelch -45 dBFS, gain 40.2 dB"; empty with no overrides
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(level) = self.squelch {
+            parts.push(format!("squelch {:.0} dBFS", level));
+        }
+        if let Some(gain) = self.gain {
+            parts.push(format!("gain {}.{} dB", gain / 10, gain % 10));
+        }
+        parts.join(", ")
+    }
+}
+
+/// A named channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    /// Hz
+    pub frequency: u32,
+    pub name: String,
+    pub mode: DemodMode,
+    /// Filter width in Hz, None for the mode's own
+    pub bandwidth: Option<u32>,
+    pub tags: Vec<String>,
+    pub overrides: LevelOverrides,
+}
+
+/// Bookmarks in frequency order, at most one per [`PARK_TOLERANCE`]
+#[derive(Debug, Clone, Default)]
+pub struct Bookmarks {
+    entries: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    /// The bookmarks saved in the config file, leaving out entries that
+    /// don't parse
+    pub fn from_config(list: &[BookmarkConfig]) -> Self {
+        let mut bookmarks = Self::default();
+        for entry in list {
+            let mode = match entry.mode.parse() {
+                Ok(mode) => mode,
+                Err(e) => {
+                    log::warn!("Bookmark '{}': {}", entry.name, e);
+                    continue;
+                }
+            };
+            if !(entry.frequency > 0.0 && entry.frequency < 100_000.0) {
+                log::warn!(
+                    "Bookmark '{}': bad frequency {}",
+                    entry.name,
+                    entry.frequency
+                );
+                continue;
+            }
+            bookmarks.add(Bookmark {
+                frequency: (entry.frequency * 1e6).round() as u32,
+                name: entry.name.clone(),
+                mode,
+                bandwidth: entry.bandwidth,
+                tags: entry.tags.clone(),
+                overrides: LevelOverrides {
+                    squelch: entry.squelch,
+                    gain: entry.gain.map(|db| (db * 10.0).round() as i32),
+                },
+            });
+        }
+        bookmarks
+    }
+
+    /// Add `bookmark`, replacing any already at its frequency
+    pub fn add(&mut self, bookmark: Bookmark) {
+        self.entries
+            .retain(|b| b.frequency.abs_diff(bookmark.frequency) > PARK_TOLERANCE);
+        let at = self
+            .entries
+            .partition_point(|b| b.frequency < bookmark.frequency);
+        self.entries.insert(at, bookmark);
+    }
+
+    /// Take in `bookmark` from another list; whether it was new
+    ///
+    /// One already at its frequency takes its name, mode and bandwidth and
+    /// gains its tags, and keeps its own levels where it has them.
+    pub fn merge(&mut self, bookmark: Bookmark) -> bool {
+        let Some(existing) = self.at_mut(bookmark.frequency) else {
+            self.add(bookmark);
+            return true;
+        };
+        if !bookmark.name.is_empty() {
+            existing.name = bookmark.name;
+        }
+        existing.mode = bookmark.mode;
+        existing.bandwidth = bookmark.bandwidth.or(existing.bandwidth);
+        for tag in bookmark.tags {
+            if !existing.tags.contains(&tag) {
+                existing.tags.push(tag);
+            }
+        }
+        let overrides = &mut existing.overrides;
+        overrides.squelch = overrides.squelch.or(bookmark.overrides.squelch);
+        overrides.gain = overrides.gain.or(bookmark.overrides.gain);
+        false
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Bookmark> {
+        (index < self.entries.len()).then(|| self.entries.remove(index))
+    }
+
+    /// The bookmark at `frequency`, if any
+    pub fn at_mut(&mut self, frequency: u32) -> Option<&mut Bookmark> {
+        self.entries
+            .iter_mut()
+            .find(|b| b.frequency.abs_diff(frequency) <= PARK_TOLERANCE)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Bookmark> {
+        self.entries.get_mut(index)
+    }
+
+    /// In frequency order
+    pub fn entries(&self) -> &[Bookmark] {
+        &self.entries
+    }
+
+    /// The bookmarks as a TOML array for the config file
+    pub fn to_toml(&self) -> String {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|bookmark| {
+                let mut fields = vec![
+                    format!("frequency = {:.6}", bookmark.frequency as f64 / 1e6),
+                    format!("name = {}", toml::Value::String(bookmark.name.clone())),
+                    format!(
+                        "mode = \"{}\"",
+                        bookmark.mode.file_tag().to_ascii_lowercase()
+                    ),
+                ];
+                if let Some(width) = bookmark.bandwidth {
+                    fields.push(format!("bandwidth = {}", width));
+                }
+                if !bookmark.tags.is_empty() {
+                    let tags: Vec<String> = bookmark
+                        .tags
+                        .iter()
+                        .map(|tag| toml::Value::String(tag.clone()).to_string())
+                        .collect();
+                    fields.push(format!("tags = [{}]", tags.join(", ")));
+                }
+                if let Some(level) = bookmark.overrides.squelch {
+                    fields.push(format!("squelch = {:.1}", level));
+                }
+                if let Some(gain) = bookmark.overrides.gain {
+                    fields.push(format!("gain = {:.1}", gain as f32 / 10.0));
+                }
+                format!("{{ {} }}", fields.join(", "))
+            })
+            .collect();
+        format!("[{}]", entries.join(", "))
+    }
+}
+
+/// Levels set by hand on a bookmark, to save to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WriteBack {
+    /// The bookmark's frequency in Hz
+    pub frequency: u32,
+    pub overrides: LevelOverrides,
+}
+
+/// A change to the receiver's levels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LevelChange {
+    /// Arrived on the bookmark: set these
+    Apply(Levels),
+    /// Left the bookmark: set these, and save what was set by hand there
+    /// to it if there is anything
+    Restore {
+        levels: Levels,
+        write_back: Option<WriteBack>,
+    },
+}
+
+/// Where the bracketing of a bookmark's levels stands
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Stage {
+    #[default]
+    Idle,
+    /// Recalled; applied once chain A reaches `frequency`. `saved` carries
+    /// the levels from before an earlier bookmark's took over
+    Arriving {
+        frequency: u32,
+        overrides: LevelOverrides,
+        saved: Option<Levels>,
+    },
+    /// On the bookmark with `applied` set, `saved` to go back to
+    Parked {
+        frequency: u32,
+        saved: Levels,
+        applied: Levels,
+    },
+}
+
+/// Applies a recalled bookmark's levels and puts the earlier ones back on
+/// leaving it
+///
+/// Only one set of earlier levels is kept: going from one bookmark straight
+/// to another puts back the levels from before the first on leaving the
+/// second. A level found changed on leaving was set by hand; it stays, or
+/// with `write_back` is saved to the bookmark and the earlier one put back.
+/// Squelch off and auto gain can't be saved to a bookmark, so those are
+/// simply undone then.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LevelOverride {
+    stage: Stage,
+    write_back: bool,
+}
+
+impl LevelOverride {
+    pub fn new(write_back: bool) -> Self {
+        Self {
+            stage: Stage::Idle,
+            write_back,
+        }
+    }
+
+    /// A bookmark at `frequency` setting `overrides` was recalled, with the
+    /// receiver's levels `current`; what to save to the bookmark left for it
+    pub fn recall(
+        &mut self,
+        frequency: u32,
+        overrides: LevelOverrides,
+        current: Levels,
+    ) -> Option<WriteBack> {
+        let (saved, write_back) = match self.stage {
+            Stage::Idle => (None, None),
+            Stage::Arriving { saved, .. } => (saved, None),
+            Stage::Parked {
+                frequency,
+                saved,
+                applied,
+            } => {
+                let (levels, write_back) = self.leave(frequency, saved, applied, current);
+                (Some(levels), write_back)
+            }
+        };
+        self.stage = Stage::Arriving {
+            frequency,
+            overrides,
+            saved,
+        };
+        write_back
+    }
+
+    /// The frequency of the bookmark whose levels are applied
+    pub fn parked_on(&self) -> Option<u32> {
+        match self.stage {
+            Stage::Parked { frequency, .. } => Some(frequency),
+            _ => None,
+        }
+    }
+
+    /// Chain A is at `tuned` and the receiver's levels are `current`: the
+    /// change due, if any
+    pub fn update(&mut self, tuned: u32, current: Levels) -> Option<LevelChange> {
+        match self.stage {
+            Stage::Idle => None,
+            Stage::Arriving {
+                frequency,
+                overrides,
+                saved,
+            } => {
+                if tuned.abs_diff(frequency) > PARK_TOLERANCE {
+                    return None;
+                }
+                let saved = saved.unwrap_or(current);
+                let applied = Levels {
+                    squelch: overrides.squelch.or(saved.squelch),
+                    gain: overrides.gain.or(saved.gain),
+                };
+                if overrides.is_empty() && applied == current {
+                    self.stage = Stage::Idle;
+                    return None;
+                }
+                self.stage = Stage::Parked {
+                    frequency,
+                    saved,
+                    applied,
+                };
+                (applied != current).then_some(LevelChange::Apply(applied))
+            }
+            Stage::Parked {
+                frequency,
+                saved,
+                applied,
+            } => {
+                if tuned.abs_diff(frequency) <= PARK_TOLERANCE {
+                    return None;
+                }
+                self.stage = Stage::Idle;
+                let (levels, write_back) = self.leave(frequency, saved, applied, current);
+                Some(LevelChange::Restore { levels, write_back })
+            }
+        }
+    }
+
+    /// The levels to leave the bookmark at `frequency` with, and what was
+    /// set by hand there to save to it
+    fn leave(
+        &self,
+        frequency: u32,
+        saved: Levels,
+        applied: Levels,
+        current: Levels,
+    ) -> (Levels, Option<WriteBack>) {
+        let mut levels = saved;
+        let mut overrides = LevelOverrides::default();
+        if current.squelch != applied.squelch {
+            match self.write_back {
+                true => overrides.squelch = current.squelch,
+                false => levels.squelch = current.squelch,
+            }
+        }
+        if current.gain != applied.gain {
+            match self.write_back {
+                true => overrides.gain = current.gain,
+                false => levels.gain = current.gain,
+            }
+        }
+        let write_back = (!overrides.is_empty()).then_some(WriteBack {
+            frequency,
+            overrides,
+        });
+        (levels, write_back)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AppConfig;
+
+    const REPEATER: u32 = 146_940_000;
+    const NOAA: u32 = 162_550_000;
+
+    fn levels(squelch: Option<f32>, gain: Option<i32>) -> Levels {
+        Levels { squelch, gain }
+    }
+
+    fn overrides(squelch: Option<f32>, gain: Option<i32>) -> LevelOverrides {
+        LevelOverrides { squelch, gain }
+    }
+
+    fn restore(levels: Levels) -> Option<LevelChange> {
+        Some(LevelChange::Restore {
+            levels,
+            write_back: None,
+        })
+    }
+
+    #[test]
+    fn test_apply_and_restore() {
+        let globals = levels(Some(-60.0), None);
+        let mut parking = LevelOverride::default();
+        assert_eq!(parking.update(REPEATER, globals), None);
+
+        parking.recall(REPEATER, overrides(Some(-30.0), Some(200)), globals);
+        // Nothing until the receiver gets there
+        assert_eq!(parking.update(144_390_000, globals), None);
+        let applied = levels(Some(-30.0), Some(200));
+        assert_eq!(
+            parking.update(REPEATER + 200, globals),
+            Some(LevelChange::Apply(applied))
+        );
+        assert_eq!(parking.parked_on(), Some(REPEATER));
+        assert_eq!(parking.update(REPEATER, applied), None);
+
+        // Tuning away puts the globals back, once
+        assert_eq!(parking.update(REPEATER + 12_500, applied), restore(globals));
+        assert_eq!(parking.parked_on(), None);
+        assert_eq!(parking.update(REPEATER, globals), None);
+    }
+
+    #[test]
+    fn test_partial_overrides() {
+        // Only the squelch is overridden; the gain stays global
+        let globals = levels(Some(-60.0), Some(300));
+        let mut parking = LevelOverride::default();
+        parking.recall(NOAA, overrides(Some(-45.0), None), globals);
+        assert_eq!(
+            parking.update(NOAA, globals),
+            Some(LevelChange::Apply(levels(Some(-45.0), Some(300))))
+        );
+
+        // A bookmark without overrides changes nothing at all
+        let mut parking = LevelOverride::default();
+        parking.recall(NOAA, LevelOverrides::default(), globals);
+        assert_eq!(parking.update(NOAA, globals), None);
+        assert_eq!(parking.parked_on(), None);
+        assert_eq!(parking.update(REPEATER, globals), None);
+    }
+
+    #[test]
+    fn test_manual_change_wins() {
+        let globals = levels(Some(-60.0), None);
+        let mut parking = LevelOverride::default();
+        parking.recall(REPEATER, overrides(Some(-30.0), Some(200)), globals);
+        parking.update(REPEATER, globals);
+
+        // The squelch is raised by hand while parked: it stays on leaving,
+        // and the gain goes back to auto
+        let current = levels(Some(-25.0), Some(200));
+        assert_eq!(
+            parking.update(NOAA, current),
+            restore(levels(Some(-25.0), None))
+        );
+    }
+
+    #[test]
+    fn test_write_back() {
+        let globals = levels(Some(-60.0), None);
+        let mut parking = LevelOverride::new(true);
+        parking.recall(REPEATER, overrides(Some(-30.0), None), globals);
+        parking.update(REPEATER, globals);
+
+        // Squelch raised and a gain set by hand: both are saved to the
+        // bookmark and the globals put back
+        let current = levels(Some(-25.0), Some(150));
+        assert_eq!(
+            parking.update(NOAA, current),
+            Some(LevelChange::Restore {
+                levels: globals,
+                write_back: Some(WriteBack {
+                    frequency: REPEATER,
+                    overrides: overrides(Some(-25.0), Some(150)),
+                }),
+            })
+        );
+
+        // Squelch turned off can't be saved; it is just undone
+        parking.recall(REPEATER, overrides(Some(-30.0), None), globals);
+        parking.update(REPEATER, globals);
+        let current = levels(None, None);
+        assert_eq!(parking.update(NOAA, current), restore(globals));
+    }
+
+    #[test]
+    fn test_bookmark_to_bookmark() {
+        let globals = levels(None, None);
+        let mut parking = LevelOverride::default();
+        parking.recall(REPEATER, overrides(Some(-30.0), Some(200)), globals);
+        let first = levels(Some(-30.0), Some(200));
+        assert_eq!(
+            parking.update(REPEATER, globals),
+            Some(LevelChange::Apply(first))
+        );
+
+        // Straight on to a bookmark that only sets a squelch: the gain
+        // goes back to the global one rather than staying at the first's
+        assert_eq!(
+            parking.recall(NOAA, overrides(Some(-45.0), None), first),
+            None
+        );
+        assert_eq!(parking.update(REPEATER, first), None);
+        let second = levels(Some(-45.0), None);
+        assert_eq!(
+            parking.update(NOAA, first),
+            Some(LevelChange::Apply(second))
+        );
+
+        // And leaving that puts back the levels from before the first
+        assert_eq!(parking.update(144_390_000, second), restore(globals));
+
+        // Written back when leaving one bookmark for another too
+        let mut parking = LevelOverride::new(true);
+        parking.recall(REPEATER, overrides(Some(-30.0), None), globals);
+        parking.update(REPEATER, globals);
+        assert_eq!(
+            parking.recall(NOAA, LevelOverrides::default(), levels(Some(-35.0), None)),
+            Some(WriteBack {
+                frequency: REPEATER,
+                overrides: overrides(Some(-35.0), None),
+            })
+        );
+    }
+
+    #[test]
+    fn test_bookmark_list() {
+        let config = AppConfig::parse(
+            r#"
+            [[bookmarks.list]]
+            frequency = 162.55
+            name = "NOAA 7"
+            mode = "nfm"
+            squelch = -45.0
+
+            [[bookmarks.list]]
+            frequency = 146.94
+            name = "Repeater"
+            mode = "nfm"
+            gain = 20.7
+            bandwidth = 12500
+            tags = ["ham", "local"]
+
+            [[bookmarks.list]]
+            frequency = 120.0
+            name = "Bad"
+            mode = "fm stereo"
+            "#,
+        )
+        .unwrap();
+        let mut bookmarks = Bookmarks::from_config(&config.bookmarks.list);
+        let names: Vec<&str> = bookmarks
+            .entries()
+            .iter()
+            .map(|b| b.name.as_str())
+            .collect();
+        assert_eq!(names, ["Repeater", "NOAA 7"]);
+        assert_eq!(bookmarks.entries()[0].overrides, overrides(None, Some(207)));
+        assert_eq!(
+            bookmarks.entries()[1].overrides.summary(),
+            "squelch -45 dBFS"
+        );
+
+        // One per frequency: a new one close by replaces the old
+        bookmarks.add(Bookmark {
+            frequency: NOAA + 500,
+            name: "Weather \"7\"".to_string(),
+            mode: DemodMode::FmNarrow,
+            bandwidth: None,
+            tags: vec!["noaa".to_string()],
+            overrides: LevelOverrides::default(),
+        });
+        assert_eq!(bookmarks.entries().len(), 2);
+        assert_eq!(bookmarks.at_mut(NOAA).unwrap().frequency, NOAA + 500);
+
+        // What is saved reads back the same
+        let text = format!("[bookmarks]\nlist = {}\n", bookmarks.to_toml());
+        let saved = AppConfig::parse(&text).unwrap();
+        assert_eq!(
+            Bookmarks::from_config(&saved.bookmarks.list).entries(),
+            bookmarks.entries()
+        );
+        assert_eq!(bookmarks.remove(0).unwrap().name, "Repeater");
+        assert_eq!(bookmarks.remove(5), None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Calibrating levels from dBFS to approximate dBm
+//!
+//! Spectrum and channel levels are relative to the ADC's full scale. Fed a
+//! signal of known power, the difference to dBm is measured once per tuner
+//! gain; gains between the ones measured are interpolated, and a single
+//! offset covers auto gain or a receiver with no table. Levels are shown
+//! as "dBm (cal)" only where an offset applies, dBFS otherwise.
+
+use crate::types::config::CalibrationConfig;
+use std::collections::BTreeMap;
+
+/// dB to add to dBFS for dBm, by tuner gain
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Calibration {
+    /// For auto gain, or gains with no table
+    fallback: Option<f32>,
+    /// By manual gain in tenths of dB
+    by_gain: BTreeMap<i32, f32>,
+}
+
+impl Calibration {
+    /// Offsets from the config file, gains written in dB
+    pub fn from_config(config: &CalibrationConfig) -> Self {
+        let mut by_gain = BTreeMap::new();
+        for (gain, &offset) in &config.gains {
+            match gain.trim().parse::<f32>() {
+                Ok(db) => {
+                    by_gain.insert((db * 10.0).round() as i32, offset);
+                }
+                Err(_) => log::warn!("Calibration: '{}' is not a gain in dB", gain),
+            }
+        }
+        Self {
+            fallback: config.offset_db,
+            by_gain,
+        }
+    }
+
+    /// The offset at `gain` in tenths of dB (None for auto gain):
+    /// interpolated between the gains measured, the nearest beyond them
+    pub fn offset(&self, gain: Option<i32>) -> Option<f32> {
+        let Some(gain) = gain else {
+            return self.fallback;
+        };
+        let below = self.by_gain.range(..=gain).next_back();
+        let above = self.by_gain.range(gain..).next();
+        match (below, above) {
+            (Some((&low, &a)), Some((&high, &b))) if high > low => {
+                let t = (gain - low) as f32 / (high - low) as f32;
+                Some(a + (b - a) * t)
+            }
+            (Some((_, &offset)), _) | (None, Some((_, &offset))) => Some(offset),
+            (None, None) => self.fallback,
+        }
+    }
+
+    /// Remember `offset` for `gain` (None for auto gain); the config file
+    /// section, key and value to save it under
+    pub fn set(&mut self, gain: Option<i32>, offset: f32) -> (&'static str, String, String) {
+        let value = format!("{:.1}", offset);
+        match gain {
+            Some(gain) => {
+                self.by_gain.insert(gain, offset);
+                let key = format!("\"{}.{}\"", gain / 10, gain % 10);
+                ("calibration.gains", key, value)
+            }
+            None => {
+                self.fallback = Some(offset);
+                ("calibration", "offset_db".to_string(), value)
+            }
+        }
+    }
+}
+
+/// How levels are shown: dBFS, or dBm with a calibration offset
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LevelScale {
+    offset: Option<f32>,
+}
+
+impl LevelScale {
+    pub fn new(offset: Option<f32>) -> Self {
+        Self { offset }
+    }
+
+    /// `dbfs` on this scale
+    pub fn apply(self, dbfs: f32) -> f32 {
+        dbfs + self.offset.unwrap_or(0.0)
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self.offset {
+            Some(_) => "dBm (cal)",
+            None => "dBFS",
+        }
+    }
+
+    /// `dbfs` on this scale with its unit, to `precision` decimals
+    pub fn format(self, dbfs: f32, precision: usize) -> String {
+        format!("{:.*} {}", precision, self.apply(dbfs), self.unit())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Calibration {
+        let config: CalibrationConfig =
+            toml::from_str("offset_db = -30.0\n[gains]\n\"20.7\" = -20.0\n\"40.2\" = -40.0\n")
+                .unwrap();
+        Calibration::from_config(&config)
+    }
+
+    #[test]
+    fn test_offset_lookup() {
+        let calibration = table();
+        // On a measured gain, between two, and beyond either end
+        assert_eq!(calibration.offset(Some(207)), Some(-20.0));
+        assert_eq!(calibration.offset(Some(402)), Some(-40.0));
+        let halfway = calibration.offset(Some(305)).unwrap();
+        assert!((halfway + 30.05).abs() < 0.01, "{}", halfway);
+        assert_eq!(calibration.offset(Some(0)), Some(-20.0));
+        assert_eq!(calibration.offset(Some(496)), Some(-40.0));
+        // Auto gain takes the single offset
+        assert_eq!(calibration.offset(None), Some(-30.0));
+
+        // With no table the single offset covers every gain, and with
+        // nothing at all there is no calibration
+        let single = Calibration {
+            fallback: Some(-25.0),
+            ..Default::default()
+        };
+        assert_eq!(single.offset(Some(297)), Some(-25.0));
+        assert_eq!(Calibration::default().offset(Some(297)), None);
+        assert_eq!(Calibration::default().offset(None), None);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut calibration = Calibration::default();
+        assert_eq!(
+            calibration.set(Some(297), -31.25),
+            (
+                "calibration.gains",
+                "\"29.7\"".to_string(),
+                "-31.2".to_string()
+            )
+        );
+        assert_eq!(calibration.offset(Some(297)), Some(-31.25));
+        // Auto gain has no table entry: the single offset
+        assert_eq!(calibration.offset(None), None);
+        assert_eq!(
+            calibration.set(None, -28.0),
+            ("calibration", "offset_db".to_string(), "-28.0".to_string())
+        );
+        assert_eq!(calibration.offset(None), Some(-28.0));
+
+        // What is saved reads back the same
+        let text = "offset_db = -28.0\n[gains]\n\"29.7\" = -31.25\n";
+        let config: CalibrationConfig = toml::from_str(text).unwrap();
+        assert_eq!(Calibration::from_config(&config), calibration);
+    }
+
+    #[test]
+    fn test_level_scale() {
+        let uncalibrated = LevelScale::default();
+        assert_eq!(uncalibrated.format(-42.0, 0), "-42 dBFS");
+        let calibrated = LevelScale::new(Some(-30.5));
+        assert_eq!(calibrated.apply(-42.0), -72.5);
+        assert_eq!(calibrated.format(-42.0, 1), "-72.5 dBm (cal)");
+    }
+}
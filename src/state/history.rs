@@ -0,0 +1,299 @@
+//! Recently tuned frequencies
+//!
+//! Every manual retune moves the frequency left and the one arrived at to
+//! the front, so the second entry is always the one to flip back to.
+//!
+//! Stepping with the arrow keys passes through frequencies nobody means to
+//! keep, so a step only marks where the steps are heading. That goes into
+//! the history once the steps have stopped for [`SETTLE`], and the
+//! frequencies passed on the way never do.
+
+use crate::types::config::RecentConfig;
+use crate::types::DemodMode;
+use std::time::{Duration, Instant};
+
+/// Distinct frequencies remembered
+pub const HISTORY_LEN: usize = 10;
+/// How long after the last step the frequency arrived at is remembered
+pub const SETTLE: Duration = Duration::from_secs(2);
+
+/// A frequency chain A was tuned to and the mode it was heard in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tuned {
+    /// Hz
+    pub frequency: u32,
+    pub mode: DemodMode,
+}
+
+/// Most recently used frequencies, newest first and each only once
+#[derive(Debug, Clone, Default)]
+pub struct FrequencyHistory {
+    entries: Vec<Tuned>,
+    /// Where stepping has got to and when, until it settles
+    stepping: Option<(Tuned, Instant)>,
+}
+
+impl FrequencyHistory {
+    /// A history holding `entries`, newest first
+    pub fn new(entries: impl IntoIterator<Item = Tuned>) -> Self {
+        let entries: Vec<Tuned> = entries.into_iter().collect();
+        let mut history = Self::default();
+        for &entry in entries.iter().rev() {
+            history.record(entry);
+        }
+        history
+    }
+
+    /// The history saved in the config file, leaving out entries that
+    /// don't parse
+    pub fn from_config(recent: &[RecentConfig]) -> Self {
+        Self::new(recent.iter().filter_map(|entry| match entry.mode.parse() {
+            Ok(mode) if entry.frequency > 0.0 => Some(Tuned {
+                frequency: (entry.frequency * 1e6).round() as u32,
+                mode,
+            }),
+            Ok(_) => {
+                log::warn!("History: bad frequency {}", entry.frequency);
+                None
+            }
+            Err(e) => {
+                log::warn!("History: {}", e);
+                None
+            }
+        }))
+    }
+
+    /// Move `entry` to the front, dropping the oldest beyond
+    /// [`HISTORY_LEN`]; returns whether anything changed
+    pub fn record(&mut self, entry: Tuned) -> bool {
+        if self.entries.first() == Some(&entry) {
+            return false;
+        }
+        self.entries.retain(|e| e.frequency != entry.frequency);
+        self.entries.insert(0, entry);
+        self.entries.truncate(HISTORY_LEN);
+        true
+    }
+
+    /// Remember a retune from `from` to `to`; returns whether anything
+    /// changed
+    pub fn retune(&mut self, from: Tuned, to: Tuned) -> bool {
+        self.stepping = None;
+        let left = self.record(from);
+        self.record(to) || left
+    }
+
+    /// Remember a step from `from` to `to` at `now`: `from` if it's where
+    /// the steps began, and `to` once they settle; returns whether anything
+    /// changed
+    pub fn step(&mut self, from: Tuned, to: Tuned, now: Instant) -> bool {
+        let left = self.stepping.is_none() && self.record(from);
+        self.stepping = Some((to, now));
+        left
+    }
+
+    /// Remember where stepping stopped, if it has been still for
+    /// [`SETTLE`] by `now`; returns whether anything changed
+    pub fn settle(&mut self, now: Instant) -> bool {
+        match self.stepping {
+            Some((to, at)) if now.saturating_duration_since(at) >= SETTLE => {
+                self.stepping = None;
+                self.record(to)
+            }
+            _ => false,
+        }
+    }
+
+    /// Remember where stepping stopped straight away, as when the history
+    /// is about to be used; returns whether anything changed
+    pub fn settle_now(&mut self) -> bool {
+        match self.stepping.take() {
+            Some((to, _)) => self.record(to),
+            None => false,
+        }
+    }
+
+    /// The frequency before the current one
+    pub fn previous(&self) -> Option<Tuned> {
+        let current = self.stepping.map(|(to, _)| to).or(self.entries.first().copied());
+        self.entries
+            .iter()
+            .find(|entry| Some(entry.frequency) != current.map(|c| c.frequency))
+            .copied()
+    }
+
+    /// Newest first
+    pub fn entries(&self) -> &[Tuned] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The history as a TOML array for the config file
+    pub fn to_toml(&self) -> String {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{{ frequency = {:.6}, mode = \"{}\" }}",
+                    entry.frequency as f64 / 1e6,
+                    entry.mode.file_tag().to_ascii_lowercase()
+                )
+            })
+            .collect();
+        format!("[{}]", entries.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuned(frequency: u32) -> Tuned {
+        Tuned {
+            frequency,
+            mode: DemodMode::FmNarrow,
+        }
+    }
+
+    fn frequencies(history: &FrequencyHistory) -> Vec<u32> {
+        history.entries().iter().map(|e| e.frequency).collect()
+    }
+
+    #[test]
+    fn test_record_dedupes_and_orders() {
+        let mut history = FrequencyHistory::default();
+        assert!(history.is_empty());
+        assert!(history.record(tuned(1)));
+        assert!(history.record(tuned(2)));
+        assert!(history.record(tuned(3)));
+        assert_eq!(frequencies(&history), [3, 2, 1]);
+
+        // Coming back moves it to the front rather than adding it twice
+        assert!(history.record(tuned(1)));
+        assert_eq!(frequencies(&history), [1, 3, 2]);
+        assert!(!history.record(tuned(1)));
+
+        // The newest mode wins
+        let am = Tuned {
+            frequency: 3,
+            mode: DemodMode::Am,
+        };
+        assert!(history.record(am));
+        assert_eq!(history.entries()[0], am);
+        assert_eq!(frequencies(&history), [3, 1, 2]);
+    }
+
+    #[test]
+    fn test_record_caps_length() {
+        let mut history = FrequencyHistory::default();
+        for frequency in 0..HISTORY_LEN as u32 + 5 {
+            history.record(tuned(frequency));
+        }
+        assert_eq!(history.entries().len(), HISTORY_LEN);
+        assert_eq!(history.entries()[0].frequency, HISTORY_LEN as u32 + 4);
+        // The oldest went first
+        assert_eq!(history.entries()[HISTORY_LEN - 1].frequency, 5);
+    }
+
+    #[test]
+    fn test_retune_flips_back() {
+        let mut history = FrequencyHistory::default();
+        assert_eq!(history.previous(), None);
+
+        // The frequency left is remembered even if it never was before
+        assert!(history.retune(tuned(100), tuned(200)));
+        assert_eq!(history.previous(), Some(tuned(100)));
+
+        // Flipping swaps the two, and again
+        assert!(history.retune(tuned(200), tuned(100)));
+        assert_eq!(frequencies(&history), [100, 200]);
+        assert!(history.retune(tuned(100), tuned(200)));
+        assert_eq!(frequencies(&history), [200, 100]);
+
+        assert!(history.retune(tuned(200), tuned(300)));
+        assert_eq!(frequencies(&history), [300, 200, 100]);
+        // Retuning to where it already is changes nothing
+        assert!(!history.retune(tuned(300), tuned(300)));
+    }
+
+    #[test]
+    fn test_steps_settle() {
+        let start = Instant::now();
+        let mut history = FrequencyHistory::new([tuned(50)]);
+
+        // Stepping through 200 and 300 to 400 keeps only where it began
+        assert!(history.step(tuned(100), tuned(200), start));
+        assert!(!history.step(tuned(200), tuned(300), start));
+        assert!(!history.step(tuned(300), tuned(400), start + SETTLE / 2));
+        assert_eq!(frequencies(&history), [100, 50]);
+        // Flipping back goes to where the steps began
+        assert_eq!(history.previous(), Some(tuned(100)));
+
+        // Then where it stopped, once it has been there a while
+        assert!(!history.settle(start + SETTLE));
+        assert!(history.settle(start + SETTLE / 2 + SETTLE));
+        assert_eq!(frequencies(&history), [400, 100, 50]);
+        assert!(!history.settle(start + SETTLE * 10));
+
+        // A jump before the steps settle remembers the frequency left
+        assert!(!history.step(tuned(400), tuned(500), start));
+        assert!(history.retune(tuned(500), tuned(600)));
+        assert_eq!(frequencies(&history), [600, 500, 400, 100, 50]);
+        assert!(!history.settle(start + SETTLE * 10));
+
+        // Or the history is wanted before they settle
+        history.step(tuned(600), tuned(700), start);
+        assert!(history.settle_now());
+        assert_eq!(history.previous(), Some(tuned(600)));
+        assert!(!history.settle_now());
+    }
+
+    #[test]
+    fn test_new_and_toml() {
+        let history = FrequencyHistory::new([
+            tuned(162_550_000),
+            Tuned {
+                frequency: 118_100_000,
+                mode: DemodMode::Am,
+            },
+            tuned(162_550_000),
+        ]);
+        // Newest first, so the older duplicate is the one dropped
+        assert_eq!(frequencies(&history), [162_550_000, 118_100_000]);
+        assert_eq!(
+            history.to_toml(),
+            "[{ frequency = 162.550000, mode = \"nfm\" }, \
+             { frequency = 118.100000, mode = \"am\" }]"
+        );
+        assert_eq!(FrequencyHistory::default().to_toml(), "[]");
+
+        // Saved and loaded back
+        let text = crate::types::config::set_value("", "history", "recent", &history.to_toml());
+        let config = crate::types::AppConfig::parse(&text).unwrap();
+        let loaded = FrequencyHistory::from_config(&config.history.recent);
+        assert_eq!(loaded.entries(), history.entries());
+    }
+
+    #[test]
+    fn test_from_config_skips_bad_entries() {
+        let recent = [
+            RecentConfig {
+                frequency: 146.52,
+                mode: "nfm".into(),
+            },
+            RecentConfig {
+                frequency: 7.1,
+                mode: "morse".into(),
+            },
+            RecentConfig {
+                frequency: -1.0,
+                mode: "am".into(),
+            },
+        ];
+        assert_eq!(frequencies(&FrequencyHistory::from_config(&recent)), [146_520_000]);
+    }
+}
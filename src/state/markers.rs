@@ -0,0 +1,261 @@
+//! Measurement markers on the spectrum
+//!
+//! A marker is dropped on the strongest signal in the spectrum not already
+//! marked by the other, and holds on to its absolute frequency, so it stays
+//! on the signal through a retune and drops out of the readout once the capture no longer covers it. Its
+//! level is read from the latest spectrum each time it is shown.
+
+use super::calibration::LevelScale;
+use crate::scan::{bin_offset, peak_bin, step_bins};
+
+/// How many markers can be placed
+pub const MARKER_COUNT: usize = 2;
+/// How far either side of another marker a new one isn't put, in Hz
+const MARKER_GUARD_HZ: u32 = 10_000;
+
+/// A marker's frequency and its level in the latest spectrum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarkerReading {
+    /// Absolute frequency in Hz
+    pub frequency: u32,
+    /// Offset from the center in Hz
+    pub offset: i64,
+    /// dB at the marker, None while the capture doesn't cover it
+    pub level: Option<f32>,
+}
+
+/// The markers placed, by number
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Markers {
+    /// Absolute frequency of each marker in Hz
+    slots: [Option<u32>; MARKER_COUNT],
+    /// The marker placed next
+    next: usize,
+}
+
+impl Markers {
+    /// Drop the next marker on the peak of `fft`, a spectrum centered on
+    /// `center` across `sample_rate`, away from the other markers; its
+    /// number, None with nothing to mark
+    pub fn place_at_peak(&mut self, fft: &[f32], center: u32, sample_rate: u32) -> Option<usize> {
+        let mut unmarked = fft.to_vec();
+        for (index, slot) in self.slots.iter().enumerate() {
+            let Some(frequency) = slot.filter(|_| index != self.next) else {
+                continue;
+            };
+            let offset = frequency as i64 - center as i64;
+            if offset.abs() <= sample_rate as i64 / 2 + MARKER_GUARD_HZ as i64 {
+                let guard = step_bins(offset, 2 * MARKER_GUARD_HZ, sample_rate, fft.len());
+                unmarked[guard].fill(f32::NEG_INFINITY);
+            }
+        }
+        let bin = peak_bin(&unmarked, 0..fft.len()).filter(|&bin| unmarked[bin].is_finite())?;
+        let offset = bin_offset(bin, sample_rate, fft.len());
+        let frequency = u32::try_from(center as i64 + offset).ok()?;
+        Some(self.place(frequency))
+    }
+
+    /// Drop the next marker at `frequency`, taking turns; its number
+    pub fn place(&mut self, frequency: u32) -> usize {
+        let index = self.next;
+        self.slots[index] = Some(frequency);
+        self.next = (index + 1) % MARKER_COUNT;
+        index + 1
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    /// Each placed marker's number and reading against `fft`, a spectrum
+    /// centered on `center` across `sample_rate`
+    pub fn readings(
+        &self,
+        fft: &[f32],
+        center: u32,
+        sample_rate: u32,
+    ) -> Vec<(usize, MarkerReading)> {
+        let half = sample_rate as i64 / 2;
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let frequency = (*slot)?;
+                let offset = frequency as i64 - center as i64;
+                let level = (-half..half)
+                    .contains(&offset)
+                    .then(|| step_bins(offset, 0, sample_rate, fft.len()).start)
+                    .and_then(|bin| fft.get(bin).copied());
+                let reading = MarkerReading {
+                    frequency,
+                    offset,
+                    level,
+                };
+                Some((index + 1, reading))
+            })
+            .collect()
+    }
+
+    /// Marker 2 less marker 1 in Hz and dB, once both are placed; the dB
+    /// difference only while both are in the capture
+    pub fn delta(readings: &[(usize, MarkerReading)]) -> Option<(i64, Option<f32>)> {
+        let [(_, first), (_, second)] = readings else {
+            return None;
+        };
+        let hz = second.frequency as i64 - first.frequency as i64;
+        let db = second.level.zip(first.level).map(|(b, a)| b - a);
+        Some((hz, db))
+    }
+
+    /// One line of readings, with levels on `scale`, and their delta; None
+    /// with no markers
+    pub fn summary(
+        &self,
+        fft: &[f32],
+        center: u32,
+        sample_rate: u32,
+        scale: LevelScale,
+    ) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let readings = self.readings(fft, center, sample_rate);
+        let mut parts: Vec<String> = readings
+            .iter()
+            .map(|(number, reading)| {
+                let level = match reading.level {
+                    Some(db) => scale.format(db, 1),
+                    None => "off screen".to_string(),
+                };
+                format!(
+                    "M{} {:.4} MHz {}",
+                    number,
+                    reading.frequency as f64 / 1_000_000.0,
+                    level
+                )
+            })
+            .collect();
+        if let Some((hz, db)) = Self::delta(&readings) {
+            let mut delta = format!("Δf {:+.3} kHz", hz as f64 / 1000.0);
+            if let Some(db) = db {
+                delta.push_str(&format!(" ΔdB {:+.1}", db));
+            }
+            parts.push(delta);
+        }
+        Some(parts.join("  "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 2_048_000;
+    const CENTER: u32 = 145_000_000;
+
+    /// 1 kHz bins with signals at the given offsets and levels
+    fn spectrum(signals: &[(i64, f32)]) -> Vec<f32> {
+        let mut fft = vec![-90.0; 2048];
+        for &(offset, db) in signals {
+            fft[(1024 + offset / 1000) as usize] = db;
+        }
+        fft
+    }
+
+    #[test]
+    fn test_placed_on_the_peak() {
+        let mut markers = Markers::default();
+        assert!(markers.is_empty());
+        assert_eq!(markers.place_at_peak(&[], CENTER, RATE), None);
+
+        // The DC spike is passed over for the signal
+        let fft = spectrum(&[(0, -5.0), (25_000, -30.0), (-100_000, -50.0)]);
+        assert_eq!(markers.place_at_peak(&fft, CENTER, RATE), Some(1));
+        let readings = markers.readings(&fft, CENTER, RATE);
+        assert_eq!(readings[0].1.frequency, 145_025_000);
+        assert_eq!(readings[0].1.level, Some(-30.0));
+
+        // Then marker 2, then round to 1 again
+        assert_eq!(markers.place(144_900_000), 2);
+        assert_eq!(markers.place(145_012_500), 1);
+        let readings = markers.readings(&fft, CENTER, RATE);
+        assert_eq!(readings[0].1.frequency, 145_012_500);
+        assert_eq!(readings[1].1.level, Some(-50.0));
+
+        // The other marker's peak is passed over for the next strongest
+        let mut markers = Markers::default();
+        assert_eq!(markers.place_at_peak(&fft, CENTER, RATE), Some(1));
+        assert_eq!(markers.place_at_peak(&fft, CENTER, RATE), Some(2));
+        let readings = markers.readings(&fft, CENTER, RATE);
+        assert_eq!(readings[0].1.frequency, 145_025_000);
+        assert_eq!(readings[1].1.frequency, 144_900_000);
+        // Moving marker 1 again, only marker 2's peak is out of bounds
+        assert_eq!(markers.place_at_peak(&fft, CENTER, RATE), Some(1));
+        assert_eq!(markers.readings(&fft, CENTER, RATE)[0].1.frequency, 145_025_000);
+        // Nowhere but the other marker's bin: nothing to mark
+        let mut markers = Markers::default();
+        markers.place(CENTER);
+        assert_eq!(markers.place_at_peak(&[-90.0], CENTER, RATE), None);
+
+        markers.clear();
+        assert!(markers.is_empty());
+        assert_eq!(markers.summary(&fft, CENTER, RATE, LevelScale::default()), None);
+        assert_eq!(markers.place(CENTER), 1);
+    }
+
+    #[test]
+    fn test_delta() {
+        let fft = spectrum(&[(25_000, -30.0), (37_000, -72.5)]);
+        let mut markers = Markers::default();
+        markers.place(145_025_000);
+        assert_eq!(Markers::delta(&markers.readings(&fft, CENTER, RATE)), None);
+        markers.place(145_037_000);
+        assert_eq!(
+            Markers::delta(&markers.readings(&fft, CENTER, RATE)),
+            Some((12_000, Some(-42.5)))
+        );
+        assert_eq!(
+            markers.summary(&fft, CENTER, RATE, LevelScale::default()).unwrap(),
+            "M1 145.0250 MHz -30.0 dBFS  M2 145.0370 MHz -72.5 dBFS  Δf +12.000 kHz ΔdB -42.5"
+        );
+        // Calibrated, the levels move and the difference doesn't
+        let calibrated = LevelScale::new(Some(-20.0));
+        assert_eq!(
+            markers.summary(&fft, CENTER, RATE, calibrated).unwrap(),
+            "M1 145.0250 MHz -50.0 dBm (cal)  M2 145.0370 MHz -92.5 dBm (cal)  \
+             Δf +12.000 kHz ΔdB -42.5"
+        );
+    }
+
+    #[test]
+    fn test_markers_keep_their_frequency() {
+        let mut markers = Markers::default();
+        markers.place(145_025_000);
+        markers.place(146_000_000);
+
+        // Retuned 500 kHz up: both signals are still in the capture, at new
+        // offsets
+        let center = 145_500_000;
+        let fft = spectrum(&[(-475_000, -30.0), (500_000, -60.0)]);
+        let readings = markers.readings(&fft, center, RATE);
+        assert_eq!(readings[0].1.offset, -475_000);
+        assert_eq!(readings[0].1.level, Some(-30.0));
+        assert_eq!(readings[1].1.offset, 500_000);
+        assert_eq!(readings[1].1.level, Some(-60.0));
+
+        // A narrower capture leaves marker 2 off the edge, but it is kept
+        let fft = vec![-90.0; 1024];
+        let readings = markers.readings(&fft, center, 250_000);
+        assert_eq!(readings[0].1.level, None);
+        assert_eq!(readings[1].1.frequency, 146_000_000);
+        assert_eq!(Markers::delta(&readings), Some((975_000, None)));
+        assert!(markers
+            .summary(&fft, center, 250_000, LevelScale::default())
+            .unwrap()
+            .contains("M2 146.0000 MHz off screen"));
+    }
+}
@@ -0,0 +1,159 @@
+//! What the decoder pane shows
+//!
+//! The pane can be paused for reading while messages keep arriving: it stays
+//! on the ones it had and counts the rest as new. It can also be expanded to
+//! show each message as received under its summary. Messages are counted as
+//! they are received rather than by position, since the oldest are dropped
+//! once `DecoderState::max_messages` is reached.
+
+use super::app_state::ClockZone;
+use crate::types::DecodedMessage;
+use chrono::{DateTime, TimeZone};
+use std::path::{Path, PathBuf};
+
+/// Whether the decoder pane follows new messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageView {
+    /// Messages received when paused, None while following
+    paused_at: Option<u64>,
+    /// Whether raw messages are shown under their summaries
+    expanded: bool,
+}
+
+impl MessageView {
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Pause at the `received` messages so far, or follow again; returns
+    /// whether it is now paused
+    pub fn toggle_pause(&mut self, received: u64) -> bool {
+        self.paused_at = match self.paused_at {
+            Some(_) => None,
+            None => Some(received),
+        };
+        self.is_paused()
+    }
+
+    pub fn is_expanded(&self) -> bool {
+        self.expanded
+    }
+
+    /// Show or hide raw messages; returns whether they are now shown
+    pub fn toggle_expanded(&mut self) -> bool {
+        self.expanded = !self.expanded;
+        self.expanded
+    }
+
+    /// Follow new messages again
+    pub fn resume(&mut self) {
+        self.paused_at = None;
+    }
+
+    /// The part of `messages` shown, the last `received` in total: all of
+    /// them when following, else those that came before the pause
+    pub fn shown<'a>(&self, messages: &'a [DecodedMessage], received: u64) -> &'a [DecodedMessage] {
+        let hidden = self.new_count(received).min(messages.len() as u64) as usize;
+        &messages[..messages.len() - hidden]
+    }
+
+    /// Messages received since the pause
+    pub fn new_count(&self, received: u64) -> u64 {
+        self.paused_at.map_or(0, |at| received.saturating_sub(at))
+    }
+}
+
+/// `messages` as plain text, one per line with its date and time in `clock`
+pub fn export_text(messages: &[DecodedMessage], clock: ClockZone) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            format!(
+                "{} {} {}\n",
+                clock.date_time(message.timestamp),
+                message.mode.file_tag(),
+                message.content
+            )
+        })
+        .collect()
+}
+
+/// Where messages exported at `time` are written in `dir`
+///
+/// e.g. `decodes_20240601_142503.txt`
+pub fn export_path<Tz: TimeZone>(dir: &Path, time: &DateTime<Tz>) -> PathBuf
+where
+    Tz::Offset: std::fmt::Display,
+{
+    dir.join(format!("decodes_{}.txt", time.format("%Y%m%d_%H%M%S")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DemodMode;
+    use chrono::Utc;
+
+    fn messages(contents: &[&str]) -> Vec<DecodedMessage> {
+        contents
+            .iter()
+            .map(|content| DecodedMessage::new(DemodMode::Aprs, content.to_string()))
+            .collect()
+    }
+
+    fn contents(messages: &[DecodedMessage]) -> Vec<&str> {
+        messages.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_pause_holds_view_and_counts_new() {
+        let mut view = MessageView::default();
+        let all = messages(&["a", "b", "c", "d", "e"]);
+
+        // Following shows everything
+        assert_eq!(contents(view.shown(&all[..3], 3)), ["a", "b", "c"]);
+        assert_eq!(view.new_count(3), 0);
+
+        assert!(view.toggle_pause(3));
+        assert_eq!(contents(view.shown(&all, 5)), ["a", "b", "c"]);
+        assert_eq!(view.new_count(5), 2);
+
+        // The oldest dropped off the front while paused
+        assert_eq!(contents(view.shown(&all[2..], 5)), ["c"]);
+        // More new than kept
+        assert!(view.shown(&all[..2], 9).is_empty());
+
+        assert!(!view.toggle_pause(9));
+        assert_eq!(view.new_count(9), 0);
+        assert_eq!(contents(view.shown(&all, 9)), ["a", "b", "c", "d", "e"]);
+
+        view.toggle_pause(9);
+        view.resume();
+        assert!(!view.is_paused());
+
+        // Expanding is independent of pausing
+        assert!(view.toggle_expanded());
+        assert!(view.is_expanded() && !view.is_paused());
+        assert!(!view.toggle_expanded());
+    }
+
+    #[test]
+    fn test_export() {
+        let mut all = messages(&["N0CALL>APRS:!4903.50N/07201.75W-", "second"]);
+        all[0].timestamp = Utc.with_ymd_and_hms(2024, 6, 1, 14, 25, 3).unwrap();
+        all[1].timestamp = Utc.with_ymd_and_hms(2024, 6, 1, 14, 26, 0).unwrap();
+        all[1].mode = DemodMode::Ais;
+        assert_eq!(
+            export_text(&all, ClockZone::Utc),
+            "2024-06-01 14:25:03Z APRS N0CALL>APRS:!4903.50N/07201.75W-\n\
+             2024-06-01 14:26:00Z AIS second\n"
+        );
+        assert_eq!(export_text(&[], ClockZone::Utc), "");
+
+        let time = Utc.with_ymd_and_hms(2024, 6, 1, 14, 25, 3).unwrap();
+        assert_eq!(
+            export_path(Path::new("recordings"), &time),
+            Path::new("recordings/decodes_20240601_142503.txt")
+        );
+    }
+}
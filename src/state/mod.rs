@@ -1,7 +1,25 @@
 pub mod app_state;
+pub mod bookmark_csv;
+pub mod bookmarks;
+pub mod calibration;
+pub mod history;
+pub mod markers;
+pub mod message_view;
+pub mod mode_settings;
+pub mod playback;
+pub mod receivers;
+pub mod stats;
+pub mod undo;
+pub mod waterfall;
 
 // Re-export commonly used types
 pub use app_state::{
-    AppState, ControlId, DecoderState, RecordingState, SdrState, SharedState, SpectrumState,
-    UiState,
+    AppState, ControlId, DecoderState, Modal, PaneId, RecordingMode, RecordingState, SdrState,
+    SharedState, SpectrumState, StateChanged, StreamingState, UiState, VfoConfig, VoxSettings,
 };
+pub use bookmarks::{Bookmark, Bookmarks, LevelOverride};
+pub use history::{FrequencyHistory, Tuned};
+pub use mode_settings::ModeMemory;
+pub use playback::PlaybackState;
+pub use receivers::{Receiver, Receivers};
+pub use stats::RateMeter;
@@ -0,0 +1,285 @@
+//! Settings remembered for each demodulation mode
+//!
+//! Leaving a mode stores its filter width, squelch, de-emphasis, BFO offset
+//! and AGC speed, and coming back to it puts them back, so a switch from WFM
+//! listening to NFM packet doesn't mean setting them up again. Modes not
+//! used yet start from a built-in table.
+//! The command thread switches modes under one state lock, so the DSP thread
+//! never sees one mode with another's settings.
+
+use crate::dsp::filters::AgcSpeed;
+use crate::types::config::SavedModeSettings;
+use crate::types::DemodMode;
+use std::collections::{BTreeMap, HashMap};
+
+/// How far one key press moves the BFO, in Hz
+pub const BFO_STEP: i32 = 50;
+/// Furthest the BFO goes from the carrier either way, in Hz
+pub const BFO_LIMIT: i32 = 3_000;
+
+/// Whether `mode`'s audio is FM, and so de-emphasised
+pub fn uses_deemphasis(mode: DemodMode) -> bool {
+    matches!(
+        mode,
+        DemodMode::FmNarrow | DemodMode::FmWide | DemodMode::Aprs | DemodMode::Adsb | DemodMode::Ais
+    )
+}
+
+/// Whether `mode` is heard through the beat oscillator
+pub fn uses_bfo(mode: DemodMode) -> bool {
+    matches!(mode, DemodMode::Usb | DemodMode::Lsb | DemodMode::Cw)
+}
+
+/// A de-emphasis time constant for display, e.g. "75 µs"
+pub fn deemphasis_label(deemphasis: Option<u32>) -> String {
+    match deemphasis {
+        Some(us) => format!("{} µs", us),
+        None => "Off".to_string(),
+    }
+}
+
+/// What is remembered for a mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModeSettings {
+    /// Channel filter width in Hz, None for the mode's default
+    pub filter_width: Option<u32>,
+    /// Squelch threshold in dBFS, None for squelch off
+    pub squelch_level: Option<f32>,
+    /// FM de-emphasis time constant in µs, None for a flat response
+    pub deemphasis: Option<u32>,
+    /// Where the SSB and CW beat oscillator sits from the carrier, in Hz
+    pub bfo_offset: i32,
+    /// How quickly the audio AGC follows the level
+    pub agc_speed: AgcSpeed,
+}
+
+impl ModeSettings {
+    /// Settings for a mode that hasn't been used yet
+    pub fn default_for(mode: DemodMode) -> Self {
+        let squelch_level = match mode {
+            // Voice channels sit quiet between transmissions
+            DemodMode::FmNarrow | DemodMode::Am => Some(-40.0),
+            _ => None,
+        };
+        let deemphasis = match mode {
+            // Broadcast FM is pre-emphasised at 75 µs in the Americas
+            DemodMode::FmWide => Some(75),
+            mode if uses_deemphasis(mode) => Some(50),
+            _ => None,
+        };
+        let (bfo_offset, agc_speed) = match mode {
+            // Sideband and Morse come and go with the voice and the keying
+            mode if uses_bfo(mode) => (1_500, AgcSpeed::Slow),
+            _ => (0, AgcSpeed::Off),
+        };
+        Self {
+            filter_width: None,
+            squelch_level,
+            deemphasis,
+            bfo_offset,
+            agc_speed,
+        }
+    }
+
+    /// `saved` for `mode`, which takes the mode's defaults for what isn't
+    /// given beyond the filter width and squelch
+    pub fn from_saved(mode: DemodMode, saved: &SavedModeSettings) -> Self {
+        let defaults = Self::default_for(mode);
+        // 0 turns the de-emphasis off
+        let deemphasis = match saved.deemphasis {
+            Some(0) => None,
+            Some(us) => Some(us),
+            None => defaults.deemphasis,
+        };
+        let agc_speed = match saved.agc.as_deref().map(|name| (name, AgcSpeed::parse(name))) {
+            Some((_, Some(speed))) => speed,
+            Some((name, None)) => {
+                log::warn!("Mode settings: unknown AGC speed '{}'", name);
+                defaults.agc_speed
+            }
+            None => defaults.agc_speed,
+        };
+        Self {
+            filter_width: saved.filter_width,
+            squelch_level: saved.squelch,
+            deemphasis,
+            bfo_offset: saved.bfo.unwrap_or(defaults.bfo_offset),
+            agc_speed,
+        }
+    }
+
+    /// The settings for `mode` as a TOML inline table for the config file;
+    /// what the mode has by default is left out, beyond the squelch
+    pub fn to_toml(self, mode: DemodMode) -> String {
+        let defaults = Self::default_for(mode);
+        let mut fields = Vec::new();
+        if let Some(width) = self.filter_width {
+            fields.push(format!("filter_width = {}", width));
+        }
+        if let Some(level) = self.squelch_level {
+            fields.push(format!("squelch = {:.1}", level));
+        }
+        if self.deemphasis != defaults.deemphasis {
+            fields.push(format!("deemphasis = {}", self.deemphasis.unwrap_or(0)));
+        }
+        if self.bfo_offset != defaults.bfo_offset {
+            fields.push(format!("bfo = {}", self.bfo_offset));
+        }
+        if self.agc_speed != defaults.agc_speed {
+            fields.push(format!("agc = \"{}\"", self.agc_speed.name().to_ascii_lowercase()));
+        }
+        if fields.is_empty() {
+            return "{}".to_string();
+        }
+        format!("{{ {} }}", fields.join(", "))
+    }
+}
+
+/// Settings stored for the modes used so far
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModeMemory {
+    slots: HashMap<DemodMode, ModeSettings>,
+}
+
+impl ModeMemory {
+    /// Settings saved in the config file, by mode name
+    pub fn from_config(saved: &BTreeMap<String, SavedModeSettings>) -> Self {
+        let mut memory = Self::default();
+        for (name, settings) in saved {
+            match name.parse::<DemodMode>() {
+                Ok(mode) => memory.store(mode, ModeSettings::from_saved(mode, settings)),
+                Err(e) => log::warn!("Mode settings: {}", e),
+            }
+        }
+        memory
+    }
+
+    /// What was stored for `mode`, if it has been used
+    pub fn stored(&self, mode: DemodMode) -> Option<ModeSettings> {
+        self.slots.get(&mode).copied()
+    }
+
+    /// Settings to switch `mode` to: as it was left, else the defaults
+    pub fn get(&self, mode: DemodMode) -> ModeSettings {
+        self.stored(mode)
+            .unwrap_or_else(|| ModeSettings::default_for(mode))
+    }
+
+    pub fn store(&mut self, mode: DemodMode, settings: ModeSettings) {
+        self.slots.insert(mode, settings);
+    }
+
+    /// Config file keys and values for the stored modes, in mode order
+    pub fn to_config(&self) -> Vec<(String, String)> {
+        DemodMode::all()
+            .iter()
+            .filter_map(|&mode| {
+                let settings = self.stored(mode)?;
+                Some((mode.file_tag().to_ascii_lowercase(), settings.to_toml(mode)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::DecoderState;
+
+    #[test]
+    fn test_settings_follow_the_mode() {
+        let mut decoder = DecoderState {
+            filter_width: Some(180_000),
+            ..Default::default()
+        };
+        decoder.switch_mode(DemodMode::FmWide);
+
+        // WFM hasn't been used: the defaults
+        assert_eq!(decoder.filter_width, None);
+        assert_eq!(decoder.squelch_level, None);
+        assert_eq!(decoder.deemphasis, Some(75));
+        decoder.filter_width = Some(150_000);
+        decoder.deemphasis = Some(50);
+
+        // NFM as it was left
+        decoder.switch_mode(DemodMode::FmNarrow);
+        assert_eq!(decoder.filter_width, Some(180_000));
+        assert_eq!(decoder.squelch_level, None);
+        decoder.squelch_level = Some(-50.0);
+
+        // AM's defaults, then back through WFM to NFM
+        decoder.switch_mode(DemodMode::Am);
+        assert_eq!(decoder.squelch_level, Some(-40.0));
+        decoder.switch_mode(DemodMode::FmWide);
+        assert_eq!(decoder.filter_width, Some(150_000));
+        assert_eq!(decoder.squelch_level, None);
+        assert_eq!(decoder.deemphasis, Some(50));
+
+        // USB's BFO and AGC, kept apart from LSB's
+        decoder.switch_mode(DemodMode::Usb);
+        assert_eq!((decoder.bfo_offset, decoder.agc_speed), (1_500, AgcSpeed::Slow));
+        decoder.bfo_offset = 700;
+        decoder.agc_speed = AgcSpeed::Fast;
+        decoder.switch_mode(DemodMode::Lsb);
+        assert_eq!((decoder.bfo_offset, decoder.agc_speed), (1_500, AgcSpeed::Slow));
+        decoder.switch_mode(DemodMode::Usb);
+        assert_eq!((decoder.bfo_offset, decoder.agc_speed), (700, AgcSpeed::Fast));
+        assert_eq!(decoder.deemphasis, None);
+
+        decoder.switch_mode(DemodMode::FmNarrow);
+        assert_eq!(decoder.filter_width, Some(180_000));
+        assert_eq!(decoder.squelch_level, Some(-50.0));
+
+        // Switching to the same mode changes nothing
+        decoder.filter_width = Some(12_500);
+        decoder.switch_mode(DemodMode::FmNarrow);
+        assert_eq!(decoder.filter_width, Some(12_500));
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let mut memory = ModeMemory::default();
+        let nfm = ModeSettings {
+            filter_width: Some(12_500),
+            squelch_level: Some(-46.0),
+            ..ModeSettings::default_for(DemodMode::FmNarrow)
+        };
+        memory.store(DemodMode::FmNarrow, nfm);
+        let wfm = ModeSettings {
+            deemphasis: None,
+            ..ModeSettings::default_for(DemodMode::FmWide)
+        };
+        memory.store(DemodMode::FmWide, wfm);
+        memory.store(DemodMode::Usb, ModeSettings::default_for(DemodMode::Usb));
+        let lsb = ModeSettings {
+            bfo_offset: 1_200,
+            agc_speed: AgcSpeed::Fast,
+            ..ModeSettings::default_for(DemodMode::Lsb)
+        };
+        memory.store(DemodMode::Lsb, lsb);
+        let saved = memory.to_config();
+        assert_eq!(
+            saved,
+            [
+                (
+                    "nfm".to_string(),
+                    "{ filter_width = 12500, squelch = -46.0 }".to_string()
+                ),
+                ("wfm".to_string(), "{ deemphasis = 0 }".to_string()),
+                ("usb".to_string(), "{}".to_string()),
+                ("lsb".to_string(), "{ bfo = 1200, agc = \"fast\" }".to_string()),
+            ]
+        );
+
+        let text: String = saved
+            .iter()
+            .map(|(key, value)| format!("{} = {}\n", key, value))
+            .collect();
+        let parsed: BTreeMap<String, SavedModeSettings> = toml::from_str(&text).unwrap();
+        assert_eq!(ModeMemory::from_config(&parsed), memory);
+        assert_eq!(
+            memory.get(DemodMode::Am),
+            ModeSettings::default_for(DemodMode::Am)
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Where IQ file playback is and how it is paced
+//!
+//! The UI asks for seeks and speed changes here and the file source carries
+//! them out, marking the samples after each seek so the DSP thread knows to
+//! start afresh.
+//! Positions are in complex samples; the file source turns them into byte
+//! offsets for the file's sample format.
+
+use super::app_state::format_elapsed;
+
+/// How far one seek step moves, in seconds
+pub const SEEK_STEP_SECS: i64 = 10;
+
+/// How fast a file plays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlaybackSpeed {
+    #[default]
+    Normal,
+    Double,
+    Five,
+    /// As fast as the DSP keeps up
+    Max,
+}
+
+impl PlaybackSpeed {
+    /// The next speed up, back to normal after max
+    pub fn next(self) -> Self {
+        match self {
+            PlaybackSpeed::Normal => PlaybackSpeed::Double,
+            PlaybackSpeed::Double => PlaybackSpeed::Five,
+            PlaybackSpeed::Five => PlaybackSpeed::Max,
+            PlaybackSpeed::Max => PlaybackSpeed::Normal,
+        }
+    }
+
+    /// Multiple of real time, None when unpaced
+    pub fn factor(self) -> Option<f64> {
+        match self {
+            PlaybackSpeed::Normal => Some(1.0),
+            PlaybackSpeed::Double => Some(2.0),
+            PlaybackSpeed::Five => Some(5.0),
+            PlaybackSpeed::Max => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PlaybackSpeed::Normal => "1×",
+            PlaybackSpeed::Double => "2×",
+            PlaybackSpeed::Five => "5×",
+            PlaybackSpeed::Max => "max",
+        }
+    }
+}
+
+/// A file being played in place of a receiver
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackState {
+    /// File name, for the status bar
+    pub name: String,
+    pub sample_rate: u32,
+    /// Complex samples in the file
+    pub total: u64,
+    /// Complex samples played so far
+    pub position: u64,
+    pub speed: PlaybackSpeed,
+    pub paused: bool,
+    /// Position asked for, until the file source gets to it
+    pub seek: Option<u64>,
+}
+
+impl PlaybackState {
+    pub fn new(name: impl Into<String>, sample_rate: u32, total: u64) -> Self {
+        Self {
+            name: name.into(),
+            sample_rate,
+            total,
+            position: 0,
+            speed: PlaybackSpeed::default(),
+            paused: false,
+            seek: None,
+        }
+    }
+
+    /// Samples in `secs` seconds
+    fn samples(&self, secs: f64) -> u64 {
+        (secs * self.sample_rate as f64).round() as u64
+    }
+
+    /// Seconds into the file at sample `samples`
+    pub fn secs(&self, samples: u64) -> f64 {
+        samples as f64 / self.sample_rate.max(1) as f64
+    }
+
+    pub fn position_secs(&self) -> f64 {
+        self.secs(self.position)
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        self.secs(self.total)
+    }
+
+    /// Whether the whole file has been played
+    pub fn at_end(&self) -> bool {
+        self.position >= self.total
+    }
+
+    /// Ask to move `secs` seconds on (or back), within the file; from a
+    /// seek not yet carried out when there is one
+    pub fn seek_by(&mut self, secs: i64) -> u64 {
+        let from = self.seek.unwrap_or(self.position);
+        let step = self.samples(secs.unsigned_abs() as f64);
+        let target = if secs < 0 {
+            from.saturating_sub(step)
+        } else {
+            from.saturating_add(step)
+        };
+        self.seek_to(target)
+    }
+
+    /// Ask to move to `tenths` tenths of the way through the file
+    pub fn seek_tenths(&mut self, tenths: u64) -> u64 {
+        self.seek_to(self.total * tenths.min(10) / 10)
+    }
+
+    /// Ask to move to sample `target`, within the file
+    pub fn seek_to(&mut self, target: u64) -> u64 {
+        let target = target.min(self.total);
+        self.seek = Some(target);
+        target
+    }
+
+    /// The file source moved to `position`
+    pub fn seeked(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    /// e.g. "▶ 2× 00:01:23 / 00:10:00"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {} {} / {}",
+            if self.paused { "⏸" } else { "▶" },
+            self.speed.label(),
+            format_elapsed(self.position_secs() as i64),
+            format_elapsed(self.duration_secs() as i64)
+        )
+    }
+
+    /// A progress bar `width` characters wide
+    pub fn bar(&self, width: usize) -> String {
+        let done = (width as u64 * self.position.min(self.total))
+            .checked_div(self.total)
+            .unwrap_or_default() as usize;
+        format!("{}{}", "━".repeat(done), "─".repeat(width - done))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seek_within_file() {
+        // 60 s at 1 MS/s
+        let mut playback = PlaybackState::new("capture.iq", 1_000_000, 60_000_000);
+        assert_eq!(playback.seek_by(SEEK_STEP_SECS), 10_000_000);
+        // A second step before the first is carried out goes on from it
+        assert_eq!(playback.seek_by(SEEK_STEP_SECS), 20_000_000);
+        playback.seeked(20_000_000);
+        assert_eq!(playback.position, 20_000_000);
+
+        assert_eq!(playback.seek_by(-30), 0);
+        assert_eq!(playback.seek_by(120), 60_000_000);
+        assert_eq!(playback.seek_tenths(5), 30_000_000);
+        assert_eq!(playback.seek_tenths(0), 0);
+        assert_eq!(playback.seek_to(u64::MAX), 60_000_000);
+    }
+
+    #[test]
+    fn test_summary_and_bar() {
+        let mut playback = PlaybackState::new("capture.iq", 2_048_000, 2_048_000 * 600);
+        playback.position = 2_048_000 * 83;
+        playback.speed = playback.speed.next();
+        assert_eq!(playback.summary(), "▶ 2× 00:01:23 / 00:10:00");
+        playback.paused = true;
+        playback.speed = PlaybackSpeed::Max;
+        assert!(playback.summary().starts_with("⏸ max"));
+
+        playback.position = playback.total / 4;
+        assert_eq!(playback.bar(8), "━━──────");
+        playback.position = playback.total;
+        assert!(playback.at_end());
+        assert_eq!(playback.bar(4), "━━━━");
+        assert_eq!(PlaybackState::new("empty.iq", 1000, 0).bar(3), "───");
+        assert_eq!(PlaybackSpeed::Max.next(), PlaybackSpeed::Normal);
+    }
+}
@@ -0,0 +1,202 @@
+//! Every receiver's state, for running more than one dongle at once
+//!
+//! Each receiver has an [`AppState`](super::AppState) of its own, fed by its
+//! own SDR and DSP threads and tuned through its own command channel. One
+//! of them is selected: the controls, spectrum and waterfall show it, its
+//! commands go to its SDR thread and the speaker plays its audio. Each
+//! receiver keeps its own UI section, so status messages written to one in
+//! the background are there when it is selected again; the layout, focus
+//! and bookmarks are handed over on switching (see
+//! [`UiState::hand_over`](super::app_state::UiState::hand_over)), and
+//! receivers in the background don't compute displays nobody sees.
+//!
+//! The first receiver is the one the rest of the program follows: the
+//! network audio stream and audio pipe, the scheduler, the priority watch,
+//! a band scan and the aircraft export all go with it, whichever receiver
+//! is selected. The others are for listening, decoding and recording.
+
+use super::SharedState;
+use crate::types::Command;
+use anyhow::Result;
+use crossbeam::channel::Sender;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// One receiver's state and the commands into its SDR thread
+#[derive(Clone)]
+pub struct Receiver {
+    pub state: SharedState,
+    pub commands: Sender<Command>,
+}
+
+/// All receivers and which one is selected
+#[derive(Clone)]
+pub struct Receivers {
+    receivers: Vec<Receiver>,
+    /// Shared with the audio output, which plays the selected receiver
+    selected: Arc<AtomicUsize>,
+}
+
+impl Receivers {
+    /// `receivers` with the first one selected
+    pub fn new(receivers: Vec<Receiver>) -> Self {
+        assert!(!receivers.is_empty(), "at least one receiver is needed");
+        Self {
+            receivers,
+            selected: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.receivers.len()
+    }
+
+    /// Always false; `new` insists on at least one receiver
+    pub fn is_empty(&self) -> bool {
+        self.receivers.is_empty()
+    }
+
+    /// Index of the selected receiver
+    pub fn selected(&self) -> usize {
+        self.selected.load(Ordering::Relaxed)
+    }
+
+    /// The selected receiver
+    pub fn current(&self) -> &Receiver {
+        &self.receivers[self.selected()]
+    }
+
+    /// The selected index, for the audio output to follow
+    pub fn selection(&self) -> Arc<AtomicUsize> {
+        self.selected.clone()
+    }
+
+    /// Receiver number `index`, if there is one
+    pub fn get(&self, index: usize) -> Option<&Receiver> {
+        self.receivers.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Receiver> {
+        self.receivers.iter()
+    }
+
+    /// Select the next receiver, handing it the screen, and return it
+    pub fn select_next(&self) -> &Receiver {
+        let from = self.selected();
+        let to = (from + 1) % self.receivers.len();
+        if to != from {
+            let mut next = self.receivers[to].state.write();
+            self.receivers[from].state.write().ui.hand_over(&mut next.ui);
+            drop(next);
+            self.selected.store(to, Ordering::Relaxed);
+        }
+        &self.receivers[to]
+    }
+
+    /// Send a command to the selected receiver
+    pub fn send(&self, command: Command) -> Result<()> {
+        self.current().commands.send(command)?;
+        Ok(())
+    }
+
+    /// Send a command to every receiver, ignoring any that have stopped
+    pub fn broadcast(&self, command: Command) {
+        for receiver in &self.receivers {
+            let _ = receiver.commands.send(command.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AppState, ControlId};
+    use crossbeam::channel::{self, Receiver as ChannelReceiver};
+
+    fn receivers(count: usize) -> (Receivers, Vec<ChannelReceiver<Command>>) {
+        let (receivers, commands) = (0..count)
+            .map(|_| {
+                let (tx, rx) = channel::unbounded();
+                (
+                    Receiver {
+                        state: AppState::new_shared(),
+                        commands: tx,
+                    },
+                    rx,
+                )
+            })
+            .unzip();
+        (Receivers::new(receivers), commands)
+    }
+
+    #[test]
+    fn test_commands_go_to_selected_receiver() {
+        let (receivers, commands) = receivers(2);
+
+        receivers
+            .send(Command::SetFrequency(1_090_000_000))
+            .unwrap();
+        receivers.select_next();
+        receivers.send(Command::SetFrequency(144_390_000)).unwrap();
+
+        assert_eq!(
+            commands[0].try_recv().unwrap(),
+            Command::SetFrequency(1_090_000_000)
+        );
+        assert!(commands[0].try_recv().is_err());
+        assert_eq!(
+            commands[1].try_recv().unwrap(),
+            Command::SetFrequency(144_390_000)
+        );
+
+        receivers.broadcast(Command::Quit);
+        assert_eq!(commands[0].try_recv().unwrap(), Command::Quit);
+        assert_eq!(commands[1].try_recv().unwrap(), Command::Quit);
+    }
+
+    #[test]
+    fn test_ui_moves_with_selection() {
+        let (receivers, _commands) = receivers(3);
+        {
+            let ui = &mut receivers.current().state.write().ui;
+            ui.selected_control = ControlId::Gain;
+            ui.show_scope = true;
+        }
+        // Receiver state stays where it is
+        receivers.current().state.write().sdr.frequency = 1_090_000_000;
+        receivers.current().state.write().ui.status_message = "Scan done".to_string();
+
+        let selection = receivers.selection();
+        let next = receivers.select_next();
+        assert_eq!(selection.load(Ordering::Relaxed), 1);
+        assert_eq!(next.state.read().ui.selected_control, ControlId::Gain);
+        assert!(next.state.read().ui.show_scope);
+        assert_ne!(next.state.read().sdr.frequency, 1_090_000_000);
+
+        // The receiver left behind shows nothing, and keeps its status
+        let first = &receivers.iter().next().unwrap().state;
+        assert!(!first.read().ui.show_scope);
+        assert_eq!(first.read().ui.status_message, "Scan done");
+        first.write().ui.status_message = "Priority channel quiet".to_string();
+
+        // Round the end back to the first
+        receivers.select_next();
+        let first_again = receivers.select_next();
+        assert_eq!(receivers.selected(), 0);
+        assert!(first_again.state.read().ui.show_scope);
+        assert_eq!(first_again.state.read().sdr.frequency, 1_090_000_000);
+        assert_eq!(
+            first_again.state.read().ui.status_message,
+            "Priority channel quiet"
+        );
+    }
+
+    #[test]
+    fn test_single_receiver_stays_selected() {
+        let (receivers, _commands) = receivers(1);
+        receivers.current().state.write().ui.show_stats = true;
+        receivers.select_next();
+        assert_eq!(receivers.selected(), 0);
+        assert!(receivers.current().state.read().ui.show_stats);
+    }
+}
@@ -0,0 +1,287 @@
+//! Processing statistics
+//!
+//! The processing threads time each buffer with `Instant` and fold the
+//! results into exponential moving averages, so the cost is a couple of
+//! clock reads per buffer rather than anything per sample.
+
+use crate::audio::latency::LatencyEstimate;
+use crate::sdr::adc::AdcMeter;
+use std::time::{Duration, Instant};
+
+/// Weight of each new measurement in the moving averages
+const EMA_ALPHA: f32 = 0.1;
+
+/// Smoothed share of clipped IQ samples above which the front end is
+/// overloaded
+pub const OVERLOAD_LIMIT: f32 = 0.01;
+
+/// Columns of the ADC histogram line
+const ADC_COLUMNS: usize = 32;
+
+/// Exponential moving average; the first sample seeds it
+#[derive(Debug, Clone, Copy)]
+pub struct Ema {
+    alpha: f32,
+    value: Option<f32>,
+}
+
+impl Ema {
+    pub const fn new(alpha: f32) -> Self {
+        Self { alpha, value: None }
+    }
+
+    pub fn update(&mut self, sample: f32) -> f32 {
+        let value = match self.value {
+            Some(value) => value + self.alpha * (sample - value),
+            None => sample,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    /// The average so far, None before the first sample
+    pub fn value(&self) -> Option<f32> {
+        self.value
+    }
+}
+
+impl Default for Ema {
+    fn default() -> Self {
+        Self::new(EMA_ALPHA)
+    }
+}
+
+/// Counts buffers and samples, reporting rates about once a second
+#[derive(Debug)]
+pub struct RateMeter {
+    since: Instant,
+    buffers: u64,
+    samples: u64,
+}
+
+impl RateMeter {
+    pub fn new(now: Instant) -> Self {
+        Self { since: now, buffers: 0, samples: 0 }
+    }
+
+    /// Count a buffer of `samples`; returns (buffers/s, samples/s) once a
+    /// second has passed since the last report
+    pub fn tick(&mut self, now: Instant, samples: usize) -> Option<(f32, f32)> {
+        self.buffers += 1;
+        self.samples += samples as u64;
+
+        let elapsed = now.saturating_duration_since(self.since);
+        if elapsed < Duration::from_secs(1) {
+            return None;
+        }
+        let secs = elapsed.as_secs_f32();
+        let rates = (self.buffers as f32 / secs, self.samples as f32 / secs);
+        *self = Self::new(now);
+        Some(rates)
+    }
+}
+
+/// Smoothed per-stage timings, throughput and drop counters
+#[derive(Debug, Clone, Default)]
+pub struct StatsState {
+    /// DSP thread: spectrum FFT per buffer, in µs
+    pub fft_us: Ema,
+    /// DSP thread: demodulation and audio filtering per buffer, in µs
+    pub demod_us: Ema,
+    /// DSP thread: whole buffer as a share of the buffer's duration
+    pub dsp_load: Ema,
+    /// Decoder thread: decoding per buffer, in µs
+    pub decode_us: Ema,
+    /// Chain B's decoder thread: decoding per buffer, in µs
+    pub decode_b_us: Ema,
+    pub buffers_per_sec: f32,
+    /// IQ samples per second reaching the DSP thread
+    pub samples_per_sec: f32,
+    /// SDR thread: share of the latest buffer's IQ samples at full scale
+    pub clip_fraction: f32,
+    /// SDR thread: the clipped share, smoothed
+    pub clipping: Ema,
+    /// SDR thread: how much of the ADC's range the raw samples use
+    pub adc: AdcMeter,
+    /// IQ buffers the SDR thread dropped because the DSP thread was busy
+    pub sdr_dropped: u64,
+    /// Buffers dropped on the way to the decoder thread
+    pub decoder_dropped: u64,
+    /// IQ or audio buffers dropped on the way to the recorder
+    pub recorder_dropped: u64,
+    /// DSP thread: what is queued on the way to the speaker and streams
+    pub latency: LatencyEstimate,
+}
+
+impl StatsState {
+    /// Count a buffer's clipped share; returns whether the front end is now
+    /// overloaded when that changes
+    pub fn add_clipping(&mut self, fraction: f32) -> Option<bool> {
+        let was = self.overloaded();
+        self.clip_fraction = fraction;
+        self.clipping.update(fraction);
+        let now = self.overloaded();
+        (now != was).then_some(now)
+    }
+
+    /// Whether the raw IQ is clipping enough to call for less gain
+    pub fn overloaded(&self) -> bool {
+        self.clipping.value().is_some_and(|clipping| clipping > OVERLOAD_LIMIT)
+    }
+
+    /// The stats as aligned lines of text
+    pub fn lines(&self) -> Vec<String> {
+        let micros = |ema: &Ema| match ema.value() {
+            Some(us) => format!("{:>8.0} µs/buffer", us),
+            None => format!("{:>8}", "-"),
+        };
+        let latency = &self.latency;
+        let mut lines = vec![
+            match self.dsp_load.value() {
+                Some(load) => format!("DSP load   {:>7.1}% of real time", load * 100.0),
+                None => format!("DSP load   {:>8}", "-"),
+            },
+            format!("FFT        {}", micros(&self.fft_us)),
+            format!("Demod      {}", micros(&self.demod_us)),
+            format!("Decode     {}", micros(&self.decode_us)),
+        ];
+        if self.decode_b_us.value().is_some() {
+            lines.push(format!("Decode B   {}", micros(&self.decode_b_us)));
+        }
+        lines.extend([
+            format!("Buffers    {:>8.1} /s", self.buffers_per_sec),
+            format!("Throughput {:>8.3} MS/s", self.samples_per_sec / 1e6),
+            match self.clipping.value() {
+                Some(clipping) => format!("Clipping   {:>7.2}%", clipping * 100.0),
+                None => format!("Clipping   {:>8}", "-"),
+            },
+            format!(
+                "Dropped    SDR {}  decoder {}  recorder {}",
+                self.sdr_dropped, self.decoder_dropped, self.recorder_dropped
+            ),
+            format!(
+                "Latency    {:>8.0} ms  IQ {:.0} + audio {:.0}",
+                latency.total_ms(),
+                latency.iq_ms(),
+                latency.audio_ms()
+            ),
+            format!(
+                "Queued     IQ {} buffers  audio {} samples",
+                latency.iq_queued, latency.audio_queued
+            ),
+        ]);
+        if let Some(stream_ms) = latency.stream_ms() {
+            lines.push(format!("Stream     {:>8.0} ms", stream_ms));
+        }
+        if let (Some(usage), Some(histogram)) = (self.adc.usage, &self.adc.histogram) {
+            lines.push(format!(
+                "ADC use    {:>7.0}%  crest {:.1} dB",
+                usage.range * 100.0,
+                usage.crest_db
+            ));
+            lines.push(format!("ADC levels |{}|", histogram.bars(ADC_COLUMNS)));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ema() {
+        let mut ema = Ema::new(0.5);
+        assert_eq!(ema.value(), None);
+        assert_eq!(ema.update(10.0), 10.0);
+        assert_eq!(ema.update(20.0), 15.0);
+        assert_eq!(ema.update(20.0), 17.5);
+
+        // Converges on a steady input
+        let mut ema = Ema::default();
+        ema.update(0.0);
+        for _ in 0..100 {
+            ema.update(1.0);
+        }
+        assert!((ema.value().unwrap() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_rate_meter() {
+        let start = Instant::now();
+        let mut meter = RateMeter::new(start);
+        for i in 1..10 {
+            assert_eq!(meter.tick(start + Duration::from_millis(i * 100), 16_384), None);
+        }
+        let (buffers, samples) = meter.tick(start + Duration::from_secs(1), 16_384).unwrap();
+        assert!((buffers - 10.0).abs() < 1e-3);
+        assert!((samples - 163_840.0).abs() < 1e-1);
+
+        // Counting starts over after a report
+        assert_eq!(meter.tick(start + Duration::from_millis(1500), 1), None);
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut stats = StatsState::default();
+        assert_eq!(stats.lines()[0], "DSP load          -");
+        assert_eq!(stats.lines()[1], "FFT               -");
+
+        stats.dsp_load.update(0.234);
+        stats.fft_us.update(812.4);
+        stats.buffers_per_sec = 125.0;
+        stats.samples_per_sec = 2_048_000.0;
+        stats.decoder_dropped = 3;
+        let lines = stats.lines();
+        assert_eq!(lines[0], "DSP load      23.4% of real time");
+        assert_eq!(lines[1], "FFT             812 µs/buffer");
+        assert_eq!(lines[4], "Buffers       125.0 /s");
+        assert_eq!(lines[5], "Throughput    2.048 MS/s");
+        stats.add_clipping(0.0125);
+        assert_eq!(stats.lines()[6], "Clipping      1.25%");
+        assert_eq!(lines[6], "Clipping          -");
+        assert_eq!(lines[7], "Dropped    SDR 0  decoder 3  recorder 0");
+        assert_eq!(lines.len(), 10);
+
+        stats.latency = LatencyEstimate {
+            iq_buffer_secs: 0.008,
+            iq_queued: 2,
+            audio_queued: 9_600,
+            stream_queued: Some(1),
+        };
+        let lines = stats.lines();
+        assert_eq!(lines[8], "Latency         224 ms  IQ 24 + audio 200");
+        assert_eq!(lines[9], "Queued     IQ 2 buffers  audio 9600 samples");
+        assert_eq!(lines[10], "Stream           32 ms");
+    }
+
+    #[test]
+    fn test_overload() {
+        let mut stats = StatsState::default();
+        assert!(!stats.overloaded());
+
+        // Clean buffers and the odd clipped sample stay quiet
+        for _ in 0..50 {
+            assert_eq!(stats.add_clipping(0.0), None);
+        }
+        assert_eq!(stats.add_clipping(0.002), None);
+        assert!(!stats.overloaded());
+
+        // A single bad buffer is smoothed over...
+        assert_eq!(stats.add_clipping(0.05), None);
+        assert_eq!(stats.clip_fraction, 0.05);
+        // ...sustained clipping is reported once
+        let reports: Vec<_> = (0..20).filter_map(|_| stats.add_clipping(0.05)).collect();
+        assert_eq!(reports, vec![true]);
+        assert!(stats.overloaded());
+
+        // And cleared once it stops
+        let reports: Vec<_> = (0..50).filter_map(|_| stats.add_clipping(0.0)).collect();
+        assert_eq!(reports, vec![false]);
+        assert!(!stats.overloaded());
+
+        // Heavy clipping trips it straight away
+        let mut stats = StatsState::default();
+        assert_eq!(stats.add_clipping(0.3), Some(true));
+    }
+}
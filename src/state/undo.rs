@@ -0,0 +1,359 @@
+//! Undo and redo for tuning and settings
+//!
+//! The settings one wrong key can lose — chain A's frequency and mode, the
+//! gain, the squelch and the filter width — are looked at after every event
+//! and render tick. Commands take effect on the SDR thread a moment after
+//! the key, so a change seen shortly after a key counts as the user's and
+//! goes on the undo stack with the settings from before it. A change with no
+//! key behind it (the scanner, the priority watch, a remote client) only
+//! moves the baseline on, so undo walks back the user's own steps.
+//!
+//! Repeated changes to the same settings in quick succession, like holding
+//! an arrow key to tune, make one step.
+
+use crate::types::DemodMode;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Steps kept; the oldest go first
+pub const UNDO_LEN: usize = 50;
+/// How long after a key a change still counts as the user's
+pub const ATTRIBUTION: Duration = Duration::from_secs(1);
+/// Changes to the same settings closer together than this are one step
+pub const COALESCE: Duration = Duration::from_secs(2);
+
+/// The settings undo puts back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    /// Chain A's frequency in Hz
+    pub frequency: u32,
+    pub mode: DemodMode,
+    /// Tenths of a dB, None for auto gain
+    pub gain: Option<i32>,
+    /// dBFS, None for squelch off
+    pub squelch: Option<f32>,
+    /// Hz, None for the mode's default
+    pub filter_width: Option<u32>,
+}
+
+impl Settings {
+    /// Which settings differ from `other`, in the order they are described
+    fn differences(&self, other: &Settings) -> [bool; 5] {
+        [
+            self.frequency != other.frequency,
+            self.mode != other.mode,
+            self.gain != other.gain,
+            self.squelch != other.squelch,
+            self.filter_width != other.filter_width,
+        ]
+    }
+
+    /// What going from these settings to `to` changes, e.g.
+    /// "frequency 162.550 → 144.390, gain 20.7 dB → auto"
+    pub fn describe(&self, to: &Settings) -> String {
+        let gain = |gain: Option<i32>| match gain {
+            Some(gain) => format!("{:.1} dB", gain as f64 / 10.0),
+            None => "auto".to_string(),
+        };
+        let squelch = |level: Option<f32>| match level {
+            Some(level) => format!("{:.0} dBFS", level),
+            None => "off".to_string(),
+        };
+        let width = |width: Option<u32>| match width {
+            Some(width) => format!("{:.1} kHz", width as f64 / 1000.0),
+            None => "default".to_string(),
+        };
+        let [frequency, mode, gain_changed, squelch_changed, width_changed] = self.differences(to);
+        let mut parts = Vec::new();
+        if frequency {
+            parts.push(format!(
+                "frequency {:.3} \u{2192} {:.3}",
+                self.frequency as f64 / 1_000_000.0,
+                to.frequency as f64 / 1_000_000.0
+            ));
+        }
+        if mode {
+            parts.push(format!(
+                "mode {} \u{2192} {}",
+                self.mode.file_tag(),
+                to.mode.file_tag()
+            ));
+        }
+        if gain_changed {
+            parts.push(format!(
+                "gain {} \u{2192} {}",
+                gain(self.gain),
+                gain(to.gain)
+            ));
+        }
+        if squelch_changed {
+            parts.push(format!(
+                "squelch {} \u{2192} {}",
+                squelch(self.squelch),
+                squelch(to.squelch)
+            ));
+        }
+        if width_changed {
+            parts.push(format!(
+                "filter {} \u{2192} {}",
+                width(self.filter_width),
+                width(to.filter_width)
+            ));
+        }
+        if parts.is_empty() {
+            "no change".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// One user change, undone by going back to `before`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub before: Settings,
+    pub after: Settings,
+    /// When it was last added to
+    at: Instant,
+}
+
+/// Undo and redo stacks, and the settings last seen
+#[derive(Debug, Clone, Default)]
+pub struct UndoHistory {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    baseline: Option<Settings>,
+    key_at: Option<Instant>,
+}
+
+impl UndoHistory {
+    /// Note a key at `now`, so changes seen shortly after count as the
+    /// user's
+    pub fn key(&mut self, now: Instant) {
+        self.key_at = Some(now);
+    }
+
+    /// Take in the settings as they are at `now`; returns whether that
+    /// recorded or extended an undo step
+    pub fn observe(&mut self, current: Settings, now: Instant) -> bool {
+        let Some(baseline) = self.baseline.replace(current) else {
+            return false;
+        };
+        if baseline == current {
+            return false;
+        }
+        let by_user = self
+            .key_at
+            .is_some_and(|at| now.saturating_duration_since(at) <= ATTRIBUTION);
+        if !by_user {
+            return false;
+        }
+        self.redo.clear();
+
+        // More of the same straight after the last step adds to it
+        if let Some(top) = self.undo.back_mut() {
+            if top.after == baseline
+                && now.saturating_duration_since(top.at) <= COALESCE
+                && top.before.differences(&top.after) == baseline.differences(&current)
+            {
+                top.after = current;
+                top.at = now;
+                if top.before == top.after {
+                    self.undo.pop_back();
+                }
+                return true;
+            }
+        }
+        self.undo.push_back(Step {
+            before: baseline,
+            after: current,
+            at: now,
+        });
+        if self.undo.len() > UNDO_LEN {
+            self.undo.pop_front();
+        }
+        true
+    }
+
+    /// Take the last step back: the caller puts back its `before`
+    pub fn undo(&mut self) -> Option<Step> {
+        let step = self.undo.pop_back()?;
+        self.redo.push(step);
+        self.applying(step.before);
+        Some(step)
+    }
+
+    /// Redo the last step undone: the caller puts back its `after`
+    pub fn redo(&mut self) -> Option<Step> {
+        let step = self.redo.pop()?;
+        self.undo.push_back(step);
+        self.applying(step.after);
+        Some(step)
+    }
+
+    /// Settings being put back aren't a change of the user's own
+    fn applying(&mut self, target: Settings) {
+        self.baseline = Some(target);
+        self.key_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(frequency: u32) -> Settings {
+        Settings {
+            frequency,
+            mode: DemodMode::FmNarrow,
+            gain: Some(207),
+            squelch: Some(-45.0),
+            filter_width: None,
+        }
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_user_changes_only() {
+        let start = Instant::now();
+        let mut history = UndoHistory::default();
+        assert!(!history.observe(settings(144_390_000), start));
+
+        // A key, then the change it made a moment later
+        history.key(start + ms(100));
+        assert!(history.observe(settings(162_550_000), start + ms(150)));
+
+        // The scanner moving on its own, long after any key
+        assert!(!history.observe(settings(162_400_000), start + ms(5_000)));
+        assert!(!history.undo.is_empty());
+
+        // Undo goes back to before the user's change, not the scanner's
+        let step = history.undo().unwrap();
+        assert_eq!(step.before, settings(144_390_000));
+        assert_eq!(step.after, settings(162_550_000));
+        assert!(history.undo.is_empty());
+
+        // Putting it back isn't recorded as a change
+        assert!(!history.observe(settings(144_390_000), start + ms(5_100)));
+        assert!(history.undo.is_empty());
+        assert!(!history.redo.is_empty());
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let start = Instant::now();
+        let mut history = UndoHistory::default();
+        history.observe(settings(146_000_000), start);
+
+        // Holding an arrow key: one step
+        for n in 1..=20u32 {
+            let now = start + ms(n as u64 * 50);
+            history.key(now);
+            history.observe(settings(146_000_000 + n * 12_500), now + ms(10));
+        }
+        // A gain change straight after is a step of its own
+        let now = start + ms(1_100);
+        history.key(now);
+        let mut gain = settings(146_250_000);
+        gain.gain = None;
+        history.observe(gain, now + ms(10));
+
+        let step = history.undo().unwrap();
+        assert_eq!(step.after.gain, None);
+        assert_eq!(step.before.gain, Some(207));
+        let step = history.undo().unwrap();
+        assert_eq!(step.before, settings(146_000_000));
+        assert_eq!(step.after, settings(146_250_000));
+        assert!(history.undo().is_none());
+
+        // Tuning again after a pause is a new step
+        let mut history = UndoHistory::default();
+        history.observe(settings(146_000_000), start);
+        history.key(start);
+        history.observe(settings(146_012_500), start + ms(10));
+        history.key(start + ms(3_000));
+        history.observe(settings(146_025_000), start + ms(3_010));
+        assert_eq!(history.undo().unwrap().before, settings(146_012_500));
+        assert_eq!(history.undo().unwrap().before, settings(146_000_000));
+
+        // Tuning there and straight back leaves nothing to undo
+        let mut history = UndoHistory::default();
+        history.observe(settings(146_000_000), start);
+        history.key(start);
+        history.observe(settings(146_012_500), start + ms(10));
+        history.key(start + ms(200));
+        history.observe(settings(146_000_000), start + ms(210));
+        assert!(history.undo.is_empty());
+    }
+
+    #[test]
+    fn test_redo() {
+        let start = Instant::now();
+        let mut history = UndoHistory::default();
+        history.observe(settings(144_390_000), start);
+        for (n, frequency) in [162_550_000, 118_100_000].into_iter().enumerate() {
+            let now = start + Duration::from_secs(5 * (n as u64 + 1));
+            history.key(now);
+            history.observe(settings(frequency), now + ms(20));
+        }
+
+        assert_eq!(history.undo().unwrap().before, settings(162_550_000));
+        history.observe(settings(162_550_000), start + ms(11_000));
+        assert_eq!(history.undo().unwrap().before, settings(144_390_000));
+        history.observe(settings(144_390_000), start + ms(11_500));
+        assert_eq!(history.redo().unwrap().after, settings(162_550_000));
+        history.observe(settings(162_550_000), start + ms(12_000));
+        assert!(!history.redo.is_empty());
+
+        // A change of the user's own ends what can be redone
+        let now = start + ms(20_000);
+        history.key(now);
+        history.observe(settings(146_520_000), now + ms(20));
+        assert!(history.redo.is_empty());
+        assert!(history.redo().is_none());
+        assert_eq!(history.undo().unwrap().before, settings(162_550_000));
+    }
+
+    #[test]
+    fn test_bounded() {
+        let start = Instant::now();
+        let mut history = UndoHistory::default();
+        history.observe(settings(100_000_000), start);
+        for n in 1..=(UNDO_LEN as u32 + 10) {
+            let now = start + Duration::from_secs(n as u64 * 3);
+            history.key(now);
+            history.observe(settings(100_000_000 + n * 1000), now);
+        }
+        let mut steps = 0;
+        let mut last = None;
+        while let Some(step) = history.undo() {
+            steps += 1;
+            last = Some(step.before);
+        }
+        assert_eq!(steps, UNDO_LEN);
+        // The oldest went first
+        assert_eq!(last, Some(settings(100_010_000)));
+    }
+
+    #[test]
+    fn test_describe() {
+        let from = settings(162_550_000);
+        let to = settings(144_390_000);
+        assert_eq!(from.describe(&to), "frequency 162.550 \u{2192} 144.390");
+
+        let mut to = from;
+        to.mode = DemodMode::Am;
+        to.gain = None;
+        to.squelch = None;
+        to.filter_width = Some(6_000);
+        assert_eq!(
+            from.describe(&to),
+            "mode NFM \u{2192} AM, gain 20.7 dB \u{2192} auto, squelch -45 dBFS \u{2192} off, \
+             filter default \u{2192} 6.0 kHz"
+        );
+        assert_eq!(from.describe(&from), "no change");
+    }
+}
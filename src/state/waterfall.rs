@@ -0,0 +1,263 @@
+//! How fast the waterfall moves
+//!
+//! A buffer's spectrum arrives every few milliseconds, far faster than
+//! anyone reads a waterfall, and at that pace a few hundred rows of history
+//! are gone in seconds. So a row is made of several frames: their mean
+//! power in each bin, or each bin's peak so brief bursts still show. How
+//! many frames go into a row follows from the lines per second asked for
+//! and how fast buffers arrive. The spectrum trace still shows every frame.
+
+/// Lines per second unless configured otherwise
+pub const DEFAULT_LINE_RATE: f32 = 10.0;
+/// Waterfall speeds the keys step through, in lines per second
+pub const LINE_RATES: [f32; 9] = [0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0];
+
+/// How the frames in a row are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Integration {
+    /// Mean power per bin, which steadies the noise floor
+    #[default]
+    Average,
+    /// Strongest per bin, which keeps short bursts
+    Peak,
+}
+
+impl Integration {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Integration::Average => "average",
+            Integration::Peak => "peak",
+        }
+    }
+
+    pub fn toggle(self) -> Self {
+        match self {
+            Integration::Average => Integration::Peak,
+            Integration::Peak => Integration::Average,
+        }
+    }
+}
+
+impl std::str::FromStr for Integration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "average" | "avg" | "mean" => Ok(Integration::Average),
+            "peak" | "max" => Ok(Integration::Peak),
+            _ => Err(format!(
+                "unknown waterfall integration '{}' (expected average or peak)",
+                s
+            )),
+        }
+    }
+}
+
+/// Frames per row for `lines_per_second` when frames arrive at
+/// `frames_per_second`; one each while either isn't known
+pub fn frames_per_line(lines_per_second: f32, frames_per_second: f32) -> usize {
+    if lines_per_second <= 0.0 || frames_per_second <= 0.0 {
+        return 1;
+    }
+    ((frames_per_second / lines_per_second).round() as usize).max(1)
+}
+
+/// The next speed in [`LINE_RATES`] above `current`, or below it
+pub fn step_line_rate(current: f32, faster: bool) -> f32 {
+    let next = if faster {
+        LINE_RATES.iter().copied().find(|&rate| rate > current)
+    } else {
+        LINE_RATES
+            .iter()
+            .copied()
+            .rev()
+            .find(|&rate| rate < current)
+    };
+    next.unwrap_or(current)
+}
+
+/// Frames being gathered into the next waterfall row
+#[derive(Debug, Clone, Default)]
+pub struct RowIntegrator {
+    /// The first frame in dB, then power sums for an average or dB for a
+    /// peak, so a row of one frame comes back exactly as it went in
+    bins: Vec<f32>,
+    frames: usize,
+    integration: Integration,
+}
+
+impl RowIntegrator {
+    /// Add a frame of bins in dB; once it makes `per_row` frames, the
+    /// finished row in dB, and the next row starts afresh
+    ///
+    /// A row in progress starts over if the frame size or the integration
+    /// changes under it.
+    pub fn add(
+        &mut self,
+        frame: &[f32],
+        integration: Integration,
+        per_row: usize,
+    ) -> Option<Vec<f32>> {
+        if self.frames > 0 && (frame.len() != self.bins.len() || integration != self.integration) {
+            self.reset();
+        }
+        if self.frames == 0 {
+            self.integration = integration;
+            self.bins.clear();
+            self.bins.extend_from_slice(frame);
+        } else {
+            if self.frames == 1 && integration == Integration::Average {
+                for bin in &mut self.bins {
+                    *bin = power(*bin);
+                }
+            }
+            for (bin, &db) in self.bins.iter_mut().zip(frame) {
+                match integration {
+                    Integration::Average => *bin += power(db),
+                    Integration::Peak => *bin = bin.max(db),
+                }
+            }
+        }
+        self.frames += 1;
+        if self.frames < per_row.max(1) {
+            return None;
+        }
+
+        let frames = std::mem::take(&mut self.frames) as f32;
+        let mut row = std::mem::take(&mut self.bins);
+        if integration == Integration::Average && frames > 1.0 {
+            for bin in &mut row {
+                *bin = 10.0 * (*bin / frames).max(f32::MIN_POSITIVE).log10();
+            }
+        }
+        Some(row)
+    }
+
+    /// Frames in the row so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Drop the row in progress
+    pub fn reset(&mut self) {
+        self.frames = 0;
+        self.bins.clear();
+    }
+}
+
+fn power(db: f32) -> f32 {
+    10f32.powf(db / 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(row: &[f32], expected: &[f32]) {
+        assert_eq!(row.len(), expected.len());
+        for (got, want) in row.iter().zip(expected) {
+            assert!((got - want).abs() < 1e-3, "{:?} vs {:?}", row, expected);
+        }
+    }
+
+    #[test]
+    fn test_average_is_mean_power() {
+        let mut integrator = RowIntegrator::default();
+        assert_eq!(
+            integrator.add(&[-20.0, -10.0], Integration::Average, 2),
+            None
+        );
+        assert_eq!(integrator.frames(), 1);
+        // 0.01 and 0.1 average to 0.055, not the -15 dB averaging dB gives
+        let row = integrator
+            .add(&[-10.0, -10.0], Integration::Average, 2)
+            .unwrap();
+        assert_close(&row, &[10.0 * 0.055f32.log10(), -10.0]);
+
+        // The next row starts from nothing
+        assert_eq!(integrator.frames(), 0);
+        assert_eq!(
+            integrator.add(&[-30.0, -30.0], Integration::Average, 2),
+            None
+        );
+        let row = integrator
+            .add(&[-30.0, -30.0], Integration::Average, 2)
+            .unwrap();
+        assert_close(&row, &[-30.0, -30.0]);
+    }
+
+    #[test]
+    fn test_peak_holds_maximum() {
+        let mut integrator = RowIntegrator::default();
+        assert_eq!(
+            integrator.add(&[-90.0, -40.0, -80.0], Integration::Peak, 3),
+            None
+        );
+        assert_eq!(
+            integrator.add(&[-20.0, -70.0, -80.0], Integration::Peak, 3),
+            None
+        );
+        let row = integrator
+            .add(&[-90.0, -90.0, -60.0], Integration::Peak, 3)
+            .unwrap();
+        assert_eq!(row, [-20.0, -40.0, -60.0]);
+        // Reset per row: the old peaks don't carry over
+        let row = integrator
+            .add(&[-90.0, -90.0, -90.0], Integration::Peak, 1)
+            .unwrap();
+        assert_eq!(row, [-90.0; 3]);
+    }
+
+    #[test]
+    fn test_row_restarts_on_change() {
+        let mut integrator = RowIntegrator::default();
+        integrator.add(&[0.0; 4], Integration::Peak, 3);
+        // A new FFT size, then a new integration, each start the row over
+        assert_eq!(integrator.add(&[-50.0; 2], Integration::Peak, 3), None);
+        assert_eq!(integrator.frames(), 1);
+        assert_eq!(integrator.add(&[-50.0; 2], Integration::Average, 2), None);
+        assert_eq!(integrator.frames(), 1);
+        let row = integrator
+            .add(&[-50.0; 2], Integration::Average, 2)
+            .unwrap();
+        assert_close(&row, &[-50.0, -50.0]);
+
+        integrator.add(&[0.0; 2], Integration::Average, 2);
+        integrator.reset();
+        let row = integrator
+            .add(&[-50.0; 2], Integration::Average, 1)
+            .unwrap();
+        assert_close(&row, &[-50.0, -50.0]);
+    }
+
+    #[test]
+    fn test_frames_per_line() {
+        // 16384-sample buffers at 2.4 MS/s come about 146 times a second
+        let buffers = 2_400_000.0 / 16_384.0;
+        assert_eq!(frames_per_line(10.0, buffers), 15);
+        assert_eq!(frames_per_line(1.0, buffers), 146);
+        // Never fewer than one, nor while the rate isn't known
+        assert_eq!(frames_per_line(200.0, buffers), 1);
+        assert_eq!(frames_per_line(10.0, 0.0), 1);
+        assert_eq!(frames_per_line(0.0, buffers), 1);
+    }
+
+    #[test]
+    fn test_step_line_rate() {
+        assert_eq!(step_line_rate(10.0, true), 20.0);
+        assert_eq!(step_line_rate(10.0, false), 5.0);
+        assert_eq!(step_line_rate(200.0, true), 200.0);
+        assert_eq!(step_line_rate(0.5, false), 0.5);
+        // Off the ladder, to the nearest step that way
+        assert_eq!(step_line_rate(7.0, true), 10.0);
+        assert_eq!(step_line_rate(7.0, false), 5.0);
+    }
+
+    #[test]
+    fn test_parse_integration() {
+        assert_eq!("avg".parse(), Ok(Integration::Average));
+        assert_eq!("Peak".parse(), Ok(Integration::Peak));
+        assert_eq!("max".parse(), Ok(Integration::Peak));
+        assert!("median".parse::<Integration>().is_err());
+    }
+}
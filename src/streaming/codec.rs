@@ -0,0 +1,244 @@
+//! How the audio stream is encoded
+//!
+//! One encoding for every client, chosen with `--stream-codec`. Raw and WAV
+//! send 16-bit PCM at 768 kbit/s; Opus brings that down to a few tens of
+//! kbit/s for listening over a slow link, and needs the `opus` feature.
+
+use anyhow::Result;
+
+/// Samples in an Opus frame: 20 ms at 48 kHz
+pub const OPUS_FRAME: usize = 960;
+/// Opus bitrate when none is given, in bits per second
+pub const DEFAULT_OPUS_BITRATE: u32 = 32_000;
+
+/// Encoding of the TCP audio stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamCodec {
+    /// 16-bit signed little-endian PCM with no header
+    #[default]
+    Raw,
+    /// The same PCM after a WAV header
+    Wav,
+    /// Ogg Opus, a page per 20 ms packet
+    Opus,
+}
+
+impl StreamCodec {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StreamCodec::Raw => "raw",
+            StreamCodec::Wav => "wav",
+            StreamCodec::Opus => "opus",
+        }
+    }
+
+    /// A command that plays the stream from `port` on this machine
+    pub fn receive_command(&self, port: u16) -> String {
+        match self {
+            StreamCodec::Raw => format!("nc localhost {} | aplay -r 48000 -f S16_LE -c 1", port),
+            StreamCodec::Wav | StreamCodec::Opus => format!("nc localhost {} | mpv -", port),
+        }
+    }
+
+    /// Whether this build can send the codec
+    pub fn check_supported(&self) -> Result<()> {
+        if *self == StreamCodec::Opus && !cfg!(feature = "opus") {
+            anyhow::bail!("Built without Opus support; rebuild with --features opus");
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for StreamCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "raw" | "pcm" => Ok(StreamCodec::Raw),
+            "wav" => Ok(StreamCodec::Wav),
+            "opus" => Ok(StreamCodec::Opus),
+            _ => Err(format!(
+                "unknown stream codec '{}' (expected raw, wav or opus)",
+                s
+            )),
+        }
+    }
+}
+
+/// Audio ready to send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Encoded {
+    /// The same bytes for every client
+    Bytes(Vec<u8>),
+    /// Opus packets of `OPUS_FRAME` samples, each framed per client
+    #[cfg_attr(not(feature = "opus"), allow(dead_code))]
+    Packets(Vec<Vec<u8>>),
+}
+
+/// Turns the stream's samples into `Encoded` audio, on the streaming thread
+pub enum StreamEncoder {
+    Pcm,
+    #[cfg(feature = "opus")]
+    Opus(opus::OpusEncoder),
+}
+
+impl StreamEncoder {
+    /// An encoder for `codec`; `bitrate` (bits per second) is for Opus
+    #[cfg_attr(not(feature = "opus"), allow(unused_variables))]
+    pub fn new(codec: StreamCodec, bitrate: u32) -> Result<Self> {
+        codec.check_supported()?;
+        match codec {
+            StreamCodec::Raw | StreamCodec::Wav => Ok(StreamEncoder::Pcm),
+            #[cfg(feature = "opus")]
+            StreamCodec::Opus => Ok(StreamEncoder::Opus(opus::OpusEncoder::new(bitrate)?)),
+            #[cfg(not(feature = "opus"))]
+            StreamCodec::Opus => unreachable!("checked above"),
+        }
+    }
+
+    /// Samples of encoder delay for players to skip, as the Ogg Opus header
+    /// gives it
+    pub fn pre_skip(&self) -> u16 {
+        match self {
+            StreamEncoder::Pcm => 0,
+            #[cfg(feature = "opus")]
+            StreamEncoder::Opus(encoder) => encoder.pre_skip(),
+        }
+    }
+
+    /// Encode `samples` in [-1.0, 1.0]; Opus holds back any short of a frame
+    pub fn encode(&mut self, samples: &[f32]) -> Result<Encoded> {
+        match self {
+            StreamEncoder::Pcm => Ok(Encoded::Bytes(pcm_bytes(samples))),
+            #[cfg(feature = "opus")]
+            StreamEncoder::Opus(encoder) => Ok(Encoded::Packets(encoder.encode(samples)?)),
+        }
+    }
+}
+
+/// Samples as 16-bit signed little-endian PCM
+pub fn pcm_bytes(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|&sample| ((sample.clamp(-1.0, 1.0) * 32767.0) as i16).to_le_bytes())
+        .collect()
+}
+
+#[cfg(feature = "opus")]
+mod opus {
+    use super::OPUS_FRAME;
+    use anyhow::Result;
+    use audiopus::coder::Encoder;
+    use audiopus::{Application, Bitrate, Channels, SampleRate};
+
+    /// Largest packet Opus produces
+    const MAX_PACKET: usize = 1275;
+    /// Encoder delay at 48 kHz, should the encoder not say
+    const DEFAULT_PRE_SKIP: u16 = 312;
+
+    /// Mono 48 kHz Opus, in 20 ms frames
+    pub struct OpusEncoder {
+        encoder: Encoder,
+        /// Samples waiting for a full frame
+        pending: Vec<f32>,
+    }
+
+    impl OpusEncoder {
+        pub fn new(bitrate: u32) -> Result<Self> {
+            let mut encoder =
+                Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Audio)?;
+            encoder.set_bitrate(Bitrate::BitsPerSecond(bitrate as i32))?;
+            Ok(Self {
+                encoder,
+                pending: Vec::with_capacity(OPUS_FRAME * 2),
+            })
+        }
+
+        pub fn pre_skip(&self) -> u16 {
+            self.encoder
+                .lookahead()
+                .ok()
+                .and_then(|samples| u16::try_from(samples).ok())
+                .unwrap_or(DEFAULT_PRE_SKIP)
+        }
+
+        /// A packet for each whole frame now waiting
+        pub fn encode(&mut self, samples: &[f32]) -> Result<Vec<Vec<u8>>> {
+            self.pending.extend_from_slice(samples);
+            let mut packets = Vec::new();
+            let mut packet = [0u8; MAX_PACKET];
+            let mut start = 0;
+            while self.pending.len() - start >= OPUS_FRAME {
+                let frame = &self.pending[start..start + OPUS_FRAME];
+                let len = self.encoder.encode_float(frame, &mut packet)?;
+                packets.push(packet[..len].to_vec());
+                start += OPUS_FRAME;
+            }
+            self.pending.drain(..start);
+            Ok(packets)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_codec_from_str() {
+        assert_eq!("Opus".parse(), Ok(StreamCodec::Opus));
+        assert_eq!("pcm".parse(), Ok(StreamCodec::Raw));
+        assert_eq!("wav".parse(), Ok(StreamCodec::Wav));
+        assert!("mp3".parse::<StreamCodec>().is_err());
+        assert_eq!(StreamCodec::default().name(), "raw");
+    }
+
+    #[test]
+    fn test_pcm_encoding() {
+        assert_eq!(pcm_bytes(&[0.0, 1.0, -2.0]), [0, 0, 0xff, 0x7f, 0x01, 0x80]);
+        let mut encoder = StreamEncoder::new(StreamCodec::Wav, DEFAULT_OPUS_BITRATE).unwrap();
+        assert_eq!(encoder.pre_skip(), 0);
+        assert_eq!(
+            encoder.encode(&[0.5]).unwrap(),
+            Encoded::Bytes(vec![0xff, 0x3f])
+        );
+    }
+
+    #[cfg(not(feature = "opus"))]
+    #[test]
+    fn test_opus_needs_feature() {
+        assert!(StreamCodec::Opus.check_supported().is_err());
+        assert!(StreamEncoder::new(StreamCodec::Opus, DEFAULT_OPUS_BITRATE).is_err());
+    }
+
+    #[cfg(feature = "opus")]
+    #[test]
+    fn test_opus_bitrate() {
+        // Two seconds of a warbling tone over noise, in odd-sized chunks
+        let samples: Vec<f32> = (0..96_000)
+            .map(|i| {
+                let t = i as f32 / 48_000.0;
+                let tone =
+                    (2.0 * std::f32::consts::PI * (600.0 + 200.0 * (3.0 * t).sin()) * t).sin();
+                0.4 * tone + 0.05 * (rand::random::<f32>() - 0.5)
+            })
+            .collect();
+        let mut encoder = StreamEncoder::new(StreamCodec::Opus, 32_000).unwrap();
+        assert!(encoder.pre_skip() > 0);
+        let mut packets = Vec::new();
+        for chunk in samples.chunks(4096) {
+            match encoder.encode(chunk).unwrap() {
+                Encoded::Packets(more) => packets.extend(more),
+                Encoded::Bytes(_) => panic!("expected packets"),
+            }
+        }
+        // Whole frames only; the rest waits for more samples
+        assert_eq!(packets.len(), 96_000 / OPUS_FRAME);
+
+        // About 32 kbit/s: 80 bytes a frame on average
+        let bits: usize = packets.iter().map(|p| p.len() * 8).sum();
+        let rate = bits as f64 / 2.0;
+        assert!((16_000.0..48_000.0).contains(&rate), "{} bit/s", rate);
+        assert!(packets.iter().all(|p| p.len() < 400));
+    }
+}
@@ -0,0 +1,305 @@
+//! TCP Audio Streaming Server
+//!
+//! Streams audio over TCP for remote listening, mono at 48kHz, in one of:
+//!
+//! - raw: 16-bit signed little-endian PCM,
+//!   `nc localhost <port> | aplay -r 48000 -f S16_LE -c 1`
+//! - wav: the same after a WAV header, `nc localhost <port> | mpv -`
+//! - opus: 20 ms Opus packets, each in an Ogg page that gives its length,
+//!   `nc localhost <port> | mpv -` or
+//!   `ffmpeg -i tcp://localhost:<port> out.wav`
+
+pub mod codec;
+pub mod ogg;
+pub mod pipe;
+
+use crate::recorder::wav;
+use crate::state::SharedState;
+use anyhow::{Context, Result};
+use codec::{Encoded, StreamCodec, StreamEncoder, OPUS_FRAME};
+use ogg::OggStream;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use crossbeam::channel::{Receiver, Sender};
+
+/// Audio sample rate for streaming
+pub const STREAM_SAMPLE_RATE: u32 = 48000;
+
+/// A listener, and the Ogg stream it is sent when the codec is Opus
+struct Client {
+    stream: TcpStream,
+    ogg: Option<OggStream>,
+}
+
+impl Client {
+    /// Send whatever the codec starts a stream with
+    fn start(stream: TcpStream, codec: StreamCodec, pre_skip: u16) -> std::io::Result<Self> {
+        let mut client = Self { stream, ogg: None };
+        match codec {
+            StreamCodec::Raw => {}
+            StreamCodec::Wav => client
+                .stream
+                .write_all(&wav::stream_header(STREAM_SAMPLE_RATE))?,
+            StreamCodec::Opus => {
+                let mut ogg = OggStream::new(rand::random());
+                client.stream.write_all(&ogg.headers(pre_skip))?;
+                client.ogg = Some(ogg);
+            }
+        }
+        Ok(client)
+    }
+
+    fn send(&mut self, audio: &Encoded) -> std::io::Result<()> {
+        match (audio, &mut self.ogg) {
+            (Encoded::Bytes(bytes), _) => self.stream.write_all(bytes),
+            (Encoded::Packets(packets), Some(ogg)) => {
+                let pages: Vec<u8> = packets
+                    .iter()
+                    .flat_map(|packet| ogg.packet(packet, OPUS_FRAME as u64))
+                    .collect();
+                self.stream.write_all(&pages)
+            }
+            (Encoded::Packets(_), None) => Ok(()),
+        }
+    }
+}
+
+/// Start a TCP audio streaming server on `listener` sending `codec` to
+/// every client, at `bitrate` bits per second for Opus
+///
+/// Returns a sender channel to push audio samples to stream, holding up to
+/// `queue` buffers
+pub fn start_streaming_server(
+    listener: TcpListener,
+    codec: StreamCodec,
+    bitrate: u32,
+    queue: usize,
+    state: SharedState,
+    shutdown: Arc<AtomicBool>,
+) -> Result<Sender<Vec<f32>>> {
+    codec.check_supported()?;
+    let (tx, rx) = crossbeam::channel::bounded::<Vec<f32>>(queue);
+
+    listener.set_nonblocking(true)?;
+    let port = listener.local_addr()?.port();
+
+    log::info!("Audio streaming server started on port {}", port);
+    state.write().streaming.port = Some(port);
+    log::info!("Streaming {} audio", codec.name());
+    log::info!("Connect with: {}", codec.receive_command(port));
+
+    thread::spawn(move || {
+        let mut encoder = match StreamEncoder::new(codec, bitrate) {
+            Ok(encoder) => encoder,
+            Err(e) => {
+                log::error!("Failed to start the {} encoder: {}", codec.name(), e);
+                return;
+            }
+        };
+        let mut clients: Vec<Client> = Vec::new();
+
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                break;
+            }
+
+            // Accept new connections (non-blocking)
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    log::info!("Audio client connected from {}", addr);
+                    if let Err(e) = stream.set_nonblocking(false) {
+                        log::warn!("Failed to set stream blocking: {}", e);
+                    }
+                    // Set TCP_NODELAY for lower latency
+                    if let Err(e) = stream.set_nodelay(true) {
+                        log::warn!("Failed to set TCP_NODELAY: {}", e);
+                    }
+                    match Client::start(stream, codec, encoder.pre_skip()) {
+                        Ok(client) => {
+                            clients.push(client);
+                            state.write().streaming.clients = clients.len();
+                        }
+                        Err(e) => log::info!("Client disconnected: {}", e),
+                    }
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    // No new connections, continue
+                }
+                Err(e) => {
+                    log::warn!("Accept error: {}", e);
+                }
+            }
+
+            // Receive audio samples
+            match rx.recv_timeout(std::time::Duration::from_millis(10)) {
+                Ok(samples) => {
+                    let audio = match encoder.encode(&samples) {
+                        Ok(audio) => audio,
+                        Err(e) => {
+                            log::warn!("Audio encoding failed: {}", e);
+                            continue;
+                        }
+                    };
+
+                    // Send to all connected clients
+                    let connected = clients.len();
+                    clients.retain_mut(|client| {
+                        match client.send(&audio) {
+                            Ok(_) => true,
+                            Err(e) => {
+                                log::info!("Client disconnected: {}", e);
+                                false
+                            }
+                        }
+                    });
+                    if clients.len() != connected {
+                        state.write().streaming.clients = clients.len();
+                    }
+                }
+                Err(crossbeam::channel::RecvTimeoutError::Timeout) => {
+                    continue;
+                }
+                Err(crossbeam::channel::RecvTimeoutError::Disconnected) => {
+                    log::info!("Audio stream channel disconnected");
+                    break;
+                }
+            }
+        }
+
+        state.write().streaming.clients = 0;
+        log::info!("Streaming server stopped");
+    });
+
+    Ok(tx)
+}
+
+/// Start a TCP server on `listener` sending NMEA sentences (e.g. `!AIVDM`)
+/// to every client
+///
+/// Chart plotters such as OpenCPN connect to it as a TCP NMEA data source.
+/// Returns a sender for complete sentences (without line endings).
+pub fn start_nmea_server(
+    listener: TcpListener,
+    shutdown: Arc<AtomicBool>,
+) -> Result<Sender<String>> {
+    start_line_output("NMEA", Some(listener), None, shutdown)
+}
+
+/// Start a thread sending lines of text to every client of a TCP server on
+/// `listener` and appending them to the file `log`, where given
+///
+/// `kind` names the lines in the log. Clients get CRLF line endings, as
+/// NMEA requires, the file plain newlines. Returns a sender for complete
+/// lines (without line endings).
+pub fn start_line_output(
+    kind: &'static str,
+    listener: Option<TcpListener>,
+    log: Option<&Path>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<Sender<String>> {
+    let (tx, rx) = crossbeam::channel::bounded::<String>(256);
+
+    if let Some(listener) = &listener {
+        listener.set_nonblocking(true)?;
+        log::info!("{} server started on port {}", kind, listener.local_addr()?.port());
+    }
+    let mut file = match log {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            log::info!("{} lines appended to {}", kind, path.display());
+            Some(BufWriter::new(file))
+        }
+        None => None,
+    };
+
+    thread::spawn(move || {
+        let mut clients: Vec<TcpStream> = Vec::new();
+
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                break;
+            }
+
+            match listener.as_ref().map(TcpListener::accept) {
+                Some(Ok((stream, addr))) => {
+                    log::info!("{} client connected from {}", kind, addr);
+                    if let Err(e) = stream.set_nonblocking(false) {
+                        log::warn!("Failed to set stream blocking: {}", e);
+                    }
+                    clients.push(stream);
+                }
+                Some(Err(ref e)) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Some(Err(e)) => {
+                    log::warn!("Accept error: {}", e);
+                }
+                None => {}
+            }
+
+            match rx.recv_timeout(std::time::Duration::from_millis(100)) {
+                Ok(line) => {
+                    let sent = format!("{}\r\n", line);
+                    clients.retain_mut(|client| match client.write_all(sent.as_bytes()) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            log::info!("{} client disconnected: {}", kind, e);
+                            false
+                        }
+                    });
+                    if let Some(writer) = &mut file {
+                        if let Err(e) = writeln!(writer, "{}", line).and_then(|()| writer.flush()) {
+                            log::error!("{} log: {}", kind, e);
+                            file = None;
+                        }
+                    }
+                }
+                Err(crossbeam::channel::RecvTimeoutError::Timeout) => continue,
+                Err(crossbeam::channel::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        log::info!("{} output stopped", kind);
+    });
+
+    Ok(tx)
+}
+
+/// Audio streaming sink that sends samples to the TCP server
+pub struct StreamingSink {
+    tx: Sender<Vec<f32>>,
+    buffer: Vec<f32>,
+    buffer_size: usize,
+}
+
+impl StreamingSink {
+    pub fn new(tx: Sender<Vec<f32>>) -> Self {
+        Self {
+            tx,
+            buffer: Vec::with_capacity(4096),
+            buffer_size: 4096, // Buffer ~85ms at 48kHz
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.buffer.push(sample);
+
+        if self.buffer.len() >= self.buffer_size {
+            let _ = self.tx.try_send(std::mem::take(&mut self.buffer));
+            self.buffer = Vec::with_capacity(self.buffer_size);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            let _ = self.tx.try_send(std::mem::take(&mut self.buffer));
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Ogg framing for the Opus stream (RFC 3533, RFC 7845)
+//!
+//! Each Opus packet goes out in a page of its own, so a page is a small
+//! header, the packet's length as a run of lacing bytes, then the packet.
+//! Every client gets a stream of its own, starting with the two header
+//! pages, so players such as ffmpeg and mpv can join at any time.
+
+/// Page header flag: first page of the stream
+const BEGIN_OF_STREAM: u8 = 0x02;
+/// Reported to players as the encoder
+const VENDOR: &str = concat!("rtl-sdr-tui ", env!("CARGO_PKG_VERSION"));
+
+/// One client's Ogg Opus stream
+#[derive(Debug, Clone)]
+pub struct OggStream {
+    serial: u32,
+    /// Next page's sequence number
+    sequence: u32,
+    /// 48 kHz samples in the pages so far
+    granule: u64,
+}
+
+impl OggStream {
+    pub fn new(serial: u32) -> Self {
+        Self {
+            serial,
+            sequence: 0,
+            granule: 0,
+        }
+    }
+
+    /// The identification and comment header pages the stream starts with,
+    /// for `pre_skip` samples of encoder delay
+    pub fn headers(&mut self, pre_skip: u16) -> Vec<u8> {
+        let mut pages = self.page(BEGIN_OF_STREAM, &opus_head(pre_skip));
+        pages.extend(self.page(0, &opus_tags()));
+        pages
+    }
+
+    /// A page holding `packet`, which decodes to `samples` samples at 48 kHz
+    pub fn packet(&mut self, packet: &[u8], samples: u64) -> Vec<u8> {
+        self.granule += samples;
+        self.page(0, packet)
+    }
+
+    fn page(&mut self, flags: u8, packet: &[u8]) -> Vec<u8> {
+        let page = page(flags, self.granule, self.serial, self.sequence, packet);
+        self.sequence += 1;
+        page
+    }
+}
+
+/// An Ogg page holding the whole of `packet`, which must be shorter than
+/// 255 × 255 bytes
+pub fn page(flags: u8, granule: u64, serial: u32, sequence: u32, packet: &[u8]) -> Vec<u8> {
+    let lacing = lacing(packet.len());
+    let mut page = Vec::with_capacity(27 + lacing.len() + packet.len());
+    page.extend_from_slice(b"OggS");
+    page.push(0); // version
+    page.push(flags);
+    page.extend_from_slice(&granule.to_le_bytes());
+    page.extend_from_slice(&serial.to_le_bytes());
+    page.extend_from_slice(&sequence.to_le_bytes());
+    page.extend_from_slice(&[0; 4]); // checksum, filled in below
+    page.push(lacing.len() as u8);
+    page.extend_from_slice(&lacing);
+    page.extend_from_slice(packet);
+    let checksum = crc(&page);
+    page[22..26].copy_from_slice(&checksum.to_le_bytes());
+    page
+}
+
+/// Segment table for a packet of `len` bytes: 255 for each full segment,
+/// then what's left, which is 0 when `len` is a multiple of 255
+fn lacing(len: usize) -> Vec<u8> {
+    let mut table = vec![255; len / 255];
+    table.push((len % 255) as u8);
+    table
+}
+
+/// The Ogg checksum: CRC-32 with polynomial 0x04c11db7, no reflection,
+/// starting from 0
+fn crc(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u32) << 24), |crc, _| {
+            if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Identification header for mono 48 kHz Opus
+fn opus_head(pre_skip: u16) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1); // version
+    head.push(1); // channels
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&48_000u32.to_le_bytes()); // input sample rate
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // channel mapping family: mono or stereo
+    head
+}
+
+/// Comment header naming the encoder, without comments
+fn opus_tags() -> Vec<u8> {
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    tags.extend_from_slice(VENDOR.as_bytes());
+    tags.extend_from_slice(&0u32.to_le_bytes());
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_crc() {
+        // The CRC-32/POSIX check value, without its final inversion
+        assert_eq!(crc(b"123456789"), !0x765e_7680);
+        assert_eq!(crc(b""), 0);
+    }
+
+    #[test]
+    fn test_lacing() {
+        assert_eq!(lacing(0), [0]);
+        assert_eq!(lacing(80), [80]);
+        assert_eq!(lacing(255), [255, 0]);
+        assert_eq!(lacing(600), [255, 255, 90]);
+    }
+
+    #[test]
+    fn test_page_layout() {
+        let packet = [7u8; 300];
+        let page = page(0, 1920, 0xdead_beef, 5, &packet);
+        assert_eq!(&page[..4], b"OggS");
+        assert_eq!(u64::from_le_bytes(page[6..14].try_into().unwrap()), 1920);
+        assert_eq!(u32_at(&page, 14), 0xdead_beef);
+        assert_eq!(u32_at(&page, 18), 5);
+        // Two segments give the packet's length, then the packet
+        assert_eq!(page[26..29], [2, 255, 45]);
+        assert_eq!(page[29..], packet);
+
+        // The checksum covers the page with its own field zeroed
+        let mut zeroed = page.clone();
+        zeroed[22..26].fill(0);
+        assert_eq!(u32_at(&page, 22), crc(&zeroed));
+    }
+
+    #[test]
+    fn test_stream_pages() {
+        let mut stream = OggStream::new(42);
+        let headers = stream.headers(312);
+
+        // OpusHead on the first page, marked as the start
+        assert_eq!(headers[5], BEGIN_OF_STREAM);
+        assert_eq!(headers[26..28], [1, 19]);
+        let head = &headers[28..47];
+        assert_eq!(&head[..8], b"OpusHead");
+        assert_eq!(head[8..12], [1, 1, 0x38, 0x01]);
+        assert_eq!(u32_at(head, 12), 48_000);
+
+        // Then OpusTags
+        let tags = &headers[47..];
+        assert_eq!(u32_at(tags, 18), 1);
+        assert_eq!(&tags[28..36], b"OpusTags");
+        assert!(tags.ends_with(&[0; 4]));
+
+        // Audio pages count on from there, with the samples so far
+        let first = stream.packet(&[1, 2, 3], 960);
+        let second = stream.packet(&[4, 5], 960);
+        assert_eq!((u32_at(&first, 18), u32_at(&second, 18)), (2, 3));
+        assert_eq!(u64::from_le_bytes(second[6..14].try_into().unwrap()), 1920);
+        assert_eq!(first[5], 0);
+        assert_eq!(second[26..], [1, 2, 4, 5]);
+    }
+}
@@ -0,0 +1,386 @@
+//! Audio to a named pipe or stdout, for external decoders
+//!
+//! multimon-ng, direwolf and the like read raw audio on stdin. `--audio-pipe`
+//! writes the stream as 48 kHz 16-bit little-endian mono PCM to a FIFO, made
+//! if it doesn't exist, or to stdout with `-`. The DSP thread only queues
+//! buffers, so a reader that stalls costs audio, never the receiver: buffers
+//! are dropped when the queue or the pipe is full. A FIFO is written without
+//! blocking; when its reader goes away it is reopened once one is back, so a
+//! decoder can be restarted without restarting the receiver.
+
+use super::codec::pcm_bytes;
+use anyhow::{Context, Result};
+use crossbeam::channel::Sender;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a FIFO without a reader is tried again
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where `--audio-pipe` sends the audio
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipeTarget {
+    Stdout,
+    Fifo(PathBuf),
+}
+
+impl PipeTarget {
+    /// The target for an `--audio-pipe` argument: `-` is stdout
+    pub fn from_arg(path: &Path) -> Self {
+        if path == Path::new("-") {
+            PipeTarget::Stdout
+        } else {
+            PipeTarget::Fifo(path.to_path_buf())
+        }
+    }
+}
+
+/// What became of a buffer written to a FIFO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+    /// Taken by the pipe, possibly with a tail left to go first next time
+    Written,
+    /// Dropped as nothing is reading
+    NoReader,
+    /// Dropped as the reader isn't keeping up
+    Full,
+}
+
+/// Non-blocking writer to a FIFO that reconnects when a reader comes back
+pub struct FifoWriter {
+    path: PathBuf,
+    file: Option<File>,
+    /// The part of a buffer the pipe didn't take, sent before anything else
+    /// so samples stay whole
+    pending: Vec<u8>,
+    /// When to next try opening the FIFO while there is no reader
+    retry_at: Option<Instant>,
+    /// Buffers dropped so far
+    dropped: u64,
+}
+
+impl FifoWriter {
+    /// Make the FIFO at `path` if there isn't one; it is opened when written
+    pub fn create(path: &Path) -> Result<Self> {
+        sys::make_fifo(path)
+            .with_context(|| format!("Can't use {} as an audio pipe", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: None,
+            pending: Vec::new(),
+            retry_at: None,
+            dropped: 0,
+        })
+    }
+
+    /// Buffers dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Write one buffer without blocking, as of `now`
+    pub fn write(&mut self, bytes: &[u8], now: Instant) -> WriteOutcome {
+        let outcome = self.try_write(bytes, now);
+        if outcome != WriteOutcome::Written {
+            self.dropped += 1;
+        }
+        outcome
+    }
+
+    fn try_write(&mut self, bytes: &[u8], now: Instant) -> WriteOutcome {
+        if self.file.is_none() && !self.connect(now) {
+            return WriteOutcome::NoReader;
+        }
+        let Some(file) = self.file.as_mut() else {
+            return WriteOutcome::NoReader;
+        };
+
+        let result = write_available(file, &self.pending).and_then(|sent| {
+            self.pending.drain(..sent);
+            if !self.pending.is_empty() {
+                return Ok(WriteOutcome::Full);
+            }
+            let sent = write_available(file, bytes)?;
+            if sent == 0 && !bytes.is_empty() {
+                return Ok(WriteOutcome::Full);
+            }
+            self.pending.extend_from_slice(&bytes[sent..]);
+            Ok(WriteOutcome::Written)
+        });
+        match result {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                log::info!("Audio pipe {} reader went away: {}", self.path.display(), e);
+                self.file = None;
+                self.pending.clear();
+                // A new reader may already be waiting
+                self.retry_at = None;
+                WriteOutcome::NoReader
+            }
+        }
+    }
+
+    /// Open the FIFO if a reader is there; tried at most once a second
+    fn connect(&mut self, now: Instant) -> bool {
+        if self.retry_at.is_some_and(|at| now < at) {
+            return false;
+        }
+        match sys::open_writer(&self.path) {
+            Ok(Some(file)) => {
+                log::info!("Audio pipe {} reader attached", self.path.display());
+                self.file = Some(file);
+                self.retry_at = None;
+                true
+            }
+            Ok(None) => {
+                self.retry_at = Some(now + RETRY_INTERVAL);
+                false
+            }
+            Err(e) => {
+                if self.retry_at.is_none() {
+                    log::warn!("Can't open audio pipe {}: {}", self.path.display(), e);
+                }
+                self.retry_at = Some(now + RETRY_INTERVAL);
+                false
+            }
+        }
+    }
+}
+
+/// Write as much of `bytes` as the pipe takes without blocking
+fn write_available(file: &mut File, bytes: &[u8]) -> io::Result<usize> {
+    let mut sent = 0;
+    while sent < bytes.len() {
+        match file.write(&bytes[sent..]) {
+            Ok(0) => break,
+            Ok(n) => sent += n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(sent)
+}
+
+/// Start a thread writing the audio stream to `target`
+///
+/// Returns a sender for up to `queue` audio buffers; the DSP thread never
+/// waits on it.
+pub fn start_audio_pipe(
+    target: PipeTarget,
+    queue: usize,
+    shutdown: Arc<AtomicBool>,
+) -> Result<Sender<Vec<f32>>> {
+    let mut fifo = match &target {
+        PipeTarget::Stdout => None,
+        PipeTarget::Fifo(path) => Some(FifoWriter::create(path)?),
+    };
+    let (tx, rx) = crossbeam::channel::bounded::<Vec<f32>>(queue);
+    match &target {
+        PipeTarget::Stdout => log::info!("Writing 48 kHz S16LE audio to stdout"),
+        PipeTarget::Fifo(path) => log::info!("Writing 48 kHz S16LE audio to {}", path.display()),
+    }
+
+    thread::spawn(move || {
+        let mut stdout = io::stdout().lock();
+        while !shutdown.load(Ordering::Relaxed) {
+            let samples = match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(samples) => samples,
+                Err(crossbeam::channel::RecvTimeoutError::Timeout) => continue,
+                Err(crossbeam::channel::RecvTimeoutError::Disconnected) => break,
+            };
+            let bytes = pcm_bytes(&samples);
+            match fifo.as_mut() {
+                Some(fifo) => {
+                    fifo.write(&bytes, Instant::now());
+                }
+                // Stdout can't get a new reader, so a broken pipe ends it
+                None => {
+                    if let Err(e) = stdout.write_all(&bytes).and_then(|_| stdout.flush()) {
+                        log::warn!("Audio to stdout stopped: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+        if let Some(fifo) = fifo.filter(|fifo| fifo.dropped() > 0) {
+            log::info!("Audio pipe dropped {} buffers", fifo.dropped());
+        }
+        log::info!("Audio pipe stopped");
+    });
+
+    Ok(tx)
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::CString;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+    use std::path::Path;
+
+    /// Make a FIFO at `path`, unless there already is one
+    pub fn make_fifo(path: &Path) -> io::Result<()> {
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.file_type().is_fifo() => return Ok(()),
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "not a named pipe",
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Open the FIFO for non-blocking writes; None while nothing reads it
+    pub fn open_writer(path: &Path) -> io::Result<Option<File>> {
+        match OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+        {
+            Ok(file) => Ok(Some(file)),
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::path::Path;
+
+    pub fn make_fifo(_path: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "named pipes need a Unix system; use --audio-pipe - for stdout",
+        ))
+    }
+
+    pub fn open_writer(_path: &Path) -> io::Result<Option<File>> {
+        Ok(None)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+
+    fn temp_fifo(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("rtl-sdr-tui-pipe-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Attach a reader that never blocks
+    fn reader(path: &Path) -> File {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .unwrap()
+    }
+
+    /// Everything waiting in the pipe
+    fn read_all(reader: &mut File) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut chunk = [0u8; 8192];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => bytes.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_reconnects_when_reader_returns() {
+        let path = temp_fifo("reconnect");
+        let mut fifo = FifoWriter::create(&path).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().file_type().is_fifo());
+        let start = Instant::now();
+
+        // Nothing reading: dropped, and not tried again for a while
+        assert_eq!(fifo.write(&[1, 2], start), WriteOutcome::NoReader);
+        let mut first = reader(&path);
+        assert_eq!(fifo.write(&[3, 4], start), WriteOutcome::NoReader);
+        assert_eq!(
+            fifo.write(&[5, 6], start + RETRY_INTERVAL),
+            WriteOutcome::Written
+        );
+        assert_eq!(read_all(&mut first), [5, 6]);
+
+        // The reader goes away and another takes its place
+        drop(first);
+        assert_eq!(
+            fifo.write(&[7, 8], start + RETRY_INTERVAL),
+            WriteOutcome::NoReader
+        );
+        let mut second = reader(&path);
+        assert_eq!(
+            fifo.write(&[9, 10], start + RETRY_INTERVAL),
+            WriteOutcome::Written
+        );
+        assert_eq!(read_all(&mut second), [9, 10]);
+        assert_eq!(fifo.dropped(), 3);
+
+        // An existing FIFO is reused; anything else isn't touched
+        assert!(FifoWriter::create(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, b"data").unwrap();
+        assert!(FifoWriter::create(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_drops_when_reader_stalls() {
+        let path = temp_fifo("stall");
+        let mut fifo = FifoWriter::create(&path).unwrap();
+        let mut reader = reader(&path);
+        let now = Instant::now();
+
+        // Write numbered buffers of an odd length until the pipe is full
+        let buffer = |n: u8| vec![n; 1001];
+        let mut written = Vec::new();
+        for n in 0..=255u8 {
+            if fifo.write(&buffer(n), now) == WriteOutcome::Written {
+                written.push(n);
+            }
+        }
+        assert!(fifo.dropped() > 0);
+        assert!(!written.is_empty());
+
+        // Once the reader catches up, the next buffer goes after the part
+        // left over, so only whole buffers arrive
+        let mut received = read_all(&mut reader);
+        assert_eq!(fifo.write(&buffer(0), now), WriteOutcome::Written);
+        written.push(0);
+        received.extend(read_all(&mut reader));
+        let expected: Vec<u8> = written.iter().flat_map(|&n| buffer(n)).collect();
+        assert_eq!(received, expected);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
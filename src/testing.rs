@@ -0,0 +1,445 @@
+//! The receiver end to end, on signals made up for the purpose
+//!
+//! [`Harness`] runs the SDR command thread and the DSP thread as the app
+//! does, over a [`MockSdrSource`] playing buffers from a [`Signal`]
+//! generator, with a [`Capture`] where the speaker would be. Tests drive it
+//! with [`Command`]s and look at what the speaker got and what the state
+//! shows.
+//!
+//! [`temp_dir`] gives tests a scratch directory; the binary mounts the same
+//! file as its own `testing` module.
+
+use crate::audio::latency::AUDIO_RATE;
+use crate::dsp::decoder::decoder_channel;
+use crate::dsp::decoder::thread::DecoderReceiver;
+use crate::dsp::{start_dsp_thread, AudioSink, DspOutputs};
+use crate::recorder::RecorderEvent;
+use crate::sdr::{start_sdr_thread, Capabilities, SampleSink, SdrSource};
+use crate::state::{AppState, SharedState};
+use crate::types::{Command, DemodMode};
+use anyhow::Result;
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender};
+use num_complex::Complex;
+use parking_lot::Mutex;
+use std::f64::consts::TAU;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+mod files;
+
+pub use files::temp_dir;
+
+/// IQ rate the harness runs at, one a dongle runs at
+pub const RATE: u32 = 1_024_000;
+/// Samples per buffer, a tenth of a second
+pub const BUFFER_LEN: usize = RATE as usize / 10;
+/// Audio heard for each buffer
+pub const AUDIO_LEN: usize = AUDIO_RATE as usize / 10;
+/// Longest anything is waited for
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How a [`Signal`] carries its tone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Modulation {
+    /// Frequency modulation, deviating this many Hz either way
+    Fm { deviation: f64 },
+    /// Amplitude modulation to this depth, 0-1
+    Am { depth: f64 },
+    /// Upper sideband, carrier suppressed: the tone's frequency above it
+    Usb,
+}
+
+/// A carrier `offset` Hz from the center, modulated by a tone
+#[derive(Debug, Clone)]
+pub struct Signal {
+    pub offset: f64,
+    pub tone: f64,
+    pub modulation: Modulation,
+    /// Carrier amplitude, full scale being 1
+    pub amplitude: f32,
+    sample_rate: u32,
+    /// Samples made so far
+    made: u64,
+    /// Carrier phase in cycles, carried on so buffers join up
+    phase: f64,
+}
+
+impl Signal {
+    /// At half full scale
+    pub fn new(sample_rate: u32, offset: f64, tone: f64, modulation: Modulation) -> Self {
+        Self {
+            offset,
+            tone,
+            modulation,
+            amplitude: 0.5,
+            sample_rate,
+            made: 0,
+            phase: 0.0,
+        }
+    }
+
+    pub fn with_amplitude(mut self, amplitude: f32) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+
+    /// The next `len` samples
+    pub fn generate(&mut self, len: usize) -> Vec<Complex<f32>> {
+        let rate = self.sample_rate as f64;
+        (0..len)
+            .map(|_| {
+                let tone = (TAU * self.tone * self.made as f64 / rate).sin();
+                self.made += 1;
+                let (frequency, envelope) = match self.modulation {
+                    Modulation::Fm { deviation } => (self.offset + deviation * tone, 1.0),
+                    Modulation::Am { depth } => (self.offset, 1.0 + depth * tone),
+                    Modulation::Usb => (self.offset + self.tone, 1.0),
+                };
+                let sample = Complex::from_polar(
+                    self.amplitude * envelope as f32,
+                    (TAU * self.phase) as f32,
+                );
+                self.phase = (self.phase + frequency / rate).rem_euclid(1.0);
+                sample
+            })
+            .collect()
+    }
+}
+
+/// A receiver that streams whatever buffers are sent to its script, as a
+/// dongle streams what it hears
+pub struct MockSdrSource {
+    capabilities: Capabilities,
+    script: Option<Receiver<Vec<Complex<f32>>>>,
+}
+
+impl MockSdrSource {
+    pub fn new(script: Receiver<Vec<Complex<f32>>>) -> Self {
+        Self {
+            capabilities: Capabilities::rtl(),
+            script: Some(script),
+        }
+    }
+}
+
+impl SdrSource for MockSdrSource {
+    fn describe(&self) -> String {
+        "mock SDR".to_string()
+    }
+
+    fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    fn set_frequency(&mut self, _freq: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_sample_rate(&mut self, _rate: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_gain(&mut self, _gain: Option<i32>) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_ppm(&mut self, _ppm: i32) -> Result<()> {
+        Ok(())
+    }
+
+    fn start(&mut self, sink: SampleSink) -> Result<JoinHandle<()>> {
+        let script = self.script.take().expect("started once");
+        Ok(thread::spawn(move || {
+            while !sink.is_shutdown() {
+                match script.recv_timeout(Duration::from_millis(50)) {
+                    Ok(buffer) => sink.push(buffer),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        }))
+    }
+}
+
+/// Stands in for the speaker, keeping everything it is given
+#[derive(Debug, Clone, Default)]
+pub struct Capture(Arc<Mutex<Vec<f32>>>);
+
+impl Capture {
+    /// Samples kept so far
+    pub fn count(&self) -> usize {
+        self.0.lock().len()
+    }
+
+    /// Everything kept, leaving none
+    pub fn take(&self) -> Vec<f32> {
+        std::mem::take(&mut self.0.lock())
+    }
+}
+
+impl AudioSink for Capture {
+    fn play(&mut self, sample: f32) {
+        self.0.lock().push(sample);
+    }
+
+    fn queued(&self) -> usize {
+        0
+    }
+}
+
+/// The SDR and DSP threads over a [`MockSdrSource`], heard by a [`Capture`]
+pub struct Harness {
+    pub state: SharedState,
+    commands: Sender<Command>,
+    script: Option<Sender<Vec<Complex<f32>>>>,
+    audio: Capture,
+    shutdown: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    // Held so the decoder taps and the recorder have somewhere to send
+    _decoders: [DecoderReceiver; 2],
+    _recorder: Receiver<RecorderEvent>,
+}
+
+impl Harness {
+    /// A receiver at [`RATE`] in `mode`
+    pub fn start(mode: DemodMode) -> Self {
+        let state = AppState::new_shared();
+        {
+            let mut state = state.write();
+            state.sdr.sample_rate = RATE;
+            // The signals are made at the one rate
+            state.sdr.auto_rate = false;
+            state.decoder.switch_mode(mode);
+        }
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (script_tx, script_rx) = channel::unbounded();
+        let (samples_tx, samples_rx) = channel::unbounded();
+        let (command_tx, command_rx) = channel::unbounded();
+        let (recorder_tx, recorder_rx) = channel::unbounded();
+
+        let stream = start_sdr_thread(
+            Box::new(MockSdrSource::new(script_rx)),
+            None,
+            state.clone(),
+            samples_tx,
+            command_rx,
+            recorder_tx.clone(),
+            shutdown.clone(),
+        )
+        .expect("the mock source starts");
+
+        let audio = Capture::default();
+        let (decoder_tap, decoder_rx) = decoder_channel();
+        let (channel_b_tap, channel_b_rx) = decoder_channel();
+        let outputs = DspOutputs {
+            audio: Some(audio.clone()),
+            audible: Arc::new(AtomicBool::new(false)),
+            speaker_rate: Arc::new(AtomicU32::new(AUDIO_RATE)),
+            streams: Vec::new(),
+            decoder_tap,
+            channel_b_tap,
+            recorder_tx,
+        };
+        let dsp = start_dsp_thread(state.clone(), samples_rx, outputs, shutdown.clone());
+
+        Self {
+            state,
+            commands: command_tx,
+            script: Some(script_tx),
+            audio,
+            shutdown,
+            threads: vec![stream, dsp],
+            _decoders: [decoder_rx, channel_b_rx],
+            _recorder: recorder_rx,
+        }
+    }
+
+    /// Send `command` as the UI would
+    pub fn command(&self, command: Command) {
+        self.commands
+            .send(command)
+            .expect("the command thread is running");
+    }
+
+    /// Wait until `done` is true of the state
+    pub fn wait_for(&self, done: impl Fn(&AppState) -> bool) {
+        wait(|| done(&self.state.read()));
+    }
+
+    /// Stream a buffer, without waiting for it to be heard
+    pub fn feed(&self, buffer: Vec<Complex<f32>>) {
+        let script = self.script.as_ref().expect("the script is open");
+        script.send(buffer).expect("the mock source is streaming");
+    }
+
+    /// Play `buffers` of `signal`, each once the one before has been
+    /// heard; what the speaker got
+    ///
+    /// The mode must have audio, or nothing is ever heard.
+    pub fn play(&self, signal: &mut Signal, buffers: usize) -> Vec<f32> {
+        self.audio.take();
+        for _ in 0..buffers {
+            let heard = self.audio.count();
+            self.feed(signal.generate(BUFFER_LEN));
+            // The FM discriminator makes one sample fewer than it is given
+            wait(|| self.audio.count() >= heard + AUDIO_LEN - 1);
+        }
+        self.audio.take()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        self.script = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn wait(mut done: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !done() {
+        assert!(start.elapsed() < TIMEOUT, "timed out");
+        thread::sleep(Duration::from_millis(2));
+    }
+}
+
+/// Amplitude of the part of `audio` at `frequency`, by correlation
+pub fn tone_level(audio: &[f32], frequency: f64, sample_rate: u32) -> f64 {
+    let step = TAU * frequency / sample_rate as f64;
+    let (mut re, mut im) = (0.0, 0.0);
+    for (n, &sample) in audio.iter().enumerate() {
+        let (sin, cos) = (step * n as f64).sin_cos();
+        re += sample as f64 * cos;
+        im += sample as f64 * sin;
+    }
+    2.0 * (re * re + im * im).sqrt() / audio.len() as f64
+}
+
+pub fn rms(audio: &[f32]) -> f64 {
+    let power: f64 = audio.iter().map(|&x| x as f64 * x as f64).sum();
+    (power / audio.len() as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Chain;
+
+    /// Buffers given to the filters to settle after a change
+    const SETTLE: usize = 3;
+
+    /// A harness in `mode` with chain A moved `offset` Hz off the center,
+    /// clear of the DC notch
+    fn tuned(mode: DemodMode, offset: i32) -> Harness {
+        let harness = Harness::start(mode);
+        harness.command(Command::SetChannelOffset(Chain::A, offset));
+        harness.wait_for(|state| state.channels.offset_a == offset);
+        harness
+    }
+
+    #[test]
+    fn test_nfm_tone() {
+        let harness = tuned(DemodMode::FmNarrow, 10_000);
+        let fm = Modulation::Fm { deviation: 3_000.0 };
+        let mut signal = Signal::new(RATE, 10_000.0, 1_000.0, fm);
+        harness.play(&mut signal, SETTLE);
+        let audio = harness.play(&mut signal, 10);
+        assert!(harness.state.read().decoder.squelch_open);
+        // Brought down from the IQ rate: a second of audio for a second of
+        // IQ, but for the sample a buffer the discriminator drops
+        assert!(
+            (10 * AUDIO_LEN - 10..=10 * AUDIO_LEN).contains(&audio.len()),
+            "{}",
+            audio.len()
+        );
+
+        // The channel is demodulated at 64 kHz, where 3 kHz of deviation in
+        // the 32 kHz either side of its center reads as 3/32 of full scale,
+        // less 5% lost to the channel filter and the audio filters; 50µs
+        // de-emphasis (one pole at 3.2 kHz) takes another 5% off at 1 kHz,
+        // for about 0.085. Measured at the audio rate, so the tone is only
+        // found there if that is the rate it came out at
+        let level = tone_level(&audio, 1_000.0, AUDIO_RATE);
+        assert!((0.080..0.090).contains(&level), "{}", level);
+        // And the tone is nearly all there is
+        assert!(rms(&audio) < 1.1 * level / 2f64.sqrt(), "{}", rms(&audio));
+        assert!(tone_level(&audio, 2_000.0, AUDIO_RATE) < level / 100.0);
+    }
+
+    #[test]
+    fn test_mode_switch() {
+        let harness = tuned(DemodMode::FmNarrow, 8_000);
+        let am = Modulation::Am { depth: 0.5 };
+        let mut signal = Signal::new(RATE, 8_000.0, 1_000.0, am);
+        harness.play(&mut signal, SETTLE);
+        let fm = harness.play(&mut signal, 5);
+
+        harness.command(Command::SetMode(DemodMode::Am));
+        harness.wait_for(|state| state.decoder.mode == DemodMode::Am);
+        harness.play(&mut signal, SETTLE);
+        let am = harness.play(&mut signal, 5);
+        // The FM demodulator hears nothing in an AM signal; the AM one hears
+        // the tone at the modulation depth
+        assert!(rms(&fm) < 1e-3, "{}", rms(&fm));
+        let level = tone_level(&am, 1_000.0, AUDIO_RATE);
+        assert!((level - 0.25).abs() < 0.01, "{}", level);
+
+        // Sideband: nothing to hear in its steady envelope until the
+        // demodulator changes
+        let mut signal = Signal::new(RATE, 8_000.0, 1_000.0, Modulation::Usb);
+        harness.play(&mut signal, SETTLE);
+        let am = harness.play(&mut signal, 5);
+        harness.command(Command::SetMode(DemodMode::Usb));
+        harness.wait_for(|state| state.decoder.mode == DemodMode::Usb);
+        harness.play(&mut signal, SETTLE);
+        let usb = harness.play(&mut signal, 5);
+        assert!(rms(&am) < 1e-3, "{}", rms(&am));
+        assert!(rms(&usb) > 0.1, "{}", rms(&usb));
+    }
+
+    #[test]
+    fn test_squelch() {
+        let harness = tuned(DemodMode::FmNarrow, 10_000);
+        harness.state.write().decoder.squelch_level = Some(-40.0);
+        let fm = Modulation::Fm { deviation: 3_000.0 };
+        let mut weak = Signal::new(RATE, 10_000.0, 1_000.0, fm).with_amplitude(0.005);
+        harness.play(&mut weak, SETTLE);
+        let muted = harness.play(&mut weak, 5);
+        // At -46 dBFS: closed, and silent
+        {
+            let decoder = &harness.state.read().decoder;
+            assert!(
+                (decoder.signal_level + 46.0).abs() < 0.5,
+                "{}",
+                decoder.signal_level
+            );
+            assert!(!decoder.squelch_open);
+        }
+        assert!(muted.iter().all(|&x| x == 0.0));
+
+        let mut strong = Signal::new(RATE, 10_000.0, 1_000.0, fm);
+        harness.play(&mut strong, SETTLE);
+        let open = harness.play(&mut strong, 5);
+        assert!(harness.state.read().decoder.squelch_open);
+        assert!(tone_level(&open, 1_000.0, AUDIO_RATE) > 0.04);
+    }
+
+    #[test]
+    fn test_spectrum_peak() {
+        // 2048 bins over 1.024 MHz, the center in bin 1024: 500 Hz a bin
+        let harness = Harness::start(DemodMode::FmNarrow);
+        for (offset, bin) in [(6_000.0, 1036), (-15_000.0, 994)] {
+            let carrier = Modulation::Fm { deviation: 0.0 };
+            let mut signal = Signal::new(RATE, offset, 1_000.0, carrier);
+            harness.play(&mut signal, 2);
+            let fft = harness.state.read().spectrum.fft_data.clone();
+            let peak = (0..fft.len()).max_by(|&a, &b| fft[a].total_cmp(&fft[b]));
+            assert_eq!(peak, Some(bin), "{} Hz", offset);
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! Scratch files for tests, shared by the library and the binary
+
+use std::path::PathBuf;
+
+/// An empty directory `name` under the system temp directory, unique to
+/// this test run
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,72 @@
+//! A subscriber for tests that keeps the spans it sees, with their fields
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+
+/// A span seen, with the fields given it so far, as `Debug` shows them
+#[derive(Debug, Clone)]
+pub struct CapturedSpan {
+    pub name: &'static str,
+    pub fields: BTreeMap<&'static str, String>,
+}
+
+impl CapturedSpan {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+impl Visit for CapturedSpan {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+/// Run `f`, returning what it did and the spans it made on this thread
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<CapturedSpan>) {
+    let layer = Capture::default();
+    let spans = layer.spans.clone();
+    let result = tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), f);
+    let spans = spans.lock().clone();
+    (result, spans)
+}
+
+#[derive(Default)]
+struct Capture {
+    spans: Arc<Mutex<Vec<CapturedSpan>>>,
+}
+
+/// Where a span is in the list, kept with the span
+struct Index(usize);
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Capture {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut span = CapturedSpan {
+            name: attrs.metadata().name(),
+            fields: BTreeMap::new(),
+        };
+        attrs.record(&mut span);
+        let mut spans = self.spans.lock();
+        spans.push(span);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Index(spans.len() - 1));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let at = ctx
+            .span(id)
+            .and_then(|span| span.extensions().get().map(|&Index(at)| at));
+        if let Some(at) = at {
+            values.record(&mut self.spans.lock()[at]);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::cmp::{Ordering, Reverse};
+use std::time::{Duration, Instant};
+
+/// Aircraft not heard from for this long are shown dimmed
+pub const AIRCRAFT_STALE: Duration = Duration::from_secs(60);
+/// Aircraft not heard from for this long are dropped
+pub const AIRCRAFT_EXPIRE: Duration = Duration::from_secs(300);
+
+/// What an ADS-B decoder knows about one aircraft, keyed by ICAO address
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aircraft {
+    /// 24-bit ICAO address
+    pub icao: u32,
+    pub callsign: Option<String>,
+    /// Barometric altitude in feet
+    pub altitude: Option<i32>,
+    /// Ground speed in knots
+    pub ground_speed: Option<f32>,
+    /// Track over ground in degrees
+    pub heading: Option<f32>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    /// Messages received from this aircraft
+    pub messages: u64,
+    pub last_seen: Instant,
+}
+
+impl Aircraft {
+    pub fn new(icao: u32, now: Instant) -> Self {
+        Self {
+            icao,
+            callsign: None,
+            altitude: None,
+            ground_speed: None,
+            heading: None,
+            lat: None,
+            lon: None,
+            messages: 0,
+            last_seen: now,
+        }
+    }
+
+    /// ICAO address as six hex digits
+    pub fn hex(&self) -> String {
+        format!("{:06X}", self.icao)
+    }
+
+    pub fn is_stale(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_seen) > AIRCRAFT_STALE
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_seen) > AIRCRAFT_EXPIRE
+    }
+}
+
+/// Column the aircraft table is sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AircraftSort {
+    /// Most recently heard first
+    #[default]
+    LastSeen,
+    Hex,
+    Callsign,
+    /// Highest first
+    Altitude,
+    /// Fastest first
+    Speed,
+    /// Most messages first
+    Messages,
+}
+
+impl AircraftSort {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AircraftSort::LastSeen => "last seen",
+            AircraftSort::Hex => "hex",
+            AircraftSort::Callsign => "callsign",
+            AircraftSort::Altitude => "altitude",
+            AircraftSort::Speed => "speed",
+            AircraftSort::Messages => "messages",
+        }
+    }
+
+    /// The next column, wrapping around
+    pub fn next(&self) -> Self {
+        match self {
+            AircraftSort::LastSeen => AircraftSort::Hex,
+            AircraftSort::Hex => AircraftSort::Callsign,
+            AircraftSort::Callsign => AircraftSort::Altitude,
+            AircraftSort::Altitude => AircraftSort::Speed,
+            AircraftSort::Speed => AircraftSort::Messages,
+            AircraftSort::Messages => AircraftSort::LastSeen,
+        }
+    }
+
+    /// Sort aircraft by this column; missing values go last
+    pub fn sort(&self, aircraft: &mut [&Aircraft]) {
+        match self {
+            AircraftSort::LastSeen => aircraft.sort_by_key(|a| Reverse(a.last_seen)),
+            AircraftSort::Hex => aircraft.sort_by_key(|a| a.icao),
+            AircraftSort::Callsign => aircraft.sort_by(|a, b| {
+                known_first(a.callsign.as_ref(), b.callsign.as_ref(), |x, y| x.cmp(y))
+            }),
+            AircraftSort::Altitude => {
+                aircraft.sort_by(|a, b| known_first(a.altitude, b.altitude, |x, y| y.cmp(x)))
+            }
+            AircraftSort::Speed => aircraft.sort_by(|a, b| {
+                known_first(a.ground_speed, b.ground_speed, |x, y| y.total_cmp(x))
+            }),
+            AircraftSort::Messages => aircraft.sort_by_key(|a| Reverse(a.messages)),
+        }
+    }
+}
+
+/// Order known values with `cmp`, ahead of unknown ones
+fn known_first<T>(a: Option<T>, b: Option<T>, cmp: impl Fn(&T, &T) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => cmp(&a, &b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aircraft(icao: u32, callsign: Option<&str>, altitude: Option<i32>, age: u64) -> Aircraft {
+        let now = Instant::now() + Duration::from_secs(1000);
+        Aircraft {
+            callsign: callsign.map(String::from),
+            altitude,
+            messages: icao as u64,
+            ..Aircraft::new(icao, now - Duration::from_secs(age))
+        }
+    }
+
+    fn order(sort: AircraftSort, list: &[Aircraft]) -> Vec<u32> {
+        let mut refs: Vec<&Aircraft> = list.iter().collect();
+        sort.sort(&mut refs);
+        refs.iter().map(|a| a.icao).collect()
+    }
+
+    #[test]
+    fn test_sort_columns() {
+        let list = [
+            aircraft(3, Some("DLH4AB"), Some(36000), 30),
+            aircraft(1, None, None, 5),
+            aircraft(2, Some("BAW12"), Some(4500), 90),
+        ];
+
+        assert_eq!(order(AircraftSort::LastSeen, &list), vec![1, 3, 2]);
+        assert_eq!(order(AircraftSort::Hex, &list), vec![1, 2, 3]);
+        assert_eq!(order(AircraftSort::Callsign, &list), vec![2, 3, 1]);
+        assert_eq!(order(AircraftSort::Altitude, &list), vec![3, 2, 1]);
+        assert_eq!(order(AircraftSort::Messages, &list), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_staleness() {
+        let now = Instant::now() + Duration::from_secs(1000);
+        let seen = |age| Aircraft::new(0x4CA123, now - Duration::from_secs(age));
+        assert!(!seen(30).is_stale(now));
+        assert!(seen(61).is_stale(now) && !seen(61).is_expired(now));
+        assert!(seen(301).is_expired(now));
+        assert_eq!(seen(0).hex(), "4CA123");
+    }
+
+    #[test]
+    fn test_sort_cycle() {
+        let mut sort = AircraftSort::default();
+        for _ in 0..6 {
+            sort = sort.next();
+        }
+        assert_eq!(sort, AircraftSort::LastSeen);
+    }
+}
@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 
 /// Commands sent from UI thread to control the application
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Command {
     // SDR Control Commands
     SetFrequency(u32),
@@ -9,22 +9,64 @@ pub enum Command {
     DecreaseFrequency(i32),
     SetSampleRate(u32),
     SetTunerGain(i32),
-    SetAutoGain(bool),
+    /// Tuner automatic gain mode (librtlsdr tuner gain mode)
+    SetTunerAgc(bool),
+    /// RTL2832 digital AGC
+    SetRtlAgc(bool),
     SetPpmError(i32),
 
     // Demodulation Mode Commands
     SetMode(DemodMode),
+    /// Move a receive chain's channel, in Hz from the center frequency
+    SetChannelOffset(Chain, i32),
+    /// Set a receive chain's mode; chain A's is the main mode (`SetMode`)
+    /// and `Raw` turns chain B off
+    SetChannelMode(Chain, DemodMode),
+    /// Set chain A's channel filter width in Hz, within the mode's limits
+    SetFilterWidth(u32),
+    /// Make the other VFO active, retuning chain A to it
+    SwapVfo,
+    /// Copy VFO A to B
+    CopyVfo,
 
     // Recording Commands
     StartRecording(PathBuf),
+    StartSquelchRecording,
     StopRecording,
 
     // Application Commands
     Quit,
 }
 
+/// Receive chains demodulating separate channels of the captured bandwidth
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Chain {
+    /// Heard on the audio output and decoded
+    #[default]
+    A,
+    /// Decoded only
+    B,
+}
+
+impl Chain {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Chain::A => "A",
+            Chain::B => "B",
+        }
+    }
+
+    /// The other chain
+    pub fn other(&self) -> Self {
+        match self {
+            Chain::A => Chain::B,
+            Chain::B => Chain::A,
+        }
+    }
+}
+
 /// Demodulation modes supported by the application
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DemodMode {
     /// Raw IQ samples, no demodulation
     Raw,
@@ -38,10 +80,22 @@ pub enum DemodMode {
     Usb,
     /// Single Sideband - Lower Sideband
     Lsb,
+    /// Continuous Wave (Morse), upper sideband beat note
+    Cw,
     /// APRS (Automatic Packet Reporting System) decoder
     Aprs,
     /// ADS-B (Automatic Dependent Surveillance-Broadcast) decoder
     Adsb,
+    /// AIS (marine Automatic Identification System) decoder
+    Ais,
+    /// ISM band OOK sensor decoder (433/868/915 MHz)
+    Ism,
+    /// ACARS (VHF airband aircraft datalink) decoder
+    Acars,
+    /// NOAA APT weather satellite image decoder
+    Apt,
+    /// Vaisala RS41 radiosonde telemetry decoder
+    Rs41,
 }
 
 impl DemodMode {
@@ -54,8 +108,34 @@ impl DemodMode {
             DemodMode::Am => "AM",
             DemodMode::Usb => "USB",
             DemodMode::Lsb => "LSB",
+            DemodMode::Cw => "CW",
             DemodMode::Aprs => "APRS",
             DemodMode::Adsb => "ADS-B",
+            DemodMode::Ais => "AIS",
+            DemodMode::Ism => "ISM",
+            DemodMode::Acars => "ACARS",
+            DemodMode::Apt => "APT",
+            DemodMode::Rs41 => "RS41",
+        }
+    }
+
+    /// Short tag used in file names
+    pub fn file_tag(&self) -> &'static str {
+        match self {
+            DemodMode::Raw => "RAW",
+            DemodMode::FmNarrow => "NFM",
+            DemodMode::FmWide => "WFM",
+            DemodMode::Am => "AM",
+            DemodMode::Usb => "USB",
+            DemodMode::Lsb => "LSB",
+            DemodMode::Cw => "CW",
+            DemodMode::Aprs => "APRS",
+            DemodMode::Adsb => "ADSB",
+            DemodMode::Ais => "AIS",
+            DemodMode::Ism => "ISM",
+            DemodMode::Acars => "ACARS",
+            DemodMode::Apt => "APT",
+            DemodMode::Rs41 => "RS41",
         }
     }
 
@@ -68,12 +148,33 @@ impl DemodMode {
             DemodMode::Am,
             DemodMode::Usb,
             DemodMode::Lsb,
+            DemodMode::Cw,
             DemodMode::Aprs,
             DemodMode::Adsb,
+            DemodMode::Ais,
+            DemodMode::Ism,
+            DemodMode::Acars,
+            DemodMode::Apt,
+            DemodMode::Rs41,
         ]
     }
 }
 
+impl std::str::FromStr for DemodMode {
+    type Err = String;
+
+    /// Parse a mode from its name or file tag, e.g. "nfm", "FM-WFM", "usb"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DemodMode::all()
+            .iter()
+            .copied()
+            .find(|mode| {
+                mode.name().eq_ignore_ascii_case(s) || mode.file_tag().eq_ignore_ascii_case(s)
+            })
+            .ok_or_else(|| format!("unknown mode '{}'", s))
+    }
+}
+
 impl Default for DemodMode {
     fn default() -> Self {
         DemodMode::FmNarrow
@@ -1,11 +1,50 @@
 use super::commands::DemodMode;
+use crate::util::geo::Location;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 /// Application configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
     pub sdr: SdrConfig,
     pub ui: UiConfig,
     pub audio: AudioConfig,
+    /// Decoded message log
+    pub decode_log: DecodeLogConfig,
+    /// Timed recordings
+    pub schedule: Vec<ScheduleConfig>,
+    /// Key bindings by action name, replacing the defaults for those actions
+    pub keys: BTreeMap<String, KeyList>,
+    /// Colors and waterfall palette
+    pub theme: ThemeConfig,
+    /// Priority channel watch
+    pub priority: PriorityConfig,
+    /// Recently tuned frequencies
+    pub history: HistoryConfig,
+    /// Named channels and the levels each wants
+    pub bookmarks: BookmarksConfig,
+    /// VFOs as they were left
+    pub vfo: VfosConfig,
+    /// Filter width, squelch and demodulator settings as each mode was left,
+    /// by mode name
+    pub modes: BTreeMap<String, SavedModeSettings>,
+    /// MQTT publishing, used with --mqtt
+    pub mqtt: MqttConfig,
+    /// Where the receiver is, for distances to decoded positions
+    pub station: StationConfig,
+    /// Offsets from dBFS to dBm, measured against a known signal
+    pub calibration: CalibrationConfig,
+    /// What happens to finished recordings
+    pub recording: RecordingConfig,
+    /// Named setups picked with --profile or from the profile list
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    /// Tuner gain by frequency range
+    pub gain_profiles: GainProfilesConfig,
+    /// Control socket for `rtl-sdr-tui remote`
+    pub control: ControlConfig,
 }
 
 impl Default for AppConfig {
@@ -14,12 +53,165 @@ impl Default for AppConfig {
             sdr: SdrConfig::default(),
             ui: UiConfig::default(),
             audio: AudioConfig::default(),
+            decode_log: DecodeLogConfig::default(),
+            schedule: Vec::new(),
+            keys: BTreeMap::new(),
+            theme: ThemeConfig::default(),
+            priority: PriorityConfig::default(),
+            history: HistoryConfig::default(),
+            bookmarks: BookmarksConfig::default(),
+            vfo: VfosConfig::default(),
+            modes: BTreeMap::new(),
+            mqtt: MqttConfig::default(),
+            station: StationConfig::default(),
+            calibration: CalibrationConfig::default(),
+            recording: RecordingConfig::default(),
+            profiles: BTreeMap::new(),
+            gain_profiles: GainProfilesConfig::default(),
+            control: ControlConfig::default(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Default config file location, e.g. ~/.config/rtl-sdr-tui/config.toml
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("rtl-sdr-tui").join("config.toml"))
+    }
+
+    /// Load the config file
+    ///
+    /// An explicitly given path must exist; the default path is optional and
+    /// falls back to built-in defaults when missing.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        if !required && !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let config = Self::parse(&text)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        log::info!("Loaded config from {}", path.display());
+        Ok(config)
+    }
+
+    /// Parse config file contents
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Remember a setting changed at runtime: set `key` in `[section]` of
+    /// the config file at `path`, creating the file if needed
+    ///
+    /// `value` is in TOML syntax, e.g. `"\"utc\""`. The rest of the file,
+    /// comments included, is left as it is.
+    pub fn save_value(path: &Path, section: &str, key: &str, value: &str) -> Result<()> {
+        Self::save_values(path, &[(section, key, value)])
+    }
+
+    /// Remember several settings at once, as [`AppConfig::save_value`],
+    /// writing the file once
+    pub fn save_values(path: &Path, settings: &[(&str, &str, &str)]) -> Result<()> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        let updated = settings
+            .iter()
+            .fold(text, |text, (section, key, value)| set_value(&text, section, key, value));
+        // Never leave a file behind that won't load
+        Self::parse(&updated).with_context(|| format!("Failed to update {}", path.display()))?;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, updated)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        for (section, key, value) in settings {
+            log::info!("Saved {}.{} = {} to {}", section, key, value, path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Config file text with `key` in `[section]` set to `value`, replacing the
+/// key if it is there, adding it to the end of the section if not, or adding
+/// the section if that is missing too
+pub fn set_value(text: &str, section: &str, key: &str, value: &str) -> String {
+    let setting = format!("{} = {}", key, value);
+    let header = format!("[{}]", section);
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+
+    let Some(start) = lines.iter().position(|line| line.trim() == header) else {
+        if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push(header);
+        lines.push(setting);
+        return lines.join("\n") + "\n";
+    };
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+
+    let is_key = |line: &str| {
+        line.trim_start()
+            .strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+    };
+    match (start + 1..end).find(|&i| is_key(&lines[i])) {
+        Some(i) => lines[i] = setting,
+        None => {
+            // After the section's last setting, before any blank lines
+            let last = (start..end)
+                .rev()
+                .find(|&i| !lines[i].trim().is_empty())
+                .unwrap_or(start);
+            lines.insert(last + 1, setting);
         }
     }
+    lines.join("\n") + "\n"
+}
+
+/// A scheduled recording as written in the config file
+///
+/// ```toml
+/// [[schedule]]
+/// name = "NOAA"
+/// start = "14:00"          # daily; or "mon,wed 14:00", or "2024-06-01 14:00"
+/// duration = "10m"
+/// frequency = 162.550      # MHz
+/// mode = "nfm"
+/// format = "iq"            # iq, sigmf or squelch
+/// sample_format = "f32"    # u8, i16 or f32 (default: as --record-format)
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleConfig {
+    pub name: Option<String>,
+    pub start: String,
+    pub duration: String,
+    pub frequency: f64,
+    pub mode: Option<String>,
+    pub format: Option<String>,
+    pub sample_format: Option<String>,
 }
 
 /// SDR device configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct SdrConfig {
     /// Center frequency in Hz
     pub frequency: u32,
@@ -32,6 +224,19 @@ pub struct SdrConfig {
     pub ppm_error: i32,
     /// Device index (0 for first device)
     pub device_index: usize,
+    /// Pick the sample rate and tuner bandwidth to suit the mode whenever it
+    /// changes; a rate chosen by hand holds until the next mode change
+    pub auto_rate: bool,
+    /// Keeping the DC spike out of the audio when tuned onto the hardware
+    /// center: "notch" it out, tune the hardware "offset" and shift back in
+    /// software, or "off"
+    pub dc_avoidance: String,
+    /// How far the hardware is tuned off the frequency shown for
+    /// `dc_avoidance = "offset"`, in Hz
+    pub dc_offset: u32,
+    /// Seconds without samples before a local receiver is reopened, 0 to
+    /// never
+    pub stall_timeout: u64,
 }
 
 impl Default for SdrConfig {
@@ -42,6 +247,10 @@ impl Default for SdrConfig {
             tuner_gain: -1,          // Auto gain
             ppm_error: 0,
             device_index: 0,
+            auto_rate: true,
+            dc_avoidance: "notch".to_string(),
+            dc_offset: 250_000,
+            stall_timeout: 5,
         }
     }
 }
@@ -75,14 +284,38 @@ impl SdrConfig {
 }
 
 /// UI configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct UiConfig {
     /// FFT size for spectrum display
     pub fft_size: usize,
     /// Number of waterfall history lines to keep
     pub waterfall_history: usize,
+    /// Waterfall lines per second, each made of the spectrum frames that
+    /// arrive in that time, or 0 for a line per frame (changed at runtime,
+    /// and saved here)
+    pub waterfall_lps: f32,
+    /// How a waterfall line combines its frames: "average" power, or
+    /// "peak" to keep brief bursts (toggled at runtime, and saved here)
+    pub waterfall_integration: String,
     /// Target frames per second for UI updates
     pub fps: u32,
+    /// Spectrum trace: "bars" or "braille"
+    pub spectrum_mode: String,
+    /// Mirror the spectrum and waterfall, for spectrally inverted input such
+    /// as from a high-side converter (toggled at runtime, and saved here)
+    pub spectrum_invert: bool,
+    /// Spacing in Hz of the channel grid drawn over the spectrum, e.g.
+    /// 12500; 0 for none
+    pub channel_grid: u32,
+    /// Clock and message times: "local" or "utc" (toggled at runtime, and
+    /// saved here)
+    pub clock: String,
+    /// Show the frequency, mode and recording state in the terminal's
+    /// title; off for multiplexers that mangle title sequences
+    pub terminal_title: bool,
+    /// Which panes are shown and how big
+    pub layout: LayoutConfig,
 }
 
 impl Default for UiConfig {
@@ -90,18 +323,73 @@ impl Default for UiConfig {
         Self {
             fft_size: 2048,
             waterfall_history: 500,
+            waterfall_lps: crate::state::waterfall::DEFAULT_LINE_RATE,
+            waterfall_integration: "average".to_string(),
             fps: 30,
+            spectrum_mode: "bars".to_string(),
+            spectrum_invert: false,
+            channel_grid: 0,
+            clock: "local".to_string(),
+            terminal_title: true,
+            layout: LayoutConfig::default(),
+        }
+    }
+}
+
+/// Pane visibility and sizes
+///
+/// ```toml
+/// [ui.layout]
+/// waterfall = false     # panes shown; toggled at runtime and saved here
+/// spectrum_size = 30    # share of the height in percent, as is the
+/// waterfall_size = 30   # bottom row of controls and decoder output
+/// bottom_size = 40
+/// controls_width = 40   # share of the bottom row's width in percent
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    pub spectrum: bool,
+    pub waterfall: bool,
+    pub controls: bool,
+    pub decoder: bool,
+    pub spectrum_size: u16,
+    pub waterfall_size: u16,
+    pub bottom_size: u16,
+    pub controls_width: u16,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            spectrum: true,
+            waterfall: true,
+            controls: true,
+            decoder: true,
+            spectrum_size: 30,
+            waterfall_size: 30,
+            bottom_size: 40,
+            controls_width: 40,
         }
     }
 }
 
 /// Audio output configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct AudioConfig {
-    /// Audio sample rate in Hz
+    /// Speaker sample rate in Hz to ask the device for; a device that can't
+    /// run at it runs at its own, and the audio is resampled to suit
     pub sample_rate: u32,
     /// Audio buffer size in samples
     pub buffer_size: usize,
+    /// Per-mode audio shaping (NFM high-pass, SSB/AM voice bandpass);
+    /// disable to hear the raw demodulator output
+    pub filters: bool,
+    /// Soft-limit the audio rather than clip it at full scale
+    pub limiter: bool,
+    /// Share of full scale the limiter starts at, 0.1-1.0
+    pub limiter_threshold: f32,
 }
 
 impl Default for AudioConfig {
@@ -109,6 +397,423 @@ impl Default for AudioConfig {
         Self {
             sample_rate: 48_000,
             buffer_size: 4096,
+            filters: true,
+            limiter: true,
+            limiter_threshold: 0.8,
+        }
+    }
+}
+
+/// One key spec or a list of them, e.g. `quit = "q"` or `quit = ["q", "f10"]`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyList {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            KeyList::One(spec) => std::slice::from_ref(spec),
+            KeyList::Many(specs) => specs,
+        }
+    }
+}
+
+/// Color theme: a built-in preset with optional single-color overrides
+///
+/// ```toml
+/// [theme]
+/// preset = "viridis"    # default, light, viridis, inferno or grayscale
+/// accent = "#00afd7"
+/// waterfall = "inferno"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub preset: String,
+    pub accent: Option<String>,
+    pub text: Option<String>,
+    pub selected: Option<String>,
+    pub recording: Option<String>,
+    /// Waterfall colormap: classic, viridis, inferno or grayscale
+    pub waterfall: Option<String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            preset: "default".to_string(),
+            accent: None,
+            text: None,
+            selected: None,
+            recording: None,
+            waterfall: None,
+        }
+    }
+}
+
+/// Priority channel watch: check one frequency every few seconds while
+/// listening elsewhere, and switch to it while it is active
+///
+/// ```toml
+/// [priority]
+/// frequency = 162.550   # MHz
+/// interval = "5s"
+/// enabled = true        # start watching right away; P toggles
+/// threshold = -40.0     # dBFS, used while the squelch is off
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PriorityConfig {
+    pub frequency: Option<f64>,
+    pub interval: String,
+    pub enabled: bool,
+    pub threshold: f32,
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        Self {
+            frequency: None,
+            interval: "5s".to_string(),
+            enabled: false,
+            threshold: -40.0,
+        }
+    }
+}
+
+/// Recently tuned frequencies, newest first; kept up to date at runtime
+///
+/// ```toml
+/// [history]
+/// recent = [{ frequency = 162.550, mode = "nfm" }, { frequency = 118.1, mode = "am" }]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub recent: Vec<RecentConfig>,
+}
+
+/// A recently tuned frequency in MHz and its mode
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecentConfig {
+    pub frequency: f64,
+    pub mode: String,
+}
+
+/// Bookmarked channels, with the squelch (dBFS) and gain (dB) a channel
+/// wants where it differs from the global settings; kept up to date at
+/// runtime
+///
+/// ```toml
+/// [bookmarks]
+/// write_back = false   # save levels changed by hand on a bookmark to it
+/// list = [{ frequency = 146.94, name = "Repeater", mode = "nfm", squelch = -30.0 },
+///         { frequency = 162.55, name = "NOAA 7", mode = "nfm", tags = ["weather"] }]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BookmarksConfig {
+    pub write_back: bool,
+    pub list: Vec<BookmarkConfig>,
+}
+
+/// A bookmark's frequency in MHz, name, mode, filter width in Hz, tags and
+/// levels
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookmarkConfig {
+    pub frequency: f64,
+    #[serde(default)]
+    pub name: String,
+    pub mode: String,
+    pub bandwidth: Option<u32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub squelch: Option<f32>,
+    pub gain: Option<f32>,
+}
+
+/// VFOs A and B, saved on quit
+///
+/// ```toml
+/// [vfo]
+/// active = "a"
+/// a = { frequency = 162.550000, mode = "nfm" }
+/// b = { frequency = 118.100000, mode = "am", filter_width = 6000 }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VfosConfig {
+    pub active: String,
+    pub a: Option<SavedVfoConfig>,
+    pub b: Option<SavedVfoConfig>,
+}
+
+impl Default for VfosConfig {
+    fn default() -> Self {
+        Self {
+            active: "a".to_string(),
+            a: None,
+            b: None,
+        }
+    }
+}
+
+/// A VFO's frequency in MHz, mode and filter width in Hz
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedVfoConfig {
+    pub frequency: f64,
+    pub mode: String,
+    pub filter_width: Option<u32>,
+}
+
+/// A mode's filter width in Hz, squelch in dBFS, de-emphasis in µs (0 for
+/// none), BFO offset in Hz and AGC speed, saved on quit; the mode's own
+/// defaults stand in for the last three when left out
+///
+/// ```toml
+/// [modes]
+/// nfm = { filter_width = 12500, squelch = -46.0 }
+/// wfm = { filter_width = 180000, deemphasis = 50 }
+/// usb = { bfo = 1200, agc = "fast" }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedModeSettings {
+    pub filter_width: Option<u32>,
+    pub squelch: Option<f32>,
+    pub deemphasis: Option<u32>,
+    pub bfo: Option<i32>,
+    pub agc: Option<String>,
+}
+
+/// Offsets added to dBFS levels for approximate dBm, set with the level
+/// calibration key
+///
+/// ```toml
+/// [calibration]
+/// offset_db = -28.0    # on auto gain, or at every gain without a table
+///
+/// [calibration.gains]  # by manual tuner gain in dB, interpolated between
+/// "29.7" = -31.5
+/// "49.6" = -52.0
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CalibrationConfig {
+    pub offset_db: Option<f32>,
+    pub gains: BTreeMap<String, f32>,
+}
+
+/// Tuner gain by frequency range, applied when a retune crosses into
+/// another range unless the gain was set by hand since the last crossing
+///
+/// ```toml
+/// [gain_profiles]
+/// enabled = true
+/// hysteresis = 0.5   # MHz past a range's edge before it is left
+/// ranges = [{ start = 108.0, end = 174.0, gain = 40.2 },
+///           { start = 1085.0, end = 1095.0, gain = "auto" }]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GainProfilesConfig {
+    pub enabled: bool,
+    pub hysteresis: f64,
+    pub ranges: Vec<GainRangeConfig>,
+}
+
+impl Default for GainProfilesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            hysteresis: 0.5,
+            ranges: Vec::new(),
+        }
+    }
+}
+
+/// A frequency range in MHz and the gain it wants
+#[derive(Debug, Clone, Deserialize)]
+pub struct GainRangeConfig {
+    pub start: f64,
+    pub end: f64,
+    pub gain: GainSetting,
+}
+
+/// A gain in dB, or "auto"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GainSetting {
+    Db(f64),
+    Word(String),
+}
+
+/// A named setup for one job, applied with `--profile <name>`; anything
+/// left out comes from the rest of the config file, and the command line
+/// overrides any of it
+///
+/// ```toml
+/// [profiles.noaa]
+/// frequency = 162.55        # MHz
+/// mode = "nfm"
+/// sample_rate = 1024000
+/// gain = 40.2               # dB
+/// squelch = -45.0           # dBFS
+/// audio_port = 7355
+/// stream_codec = "wav"
+/// record_dir = "/srv/noaa"
+/// record_mode = "squelch"
+/// record_format = "u8"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub frequency: Option<f64>,
+    pub mode: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub gain: Option<f32>,
+    pub squelch: Option<f32>,
+    pub audio_port: Option<u16>,
+    pub stream_codec: Option<String>,
+    pub record_dir: Option<PathBuf>,
+    pub record_mode: Option<String>,
+    pub record_format: Option<String>,
+}
+
+/// Finished recordings
+///
+/// ```toml
+/// [recording]
+/// # Run on each squelch segment kept, with the WAV file's path added as
+/// # the last argument; the program is run directly, not by a shell
+/// post_record_command = ["whisper", "--model", "base.en"]
+/// post_record_jobs = 2   # commands run at once per receiver; the rest wait
+/// # Retuning during an IQ recording: "split" it into a new file, "block"
+/// # the retune, or "annotate" a SigMF recording and go on (raw ones split)
+/// retune = "split"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    pub post_record_command: Vec<String>,
+    pub post_record_jobs: usize,
+    pub retune: String,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            post_record_command: Vec::new(),
+            post_record_jobs: crate::recorder::hook::DEFAULT_POST_RECORD_JOBS,
+            retune: "split".to_string(),
+        }
+    }
+}
+
+/// Decoded message log
+///
+/// ```toml
+/// [decode_log]
+/// path = "decodes.jsonl"   # logging starts enabled when set
+/// format = "jsonl"         # jsonl, text or both
+/// max_size_mb = 50         # rotate when larger (files also rotate daily)
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DecodeLogConfig {
+    pub path: Option<PathBuf>,
+    pub format: String,
+    pub max_size_mb: u64,
+}
+
+impl Default for DecodeLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            format: "jsonl".to_string(),
+            max_size_mb: 50,
+        }
+    }
+}
+
+/// MQTT publishing to the broker given with `--mqtt`
+///
+/// ```toml
+/// [mqtt]
+/// topic_prefix = "rtl-sdr-tui"
+/// client_id = "rtl-sdr-tui"
+/// status_interval = 10   # seconds between status messages
+/// queue = 256            # messages held while the broker is away
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub topic_prefix: String,
+    pub client_id: String,
+    pub status_interval: u64,
+    pub queue: usize,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            topic_prefix: "rtl-sdr-tui".to_string(),
+            client_id: "rtl-sdr-tui".to_string(),
+            status_interval: 10,
+            queue: 256,
+        }
+    }
+}
+
+/// Control socket on localhost that `rtl-sdr-tui remote` sends its
+/// commands to; `--control-port` turns it on too
+///
+/// ```toml
+/// [control]
+/// enabled = true
+/// port = 7357
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ControlConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7357,
+        }
+    }
+}
+
+/// The receiving station, which distances and bearings to decoded positions
+/// (APRS, AIS, aircraft) are shown from; `--qth lat,lon` overrides it
+///
+/// ```toml
+/// [station]
+/// latitude = 47.6062
+/// longitude = -122.3321
+/// units = "km"   # km, mi or nm
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StationConfig {
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub units: String,
+}
+
+impl Default for StationConfig {
+    fn default() -> Self {
+        Self {
+            latitude: None,
+            longitude: None,
+            units: "km".to_string(),
         }
     }
 }
@@ -119,6 +824,11 @@ pub struct DecodedMessage {
     pub mode: DemodMode,
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub content: String,
+    /// Structured fields (e.g. MMSI, position) for logs, empty if none
+    pub fields: serde_json::Map<String, serde_json::Value>,
+    /// The message as received (e.g. an APRS packet), where `content` is a
+    /// summary of it
+    pub raw: Option<String>,
 }
 
 impl DecodedMessage {
@@ -127,6 +837,211 @@ impl DecodedMessage {
             mode,
             timestamp: chrono::Utc::now(),
             content,
+            fields: serde_json::Map::new(),
+            raw: None,
         }
     }
+
+    /// Attach structured fields, given as a JSON object
+    pub fn with_fields(mut self, fields: serde_json::Value) -> Self {
+        if let serde_json::Value::Object(fields) = fields {
+            self.fields = fields;
+        }
+        self
+    }
+
+    /// Attach the message as received
+    pub fn with_raw(mut self, raw: String) -> Self {
+        self.raw = Some(raw);
+        self
+    }
+
+    /// The position in its "lat" and "lon" fields, if it has one
+    pub fn location(&self) -> Option<Location> {
+        let degrees = |key: &str| self.fields.get(key).and_then(serde_json::Value::as_f64);
+        Location::new(degrees("lat")?, degrees("lon")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schedule() {
+        let config = AppConfig::parse(
+            r#"
+            [[schedule]]
+            name = "NOAA"
+            start = "14:00"
+            duration = "10m"
+            frequency = 162.55
+            mode = "nfm"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.schedule.len(), 1);
+        assert_eq!(config.schedule[0].start, "14:00");
+        assert_eq!(config.schedule[0].format, None);
+        assert_eq!(config.sdr.sample_rate, 2_048_000);
+    }
+
+    #[test]
+    fn test_audio_filters_bypass() {
+        assert!(AppConfig::default().audio.filters);
+        let config = AppConfig::parse("[audio]\nfilters = false").unwrap();
+        assert!(!config.audio.filters);
+        assert_eq!(config.audio.sample_rate, 48_000);
+        assert!(config.audio.limiter);
+
+        let config = AppConfig::parse("[audio]\nlimiter = false\nlimiter_threshold = 0.9").unwrap();
+        assert!(!config.audio.limiter);
+        assert_eq!(config.audio.limiter_threshold, 0.9);
+    }
+
+    #[test]
+    fn test_station_config() {
+        assert!(AppConfig::default().station.latitude.is_none());
+        let config =
+            AppConfig::parse("[station]\nlatitude = 47.6\nlongitude = -122.3\nunits = \"nm\"")
+                .unwrap();
+        assert_eq!(config.station.latitude, Some(47.6));
+        assert_eq!(config.station.units, "nm");
+
+        let message = DecodedMessage::new(DemodMode::Ais, "AIS".to_string());
+        assert_eq!(message.location(), None);
+        let message = message.with_fields(serde_json::json!({ "lat": 47.6, "lon": -122.3 }));
+        assert_eq!(message.location(), Location::new(47.6, -122.3));
+    }
+
+    #[test]
+    fn test_decode_log_config() {
+        assert!(AppConfig::default().decode_log.path.is_none());
+        let config = AppConfig::parse(
+            r#"
+            [decode_log]
+            path = "/var/log/decodes.jsonl"
+            format = "both"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.decode_log.path, Some(PathBuf::from("/var/log/decodes.jsonl")));
+        assert_eq!(config.decode_log.format, "both");
+        assert_eq!(config.decode_log.max_size_mb, 50);
+    }
+
+    #[test]
+    fn test_mqtt_config() {
+        let config = AppConfig::parse("[mqtt]\ntopic_prefix = \"home/sdr\"").unwrap();
+        assert_eq!(config.mqtt.topic_prefix, "home/sdr");
+        assert_eq!(config.mqtt.status_interval, 10);
+        assert_eq!(config.mqtt.queue, 256);
+    }
+
+    #[test]
+    fn test_set_value_keeps_the_rest() {
+        let text = concat!(
+            "# My receiver\n[ui]\nfps = 20 # smooth enough\nclock = \"local\"\n",
+            "\n[sdr]\nppm_error = 3\n"
+        );
+        let updated = set_value(text, "ui", "clock", "\"utc\"");
+        assert_eq!(updated, text.replace("\"local\"", "\"utc\""));
+
+        // Added at the end of the section
+        let updated = set_value(text, "sdr", "device_index", "1");
+        assert!(updated.ends_with("[sdr]\nppm_error = 3\ndevice_index = 1\n"));
+        let updated = set_value("[ui]\nfps = 20\n\n[sdr]\n", "ui", "clock", "\"utc\"");
+        assert_eq!(updated, "[ui]\nfps = 20\nclock = \"utc\"\n\n[sdr]\n");
+
+        // A key that only starts the same isn't replaced
+        let updated = set_value("[ui]\nclock_format = 1\n", "ui", "clock", "\"utc\"");
+        assert_eq!(updated, "[ui]\nclock_format = 1\nclock = \"utc\"\n");
+
+        // Missing section, or no file yet
+        assert_eq!(set_value("", "ui", "clock", "\"utc\""), "[ui]\nclock = \"utc\"\n");
+        assert_eq!(
+            set_value("[sdr]\nppm_error = 3", "ui", "clock", "\"utc\""),
+            "[sdr]\nppm_error = 3\n\n[ui]\nclock = \"utc\"\n"
+        );
+        let config = AppConfig::parse(&set_value(text, "ui", "clock", "\"utc\"")).unwrap();
+        assert_eq!(config.ui.clock, "utc");
+        assert_eq!(config.ui.fps, 20);
+    }
+
+    #[test]
+    fn test_history_config() {
+        assert!(AppConfig::default().history.recent.is_empty());
+        let config = AppConfig::parse(
+            "[history]\nrecent = [{ frequency = 162.55, mode = \"nfm\" }, \
+             { frequency = 118.1, mode = \"am\" }]\n",
+        )
+        .unwrap();
+        let recent = &config.history.recent;
+        assert_eq!(recent.len(), 2);
+        assert_eq!((recent[1].frequency, recent[1].mode.as_str()), (118.1, "am"));
+    }
+
+    #[test]
+    fn test_layout_config() {
+        assert_eq!(AppConfig::default().ui.layout, LayoutConfig::default());
+        let config = AppConfig::parse(
+            "[ui]
+fps = 20
+spectrum_invert = true
+channel_grid = 25000
+waterfall_lps = 2.5
+waterfall_integration = \"peak\"
+
+[ui.layout]
+waterfall = false
+bottom_size = 70
+",
+        )
+        .unwrap();
+        let layout = &config.ui.layout;
+        assert!(!layout.waterfall && layout.spectrum);
+        assert_eq!((layout.spectrum_size, layout.bottom_size), (30, 70));
+        assert_eq!(config.ui.fps, 20);
+        assert!(config.ui.spectrum_invert);
+        assert_eq!(config.ui.channel_grid, 25_000);
+        assert_eq!(config.ui.waterfall_lps, 2.5);
+        assert_eq!(config.ui.waterfall_integration, "peak");
+    }
+
+    #[test]
+    fn test_dc_avoidance_config() {
+        let config = AppConfig::default();
+        assert_eq!(config.sdr.dc_avoidance, "notch");
+        let config = AppConfig::parse("[sdr]\ndc_avoidance = \"offset\"\ndc_offset = -300000");
+        assert!(config.is_err());
+        let config = AppConfig::parse("[sdr]\ndc_avoidance = \"offset\"\ndc_offset = 300000");
+        assert_eq!(config.unwrap().sdr.dc_offset, 300_000);
+    }
+
+    #[test]
+    fn test_recording_config() {
+        assert!(AppConfig::default().recording.post_record_command.is_empty());
+        let config = AppConfig::parse(
+            r#"
+            [recording]
+            post_record_command = ["whisper", "--model", "base.en"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.recording.post_record_command,
+            ["whisper", "--model", "base.en"]
+        );
+        assert_eq!(config.recording.post_record_jobs, 2);
+        // A single string would be handed to a shell by most tools; not here
+        assert!(AppConfig::parse("[recording]\npost_record_command = \"whisper $1\"").is_err());
+    }
+
+    #[test]
+    fn test_empty_config() {
+        let config = AppConfig::parse("").unwrap();
+        assert!(config.schedule.is_empty());
+        assert!(AppConfig::parse("schedule = 5").is_err());
+    }
 }
@@ -1,6 +1,11 @@
+pub mod aircraft;
 pub mod commands;
 pub mod config;
 
 // Re-export commonly used types
-pub use commands::{Command, DemodMode};
-pub use config::{AppConfig, AudioConfig, DecodedMessage, SdrConfig, UiConfig};
+pub use aircraft::{Aircraft, AircraftSort};
+pub use commands::{Chain, Command, DemodMode};
+pub use config::{
+    AppConfig, AudioConfig, DecodedMessage, KeyList, RecordingConfig, ScheduleConfig, SdrConfig,
+    ThemeConfig, UiConfig,
+};
@@ -1,14 +1,51 @@
-use crate::state::SharedState;
-use crate::types::Command;
+use super::keymap::Keymap;
+use super::layout::PaneLayout;
+use super::theme::Theme;
+use super::widgets::SpectrumMode;
+use crate::profile::Profile;
+use crate::state::{Modal, Receivers, SharedState, VfoConfig};
+use crate::types::{AppConfig, Command};
+use crate::util::geo::Station;
 use anyhow::Result;
-use crossbeam::channel::Sender;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// How long settings changed at runtime wait for the changes to stop
+/// before they are written to the config file
+pub const SAVE_DELAY: Duration = Duration::from_secs(1);
 
 /// TUI Application structure
 pub struct App {
-    /// Shared application state
+    /// Shared application state of the selected receiver
     pub state: SharedState,
-    /// Command sender to control threads
-    pub command_tx: Option<Sender<Command>>,
+    /// Every receiver, and the commands into their threads
+    pub receivers: Option<Receivers>,
+    /// Effective key bindings
+    pub keymap: Keymap,
+    /// Active color theme
+    pub theme: Theme,
+    /// How the spectrum trace is drawn
+    pub spectrum_mode: SpectrumMode,
+    /// Whether the spectrum and waterfall are mirrored
+    pub spectrum_invert: bool,
+    /// Channel grid spacing in Hz, if drawn
+    pub channel_grid: Option<u32>,
+    /// Which panes are shown and how big
+    pub layout: PaneLayout,
+    /// Config file settings changed at runtime are saved to
+    pub config_path: Option<PathBuf>,
+    /// Settings changed at runtime and not yet written, by section and key
+    unsaved: RefCell<BTreeMap<(String, String), String>>,
+    /// When a setting last changed, so writing waits for a pause
+    last_change: Cell<Option<Instant>>,
+    /// When the app started, for the uptime
+    pub started: Instant,
+    /// Where distances and bearings to decoded positions are measured from
+    pub station: Option<Station>,
+    /// Profiles from the config file, for the profile list
+    pub profiles: Vec<Profile>,
 }
 
 impl App {
@@ -16,19 +53,169 @@ impl App {
     pub fn new(state: SharedState) -> Self {
         Self {
             state,
-            command_tx: None,
+            receivers: None,
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            spectrum_mode: SpectrumMode::default(),
+            spectrum_invert: false,
+            channel_grid: None,
+            layout: PaneLayout::default(),
+            config_path: None,
+            unsaved: RefCell::default(),
+            last_change: Cell::new(None),
+            started: Instant::now(),
+            station: None,
+            profiles: Vec::new(),
+        }
+    }
+
+    /// Control `receivers`, starting with the selected one
+    pub fn set_receivers(&mut self, receivers: Receivers) {
+        self.state = receivers.current().state.clone();
+        self.receivers = Some(receivers);
+    }
+
+    /// How many receivers are running
+    pub fn receiver_count(&self) -> usize {
+        self.receivers.as_ref().map_or(1, Receivers::len)
+    }
+
+    /// Switch to the next receiver, returning its index
+    pub fn next_receiver(&mut self) -> usize {
+        match &self.receivers {
+            Some(receivers) => {
+                self.state = receivers.select_next().state.clone();
+                receivers.selected()
+            }
+            None => 0,
+        }
+    }
+
+    /// Use `keymap` instead of the default key bindings
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    /// Use `theme` for the UI colors
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// Draw the spectrum as `mode`
+    pub fn set_spectrum_mode(&mut self, mode: SpectrumMode) {
+        self.spectrum_mode = mode;
+    }
+
+    /// Mirror the spectrum and waterfall, or not
+    pub fn set_spectrum_invert(&mut self, inverted: bool) {
+        self.spectrum_invert = inverted;
+    }
+
+    /// Draw a channel grid every `spacing` Hz, or none
+    pub fn set_channel_grid(&mut self, spacing: Option<u32>) {
+        self.channel_grid = spacing;
+    }
+
+    /// Arrange the panes as `layout`
+    pub fn set_layout(&mut self, layout: PaneLayout) {
+        self.layout = layout;
+    }
+
+    /// Show distances and bearings from `station`, if there is one
+    pub fn set_station(&mut self, station: Option<Station>) {
+        self.station = station;
+    }
+
+    /// List `profiles` from the config file in the profile list
+    pub fn set_profiles(&mut self, profiles: Vec<Profile>) {
+        self.profiles = profiles;
+    }
+
+    /// Save settings changed at runtime to the config file at `path`
+    pub fn set_config_path(&mut self, path: Option<PathBuf>) {
+        self.config_path = path;
+    }
+
+    /// Save a setting to the config file, as `AppConfig::save_value`
+    ///
+    /// The file is written once the settings have stopped changing for
+    /// [`SAVE_DELAY`] (see [`App::write_settings`]), so a key held down
+    /// doesn't rewrite it at every repeat.
+    pub fn save_setting(&self, section: &str, key: &str, value: &str) -> Result<()> {
+        if self.config_path.is_none() {
+            anyhow::bail!("no config file location");
         }
+        self.unsaved
+            .borrow_mut()
+            .insert((section.to_string(), key.to_string()), value.to_string());
+        self.last_change.set(Some(Instant::now()));
+        Ok(())
     }
 
-    /// Set the command sender for controlling threads
-    pub fn set_command_tx(&mut self, tx: Sender<Command>) {
-        self.command_tx = Some(tx);
+    /// Write the settings saved since the last write, if they haven't
+    /// changed for [`SAVE_DELAY`] by `now`
+    pub fn write_settings(&self, now: Instant) -> Result<()> {
+        match self.last_change.get() {
+            Some(changed) if now.saturating_duration_since(changed) >= SAVE_DELAY => {
+                self.write_all_settings()
+            }
+            _ => Ok(()),
+        }
     }
 
-    /// Send a command to the application threads
+    /// Write the settings saved since the last write straight away
+    pub fn write_all_settings(&self) -> Result<()> {
+        self.last_change.set(None);
+        let unsaved = std::mem::take(&mut *self.unsaved.borrow_mut());
+        let Some(path) = self.config_path.as_deref().filter(|_| !unsaved.is_empty()) else {
+            return Ok(());
+        };
+        let settings: Vec<(&str, &str, &str)> = unsaved
+            .iter()
+            .map(|((section, key), value)| (section.as_str(), key.as_str(), value.as_str()))
+            .collect();
+        AppConfig::save_values(path, &settings)
+    }
+
+    /// Save both VFOs and which one is active to the config file
+    pub fn save_vfos(&self) -> Result<()> {
+        let (vfo, active) = {
+            let state = self.state.read();
+            (state.sdr.vfo, state.sdr.active_vfo)
+        };
+        self.save_setting("vfo", "a", &vfo[0].to_toml())?;
+        self.save_setting("vfo", "b", &vfo[1].to_toml())?;
+        let active = VfoConfig::name(active).to_ascii_lowercase();
+        self.save_setting("vfo", "active", &format!("\"{}\"", active))
+    }
+
+    /// Save each mode's filter width, squelch and demodulator settings to
+    /// the config file
+    pub fn save_mode_settings(&self) -> Result<()> {
+        let memory = {
+            let state = self.state.read();
+            let mut memory = state.decoder.mode_memory.clone();
+            memory.store(state.decoder.mode, state.decoder.mode_settings());
+            memory
+        };
+        for (key, value) in memory.to_config() {
+            self.save_setting("modes", &key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Save which panes are shown to the config file
+    pub fn save_layout(&self) -> Result<()> {
+        for (key, shown) in self.layout.saved_visibility() {
+            self.save_setting("ui.layout", key, &shown.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Send a command to the selected receiver's threads
     pub fn send_command(&self, command: Command) -> Result<()> {
-        if let Some(tx) = &self.command_tx {
-            tx.send(command)?;
+        if let Some(receivers) = &self.receivers {
+            receivers.send(command)?;
         }
         Ok(())
     }
@@ -41,7 +228,35 @@ impl App {
     /// Handle application quit
     pub fn quit(&mut self) {
         self.state.write().ui.should_quit = true;
-        let _ = self.send_command(Command::Quit);
+        if let Some(receivers) = &self.receivers {
+            receivers.broadcast(Command::Quit);
+        }
+    }
+
+    /// Explain why quitting right now would lose something, if it would
+    pub fn quit_confirmation_reason(&self) -> Option<String> {
+        // Any receiver may be recording or streaming, selected or not
+        let states = match &self.receivers {
+            Some(receivers) => receivers.iter().map(|r| r.state.clone()).collect(),
+            None => vec![self.state.clone()],
+        };
+        let recording = states.iter().any(|state| state.read().recording.is_recording);
+        let clients: usize = states.iter().map(|state| state.read().streaming.clients).sum();
+        if recording {
+            Some("Recording in progress — quit anyway? y/N".to_string())
+        } else if clients > 0 {
+            Some(format!("{} stream client(s) connected — quit anyway? y/N", clients))
+        } else {
+            None
+        }
+    }
+
+    /// Quit, asking for confirmation first if a recording or stream is active
+    pub fn request_quit(&mut self) {
+        match self.quit_confirmation_reason() {
+            Some(reason) => self.state.write().ui.modal = Some(Modal::ConfirmQuit(reason)),
+            None => self.quit(),
+        }
     }
 
     /// Update status message
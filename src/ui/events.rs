@@ -0,0 +1,281 @@
+//! Terminal input on a thread of its own
+//!
+//! The input thread waits on the terminal and forwards each event over a
+//! channel, so a slow render (a big terminal, a slow SSH link) never holds
+//! up a key, and a key never waits out a poll interval. The UI loop takes
+//! whichever comes first: an event, word from another thread that the
+//! state has changed (a decoded message, a receiver warning), or the
+//! render tick that keeps the spectrum moving.
+//!
+//! The thread only waits a short while for each event before checking
+//! whether it should stop, so it is never left blocked in a read once the
+//! UI has quit and the terminal is handed back.
+//!
+//! Windows reports a key's release as well as its press. Releases are
+//! dropped here, so a key acts once everywhere.
+
+use crate::state::StateChanged;
+use crossbeam::channel::{self, select, Receiver};
+use crossterm::event::{self, Event, KeyEventKind};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the screen is redrawn while no input comes
+pub const RENDER_INTERVAL: Duration = Duration::from_millis(100);
+/// How long the input thread waits for an event before checking whether to
+/// stop
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What the UI loop does next
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Handle a terminal event
+    Input(Event),
+    /// Redraw, nothing having come in for a while
+    Render,
+    /// Redraw, another thread having changed the state
+    Changed,
+    /// The input thread has gone and nothing more will come
+    Closed,
+}
+
+/// Waits up to the given time for a terminal event; None if none came
+pub type ReadEvent = Box<dyn FnMut(Duration) -> io::Result<Option<Event>> + Send>;
+
+/// Reads events from the terminal
+pub fn terminal_reader() -> ReadEvent {
+    Box::new(|timeout| {
+        if event::poll(timeout)? {
+            event::read().map(Some)
+        } else {
+            Ok(None)
+        }
+    })
+}
+
+/// Terminal events from the input thread, and render ticks between them
+///
+/// Dropping it stops the input thread and waits for it to finish.
+pub struct UiEvents {
+    input: Receiver<Event>,
+    ticks: Receiver<Instant>,
+    /// Held here too, so the channel never closes
+    notifier: StateChanged,
+    changes: Receiver<()>,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl UiEvents {
+    /// Start the input thread reading with `read`, and a render tick every
+    /// `interval`
+    pub fn start(mut read: ReadEvent, interval: Duration) -> Self {
+        let (input_tx, input) = channel::unbounded();
+        let (notifier, changes) = StateChanged::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            log::info!("Input thread started");
+            while !thread_stop.load(Ordering::Relaxed) {
+                match read(STOP_CHECK_INTERVAL) {
+                    Ok(Some(Event::Key(key))) if key.kind == KeyEventKind::Release => {}
+                    Ok(Some(event)) => {
+                        if input_tx.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        log::error!("Terminal input failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            log::info!("Input thread stopped");
+        });
+        Self {
+            input,
+            ticks: channel::tick(interval),
+            notifier,
+            changes,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Wait for the next thing to do
+    pub fn next(&self) -> Dispatch {
+        select! {
+            recv(self.input) -> event => match event {
+                Ok(event) => Dispatch::Input(event),
+                Err(_) => Dispatch::Closed,
+            },
+            recv(self.ticks) -> _ => Dispatch::Render,
+            recv(self.changes) -> _ => Dispatch::Changed,
+        }
+    }
+
+    /// What other threads tell the UI of state changes with
+    pub fn notifier(&self) -> StateChanged {
+        self.notifier.clone()
+    }
+
+    /// Whether more input is waiting, so a redraw can wait until it has
+    /// all been handled
+    pub fn input_pending(&self) -> bool {
+        !self.input.is_empty()
+    }
+}
+
+impl Drop for UiEvents {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use parking_lot::Mutex;
+    use std::collections::VecDeque;
+
+    fn key(c: char) -> Event {
+        Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+    }
+
+    /// Reads `events` as if typed, then waits out each timeout; counts its
+    /// calls
+    fn injected(events: Vec<Event>, calls: Arc<Mutex<usize>>) -> ReadEvent {
+        let mut events = VecDeque::from(events);
+        Box::new(move |timeout| {
+            *calls.lock() += 1;
+            match events.pop_front() {
+                Some(event) => Ok(Some(event)),
+                None => {
+                    thread::sleep(timeout);
+                    Ok(None)
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_events_in_order_then_ticks() {
+        let calls = Arc::new(Mutex::new(0));
+        let typed = vec![key('q'), Event::Resize(120, 40), key('j')];
+        let events = UiEvents::start(injected(typed.clone(), calls), Duration::from_secs(60));
+
+        // Each event as it came, without waiting for a tick
+        let started = Instant::now();
+        for event in typed {
+            assert_eq!(events.next(), Dispatch::Input(event));
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(!events.input_pending());
+
+        // With nothing typed, the render tick comes round
+        let events = UiEvents::start(
+            injected(Vec::new(), Arc::default()),
+            Duration::from_millis(10),
+        );
+        assert_eq!(events.next(), Dispatch::Render);
+        assert_eq!(events.next(), Dispatch::Render);
+    }
+
+    #[test]
+    fn test_state_changes_wake() {
+        let events = UiEvents::start(injected(Vec::new(), Arc::default()), Duration::from_secs(60));
+        let notifier = events.notifier();
+
+        // Straight away, not on the tick
+        let started = Instant::now();
+        notifier.notify();
+        assert_eq!(events.next(), Dispatch::Changed);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // Several changes before the redraw make one wake, and a key
+        // still gets through
+        notifier.clone().notify();
+        notifier.notify();
+        assert_eq!(events.next(), Dispatch::Changed);
+        assert!(events.changes.is_empty());
+        let events = UiEvents::start(
+            injected(vec![key('j')], Arc::default()),
+            Duration::from_secs(60),
+        );
+        events.notifier().notify();
+        let mut seen = vec![events.next(), events.next()];
+        seen.sort_by_key(|dispatch| format!("{:?}", dispatch));
+        assert_eq!(seen, vec![Dispatch::Changed, Dispatch::Input(key('j'))]);
+    }
+
+    #[test]
+    fn test_key_releases_dropped() {
+        let release = |c| {
+            let mut key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+            key.kind = KeyEventKind::Release;
+            Event::Key(key)
+        };
+        let typed = vec![key('q'), release('q'), key('j'), release('j')];
+        let events = UiEvents::start(injected(typed, Arc::default()), Duration::from_secs(60));
+        assert_eq!(events.next(), Dispatch::Input(key('q')));
+        assert_eq!(events.next(), Dispatch::Input(key('j')));
+        thread::sleep(Duration::from_millis(20));
+        assert!(!events.input_pending());
+    }
+
+    #[test]
+    fn test_pending_input() {
+        let events = UiEvents::start(
+            injected(vec![key('a'), key('b')], Arc::default()),
+            Duration::from_secs(60),
+        );
+        // Both are queued before the first is taken
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while events.input.len() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(events.next(), Dispatch::Input(key('a')));
+        assert!(events.input_pending());
+        assert_eq!(events.next(), Dispatch::Input(key('b')));
+        assert!(!events.input_pending());
+    }
+
+    #[test]
+    fn test_drop_stops_the_thread() {
+        let calls = Arc::new(Mutex::new(0));
+        let events = UiEvents::start(injected(Vec::new(), calls.clone()), RENDER_INTERVAL);
+        thread::sleep(Duration::from_millis(120));
+
+        // Dropping waits for the thread, which stops reading within one
+        // check interval
+        let started = Instant::now();
+        drop(events);
+        assert!(started.elapsed() < STOP_CHECK_INTERVAL * 4);
+        let after_stop = *calls.lock();
+        thread::sleep(STOP_CHECK_INTERVAL * 2);
+        assert_eq!(*calls.lock(), after_stop);
+    }
+
+    #[test]
+    fn test_read_error_closes() {
+        let mut first = true;
+        let read: ReadEvent = Box::new(move |_| {
+            if std::mem::take(&mut first) {
+                Ok(Some(key('x')))
+            } else {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "terminal gone"))
+            }
+        });
+        let events = UiEvents::start(read, Duration::from_secs(60));
+        assert_eq!(events.next(), Dispatch::Input(key('x')));
+        assert_eq!(events.next(), Dispatch::Closed);
+    }
+}
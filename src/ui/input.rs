@@ -1,211 +1,1459 @@
 use super::app::App;
-use crate::state::ControlId;
-use crate::types::{Command, DemodMode};
+use super::keymap::{routing, Action, Scope};
+use crate::calibrate;
+use crate::dsp::channelizer;
+use crate::dsp::noise::NoiseReduction;
+use crate::dsp::tone::{TEST_TONE_DURATION, TEST_TONE_FREQ, TEST_TONE_LEVEL};
+use crate::gain_assist;
+use crate::profile::Profile;
+use crate::scan::peak_in;
+use crate::state::app_state::format_elapsed;
+use crate::state::bookmark_csv;
+use crate::state::bookmarks::{LevelChange, LevelOverrides, Levels, WriteBack};
+use crate::state::message_view::{export_path, export_text};
+use crate::state::mode_settings::{
+    deemphasis_label, uses_bfo, uses_deemphasis, BFO_LIMIT, BFO_STEP,
+};
+use crate::state::playback::SEEK_STEP_SECS;
+use crate::state::undo::Settings;
+use crate::state::waterfall;
+use crate::state::{Bookmark, ControlId, Modal, PaneId, Tuned, VfoConfig};
+use crate::types::{Chain, Command, DemodMode};
+use crate::waterfall_png;
 use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use std::path::PathBuf;
+use std::time::Instant;
 
-/// Handle keyboard input events
-pub fn handle_input(app: &mut App) -> Result<()> {
-    if event::poll(std::time::Duration::from_millis(100))? {
-        if let Event::Key(key) = event::read()? {
-            handle_key_event(app, key)?;
-        }
+/// Handle a terminal event from the input thread
+pub fn handle_event(app: &mut App, event: Event) -> Result<()> {
+    if let Event::Key(key) = event {
+        app.state.write().ui.undo.key(Instant::now());
+        handle_key_event(app, key)?;
     }
-    Ok(())
+    handle_tick(app)
+}
+
+/// Keep up with changes that come without a key, between events
+pub fn handle_tick(app: &mut App) -> Result<()> {
+    let now = Instant::now();
+    let settings = current_settings(app);
+    let settled = {
+        let mut state = app.state.write();
+        state.ui.undo.observe(settings, now);
+        state.ui.frequency_history.settle(now)
+    };
+    if settled {
+        save_history(app);
+    }
+    if let Err(e) = app.write_settings(now) {
+        log::warn!("Failed to save settings: {:#}", e);
+        app.set_status(format!("Settings not saved: {}", e));
+    }
+    follow_bookmark_levels(app)
 }
 
 /// Handle a single key event
 fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<()> {
-    // Global key bindings (work regardless of selected control)
-    match (key.code, key.modifiers) {
-        // Quit
-        (KeyCode::Char('q'), KeyModifiers::NONE) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-            app.quit();
-            return Ok(());
+    // An open modal captures every key until it is dismissed
+    let modal = app.state.read().ui.modal.clone();
+    if let Some(modal) = modal {
+        handle_modal_keys(app, modal, key);
+        return Ok(());
+    }
+
+    // Each scope the focused pane routes keys through, until one takes it
+    let (pane, selected, paused, playing) = {
+        let state = app.state.read();
+        let ui = &state.ui;
+        (
+            ui.focused_pane,
+            ui.selected_control,
+            ui.waterfall_paused_at.is_some(),
+            state.playback.is_some(),
+        )
+    };
+    for scope in routing(pane, selected, paused, playing) {
+        let Some(action) = app.keymap.lookup(scope, key) else {
+            continue;
+        };
+        let handled = match scope {
+            Scope::Paused => {
+                handle_paused_action(app, action);
+                true
+            }
+            Scope::Decoder => {
+                handle_decoder_action(app, action);
+                true
+            }
+            Scope::Playback => {
+                handle_playback_action(app, action);
+                true
+            }
+            Scope::Waterfall => {
+                handle_waterfall_action(app, action);
+                true
+            }
+            // Nothing of its own yet
+            Scope::Spectrum => true,
+            Scope::Global | Scope::Controls => handle_global_action(app, action)?,
+            Scope::Frequency | Scope::Adjust | Scope::Gain | Scope::Record => {
+                handle_control_action(app, selected, action)?;
+                true
+            }
+        };
+        if handled {
+            break;
         }
+    }
 
-        // Toggle recording
-        (KeyCode::Char('r'), KeyModifiers::NONE) => {
-            toggle_recording(app)?;
-            return Ok(());
+    Ok(())
+}
+
+/// Run an action on the selected control
+fn handle_control_action(app: &mut App, selected: ControlId, action: Action) -> Result<()> {
+    match selected {
+        ControlId::Frequency => handle_frequency_action(app, action)?,
+        ControlId::Mode => handle_mode_action(app, action)?,
+        ControlId::Gain => handle_gain_action(app, action)?,
+        ControlId::Squelch => handle_squelch_action(app, action),
+        ControlId::SampleRate => handle_sample_rate_action(app, action)?,
+        ControlId::Record => handle_record_action(app, action)?,
+    }
+
+    Ok(())
+}
+
+/// Rows one scroll-back key press moves the paused waterfall
+const WATERFALL_SCROLL_ROWS: usize = 10;
+
+/// Handle keys that scroll the paused waterfall
+fn handle_paused_action(app: &mut App, action: Action) {
+    let mut state = app.state.write();
+    let history = state.spectrum.waterfall.len();
+    let scroll = state.ui.waterfall_scroll;
+    state.ui.waterfall_scroll = match action {
+        Action::WaterfallOlder => (scroll + WATERFALL_SCROLL_ROWS).min(history),
+        Action::WaterfallNewer => scroll.saturating_sub(WATERFALL_SCROLL_ROWS),
+        _ => scroll,
+    };
+}
+
+/// Handle keys that pause, seek and pace a file being played
+fn handle_playback_action(app: &mut App, action: Action) {
+    let mut state = app.state.write();
+    let Some(playback) = state.playback.as_mut() else {
+        return;
+    };
+    let status = match action {
+        Action::PlaybackPause => {
+            // Starting again at the end plays from the start
+            if playback.paused && playback.at_end() {
+                playback.seek_to(0);
+            }
+            playback.paused = !playback.paused;
+            if playback.paused { "Playback paused" } else { "Playback resumed" }.to_string()
+        }
+        Action::SeekBack | Action::SeekForward => {
+            let step = if action == Action::SeekBack { -SEEK_STEP_SECS } else { SEEK_STEP_SECS };
+            let target = playback.seek_by(step);
+            format!("Seek to {}", format_elapsed(playback.secs(target) as i64))
+        }
+        Action::PlaybackSpeed => {
+            playback.speed = playback.speed.next();
+            format!("Playback speed: {}", playback.speed.label())
+        }
+        _ => {
+            let Some(tenths) = SEEK_TENTHS.iter().position(|&a| a == action) else {
+                return;
+            };
+            let target = playback.seek_tenths(tenths as u64);
+            format!("Seek to {}", format_elapsed(playback.secs(target) as i64))
+        }
+    };
+    state.ui.status_message = status;
+}
+
+/// Keys jumping to 0%, 10% ... 90% of the way through a file
+const SEEK_TENTHS: [Action; 10] = [
+    Action::Seek0,
+    Action::Seek1,
+    Action::Seek2,
+    Action::Seek3,
+    Action::Seek4,
+    Action::Seek5,
+    Action::Seek6,
+    Action::Seek7,
+    Action::Seek8,
+    Action::Seek9,
+];
+
+/// Handle keys for the focused decoder pane
+fn handle_decoder_action(app: &mut App, action: Action) {
+    match action {
+        Action::ClearMessages => {
+            let mut state = app.state.write();
+            match state.decoder.messages.len() {
+                0 => state.ui.status_message = "No decoded messages to clear".to_string(),
+                count => {
+                    let question = format!("Clear {} decoded messages? y/N", count);
+                    state.ui.modal = Some(Modal::ConfirmClearMessages(question));
+                }
+            }
+        }
+        Action::PauseMessages => {
+            let received = app.state.read().decoder.received;
+            let paused = app.state.write().ui.message_view.toggle_pause(received);
+            app.set_status(if paused {
+                format!(
+                    "Decoder pane paused, {} to follow again",
+                    app.keymap.label(Action::PauseMessages)
+                )
+            } else {
+                "Decoder pane following new messages".to_string()
+            });
+        }
+        Action::ExpandMessages => {
+            let expanded = app.state.write().ui.message_view.toggle_expanded();
+            app.set_status(if expanded {
+                "Decoder pane showing raw messages".to_string()
+            } else {
+                "Decoder pane showing summaries".to_string()
+            });
+        }
+        Action::ExportMessages => {
+            let (text, count, path) = {
+                let state = app.state.read();
+                let shown =
+                    state.ui.message_view.shown(&state.decoder.messages, state.decoder.received);
+                let path = export_path(&state.recording.output_dir, &chrono::Local::now());
+                (export_text(shown, state.ui.clock), shown.len(), path)
+            };
+            match std::fs::write(&path, text) {
+                Ok(()) => {
+                    log::info!("Exported {} decoded messages to {}", count, path.display());
+                    app.set_status(format!("Exported {} messages to {}", count, path.display()));
+                }
+                Err(e) => {
+                    log::warn!("Failed to export decoded messages: {}", e);
+                    app.set_status(format!("Export failed: {}", e));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Handle keys for the focused waterfall pane
+fn handle_waterfall_action(app: &mut App, action: Action) {
+    // Freeze the waterfall for scrolling back, or return to live
+    if action == Action::WaterfallPause {
+        let mut state = app.state.write();
+        state.ui.waterfall_scroll = 0;
+        state.ui.waterfall_paused_at = match state.ui.waterfall_paused_at {
+            Some(_) => None,
+            None => Some(state.spectrum.waterfall_rows),
+        };
+        state.ui.status_message = if state.ui.waterfall_paused_at.is_some() {
+            "Waterfall paused".to_string()
+        } else {
+            "Waterfall: live".to_string()
+        };
+    }
+}
+
+/// Run a global action; false if it doesn't apply in the current state
+fn handle_global_action(app: &mut App, action: Action) -> Result<bool> {
+    let (scope_shown, adsb, activity_shown) = {
+        let state = app.state.read();
+        (state.ui.show_scope, state.decoder.mode == DemodMode::Adsb, state.ui.show_activity)
+    };
+
+    match action {
+        Action::Quit => app.request_quit(),
+
+        Action::ToggleRecord => toggle_recording(app)?,
+
+        // Toggle the DTMF decoder
+        Action::ToggleDtmf => {
+            let enabled = !app.state.read().decoder.dtmf_enabled;
+            app.state.write().decoder.dtmf_enabled = enabled;
+            app.set_status(if enabled { "DTMF decoder: On (NFM)" } else { "DTMF decoder: Off" });
+        }
+
+        // Toggle the speaker, leaving the decoders running
+        Action::ToggleMonitor => {
+            let monitor = !app.state.read().ui.monitor;
+            app.state.write().ui.monitor = monitor;
+            app.set_status(if monitor { "Monitor: On" } else { "Monitor: Off (decoding only)" });
+        }
+
+        // Step the noise reduction through its strengths
+        Action::NoiseReduction => {
+            let (level, mode) = {
+                let mut state = app.state.write();
+                state.decoder.noise_reduction = state.decoder.noise_reduction.next();
+                (state.decoder.noise_reduction, state.decoder.mode)
+            };
+            app.set_status(if NoiseReduction::applies_to(mode) {
+                format!("Noise reduction: {}", level.name())
+            } else {
+                format!("Noise reduction: {} (bypassed in {})", level.name(), mode.name())
+            });
+        }
+
+        // Step the audio AGC through its speeds, remembered for the mode
+        Action::AgcSpeed => {
+            let speed = {
+                let mut state = app.state.write();
+                state.decoder.agc_speed = state.decoder.agc_speed.next();
+                state.decoder.agc_speed
+            };
+            app.set_status(format!("AGC: {}", speed.name()));
+        }
+
+        // Step the FM de-emphasis through 50 µs, 75 µs and off
+        Action::Deemphasis => {
+            let mut state = app.state.write();
+            let decoder = &mut state.decoder;
+            decoder.deemphasis = match decoder.deemphasis {
+                Some(50) => Some(75),
+                Some(_) => None,
+                None => Some(50),
+            };
+            let status = deemphasis_label(decoder.deemphasis);
+            state.ui.status_message = if uses_deemphasis(state.decoder.mode) {
+                format!("De-emphasis: {}", status)
+            } else {
+                format!("De-emphasis: {} (unused in {})", status, state.decoder.mode.name())
+            };
+        }
+
+        // Move the SSB and CW beat oscillator
+        Action::BfoDown | Action::BfoUp => {
+            let step = if action == Action::BfoUp { BFO_STEP } else { -BFO_STEP };
+            let mut state = app.state.write();
+            let decoder = &mut state.decoder;
+            decoder.bfo_offset = (decoder.bfo_offset + step).clamp(-BFO_LIMIT, BFO_LIMIT);
+            let bfo = decoder.bfo_offset;
+            state.ui.status_message = if uses_bfo(state.decoder.mode) {
+                format!("BFO: {} Hz", bfo)
+            } else {
+                format!("BFO: {} Hz (unused in {})", bfo, state.decoder.mode.name())
+            };
+        }
+
+        // Play the test tone over the audio at the speaker and streams
+        Action::TestTone => {
+            app.state.write().ui.test_tone = Some(Instant::now() + TEST_TONE_DURATION);
+            app.set_status(format!(
+                "Test tone: {:.0} Hz at {:.0} dBFS for {} s",
+                TEST_TONE_FREQ,
+                TEST_TONE_LEVEL,
+                TEST_TONE_DURATION.as_secs()
+            ));
+        }
+
+        // Toggle the decode log
+        Action::ToggleDecodeLog => {
+            let mut state = app.state.write();
+            state.decoder.log_enabled = !state.decoder.log_enabled;
+            state.ui.status_message = if state.decoder.log_enabled {
+                format!("Decode log: On ({})", state.decoder.log_path.display())
+            } else {
+                "Decode log: Off".to_string()
+            };
+        }
+
+        // Toggle the AF spectrum pane
+        Action::ToggleAfSpectrum => {
+            let mut state = app.state.write();
+            state.ui.show_af_spectrum = !state.ui.show_af_spectrum;
+            // Don't flash a stale spectrum when reopened
+            state.spectrum.af_fft.clear();
+        }
+
+        // Toggle the IQ constellation pane
+        Action::ToggleConstellation => {
+            let mut state = app.state.write();
+            state.ui.show_constellation = !state.ui.show_constellation;
+            state.spectrum.iq_snapshot.clear();
+        }
+
+        // Toggle the processing stats overlay
+        Action::ToggleStats => {
+            let mut state = app.state.write();
+            state.ui.show_stats = !state.ui.show_stats;
+        }
+
+        // Step to the next built-in theme
+        Action::CycleTheme => {
+            let theme = app.theme.next();
+            app.set_status(format!("Theme: {}", theme.name));
+            app.set_theme(theme);
+        }
+
+        // Fewer or more waterfall lines a second, each of more or fewer frames
+        Action::WaterfallSlower | Action::WaterfallFaster => {
+            let (rate, per_line) = {
+                let mut state = app.state.write();
+                let spectrum = &mut state.spectrum;
+                let faster = action == Action::WaterfallFaster;
+                spectrum.line_rate = waterfall::step_line_rate(spectrum.line_rate, faster);
+                let per_line = waterfall::frames_per_line(spectrum.line_rate, spectrum.frame_rate);
+                (spectrum.line_rate, per_line)
+            };
+            let status = format!("Waterfall: {} lines/s, {} frames each", rate, per_line);
+            match app.save_setting("ui", "waterfall_lps", &format!("{:?}", rate)) {
+                Ok(()) => app.set_status(status),
+                Err(e) => {
+                    log::warn!("Failed to save the waterfall speed: {:#}", e);
+                    app.set_status(format!("{} (not saved: {})", status, e));
+                }
+            }
+        }
+
+        // Average the frames in a waterfall line, or keep their peaks
+        Action::WaterfallIntegration => {
+            let integration = {
+                let mut state = app.state.write();
+                state.spectrum.integration = state.spectrum.integration.toggle();
+                state.spectrum.integration
+            };
+            let value = format!("\"{}\"", integration.name());
+            match app.save_setting("ui", "waterfall_integration", &value) {
+                Ok(()) => app.set_status(format!("Waterfall: {}", integration.name())),
+                Err(e) => {
+                    log::warn!("Failed to save the waterfall integration: {:#}", e);
+                    app.set_status(format!(
+                        "Waterfall: {} (not saved: {})",
+                        integration.name(),
+                        e
+                    ));
+                }
+            }
+        }
+
+        // Choose which receive chain the frequency and mode controls adjust
+        Action::SelectChain => {
+            let mut state = app.state.write();
+            state.ui.selected_chain = state.ui.selected_chain.other();
+            state.ui.status_message =
+                format!("Adjusting channel {}", state.ui.selected_chain.name());
+        }
+
+        // Start or stop watching the priority channel
+        Action::TogglePriority => {
+            let mut state = app.state.write();
+            let priority = &mut state.priority;
+            let message = match priority.frequency {
+                None => "No priority channel configured".to_string(),
+                Some(frequency) => {
+                    priority.enabled = !priority.enabled;
+                    if priority.enabled {
+                        format!("Priority watch on ({:.3} MHz)", frequency as f64 / 1_000_000.0)
+                    } else {
+                        "Priority watch off".to_string()
+                    }
+                }
+            };
+            state.ui.status_message = message;
+        }
+
+        // Switch the spectrum between bars and Braille
+        Action::SpectrumMode => {
+            let mode = app.spectrum_mode.next();
+            app.set_status(format!("Spectrum: {}", mode.name()));
+            app.set_spectrum_mode(mode);
+        }
+        Action::SpectrumInvert => {
+            let inverted = !app.spectrum_invert;
+            app.set_spectrum_invert(inverted);
+            let name = if inverted { "inverted" } else { "normal" };
+            match app.save_setting("ui", "spectrum_invert", &inverted.to_string()) {
+                Ok(()) => app.set_status(format!("Spectrum: {}", name)),
+                Err(e) => {
+                    log::warn!("Failed to save spectrum inversion: {:#}", e);
+                    app.set_status(format!("Spectrum: {} (not saved: {})", name, e));
+                }
+            }
+        }
+
+        // Save the whole waterfall history as an image
+        Action::ExportWaterfall => {
+            let (snapshot, path) = {
+                let state = app.state.read();
+                let path =
+                    waterfall_png::export_path(&state.recording.output_dir, &chrono::Local::now());
+                let snapshot =
+                    waterfall_png::Snapshot::take(&state, app.theme.waterfall, app.spectrum_invert);
+                (snapshot, path)
+            };
+            app.set_status(format!("Saving the waterfall to {}...", path.display()));
+            waterfall_png::start_export_thread(snapshot, path, app.state.clone());
+        }
+
+        // Oscilloscope: toggle, trigger and vertical scale
+        Action::ToggleScope => {
+            let mut state = app.state.write();
+            state.ui.show_scope = !state.ui.show_scope;
+            state.spectrum.scope.clear();
+        }
+        Action::ScopeTrigger if scope_shown => {
+            let mut state = app.state.write();
+            state.ui.scope_trigger = !state.ui.scope_trigger;
+            state.ui.status_message =
+                format!("Scope trigger: {}", if state.ui.scope_trigger { "On" } else { "Off" });
+        }
+        Action::ScopeZoomIn | Action::ScopeZoomOut if scope_shown => {
+            let mut state = app.state.write();
+            let range = state.ui.scope_range;
+            state.ui.scope_range = if action == Action::ScopeZoomOut {
+                (range * 2.0).min(2.0)
+            } else {
+                (range / 2.0).max(1.0 / 64.0)
+            };
+        }
+
+        // Aircraft table sort and scroll (ADS-B mode)
+        Action::AircraftSort if adsb => {
+            let mut state = app.state.write();
+            state.ui.aircraft_sort = state.ui.aircraft_sort.next();
+            state.ui.status_message = format!("Aircraft sorted by {}", state.ui.aircraft_sort.name());
+        }
+        Action::AircraftPageUp | Action::AircraftPageDown if adsb => {
+            let mut state = app.state.write();
+            let last = state.decoder.aircraft.len().saturating_sub(1);
+            let scroll = state.ui.aircraft_scroll;
+            state.ui.aircraft_scroll = if action == Action::AircraftPageDown {
+                (scroll + 5).min(last)
+            } else {
+                scroll.saturating_sub(5)
+            };
+        }
+
+        // Measurement markers on the strongest signal, in turn
+        Action::PlaceMarker => {
+            let placed = {
+                let mut state = app.state.write();
+                let (center, rate) = (state.sdr.frequency, state.sdr.sample_rate);
+                let spectrum = &mut state.spectrum;
+                spectrum.markers.place_at_peak(&spectrum.fft_data, center, rate)
+            };
+            match placed {
+                Some(number) => app.set_status(format!("Marker {} placed on the peak", number)),
+                None => app.set_status("No spectrum to place a marker on"),
+            }
+        }
+        Action::ClearMarkers => {
+            app.state.write().spectrum.markers.clear();
+            app.set_status("Markers cleared");
+        }
+
+        // Ask what the peak measures in dBm, to calibrate levels at this gain
+        Action::CalibrateLevel => {
+            let fft = app.state.read().spectrum.fft_data.clone();
+            match peak_in(&fft, 0..fft.len()) {
+                Some(peak) => {
+                    app.state.write().ui.modal = Some(Modal::CalibrateLevel {
+                        peak,
+                        input: String::new(),
+                    });
+                }
+                None => app.set_status("No spectrum to calibrate against"),
+            }
+        }
+
+        // Activity table: toggle, select a signal and tune chain A to it
+        Action::ToggleActivity => {
+            let mut state = app.state.write();
+            state.ui.show_activity = !state.ui.show_activity;
+        }
+        Action::ActivityPrev | Action::ActivityNext if activity_shown => {
+            let mut state = app.state.write();
+            let last = state.spectrum.activity.signals().len().saturating_sub(1);
+            let selected = state.ui.activity_selected.min(last);
+            state.ui.activity_selected = if action == Action::ActivityNext {
+                (selected + 1).min(last)
+            } else {
+                selected.saturating_sub(1)
+            };
+        }
+        Action::ActivityTune if activity_shown => tune_to_activity(app)?,
+
+        // Frequency history: flip back to the last frequency, or pick one
+        Action::FrequencyFlip => {
+            settle_history(app);
+            let previous = app.state.read().ui.frequency_history.previous();
+            match previous {
+                Some(entry) => tune_to_entry(app, entry)?,
+                None => app.set_status("No previous frequency"),
+            }
+        }
+        Action::ShowHistory => {
+            settle_history(app);
+            let mut state = app.state.write();
+            let history = &state.ui.frequency_history;
+            if history.is_empty() {
+                state.ui.status_message = "No frequencies tuned yet".to_string();
+            } else {
+                // The previous frequency is the likeliest pick
+                let selected = 1.min(history.entries().len() - 1);
+                state.ui.modal = Some(Modal::History { selected });
+            }
+        }
+        Action::ShowProfiles => {
+            if app.profiles.is_empty() {
+                app.set_status("No profiles: add [profiles.<name>] to the config file");
+            } else {
+                app.state.write().ui.modal = Some(Modal::Profiles { selected: 0 });
+            }
+        }
+        Action::ShowBookmarks => {
+            app.state.write().ui.modal = Some(Modal::Bookmarks { selected: 0 });
+        }
+        Action::SwapVfo => {
+            app.send_command(Command::SwapVfo)?;
+            let active = app.state.read().sdr.active_vfo;
+            app.set_status(format!("VFO {}", VfoConfig::name(active ^ 1)));
+        }
+        Action::CopyVfo => {
+            app.send_command(Command::CopyVfo)?;
+            app.set_status("VFO A copied to B");
+        }
+        Action::ToggleSpectrumPane
+        | Action::ToggleWaterfallPane
+        | Action::ToggleControlsPane
+        | Action::ToggleDecoderPane => {
+            let pane = match action {
+                Action::ToggleSpectrumPane => PaneId::Spectrum,
+                Action::ToggleWaterfallPane => PaneId::Waterfall,
+                Action::ToggleControlsPane => PaneId::Controls,
+                _ => PaneId::Decoder,
+            };
+            if !app.layout.toggle(pane) {
+                app.set_status("At least one pane stays shown");
+            } else if app.layout.is_visible(pane) {
+                app.set_status(format!("Showing the {} pane", pane.name()));
+            } else {
+                // Keys shouldn't go to a pane that isn't there
+                let mut state = app.state.write();
+                while !app.layout.is_visible(state.ui.focused_pane) {
+                    state.ui.focused_pane = state.ui.focused_pane.next();
+                }
+                state.ui.status_message = format!("Hid the {} pane", pane.name());
+            }
+        }
+        Action::NextPane | Action::PrevPane => {
+            let step = if action == Action::NextPane { PaneId::next } else { PaneId::prev };
+            let pane = {
+                let mut state = app.state.write();
+                // Hidden panes are passed over
+                let mut pane = step(state.ui.focused_pane);
+                while !app.layout.is_visible(pane) {
+                    pane = step(pane);
+                }
+                state.ui.focused_pane = pane;
+                pane
+            };
+            app.set_status(format!("Focus: {}", pane.name()));
+        }
+        Action::ToggleClock => {
+            let clock = {
+                let mut state = app.state.write();
+                state.ui.clock = state.ui.clock.toggle();
+                state.ui.clock
+            };
+            let value = format!("\"{}\"", clock.name());
+            match app.save_setting("ui", "clock", &value) {
+                Ok(()) => app.set_status(format!("Clock: {}", clock.name())),
+                Err(e) => {
+                    log::warn!("Failed to save clock setting: {:#}", e);
+                    app.set_status(format!("Clock: {} (not saved: {})", clock.name(), e));
+                }
+            }
+        }
+        Action::Undo => {
+            let step = app.state.write().ui.undo.undo();
+            match step {
+                Some(step) => {
+                    put_back_settings(app, step.before)?;
+                    app.set_status(format!("undo: {}", step.after.describe(&step.before)));
+                }
+                None => app.set_status("Nothing to undo".to_string()),
+            }
+        }
+        Action::Redo => {
+            let step = app.state.write().ui.undo.redo();
+            match step {
+                Some(step) => {
+                    put_back_settings(app, step.after)?;
+                    app.set_status(format!("redo: {}", step.before.describe(&step.after)));
+                }
+                None => app.set_status("Nothing to redo".to_string()),
+            }
+        }
+        Action::FilterNarrower | Action::FilterWider => {
+            let (mode, width) = {
+                let state = app.state.read();
+                (state.decoder.mode, state.decoder.filter_width)
+            };
+            match channelizer::step_width(mode, width, action == Action::FilterWider) {
+                Some(width) => {
+                    app.send_command(Command::SetFilterWidth(width))?;
+                    app.set_status(format!("Filter width: {:.1} kHz", width as f64 / 1000.0));
+                }
+                None => app.set_status(format!("{} has a fixed filter width", mode.name())),
+            }
+        }
+        Action::GainAssist => {
+            let started = app.receivers.as_ref().map(|receivers| {
+                let receiver = receivers.current().clone();
+                gain_assist::start_gain_sweep(receiver.state, receiver.commands)
+            });
+            match started {
+                Some(true) => app.set_status("Gain sweep started"),
+                Some(false) => app.set_status("Gain sweep already running"),
+                None => app.set_status("No receiver"),
+            }
+        }
+        Action::CalibratePpm => {
+            let started = app.receivers.as_ref().map(|receivers| {
+                let receiver = receivers.current().clone();
+                calibrate::start_calibration(receiver.state, receiver.commands)
+            });
+            match started {
+                Some(true) => app.set_status("PPM calibration started"),
+                Some(false) => app.set_status("PPM calibration already running"),
+                None => app.set_status("No receiver"),
+            }
+        }
+        Action::CancelGainSweep => {
+            let mut state = app.state.write();
+            if state.sdr.gain_sweep.is_none() {
+                return Ok(false);
+            }
+            state.sdr.gain_sweep_cancel = true;
+        }
+        Action::NextReceiver => {
+            if app.receiver_count() > 1 {
+                let selected = app.next_receiver();
+                let count = app.receiver_count();
+                app.set_status(format!("Receiver {}/{}", selected + 1, count));
+            } else {
+                app.set_status("Only one receiver");
+            }
         }
 
         // Navigation between controls
-        (KeyCode::Tab, KeyModifiers::NONE) => {
+        Action::NextControl => {
             let current = app.state.read().ui.selected_control;
             app.state.write().ui.selected_control = current.next();
-            return Ok(());
         }
-        (KeyCode::BackTab, KeyModifiers::SHIFT) => {
+        Action::PrevControl => {
             let current = app.state.read().ui.selected_control;
             app.state.write().ui.selected_control = current.prev();
-            return Ok(());
         }
 
-        _ => {}
+        _ => return Ok(false),
     }
+    Ok(true)
+}
 
-    // Control-specific key bindings
-    let selected = app.state.read().ui.selected_control;
-    match selected {
-        ControlId::Frequency => handle_frequency_keys(app, key)?,
-        ControlId::Mode => handle_mode_keys(app, key)?,
-        ControlId::Gain => handle_gain_keys(app, key)?,
-        ControlId::SampleRate => handle_sample_rate_keys(app, key)?,
-        ControlId::Record => handle_record_keys(app, key)?,
+/// Handle keys while a modal dialog is open
+fn handle_modal_keys(app: &mut App, modal: Modal, key: KeyEvent) {
+    app.state.write().ui.modal = None;
+
+    match modal {
+        Modal::ConfirmQuit(_) => {
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                app.quit();
+            } else {
+                app.set_status("Quit cancelled");
+            }
+        }
+        Modal::ConfirmClearMessages(_) => {
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                let mut state = app.state.write();
+                state.decoder.clear_messages();
+                state.ui.message_view.resume();
+                state.ui.status_message = "Decoded messages cleared".to_string();
+            } else {
+                app.set_status("Clear cancelled");
+            }
+        }
+        Modal::History { selected } => {
+            let entries = app.state.read().ui.frequency_history.entries().to_vec();
+            let picked = match key.code {
+                KeyCode::Up | KeyCode::Char('k') => {
+                    let selected = selected.saturating_sub(1);
+                    app.state.write().ui.modal = Some(Modal::History { selected });
+                    return;
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    let selected = (selected + 1).min(entries.len().saturating_sub(1));
+                    app.state.write().ui.modal = Some(Modal::History { selected });
+                    return;
+                }
+                KeyCode::Enter => entries.get(selected),
+                KeyCode::Char('0') => entries.get(9),
+                KeyCode::Char(c @ '1'..='9') => entries.get(c as usize - '1' as usize),
+                _ => None,
+            };
+            if let Some(&entry) = picked {
+                if let Err(e) = tune_to_entry(app, entry) {
+                    app.set_status(format!("Retune failed: {}", e));
+                }
+            }
+        }
+        Modal::Profiles { selected } => {
+            let count = app.profiles.len();
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => {
+                    let selected = selected.saturating_sub(1);
+                    app.state.write().ui.modal = Some(Modal::Profiles { selected });
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    let selected = (selected + 1).min(count.saturating_sub(1));
+                    app.state.write().ui.modal = Some(Modal::Profiles { selected });
+                }
+                KeyCode::Enter => {
+                    if let Some(profile) = app.profiles.get(selected).cloned() {
+                        if let Err(e) = switch_profile(app, &profile) {
+                            app.set_status(format!("Profile switch failed: {}", e));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Modal::Bookmarks { selected } => {
+            let count = app.state.read().ui.bookmarks.entries().len();
+            let selected = match key.code {
+                KeyCode::Up | KeyCode::Char('k') => selected.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => (selected + 1).min(count.saturating_sub(1)),
+                KeyCode::Enter => {
+                    if let Err(e) = recall_bookmark(app, selected) {
+                        app.set_status(format!("Retune failed: {}", e));
+                    }
+                    return;
+                }
+                KeyCode::Char('a') => add_bookmark(app),
+                KeyCode::Char('d') => {
+                    remove_bookmark(app, selected);
+                    selected.min(count.saturating_sub(2))
+                }
+                KeyCode::Char('s') => {
+                    store_bookmark_levels(app, selected);
+                    selected
+                }
+                KeyCode::Char('i') => {
+                    import_bookmarks(app);
+                    selected
+                }
+                KeyCode::Char('e') => {
+                    export_bookmarks(app);
+                    selected
+                }
+                _ => return,
+            };
+            app.state.write().ui.modal = Some(Modal::Bookmarks { selected });
+        }
+        Modal::CalibrateLevel { peak, mut input } => {
+            match key.code {
+                KeyCode::Char(c) if c.is_ascii_digit() || matches!(c, '-' | '+' | '.') => {
+                    input.push(c);
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => return calibrate_level(app, peak, &input),
+                _ => {
+                    app.set_status("Level calibration cancelled");
+                    return;
+                }
+            }
+            app.state.write().ui.modal = Some(Modal::CalibrateLevel { peak, input });
+        }
+        Modal::ApplyPpm { ppm, .. } => {
+            if !matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                app.set_status("PPM correction left as it was");
+                return;
+            }
+            if let Err(e) = app.send_command(Command::SetPpmError(ppm)) {
+                app.set_status(format!("PPM correction not applied: {}", e));
+                return;
+            }
+            match app.save_setting("sdr", "ppm_error", &ppm.to_string()) {
+                Ok(()) => app.set_status(format!("PPM correction: {}", ppm)),
+                Err(e) => {
+                    log::warn!("Failed to save PPM correction: {:#}", e);
+                    app.set_status(format!("PPM correction: {} (not saved: {})", ppm, e));
+                }
+            }
+        }
+    }
+}
+
+/// Set the receiver up as `profile` says: the mode and squelch at once, then
+/// the tuning, sample rate and gain through the receiver
+fn switch_profile(app: &mut App, profile: &Profile) -> Result<()> {
+    let settings = &profile.settings;
+    let recording = {
+        let mut state = app.state.write();
+        let recording = state.recording.is_recording;
+        // A recording carries on as it was started
+        let mut settings = settings.clone();
+        if recording {
+            settings.record_dir = None;
+            settings.record_mode = None;
+            settings.record_format = None;
+        }
+        settings.apply(&mut state);
+        recording
+    };
+    if let Some(mode) = settings.mode {
+        // Picks the mode's sample rate, unless the profile gives one
+        app.send_command(Command::SetMode(mode))?;
+    }
+    if let Some(frequency) = settings.frequency {
+        app.send_command(Command::SetFrequency(frequency))?;
+        app.send_command(Command::SetChannelOffset(Chain::A, 0))?;
+    }
+    if let Some(rate) = settings.sample_rate {
+        app.send_command(Command::SetSampleRate(rate))?;
+    }
+    if let Some(gain) = settings.gain {
+        app.send_command(Command::SetTunerGain((gain * 10.0).round() as i32))?;
+    }
+
+    let mut status = format!("Profile {}", profile.name);
+    let recorded = settings.record_dir.is_some()
+        || settings.record_mode.is_some()
+        || settings.record_format.is_some();
+    if recording && recorded {
+        status.push_str(" (recording options left as they are while recording)");
+    } else if settings.audio_port.is_some() || settings.stream_codec.is_some() {
+        status.push_str(" (streaming options take effect on restart)");
+    }
+    log::info!("Switched to profile {}: {}", profile.name, profile.summary());
+    app.set_status(status);
+    Ok(())
+}
+
+/// Tune chain A to bookmark `index` in its mode and filter width; its
+/// levels follow once the receiver is there
+fn recall_bookmark(app: &mut App, index: usize) -> Result<()> {
+    let Some(bookmark) = app.state.read().ui.bookmarks.entries().get(index).cloned() else {
+        return Ok(());
+    };
+    tune_to_entry(
+        app,
+        Tuned {
+            frequency: bookmark.frequency,
+            mode: bookmark.mode,
+        },
+    )?;
+    if let Some(width) = bookmark.bandwidth {
+        app.send_command(Command::SetFilterWidth(width))?;
+    }
+    let current = current_levels(app);
+    let left = app
+        .state
+        .write()
+        .ui
+        .level_override
+        .recall(bookmark.frequency, bookmark.overrides, current);
+    if let Some(write_back) = left {
+        write_back_levels(app, write_back);
+    }
+    let mut status = format!(
+        "{}: {:.4} MHz {}",
+        bookmark.name,
+        bookmark.frequency as f64 / 1_000_000.0,
+        bookmark.mode.name()
+    );
+    if !bookmark.overrides.is_empty() {
+        status.push_str(&format!(" ({})", bookmark.overrides.summary()));
+    }
+    app.set_status(status);
+    Ok(())
+}
+
+/// Bookmark chain A's frequency, mode and filter width; the row it lands on
+fn add_bookmark(app: &mut App) -> usize {
+    let tuned = tuned_a(app);
+    let name = format!("{:.4} MHz", tuned.frequency as f64 / 1_000_000.0);
+    let index = {
+        let mut state = app.state.write();
+        let bandwidth = state.decoder.filter_width;
+        let bookmarks = &mut state.ui.bookmarks;
+        bookmarks.add(Bookmark {
+            frequency: tuned.frequency,
+            name: name.clone(),
+            mode: tuned.mode,
+            bandwidth,
+            tags: Vec::new(),
+            overrides: LevelOverrides::default(),
+        });
+        bookmarks
+            .entries()
+            .iter()
+            .position(|b| b.frequency == tuned.frequency)
+            .unwrap_or(0)
+    };
+    save_bookmarks(app);
+    app.set_status(format!("Bookmarked {} {}", name, tuned.mode.name()));
+    index
+}
+
+fn remove_bookmark(app: &mut App, index: usize) {
+    let removed = app.state.write().ui.bookmarks.remove(index);
+    if let Some(bookmark) = removed {
+        save_bookmarks(app);
+        app.set_status(format!("Removed bookmark {}", bookmark.name));
+    }
+}
+
+/// Give bookmark `index` the squelch and gain set now, as far as a
+/// bookmark can hold them
+fn store_bookmark_levels(app: &mut App, index: usize) {
+    let current = current_levels(app);
+    let stored = {
+        let mut state = app.state.write();
+        state.ui.bookmarks.get_mut(index).map(|bookmark| {
+            bookmark.overrides = LevelOverrides {
+                squelch: current.squelch,
+                gain: current.gain,
+            };
+            (bookmark.name.clone(), bookmark.overrides)
+        })
+    };
+    let Some((name, overrides)) = stored else {
+        return;
+    };
+    save_bookmarks(app);
+    if overrides.is_empty() {
+        app.set_status(format!("{}: global squelch and gain", name));
+    } else {
+        app.set_status(format!("{}: {}", name, overrides.summary()));
+    }
+}
+
+/// Save levels set by hand on a bookmark to it
+fn write_back_levels(app: &mut App, write_back: WriteBack) {
+    let name = {
+        let mut state = app.state.write();
+        let Some(bookmark) = state.ui.bookmarks.at_mut(write_back.frequency) else {
+            return;
+        };
+        let overrides = &mut bookmark.overrides;
+        overrides.squelch = write_back.overrides.squelch.or(overrides.squelch);
+        overrides.gain = write_back.overrides.gain.or(overrides.gain);
+        bookmark.name.clone()
+    };
+    save_bookmarks(app);
+    log::info!("Saved {} to bookmark {}", write_back.overrides.summary(), name);
+}
+
+/// The CSV file the bookmark list is exported to and imported from
+fn bookmark_file(app: &App) -> PathBuf {
+    app.state.read().recording.output_dir.join("bookmarks.csv")
+}
+
+/// Merge in the bookmarks from the exported CSV file, or from GQRX's
+/// bookmarks when there is none
+fn import_bookmarks(app: &mut App) {
+    let ours = bookmark_file(app);
+    let path = match bookmark_csv::gqrx_path() {
+        Some(gqrx) if !ours.exists() && gqrx.exists() => gqrx,
+        _ => ours,
+    };
+    let import = match bookmark_csv::read(&path) {
+        Ok(import) => import,
+        Err(e) => {
+            app.set_status(format!("Import failed: {:#}", e));
+            return;
+        }
+    };
+    for warning in &import.warnings {
+        log::warn!("{}: {}", path.display(), warning);
+    }
+    let summary = import.merge_into(&mut app.state.write().ui.bookmarks);
+    save_bookmarks(app);
+    app.set_status(format!("{} from {}", summary, path.display()));
+}
+
+/// Write the bookmark list to its CSV file
+fn export_bookmarks(app: &mut App) {
+    let path = bookmark_file(app);
+    let result = {
+        let state = app.state.read();
+        bookmark_csv::write(&path, state.ui.bookmarks.entries())
+            .map(|()| state.ui.bookmarks.entries().len())
+    };
+    match result {
+        Ok(count) => app.set_status(format!("{} bookmarks exported to {}", count, path.display())),
+        Err(e) => app.set_status(format!("Export failed: {:#}", e)),
+    }
+}
+
+/// Save the bookmark list to the config file
+fn save_bookmarks(app: &App) {
+    let list = app.state.read().ui.bookmarks.to_toml();
+    if let Err(e) = app.save_setting("bookmarks", "list", &list) {
+        log::warn!("Failed to save bookmarks: {:#}", e);
+    }
+}
+
+/// The squelch and gain set now
+fn current_levels(app: &App) -> Levels {
+    let state = app.state.read();
+    Levels {
+        squelch: state.decoder.squelch_level,
+        gain: state.sdr.manual_gain(),
+    }
+}
+
+/// The settings undo keeps track of
+fn current_settings(app: &App) -> Settings {
+    let state = app.state.read();
+    Settings {
+        frequency: state.sdr.frequency.saturating_add_signed(state.channels.offset_a),
+        mode: state.decoder.mode,
+        gain: state.sdr.manual_gain(),
+        squelch: state.decoder.squelch_level,
+        filter_width: state.decoder.filter_width,
+    }
+}
+
+/// Go back to settings from the undo history, sending only what differs
+fn put_back_settings(app: &mut App, to: Settings) -> Result<()> {
+    let current = current_settings(app);
+    if to.frequency != current.frequency || to.mode != current.mode {
+        tune_to_entry(
+            app,
+            Tuned {
+                frequency: to.frequency,
+                mode: to.mode,
+            },
+        )?;
+    }
+    if to.gain != current.gain {
+        match to.gain {
+            Some(gain) => app.send_command(Command::SetTunerGain(gain))?,
+            None => app.send_command(Command::SetTunerAgc(true))?,
+        }
     }
+    if to.mode == current.mode {
+        if to.squelch != current.squelch {
+            app.state.write().decoder.squelch_level = to.squelch;
+        }
+        if to.filter_width != current.filter_width {
+            if let Some(width) = to.filter_width.or(channelizer::default_width(to.mode)) {
+                app.send_command(Command::SetFilterWidth(width))?;
+            }
+        }
+    } else if let Some(width) = to.filter_width {
+        // Switching mode brings back that mode's own squelch and filter
+        // width; only a width set since needs sending, after the mode
+        app.send_command(Command::SetFilterWidth(width))?;
+    }
+    Ok(())
+}
 
+/// Apply a recalled bookmark's levels once chain A is on it, and put the
+/// earlier ones back once it has moved off; a priority check's trip away
+/// doesn't count as moving off
+fn follow_bookmark_levels(app: &mut App) -> Result<()> {
+    if app.state.read().priority.away() {
+        return Ok(());
+    }
+    let tuned = tuned_a(app).frequency;
+    let current = current_levels(app);
+    let change = app.state.write().ui.level_override.update(tuned, current);
+    let (levels, write_back) = match change {
+        None => return Ok(()),
+        Some(LevelChange::Apply(levels)) => (levels, None),
+        Some(LevelChange::Restore { levels, write_back }) => (levels, write_back),
+    };
+
+    if levels.squelch != current.squelch {
+        app.state.write().decoder.squelch_level = levels.squelch;
+    }
+    if levels.gain != current.gain {
+        match levels.gain {
+            Some(gain) => app.send_command(Command::SetTunerGain(gain))?,
+            None => app.send_command(Command::SetTunerAgc(true))?,
+        }
+    }
+    if let Some(write_back) = write_back {
+        write_back_levels(app, write_back);
+    }
+    match change {
+        Some(LevelChange::Apply(_)) => {
+            app.set_status(format!("Bookmark levels: {}", levels.summary()))
+        }
+        _ => app.set_status(format!("Levels restored: {}", levels.summary())),
+    }
     Ok(())
 }
 
-/// Handle frequency control keys
-fn handle_frequency_keys(app: &mut App, key: KeyEvent) -> Result<()> {
-    match key.code {
-        KeyCode::Up | KeyCode::Char('k') => {
+/// Calibrate levels at the current gain so the spectrum's peak, `peak`
+/// dBFS, reads as `dbm`, and save the offset
+fn calibrate_level(app: &mut App, peak: f32, dbm: &str) {
+    let Ok(dbm) = dbm.trim().parse::<f32>() else {
+        app.set_status(format!("Level calibration: '{}' is not a level in dBm", dbm));
+        return;
+    };
+    let offset = dbm - peak;
+    let (gain, (section, key, value)) = {
+        let mut state = app.state.write();
+        let gain = state.sdr.manual_gain();
+        (gain, state.sdr.calibration.set(gain, offset))
+    };
+    let at = match gain {
+        Some(gain) => format!("{}.{} dB gain", gain / 10, gain % 10),
+        None => "auto gain".to_string(),
+    };
+    match app.save_setting(section, &key, &value) {
+        Ok(()) => app.set_status(format!("Calibration: {:+.1} dB at {}", offset, at)),
+        Err(e) => {
+            log::warn!("Failed to save level calibration: {:#}", e);
+            app.set_status(format!("Calibration: {:+.1} dB at {} (not saved: {})", offset, at, e));
+        }
+    }
+}
+
+/// Frequency presets: action, frequency in Hz and description
+const PRESETS: [(Action, u32, &str); 10] = [
+    (Action::Preset1, 144_390_000, "APRS North America (144.390 MHz)"),
+    (Action::Preset2, 144_800_000, "APRS Europe (144.800 MHz)"),
+    (Action::Preset3, 162_400_000, "NOAA Weather 1 (162.400 MHz)"),
+    (Action::Preset4, 162_425_000, "NOAA Weather 2 (162.425 MHz)"),
+    (Action::Preset5, 162_450_000, "NOAA Weather 3 (162.450 MHz)"),
+    (Action::Preset6, 162_475_000, "NOAA Weather 4 (162.475 MHz)"),
+    (Action::Preset7, 162_500_000, "NOAA Weather 5 (162.500 MHz)"),
+    (Action::Preset8, 162_525_000, "NOAA Weather 6 (162.525 MHz)"),
+    (Action::Preset9, 162_550_000, "NOAA Weather 7 (162.550 MHz)"),
+    (Action::Preset0, 1_090_000_000, "ADS-B Aircraft (1090 MHz)"),
+];
+
+/// Handle frequency control actions
+fn handle_frequency_action(app: &mut App, action: Action) -> Result<()> {
+    // Channel B moves within the captured band; presets still retune
+    if app.state.read().ui.selected_chain == Chain::B {
+        let step = match action {
+            Action::FreqUpSmall => 10_000,
+            Action::FreqDownSmall => -10_000,
+            Action::FreqUpLarge => 100_000,
+            Action::FreqDownLarge => -100_000,
+            _ => 0,
+        };
+        if step != 0 {
+            let offset = app.state.read().channels.offset_b + step;
+            app.send_command(Command::SetChannelOffset(Chain::B, offset))?;
+            app.set_status(format!("Channel B offset {:+} kHz", step / 1000));
+            return Ok(());
+        }
+    }
+
+    // Where the center lands, for the frequency history
+    let from = tuned_a(app);
+    let center = {
+        let state = app.state.read();
+        let current = state.sdr.frequency;
+        let clamp = |frequency| state.sdr.capabilities.clamp_frequency(frequency);
+        match action {
+            Action::FreqUpSmall => clamp(current.saturating_add(100_000)),
+            Action::FreqDownSmall => clamp(current.saturating_sub(100_000)),
+            Action::FreqUpLarge => clamp(current.saturating_add(1_000_000)),
+            Action::FreqDownLarge => clamp(current.saturating_sub(1_000_000)),
+            _ => match PRESETS.iter().find(|(a, _, _)| *a == action) {
+                Some(&(_, freq, _)) => clamp(freq),
+                None => return Ok(()),
+            },
+        }
+    };
+
+    match action {
+        Action::FreqUpSmall => {
             // Increase frequency by 100 kHz
             app.send_command(Command::IncreaseFrequency(100_000))?;
             app.set_status("Frequency +100 kHz");
         }
-        KeyCode::Down | KeyCode::Char('j') => {
+        Action::FreqDownSmall => {
             // Decrease frequency by 100 kHz
             app.send_command(Command::DecreaseFrequency(100_000))?;
             app.set_status("Frequency -100 kHz");
         }
-        KeyCode::Right | KeyCode::Char('l') => {
+        Action::FreqUpLarge => {
             // Increase frequency by 1 MHz
             app.send_command(Command::IncreaseFrequency(1_000_000))?;
             app.set_status("Frequency +1 MHz");
         }
-        KeyCode::Left | KeyCode::Char('h') => {
+        Action::FreqDownLarge => {
             // Decrease frequency by 1 MHz
             app.send_command(Command::DecreaseFrequency(1_000_000))?;
             app.set_status("Frequency -1 MHz");
         }
-        // Quick select presets using number keys
-        KeyCode::Char('1') => {
-            app.send_command(Command::SetFrequency(144_390_000))?;
-            app.set_status("Preset: APRS North America (144.390 MHz)");
-        }
-        KeyCode::Char('2') => {
-            app.send_command(Command::SetFrequency(144_800_000))?;
-            app.set_status("Preset: APRS Europe (144.800 MHz)");
-        }
-        KeyCode::Char('3') => {
-            app.send_command(Command::SetFrequency(162_400_000))?;
-            app.set_status("Preset: NOAA Weather 1 (162.400 MHz)");
-        }
-        KeyCode::Char('4') => {
-            app.send_command(Command::SetFrequency(162_425_000))?;
-            app.set_status("Preset: NOAA Weather 2 (162.425 MHz)");
-        }
-        KeyCode::Char('5') => {
-            app.send_command(Command::SetFrequency(162_450_000))?;
-            app.set_status("Preset: NOAA Weather 3 (162.450 MHz)");
-        }
-        KeyCode::Char('6') => {
-            app.send_command(Command::SetFrequency(162_475_000))?;
-            app.set_status("Preset: NOAA Weather 4 (162.475 MHz)");
-        }
-        KeyCode::Char('7') => {
-            app.send_command(Command::SetFrequency(162_500_000))?;
-            app.set_status("Preset: NOAA Weather 5 (162.500 MHz)");
-        }
-        KeyCode::Char('8') => {
-            app.send_command(Command::SetFrequency(162_525_000))?;
-            app.set_status("Preset: NOAA Weather 6 (162.525 MHz)");
-        }
-        KeyCode::Char('9') => {
-            app.send_command(Command::SetFrequency(162_550_000))?;
-            app.set_status("Preset: NOAA Weather 7 (162.550 MHz)");
+        // Quick select presets
+        _ => {
+            if let Some(&(_, freq, name)) = PRESETS.iter().find(|(a, _, _)| *a == action) {
+                app.send_command(Command::SetFrequency(freq))?;
+                app.set_status(format!("Preset: {}", name));
+            }
         }
-        KeyCode::Char('0') => {
-            app.send_command(Command::SetFrequency(1_090_000_000))?;
-            app.set_status("Preset: ADS-B Aircraft (1090 MHz)");
+    }
+    let offset = app.state.read().channels.offset_a;
+    let to = Tuned {
+        frequency: center.saturating_add_signed(offset),
+        mode: from.mode,
+    };
+    let stepped = matches!(
+        action,
+        Action::FreqUpSmall | Action::FreqDownSmall | Action::FreqUpLarge | Action::FreqDownLarge
+    );
+    if stepped {
+        let changed = app.state.write().ui.frequency_history.step(from, to, Instant::now());
+        if changed {
+            save_history(app);
         }
-        _ => {}
+    } else {
+        remember_retune(app, from, to);
     }
     Ok(())
 }
 
-/// Handle mode control keys
-fn handle_mode_keys(app: &mut App, key: KeyEvent) -> Result<()> {
-    let current_mode = app.get_mode();
+/// Handle mode control actions
+fn handle_mode_action(app: &mut App, action: Action) -> Result<()> {
+    let chain = app.state.read().ui.selected_chain;
+    let current_mode = match chain {
+        Chain::A => app.get_mode(),
+        Chain::B => app.state.read().channels.mode_b,
+    };
     let modes = DemodMode::all();
     let current_idx = modes.iter().position(|&m| m == current_mode).unwrap_or(0);
 
-    match key.code {
-        KeyCode::Up | KeyCode::Char('k') | KeyCode::Right | KeyCode::Char('l') => {
-            let next_idx = (current_idx + 1) % modes.len();
-            let next_mode = modes[next_idx];
-            app.send_command(Command::SetMode(next_mode))?;
-            app.set_status(format!("Mode: {}", next_mode.name()));
-        }
-        KeyCode::Down | KeyCode::Char('j') | KeyCode::Left | KeyCode::Char('h') => {
+    let mode = match action {
+        Action::Increase => modes[(current_idx + 1) % modes.len()],
+        Action::Decrease => {
             let prev_idx = if current_idx == 0 {
                 modes.len() - 1
             } else {
                 current_idx - 1
             };
-            let prev_mode = modes[prev_idx];
-            app.send_command(Command::SetMode(prev_mode))?;
-            app.set_status(format!("Mode: {}", prev_mode.name()));
+            modes[prev_idx]
+        }
+        _ => return Ok(()),
+    };
+    match chain {
+        Chain::A => {
+            app.send_command(Command::SetMode(mode))?;
+            app.set_status(format!("Mode: {}", mode.name()));
+        }
+        // Raw turns channel B off
+        Chain::B => {
+            app.send_command(Command::SetChannelMode(Chain::B, mode))?;
+            let name = if mode == DemodMode::Raw { "Off" } else { mode.name() };
+            app.set_status(format!("Channel B: {}", name));
         }
-        _ => {}
     }
     Ok(())
 }
 
-/// Handle gain control keys
-fn handle_gain_keys(app: &mut App, key: KeyEvent) -> Result<()> {
+/// Handle gain control actions
+fn handle_gain_action(app: &mut App, action: Action) -> Result<()> {
     let current_gain = app.get_gain();
+    let (tuner_agc, rtl_agc, range) = {
+        let state = app.state.read();
+        (state.sdr.tuner_agc, state.sdr.rtl_agc, state.sdr.capabilities.gain.clone())
+    };
+    // The receiver's own gain range
+    let (min_gain, max_gain) = (*range.start(), *range.end());
 
-    match key.code {
-        KeyCode::Up | KeyCode::Char('k') | KeyCode::Right | KeyCode::Char('l') => {
-            if current_gain == -1 {
-                // Switch from auto to manual (start at 200 = 20.0 dB)
-                app.send_command(Command::SetTunerGain(200))?;
-                app.set_status("Gain: 20.0 dB");
+    match action {
+        Action::Increase => {
+            if tuner_agc || current_gain == -1 {
+                // Switch from auto to manual (start at 200 = 20.0 dB if never set)
+                let new_gain = if current_gain == -1 { 200 } else { current_gain };
+                let new_gain = new_gain.clamp(min_gain, max_gain);
+                app.send_command(Command::SetTunerGain(new_gain))?;
+                app.set_status(format!("Gain: {}.{} dB", new_gain / 10, new_gain % 10));
             } else {
                 // Increase gain by 5 dB (50 tenths)
-                let new_gain = (current_gain + 50).min(500);
+                let new_gain = (current_gain + 50).min(max_gain);
                 app.send_command(Command::SetTunerGain(new_gain))?;
                 app.set_status(format!("Gain: {}.{} dB", new_gain / 10, new_gain % 10));
             }
         }
-        KeyCode::Down | KeyCode::Char('j') | KeyCode::Left | KeyCode::Char('h') => {
-            if current_gain == -1 {
+        Action::Decrease => {
+            if tuner_agc {
                 // Already on auto
             } else {
                 // Decrease gain by 5 dB (50 tenths)
-                let new_gain = (current_gain - 50).max(0);
+                let new_gain = (current_gain - 50).max(min_gain);
                 app.send_command(Command::SetTunerGain(new_gain))?;
                 app.set_status(format!("Gain: {}.{} dB", new_gain / 10, new_gain % 10));
             }
         }
-        KeyCode::Char('a') => {
-            // Toggle auto gain
-            app.send_command(Command::SetAutoGain(true))?;
-            app.set_status("Gain: Auto");
+        Action::TunerAgc => {
+            // Toggle tuner auto gain
+            app.send_command(Command::SetTunerAgc(!tuner_agc))?;
+            app.set_status(if tuner_agc { "Tuner gain: Manual" } else { "Tuner gain: Auto" });
+        }
+        Action::RtlAgc => {
+            // Toggle RTL2832 digital AGC
+            app.send_command(Command::SetRtlAgc(!rtl_agc))?;
+            app.set_status(if rtl_agc { "RTL AGC: Off" } else { "RTL AGC: On" });
         }
         _ => {}
     }
     Ok(())
 }
 
-/// Handle sample rate control keys
-fn handle_sample_rate_keys(app: &mut App, key: KeyEvent) -> Result<()> {
-    let rates = crate::sdr::config::COMMON_SAMPLE_RATES;
-    let current_rate = app.get_sample_rate();
-    let current_idx = rates.iter().position(|&r| r == current_rate).unwrap_or(6); // Default to 2.048 MHz
+/// Handle squelch control actions
+fn handle_squelch_action(app: &mut App, action: Action) {
+    let current = app.state.read().decoder.squelch_level;
+
+    let new_level = match action {
+        Action::Increase => {
+            // Turning the squelch on starts at -40 dBFS
+            Some(current.map_or(-40.0, |level| (level + 2.0).min(0.0)))
+        }
+        Action::Decrease => {
+            // Going below -80 dBFS turns the squelch off
+            current.map(|level| level - 2.0).filter(|&level| level >= -80.0)
+        }
+        _ => return,
+    };
+
+    app.state.write().decoder.squelch_level = new_level;
+    match new_level {
+        Some(level) => app.set_status(format!("Squelch: {:.0} dBFS", level)),
+        None => app.set_status("Squelch: Off"),
+    }
+}
+
+/// Handle sample rate control actions
+fn handle_sample_rate_action(app: &mut App, action: Action) -> Result<()> {
+    let (rates, current_idx) = {
+        let state = app.state.read();
+        let capabilities = &state.sdr.capabilities;
+        (capabilities.sample_rates.clone(), capabilities.sample_rate_index(state.sdr.sample_rate))
+    };
+    if rates.is_empty() {
+        return Ok(());
+    }
 
-    match key.code {
-        KeyCode::Up | KeyCode::Char('k') | KeyCode::Right | KeyCode::Char('l') => {
+    match action {
+        Action::Increase => {
             let next_idx = (current_idx + 1).min(rates.len() - 1);
             let next_rate = rates[next_idx];
             app.send_command(Command::SetSampleRate(next_rate))?;
             app.set_status(format!("Sample Rate: {} kHz", next_rate / 1000));
         }
-        KeyCode::Down | KeyCode::Char('j') | KeyCode::Left | KeyCode::Char('h') => {
+        Action::Decrease => {
             let prev_idx = current_idx.saturating_sub(1);
             let prev_rate = rates[prev_idx];
             app.send_command(Command::SetSampleRate(prev_rate))?;
@@ -216,17 +1464,125 @@ fn handle_sample_rate_keys(app: &mut App, key: KeyEvent) -> Result<()> {
     Ok(())
 }
 
-/// Handle record control keys
-fn handle_record_keys(app: &mut App, key: KeyEvent) -> Result<()> {
-    match key.code {
-        KeyCode::Enter | KeyCode::Char(' ') => {
+/// Handle record control actions
+fn handle_record_action(app: &mut App, action: Action) -> Result<()> {
+    match action {
+        Action::Activate => {
             toggle_recording(app)?;
         }
+        Action::Increase | Action::Decrease => {
+            // The recording mode can only change between recordings
+            if app.is_recording() {
+                app.set_status("Stop recording before changing the recording mode");
+            } else {
+                let mode = app.state.read().recording.mode.toggle();
+                app.state.write().recording.mode = mode;
+                app.set_status(format!("Recording mode: {}", mode.name()));
+            }
+        }
+        Action::RecordFormat => {
+            // Like the mode, the format is for the next recording
+            if app.is_recording() {
+                app.set_status("Stop recording before changing the IQ format");
+            } else {
+                let format = app.state.read().recording.format.next();
+                app.state.write().recording.format = format;
+                app.set_status(format!("IQ recording format: {}", format.name()));
+            }
+        }
         _ => {}
     }
     Ok(())
 }
 
+/// Tune chain A to the selected activity table signal, retuning the center
+/// when the signal is no longer in the captured band
+fn tune_to_activity(app: &mut App) -> Result<()> {
+    let (selected, center, sample_rate) = {
+        let state = app.state.read();
+        let signals = state.spectrum.activity.signals();
+        let selected = state.ui.activity_selected.min(signals.len().saturating_sub(1));
+        (
+            signals.get(selected).map(|signal| signal.frequency),
+            state.sdr.frequency,
+            state.sdr.sample_rate,
+        )
+    };
+    let Some(frequency) = selected else {
+        app.set_status("No active signals");
+        return Ok(());
+    };
+
+    let from = tuned_a(app);
+    let offset = frequency as i64 - center as i64;
+    if offset.unsigned_abs() < sample_rate as u64 / 2 {
+        app.send_command(Command::SetChannelOffset(Chain::A, offset as i32))?;
+    } else {
+        app.send_command(Command::SetFrequency(frequency))?;
+        app.send_command(Command::SetChannelOffset(Chain::A, 0))?;
+    }
+    app.set_status(format!("Tuned to {:.4} MHz", frequency as f64 / 1_000_000.0));
+    remember_retune(app, from, Tuned { frequency, mode: from.mode });
+    Ok(())
+}
+
+/// Tune chain A back to a frequency from the history, in its mode
+fn tune_to_entry(app: &mut App, entry: Tuned) -> Result<()> {
+    let from = tuned_a(app);
+    let (center, sample_rate) = (app.get_frequency(), app.get_sample_rate());
+    let offset = entry.frequency as i64 - center as i64;
+    // A mode change may change the sample rate, so retune outright then
+    if entry.mode == from.mode && offset.unsigned_abs() < sample_rate as u64 / 2 {
+        app.send_command(Command::SetChannelOffset(Chain::A, offset as i32))?;
+    } else {
+        if entry.mode != from.mode {
+            app.send_command(Command::SetMode(entry.mode))?;
+        }
+        app.send_command(Command::SetFrequency(entry.frequency))?;
+        app.send_command(Command::SetChannelOffset(Chain::A, 0))?;
+    }
+    app.set_status(format!(
+        "Tuned to {:.4} MHz {}",
+        entry.frequency as f64 / 1_000_000.0,
+        entry.mode.name()
+    ));
+    remember_retune(app, from, entry);
+    Ok(())
+}
+
+/// Chain A's frequency and mode, as the frequency history keeps them
+fn tuned_a(app: &App) -> Tuned {
+    let state = app.state.read();
+    Tuned {
+        frequency: state.sdr.frequency.saturating_add_signed(state.channels.offset_a),
+        mode: state.decoder.mode,
+    }
+}
+
+/// Note a manual retune in the frequency history, saving the history to
+/// the config file when it changed
+fn remember_retune(app: &App, from: Tuned, to: Tuned) {
+    if app.state.write().ui.frequency_history.retune(from, to) {
+        save_history(app);
+    }
+}
+
+/// Put where stepping stopped in the frequency history now, as it is about
+/// to be used
+fn settle_history(app: &App) {
+    if app.state.write().ui.frequency_history.settle_now() {
+        save_history(app);
+    }
+}
+
+/// Save the frequency history to the config file
+fn save_history(app: &App) {
+    let recent = app.state.read().ui.frequency_history.to_toml();
+    if let Err(e) = app.save_setting("history", "recent", &recent) {
+        log::warn!("Failed to save frequency history: {:#}", e);
+    }
+}
+
 /// Toggle recording on/off
 fn toggle_recording(app: &mut App) -> Result<()> {
     let is_recording = app.is_recording();
@@ -234,12 +1590,13 @@ fn toggle_recording(app: &mut App) -> Result<()> {
         app.send_command(Command::StopRecording)?;
         app.set_status("Recording stopped");
     } else {
-        // Generate filename with timestamp
-        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-        let filename = format!("recording_{}.iq", timestamp);
-        let path = std::path::PathBuf::from(filename);
-        app.send_command(Command::StartRecording(path))?;
-        app.set_status("Recording started");
+        let command = app.state.read().start_recording_command();
+        let status = match command {
+            Command::StartSquelchRecording => "Squelch recording started",
+            _ => "Recording started",
+        };
+        app.send_command(command)?;
+        app.set_status(status);
     }
     Ok(())
 }
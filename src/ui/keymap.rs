@@ -0,0 +1,676 @@
+//! Key bindings
+//!
+//! Every key the UI reacts to is bound to an [`Action`] by name. The config
+//! file can rebind actions under `[keys]`; anything left out keeps its
+//! default:
+//!
+//! ```toml
+//! [keys]
+//! next_control = ["f2", "tab"]
+//! quit = "ctrl+q"
+//! ```
+//!
+//! Key specs are a key name with optional `ctrl+`, `alt+` and `shift+`
+//! prefixes: `"q"`, `"A"`, `"ctrl+c"`, `"shift+tab"`, `"f5"`, `"pageup"`.
+//!
+//! Tab moves focus between panes, and keys for a pane only reach it while it
+//! has focus; with the controls focused every control key works as it always
+//! has. To keep Tab for stepping through the controls instead:
+//!
+//! ```toml
+//! [keys]
+//! next_control = "tab"
+//! prev_control = "shift+tab"
+//! next_pane = "f"
+//! prev_pane = "F"
+//! ```
+
+use crate::state::{ControlId, PaneId};
+use crate::types::KeyList;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Where an action applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Whichever control is selected
+    Global,
+    /// The frequency control
+    Frequency,
+    /// Every control other than frequency
+    Adjust,
+    /// The gain control
+    Gain,
+    /// The record control
+    Record,
+    /// While the waterfall is paused; takes precedence over global keys
+    Paused,
+    /// While an IQ file is played; takes precedence over global keys
+    Playback,
+    /// While the controls pane has focus, whichever control is selected
+    Controls,
+    /// While the spectrum pane has focus; takes precedence over global keys
+    Spectrum,
+    /// While the waterfall pane has focus; takes precedence over global keys
+    Waterfall,
+    /// While the decoder pane has focus; takes precedence over global keys
+    Decoder,
+}
+
+impl Scope {
+    /// Whether a key could reach actions in both scopes
+    fn overlaps(self, other: Scope) -> bool {
+        match (self, other) {
+            (Scope::Paused, b) | (b, Scope::Paused) => b == Scope::Paused,
+            (Scope::Playback, b) | (b, Scope::Playback) => b == Scope::Playback,
+            (Scope::Spectrum, b) | (b, Scope::Spectrum) => b == Scope::Spectrum,
+            (Scope::Waterfall, b) | (b, Scope::Waterfall) => b == Scope::Waterfall,
+            (Scope::Decoder, b) | (b, Scope::Decoder) => b == Scope::Decoder,
+            (Scope::Global, _) | (_, Scope::Global) => true,
+            (Scope::Controls, _) | (_, Scope::Controls) => true,
+            (Scope::Adjust, Scope::Gain | Scope::Record)
+            | (Scope::Gain | Scope::Record, Scope::Adjust) => true,
+            (a, b) => a == b,
+        }
+    }
+}
+
+/// Defines [`Action`] with its config name, scope and default keys
+macro_rules! actions {
+    ($($variant:ident => $name:literal, $scope:ident, [$($key:literal),*];)*) => {
+        /// Something a key press can do
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Action {
+            $($variant,)*
+        }
+
+        impl Action {
+            pub const ALL: &'static [Action] = &[$(Action::$variant,)*];
+
+            /// Name used in the config file
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Action::$variant => $name,)*
+                }
+            }
+
+            pub fn scope(self) -> Scope {
+                match self {
+                    $(Action::$variant => Scope::$scope,)*
+                }
+            }
+
+            fn default_keys(self) -> &'static [&'static str] {
+                match self {
+                    $(Action::$variant => &[$($key),*],)*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    Quit => "quit", Global, ["q", "ctrl+c"];
+    ToggleRecord => "toggle_record", Global, ["r"];
+    ToggleDtmf => "toggle_dtmf", Global, ["d"];
+    ToggleMonitor => "toggle_monitor", Global, ["m"];
+    ToggleDecodeLog => "toggle_decode_log", Global, ["L"];
+    ToggleAfSpectrum => "toggle_af_spectrum", Global, ["o"];
+    ToggleConstellation => "toggle_constellation", Global, ["v"];
+    ToggleStats => "toggle_stats", Global, ["i"];
+    CycleTheme => "cycle_theme", Global, ["T"];
+    SpectrumMode => "spectrum_mode", Global, ["b"];
+    SpectrumInvert => "spectrum_invert", Global, ["I"];
+    ExportWaterfall => "export_waterfall", Global, ["X"];
+    WaterfallSlower => "waterfall_slower", Global, ["{"];
+    WaterfallFaster => "waterfall_faster", Global, ["}"];
+    WaterfallIntegration => "waterfall_integration", Global, ["W"];
+    SelectChain => "select_chain", Global, ["x"];
+    TogglePriority => "toggle_priority", Global, ["P"];
+    ToggleScope => "toggle_scope", Global, ["w"];
+    ScopeTrigger => "scope_trigger", Global, ["t"];
+    ScopeZoomIn => "scope_zoom_in", Global, ["+", "="];
+    ScopeZoomOut => "scope_zoom_out", Global, ["-"];
+    AircraftSort => "aircraft_sort", Global, ["s"];
+    AircraftPageUp => "aircraft_page_up", Global, ["pageup"];
+    AircraftPageDown => "aircraft_page_down", Global, ["pagedown"];
+    ToggleActivity => "toggle_activity", Global, ["S"];
+    ActivityPrev => "activity_prev", Global, [","];
+    ActivityNext => "activity_next", Global, ["."];
+    ActivityTune => "activity_tune", Global, ["g"];
+    NextReceiver => "next_receiver", Global, ["R"];
+    ToggleClock => "toggle_clock", Global, ["U"];
+    Undo => "undo", Global, ["u"];
+    Redo => "redo", Global, ["ctrl+r"];
+    FilterNarrower => "filter_narrower", Global, ["["];
+    FilterWider => "filter_wider", Global, ["]"];
+    GainAssist => "gain_assist", Global, ["G"];
+    CancelGainSweep => "cancel_gain_sweep", Global, ["esc"];
+    CalibratePpm => "calibrate_ppm", Global, ["K"];
+    FrequencyFlip => "frequency_flip", Global, ["`", "backspace"];
+    ShowHistory => "show_history", Global, ["H"];
+    ShowProfiles => "show_profiles", Global, ["O"];
+    ShowBookmarks => "show_bookmarks", Global, ["'"];
+    SwapVfo => "swap_vfo", Global, ["V"];
+    CopyVfo => "copy_vfo", Global, ["B"];
+    TestTone => "test_tone", Global, ["ctrl+t"];
+    NoiseReduction => "noise_reduction", Global, ["z"];
+    AgcSpeed => "agc_speed", Global, ["alt+a"];
+    Deemphasis => "deemphasis", Global, ["alt+d"];
+    BfoDown => "bfo_down", Global, ["("];
+    BfoUp => "bfo_up", Global, [")"];
+    PlaceMarker => "place_marker", Global, ["M"];
+    ClearMarkers => "clear_markers", Global, ["alt+m"];
+    CalibrateLevel => "calibrate_level", Global, ["C"];
+    NextPane => "next_pane", Global, ["tab"];
+    PrevPane => "prev_pane", Global, ["shift+tab"];
+    ToggleSpectrumPane => "toggle_spectrum_pane", Global, ["f2"];
+    ToggleWaterfallPane => "toggle_waterfall_pane", Global, ["f3"];
+    ToggleControlsPane => "toggle_controls_pane", Global, ["f4"];
+    ToggleDecoderPane => "toggle_decoder_pane", Global, ["f5"];
+    NextControl => "next_control", Controls, ["n"];
+    PrevControl => "prev_control", Controls, ["N"];
+    FreqUpSmall => "freq_up_small", Frequency, ["up", "k"];
+    FreqDownSmall => "freq_down_small", Frequency, ["down", "j"];
+    FreqUpLarge => "freq_up_large", Frequency, ["right", "l"];
+    FreqDownLarge => "freq_down_large", Frequency, ["left", "h"];
+    Preset1 => "preset_1", Frequency, ["1"];
+    Preset2 => "preset_2", Frequency, ["2"];
+    Preset3 => "preset_3", Frequency, ["3"];
+    Preset4 => "preset_4", Frequency, ["4"];
+    Preset5 => "preset_5", Frequency, ["5"];
+    Preset6 => "preset_6", Frequency, ["6"];
+    Preset7 => "preset_7", Frequency, ["7"];
+    Preset8 => "preset_8", Frequency, ["8"];
+    Preset9 => "preset_9", Frequency, ["9"];
+    Preset0 => "preset_0", Frequency, ["0"];
+    Increase => "increase", Adjust, ["up", "k", "right", "l"];
+    Decrease => "decrease", Adjust, ["down", "j", "left", "h"];
+    TunerAgc => "tuner_agc", Gain, ["a"];
+    RtlAgc => "rtl_agc", Gain, ["A"];
+    Activate => "activate", Record, ["enter", "space"];
+    RecordFormat => "record_format", Record, ["E"];
+    WaterfallOlder => "waterfall_older", Paused, ["pageup"];
+    WaterfallNewer => "waterfall_newer", Paused, ["pagedown"];
+    WaterfallPause => "waterfall_pause", Waterfall, ["p"];
+    ClearMessages => "clear_messages", Decoder, ["c"];
+    PauseMessages => "pause_messages", Decoder, ["p"];
+    ExportMessages => "export_messages", Decoder, ["e"];
+    ExpandMessages => "expand_messages", Decoder, ["enter"];
+    PlaybackPause => "playback_pause", Playback, ["ctrl+p"];
+    SeekBack => "seek_back", Playback, ["alt+left"];
+    SeekForward => "seek_forward", Playback, ["alt+right"];
+    PlaybackSpeed => "playback_speed", Playback, [">"];
+    Seek0 => "seek_0", Playback, ["alt+0"];
+    Seek1 => "seek_1", Playback, ["alt+1"];
+    Seek2 => "seek_2", Playback, ["alt+2"];
+    Seek3 => "seek_3", Playback, ["alt+3"];
+    Seek4 => "seek_4", Playback, ["alt+4"];
+    Seek5 => "seek_5", Playback, ["alt+5"];
+    Seek6 => "seek_6", Playback, ["alt+6"];
+    Seek7 => "seek_7", Playback, ["alt+7"];
+    Seek8 => "seek_8", Playback, ["alt+8"];
+    Seek9 => "seek_9", Playback, ["alt+9"];
+}
+
+/// Scopes a key is looked up in, first to last, with `pane` focused,
+/// `control` selected, the waterfall `paused` or not and a file `playing`
+/// or not
+pub fn routing(pane: PaneId, control: ControlId, paused: bool, playing: bool) -> Vec<Scope> {
+    let mut scopes = Vec::new();
+    if paused {
+        scopes.push(Scope::Paused);
+    }
+    if playing {
+        scopes.push(Scope::Playback);
+    }
+    match pane {
+        PaneId::Spectrum => scopes.push(Scope::Spectrum),
+        PaneId::Waterfall => scopes.push(Scope::Waterfall),
+        PaneId::Decoder => scopes.push(Scope::Decoder),
+        PaneId::Controls => {}
+    }
+    // Global actions that don't apply let the key through to the control
+    scopes.push(Scope::Global);
+    if pane == PaneId::Controls {
+        // The control's own before the shared ones
+        scopes.push(Scope::Controls);
+        scopes.extend_from_slice(match control {
+            ControlId::Frequency => &[Scope::Frequency][..],
+            ControlId::Gain => &[Scope::Gain, Scope::Adjust],
+            ControlId::Record => &[Scope::Record, Scope::Adjust],
+            _ => &[Scope::Adjust],
+        });
+    }
+    scopes
+}
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+/// A key with its modifiers, as written in the config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySpec {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeySpec {
+    /// The spec a key event matches; shift is carried by the character
+    /// itself (or by BackTab) rather than the modifiers
+    pub fn from_event(event: KeyEvent) -> Self {
+        let mut modifiers =
+            event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if matches!(event.code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code: event.code, modifiers }
+    }
+
+    /// Short form for the help panel, e.g. "↑" or "Ctrl+C"
+    pub fn label(&self) -> String {
+        let mut label = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            label.push_str("Ctrl+");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            label.push_str("Alt+");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            label.push_str("Shift+");
+        }
+        let key = match self.code {
+            KeyCode::Up => "↑".to_string(),
+            KeyCode::Down => "↓".to_string(),
+            KeyCode::Left => "←".to_string(),
+            KeyCode::Right => "→".to_string(),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            KeyCode::Tab => "Tab".to_string(),
+            KeyCode::BackTab => "Shift+Tab".to_string(),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) if self.modifiers.is_empty() => c.to_string(),
+            KeyCode::Char(c) => c.to_ascii_uppercase().to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            code => KeySpec { code, modifiers: KeyModifiers::NONE }.to_string(),
+        };
+        label + &key
+    }
+}
+
+impl fmt::Display for KeySpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, prefix) in [
+            (KeyModifiers::CONTROL, "ctrl+"),
+            (KeyModifiers::ALT, "alt+"),
+            (KeyModifiers::SHIFT, "shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(prefix)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::BackTab => f.write_str("shift+tab"),
+            code => {
+                let name = NAMED_KEYS
+                    .iter()
+                    .find(|(_, c)| *c == code)
+                    .map_or("?", |(name, _)| name);
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+/// Keys spelled out by name
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("tab", KeyCode::Tab),
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+];
+
+impl std::str::FromStr for KeySpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        // The key is whatever follows the last '+', which may itself be '+'
+        let (prefix, key) = if spec == "+" {
+            ("", "+")
+        } else if let Some(prefix) = spec.strip_suffix("++") {
+            (prefix, "+")
+        } else {
+            spec.rsplit_once('+').unwrap_or(("", spec))
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in prefix.split('+').filter(|m| !m.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{}' in key '{}'", modifier, spec)),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let lower = key.to_ascii_lowercase();
+                if lower == "space" {
+                    KeyCode::Char(' ')
+                } else if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    KeyCode::F(n)
+                } else {
+                    NAMED_KEYS
+                        .iter()
+                        .find(|(name, _)| *name == lower)
+                        .map(|(_, code)| *code)
+                        .ok_or_else(|| format!("unknown key '{}'", spec))?
+                }
+            }
+        };
+
+        // Shift is spelled by the character itself, and Shift+Tab is BackTab
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+        Ok(Self { code, modifiers })
+    }
+}
+
+/// The effective binding of every action
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// Keys per action, in [`Action::ALL`] order
+    bindings: Vec<(Action, Vec<KeySpec>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .map(|&action| {
+                let keys = action
+                    .default_keys()
+                    .iter()
+                    .map(|spec| spec.parse().expect("default key specs are valid"))
+                    .collect();
+                (action, keys)
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// The defaults with the config file's `[keys]` applied; returns the
+    /// problems found (unknown actions or keys, conflicts) as readable lines
+    pub fn from_config(overrides: &BTreeMap<String, KeyList>) -> (Self, Vec<String>) {
+        let mut keymap = Self::default();
+        let mut problems = Vec::new();
+
+        for (name, specs) in overrides {
+            let Some(action) = Action::from_name(name) else {
+                problems.push(format!("unknown action '{}'", name));
+                continue;
+            };
+            let mut keys = Vec::new();
+            for spec in specs.as_slice() {
+                match spec.parse() {
+                    Ok(key) => keys.push(key),
+                    Err(e) => problems.push(format!("{}: {}", name, e)),
+                }
+            }
+            if let Some(binding) = keymap.bindings.iter_mut().find(|(a, _)| *a == action) {
+                binding.1 = keys;
+            }
+        }
+
+        problems.extend(keymap.conflicts());
+        (keymap, problems)
+    }
+
+    /// Keys bound to two actions that can be active at once
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        for (i, (a, a_keys)) in self.bindings.iter().enumerate() {
+            for (b, b_keys) in &self.bindings[i + 1..] {
+                if !a.scope().overlaps(b.scope()) {
+                    continue;
+                }
+                for key in a_keys.iter().filter(|k| b_keys.contains(k)) {
+                    conflicts.push(format!(
+                        "'{}' is bound to both {} and {}",
+                        key,
+                        a.name(),
+                        b.name()
+                    ));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// The action `event` triggers in `scope`, if any; the earliest
+    /// action wins when bindings conflict
+    pub fn lookup(&self, scope: Scope, event: KeyEvent) -> Option<Action> {
+        let key = KeySpec::from_event(event);
+        self.bindings
+            .iter()
+            .find(|(action, keys)| action.scope() == scope && keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeySpec] {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map_or(&[], |(_, keys)| keys.as_slice())
+    }
+
+    /// Help-panel label for an action's keys, e.g. "q/Ctrl+C"; "-" if unbound
+    pub fn label(&self, action: Action) -> String {
+        let keys = self.keys(action);
+        if keys.is_empty() {
+            return "-".to_string();
+        }
+        keys.iter().map(KeySpec::label).collect::<Vec<_>>().join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(s: &str) -> KeySpec {
+        s.parse().unwrap()
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_parse_key_specs() {
+        assert_eq!(spec("q"), KeySpec { code: KeyCode::Char('q'), modifiers: KeyModifiers::NONE });
+        assert_eq!(spec("ctrl+c").modifiers, KeyModifiers::CONTROL);
+        assert_eq!(spec("Ctrl+Alt+x").modifiers, KeyModifiers::CONTROL | KeyModifiers::ALT);
+        assert_eq!(spec("shift+tab").code, KeyCode::BackTab);
+        assert_eq!(spec("shift+a"), spec("A"));
+        assert_eq!(spec("F5").code, KeyCode::F(5));
+        assert_eq!(spec("space").code, KeyCode::Char(' '));
+        assert_eq!(spec("PageUp").code, KeyCode::PageUp);
+        assert_eq!(spec("+").code, KeyCode::Char('+'));
+        assert_eq!(spec("ctrl++").code, KeyCode::Char('+'));
+        assert_eq!(spec("ctrl++").modifiers, KeyModifiers::CONTROL);
+
+        assert!("hyper+q".parse::<KeySpec>().is_err());
+        assert!("f".parse::<KeySpec>().is_ok());
+        assert!("foo".parse::<KeySpec>().is_err());
+    }
+
+    #[test]
+    fn test_spec_round_trip() {
+        for s in ["q", "ctrl+c", "shift+tab", "f12", "space", "pagedown", "alt+left", "L"] {
+            assert_eq!(spec(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_events_match_specs() {
+        let keymap = Keymap::default();
+        let lookup = |code, modifiers| keymap.lookup(Scope::Global, key(code, modifiers));
+
+        assert_eq!(lookup(KeyCode::Char('q'), KeyModifiers::NONE), Some(Action::Quit));
+        assert_eq!(lookup(KeyCode::Char('c'), KeyModifiers::CONTROL), Some(Action::Quit));
+        // Terminals report capitals with or without Shift
+        assert_eq!(lookup(KeyCode::Char('L'), KeyModifiers::SHIFT), Some(Action::ToggleDecodeLog));
+        assert_eq!(lookup(KeyCode::Char('L'), KeyModifiers::NONE), Some(Action::ToggleDecodeLog));
+        assert_eq!(lookup(KeyCode::BackTab, KeyModifiers::SHIFT), Some(Action::PrevPane));
+        assert_eq!(lookup(KeyCode::Char('c'), KeyModifiers::NONE), None);
+        let c = key(KeyCode::Char('c'), KeyModifiers::NONE);
+        assert_eq!(keymap.lookup(Scope::Decoder, c), Some(Action::ClearMessages));
+
+        // The same key means different things per control
+        let up = key(KeyCode::Up, KeyModifiers::NONE);
+        assert_eq!(keymap.lookup(Scope::Frequency, up), Some(Action::FreqUpSmall));
+        assert_eq!(keymap.lookup(Scope::Adjust, up), Some(Action::Increase));
+    }
+
+    #[test]
+    fn test_routing_follows_focus() {
+        use Scope::*;
+        assert_eq!(
+            routing(PaneId::Controls, ControlId::Frequency, false, false),
+            [Global, Controls, Frequency]
+        );
+        assert_eq!(
+            routing(PaneId::Controls, ControlId::Gain, true, false),
+            [Paused, Global, Controls, Gain, Adjust]
+        );
+        assert_eq!(
+            routing(PaneId::Controls, ControlId::Squelch, false, false),
+            [Global, Controls, Adjust]
+        );
+        // Other panes never see the control keys
+        assert_eq!(routing(PaneId::Decoder, ControlId::Frequency, false, false), [Decoder, Global]);
+        assert_eq!(routing(PaneId::Spectrum, ControlId::Record, false, false), [Spectrum, Global]);
+        assert_eq!(
+            routing(PaneId::Waterfall, ControlId::Mode, true, false),
+            [Paused, Waterfall, Global]
+        );
+
+        // Each pane with focus has its own p, paused waterfall or not
+        let keymap = Keymap::default();
+        let p = key(KeyCode::Char('p'), KeyModifiers::NONE);
+        let first = |scopes: Vec<Scope>| scopes.into_iter().find_map(|s| keymap.lookup(s, p));
+        let route = routing(PaneId::Decoder, ControlId::Frequency, false, false);
+        assert_eq!(first(route), Some(Action::PauseMessages));
+        let route = routing(PaneId::Waterfall, ControlId::Frequency, true, false);
+        assert_eq!(first(route), Some(Action::WaterfallPause));
+        // And the waterfall's pause is only the waterfall pane's
+        let route = routing(PaneId::Controls, ControlId::Frequency, false, false);
+        assert_eq!(first(route), None);
+
+        // Playback keys are their own, so a file playing shadows nothing
+        assert_eq!(
+            routing(PaneId::Waterfall, ControlId::Mode, true, true),
+            [Paused, Playback, Waterfall, Global]
+        );
+        let first =
+            |scopes: Vec<Scope>, key| scopes.into_iter().find_map(|s| keymap.lookup(s, key));
+        let playing = |control| routing(PaneId::Controls, control, false, true);
+        let comma = key(KeyCode::Char(','), KeyModifiers::NONE);
+        assert_eq!(first(playing(ControlId::Frequency), comma), Some(Action::ActivityPrev));
+        let space = key(KeyCode::Char(' '), KeyModifiers::NONE);
+        assert_eq!(first(playing(ControlId::Record), space), Some(Action::Activate));
+        let one = key(KeyCode::Char('1'), KeyModifiers::NONE);
+        assert_eq!(first(playing(ControlId::Frequency), one), Some(Action::Preset1));
+        let alt_left = key(KeyCode::Left, KeyModifiers::ALT);
+        assert_eq!(first(playing(ControlId::Frequency), alt_left), Some(Action::SeekBack));
+        let alt_one = key(KeyCode::Char('1'), KeyModifiers::ALT);
+        assert_eq!(first(playing(ControlId::Frequency), alt_one), Some(Action::Seek1));
+    }
+
+    #[test]
+    fn test_tab_for_controls_compatibility() {
+        let overrides: BTreeMap<String, KeyList> = toml::from_str(
+            r#"
+            next_control = "tab"
+            prev_control = "shift+tab"
+            next_pane = "f"
+            prev_pane = "F"
+            "#,
+        )
+        .unwrap();
+        let (keymap, problems) = Keymap::from_config(&overrides);
+        assert_eq!(problems, Vec::<String>::new());
+        let tab = key(KeyCode::Tab, KeyModifiers::NONE);
+        assert_eq!(keymap.lookup(Scope::Global, tab), None);
+        assert_eq!(keymap.lookup(Scope::Controls, tab), Some(Action::NextControl));
+    }
+
+    #[test]
+    fn test_defaults_have_no_conflicts() {
+        assert_eq!(Keymap::default().conflicts(), Vec::<String>::new());
+        assert_eq!(Keymap::default().label(Action::Quit), "q/Ctrl+C");
+    }
+
+    #[test]
+    fn test_config_overrides_and_problems() {
+        let overrides: BTreeMap<String, KeyList> = toml::from_str(
+            r#"
+            next_control = ["f9", "n"]
+            quit = "ctrl+q"
+            toggle_mute = "m"
+            toggle_stats = ["i", "hyper+i"]
+            tuner_agc = "up"
+            "#,
+        )
+        .unwrap();
+        let (keymap, problems) = Keymap::from_config(&overrides);
+
+        assert_eq!(keymap.label(Action::NextControl), "F9/n");
+        assert_eq!(keymap.label(Action::Quit), "Ctrl+Q");
+        // Defaults stay for actions not mentioned
+        assert_eq!(keymap.label(Action::ToggleRecord), "r");
+        assert_eq!(keymap.label(Action::ToggleStats), "i");
+
+        assert_eq!(
+            problems,
+            vec![
+                "unknown action 'toggle_mute'".to_string(),
+                "toggle_stats: unknown modifier 'hyper' in key 'hyper+i'".to_string(),
+                "'up' is bound to both increase and tuner_agc".to_string(),
+            ]
+        );
+    }
+}
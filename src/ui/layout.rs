@@ -0,0 +1,361 @@
+//! Pane layout
+//!
+//! The screen is the status bar over the spectrum, the waterfall and a
+//! bottom row of controls beside the decoder output. Any pane can be hidden,
+//! collapsing to nothing while the others share its space; the shares come
+//! from `[ui.layout]`.
+
+use crate::state::PaneId;
+use crate::types::config::LayoutConfig;
+use ratatui::layout::{Constraint, Layout, Rect};
+use std::fmt;
+
+/// Height of the status bar
+const STATUS_HEIGHT: u16 = 3;
+/// Smallest a shown pane may be either way, borders included
+const MIN_PANE_SIZE: u16 = 3;
+
+/// Where each pane goes; hidden ones are empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Panes {
+    pub status: Rect,
+    pub spectrum: Rect,
+    pub waterfall: Rect,
+    pub controls: Rect,
+    pub decoder: Rect,
+}
+
+/// Why a layout can't be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutError {
+    /// Nothing but the status bar would be left
+    AllHidden,
+    /// A shown pane would be too small to draw
+    TooSmall,
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LayoutError::AllHidden => "every pane is hidden",
+            LayoutError::TooSmall => "terminal too small for the layout",
+        })
+    }
+}
+
+/// Which panes are shown and their shares of the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaneLayout {
+    spectrum: bool,
+    waterfall: bool,
+    controls: bool,
+    decoder: bool,
+    spectrum_size: u16,
+    waterfall_size: u16,
+    bottom_size: u16,
+    controls_width: u16,
+}
+
+impl Default for PaneLayout {
+    fn default() -> Self {
+        Self::from_config(&LayoutConfig::default()).0
+    }
+}
+
+impl PaneLayout {
+    /// The configured layout, returning it with any problems found as
+    /// readable lines; sizes that can't be used keep their defaults, and
+    /// every pane is shown if none would be
+    pub fn from_config(config: &LayoutConfig) -> (Self, Vec<String>) {
+        let defaults = LayoutConfig::default();
+        let mut problems = Vec::new();
+        let mut size = |name: &str, value: u16, default: u16, max: u16| {
+            if (1..=max).contains(&value) {
+                value
+            } else {
+                problems.push(format!("{}: {} is not between 1 and {}", name, value, max));
+                default
+            }
+        };
+        let mut layout = Self {
+            spectrum: config.spectrum,
+            waterfall: config.waterfall,
+            controls: config.controls,
+            decoder: config.decoder,
+            spectrum_size: size(
+                "spectrum_size",
+                config.spectrum_size,
+                defaults.spectrum_size,
+                100,
+            ),
+            waterfall_size: size(
+                "waterfall_size",
+                config.waterfall_size,
+                defaults.waterfall_size,
+                100,
+            ),
+            bottom_size: size("bottom_size", config.bottom_size, defaults.bottom_size, 100),
+            controls_width: size(
+                "controls_width",
+                config.controls_width,
+                defaults.controls_width,
+                99,
+            ),
+        };
+        if !layout.any_visible() {
+            problems.push("every pane is hidden, showing them all".to_string());
+            layout.spectrum = true;
+            layout.waterfall = true;
+            layout.controls = true;
+            layout.decoder = true;
+        }
+        (layout, problems)
+    }
+
+    pub fn is_visible(&self, pane: PaneId) -> bool {
+        match pane {
+            PaneId::Spectrum => self.spectrum,
+            PaneId::Waterfall => self.waterfall,
+            PaneId::Controls => self.controls,
+            PaneId::Decoder => self.decoder,
+        }
+    }
+
+    fn any_visible(&self) -> bool {
+        self.spectrum || self.waterfall || self.controls || self.decoder
+    }
+
+    /// Show or hide `pane`; false, changing nothing, if it is the last one
+    /// shown
+    pub fn toggle(&mut self, pane: PaneId) -> bool {
+        let shown = match pane {
+            PaneId::Spectrum => &mut self.spectrum,
+            PaneId::Waterfall => &mut self.waterfall,
+            PaneId::Controls => &mut self.controls,
+            PaneId::Decoder => &mut self.decoder,
+        };
+        *shown = !*shown;
+        if self.any_visible() {
+            true
+        } else {
+            self.toggle(pane);
+            false
+        }
+    }
+
+    /// The panes across `area`
+    pub fn split(&self, area: Rect) -> Result<Panes, LayoutError> {
+        if !self.any_visible() {
+            return Err(LayoutError::AllHidden);
+        }
+        let panes = self.place(area);
+        let shown = [
+            (self.spectrum, panes.spectrum),
+            (self.waterfall, panes.waterfall),
+            (self.controls, panes.controls),
+            (self.decoder, panes.decoder),
+        ];
+        let too_small = |rect: Rect| rect.height < MIN_PANE_SIZE || rect.width < MIN_PANE_SIZE;
+        if shown
+            .iter()
+            .any(|&(visible, rect)| visible && too_small(rect))
+        {
+            return Err(LayoutError::TooSmall);
+        }
+        Ok(panes)
+    }
+
+    /// The panes across `area`, or the default layout's if this one can't
+    /// be used there, along with why
+    pub fn split_or_default(&self, area: Rect) -> (Panes, Option<LayoutError>) {
+        match self.split(area) {
+            Ok(panes) => (panes, None),
+            Err(e) => (Self::default().place(area), Some(e)),
+        }
+    }
+
+    /// The panes across `area`, however small
+    fn place(&self, area: Rect) -> Panes {
+        let share = |visible: bool, size: u16| {
+            if visible {
+                Constraint::Fill(size)
+            } else {
+                Constraint::Length(0)
+            }
+        };
+        let bottom = self.controls || self.decoder;
+        let rows = Layout::vertical([
+            Constraint::Length(STATUS_HEIGHT),
+            share(self.spectrum, self.spectrum_size),
+            share(self.waterfall, self.waterfall_size),
+            share(bottom, self.bottom_size),
+        ])
+        .split(area);
+        let columns = Layout::horizontal([
+            share(self.controls, self.controls_width),
+            share(self.decoder, 100 - self.controls_width),
+        ])
+        .split(rows[3]);
+
+        Panes {
+            status: rows[0],
+            spectrum: rows[1],
+            waterfall: rows[2],
+            controls: columns[0],
+            decoder: columns[1],
+        }
+    }
+
+    /// The pane visibility for the config file, as (key, value) pairs
+    pub fn saved_visibility(&self) -> [(&'static str, bool); 4] {
+        [
+            ("spectrum", self.spectrum),
+            ("waterfall", self.waterfall),
+            ("controls", self.controls),
+            ("decoder", self.decoder),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heights(panes: &Panes) -> [u16; 5] {
+        [
+            panes.status.height,
+            panes.spectrum.height,
+            panes.waterfall.height,
+            panes.controls.height,
+            panes.decoder.height,
+        ]
+    }
+
+    fn layout(config: LayoutConfig) -> PaneLayout {
+        let (layout, problems) = PaneLayout::from_config(&config);
+        assert_eq!(problems, Vec::<String>::new());
+        layout
+    }
+
+    #[test]
+    fn test_default_split() {
+        let panes = PaneLayout::default()
+            .split(Rect::new(0, 0, 100, 103))
+            .unwrap();
+        assert_eq!(heights(&panes), [3, 30, 30, 40, 40]);
+        assert_eq!((panes.controls.width, panes.decoder.width), (40, 60));
+        assert_eq!(panes.spectrum.y, 3);
+        assert_eq!(panes.decoder.x, 40);
+    }
+
+    #[test]
+    fn test_hidden_panes_collapse() {
+        let area = Rect::new(0, 0, 100, 103);
+        let panes = layout(LayoutConfig {
+            waterfall: false,
+            bottom_size: 70,
+            ..LayoutConfig::default()
+        })
+        .split(area)
+        .unwrap();
+        assert_eq!(heights(&panes), [3, 30, 0, 70, 70]);
+
+        // One side of the bottom row takes all of it
+        let panes = layout(LayoutConfig {
+            controls: false,
+            ..LayoutConfig::default()
+        })
+        .split(area)
+        .unwrap();
+        assert_eq!((panes.controls.width, panes.decoder.width), (0, 100));
+
+        // Only the decoder output
+        let panes = layout(LayoutConfig {
+            spectrum: false,
+            waterfall: false,
+            controls: false,
+            ..LayoutConfig::default()
+        })
+        .split(area)
+        .unwrap();
+        assert_eq!(heights(&panes), [3, 0, 0, 100, 100]);
+        assert!(panes.controls.is_empty());
+        assert_eq!(panes.decoder.width, 100);
+    }
+
+    #[test]
+    fn test_degenerate_layouts_fall_back() {
+        // Too small for three panes
+        let small = Rect::new(0, 0, 80, 10);
+        let layout = PaneLayout::default();
+        assert_eq!(layout.split(small), Err(LayoutError::TooSmall));
+        let (panes, problem) = layout.split_or_default(small);
+        assert_eq!(problem, Some(LayoutError::TooSmall));
+        assert_eq!(panes.status.height, 3);
+
+        // A tiny share of a normal screen
+        let squeezed = PaneLayout::from_config(&LayoutConfig {
+            spectrum_size: 1,
+            bottom_size: 100,
+            ..LayoutConfig::default()
+        })
+        .0;
+        let area = Rect::new(0, 0, 100, 43);
+        assert_eq!(squeezed.split(area), Err(LayoutError::TooSmall));
+        assert_eq!(
+            squeezed.split_or_default(area).0,
+            PaneLayout::default().split(area).unwrap()
+        );
+
+        // Everything hidden in the config shows everything
+        let (layout, problems) = PaneLayout::from_config(&LayoutConfig {
+            spectrum: false,
+            waterfall: false,
+            controls: false,
+            decoder: false,
+            ..LayoutConfig::default()
+        });
+        assert_eq!(layout, PaneLayout::default());
+        assert_eq!(problems, ["every pane is hidden, showing them all"]);
+    }
+
+    #[test]
+    fn test_bad_sizes_keep_defaults() {
+        let (layout, problems) = PaneLayout::from_config(&LayoutConfig {
+            waterfall_size: 0,
+            controls_width: 100,
+            ..LayoutConfig::default()
+        });
+        assert_eq!(layout, PaneLayout::default());
+        assert_eq!(
+            problems,
+            [
+                "waterfall_size: 0 is not between 1 and 100",
+                "controls_width: 100 is not between 1 and 99",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toggle_keeps_one_pane() {
+        let mut layout = PaneLayout::default();
+        assert!(layout.toggle(PaneId::Waterfall));
+        assert!(!layout.is_visible(PaneId::Waterfall));
+        assert!(layout.toggle(PaneId::Spectrum));
+        assert!(layout.toggle(PaneId::Controls));
+        // The decoder output is all that's left
+        assert!(!layout.toggle(PaneId::Decoder));
+        assert!(layout.is_visible(PaneId::Decoder));
+        assert_eq!(
+            layout.saved_visibility(),
+            [
+                ("spectrum", false),
+                ("waterfall", false),
+                ("controls", false),
+                ("decoder", true)
+            ]
+        );
+        assert!(layout.toggle(PaneId::Waterfall));
+        assert!(layout.is_visible(PaneId::Waterfall));
+    }
+}
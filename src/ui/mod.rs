@@ -1,6 +1,11 @@
 pub mod app;
+pub mod events;
 pub mod input;
+pub mod keymap;
+pub mod layout;
 pub mod render;
+pub mod theme;
+pub mod title;
 pub mod widgets;
 
 // Re-export commonly used types
@@ -1,12 +1,21 @@
 use super::app::App;
-use crate::state::ControlId;
+use super::keymap::{Action, Keymap};
+use super::layout::LayoutError;
+use super::theme::Theme;
+use crate::dsp::channelizer;
+use crate::dsp::noise::NoiseReduction;
+use crate::state::app_state::format_elapsed;
+use crate::state::calibration::LevelScale;
+use crate::state::mode_settings::{deemphasis_label, uses_bfo, uses_deemphasis};
+use crate::state::{AppState, ControlId, Modal, PaneId, RecordingMode, VfoConfig};
+use crate::types::{Chain, DemodMode};
 use anyhow::Result;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph},
+    widgets::{Block, Borders, Clear, Paragraph},
     Frame, Terminal,
 };
 use std::io;
@@ -35,80 +44,247 @@ pub fn restore() -> Result<()> {
         crossterm::terminal::LeaveAlternateScreen,
         crossterm::event::DisableMouseCapture
     )?;
+    super::title::restore()?;
     Ok(())
 }
 
 /// Render the TUI
 pub fn render(terminal: &mut Tui, app: &App) -> Result<()> {
     terminal.draw(|f| {
-        let chunks = create_layout(f.area());
+        // Body text color; widgets only override what they style
+        f.render_widget(
+            Block::default().style(Style::default().fg(app.theme.text)),
+            f.area(),
+        );
+
+        // Hidden panes come out empty
+        let (panes, layout_problem) = app.layout.split_or_default(f.area());
 
         // Render status bar
-        render_status_bar(f, app, chunks[0]);
+        render_status_bar(f, app, panes.status, layout_problem);
 
         // Render spectrum
-        render_spectrum_placeholder(f, app, chunks[1]);
-
-        // Render waterfall
-        render_waterfall_placeholder(f, app, chunks[2]);
+        if !panes.spectrum.is_empty() {
+            render_spectrum_placeholder(f, app, panes.spectrum);
+        }
 
-        // Split bottom area into controls and decoder output
-        let bottom_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
-            .split(chunks[3]);
+        // Render waterfall and any panes sharing its space
+        if !panes.waterfall.is_empty() {
+            render_waterfall_area(f, app, panes.waterfall);
+        }
 
         // Render controls
-        render_controls(f, app, bottom_chunks[0]);
+        if !panes.controls.is_empty() {
+            render_controls(f, app, panes.controls);
+        }
 
-        // Render decoder output placeholder
-        render_decoder_placeholder(f, bottom_chunks[1]);
+        // The activity table, or in ADS-B live aircraft, replaces the message list
+        if !panes.decoder.is_empty() {
+            if app.state.read().ui.show_activity {
+                render_activity_table(f, app, panes.decoder);
+            } else if app.state.read().decoder.mode == DemodMode::Adsb {
+                render_aircraft_table(f, app, panes.decoder);
+            } else {
+                render_decoder_output(f, app, panes.decoder);
+            }
+        }
+
+        // Overlays draw over the panes, modal dialogs over everything
+        let overlay = [panes.spectrum, panes.waterfall, panes.decoder, panes.controls];
+        if let Some(area) = overlay.into_iter().find(|area| !area.is_empty()) {
+            render_stats(f, app, area);
+        }
+        render_modal(f, app);
     })?;
     Ok(())
 }
 
-/// Create the main layout
-fn create_layout(area: Rect) -> std::rc::Rc<[Rect]> {
-    Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3),  // Status bar
-            Constraint::Percentage(30),  // Spectrum
-            Constraint::Percentage(30),  // Waterfall
-            Constraint::Percentage(40),  // Bottom (controls + decoder)
-        ])
-        .split(area)
-}
-
-/// Render the status bar
-fn render_status_bar(f: &mut Frame, app: &App, area: Rect) {
+/// Render the status bar, with why the layout fell back to the default if
+/// it did
+fn render_status_bar(f: &mut Frame, app: &App, area: Rect, layout_problem: Option<LayoutError>) {
     let freq = app.get_frequency();
     let is_recording = app.is_recording();
     let status = app.get_status();
+    let (recording_summary, low_space, next_scheduled, same_alert, priority) = {
+        let state = app.state.read();
+        (
+            state.recording.summary(),
+            state.recording.is_low_on_space(),
+            state.recording.next_scheduled.clone(),
+            state.same_alert().cloned(),
+            state.priority.frequency.filter(|_| state.priority.active),
+        )
+    };
+    let playback = app.state.read().playback.clone();
+    let (test_tone, audio_warning) = {
+        let state = app.state.read();
+        (
+            state.ui.test_tone.is_some_and(|until| std::time::Instant::now() < until),
+            state.ui.audio_warning.clone(),
+        )
+    };
+    let (scan, progress, overloaded, link, tuner_gap, receiver) = {
+        let state = app.state.read();
+        let sdr = &state.sdr;
+        // Which dongle, for support questions: its tuner and serial
+        let receiver = [
+            sdr.capabilities.tuner.map(|tuner| tuner.to_string()),
+            sdr.device_serial.as_ref().map(|serial| format!("S/N {}", serial)),
+        ];
+        let receiver: Vec<String> = receiver.into_iter().flatten().collect();
+        (
+            state.ui.scan_status.clone(),
+            // A gain sweep or calibration under way
+            state.sdr.gain_sweep.clone().or(state.sdr.ppm_calibration.clone()),
+            state.stats.overloaded(),
+            state
+                .sdr
+                .waiting_for_device
+                .as_ref()
+                .map(|waiting| format!("NO DEVICE: {}", waiting))
+                .or_else(|| state.sdr.warning()),
+            sdr.capabilities.gap_warning(sdr.hardware_frequency()),
+            receiver.join(" "),
+        )
+    };
 
-    let title = if is_recording {
-        format!("[RECORDING] RTL-SDR TUI - {} MHz", freq as f64 / 1_000_000.0)
+    let mut title = if is_recording {
+        format!(
+            "[REC {}] RTL-SDR TUI - {:.3} MHz",
+            recording_summary,
+            freq as f64 / 1_000_000.0
+        )
     } else {
         format!("RTL-SDR TUI - {:.3} MHz", freq as f64 / 1_000_000.0)
     };
+    if let Some(receivers) = app.receivers.as_ref().filter(|r| r.len() > 1) {
+        title.push_str(&format!(" [RX {}/{}]", receivers.selected() + 1, receivers.len()));
+    }
+    if !receiver.is_empty() {
+        title.push_str(&format!(" [{}]", receiver));
+    }
 
-    let status_text = vec![
-        Line::from(vec![
-            Span::styled(
-                title,
-                Style::default()
-                    .fg(if is_recording { Color::Red } else { Color::Cyan })
-                    .add_modifier(Modifier::BOLD),
-            ),
-        ]),
-        Line::from(vec![
+    let theme = &app.theme;
+    let title_color = if !is_recording {
+        theme.accent
+    } else if low_space {
+        theme.warning
+    } else {
+        theme.recording
+    };
+
+    // An active weather alert takes over the status line
+    let alert_active = same_alert.is_some();
+    let mut status_spans = match same_alert {
+        Some(alert) => vec![Span::styled(
+            format!(" ALERT: {} ", alert),
+            Style::default()
+                .fg(Color::White)
+                .bg(theme.recording)
+                .add_modifier(Modifier::BOLD),
+        )],
+        None => vec![
             Span::raw("Status: "),
-            Span::styled(status, Style::default().fg(Color::Yellow)),
-        ]),
+            Span::styled(status, Style::default().fg(theme.warning)),
+        ],
+    };
+    if test_tone {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(
+            "TEST TONE",
+            Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(warning) = audio_warning {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(
+            warning,
+            Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(warning) = link {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(
+            warning,
+            Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(warning) = tuner_gap {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(
+            warning,
+            Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if overloaded {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(
+            "OVERLOAD — reduce gain",
+            Style::default().fg(theme.recording).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(frequency) = priority {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(
+            format!("PRIORITY ACTIVE {:.3} MHz", frequency as f64 / 1_000_000.0),
+            Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(scan) = scan {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(scan, Style::default().fg(theme.accent)));
+    }
+    if let Some(progress) = progress {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(progress, Style::default().fg(theme.accent)));
+    }
+    if let Some(next) = next_scheduled {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(next, Style::default().fg(theme.accent)));
+    }
+    if let Some(problem) = layout_problem {
+        status_spans.push(Span::raw("  |  "));
+        status_spans.push(Span::styled(
+            format!("Layout: {}, using the default", problem),
+            Style::default().fg(theme.warning),
+        ));
+    }
+
+    // Clock and uptime follow the title; recording time is in the title
+    let clock = app.state.read().ui.clock.clock(chrono::Utc::now());
+    let uptime = format_elapsed(app.started.elapsed().as_secs() as i64);
+
+    let mut title_spans = vec![
+        Span::styled(
+            title,
+            Style::default()
+                .fg(title_color)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!("   {}   up {}", clock, uptime),
+            Style::default().fg(theme.label),
+        ),
+    ];
+    // Where a played file has got to
+    if let Some(playback) = playback {
+        title_spans.push(Span::styled(
+            format!("   {} {}", playback.bar(PLAYBACK_BAR_WIDTH), playback.summary()),
+            Style::default().fg(theme.accent),
+        ));
+    }
+
+    let status_text = vec![
+        Line::from(title_spans),
+        Line::from(status_spans),
     ];
 
-    let paragraph = Paragraph::new(status_text)
-        .block(Block::default().borders(Borders::ALL));
+    let border_color = if alert_active { theme.recording } else { Color::Reset };
+    let paragraph = Paragraph::new(status_text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border_color)),
+    );
 
     f.render_widget(paragraph, area);
 }
@@ -119,149 +295,650 @@ fn render_spectrum_placeholder(f: &mut Frame, app: &App, area: Rect) {
     let freq = state.sdr.frequency;
     let sample_rate = state.sdr.sample_rate;
 
-    let block = Block::default()
-        .title("Spectrum Analyzer")
-        .borders(Borders::ALL);
-
+    let focused = state.ui.focused_pane == PaneId::Spectrum;
     // Get FFT data from state
     let fft_data = &state.spectrum.fft_data;
 
-    if fft_data.is_empty() {
+    let scale = state.sdr.level_scale();
+    let mut title = format!("Spectrum Analyzer {}", db_range_label(scale));
+    if let Some(summary) = state.spectrum.markers.summary(fft_data, freq, sample_rate, scale) {
+        title.push_str(&format!(" {} ", summary));
+    }
+    let block = pane_block(title, focused, &app.theme);
+
+    if let Some(waiting) = &state.sdr.waiting_for_device {
+        let text = Paragraph::new(vec![
+            Line::from(Span::styled(
+                "NO DEVICE",
+                Style::default().fg(app.theme.warning).add_modifier(Modifier::BOLD),
+            )),
+            Line::from(waiting.clone()),
+            Line::from("Changes made now are applied once the receiver is attached"),
+        ])
+        .block(block)
+        .style(Style::default().fg(app.theme.dim));
+        f.render_widget(text, area);
+    } else if fft_data.is_empty() {
         // Show placeholder if no data
         let text = Paragraph::new("Waiting for signal data...")
             .block(block)
-            .style(Style::default().fg(Color::DarkGray));
+            .style(Style::default().fg(app.theme.dim));
         f.render_widget(text, area);
     } else {
         // Render actual spectrum
-        let widget = super::widgets::SpectrumWidget::new(fft_data, freq, sample_rate)
+        let mut widget = super::widgets::SpectrumWidget::new(fft_data, freq, sample_rate)
             .block(block)
-            .db_range(-100.0, 0.0);
+            .db_range(DB_RANGE.0, DB_RANGE.1)
+            .palette(app.theme.spectrum)
+            .mode(app.spectrum_mode)
+            .inverted(app.spectrum_invert);
+        if let Some(spacing) = app.channel_grid {
+            widget = widget.grid(spacing, app.theme.dim);
+        }
+        for (low, high, width) in passbands(&state) {
+            let label = format!("{:.1}k", width as f64 / 1000.0);
+            widget = widget.passband(low, high, label, app.theme.passband);
+        }
+        // Channel markers, once there is more than one channel to tell apart
+        if state.channels.b_enabled() {
+            for (label, chain) in [('A', Chain::A), ('B', Chain::B)] {
+                let color = if state.ui.selected_chain == chain {
+                    app.theme.selected
+                } else {
+                    app.theme.accent
+                };
+                widget = widget.marker(label, state.channels.offset(chain), color);
+            }
+        }
+        // Measurement markers still in the capture
+        for (number, reading) in state.spectrum.markers.readings(fft_data, freq, sample_rate) {
+            if reading.level.is_some() {
+                let label = char::from_digit(number as u32, 10).unwrap_or('M');
+                widget = widget.marker(label, reading.offset as i32, app.theme.warning);
+            }
+        }
         f.render_widget(widget, area);
     }
 }
 
+/// Levels in dBFS at the bottom and top of the spectrum and waterfall
+const DB_RANGE: (f32, f32) = (-100.0, 0.0);
+
+/// The spectrum and waterfall level range as shown, e.g. "(-100 to 0 dBFS)"
+fn db_range_label(scale: LevelScale) -> String {
+    format!(
+        "({:.0} to {:.0} {})",
+        scale.apply(DB_RANGE.0),
+        scale.apply(DB_RANGE.1),
+        scale.unit()
+    )
+}
+
+/// The spans each active chain demodulates, as offsets from the center
+/// frequency in Hz, with their filter widths
+fn passbands(state: &AppState) -> Vec<(i32, i32, u32)> {
+    let mut chains =
+        vec![(state.decoder.mode, state.decoder.filter_width, state.channels.offset_a)];
+    if state.channels.b_enabled() {
+        chains.push((state.channels.mode_b, None, state.channels.offset_b));
+    }
+    chains
+        .into_iter()
+        .filter_map(|(mode, width, offset)| {
+            let (low, high) = channelizer::passband(mode, width)?;
+            let width = width.or(channelizer::default_width(mode))?;
+            Some((offset + low, offset + high, width))
+        })
+        .collect()
+}
+
+/// Render the waterfall, giving its lower half to the optional scope panes
+/// (side by side) when any are shown
+fn render_waterfall_area(f: &mut Frame, app: &App, area: Rect) {
+    let panes: Vec<fn(&mut Frame, &App, Rect)> = {
+        let ui = &app.state.read().ui;
+        let mut panes: Vec<fn(&mut Frame, &App, Rect)> = Vec::new();
+        if ui.show_af_spectrum {
+            panes.push(render_af_spectrum);
+        }
+        if ui.show_constellation {
+            panes.push(render_constellation);
+        }
+        if ui.show_scope {
+            panes.push(render_scope);
+        }
+        panes
+    };
+
+    if panes.is_empty() {
+        render_waterfall_placeholder(f, app, area);
+        return;
+    }
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    render_waterfall_placeholder(f, app, rows[0]);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Ratio(1, panes.len() as u32); panes.len()])
+        .split(rows[1]);
+    for (render_pane, column) in panes.iter().zip(columns.iter()) {
+        render_pane(f, app, *column);
+    }
+}
+
 /// Render waterfall display
 fn render_waterfall_placeholder(f: &mut Frame, app: &App, area: Rect) {
     let state = app.state.read();
+    let spectrum = &state.spectrum;
+    let rows = area.height.saturating_sub(2) as usize;
 
-    let block = Block::default()
-        .title("Waterfall Display")
-        .borders(Borders::ALL);
+    // Paused, the view stays put as rows keep arriving; it can't go back
+    // further than a full screen of the oldest rows
+    let back = match state.ui.waterfall_paused_at {
+        Some(paused_at) => {
+            let arrived = spectrum.waterfall_rows.saturating_sub(paused_at) as usize;
+            (arrived + state.ui.waterfall_scroll)
+                .min(spectrum.waterfall.len().saturating_sub(rows))
+        }
+        None => 0,
+    };
+    // Get the visible rows from state
+    let waterfall_data = spectrum.get_waterfall_display(back, rows);
+
+    // Scrolled back, the exact time of the bottom row
+    let clock = state.ui.clock;
+    let title = match (state.ui.waterfall_paused_at, waterfall_data.last()) {
+        (Some(_), Some(row)) => format!(
+            "Waterfall Display - PAUSED (-{} rows, {})",
+            back,
+            clock.timestamp_millis(row.time())
+        ),
+        (Some(_), None) => format!("Waterfall Display - PAUSED (-{} rows)", back),
+        (None, _) => format!(
+            "Waterfall Display - {} lines/s, {}",
+            spectrum.line_rate,
+            spectrum.integration.name()
+        ),
+    };
+    let title = format!("{} {}", title, db_range_label(state.sdr.level_scale()));
 
-    // Get waterfall data from state
-    let waterfall_data = state.spectrum.get_waterfall_display();
+    let block = pane_block(title, state.ui.focused_pane == PaneId::Waterfall, &app.theme);
 
     if waterfall_data.is_empty() {
         // Show placeholder if no data
         let text = Paragraph::new("Waiting for signal data...")
             .block(block)
-            .style(Style::default().fg(Color::DarkGray));
+            .style(Style::default().fg(app.theme.dim));
         f.render_widget(text, area);
     } else {
         // Render actual waterfall
-        let widget = super::widgets::WaterfallWidget::new(waterfall_data)
+        let first_row = spectrum.waterfall_rows - (back + waterfall_data.len()) as u64;
+        let times = waterfall_data.iter().map(|row| row.time()).collect();
+        let bins = waterfall_data.iter().map(|row| &row.bins).collect();
+        let mut widget = super::widgets::WaterfallWidget::new(bins)
+            .times(times, first_row, clock)
+            .block(block)
+            .db_range(DB_RANGE.0, DB_RANGE.1)
+            .colormap(app.theme.waterfall)
+            .inverted(app.spectrum_invert);
+        for (low, high, _) in passbands(&state) {
+            widget = widget.passband(low, high, state.sdr.sample_rate, app.theme.accent);
+        }
+        f.render_widget(widget, area);
+    }
+}
+
+/// Render the demodulated audio spectrum
+fn render_af_spectrum(f: &mut Frame, app: &App, area: Rect) {
+    let state = app.state.read();
+
+    let block = Block::default()
+        .title("Audio Spectrum (0-5 kHz)")
+        .borders(Borders::ALL);
+
+    if state.spectrum.af_fft.is_empty() {
+        let text = Paragraph::new("Waiting for audio...")
+            .block(block)
+            .style(Style::default().fg(app.theme.dim));
+        f.render_widget(text, area);
+    } else {
+        let widget = super::widgets::AfSpectrumWidget::new(&state.spectrum.af_fft)
+            .block(block)
+            .db_range(-90.0, 0.0)
+            .palette(app.theme.spectrum);
+        f.render_widget(widget, area);
+    }
+}
+
+/// Render the IQ constellation
+fn render_constellation(f: &mut Frame, app: &App, area: Rect) {
+    let state = app.state.read();
+
+    let block = Block::default()
+        .title("IQ Constellation")
+        .borders(Borders::ALL);
+
+    if state.spectrum.iq_snapshot.is_empty() {
+        let text = Paragraph::new("Waiting for signal data...")
+            .block(block)
+            .style(Style::default().fg(app.theme.dim));
+        f.render_widget(text, area);
+    } else {
+        let widget = super::widgets::ConstellationWidget::new(&state.spectrum.iq_snapshot)
+            .block(block);
+        f.render_widget(widget, area);
+    }
+}
+
+/// Render the audio oscilloscope
+fn render_scope(f: &mut Frame, app: &App, area: Rect) {
+    // Copy out so the lock isn't held while drawing
+    let (samples, sample_rate, range, trigger) = {
+        let state = app.state.read();
+        (
+            state.spectrum.scope.snapshot(),
+            state.spectrum.scope.sample_rate(),
+            state.ui.scope_range,
+            state.ui.scope_trigger,
+        )
+    };
+
+    let block = Block::default()
+        .title("Audio Scope")
+        .borders(Borders::ALL);
+
+    if samples.is_empty() {
+        let text = Paragraph::new("Waiting for audio...")
+            .block(block)
+            .style(Style::default().fg(app.theme.dim));
+        f.render_widget(text, area);
+    } else {
+        let widget = super::widgets::ScopeWidget::new(&samples, sample_rate)
             .block(block)
-            .db_range(-100.0, 0.0);
+            .range(range)
+            .trigger(trigger);
         f.render_widget(widget, area);
     }
 }
 
 /// Render controls panel
 fn render_controls(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
     let selected = app.state.read().ui.selected_control;
-    let freq = app.get_frequency();
-    let mode = app.get_mode();
-    let gain = app.get_gain();
+    let (chain, offset_b, mode_b) = {
+        let state = app.state.read();
+        (state.ui.selected_chain, state.channels.offset_b, state.channels.mode_b)
+    };
+    let freq_b = app.get_frequency().saturating_add_signed(offset_b);
+    let (freq, mode) = match chain {
+        Chain::A => (app.get_frequency(), app.get_mode()),
+        Chain::B => (freq_b, mode_b),
+    };
+    let channel_b = if mode_b == DemodMode::Raw {
+        "Off".to_string()
+    } else {
+        format!(
+            "{} {:.3} MHz ({:+.1} kHz)",
+            mode_b.name(),
+            freq_b as f64 / 1_000_000.0,
+            offset_b as f64 / 1000.0
+        )
+    };
+    let filter_str = {
+        let state = app.state.read();
+        let mode = state.decoder.mode;
+        let mut width = match (state.decoder.filter_width, channelizer::default_width(mode)) {
+            (Some(width), _) => format!("{:.1} kHz", width as f64 / 1000.0),
+            (None, Some(width)) if channelizer::width_limits(mode).is_some() => {
+                format!("{:.1} kHz (default)", width as f64 / 1000.0)
+            }
+            (None, Some(width)) => format!("{:.1} kHz (fixed)", width as f64 / 1000.0),
+            (None, None) => "None".to_string(),
+        };
+        // Say how the DC spike is being kept out
+        if state.sdr.hardware_offset != 0 {
+            width.push_str(&format!(
+                ", DC {:+.0} kHz off",
+                state.sdr.hardware_offset as f64 / 1000.0
+            ));
+        } else if state.sdr.dc_notch_active {
+            width.push_str(", DC notched");
+        }
+        width
+    };
+    let gain_str = {
+        let state = app.state.read();
+        format!(
+            "{}  (ADC use: {})",
+            state.sdr.gain_description(),
+            state.stats.adc.percent()
+        )
+    };
+    let squelch_str = {
+        let state = app.state.read();
+        let decoder = &state.decoder;
+        // The threshold is set in dBFS; the signal reads as calibrated
+        let signal = state.sdr.level_scale().format(decoder.signal_level, 0);
+        match decoder.squelch_level {
+            Some(level) => format!(
+                "{:.0} dBFS ({}, signal {})",
+                level,
+                if decoder.squelch_open { "open" } else { "closed" },
+                signal
+            ),
+            None => format!("Off (signal {})", signal),
+        }
+    };
+    let monitor_str = if app.state.read().ui.monitor { "On" } else { "Off (decoding only)" };
+    let noise_str = {
+        let state = app.state.read();
+        let level = state.decoder.noise_reduction;
+        if level == NoiseReduction::Off || NoiseReduction::applies_to(state.decoder.mode) {
+            level.name().to_string()
+        } else {
+            format!("{} (bypassed)", level.name())
+        }
+    };
+    // What shapes the current mode's audio: the de-emphasis or the BFO, and
+    // the AGC
+    let demod_str = {
+        let state = app.state.read();
+        let decoder = &state.decoder;
+        let agc = format!("AGC {}", decoder.agc_speed.name());
+        if uses_deemphasis(decoder.mode) {
+            format!("De-emphasis {}, {}", deemphasis_label(decoder.deemphasis), agc)
+        } else if uses_bfo(decoder.mode) {
+            format!("BFO {} Hz, {}", decoder.bfo_offset, agc)
+        } else {
+            agc
+        }
+    };
+    let (recording_mode, recording_format) = {
+        let state = app.state.read();
+        (state.recording.mode, state.recording.format)
+    };
     let sample_rate = app.get_sample_rate();
     let is_recording = app.is_recording();
-
-    let gain_str = if gain == -1 {
-        "Auto".to_string()
-    } else {
-        format!("{}.{} dB", gain / 10, gain % 10)
+    let (recording_file, recording_summary, low_space) = {
+        let state = app.state.read();
+        let file = state
+            .recording
+            .file_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        (file, state.recording.summary(), state.recording.is_low_on_space())
     };
 
-    let controls_text = vec![
+    let mut controls_text = vec![
         create_control_line(
-            "Frequency:",
+            format!("Frequency {}:", chain.name()),
             format!("{:.3} MHz", freq as f64 / 1_000_000.0),
             selected == ControlId::Frequency,
+            theme,
         ),
         create_control_line(
-            "Mode:",
+            format!("Mode {}:", chain.name()),
             mode.name(),
             selected == ControlId::Mode,
+            theme,
         ),
+        create_control_line("Filter A:", filter_str, false, theme),
+        vfo_line(app),
         create_control_line(
             "Gain:",
             gain_str,
             selected == ControlId::Gain,
+            theme,
         ),
+        create_control_line(
+            "Squelch:",
+            squelch_str,
+            selected == ControlId::Squelch,
+            theme,
+        ),
+        create_control_line("Monitor:", monitor_str, false, theme),
+        create_control_line("Noise Reduce:", noise_str, false, theme),
+        create_control_line("Demod:", demod_str, false, theme),
         create_control_line(
             "Sample Rate:",
             format!("{:.3} MHz", sample_rate as f64 / 1_000_000.0),
             selected == ControlId::SampleRate,
+            theme,
         ),
-        Line::from(""),
+        create_control_line("Channel B:", channel_b, false, theme),
         create_control_line(
             "Record:",
-            if is_recording { "[ACTIVE]" } else { "[Press R]" },
+            if is_recording {
+                recording_summary
+            } else {
+                let mut idle = format!(
+                    "[Press {}] {}",
+                    app.keymap.label(Action::ToggleRecord),
+                    recording_mode.name()
+                );
+                if recording_mode == RecordingMode::Iq {
+                    idle.push_str(&format!(" {}", recording_format.name()));
+                }
+                idle
+            },
             selected == ControlId::Record,
+            theme,
         ),
+        if is_recording {
+            Line::from(Span::styled(
+                format!("  {}", recording_file),
+                Style::default().fg(if low_space { theme.warning } else { theme.label }),
+            ))
+        } else {
+            Line::from("")
+        },
+    ];
+    controls_text.extend(help_lines(&app.keymap, theme));
+
+    let focused = app.state.read().ui.focused_pane == PaneId::Controls;
+    let paragraph =
+        Paragraph::new(controls_text).block(pane_block("Controls".to_string(), focused, theme));
+
+    f.render_widget(paragraph, area);
+}
+
+/// Both VFOs, the active one highlighted
+fn vfo_line(app: &App) -> Line<'static> {
+    let (vfos, active) = {
+        let state = app.state.read();
+        (state.sdr.vfo, state.sdr.active_vfo)
+    };
+    let mut spans = vec![Span::raw(format!("  {:15}", "VFO:"))];
+    for (index, vfo) in vfos.iter().enumerate() {
+        let text = format!(
+            "{} {:.3} {}",
+            VfoConfig::name(index),
+            vfo.frequency as f64 / 1_000_000.0,
+            vfo.mode.name()
+        );
+        let style = if index == active {
+            Style::default().fg(app.theme.accent).add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default().fg(app.theme.label)
+        };
+        spans.push(Span::styled(text, style));
+        spans.push(Span::raw(" "));
+    }
+    Line::from(spans)
+}
+
+/// Width of the playback progress bar in the status bar
+const PLAYBACK_BAR_WIDTH: usize = 20;
+
+/// Key help entries, one line each: the actions whose keys are shown and
+/// what they do
+const HELP: &[&[(&[Action], &str)]] = &[
+    &[
+        (&[Action::NextPane], "Next pane"),
+        (&[Action::NextControl], "Next control"),
+        (&[Action::SelectChain], "Channel A/B"),
+    ],
+    &[(&[Action::Increase, Action::Decrease], "Adjust value")],
+    &[(&[Action::TunerAgc, Action::RtlAgc], "Tuner auto gain / RTL AGC")],
+    &[(&[Action::GainAssist, Action::CancelGainSweep], "Gain assistant / cancel")],
+    &[(&[Action::CalibratePpm], "PPM calibration on the tuned carrier")],
+    &[(&[Action::FrequencyFlip], "Previous frequency"), (&[Action::ShowHistory], "History")],
+    &[(&[Action::ShowProfiles], "Switch profile")],
+    &[(&[Action::ShowBookmarks], "Bookmarks")],
+    &[(&[Action::SwapVfo], "Swap VFO A/B"), (&[Action::CopyVfo], "Copy A→B")],
+    &[(&[Action::ToggleMonitor], "Speaker on/off, decoders keep running")],
+    &[(&[Action::TestTone], "Test tone, to check the audio output")],
+    &[(&[Action::NoiseReduction], "Noise reduction off/low/medium/high (voice)")],
+    &[
+        (&[Action::AgcSpeed], "AGC off/slow/fast"),
+        (&[Action::Deemphasis], "De-emphasis"),
+        (&[Action::BfoDown, Action::BfoUp], "BFO"),
+    ],
+    &[(&[Action::ToggleDtmf], "DTMF decoder (NFM)")],
+    &[(
+        &[
+            Action::ClearMessages,
+            Action::PauseMessages,
+            Action::ExportMessages,
+            Action::ExpandMessages,
+        ],
+        "Decoder pane clear/pause/export/raw",
+    )],
+    &[(&[Action::ToggleDecodeLog], "Decode log on/off")],
+    &[
+        (&[Action::ToggleAfSpectrum], "Audio spectrum"),
+        (&[Action::ToggleConstellation], "IQ constellation"),
+    ],
+    &[(
+        &[Action::ToggleScope, Action::ScopeTrigger, Action::ScopeZoomIn, Action::ScopeZoomOut],
+        "Scope/trigger/scale",
+    )],
+    &[(&[Action::ToggleStats], "Processing stats"), (&[Action::CycleTheme], "Theme")],
+    &[
+        (&[Action::SpectrumMode], "Spectrum bars/Braille"),
+        (&[Action::SpectrumInvert], "Invert"),
+        (&[Action::TogglePriority], "Priority"),
+    ],
+    &[(&[Action::ExportWaterfall], "Save the waterfall as a PNG")],
+    &[(
+        &[Action::WaterfallPause, Action::WaterfallOlder, Action::WaterfallNewer],
+        "Waterfall pane pause/scroll",
+    )],
+    &[(
+        &[Action::WaterfallSlower, Action::WaterfallFaster, Action::WaterfallIntegration],
+        "Waterfall slower/faster, average/peak",
+    )],
+    &[(
+        &[Action::AircraftSort, Action::AircraftPageUp, Action::AircraftPageDown],
+        "Aircraft sort/scroll",
+    )],
+    &[(
+        &[Action::ToggleActivity, Action::ActivityPrev, Action::ActivityNext, Action::ActivityTune],
+        "Activity/select/tune",
+    )],
+    &[(&[Action::PlaceMarker, Action::ClearMarkers], "Marker on the peak / clear markers")],
+    &[(&[Action::CalibrateLevel], "Calibrate dBm on a known signal at the peak")],
+    &[(&[Action::NextReceiver], "Next receiver"), (&[Action::ToggleClock], "UTC/local")],
+    &[(&[Action::Undo, Action::Redo], "Undo/redo tuning and settings")],
+    &[(&[Action::FilterNarrower, Action::FilterWider], "Filter narrower/wider")],
+    &[(
+        &[
+            Action::ToggleSpectrumPane,
+            Action::ToggleWaterfallPane,
+            Action::ToggleControlsPane,
+            Action::ToggleDecoderPane,
+        ],
+        "Show/hide panes",
+    )],
+    &[(&[Action::Quit], "Quit"), (&[Action::ToggleRecord], "Record")],
+    &[(&[Action::RecordFormat], "IQ recording format")],
+    &[(
+        &[Action::PlaybackPause, Action::SeekBack, Action::SeekForward, Action::PlaybackSpeed],
+        "Playback pause/seek/speed",
+    )],
+    &[(&[Action::Seek0, Action::Seek5, Action::Seek9], "Playback jump to 0/50/90%")],
+];
+
+/// Key help and presets for the controls panel, from the effective keymap
+fn help_lines(keymap: &Keymap, theme: &Theme) -> Vec<Line<'static>> {
+    let keys = |actions: &[Action]| {
+        actions.iter().map(|&a| keymap.label(a)).collect::<Vec<_>>().join(" ")
+    };
+    let key_style = Style::default().fg(theme.key);
+    let preset_style = Style::default().fg(theme.accent);
+
+    let mut lines = vec![Line::from(vec![
+        Span::styled("Controls:", Style::default().fg(theme.label)),
+    ])];
+    for (i, entries) in HELP.iter().enumerate() {
+        let mut spans = Vec::new();
+        for (actions, text) in entries.iter() {
+            if !spans.is_empty() {
+                spans.push(Span::raw("  "));
+            }
+            spans.push(Span::styled(keys(actions), key_style));
+            spans.push(Span::raw(format!(" - {}", text)));
+        }
+        lines.push(Line::from(spans));
+        // Presets follow the value keys
+        if i == 1 {
+            lines.push(Line::from(vec![
+                Span::styled(
+                    format!(
+                        "{}-{},{}",
+                        keys(&[Action::Preset1]),
+                        keys(&[Action::Preset9]),
+                        keys(&[Action::Preset0])
+                    ),
+                    key_style,
+                ),
+                Span::raw(" - Freq presets"),
+            ]));
+        }
+    }
+
+    lines.extend([
         Line::from(""),
         Line::from(vec![
-            Span::styled("Controls:", Style::default().fg(Color::Gray)),
-        ]),
-        Line::from(vec![
-            Span::styled("Tab", Style::default().fg(Color::Green)),
-            Span::raw(" - Next control  "),
-        ]),
-        Line::from(vec![
-            Span::styled("↑↓←→/hjkl", Style::default().fg(Color::Green)),
-            Span::raw(" - Adjust value"),
-        ]),
-        Line::from(vec![
-            Span::styled("1-9,0", Style::default().fg(Color::Green)),
-            Span::raw(" - Freq presets"),
-        ]),
-        Line::from(vec![
-            Span::styled("Q", Style::default().fg(Color::Green)),
-            Span::raw(" - Quit  "),
-            Span::styled("R", Style::default().fg(Color::Green)),
-            Span::raw(" - Record"),
-        ]),
-        Line::from(""),
-        Line::from(vec![
-            Span::styled("Presets:", Style::default().fg(Color::Gray)),
+            Span::styled("Presets:", Style::default().fg(theme.label)),
         ]),
         Line::from(vec![
-            Span::styled("1", Style::default().fg(Color::Cyan)),
+            Span::styled(keys(&[Action::Preset1]), preset_style),
             Span::raw(" APRS-NA  "),
-            Span::styled("2", Style::default().fg(Color::Cyan)),
+            Span::styled(keys(&[Action::Preset2]), preset_style),
             Span::raw(" APRS-EU"),
         ]),
         Line::from(vec![
-            Span::styled("3-9", Style::default().fg(Color::Cyan)),
+            Span::styled(
+                format!("{}-{}", keys(&[Action::Preset3]), keys(&[Action::Preset9])),
+                preset_style,
+            ),
             Span::raw(" NOAA 162.4-162.55 MHz"),
         ]),
         Line::from(vec![
-            Span::styled("0", Style::default().fg(Color::Cyan)),
+            Span::styled(keys(&[Action::Preset0]), preset_style),
             Span::raw(" ADS-B (1090 MHz)"),
         ]),
-    ];
-
-    let paragraph = Paragraph::new(controls_text)
-        .block(Block::default().title("Controls").borders(Borders::ALL));
-
-    f.render_widget(paragraph, area);
+    ]);
+    lines
 }
 
 /// Create a control line with optional highlighting
-fn create_control_line(label: impl Into<String>, value: impl Into<String>, selected: bool) -> Line<'static> {
+fn create_control_line(
+    label: impl Into<String>,
+    value: impl Into<String>,
+    selected: bool,
+    theme: &Theme,
+) -> Line<'static> {
     let style = if selected {
         Style::default()
-            .fg(Color::Yellow)
+            .fg(theme.selected)
             .add_modifier(Modifier::BOLD)
     } else {
         Style::default()
@@ -270,22 +947,312 @@ fn create_control_line(label: impl Into<String>, value: impl Into<String>, selec
     Line::from(vec![
         Span::styled(
             if selected { "> " } else { "  " }.to_string(),
-            Style::default().fg(Color::Yellow),
+            Style::default().fg(theme.selected),
         ),
         Span::styled(format!("{:15}", label.into()), style),
-        Span::styled(value.into(), style.fg(Color::Cyan)),
+        Span::styled(value.into(), style.fg(theme.accent)),
     ])
 }
 
-/// Render decoder output placeholder
-fn render_decoder_placeholder(f: &mut Frame, area: Rect) {
-    let block = Block::default()
-        .title("Decoder Output")
-        .borders(Borders::ALL);
+/// Render decoder output
+fn render_decoder_output(f: &mut Frame, app: &App, area: Rect) {
+    let state = app.state.read();
+    let mut title = String::from("Decoder Output");
+    if state.decoder.dtmf_enabled {
+        title.push_str(" [DTMF]");
+    }
+    if let Some(wpm) = state.decoder.cw_wpm {
+        title.push_str(&format!(" [CW {:.0} WPM]", wpm));
+    }
+    match state.decoder.apt_lines {
+        Some(0) => title.push_str(" [APT searching]"),
+        Some(lines) => title.push_str(&format!(" [APT {} lines]", lines)),
+        None => {}
+    }
+    if state.decoder.log_enabled {
+        title.push_str(" [LOG]");
+    }
+    let view = state.ui.message_view;
+    if view.is_paused() {
+        title.push_str(&format!(" [PAUSED +{} new]", view.new_count(state.decoder.received)));
+    }
+    let focused = state.ui.focused_pane == PaneId::Decoder;
+    let block = pane_block(title, focused, &app.theme);
 
-    let text = Paragraph::new("Decoded messages (APRS, ADS-B, etc.) will appear here")
-        .block(block)
-        .style(Style::default().fg(Color::DarkGray));
+    let messages = view.shown(&state.decoder.messages, state.decoder.received);
+    let widget = super::widgets::DecoderOutputWidget::new(messages)
+        .clock(state.ui.clock)
+        .station(app.station)
+        .expanded(view.is_expanded())
+        .colors(app.theme.dim, app.theme.label)
+        .block(block);
+    f.render_widget(widget, area);
+}
+
+/// A bordered pane, its border highlighted while it has focus
+fn pane_block(title: String, focused: bool, theme: &Theme) -> Block<'static> {
+    let block = Block::default().title(title).borders(Borders::ALL);
+    if focused {
+        block.border_style(Style::default().fg(theme.selected))
+    } else {
+        block
+    }
+}
+
+/// Render the ADS-B aircraft table
+fn render_aircraft_table(f: &mut Frame, app: &App, area: Rect) {
+    // Snapshot the map so the lock isn't held while drawing
+    let (aircraft, sort, scroll, logging, focused) = {
+        let state = app.state.read();
+        (
+            state.decoder.aircraft.values().cloned().collect::<Vec<_>>(),
+            state.ui.aircraft_sort,
+            state.ui.aircraft_scroll,
+            state.decoder.log_enabled,
+            state.ui.focused_pane == PaneId::Decoder,
+        )
+    };
+
+    let mut title = format!("Aircraft ({}) - sorted by {}", aircraft.len(), sort.name());
+    if logging {
+        title.push_str(" [LOG]");
+    }
+    let block = pane_block(title, focused, &app.theme);
+
+    let widget = super::widgets::AircraftTableWidget::new(
+        aircraft.iter().collect(),
+        std::time::Instant::now(),
+    )
+    .sort(sort)
+    .scroll(scroll)
+    .station(app.station)
+    .block(block);
+    f.render_widget(widget, area);
+}
+
+/// Render the busy frequencies found by the activity detector
+fn render_activity_table(f: &mut Frame, app: &App, area: Rect) {
+    let (table, selected, focused) = {
+        let state = app.state.read();
+        (
+            state.spectrum.activity.clone(),
+            state.ui.activity_selected,
+            state.ui.focused_pane == PaneId::Decoder,
+        )
+    };
+
+    let title = format!("Activity ({} signals)", table.signals().len());
+    let block = pane_block(title, focused, &app.theme);
+    let widget =
+        super::widgets::ActivityTableWidget::new(table.signals(), std::time::Instant::now())
+            .selected(selected)
+            .block(block);
+    f.render_widget(widget, area);
+}
+
+/// Render the active modal dialog, if any, centered over the UI
+fn render_modal(f: &mut Frame, app: &App) {
+    let Some(modal) = app.state.read().ui.modal.clone() else {
+        return;
+    };
+
+    let (title, message) = match modal {
+        Modal::ConfirmQuit(reason) => ("Confirm Quit", reason),
+        Modal::ConfirmClearMessages(question) => ("Clear Messages", question),
+        Modal::ApplyPpm { message, .. } => ("PPM Calibration", message),
+        Modal::CalibrateLevel { peak, input } => (
+            "Level Calibration",
+            format!("Peak at {:.1} dBFS is this many dBm: {}_ (Enter to set)", peak, input),
+        ),
+        Modal::History { selected } => return render_history(f, app, selected),
+        Modal::Profiles { selected } => return render_profiles(f, app, selected),
+        Modal::Bookmarks { selected } => return render_bookmarks(f, app, selected),
+    };
+
+    let width = (message.chars().count() as u16 + 4).min(f.area().width);
+    let area = centered_rect(width, 3, f.area());
+
+    let paragraph = Paragraph::new(Line::from(Span::styled(
+        message,
+        Style::default().fg(app.theme.warning).add_modifier(Modifier::BOLD),
+    )))
+    .block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(app.theme.recording)),
+    );
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+/// Render the frequency history picker with row `selected` highlighted
+fn render_history(f: &mut Frame, app: &App, selected: usize) {
+    let lines: Vec<Line> = app
+        .state
+        .read()
+        .ui
+        .frequency_history
+        .entries()
+        .iter()
+        .enumerate()
+        .map(|(n, entry)| {
+            let text = format!(
+                " {}  {:>10.4} MHz  {:<6} ",
+                (n + 1) % 10,
+                entry.frequency as f64 / 1_000_000.0,
+                entry.mode.name()
+            );
+            let style = if n == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(text, style))
+        })
+        .collect();
+
+    let title = " Recent (Enter or 1-0 to tune) ";
+    let width = lines.iter().map(Line::width).max().unwrap_or(0).max(title.len()) as u16 + 2;
+    let area = centered_rect(width, lines.len() as u16 + 2, f.area());
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(app.theme.accent)),
+    );
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+/// Render the profile list with row `selected` highlighted
+fn render_profiles(f: &mut Frame, app: &App, selected: usize) {
+    let name_width = app.profiles.iter().map(|p| p.name.len()).max().unwrap_or(0);
+    let lines: Vec<Line> = app
+        .profiles
+        .iter()
+        .enumerate()
+        .map(|(n, profile)| {
+            let text = format!(
+                " {:<width$}  {} ",
+                profile.name,
+                profile.summary(),
+                width = name_width
+            );
+            let style = if n == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(text, style))
+        })
+        .collect();
+
+    let title = " Profiles (Enter to switch) ";
+    let width = lines.iter().map(Line::width).max().unwrap_or(0).max(title.len()) as u16 + 2;
+    let area = centered_rect(width, lines.len() as u16 + 2, f.area());
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(app.theme.accent)),
+    );
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+/// Render the bookmark list with row `selected` highlighted
+fn render_bookmarks(f: &mut Frame, app: &App, selected: usize) {
+    let state = app.state.read();
+    let bookmarks = state.ui.bookmarks.entries();
+    // The one whose levels are in force is starred
+    let parked = state.ui.level_override.parked_on();
+    let name_width = bookmarks.iter().map(|b| b.name.len()).max().unwrap_or(0);
+    let mut lines: Vec<Line> = bookmarks
+        .iter()
+        .enumerate()
+        .map(|(n, bookmark)| {
+            let mark = if parked == Some(bookmark.frequency) { '*' } else { ' ' };
+            let text = format!(
+                "{}{:>10.4} MHz  {:<6}  {:<width$}  {} ",
+                mark,
+                bookmark.frequency as f64 / 1_000_000.0,
+                bookmark.mode.name(),
+                bookmark.name,
+                bookmark.overrides.summary(),
+                width = name_width
+            );
+            let style = if n == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(text, style))
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::from(" No bookmarks yet "));
+    }
+    drop(state);
+
+    let title = " Bookmarks (Enter tune, a add, d delete, s store levels, i import, e export) ";
+    let width = lines.iter().map(Line::width).max().unwrap_or(0).max(title.len()) as u16 + 2;
+    let area = centered_rect(width, lines.len() as u16 + 2, f.area());
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(app.theme.accent)),
+    );
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+/// Render the processing stats overlay in the top-right corner of `area`
+fn render_stats(f: &mut Frame, app: &App, area: Rect) {
+    let lines = {
+        let state = app.state.read();
+        if !state.ui.show_stats {
+            return;
+        }
+        state.stats.lines()
+    };
 
-    f.render_widget(text, area);
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u16 + 2;
+    let height = lines.len() as u16 + 2;
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    let area = Rect {
+        x: area.right() - width,
+        y: area.y,
+        width,
+        height,
+    };
+
+    let paragraph = Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+        .block(
+            Block::default()
+                .title("Stats")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(app.theme.accent)),
+        );
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+/// Compute a rectangle of the given size centered in `area`
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
 }
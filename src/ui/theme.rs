@@ -0,0 +1,388 @@
+//! Color themes
+//!
+//! A theme picks the colors of the UI chrome, the spectrum bars and the
+//! waterfall palette. The config file chooses a built-in preset and can
+//! override single colors:
+//!
+//! ```toml
+//! [theme]
+//! preset = "light"        # default, light, viridis, inferno or grayscale
+//! accent = "blue"         # color names, indexed colors or "#rrggbb"
+//! selected = "#d07000"
+//! waterfall = "inferno"   # classic, viridis, inferno or grayscale
+//! ```
+//!
+//! Cycling themes at runtime steps through the presets as they are, without
+//! the overrides.
+
+use crate::types::ThemeConfig;
+use ratatui::style::Color;
+use std::sync::OnceLock;
+
+/// Entries in the perceptual colormap lookup tables
+const LUT_SIZE: usize = 256;
+
+/// matplotlib's viridis sampled at nine evenly spaced points
+const VIRIDIS_STOPS: [[f32; 3]; 9] = [
+    [68.0, 1.0, 84.0],
+    [72.0, 40.0, 120.0],
+    [62.0, 73.0, 137.0],
+    [49.0, 104.0, 142.0],
+    [38.0, 130.0, 142.0],
+    [31.0, 158.0, 137.0],
+    [53.0, 183.0, 121.0],
+    [110.0, 206.0, 88.0],
+    [253.0, 231.0, 37.0],
+];
+
+/// matplotlib's inferno sampled at nine evenly spaced points
+const INFERNO_STOPS: [[f32; 3]; 9] = [
+    [0.0, 0.0, 4.0],
+    [31.0, 12.0, 72.0],
+    [85.0, 15.0, 109.0],
+    [136.0, 34.0, 106.0],
+    [186.0, 54.0, 85.0],
+    [227.0, 89.0, 51.0],
+    [249.0, 142.0, 9.0],
+    [248.0, 201.0, 50.0],
+    [252.0, 255.0, 164.0],
+];
+
+type Lut = [[f32; 3]; LUT_SIZE];
+
+/// Waterfall palette, mapping weak to strong signals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colormap {
+    /// Blue through cyan, green and yellow to red
+    Classic,
+    Viridis,
+    Inferno,
+    Grayscale,
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 4] = [
+        Colormap::Classic,
+        Colormap::Viridis,
+        Colormap::Inferno,
+        Colormap::Grayscale,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Colormap::Classic => "classic",
+            Colormap::Viridis => "viridis",
+            Colormap::Inferno => "inferno",
+            Colormap::Grayscale => "grayscale",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Colormap> {
+        Colormap::ALL.iter().copied().find(|c| c.name() == name)
+    }
+
+    /// Color for a signal level in 0.0-1.0; values outside are clamped
+    pub fn color(self, normalized: f32) -> Color {
+        let normalized = if normalized.is_nan() { 0.0 } else { normalized.clamp(0.0, 1.0) };
+        match self {
+            Colormap::Classic => classic_color(normalized),
+            Colormap::Viridis => lut_color(viridis_lut(), normalized),
+            Colormap::Inferno => lut_color(inferno_lut(), normalized),
+            Colormap::Grayscale => {
+                let level = (normalized * 255.0).round() as u8;
+                Color::Rgb(level, level, level)
+            }
+        }
+    }
+}
+
+/// The original waterfall gradient: blue -> cyan -> green -> yellow -> red
+fn classic_color(normalized: f32) -> Color {
+    if normalized < 0.2 {
+        // Very weak signal: dark blue
+        Color::Rgb(0, 0, (normalized * 5.0 * 128.0) as u8 + 32)
+    } else if normalized < 0.4 {
+        // Weak signal: blue to cyan
+        let t = (normalized - 0.2) * 5.0;
+        Color::Rgb(0, (t * 128.0) as u8, 128 + (t * 127.0) as u8)
+    } else if normalized < 0.6 {
+        // Medium signal: cyan to green
+        let t = (normalized - 0.4) * 5.0;
+        Color::Rgb((t * 64.0) as u8, 128 + (t * 127.0) as u8, 255 - (t * 255.0) as u8)
+    } else if normalized < 0.8 {
+        // Strong signal: green to yellow
+        let t = (normalized - 0.6) * 5.0;
+        Color::Rgb(64 + (t * 191.0) as u8, 255, 0)
+    } else {
+        // Very strong signal: yellow to red
+        let t = (normalized - 0.8) * 5.0;
+        Color::Rgb(255, 255 - (t * 255.0) as u8, 0)
+    }
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+/// Expand evenly spaced color stops into a full lookup table
+fn build_lut(stops: &[[f32; 3]]) -> Lut {
+    let segments = (stops.len() - 1) as f32;
+    let mut lut = [[0.0; 3]; LUT_SIZE];
+    for (i, entry) in lut.iter_mut().enumerate() {
+        let position = i as f32 / (LUT_SIZE - 1) as f32 * segments;
+        let index = (position as usize).min(stops.len() - 2);
+        *entry = lerp(stops[index], stops[index + 1], position - index as f32);
+    }
+    lut
+}
+
+fn viridis_lut() -> &'static Lut {
+    static LUT: OnceLock<Lut> = OnceLock::new();
+    LUT.get_or_init(|| build_lut(&VIRIDIS_STOPS))
+}
+
+fn inferno_lut() -> &'static Lut {
+    static LUT: OnceLock<Lut> = OnceLock::new();
+    LUT.get_or_init(|| build_lut(&INFERNO_STOPS))
+}
+
+/// Look up `normalized` in a table, interpolating between neighbouring entries
+fn lut_rgb(lut: &Lut, normalized: f32) -> [f32; 3] {
+    let position = normalized * (LUT_SIZE - 1) as f32;
+    let index = (position as usize).min(LUT_SIZE - 2);
+    lerp(lut[index], lut[index + 1], position - index as f32)
+}
+
+fn lut_color(lut: &Lut, normalized: f32) -> Color {
+    let [r, g, b] = lut_rgb(lut, normalized);
+    Color::Rgb(r.round() as u8, g.round() as u8, b.round() as u8)
+}
+
+/// Colors for the UI, spectrum and waterfall
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Preset this theme started from
+    pub name: &'static str,
+    /// Title, values and overlay borders
+    pub accent: Color,
+    /// Body text
+    pub text: Color,
+    /// Placeholders, timestamps and plot axes
+    pub dim: Color,
+    /// Section headings and small labels
+    pub label: Color,
+    /// Keys in the help text
+    pub key: Color,
+    /// The selected control
+    pub selected: Color,
+    /// Status messages and low disk space
+    pub warning: Color,
+    /// Recording indicator, alerts and the quit dialog
+    pub recording: Color,
+    /// Spectrum bar colors, weakest to strongest
+    pub spectrum: [Color; 5],
+    pub waterfall: Colormap,
+    /// Background of the span being demodulated
+    pub passband: Color,
+}
+
+impl Theme {
+    /// Built-in preset names, in cycling order
+    pub const PRESETS: [&'static str; 5] = ["default", "light", "viridis", "inferno", "grayscale"];
+
+    pub fn preset(name: &str) -> Option<Theme> {
+        let theme = match name {
+            "default" => Theme::default(),
+            "light" => Theme {
+                name: "light",
+                accent: Color::Blue,
+                text: Color::Black,
+                dim: Color::Gray,
+                label: Color::DarkGray,
+                key: Color::Green,
+                selected: Color::Magenta,
+                warning: Color::Rgb(176, 112, 0),
+                recording: Color::Red,
+                spectrum: [Color::Blue, Color::Cyan, Color::Green, Color::Magenta, Color::Red],
+                waterfall: Colormap::Viridis,
+                passband: Color::Rgb(220, 226, 240),
+            },
+            "viridis" => Theme::with_colormap("viridis", Colormap::Viridis),
+            "inferno" => Theme::with_colormap("inferno", Colormap::Inferno),
+            "grayscale" => Theme {
+                name: "grayscale",
+                accent: Color::White,
+                text: Color::Reset,
+                dim: Color::DarkGray,
+                label: Color::Gray,
+                key: Color::White,
+                selected: Color::White,
+                warning: Color::White,
+                recording: Color::Gray,
+                spectrum: Theme::spectrum_from(Colormap::Grayscale),
+                waterfall: Colormap::Grayscale,
+                passband: Color::Rgb(48, 48, 48),
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// The default UI colors with spectrum and waterfall from `colormap`
+    fn with_colormap(name: &'static str, colormap: Colormap) -> Theme {
+        Theme {
+            name,
+            spectrum: Theme::spectrum_from(colormap),
+            waterfall: colormap,
+            ..Theme::default()
+        }
+    }
+
+    /// Spectrum bar colors sampled from the middle of each fifth of `colormap`
+    fn spectrum_from(colormap: Colormap) -> [Color; 5] {
+        [0.1, 0.3, 0.5, 0.7, 0.9].map(|level| colormap.color(level))
+    }
+
+    /// The preset after this one, wrapping around
+    pub fn next(&self) -> Theme {
+        let index = Theme::PRESETS.iter().position(|&p| p == self.name).unwrap_or(0);
+        let next = Theme::PRESETS[(index + 1) % Theme::PRESETS.len()];
+        Theme::preset(next).unwrap_or_default()
+    }
+
+    /// Build the configured theme, returning it with any problems found
+    /// (unknown preset, colors or colormap) as readable lines
+    pub fn from_config(config: &ThemeConfig) -> (Self, Vec<String>) {
+        let mut problems = Vec::new();
+        let mut theme = Theme::preset(&config.preset).unwrap_or_else(|| {
+            problems.push(format!(
+                "unknown preset '{}' (expected one of {})",
+                config.preset,
+                Theme::PRESETS.join(", ")
+            ));
+            Theme::default()
+        });
+
+        let overrides = [
+            ("accent", &config.accent, &mut theme.accent),
+            ("text", &config.text, &mut theme.text),
+            ("selected", &config.selected, &mut theme.selected),
+            ("recording", &config.recording, &mut theme.recording),
+        ];
+        for (field, value, color) in overrides {
+            let Some(value) = value else { continue };
+            match value.parse() {
+                Ok(parsed) => *color = parsed,
+                Err(_) => problems.push(format!("{}: unknown color '{}'", field, value)),
+            }
+        }
+
+        if let Some(name) = &config.waterfall {
+            match Colormap::from_name(name) {
+                Some(colormap) => theme.waterfall = colormap,
+                None => problems.push(format!("waterfall: unknown colormap '{}'", name)),
+            }
+        }
+
+        (theme, problems)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: "default",
+            accent: Color::Cyan,
+            text: Color::Reset,
+            dim: Color::DarkGray,
+            label: Color::Gray,
+            key: Color::Green,
+            selected: Color::Yellow,
+            warning: Color::Yellow,
+            recording: Color::Red,
+            spectrum: [Color::Blue, Color::Cyan, Color::Green, Color::Yellow, Color::Red],
+            waterfall: Colormap::Classic,
+            passband: Color::Rgb(32, 36, 56),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Relative luminance (Rec. 709 weights) of an sRGB color
+    fn luminance([r, g, b]: [f32; 3]) -> f32 {
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    }
+
+    #[test]
+    fn test_lut_luminance_is_monotonic() {
+        for lut in [viridis_lut(), inferno_lut()] {
+            for pair in lut.windows(2) {
+                assert!(luminance(pair[1]) > luminance(pair[0]), "{:?}", pair);
+            }
+            // Interpolating between entries keeps it rising
+            let mut last = f32::MIN;
+            for i in 0..=1000 {
+                let value = luminance(lut_rgb(lut, i as f32 / 1000.0));
+                assert!(value >= last);
+                last = value;
+            }
+        }
+    }
+
+    #[test]
+    fn test_colormap_ends() {
+        assert_eq!(Colormap::Viridis.color(0.0), Color::Rgb(68, 1, 84));
+        assert_eq!(Colormap::Viridis.color(1.0), Color::Rgb(253, 231, 37));
+        assert_eq!(Colormap::Inferno.color(-3.0), Color::Rgb(0, 0, 4));
+        assert_eq!(Colormap::Inferno.color(2.0), Color::Rgb(252, 255, 164));
+        assert_eq!(Colormap::Grayscale.color(0.5), Color::Rgb(128, 128, 128));
+        assert_eq!(Colormap::Classic.color(f32::NAN), Color::Rgb(0, 0, 32));
+    }
+
+    #[test]
+    fn test_presets_cycle() {
+        let mut theme = Theme::default();
+        let mut seen = vec![theme.name];
+        for _ in 1..Theme::PRESETS.len() {
+            theme = theme.next();
+            seen.push(theme.name);
+        }
+        assert_eq!(seen, Theme::PRESETS);
+        assert_eq!(theme.next(), Theme::default());
+        assert_eq!(Theme::preset("viridis").unwrap().waterfall, Colormap::Viridis);
+        assert_eq!(Theme::preset("solarized"), None);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config: ThemeConfig = toml::from_str(
+            r##"
+            preset = "light"
+            accent = "#102030"
+            selected = "chartreuse"
+            waterfall = "inferno"
+            "##,
+        )
+        .unwrap();
+        let (theme, problems) = Theme::from_config(&config);
+        assert_eq!(theme.name, "light");
+        assert_eq!(theme.accent, Color::Rgb(0x10, 0x20, 0x30));
+        // Bad overrides keep the preset's color
+        assert_eq!(theme.selected, Color::Magenta);
+        assert_eq!(theme.waterfall, Colormap::Inferno);
+        assert_eq!(problems, vec!["selected: unknown color 'chartreuse'".to_string()]);
+
+        let config: ThemeConfig = toml::from_str("preset = \"neon\"").unwrap();
+        let (theme, problems) = Theme::from_config(&config);
+        assert_eq!(theme, Theme::default());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("unknown preset 'neon'"));
+    }
+}
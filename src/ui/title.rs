@@ -0,0 +1,157 @@
+//! The terminal's window title
+//!
+//! The title says what chain A is on, so a terminal tab or a minimized
+//! window still shows it: `rtl-sdr-tui — 162.550 MHz NFM [REC]`. It is set
+//! at most once a second, straight to the terminal rather than through a
+//! redraw. The title the terminal had is pushed on xterm's title stack at
+//! start and popped on exit; terminals without the stack ignore both.
+//!
+//! Some terminal multiplexers mangle title sequences, so
+//! `ui.terminal_title = false` leaves the title alone.
+
+use super::app::App;
+use crate::types::DemodMode;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Least time between two title changes
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the original title is on the stack, to be put back at exit
+static SAVED: AtomicBool = AtomicBool::new(false);
+
+/// Title for a receiver on `frequency` in `mode`
+pub fn format_title(frequency: u32, mode: DemodMode, recording: bool) -> String {
+    let mut title = format!(
+        "rtl-sdr-tui \u{2014} {:.3} MHz {}",
+        frequency as f64 / 1_000_000.0,
+        mode.file_tag()
+    );
+    if recording {
+        title.push_str(" [REC]");
+    }
+    title
+}
+
+/// Title for what the app is on now
+pub fn app_title(app: &App) -> String {
+    let state = app.state.read();
+    format_title(
+        state
+            .sdr
+            .frequency
+            .saturating_add_signed(state.channels.offset_a),
+        state.decoder.mode,
+        state.recording.is_recording,
+    )
+}
+
+/// Keeps the title in step, without setting it more than once a second
+#[derive(Debug, Default)]
+pub struct TitleUpdater {
+    shown: Option<String>,
+    set_at: Option<Instant>,
+}
+
+impl TitleUpdater {
+    /// The title to set at `now`, if `title` is new and the last change was
+    /// long enough ago
+    ///
+    /// A change held back is returned by a later call once the interval is
+    /// up.
+    pub fn update(&mut self, title: String, now: Instant) -> Option<String> {
+        if self.shown.as_ref() == Some(&title) {
+            return None;
+        }
+        if self
+            .set_at
+            .is_some_and(|set_at| now.saturating_duration_since(set_at) < UPDATE_INTERVAL)
+        {
+            return None;
+        }
+        self.shown = Some(title.clone());
+        self.set_at = Some(now);
+        Some(title)
+    }
+}
+
+/// Push the terminal's title so [`restore`] can put it back
+pub fn save() -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x1b[22;0t")?;
+    stdout.flush()?;
+    SAVED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Set the terminal's title
+pub fn set(title: &str) -> io::Result<()> {
+    crossterm::execute!(io::stdout(), crossterm::terminal::SetTitle(title))
+}
+
+/// Put back the title [`save`] pushed; does nothing if it didn't
+pub fn restore() -> io::Result<()> {
+    if !SAVED.swap(false, Ordering::Relaxed) {
+        return Ok(());
+    }
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x1b[23;0t")?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_title() {
+        assert_eq!(
+            format_title(162_550_000, DemodMode::FmNarrow, false),
+            "rtl-sdr-tui \u{2014} 162.550 MHz NFM"
+        );
+        assert_eq!(
+            format_title(1_090_000_000, DemodMode::Adsb, true),
+            "rtl-sdr-tui \u{2014} 1090.000 MHz ADSB [REC]"
+        );
+        assert_eq!(
+            format_title(7_074_000, DemodMode::Usb, false),
+            "rtl-sdr-tui \u{2014} 7.074 MHz USB"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let start = Instant::now();
+        let mut updater = TitleUpdater::default();
+        let title = |mhz: u32| format_title(mhz * 1_000_000, DemodMode::FmNarrow, false);
+
+        // The first is set straight away, and the same again not at all
+        assert_eq!(updater.update(title(144), start), Some(title(144)));
+        assert_eq!(
+            updater.update(title(144), start + Duration::from_secs(5)),
+            None
+        );
+
+        // A change soon after waits out the interval, and only the latest
+        // is set then
+        let now = start + Duration::from_secs(6);
+        assert_eq!(updater.update(title(145), now), Some(title(145)));
+        assert_eq!(
+            updater.update(title(146), now + Duration::from_millis(300)),
+            None
+        );
+        assert_eq!(
+            updater.update(title(147), now + Duration::from_millis(600)),
+            None
+        );
+        assert_eq!(
+            updater.update(title(147), now + Duration::from_millis(1000)),
+            Some(title(147))
+        );
+
+        // Going back to a title shown before is still a change
+        let now = now + Duration::from_secs(3);
+        assert_eq!(updater.update(title(145), now), Some(title(145)));
+    }
+}
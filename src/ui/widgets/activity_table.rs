@@ -0,0 +1,163 @@
+use crate::dsp::detect::ActiveSignal;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    widgets::{Block, Widget},
+};
+use std::time::{Duration, Instant};
+
+/// Column headings, padded to the row layout
+const HEADER: &str = "  FREQ MHz  BW kHz   PEAK FIRST LAST";
+
+/// Signals not in the last few detections are shown dimmed
+const QUIET_AFTER: Duration = Duration::from_secs(2);
+
+/// Busy frequencies found by the activity detector, one row per signal
+pub struct ActivityTableWidget<'a> {
+    signals: &'a [ActiveSignal],
+    now: Instant,
+    /// Highlighted row, kept in view
+    selected: usize,
+    block: Option<Block<'a>>,
+}
+
+impl<'a> ActivityTableWidget<'a> {
+    /// Create a table of `signals` as of `now`
+    pub fn new(signals: &'a [ActiveSignal], now: Instant) -> Self {
+        Self {
+            signals,
+            now,
+            selected: 0,
+            block: None,
+        }
+    }
+
+    /// Set the block for the widget
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Set the highlighted row
+    pub fn selected(mut self, selected: usize) -> Self {
+        self.selected = selected;
+        self
+    }
+}
+
+/// One table row; times are seconds ago
+fn format_row(signal: &ActiveSignal, now: Instant) -> String {
+    format!(
+        "{:>10.4} {:>7.1} {:>6.1} {:>5} {:>4}",
+        signal.frequency as f64 / 1_000_000.0,
+        signal.bandwidth as f64 / 1_000.0,
+        signal.peak_db,
+        now.saturating_duration_since(signal.first_seen).as_secs(),
+        now.saturating_duration_since(signal.last_seen).as_secs(),
+    )
+}
+
+impl Widget for ActivityTableWidget<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(b) => {
+                let inner_area = b.inner(area);
+                b.render(area, buf);
+                inner_area
+            }
+            None => area,
+        };
+
+        if area.width < 2 || area.height < 2 {
+            return;
+        }
+
+        buf.set_stringn(
+            area.left(),
+            area.top(),
+            HEADER,
+            area.width as usize,
+            Style::default().fg(Color::Gray).add_modifier(Modifier::BOLD),
+        );
+
+        let visible = (area.height - 1) as usize;
+        let selected = self.selected.min(self.signals.len().saturating_sub(1));
+        let scroll = (selected + 1).saturating_sub(visible);
+        for (i, signal) in self.signals.iter().enumerate().skip(scroll).take(visible) {
+            let mut style = if self.now.saturating_duration_since(signal.last_seen) > QUIET_AFTER {
+                Style::default().fg(Color::DarkGray).add_modifier(Modifier::DIM)
+            } else {
+                Style::default()
+            };
+            if i == selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            buf.set_stringn(
+                area.left(),
+                area.top() + 1 + (i - scroll) as u16,
+                format_row(signal, self.now),
+                area.width as usize,
+                style,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::widgets::render_lines as render;
+
+    /// Three signals, the middle one last heard 30 s ago
+    fn fixture(now: Instant) -> Vec<ActiveSignal> {
+        let ago = |secs| now - Duration::from_secs(secs);
+        let signal = |frequency, bandwidth, peak_db, first, last| ActiveSignal {
+            frequency,
+            bandwidth,
+            peak_db,
+            first_seen: ago(first),
+            last_seen: ago(last),
+        };
+        vec![
+            signal(144_800_000, 12_000, -52.5, 95, 0),
+            signal(145_303_000, 3_000, -70.0, 60, 30),
+            signal(146_520_000, 11_000, -41.0, 5, 1),
+        ]
+    }
+
+    #[test]
+    fn test_rows() {
+        let now = Instant::now() + Duration::from_secs(1000);
+        let signals = fixture(now);
+        let (lines, buf) = render(ActivityTableWidget::new(&signals, now), 40, 5);
+
+        assert_eq!(lines[0], HEADER);
+        assert_eq!(lines[1], "  144.8000    12.0  -52.5    95    0");
+        assert_eq!(lines[2], "  145.3030     3.0  -70.0    60   30");
+        assert_eq!(lines[3], "  146.5200    11.0  -41.0     5    1");
+        assert!(lines[4].is_empty());
+
+        // The first row is selected by default; quiet signals are dimmed
+        assert!(buf[(2, 1)].modifier.contains(Modifier::REVERSED));
+        assert!(buf[(2, 2)].modifier.contains(Modifier::DIM));
+        assert!(!buf[(2, 3)].modifier.contains(Modifier::DIM));
+    }
+
+    #[test]
+    fn test_selection_kept_in_view() {
+        let now = Instant::now() + Duration::from_secs(1000);
+        let signals = fixture(now);
+
+        // Two rows fit; selecting the last scrolls it into view
+        let (lines, buf) = render(ActivityTableWidget::new(&signals, now).selected(2), 40, 3);
+        assert!(lines[1].starts_with("  145.3030"));
+        assert!(lines[2].starts_with("  146.5200"));
+        assert!(buf[(2, 2)].modifier.contains(Modifier::REVERSED));
+        assert!(!buf[(2, 1)].modifier.contains(Modifier::REVERSED));
+
+        // A selection past the end sticks to the last row
+        let (_, buf) = render(ActivityTableWidget::new(&signals, now).selected(9), 40, 5);
+        assert!(buf[(2, 3)].modifier.contains(Modifier::REVERSED));
+    }
+}
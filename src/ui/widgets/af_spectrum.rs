@@ -0,0 +1,131 @@
+use super::spectrum::draw_bars;
+use crate::dsp::AF_MAX_FREQ;
+use crate::ui::theme::Theme;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::{Block, Widget},
+};
+
+/// Compact spectrum of the demodulated audio with a 0–5 kHz axis
+pub struct AfSpectrumWidget<'a> {
+    /// Magnitude in dB, first bin at 0 Hz and last at `AF_MAX_FREQ`
+    data: &'a [f32],
+    block: Option<Block<'a>>,
+    min_db: f32,
+    max_db: f32,
+    /// Bar colors, weakest to strongest
+    palette: [Color; 5],
+}
+
+impl<'a> AfSpectrumWidget<'a> {
+    pub fn new(data: &'a [f32]) -> Self {
+        Self {
+            data,
+            block: None,
+            min_db: -90.0,
+            max_db: 0.0,
+            palette: Theme::default().spectrum,
+        }
+    }
+
+    /// Set the block for the widget
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Set the dB range for display
+    pub fn db_range(mut self, min: f32, max: f32) -> Self {
+        self.min_db = min;
+        self.max_db = max;
+        self
+    }
+
+    /// Set the bar colors, weakest to strongest
+    pub fn palette(mut self, palette: [Color; 5]) -> Self {
+        self.palette = palette;
+        self
+    }
+}
+
+impl Widget for AfSpectrumWidget<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(b) => {
+                let inner_area = b.inner(area);
+                b.render(area, buf);
+                inner_area
+            }
+            None => area,
+        };
+
+        if area.width < 2 || area.height < 2 || self.data.is_empty() {
+            return;
+        }
+
+        draw_bars(buf, area, self.data, self.min_db, self.max_db, &self.palette);
+        if area.height > 2 {
+            draw_khz_axis(buf, area);
+        }
+    }
+}
+
+/// Label every kHz along the bottom row, or just the ends when narrow
+fn draw_khz_axis(buf: &mut Buffer, area: Rect) {
+    let max_khz = (AF_MAX_FREQ / 1000.0) as u16;
+    let step = if area.width < 20 { max_khz } else { 1 };
+    let y = area.bottom() - 1;
+
+    for khz in (0..=max_khz).step_by(step as usize) {
+        let label = if khz == 0 { "0".to_string() } else { format!("{}k", khz) };
+        let x = area.left() + (area.width - 1) * khz / max_khz;
+        // Keep the last label inside the area
+        let x = x.min(area.right() - label.len() as u16);
+        buf.set_string(x, y, &label, Style::default().fg(Color::Gray));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(data: &[f32], width: u16, height: u16) -> Buffer {
+        let area = Rect::new(0, 0, width, height);
+        let mut buf = Buffer::empty(area);
+        AfSpectrumWidget::new(data).render(area, &mut buf);
+        buf
+    }
+
+    fn row(buf: &Buffer, y: u16) -> String {
+        (0..buf.area.width).map(|x| buf[(x, y)].symbol()).collect()
+    }
+
+    #[test]
+    fn test_tone_drawn_at_its_frequency() {
+        // 51 bins of 100 Hz; a tone at 1 kHz
+        let mut data = vec![-90.0; 51];
+        data[10] = 0.0;
+        let buf = render(&data, 51, 6);
+
+        // Only the tone's column reaches the top row
+        let top = row(&buf, 0);
+        assert_eq!(top.find('▁'), Some(10));
+        assert_eq!(top.matches('▁').count(), 1);
+    }
+
+    #[test]
+    fn test_khz_axis() {
+        let buf = render(&[-90.0; 64], 51, 4);
+        let axis = row(&buf, 3);
+        assert!(axis.starts_with('0'));
+        assert_eq!(axis.chars().skip(10).take(2).collect::<String>(), "1k");
+        assert!(axis.trim_end().ends_with("5k"));
+
+        // Narrow panes only label the ends
+        let axis = row(&render(&[-90.0; 64], 12, 4), 3);
+        assert!(axis.starts_with('0') && axis.ends_with("5k"));
+        assert!(!axis.contains("1k"));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::types::{Aircraft, AircraftSort};
+use crate::util::geo::{Location, Station};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    widgets::{Block, Widget},
+};
+use std::time::Instant;
+
+/// Column headings, padded to the row layout
+const HEADER: &str = "HEX    CALLSIGN    ALT   GS HDG      LAT       LON  MSGS SEEN";
+
+/// dump1090-style live table, one row per aircraft
+pub struct AircraftTableWidget<'a> {
+    aircraft: Vec<&'a Aircraft>,
+    now: Instant,
+    sort: AircraftSort,
+    /// Rows skipped at the top
+    scroll: usize,
+    /// Where the distance and bearing columns are measured from, if shown
+    station: Option<Station>,
+    block: Option<Block<'a>>,
+}
+
+impl<'a> AircraftTableWidget<'a> {
+    /// Create a table of `aircraft` as of `now`
+    pub fn new(aircraft: Vec<&'a Aircraft>, now: Instant) -> Self {
+        Self {
+            aircraft,
+            now,
+            sort: AircraftSort::default(),
+            scroll: 0,
+            station: None,
+            block: None,
+        }
+    }
+
+    /// Set the block for the widget
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Set the sort column
+    pub fn sort(mut self, sort: AircraftSort) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Set the number of rows scrolled past
+    pub fn scroll(mut self, scroll: usize) -> Self {
+        self.scroll = scroll;
+        self
+    }
+
+    /// Add distance and bearing columns from `station`, if there is one
+    pub fn station(mut self, station: Option<Station>) -> Self {
+        self.station = station;
+        self
+    }
+}
+
+/// Column headings, with the distance and bearing if measured from `station`
+fn header(station: Option<Station>) -> String {
+    match station {
+        Some(station) => format!("{} {:>7} BRG", HEADER, station.unit.label()),
+        None => HEADER.to_string(),
+    }
+}
+
+/// One table row, with the distance and bearing from `station` if given
+fn format_row(aircraft: &Aircraft, now: Instant, station: Option<Station>) -> String {
+    let opt = |v: Option<String>| v.unwrap_or_default();
+    let location = aircraft.lat.zip(aircraft.lon).and_then(|(lat, lon)| Location::new(lat, lon));
+    let range = station.zip(location).map(|(station, location)| {
+        let range = station.range(location);
+        format!(" {:>7.1} {:>3}", range.distance, range.bearing.round() as u32 % 360)
+    });
+    format!(
+        "{:<6} {:<8} {:>6} {:>4} {:>3} {:>8} {:>9} {:>5} {:>4}{}",
+        aircraft.hex(),
+        aircraft.callsign.as_deref().unwrap_or(""),
+        opt(aircraft.altitude.map(|v| v.to_string())),
+        opt(aircraft.ground_speed.map(|v| format!("{:.0}", v))),
+        opt(aircraft.heading.map(|v| format!("{:.0}", v))),
+        opt(aircraft.lat.map(|v| format!("{:.4}", v))),
+        opt(aircraft.lon.map(|v| format!("{:.4}", v))),
+        aircraft.messages,
+        now.saturating_duration_since(aircraft.last_seen).as_secs(),
+        opt(range),
+    )
+}
+
+impl Widget for AircraftTableWidget<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(b) => {
+                let inner_area = b.inner(area);
+                b.render(area, buf);
+                inner_area
+            }
+            None => area,
+        };
+
+        if area.width < 2 || area.height < 2 {
+            return;
+        }
+
+        buf.set_stringn(
+            area.left(),
+            area.top(),
+            header(self.station),
+            area.width as usize,
+            Style::default().fg(Color::Gray).add_modifier(Modifier::BOLD),
+        );
+
+        let now = self.now;
+        let mut rows: Vec<&Aircraft> =
+            self.aircraft.into_iter().filter(|a| !a.is_expired(now)).collect();
+        self.sort.sort(&mut rows);
+
+        let visible = (area.height - 1) as usize;
+        let scroll = self.scroll.min(rows.len().saturating_sub(visible));
+        for (i, aircraft) in rows.iter().skip(scroll).take(visible).enumerate() {
+            let style = if aircraft.is_stale(now) {
+                Style::default().fg(Color::DarkGray).add_modifier(Modifier::DIM)
+            } else {
+                Style::default()
+            };
+            buf.set_stringn(
+                area.left(),
+                area.top() + 1 + i as u16,
+                format_row(aircraft, now, self.station),
+                area.width as usize,
+                style,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::widgets::render_lines as render;
+    use std::time::Duration;
+
+    /// Four aircraft heard 2 s, 30 s, 90 s (stale) and 400 s (expired) ago
+    fn fixture(now: Instant) -> Vec<Aircraft> {
+        let ago = |secs| now - Duration::from_secs(secs);
+        vec![
+            Aircraft {
+                callsign: Some("DLH4AB".to_string()),
+                altitude: Some(36000),
+                ground_speed: Some(452.0),
+                heading: Some(271.0),
+                lat: Some(50.1234),
+                lon: Some(8.5678),
+                messages: 312,
+                ..Aircraft::new(0x3C6444, ago(2))
+            },
+            Aircraft {
+                altitude: Some(4500),
+                messages: 12,
+                ..Aircraft::new(0x4CA123, ago(30))
+            },
+            Aircraft {
+                callsign: Some("BAW12".to_string()),
+                messages: 40,
+                ..Aircraft::new(0x400F01, ago(90))
+            },
+            Aircraft::new(0xA00001, ago(400)),
+        ]
+    }
+
+    #[test]
+    fn test_rows_sorted_by_last_seen() {
+        let now = Instant::now() + Duration::from_secs(1000);
+        let aircraft = fixture(now);
+        let (lines, _) = render(AircraftTableWidget::new(aircraft.iter().collect(), now), 70, 6);
+
+        assert_eq!(lines[0], HEADER);
+        assert_eq!(
+            lines[1],
+            "3C6444 DLH4AB    36000  452 271  50.1234    8.5678   312    2"
+        );
+        assert!(lines[2].starts_with("4CA123"));
+        assert!(lines[3].starts_with("400F01 BAW12"));
+        // Expired aircraft are dropped
+        assert!(lines[4].is_empty());
+    }
+
+    #[test]
+    fn test_stale_rows_dimmed() {
+        let now = Instant::now() + Duration::from_secs(1000);
+        let aircraft = fixture(now);
+        let (lines, buf) = render(AircraftTableWidget::new(aircraft.iter().collect(), now), 70, 6);
+
+        assert!(lines[3].starts_with("400F01"));
+        assert!(buf[(0, 3)].modifier.contains(Modifier::DIM));
+        assert!(!buf[(0, 1)].modifier.contains(Modifier::DIM));
+    }
+
+    #[test]
+    fn test_distance_and_bearing_columns() {
+        let now = Instant::now() + Duration::from_secs(1000);
+        let aircraft = fixture(now);
+        let station = Station {
+            location: Location { lat: 50.0, lon: 8.5 },
+            unit: crate::util::geo::DistanceUnit::Km,
+        };
+        let table = AircraftTableWidget::new(aircraft.iter().collect(), now).station(Some(station));
+        let (lines, _) = render(table, 90, 6);
+
+        assert_eq!(lines[0], format!("{}      km BRG", HEADER));
+        assert!(lines[1].ends_with("   312    2    14.5  19"), "{}", lines[1]);
+        // No position, no range
+        assert!(lines[2].ends_with("    12   30"));
+    }
+
+    #[test]
+    fn test_sort_and_scroll() {
+        let now = Instant::now() + Duration::from_secs(1000);
+        let aircraft = fixture(now);
+        let table = || AircraftTableWidget::new(aircraft.iter().collect(), now);
+
+        let (lines, _) = render(table().sort(AircraftSort::Messages), 70, 5);
+        assert!(lines[1].starts_with("3C6444"));
+        assert!(lines[2].starts_with("400F01"));
+        assert!(lines[3].starts_with("4CA123"));
+
+        // Two rows fit; scrolling stops once the last row is visible
+        let (lines, _) = render(table().sort(AircraftSort::Hex).scroll(5), 70, 3);
+        assert!(lines[1].starts_with("400F01"));
+        assert!(lines[2].starts_with("4CA123"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Braille dot plotting
+//!
+//! Each terminal cell holds a 2×4 grid of Braille dots, giving plots four
+//! times the cell resolution vertically and twice horizontally.
+
+use ratatui::{buffer::Buffer, layout::Rect, style::Style};
+
+/// Bit for each dot in a Braille cell, indexed `[row][column]`
+const DOT_BITS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// A plot of dots, `width`×`height` cells with (0, 0) at the top left
+#[derive(Debug, Clone)]
+pub struct BrailleGrid {
+    width: usize,
+    height: usize,
+    cells: Vec<u8>,
+}
+
+impl BrailleGrid {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![0; width * height],
+        }
+    }
+
+    /// Horizontal resolution in dots
+    pub fn dot_width(&self) -> usize {
+        self.width * 2
+    }
+
+    /// Vertical resolution in dots
+    pub fn dot_height(&self) -> usize {
+        self.height * 4
+    }
+
+    /// Set the dot at (`x`, `y`); dots off the grid are ignored
+    pub fn set(&mut self, x: usize, y: usize) {
+        if x < self.dot_width() && y < self.dot_height() {
+            self.cells[(y / 4) * self.width + x / 2] |= DOT_BITS[y % 4][x % 2];
+        }
+    }
+
+    /// Dot bits of the cell at (`x`, `y`)
+    pub fn cell(&self, x: usize, y: usize) -> u8 {
+        self.cells[y * self.width + x]
+    }
+}
+
+/// The Braille character for a cell's dot bits
+pub fn braille_char(bits: u8) -> char {
+    char::from_u32(0x2800 + bits as u32).unwrap_or(' ')
+}
+
+/// Map `value` in `-range..=range` onto dots `0..dots`; None when outside
+pub fn value_to_dot(value: f32, range: f32, dots: usize) -> Option<usize> {
+    if dots == 0 || !value.is_finite() || value.abs() > range {
+        return None;
+    }
+    let position = (value / range + 1.0) / 2.0 * (dots - 1) as f32;
+    Some(position.round() as usize)
+}
+
+/// Draw stacked grids into `area`; dots combine and later layers set the
+/// style of cells they touch. Empty cells are left as they are.
+pub fn render_layers(buf: &mut Buffer, area: Rect, layers: &[(&BrailleGrid, Style)]) {
+    for y in 0..area.height {
+        for x in 0..area.width {
+            let mut bits = 0;
+            let mut style = None;
+            for (grid, layer_style) in layers {
+                let (cx, cy) = (x as usize, y as usize);
+                if cx < grid.width && cy < grid.height && grid.cell(cx, cy) != 0 {
+                    bits |= grid.cell(cx, cy);
+                    style = Some(*layer_style);
+                }
+            }
+            if let Some(style) = style {
+                buf[(area.left() + x, area.top() + y)]
+                    .set_char(braille_char(bits))
+                    .set_style(style);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Color;
+
+    #[test]
+    fn test_dot_bits() {
+        let mut grid = BrailleGrid::new(2, 1);
+        grid.set(0, 0);
+        grid.set(1, 3);
+        grid.set(2, 1);
+        // Off the grid
+        grid.set(4, 0);
+        grid.set(0, 4);
+
+        assert_eq!(braille_char(grid.cell(0, 0)), '⢁');
+        assert_eq!(braille_char(grid.cell(1, 0)), '⠂');
+        assert_eq!(braille_char(0xFF), '⣿');
+    }
+
+    #[test]
+    fn test_value_to_dot() {
+        assert_eq!(value_to_dot(-1.0, 1.0, 9), Some(0));
+        assert_eq!(value_to_dot(0.0, 1.0, 9), Some(4));
+        assert_eq!(value_to_dot(1.0, 1.0, 9), Some(8));
+        assert_eq!(value_to_dot(0.5, 2.0, 9), Some(5));
+        assert_eq!(value_to_dot(1.5, 1.0, 9), None);
+        assert_eq!(value_to_dot(f32::NAN, 1.0, 9), None);
+    }
+
+    #[test]
+    fn test_render_layers() {
+        let mut back = BrailleGrid::new(2, 1);
+        back.set(0, 0);
+        back.set(2, 0);
+        let mut front = BrailleGrid::new(2, 1);
+        front.set(1, 0);
+
+        let area = Rect::new(0, 0, 3, 1);
+        let mut buf = Buffer::empty(area);
+        let dim = Style::default().fg(Color::DarkGray);
+        let bright = Style::default().fg(Color::Green);
+        render_layers(&mut buf, area, &[(&back, dim), (&front, bright)]);
+
+        assert_eq!(buf[(0, 0)].symbol(), "⠉");
+        assert_eq!(buf[(0, 0)].fg, Color::Green);
+        assert_eq!(buf[(1, 0)].symbol(), "⠁");
+        assert_eq!(buf[(1, 0)].fg, Color::DarkGray);
+        assert_eq!(buf[(2, 0)].symbol(), " ");
+    }
+}
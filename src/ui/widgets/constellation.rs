@@ -0,0 +1,137 @@
+use super::braille::{render_layers, value_to_dot, BrailleGrid};
+use crate::sdr::clipped_fraction;
+use num_complex::Complex;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    widgets::{Block, Widget},
+};
+
+/// Half-width of the plotted I/Q plane; leaves room around the unit circle
+const PLOT_RANGE: f32 = 1.2;
+/// Share of clipped samples that triggers the warning
+const CLIP_WARN_FRACTION: f32 = 0.01;
+
+/// Scatter plot of raw IQ samples on the I/Q plane
+pub struct ConstellationWidget<'a> {
+    samples: &'a [Complex<f32>],
+    block: Option<Block<'a>>,
+}
+
+impl<'a> ConstellationWidget<'a> {
+    pub fn new(samples: &'a [Complex<f32>]) -> Self {
+        Self { samples, block: None }
+    }
+
+    /// Set the block for the widget
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+}
+
+/// Dot for a sample on a `dots`×`dots` plot, Q increasing upwards
+fn sample_to_dot(sample: Complex<f32>, dots: usize) -> Option<(usize, usize)> {
+    let x = value_to_dot(sample.re, PLOT_RANGE, dots)?;
+    let y = value_to_dot(-sample.im, PLOT_RANGE, dots)?;
+    Some((x, y))
+}
+
+impl Widget for ConstellationWidget<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(b) => {
+                let inner_area = b.inner(area);
+                b.render(area, buf);
+                inner_area
+            }
+            None => area,
+        };
+
+        if area.width < 4 || area.height < 2 {
+            return;
+        }
+
+        // Braille dots are roughly square, so a square of dots keeps the
+        // circle round; center it in the area
+        let dots = (area.width as usize * 2).min(area.height as usize * 4);
+        let plot = Rect::new(
+            area.left() + (area.width - dots.div_ceil(2) as u16) / 2,
+            area.top() + (area.height - dots.div_ceil(4) as u16) / 2,
+            dots.div_ceil(2) as u16,
+            dots.div_ceil(4) as u16,
+        );
+
+        let mut axes = BrailleGrid::new(plot.width as usize, plot.height as usize);
+        let steps = dots * 4;
+        for i in 0..steps {
+            let angle = i as f32 / steps as f32 * std::f32::consts::TAU;
+            if let Some((x, y)) = sample_to_dot(Complex::from_polar(1.0, angle), dots) {
+                axes.set(x, y);
+            }
+        }
+        let mut points = BrailleGrid::new(plot.width as usize, plot.height as usize);
+        for &sample in self.samples {
+            if let Some((x, y)) = sample_to_dot(sample, dots) {
+                points.set(x, y);
+            }
+        }
+        render_layers(
+            buf,
+            plot,
+            &[
+                (&axes, Style::default().fg(Color::DarkGray)),
+                (&points, Style::default().fg(Color::Green)),
+            ],
+        );
+
+        if clipped_fraction(self.samples) > CLIP_WARN_FRACTION {
+            buf.set_stringn(
+                area.left(),
+                area.top(),
+                "CLIPPING",
+                area.width as usize,
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_to_dot() {
+        // 25 dots span -1.2..=1.2, so ±1.0 sits two dots in from the edge
+        assert_eq!(sample_to_dot(Complex::new(0.0, 0.0), 25), Some((12, 12)));
+        assert_eq!(sample_to_dot(Complex::new(1.0, 0.0), 25), Some((22, 12)));
+        // Positive Q is up
+        assert_eq!(sample_to_dot(Complex::new(0.0, 1.0), 25), Some((12, 2)));
+        assert_eq!(sample_to_dot(Complex::new(-1.2, -1.2), 25), Some((0, 24)));
+        assert_eq!(sample_to_dot(Complex::new(2.0, 0.0), 25), None);
+    }
+
+    fn render(samples: &[Complex<f32>]) -> Buffer {
+        let area = Rect::new(0, 0, 20, 8);
+        let mut buf = Buffer::empty(area);
+        ConstellationWidget::new(samples).render(area, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_render_circle_and_warning() {
+        // The origin lands in the middle cell, drawn over the dim circle
+        let buf = render(&[Complex::new(0.0, 0.0)]);
+        let center = &buf[(10, 4)];
+        assert_ne!(center.symbol(), " ");
+        assert_eq!(center.fg, Color::Green);
+        assert!((0..20).any(|x| buf[(x, 0)].fg == Color::DarkGray));
+
+        let clipped = vec![Complex::new(1.0, 1.0); 10];
+        let buf = render(&clipped);
+        let top: String = (0..8).map(|x| buf[(x, 0)].symbol()).collect();
+        assert_eq!(top, "CLIPPING");
+    }
+}
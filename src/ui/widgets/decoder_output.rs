@@ -0,0 +1,152 @@
+use crate::state::app_state::ClockZone;
+use crate::types::DecodedMessage;
+use crate::util::geo::Station;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Widget},
+};
+
+/// Shown until the first message is decoded
+const EMPTY: &str = "Decoded messages (APRS, ADS-B, etc.) will appear here";
+
+/// Decoded messages, newest at the bottom, keeping as many as fit
+pub struct DecoderOutputWidget<'a> {
+    messages: &'a [DecodedMessage],
+    clock: ClockZone,
+    /// Where the range to messages with a position is measured from
+    station: Option<Station>,
+    /// Whether each message's raw form follows its summary
+    expanded: bool,
+    /// Times and raw messages, and ranges
+    dim: Color,
+    label: Color,
+    block: Option<Block<'a>>,
+}
+
+impl<'a> DecoderOutputWidget<'a> {
+    /// Create a list of `messages`, oldest first
+    pub fn new(messages: &'a [DecodedMessage]) -> Self {
+        Self {
+            messages,
+            clock: ClockZone::default(),
+            station: None,
+            expanded: false,
+            dim: Color::DarkGray,
+            label: Color::Gray,
+            block: None,
+        }
+    }
+
+    /// Set the block for the widget
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Show message times in `clock`
+    pub fn clock(mut self, clock: ClockZone) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Show the range to messages with a position from `station`
+    pub fn station(mut self, station: Option<Station>) -> Self {
+        self.station = station;
+        self
+    }
+
+    /// Follow each summary with the message as received
+    pub fn expanded(mut self, expanded: bool) -> Self {
+        self.expanded = expanded;
+        self
+    }
+
+    /// Set the colors for times and raw messages, and for ranges
+    pub fn colors(mut self, dim: Color, label: Color) -> Self {
+        self.dim = dim;
+        self.label = label;
+        self
+    }
+}
+
+impl Widget for DecoderOutputWidget<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(b) => {
+                let inner_area = b.inner(area);
+                b.render(area, buf);
+                inner_area
+            }
+            None => area,
+        };
+
+        if self.messages.is_empty() {
+            Paragraph::new(EMPTY)
+                .style(Style::default().fg(self.dim))
+                .render(area, buf);
+            return;
+        }
+
+        let visible = area.height as usize;
+        let mut lines: Vec<Line> = Vec::new();
+        for message in self
+            .messages
+            .iter()
+            .skip(self.messages.len().saturating_sub(visible))
+        {
+            let mut line = Line::from(vec![
+                Span::styled(
+                    format!("{} ", self.clock.timestamp(message.timestamp)),
+                    Style::default().fg(self.dim),
+                ),
+                Span::raw(message.content.clone()),
+            ]);
+            if let (Some(station), Some(location)) = (self.station, message.location()) {
+                line.push_span(Span::styled(
+                    format!("  {}", station.range(location)),
+                    Style::default().fg(self.label),
+                ));
+            }
+            lines.push(line);
+            if let Some(raw) = message.raw.as_ref().filter(|_| self.expanded) {
+                lines.push(Line::styled(
+                    format!("  {}", raw),
+                    Style::default().fg(self.dim),
+                ));
+            }
+        }
+        let lines = lines.split_off(lines.len().saturating_sub(visible));
+        Paragraph::new(lines).render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DemodMode;
+
+    #[test]
+    fn test_newest_at_bottom() {
+        let messages: Vec<DecodedMessage> = ["one", "two", "three"]
+            .iter()
+            .map(|content| {
+                DecodedMessage::new(DemodMode::Aprs, content.to_string())
+                    .with_raw(format!("raw {}", content))
+            })
+            .collect();
+        let area = Rect::new(0, 0, 40, 3);
+        let mut buf = Buffer::empty(area);
+        DecoderOutputWidget::new(&messages)
+            .expanded(true)
+            .render(area, &mut buf);
+        let row = |y| (0..40).map(|x| buf[(x, y)].symbol()).collect::<String>();
+
+        // The last message and its raw form fill the bottom rows
+        assert!(row(0).contains("raw two"), "{}", row(0));
+        assert!(row(1).trim_end().ends_with("three"));
+        assert!(row(2).contains("raw three"));
+    }
+}
@@ -3,7 +3,42 @@ pub mod spectrum;
 pub mod waterfall;
 pub mod controls;
 pub mod decoder_output;
+pub mod aircraft_table;
+pub mod activity_table;
+pub mod af_spectrum;
+pub mod braille;
+pub mod constellation;
+pub mod scope;
 
 // Re-export widgets
-pub use spectrum::SpectrumWidget;
+pub use spectrum::{SpectrumMode, SpectrumWidget};
 pub use waterfall::WaterfallWidget;
+pub use aircraft_table::AircraftTableWidget;
+pub use decoder_output::DecoderOutputWidget;
+pub use activity_table::ActivityTableWidget;
+pub use af_spectrum::AfSpectrumWidget;
+pub use constellation::ConstellationWidget;
+pub use scope::ScopeWidget;
+
+/// Render `widget` into a `width` by `height` buffer; its rows as text,
+/// trailing blanks trimmed, and the buffer for the styles
+#[cfg(test)]
+fn render_lines(
+    widget: impl ratatui::widgets::Widget,
+    width: u16,
+    height: u16,
+) -> (Vec<String>, ratatui::buffer::Buffer) {
+    let area = ratatui::layout::Rect::new(0, 0, width, height);
+    let mut buf = ratatui::buffer::Buffer::empty(area);
+    widget.render(area, &mut buf);
+    let lines = (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| buf[(x, y)].symbol())
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+    (lines, buf)
+}
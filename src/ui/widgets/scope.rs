@@ -0,0 +1,216 @@
+use super::braille::{render_layers, value_to_dot, BrailleGrid};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::{Block, Widget},
+};
+
+/// Share of the vertical range the signal must dip below zero before a
+/// rising crossing counts, so noise doesn't retrigger
+const TRIGGER_HYSTERESIS: f32 = 0.05;
+
+/// Oscilloscope trace of recent audio
+pub struct ScopeWidget<'a> {
+    /// Audio, oldest first
+    samples: &'a [f32],
+    sample_rate: u32,
+    /// Amplitude at the top and bottom edges
+    range: f32,
+    /// Start the trace at a rising zero crossing
+    trigger: bool,
+    block: Option<Block<'a>>,
+}
+
+impl<'a> ScopeWidget<'a> {
+    pub fn new(samples: &'a [f32], sample_rate: u32) -> Self {
+        Self {
+            samples,
+            sample_rate,
+            range: 1.0,
+            trigger: true,
+            block: None,
+        }
+    }
+
+    /// Set the block for the widget
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Set the amplitude shown at the top and bottom edges
+    pub fn range(mut self, range: f32) -> Self {
+        self.range = range;
+        self
+    }
+
+    /// Enable or disable the rising-edge trigger
+    pub fn trigger(mut self, trigger: bool) -> Self {
+        self.trigger = trigger;
+        self
+    }
+}
+
+/// First rising zero crossing that leaves `span` samples after it
+pub fn find_trigger(samples: &[f32], span: usize, hysteresis: f32) -> Option<usize> {
+    let last = samples.len().checked_sub(span)?;
+    let mut armed = false;
+    for (i, &sample) in samples.iter().enumerate().take(last + 1) {
+        if sample < -hysteresis {
+            armed = true;
+        } else if armed && sample >= 0.0 {
+            return Some(i);
+        }
+    }
+    None
+}
+
+impl Widget for ScopeWidget<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(b) => {
+                let inner_area = b.inner(area);
+                b.render(area, buf);
+                inner_area
+            }
+            None => area,
+        };
+
+        if area.width < 4 || area.height < 2 || self.samples.len() < 2 {
+            return;
+        }
+
+        let mut axis = BrailleGrid::new(area.width as usize, area.height as usize);
+        let mut trace = BrailleGrid::new(area.width as usize, area.height as usize);
+        let (dots_x, dots_y) = (trace.dot_width(), trace.dot_height());
+
+        for x in (0..dots_x).step_by(2) {
+            axis.set(x, dots_y / 2);
+        }
+
+        // Half the history is shown so the trigger has room to move
+        let span = self.samples.len() / 2;
+        let triggered = if self.trigger {
+            find_trigger(self.samples, span, self.range * TRIGGER_HYSTERESIS)
+        } else {
+            None
+        };
+        let start = triggered.unwrap_or(self.samples.len() - span);
+
+        let mut prev_y = None;
+        for x in 0..dots_x {
+            let sample = self.samples[start + x * span / dots_x];
+            // Out-of-range audio is pinned to the edge so clipping shows flat
+            let value = (-sample).clamp(-self.range, self.range);
+            let Some(y) = value_to_dot(value, self.range, dots_y) else {
+                continue;
+            };
+            // Join to the previous column so steep edges stay continuous
+            let (lo, hi) = match prev_y {
+                Some(p) => (y.min(p), y.max(p)),
+                None => (y, y),
+            };
+            for dot_y in lo..=hi {
+                trace.set(x, dot_y);
+            }
+            prev_y = Some(y);
+        }
+
+        render_layers(
+            buf,
+            area,
+            &[
+                (&axis, Style::default().fg(Color::DarkGray)),
+                (&trace, Style::default().fg(Color::Green)),
+            ],
+        );
+
+        let trigger_label = match (self.trigger, triggered) {
+            (false, _) => "free",
+            (true, Some(_)) => "trig",
+            (true, None) => "trig?",
+        };
+        let label = format!("±{} {}", self.range, trigger_label);
+        buf.set_stringn(
+            area.left(),
+            area.top(),
+            label,
+            area.width as usize,
+            Style::default().fg(Color::Gray),
+        );
+        if self.sample_rate > 0 {
+            let span_ms = format!("{:.0} ms", span as f32 * 1000.0 / self.sample_rate as f32);
+            let x = area.right().saturating_sub(span_ms.len() as u16).max(area.left());
+            buf.set_string(x, area.bottom() - 1, span_ms, Style::default().fg(Color::Gray));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(len: usize, phase: f32) -> Vec<f32> {
+        // 200 Hz at 48 kHz
+        (0..len)
+            .map(|i| (std::f32::consts::TAU * i as f32 / 240.0 + phase).sin() * 0.8)
+            .collect()
+    }
+
+    fn render(samples: &[f32], trigger: bool) -> Buffer {
+        let area = Rect::new(0, 0, 40, 8);
+        let mut buf = Buffer::empty(area);
+        ScopeWidget::new(samples, 48_000).trigger(trigger).render(area, &mut buf);
+        buf
+    }
+
+    /// Cells the trace is drawn in, as (x, y)
+    fn trace_cells(buf: &Buffer) -> Vec<(u16, u16)> {
+        let area = buf.area;
+        (0..area.height)
+            .flat_map(|y| (0..area.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| buf[(x, y)].fg == Color::Green)
+            .collect()
+    }
+
+    #[test]
+    fn test_find_trigger() {
+        let samples = [0.5, 0.1, -0.2, -0.01, 0.3, 0.6, -0.3, 0.2];
+        assert_eq!(find_trigger(&samples, 2, 0.1), Some(4));
+        // No room for the span after the crossing
+        assert_eq!(find_trigger(&samples, 5, 0.1), None);
+        // The dip never clears the hysteresis
+        assert_eq!(find_trigger(&[0.2, -0.05, 0.2, 0.1], 1, 0.1), None);
+    }
+
+    #[test]
+    fn test_sine_trace_is_stable_when_triggered() {
+        let buf = render(&sine(2400, 0.0), true);
+        let cells = trace_cells(&buf);
+        // Five cycles span the full height
+        assert!(cells.iter().any(|&(_, y)| y == 0));
+        assert!(cells.iter().any(|&(_, y)| y == 7));
+        // Starting on a rising crossing, the trace leaves mid-height (the
+        // bottom of row 3) upwards
+        let first: Vec<u16> = cells.iter().filter(|&&(x, _)| x == 0).map(|&(_, y)| y).collect();
+        assert_eq!(first.last(), Some(&3), "first column at {:?}", first);
+        assert!(first.iter().all(|&y| y >= 2), "first column at {:?}", first);
+
+        // Any phase of the same tone draws the same picture
+        let shifted = render(&sine(2400, 1.3), true);
+        assert_eq!(trace_cells(&shifted), cells);
+
+        // Free-running, the phase shows through
+        assert_ne!(trace_cells(&render(&sine(2400, 1.3), false)), cells);
+    }
+
+    #[test]
+    fn test_labels() {
+        let buf = render(&sine(2400, 0.0), true);
+        let top: String = (0..8).map(|x| buf[(x, 0)].symbol()).collect();
+        assert_eq!(top, "±1 trig ");
+        let bottom: String = (35..40).map(|x| buf[(x, 7)].symbol()).collect();
+        assert_eq!(bottom, "25 ms");
+    }
+}
@@ -1,3 +1,6 @@
+use super::braille::{braille_char, BrailleGrid};
+use crate::ui::theme::Theme;
+use std::borrow::Cow;
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
@@ -5,6 +8,45 @@ use ratatui::{
     widgets::{Block, Widget},
 };
 
+/// How the spectrum trace is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpectrumMode {
+    /// One solid bar per column
+    #[default]
+    Bars,
+    /// A line through Braille dots, 2×4 per cell
+    Braille,
+}
+
+impl SpectrumMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpectrumMode::Bars => "bars",
+            SpectrumMode::Braille => "braille",
+        }
+    }
+
+    /// The other mode
+    pub fn next(&self) -> Self {
+        match self {
+            SpectrumMode::Bars => SpectrumMode::Braille,
+            SpectrumMode::Braille => SpectrumMode::Bars,
+        }
+    }
+}
+
+impl std::str::FromStr for SpectrumMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bars" => Ok(SpectrumMode::Bars),
+            "braille" => Ok(SpectrumMode::Braille),
+            _ => Err(format!("unknown spectrum mode '{}' (expected bars or braille)", s)),
+        }
+    }
+}
+
 /// Spectrum analyzer widget that displays FFT data as a line chart
 pub struct SpectrumWidget<'a> {
     /// FFT magnitude data in dB
@@ -19,6 +61,18 @@ pub struct SpectrumWidget<'a> {
     min_db: f32,
     /// Maximum dB value for display
     max_db: f32,
+    /// Bar colors, weakest to strongest
+    palette: [Color; 5],
+    mode: SpectrumMode,
+    /// Labels drawn along the top at offsets from the center in Hz
+    markers: Vec<(char, i32, Color)>,
+    /// Spans shaded behind the trace, as offsets from the center in Hz,
+    /// with labels
+    passbands: Vec<(i32, i32, String, Color)>,
+    /// Draw the bins in reverse, for spectrally inverted input
+    inverted: bool,
+    /// Channel grid spacing in Hz and its color
+    grid: Option<(u32, Color)>,
 }
 
 impl<'a> SpectrumWidget<'a> {
@@ -31,6 +85,12 @@ impl<'a> SpectrumWidget<'a> {
             block: None,
             min_db: -100.0,
             max_db: 0.0,
+            palette: Theme::default().spectrum,
+            mode: SpectrumMode::default(),
+            markers: Vec::new(),
+            passbands: Vec::new(),
+            inverted: false,
+            grid: None,
         }
     }
 
@@ -46,6 +106,45 @@ impl<'a> SpectrumWidget<'a> {
         self.max_db = max;
         self
     }
+
+    /// Set the bar colors, weakest to strongest
+    pub fn palette(mut self, palette: [Color; 5]) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// Set how the trace is drawn
+    pub fn mode(mut self, mode: SpectrumMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Mark `offset` Hz from the center with `label`
+    pub fn marker(mut self, label: char, offset: i32, color: Color) -> Self {
+        self.markers.push((label, offset, color));
+        self
+    }
+
+    /// Shade `low` to `high` Hz from the center with a `color` background,
+    /// labelled `label` under the top row
+    pub fn passband(mut self, low: i32, high: i32, label: String, color: Color) -> Self {
+        self.passbands.push((low, high, label, color));
+        self
+    }
+
+    /// Reverse the bins, and the markers and passbands with them, so a
+    /// spectrally inverted signal reads the right way round against the
+    /// frequency labels
+    pub fn inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    /// Draw a line every `spacing` Hz of absolute frequency, in `color`
+    pub fn grid(mut self, spacing: u32, color: Color) -> Self {
+        self.grid = Some((spacing, color));
+        self
+    }
 }
 
 impl Widget for SpectrumWidget<'_> {
@@ -68,44 +167,39 @@ impl Widget for SpectrumWidget<'_> {
             return;
         }
 
-        let width = area.width as usize;
-        let height = area.height as usize;
-
-        // Downsample or interpolate data to fit width
-        let displayed_data = resample_data(self.data, width);
-
-        // Convert dB values to pixel heights
-        let pixel_heights: Vec<usize> = displayed_data
-            .iter()
-            .map(|&db| {
-                let normalized = ((db - self.min_db) / (self.max_db - self.min_db))
-                    .max(0.0)
-                    .min(1.0);
-                ((height - 1) as f32 * normalized) as usize
-            })
-            .collect();
-
-        // Draw the spectrum using vertical bars
-        for (x, &pixel_height) in pixel_heights.iter().enumerate() {
-            if x >= width {
-                break;
+        let view = full_view(self.sample_rate);
+        let mut labels = Vec::new();
+        for (low, high, label, color) in &self.passbands {
+            let (low, high) = display_span(*low, *high, self.inverted);
+            if let Some((first, last)) = span_columns(low, high, view, area.width) {
+                for x in first..=last {
+                    for y in area.top()..area.bottom() {
+                        buf[(area.left() + x, y)].set_bg(*color);
+                    }
+                }
+                labels.push((first, label));
             }
+        }
 
-            // Determine color based on signal strength
-            let color = get_signal_color(pixel_height, height);
-
-            // Draw vertical line from bottom to pixel_height
-            for y_offset in 0..=pixel_height.min(height - 1) {
-                let y = area.bottom() - 1 - y_offset as u16;
-                if y >= area.top() && y < area.bottom() {
-                    let x_pos = area.left() + x as u16;
-                    buf.get_mut(x_pos, y)
-                        .set_char('▁')
-                        .set_fg(color);
+        // Under the trace, clear of the labels
+        if let Some((spacing, color)) = self.grid {
+            for x in grid_columns(self.center_freq, self.sample_rate, spacing, area.width) {
+                for y in area.top() + 1..area.bottom() - 1 {
+                    buf[(area.left() + x, y)].set_char('┊').set_fg(color);
                 }
             }
         }
 
+        let data = display_bins(self.data, self.inverted);
+        match self.mode {
+            SpectrumMode::Bars => {
+                draw_bars(buf, area, &data, self.min_db, self.max_db, &self.palette)
+            }
+            SpectrumMode::Braille => {
+                draw_braille(buf, area, &data, self.min_db, self.max_db, &self.palette)
+            }
+        }
+
         // Draw frequency labels (if space allows)
         if area.height > 3 {
             draw_frequency_labels(
@@ -115,6 +209,199 @@ impl Widget for SpectrumWidget<'_> {
                 self.sample_rate,
             );
         }
+
+        // Labels go over the trace, beside the markers
+        if area.height > 3 {
+            for (first, label) in labels {
+                let x = (area.left() + first).min(area.right().saturating_sub(label.len() as u16));
+                buf.set_stringn(
+                    x.max(area.left()),
+                    area.top() + 1,
+                    label,
+                    area.width as usize,
+                    Style::default().fg(Color::Gray),
+                );
+            }
+        }
+
+        for &(label, offset, color) in &self.markers {
+            let offset = display_span(offset, offset, self.inverted).0;
+            if let Some(x) = offset_column(offset, self.sample_rate, area.width) {
+                buf[(area.left() + x, area.top())].set_char(label).set_fg(color);
+            }
+        }
+    }
+}
+
+/// Column of `offset` Hz from the center across `width` columns spanning
+/// `sample_rate`, None outside it
+fn offset_column(offset: i32, sample_rate: u32, width: u16) -> Option<u16> {
+    let position = offset as f64 / sample_rate.max(1) as f64 + 0.5;
+    (0.0..1.0).contains(&position).then_some((position * width as f64) as u16)
+}
+
+/// Offsets from the center in Hz at the left and right edges of a view of
+/// the whole of `sample_rate`
+pub fn full_view(sample_rate: u32) -> (i32, i32) {
+    let half = (sample_rate / 2) as i32;
+    (-half, half)
+}
+
+/// First and last of `width` columns spanning `view` (offsets from the
+/// center in Hz) that `low..high` Hz covers, at least one column however
+/// narrow; None when it is out of view
+pub fn span_columns(low: i32, high: i32, view: (i32, i32), width: u16) -> Option<(u16, u16)> {
+    let (view_low, view_high) = (view.0 as f64, view.1 as f64);
+    if width == 0 || view_high <= view_low || high as f64 <= view_low || low as f64 >= view_high {
+        return None;
+    }
+    let column = |hz: i32| (hz as f64 - view_low) / (view_high - view_low) * width as f64;
+    let last_column = (width - 1) as f64;
+    let first = column(low).floor().clamp(0.0, last_column);
+    let last = (column(high).ceil() - 1.0).clamp(first, last_column);
+    Some((first as u16, last as u16))
+}
+
+/// FFT bins (centered, lowest first) in the order they are drawn, left to
+/// right: mirrored about the center bin when `inverted`, as the signal's
+/// low side is then above the tuned frequency
+///
+/// The first bin, at half the sample rate either side, stays first.
+pub fn display_bins(data: &[f32], inverted: bool) -> Cow<'_, [f32]> {
+    if inverted && !data.is_empty() {
+        Cow::Owned(data[..1].iter().chain(data[1..].iter().rev()).copied().collect())
+    } else {
+        Cow::Borrowed(data)
+    }
+}
+
+/// Where `low..high` Hz from the center of the signal is drawn, as offsets
+/// from the center of the frequency axis; mirrored when `inverted`
+pub fn display_span(low: i32, high: i32, inverted: bool) -> (i32, i32) {
+    if inverted {
+        (-high, -low)
+    } else {
+        (low, high)
+    }
+}
+
+/// Columns of `width` spanning `sample_rate` around `center_freq` that fall
+/// on a multiple of `spacing` Hz, so the lines keep to the same channels
+/// when retuning; none if they would be less than two columns apart
+pub fn grid_columns(center_freq: u32, sample_rate: u32, spacing: u32, width: u16) -> Vec<u16> {
+    if spacing == 0 || sample_rate == 0 || width == 0 {
+        return Vec::new();
+    }
+    let (spacing, sample_rate) = (spacing as u64, sample_rate as u64);
+    if spacing * (width as u64) < 2 * sample_rate {
+        return Vec::new();
+    }
+    let start = (center_freq as u64).saturating_sub(sample_rate / 2);
+    let end = start + sample_rate;
+    let first = start.div_ceil(spacing) * spacing;
+    (first..end)
+        .step_by(spacing as usize)
+        .map(|freq| ((freq - start) * width as u64 / sample_rate) as u16)
+        .collect()
+}
+
+/// Draw dB values as vertical bars filling `area`, resampled to its width
+/// and colored from `palette` by height
+pub fn draw_bars(
+    buf: &mut Buffer,
+    area: Rect,
+    data: &[f32],
+    min_db: f32,
+    max_db: f32,
+    palette: &[Color; 5],
+) {
+    let width = area.width as usize;
+    let height = area.height as usize;
+
+    // Downsample or interpolate data to fit width
+    let displayed_data = resample_data(data, width);
+
+    // Convert dB values to pixel heights
+    let pixel_heights: Vec<usize> = displayed_data
+        .iter()
+        .map(|&db| {
+            let normalized = ((db - min_db) / (max_db - min_db))
+                .max(0.0)
+                .min(1.0);
+            ((height - 1) as f32 * normalized) as usize
+        })
+        .collect();
+
+    // Draw the spectrum using vertical bars
+    for (x, &pixel_height) in pixel_heights.iter().enumerate() {
+        if x >= width {
+            break;
+        }
+
+        // Determine color based on signal strength
+        let color = get_signal_color(pixel_height, height, palette);
+
+        // Draw vertical line from bottom to pixel_height
+        for y_offset in 0..=pixel_height.min(height - 1) {
+            let y = area.bottom() - 1 - y_offset as u16;
+            if y >= area.top() && y < area.bottom() {
+                let x_pos = area.left() + x as u16;
+                buf.get_mut(x_pos, y)
+                    .set_char('▁')
+                    .set_fg(color);
+            }
+        }
+    }
+}
+
+/// Draw dB values as a Braille line, two dots per column and four per row
+///
+/// A cell takes one color, so each is colored by the highest dot in it.
+fn draw_braille(
+    buf: &mut Buffer,
+    area: Rect,
+    data: &[f32],
+    min_db: f32,
+    max_db: f32,
+    palette: &[Color; 5],
+) {
+    let (width, height) = (area.width as usize, area.height as usize);
+    let mut grid = BrailleGrid::new(width, height);
+    let (dots_x, dots_y) = (grid.dot_width(), grid.dot_height());
+
+    // Level of each dot column in dots above the bottom
+    let levels: Vec<usize> = resample_data(data, dots_x)
+        .iter()
+        .map(|&db| {
+            let normalized = ((db - min_db) / (max_db - min_db)).clamp(0.0, 1.0);
+            ((dots_y - 1) as f32 * normalized).round() as usize
+        })
+        .collect();
+
+    // Highest level drawn in each cell
+    let mut peaks = vec![None; width * height];
+    let mut prev = None;
+    for (x, &level) in levels.iter().enumerate() {
+        // Join to the previous column so steep edges stay continuous
+        let (lo, hi) = match prev {
+            Some(p) => (level.min(p), level.max(p)),
+            None => (level, level),
+        };
+        for dot_level in lo..=hi {
+            let y = dots_y - 1 - dot_level;
+            grid.set(x, y);
+            let peak = &mut peaks[(y / 4) * width + x / 2];
+            *peak = Some(peak.map_or(dot_level, |p: usize| p.max(dot_level)));
+        }
+        prev = Some(level);
+    }
+
+    for (i, peak) in peaks.iter().enumerate() {
+        let Some(peak) = *peak else { continue };
+        let (x, y) = (i % width, i / width);
+        buf[(area.left() + x as u16, area.top() + y as u16)]
+            .set_char(braille_char(grid.cell(x, y)))
+            .set_fg(get_signal_color(peak, dots_y, palette));
     }
 }
 
@@ -150,20 +437,20 @@ fn resample_data(data: &[f32], target_width: usize) -> Vec<f32> {
     result
 }
 
-/// Get color based on signal strength
-fn get_signal_color(pixel_height: usize, max_height: usize) -> Color {
+/// Get color from `palette` based on signal strength
+fn get_signal_color(pixel_height: usize, max_height: usize, palette: &[Color; 5]) -> Color {
     let ratio = pixel_height as f32 / max_height as f32;
 
     if ratio > 0.8 {
-        Color::Red
+        palette[4]
     } else if ratio > 0.6 {
-        Color::Yellow
+        palette[3]
     } else if ratio > 0.4 {
-        Color::Green
+        palette[2]
     } else if ratio > 0.2 {
-        Color::Cyan
+        palette[1]
     } else {
-        Color::Blue
+        palette[0]
     }
 }
 
@@ -225,10 +512,178 @@ mod tests {
 
     #[test]
     fn test_get_signal_color() {
-        assert_eq!(get_signal_color(90, 100), Color::Red);
-        assert_eq!(get_signal_color(70, 100), Color::Yellow);
-        assert_eq!(get_signal_color(50, 100), Color::Green);
-        assert_eq!(get_signal_color(30, 100), Color::Cyan);
-        assert_eq!(get_signal_color(10, 100), Color::Blue);
+        let palette = Theme::default().spectrum;
+        assert_eq!(get_signal_color(90, 100, &palette), Color::Red);
+        assert_eq!(get_signal_color(70, 100, &palette), Color::Yellow);
+        assert_eq!(get_signal_color(50, 100, &palette), Color::Green);
+        assert_eq!(get_signal_color(30, 100, &palette), Color::Cyan);
+        assert_eq!(get_signal_color(10, 100, &palette), Color::Blue);
+
+        let palette = Theme::preset("grayscale").unwrap().spectrum;
+        assert_eq!(get_signal_color(90, 100, &palette), palette[4]);
+        assert_eq!(get_signal_color(10, 100, &palette), palette[0]);
+    }
+
+    /// A peak in the middle of an 8×4 area, as rendered in `mode`
+    fn render_peak(mode: SpectrumMode) -> Buffer {
+        let data = [-100.0, -100.0, -80.0, -20.0, 0.0, -50.0, -100.0, -100.0];
+        let area = Rect::new(0, 0, 8, 4);
+        let mut buf = Buffer::empty(area);
+        SpectrumWidget::new(&data, 100_000_000, 2_048_000)
+            .mode(mode)
+            .render(area, &mut buf);
+        buf
+    }
+
+    fn symbols(buf: &Buffer) -> Vec<String> {
+        (0..buf.area.height)
+            .map(|y| (0..buf.area.width).map(|x| buf[(x, y)].symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_render_golden() {
+        assert_eq!(
+            symbols(&render_peak(SpectrumMode::Bars)),
+            ["    ▁   ", "   ▁▁   ", "   ▁▁▁  ", "▁▁▁▁▁▁▁▁"]
+        );
+
+        let braille = render_peak(SpectrumMode::Braille);
+        assert_eq!(
+            symbols(&braille),
+            ["   ⣰⢻   ", "  ⢀⡇⠈⣇  ", "  ⢸  ⢸⡀ ", "⣀⣰⠋   ⣇⣀"]
+        );
+        // Each cell takes the color of its highest dot
+        assert_eq!(braille[(3, 0)].fg, Color::Red);
+        assert_eq!(braille[(3, 1)].fg, Color::Yellow);
+        assert_eq!(braille[(2, 2)].fg, Color::Green);
+        assert_eq!(braille[(6, 2)].fg, Color::Cyan);
+        assert_eq!(braille[(0, 3)].fg, Color::Blue);
+    }
+
+    #[test]
+    fn test_markers() {
+        assert_eq!(offset_column(0, 2_048_000, 80), Some(40));
+        assert_eq!(offset_column(-1_024_000, 2_048_000, 80), Some(0));
+        assert_eq!(offset_column(512_000, 2_048_000, 80), Some(60));
+        assert_eq!(offset_column(1_024_000, 2_048_000, 80), None);
+
+        let data = [-100.0; 8];
+        let area = Rect::new(0, 0, 8, 4);
+        let mut buf = Buffer::empty(area);
+        SpectrumWidget::new(&data, 100_000_000, 2_048_000)
+            .marker('A', 0, Color::Magenta)
+            .marker('B', -768_000, Color::Magenta)
+            .render(area, &mut buf);
+        assert_eq!(symbols(&buf)[0], " B  A   ");
+        assert_eq!(buf[(4, 0)].fg, Color::Magenta);
+    }
+
+    #[test]
+    fn test_span_columns() {
+        let view = full_view(2_048_000);
+        // 80 columns of 25.6 kHz; ±8 kHz around the center straddles two
+        assert_eq!(span_columns(-8_000, 8_000, view, 80), Some((39, 40)));
+        // However narrow, a passband gets a column
+        assert_eq!(span_columns(300, 2_700, view, 80), Some((40, 40)));
+        assert_eq!(span_columns(-2_700, -300, view, 80), Some((39, 39)));
+        // Clipped at the edges, gone past them
+        assert_eq!(span_columns(1_000_000, 1_100_000, view, 80), Some((79, 79)));
+        assert_eq!(span_columns(1_100_000, 1_200_000, view, 80), None);
+        assert_eq!(span_columns(-2_000_000, 2_000_000, view, 80), Some((0, 79)));
+
+        // Zoomed in to 100-200 kHz above the center: 1 kHz per column
+        let zoomed = (100_000, 200_000);
+        assert_eq!(span_columns(140_000, 150_000, zoomed, 100), Some((40, 49)));
+        assert_eq!(span_columns(-8_000, 8_000, zoomed, 100), None);
+    }
+
+    #[test]
+    fn test_passband_shading() {
+        let data = [-100.0; 8];
+        let area = Rect::new(0, 0, 8, 4);
+        let mut buf = Buffer::empty(area);
+        SpectrumWidget::new(&data, 100_000_000, 2_048_000)
+            .passband(0, 512_000, "6k".to_string(), Color::Indexed(236))
+            .passband(900_000, 1_000_000, "9k".to_string(), Color::Indexed(236))
+            .render(area, &mut buf);
+        for y in 0..4 {
+            let shaded: Vec<bool> = (0..8).map(|x| buf[(x, y)].bg == Color::Indexed(236)).collect();
+            assert_eq!(shaded, [false, false, false, false, true, true, false, true]);
+        }
+        // The trace still draws over it, and labels stay in the area
+        assert_eq!(buf[(4, 3)].symbol(), "▁");
+        assert_eq!(symbols(&buf)[1], "    6k9k");
+    }
+
+    #[test]
+    fn test_inversion() {
+        // -2, -1, 0 and +1 bins from the center
+        let data = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(*display_bins(&data, false), [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(*display_bins(&data, true), [1.0, 4.0, 3.0, 2.0]);
+        assert!(display_bins(&[], true).is_empty());
+        assert_eq!(display_span(300, 2_700, false), (300, 2_700));
+        assert_eq!(display_span(300, 2_700, true), (-2_700, -300));
+
+        let render = |inverted: bool| {
+            let mut data = [-100.0; 24];
+            data[18] = 0.0;
+            let area = Rect::new(0, 0, 24, 5);
+            let mut buf = Buffer::empty(area);
+            SpectrumWidget::new(&data, 100_000_000, 2_400_000)
+                .marker('A', 600_000, Color::Magenta)
+                .inverted(inverted)
+                .render(area, &mut buf);
+            symbols(&buf)
+        };
+        let (upright, inverted) = (render(false), render(true));
+        // The peak and its marker move to the other side of the center
+        assert_eq!(upright[0], "                  A     ");
+        assert_eq!(inverted[0], "      A                 ");
+        assert_eq!(upright[1], "                  ▁     ");
+        assert_eq!(inverted[1], "      ▁                 ");
+        // The frequency axis doesn't
+        assert_eq!(inverted[4], upright[4]);
+        assert!(inverted[4].starts_with("98.80"));
+    }
+
+    #[test]
+    fn test_grid_columns() {
+        // 1 kHz per column; lines every 100 kHz from 99.1 MHz
+        let columns = grid_columns(100_100_000, 2_048_000, 100_000, 2048);
+        assert_eq!(columns.len(), 21);
+        assert_eq!(columns[..3], [24, 124, 224]);
+        // A channel up, the lines are on the same columns, a channel along
+        assert_eq!(grid_columns(100_200_000, 2_048_000, 100_000, 2048), columns);
+        // Half a channel up, they move half a channel down
+        assert_eq!(grid_columns(100_150_000, 2_048_000, 100_000, 2048)[..2], [74, 174]);
+        // A line on the left edge is drawn, one on the right edge isn't
+        assert_eq!(grid_columns(1_000_000, 2_000_000, 500_000, 8), [0, 2, 4, 6]);
+
+        // Too close together to tell apart, or off
+        assert!(grid_columns(100_000_000, 2_048_000, 25_000, 80).is_empty());
+        assert_eq!(grid_columns(100_000_000, 2_048_000, 51_200, 80).len(), 40);
+        assert!(grid_columns(100_000_000, 2_048_000, 0, 80).is_empty());
+    }
+
+    #[test]
+    fn test_grid_under_trace() {
+        let data = [-100.0, -100.0, 0.0, -100.0];
+        let area = Rect::new(0, 0, 4, 4);
+        let mut buf = Buffer::empty(area);
+        SpectrumWidget::new(&data, 1_000_000, 2_000_000)
+            .grid(1_000_000, Color::DarkGray)
+            .render(area, &mut buf);
+        assert_eq!(symbols(&buf), ["  ▁ ", "┊ ▁ ", "┊ ▁ ", "▁▁▁▁"]);
+        assert_eq!(buf[(0, 1)].fg, Color::DarkGray);
+    }
+
+    #[test]
+    fn test_spectrum_mode_from_str() {
+        assert_eq!("Braille".parse(), Ok(SpectrumMode::Braille));
+        assert_eq!("bars".parse(), Ok(SpectrumMode::Bars));
+        assert!("dots".parse::<SpectrumMode>().is_err());
+        assert_eq!(SpectrumMode::Bars.next().next(), SpectrumMode::Bars);
     }
 }
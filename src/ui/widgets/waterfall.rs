@@ -1,3 +1,7 @@
+use super::spectrum::{display_bins, display_span, full_view, span_columns};
+use crate::state::app_state::ClockZone;
+use crate::ui::theme::Colormap;
+use chrono::{DateTime, Utc};
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
@@ -5,6 +9,11 @@ use ratatui::{
     widgets::{Block, Widget},
 };
 
+/// A time label every this many rows
+const LABEL_EVERY: u64 = 5;
+/// Columns of rows left clear of the time labels before they're dropped
+const MIN_ROW_WIDTH: u16 = 16;
+
 /// Waterfall display widget that shows spectrum history over time
 pub struct WaterfallWidget<'a> {
     /// Waterfall history data (oldest to newest)
@@ -15,6 +24,17 @@ pub struct WaterfallWidget<'a> {
     min_db: f32,
     /// Maximum dB value for color mapping
     max_db: f32,
+    /// Palette from weak to strong
+    colormap: Colormap,
+    /// Sample rate in Hz, which the rows span
+    sample_rate: u32,
+    /// Spans bracketed down each row, as offsets from the center in Hz
+    passbands: Vec<(i32, i32, Color)>,
+    /// Draw the bins in reverse, for spectrally inverted input
+    inverted: bool,
+    /// When each row was added, the first row's number since the waterfall
+    /// started, and how to show the times
+    times: Option<(Vec<DateTime<Utc>>, u64, ClockZone)>,
 }
 
 impl<'a> WaterfallWidget<'a> {
@@ -25,6 +45,11 @@ impl<'a> WaterfallWidget<'a> {
             block: None,
             min_db: -100.0,
             max_db: 0.0,
+            colormap: Colormap::Classic,
+            sample_rate: 0,
+            passbands: Vec::new(),
+            inverted: false,
+            times: None,
         }
     }
 
@@ -40,6 +65,35 @@ impl<'a> WaterfallWidget<'a> {
         self.max_db = max;
         self
     }
+
+    /// Set the palette for color mapping
+    pub fn colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = colormap;
+        self
+    }
+
+    /// Bracket `low` to `high` Hz from the center of rows spanning
+    /// `sample_rate`, in `color`
+    pub fn passband(mut self, low: i32, high: i32, sample_rate: u32, color: Color) -> Self {
+        self.sample_rate = sample_rate;
+        self.passbands.push((low, high, color));
+        self
+    }
+
+    /// Reverse the bins, and the passbands with them, as
+    /// `SpectrumWidget::inverted`
+    pub fn inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    /// Label the rows, added at `times`, over their left end, so the rows
+    /// keep the spectrum's width and line up with it; the first is row
+    /// `first_row` of all added, so labels move up with their rows
+    pub fn times(mut self, times: Vec<DateTime<Utc>>, first_row: u64, clock: ClockZone) -> Self {
+        self.times = Some((times, first_row, clock));
+        self
+    }
 }
 
 impl Widget for WaterfallWidget<'_> {
@@ -62,7 +116,6 @@ impl Widget for WaterfallWidget<'_> {
             return;
         }
 
-        let width = area.width as usize;
         let height = area.height as usize;
 
         // Determine how many rows of history to display
@@ -75,12 +128,15 @@ impl Widget for WaterfallWidget<'_> {
             0
         };
 
+        let width = area.width as usize;
+
         // Render each row of the waterfall (newest at bottom)
         for (row_idx, fft_data) in self.data[start_idx..].iter().enumerate() {
             let y = area.top() + row_idx as u16;
 
             // Resample FFT data to fit width
-            let row_data = resample_waterfall_row(fft_data, width);
+            let row_data =
+                resample_waterfall_row(&display_bins(fft_data, self.inverted), width);
 
             // Draw each pixel in the row
             for (x, &db_value) in row_data.iter().enumerate() {
@@ -88,7 +144,7 @@ impl Widget for WaterfallWidget<'_> {
                     break;
                 }
 
-                let color = db_to_color(db_value, self.min_db, self.max_db);
+                let color = db_to_color(db_value, self.min_db, self.max_db, self.colormap);
                 let x_pos = area.left() + x as u16;
 
                 buf.get_mut(x_pos, y)
@@ -96,9 +152,56 @@ impl Widget for WaterfallWidget<'_> {
                     .set_bg(color);
             }
         }
+
+        let view = full_view(self.sample_rate);
+        for &(low, high, color) in &self.passbands {
+            let (low, high) = display_span(low, high, self.inverted);
+            if let Some((first, last)) = span_columns(low, high, view, area.width) {
+                for y in area.top()..area.top() + rows_to_display as u16 {
+                    buf[(area.left() + first, y)].set_char('▏').set_fg(color);
+                    buf[(area.left() + last, y)].set_char('▕').set_fg(color);
+                }
+            }
+        }
+
+        // Time labels over the left end of the rows, if that leaves enough
+        // of them clear
+        if let Some((times, first_row, clock)) = &self.times {
+            let first_row = first_row + start_idx as u64;
+            let labels: Vec<(usize, String)> = label_rows(first_row, rows_to_display)
+                .filter_map(|row| Some((row, clock.timestamp(*times.get(start_idx + row)?))))
+                .collect();
+            let label_width = labels.iter().map(|(_, label)| label.len()).max().unwrap_or(0);
+            if labels_fit(area.width, label_width) {
+                for (row, label) in labels {
+                    buf.set_string(
+                        area.left(),
+                        area.top() + row as u16,
+                        label,
+                        Style::default().fg(Color::Gray).bg(Color::Black),
+                    );
+                }
+            }
+        }
     }
 }
 
+/// Whether `label_width` wide labels leave enough of `width` columns of
+/// rows clear
+fn labels_fit(width: u16, label_width: usize) -> bool {
+    match u16::try_from(label_width) {
+        Ok(0) | Err(_) => false,
+        Ok(label_width) => width.saturating_sub(label_width) >= MIN_ROW_WIDTH,
+    }
+}
+
+/// Which of `rows` shown rows, the first being row `first_row` of all
+/// added, get a time label: every `LABEL_EVERY`th, so they stay on the
+/// same rows as the waterfall moves
+fn label_rows(first_row: u64, rows: usize) -> impl Iterator<Item = usize> {
+    (0..rows).filter(move |&row| (first_row + row as u64).is_multiple_of(LABEL_EVERY))
+}
+
 /// Resample a single waterfall row to fit the target width
 fn resample_waterfall_row(data: &[f32], target_width: usize) -> Vec<f32> {
     if data.is_empty() {
@@ -131,55 +234,20 @@ fn resample_waterfall_row(data: &[f32], target_width: usize) -> Vec<f32> {
     result
 }
 
-/// Convert dB value to color (blue = weak, red = strong)
-fn db_to_color(db: f32, min_db: f32, max_db: f32) -> Color {
+/// Convert dB value to a color from `colormap` (weak to strong)
+fn db_to_color(db: f32, min_db: f32, max_db: f32, colormap: Colormap) -> Color {
     // Normalize to 0.0-1.0
     let normalized = ((db - min_db) / (max_db - min_db))
         .max(0.0)
         .min(1.0);
 
-    // Map to color gradient: blue -> cyan -> green -> yellow -> red
-    if normalized < 0.2 {
-        // Very weak signal: dark blue
-        Color::Rgb(0, 0, (normalized * 5.0 * 128.0) as u8 + 32)
-    } else if normalized < 0.4 {
-        // Weak signal: blue to cyan
-        let t = (normalized - 0.2) * 5.0;
-        Color::Rgb(
-            0,
-            (t * 128.0) as u8,
-            128 + (t * 127.0) as u8,
-        )
-    } else if normalized < 0.6 {
-        // Medium signal: cyan to green
-        let t = (normalized - 0.4) * 5.0;
-        Color::Rgb(
-            (t * 64.0) as u8,
-            128 + (t * 127.0) as u8,
-            255 - (t * 255.0) as u8,
-        )
-    } else if normalized < 0.8 {
-        // Strong signal: green to yellow
-        let t = (normalized - 0.6) * 5.0;
-        Color::Rgb(
-            64 + (t * 191.0) as u8,
-            255,
-            0,
-        )
-    } else {
-        // Very strong signal: yellow to red
-        let t = (normalized - 0.8) * 5.0;
-        Color::Rgb(
-            255,
-            255 - (t * 255.0) as u8,
-            0,
-        )
-    }
+    colormap.color(normalized)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
 
     #[test]
     fn test_resample_waterfall_row() {
@@ -199,10 +267,90 @@ mod tests {
         assert_eq!(resampled[0], -100.0);
     }
 
+    #[test]
+    fn test_passband_brackets() {
+        let row = vec![-100.0; 8];
+        let area = Rect::new(0, 0, 8, 3);
+        let mut buf = Buffer::empty(area);
+        WaterfallWidget::new(vec![&row, &row])
+            .passband(-512_000, 0, 2_048_000, Color::White)
+            .render(area, &mut buf);
+        for y in 0..2 {
+            let line: String = (0..8).map(|x| buf[(x, y)].symbol()).collect();
+            assert_eq!(line, "  ▏▕    ");
+        }
+        // Only rows with data
+        assert_eq!(buf[(2, 2)].symbol(), " ");
+
+        // Inverted, to the other side of the center
+        let mut row = vec![-100.0; 8];
+        row[2] = 0.0;
+        let mut buf = Buffer::empty(area);
+        WaterfallWidget::new(vec![&row])
+            .passband(-512_000, 0, 2_048_000, Color::White)
+            .inverted(true)
+            .render(area, &mut buf);
+        let line: String = (0..8).map(|x| buf[(x, 0)].symbol()).collect();
+        assert_eq!(line, "    ▏▕  ");
+        assert_eq!(buf[(6, 0)].bg, db_to_color(0.0, -100.0, 0.0, Colormap::Classic));
+    }
+
+    #[test]
+    fn test_labels_fit() {
+        // "14:25:03Z", when that leaves 16 columns
+        assert!(labels_fit(80, 9));
+        assert!(labels_fit(25, 9));
+        assert!(!labels_fit(24, 9));
+        assert!(!labels_fit(80, 0));
+        assert!(!labels_fit(0, 9));
+        assert!(!labels_fit(u16::MAX, usize::MAX));
+    }
+
+    #[test]
+    fn test_label_rows() {
+        assert_eq!(label_rows(0, 12).collect::<Vec<_>>(), [0, 5, 10]);
+        // One row further on, the labels have moved up one
+        assert_eq!(label_rows(1, 12).collect::<Vec<_>>(), [4, 9]);
+        assert_eq!(label_rows(3, 2).collect::<Vec<_>>(), Vec::<usize>::new());
+        assert_eq!(label_rows(7, 0).count(), 0);
+    }
+
+    #[test]
+    fn test_time_labels() {
+        let row = vec![-100.0; 4];
+        let rows = vec![&row; 8];
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 14, 25, 3).unwrap();
+        let times: Vec<_> = (0..8).map(|i| start + chrono::Duration::seconds(i)).collect();
+        let render = |width: u16| {
+            let area = Rect::new(0, 0, width, 6);
+            let mut buf = Buffer::empty(area);
+            // Rows 11-18 of all added, the last 6 shown: 13-18
+            WaterfallWidget::new(rows.clone())
+                .times(times.clone(), 11, ClockZone::Utc)
+                .render(area, &mut buf);
+            buf
+        };
+
+        let buf = render(30);
+        let label = |y: u16| -> String { (0..10).map(|x| buf[(x, y)].symbol()).collect() };
+        assert_eq!(label(2), "14:25:07Z ");
+        assert!((0..6).filter(|&y| y != 2).all(|y| label(y).trim().is_empty()));
+        // Over the rows, which still start at the left edge as the
+        // spectrum's columns do
+        assert_eq!(buf[(0, 2)].bg, Color::Black);
+        assert_ne!(buf[(9, 2)].bg, Color::Reset);
+        assert_ne!(buf[(0, 1)].bg, Color::Reset);
+
+        // Too narrow to spare the columns
+        let buf = render(20);
+        assert_eq!(buf[(0, 2)].symbol(), " ");
+        assert_ne!(buf[(0, 2)].bg, Color::Reset);
+    }
+
     #[test]
     fn test_db_to_color() {
         // Test weak signal (blue-ish)
-        let color = db_to_color(-100.0, -100.0, 0.0);
+        let color = db_to_color(-100.0, -100.0, 0.0, Colormap::Classic);
         match color {
             Color::Rgb(r, g, b) => {
                 assert!(b > 0); // Should have blue component
@@ -211,12 +359,22 @@ mod tests {
         }
 
         // Test strong signal (red-ish)
-        let color = db_to_color(0.0, -100.0, 0.0);
+        let color = db_to_color(0.0, -100.0, 0.0, Colormap::Classic);
         match color {
             Color::Rgb(r, g, b) => {
                 assert!(r > 200); // Should be mostly red
             }
             _ => panic!("Expected RGB color"),
         }
+
+        // Other palettes get the same normalized level
+        assert_eq!(
+            db_to_color(-50.0, -100.0, 0.0, Colormap::Grayscale),
+            Color::Rgb(128, 128, 128)
+        );
+        assert_eq!(
+            db_to_color(-150.0, -100.0, 0.0, Colormap::Viridis),
+            Colormap::Viridis.color(0.0)
+        );
     }
 }
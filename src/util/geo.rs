@@ -0,0 +1,227 @@
+//! Distance and bearing from the station to decoded positions
+//!
+//! Distances are great-circle distances by the haversine formula on a
+//! spherical Earth, which is within 0.5% of the ellipsoid; bearings are the
+//! initial true bearing from the station.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Mean Earth radius in km
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// A point on the Earth in degrees, north and east positive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Location {
+    /// A location, if `lat` and `lon` are in range
+    pub fn new(lat: f64, lon: f64) -> Option<Self> {
+        ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon))
+            .then_some(Self { lat, lon })
+    }
+
+    /// Great-circle distance to `to` in km
+    pub fn distance_km(&self, to: Location) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), to.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (to.lon - self.lon).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+    }
+
+    /// Initial bearing to `to` in degrees from true north, 0 to 360
+    pub fn bearing(&self, to: Location) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), to.lat.to_radians());
+        let dlon = (to.lon - self.lon).to_radians();
+        let y = dlon.sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+        y.atan2(x).to_degrees().rem_euclid(360.0)
+    }
+}
+
+/// "lat,lon" in decimal degrees, e.g. "47.6062,-122.3321"
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (lat, lon) = s
+            .split_once(',')
+            .ok_or_else(|| format!("location '{}' should be lat,lon", s))?;
+        let parse = |v: &str| v.trim().parse::<f64>().ok();
+        match (parse(lat), parse(lon)) {
+            (Some(lat), Some(lon)) => {
+                Location::new(lat, lon).ok_or_else(|| format!("location '{}' is out of range", s))
+            }
+            _ => Err(format!("location '{}' should be lat,lon in degrees", s)),
+        }
+    }
+}
+
+/// Unit distances are shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceUnit {
+    #[default]
+    Km,
+    Mi,
+    Nm,
+}
+
+impl DistanceUnit {
+    pub fn label(self) -> &'static str {
+        match self {
+            DistanceUnit::Km => "km",
+            DistanceUnit::Mi => "mi",
+            DistanceUnit::Nm => "nm",
+        }
+    }
+
+    /// `km` in this unit
+    pub fn convert(self, km: f64) -> f64 {
+        match self {
+            DistanceUnit::Km => km,
+            DistanceUnit::Mi => km / 1.609_344,
+            DistanceUnit::Nm => km / 1.852,
+        }
+    }
+}
+
+impl FromStr for DistanceUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "km" => Ok(DistanceUnit::Km),
+            "mi" => Ok(DistanceUnit::Mi),
+            "nm" => Ok(DistanceUnit::Nm),
+            _ => Err(format!(
+                "unknown distance unit '{}' (expected km, mi or nm)",
+                s
+            )),
+        }
+    }
+}
+
+/// The receiving station, which distances are measured from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Station {
+    pub location: Location,
+    pub unit: DistanceUnit,
+}
+
+impl Station {
+    /// Distance to `to` in the station's unit and bearing in degrees
+    pub fn range(&self, to: Location) -> Range {
+        Range {
+            distance: self.unit.convert(self.location.distance_km(to)),
+            bearing: self.location.bearing(to),
+            unit: self.unit,
+        }
+    }
+}
+
+/// How far and which way a position is from the station
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub distance: f64,
+    /// Degrees from true north
+    pub bearing: f64,
+    pub unit: DistanceUnit,
+}
+
+/// e.g. "12.4 km @ 213°"
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // 359.6° rounds to 360; show it as north
+        let bearing = self.bearing.round() as u32 % 360;
+        write!(
+            f,
+            "{:.1} {} @ {:03}°",
+            self.distance,
+            self.unit.label(),
+            bearing
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEATTLE: Location = Location {
+        lat: 47.6062,
+        lon: -122.3321,
+    };
+    const PORTLAND: Location = Location {
+        lat: 45.5152,
+        lon: -122.6784,
+    };
+
+    #[test]
+    fn test_distance_and_bearing() {
+        assert!((SEATTLE.distance_km(PORTLAND) - 234.0).abs() < 0.1);
+        assert!((SEATTLE.bearing(PORTLAND) - 186.6).abs() < 0.1);
+        assert!((PORTLAND.bearing(SEATTLE) - 6.4).abs() < 0.1);
+        assert_eq!(SEATTLE.distance_km(SEATTLE), 0.0);
+
+        // A quarter of the way round the equator, due east
+        let origin = Location { lat: 0.0, lon: 0.0 };
+        let east = Location {
+            lat: 0.0,
+            lon: 90.0,
+        };
+        assert!((origin.distance_km(east) - 10_007.5).abs() < 1.0);
+        assert!((origin.bearing(east) - 90.0).abs() < 1e-9);
+
+        // Antipodes and across the date line stay finite and in range
+        let antipode = Location {
+            lat: 0.0,
+            lon: 180.0,
+        };
+        assert!((origin.distance_km(antipode) - 20_015.1).abs() < 1.0);
+        let fiji = Location {
+            lat: -17.7,
+            lon: 178.0,
+        };
+        let samoa = Location {
+            lat: -13.8,
+            lon: -172.1,
+        };
+        assert!(fiji.distance_km(samoa) < 1200.0);
+        assert!((0.0..360.0).contains(&fiji.bearing(samoa)));
+    }
+
+    #[test]
+    fn test_range_display() {
+        let station = Station {
+            location: SEATTLE,
+            unit: DistanceUnit::Km,
+        };
+        assert_eq!(station.range(PORTLAND).to_string(), "234.0 km @ 187°");
+
+        let station = Station {
+            unit: DistanceUnit::Nm,
+            ..station
+        };
+        let north = Location {
+            lat: 47.8,
+            lon: -122.3322,
+        };
+        assert_eq!(station.range(north).to_string(), "11.6 nm @ 000°");
+        let mi = DistanceUnit::Mi.convert(SEATTLE.distance_km(PORTLAND));
+        assert!((mi - 145.4).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("47.6062, -122.3321".parse(), Ok(SEATTLE));
+        assert!("47.6".parse::<Location>().is_err());
+        assert!("91,0".parse::<Location>().is_err());
+        assert!("north,west".parse::<Location>().is_err());
+        assert_eq!("NM".parse(), Ok(DistanceUnit::Nm));
+        assert!("furlongs".parse::<DistanceUnit>().is_err());
+    }
+}
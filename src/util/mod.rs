@@ -0,0 +1,5 @@
+//! Helpers shared across the app
+
+pub mod geo;
+pub mod net;
+pub mod units;
@@ -0,0 +1,40 @@
+//! Listening sockets for the servers that answer one connection at a time
+
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// How often a quiet listener looks at the shutdown flag
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Accept connections on `listener` until `shutdown` is set, handing each
+/// to `handle` in turn. `name` labels the log lines, e.g. "HTTP server".
+pub fn accept_loop(
+    listener: TcpListener,
+    name: &str,
+    shutdown: &AtomicBool,
+    mut handle: impl FnMut(TcpStream) -> io::Result<()>,
+) {
+    if let Err(e) = listener.set_nonblocking(true) {
+        log::warn!("{} can't poll for shutdown: {}", name, e);
+    }
+    while !shutdown.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                if let Err(e) = handle(stream) {
+                    log::debug!("{}: connection from {} failed: {}", name, addr, e);
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => {
+                log::warn!("{}: accept error: {}", name, e);
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+    log::info!("{} stopped", name);
+}
@@ -0,0 +1,115 @@
+//! Frequencies and durations as people type them
+
+use anyhow::{anyhow, bail, Result};
+use std::time::Duration;
+
+/// Bare numbers below this are taken as MHz, at and above it as Hz
+const BARE_MHZ_LIMIT: f64 = 100_000.0;
+
+/// A frequency in Hz from text such as "162.55M", "162.550 MHz", "27185k",
+/// "1.09G" or "1090000000"
+///
+/// A bare number below 100 000 is taken as MHz, so "162.55" works as it
+/// does on the command line.
+pub fn parse_frequency(text: &str) -> Result<u32, String> {
+    let trimmed = text.trim();
+    let lower = trimmed.to_ascii_lowercase();
+    let lower = lower.strip_suffix("hz").unwrap_or(&lower).trim_end();
+    let (number, scale) = match lower.char_indices().last() {
+        Some((at, 'k')) => (&lower[..at], Some(1e3)),
+        Some((at, 'm')) => (&lower[..at], Some(1e6)),
+        Some((at, 'g')) => (&lower[..at], Some(1e9)),
+        _ => (lower, None),
+    };
+    let invalid = || {
+        format!(
+            "'{}' is not a frequency, e.g. 162.55M or 162550000",
+            trimmed
+        )
+    };
+    let value: f64 = number
+        .trim()
+        .replace('_', "")
+        .parse()
+        .map_err(|_| invalid())?;
+    if !value.is_finite() || value <= 0.0 {
+        return Err(invalid());
+    }
+    let scale = scale.unwrap_or(if value < BARE_MHZ_LIMIT { 1e6 } else { 1.0 });
+    let hz = (value * scale).round();
+    if hz > u32::MAX as f64 {
+        return Err(format!("{} is above the highest frequency", trimmed));
+    }
+    Ok(hz as u32)
+}
+
+/// Parse a duration such as "90s", "10m", "1h30m" (a bare number is seconds)
+pub fn parse_duration(spec: &str) -> Result<Duration> {
+    let spec = spec.trim();
+    if let Ok(secs) = spec.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in spec.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => bail!("invalid duration '{}'", spec),
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| anyhow!("invalid duration '{}'", spec))?;
+        total += value * unit;
+        number.clear();
+    }
+
+    if !number.is_empty() || total == 0 {
+        bail!("invalid duration '{}'", spec);
+    }
+    Ok(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_frequency() {
+        for (text, hz) in [
+            ("162.55M", 162_550_000),
+            ("162.550 MHz", 162_550_000),
+            ("162.55mhz", 162_550_000),
+            ("27185k", 27_185_000),
+            ("27185 kHz", 27_185_000),
+            ("1.09G", 1_090_000_000),
+            ("1090000000", 1_090_000_000),
+            ("1_090_000_000", 1_090_000_000),
+            ("146520000 Hz", 146_520_000),
+            ("162.55", 162_550_000),
+            (" 7.074 ", 7_074_000),
+        ] {
+            assert_eq!(parse_frequency(text), Ok(hz), "{}", text);
+        }
+        for text in ["", "M", "abc", "-5M", "0", "162.55X", "5G"] {
+            assert!(parse_frequency(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap().as_secs(), 90);
+        assert_eq!(parse_duration("10m").unwrap().as_secs(), 600);
+        assert_eq!(parse_duration("1h30m").unwrap().as_secs(), 5400);
+        assert_eq!(parse_duration("2h5s").unwrap().as_secs(), 7205);
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("0m").is_err());
+    }
+}
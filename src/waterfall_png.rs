@@ -0,0 +1,451 @@
+//! Waterfall history as a PNG
+//!
+//! The whole history, not just what fits on screen: one pixel column per
+//! bin and one row per history entry, oldest at the top, colored with the
+//! waterfall palette. Frequencies are labelled along the top and times down
+//! the left, in a small built-in font. The axis is that of the current
+//! tuning, so rows from before a retune are labelled as if at it.
+
+use crate::state::app_state::{ClockZone, WaterfallRow};
+use crate::state::{AppState, SharedState};
+use crate::ui::theme::Colormap;
+use crate::ui::widgets::spectrum::display_bins;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use ratatui::style::Color;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// dB mapped to the weakest and strongest colors, as on screen
+const MIN_DB: f32 = -100.0;
+const MAX_DB: f32 = 0.0;
+/// Font pixels per image pixel
+const SCALE: usize = 2;
+/// Size of a character cell, spacing included
+const CHAR_WIDTH: usize = 4 * SCALE;
+const CHAR_HEIGHT: usize = 6 * SCALE;
+/// Space around labels
+const PAD: usize = 2;
+/// Length of the tick marks
+const TICK: usize = 4;
+/// Height of the frequency axis above the rows
+const AXIS_HEIGHT: usize = PAD + CHAR_HEIGHT + TICK;
+/// Ticks along the frequency axis, as shares of the span
+const AXIS_TICKS: [f64; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+/// Time ruler intervals to pick from, in seconds
+const RULER_STEPS: [i64; 13] = [1, 2, 5, 10, 15, 30, 60, 120, 300, 600, 900, 1800, 3600];
+/// Label and tick color
+const INK: [u8; 3] = [200, 200, 200];
+
+/// What's needed to draw the waterfall, copied out of the state so the
+/// image is made without holding the lock
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Oldest to newest
+    pub rows: Vec<WaterfallRow>,
+    pub center_freq: u32,
+    pub sample_rate: u32,
+    pub clock: ClockZone,
+    pub colormap: Colormap,
+    /// Mirrored, as `SpectrumWidget::inverted`
+    pub inverted: bool,
+}
+
+impl Snapshot {
+    /// The waterfall history in `state`, drawn with `colormap`
+    pub fn take(state: &AppState, colormap: Colormap, inverted: bool) -> Self {
+        Self {
+            rows: state
+                .spectrum
+                .get_waterfall_display(0, usize::MAX)
+                .into_iter()
+                .cloned()
+                .collect(),
+            center_freq: state.sdr.frequency,
+            sample_rate: state.sdr.sample_rate,
+            clock: state.ui.clock,
+            colormap,
+            inverted,
+        }
+    }
+}
+
+/// An RGB image, 8 bits a channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// Rows top to bottom, three bytes a pixel
+    pub rgb: Vec<u8>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            rgb: vec![0; width * height * 3],
+        }
+    }
+
+    /// Set a pixel; outside the image is ignored
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let i = (y * self.width + x) * 3;
+            self.rgb[i..i + 3].copy_from_slice(&color);
+        }
+    }
+
+    /// Draw `text` with its top left at `x`, `y`
+    fn text(&mut self, x: usize, y: usize, text: &str, color: [u8; 3]) {
+        for (i, c) in text.chars().enumerate() {
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) == 0 {
+                        continue;
+                    }
+                    for (dx, dy) in (0..SCALE).flat_map(|dx| (0..SCALE).map(move |dy| (dx, dy))) {
+                        let px = x + i * CHAR_WIDTH + column * SCALE + dx;
+                        self.set(px, y + row * SCALE + dy, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Encode as a PNG to `path`
+    pub fn write_png(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgb)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Rows of a 3×5 glyph, top to bottom, the high of three bits leftmost;
+/// blank for characters without one
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        _ => [0; 5],
+    }
+}
+
+/// `colormap` at 256 evenly spaced levels, weakest first
+pub fn palette_lut(colormap: Colormap) -> Vec<[u8; 3]> {
+    (0..=255)
+        .map(|level| match colormap.color(level as f32 / 255.0) {
+            Color::Rgb(r, g, b) => [r, g, b],
+            _ => [0, 0, 0],
+        })
+        .collect()
+}
+
+/// Entry of a 256-color LUT for `db`
+fn lut_index(db: f32) -> usize {
+    let normalized = ((db - MIN_DB) / (MAX_DB - MIN_DB)).clamp(0.0, 1.0);
+    if normalized.is_nan() {
+        0
+    } else {
+        (normalized * 255.0).round() as usize
+    }
+}
+
+/// Frequency labels for `columns` spanning `sample_rate` around
+/// `center_freq`: (tick column, label column, text in MHz), left to right,
+/// leaving out any that would run into the one before
+pub fn axis_labels(
+    center_freq: u32,
+    sample_rate: u32,
+    columns: usize,
+) -> Vec<(usize, usize, String)> {
+    if columns == 0 {
+        return Vec::new();
+    }
+    let start = center_freq as f64 - sample_rate as f64 / 2.0;
+    let mut labels: Vec<(usize, usize, String)> = Vec::new();
+    for share in AXIS_TICKS {
+        let text = format!("{:.3}", (start + share * sample_rate as f64) / 1e6);
+        let width = text.len() * CHAR_WIDTH;
+        let tick = ((share * columns as f64) as usize).min(columns - 1);
+        // Centered on the tick, kept inside the image
+        let x = tick
+            .saturating_sub(width / 2)
+            .min(columns.saturating_sub(width));
+        let clear = labels
+            .last()
+            .is_none_or(|(_, last_x, last)| x >= last_x + last.len() * CHAR_WIDTH + CHAR_WIDTH);
+        if clear && x + width <= columns {
+            labels.push((tick, x, text));
+        }
+    }
+    labels
+}
+
+/// Rows, of those added at `times` (Unix milliseconds), that start a new
+/// interval of a round number of seconds, the interval chosen so labels
+/// average at least `min_gap` rows apart; none closer than that are kept
+pub fn ruler_rows(times: &[i64], min_gap: usize) -> Vec<usize> {
+    let (Some(first), Some(last)) = (times.first(), times.last()) else {
+        return Vec::new();
+    };
+    let wanted = (last - first) as f64 * min_gap as f64 / times.len() as f64;
+    let step = RULER_STEPS
+        .iter()
+        .map(|seconds| seconds * 1000)
+        .find(|&ms| ms as f64 >= wanted)
+        .unwrap_or(RULER_STEPS[RULER_STEPS.len() - 1] * 1000);
+
+    let mut rows: Vec<usize> = Vec::new();
+    for (i, pair) in times.windows(2).enumerate() {
+        let row = i + 1;
+        let crossed = pair[0].div_euclid(step) != pair[1].div_euclid(step);
+        if crossed && rows.last().is_none_or(|&last| row - last >= min_gap) {
+            rows.push(row);
+        }
+    }
+    rows
+}
+
+/// Draw the snapshot's history with its axes
+pub fn render(snapshot: &Snapshot) -> Image {
+    let lut = palette_lut(snapshot.colormap);
+    let columns = snapshot
+        .rows
+        .iter()
+        .map(|row| row.bins.len())
+        .max()
+        .unwrap_or(0);
+    let times: Vec<i64> = snapshot.rows.iter().map(|row| row.time).collect();
+    let label = |row: &WaterfallRow| snapshot.clock.timestamp(row.time());
+    let ruler_width = snapshot
+        .rows
+        .first()
+        .map_or(0, |row| label(row).len() * CHAR_WIDTH)
+        + 2 * PAD
+        + TICK;
+
+    let mut image = Image::new(ruler_width + columns, AXIS_HEIGHT + snapshot.rows.len());
+    for (y, row) in snapshot.rows.iter().enumerate() {
+        for (x, &db) in display_bins(&row.bins, snapshot.inverted)
+            .iter()
+            .enumerate()
+        {
+            image.set(ruler_width + x, AXIS_HEIGHT + y, lut[lut_index(db)]);
+        }
+    }
+
+    for (tick, x, text) in axis_labels(snapshot.center_freq, snapshot.sample_rate, columns) {
+        image.text(ruler_width + x, PAD, &text, INK);
+        for y in AXIS_HEIGHT - TICK..AXIS_HEIGHT {
+            image.set(ruler_width + tick, y, INK);
+        }
+    }
+
+    for row in ruler_rows(&times, CHAR_HEIGHT + PAD) {
+        let y = AXIS_HEIGHT + row;
+        let top = y.saturating_sub(CHAR_HEIGHT / 2).max(AXIS_HEIGHT);
+        image.text(PAD, top, &label(&snapshot.rows[row]), INK);
+        for x in ruler_width - TICK..ruler_width {
+            image.set(x, y, INK);
+        }
+    }
+    image
+}
+
+/// Where a waterfall exported at `time` is written in `dir`
+///
+/// e.g. `waterfall_20240601_142503.png`
+pub fn export_path(dir: &Path, time: &DateTime<Local>) -> PathBuf {
+    dir.join(format!("waterfall_{}.png", time.format("%Y%m%d_%H%M%S")))
+}
+
+/// Draw `snapshot` and write it to `path`; returns the rows written
+pub fn export(snapshot: &Snapshot, path: &Path) -> Result<usize> {
+    if snapshot.rows.is_empty() {
+        anyhow::bail!("the waterfall is empty");
+    }
+    render(snapshot).write_png(path)?;
+    Ok(snapshot.rows.len())
+}
+
+/// Export `snapshot` to `path` on its own thread, reporting how it went in
+/// the status bar
+pub fn start_export_thread(
+    snapshot: Snapshot,
+    path: PathBuf,
+    state: SharedState,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let message = match export(&snapshot, &path) {
+            Ok(rows) => {
+                log::info!("Exported {} waterfall rows to {}", rows, path.display());
+                format!("Waterfall saved to {}", path.display())
+            }
+            Err(e) => {
+                log::warn!("Failed to export the waterfall: {:#}", e);
+                format!("Waterfall export failed: {:#}", e)
+            }
+        };
+        state.write().ui.status_message = message;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `count` rows of `bins`, a second apart from `start` ms
+    fn history(bins: &[f32], count: usize, start: i64) -> Vec<WaterfallRow> {
+        (0..count)
+            .map(|i| WaterfallRow {
+                time: start + i as i64 * 1000,
+                bins: bins.to_vec(),
+            })
+            .collect()
+    }
+
+    fn pixel(image: &Image, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * image.width + x) * 3;
+        [image.rgb[i], image.rgb[i + 1], image.rgb[i + 2]]
+    }
+
+    fn snapshot(rows: Vec<WaterfallRow>) -> Snapshot {
+        Snapshot {
+            rows,
+            center_freq: 100_000_000,
+            sample_rate: 2_048_000,
+            clock: ClockZone::Utc,
+            colormap: Colormap::Grayscale,
+            inverted: false,
+        }
+    }
+
+    #[test]
+    fn test_palette_lut() {
+        for colormap in Colormap::ALL {
+            let lut = palette_lut(colormap);
+            assert_eq!(lut.len(), 256);
+            let rgb = |c: Color| match c {
+                Color::Rgb(r, g, b) => [r, g, b],
+                _ => panic!("not RGB"),
+            };
+            assert_eq!(lut[0], rgb(colormap.color(0.0)));
+            assert_eq!(lut[255], rgb(colormap.color(1.0)));
+        }
+        let gray = palette_lut(Colormap::Grayscale);
+        assert_eq!(gray[128], [128, 128, 128]);
+
+        assert_eq!(lut_index(-100.0), 0);
+        assert_eq!(lut_index(-150.0), 0);
+        assert_eq!(lut_index(-50.0), 128);
+        assert_eq!(lut_index(10.0), 255);
+        assert_eq!(lut_index(f32::NAN), 0);
+    }
+
+    #[test]
+    fn test_render_pixels() {
+        let bins = [-100.0, -50.0, 0.0, -25.0];
+        let mut rows = history(&bins, 3, 0);
+        rows[1].bins = vec![0.0; 4];
+        let image = render(&snapshot(rows.clone()));
+
+        // "00:00:00Z" gutter, axis above
+        let left = 9 * CHAR_WIDTH + 2 * PAD + TICK;
+        assert_eq!((image.width, image.height), (left + 4, AXIS_HEIGHT + 3));
+        let row = |image: &Image, y: usize| -> Vec<[u8; 3]> {
+            (0..4)
+                .map(|x| pixel(image, left + x, AXIS_HEIGHT + y))
+                .collect()
+        };
+        assert_eq!(row(&image, 0), [[0; 3], [128; 3], [255; 3], [191; 3]]);
+        assert_eq!(row(&image, 1), [[255; 3]; 4]);
+
+        let mut inverted = snapshot(rows);
+        inverted.inverted = true;
+        assert_eq!(
+            row(&render(&inverted), 0),
+            [[0; 3], [191; 3], [255; 3], [128; 3]]
+        );
+    }
+
+    #[test]
+    fn test_axis_labels() {
+        let labels = axis_labels(100_000_000, 2_048_000, 1024);
+        let texts: Vec<&str> = labels.iter().map(|(_, _, text)| text.as_str()).collect();
+        assert_eq!(texts, ["98.976", "99.488", "100.000", "100.512", "101.024"]);
+        let ticks: Vec<usize> = labels.iter().map(|&(tick, _, _)| tick).collect();
+        assert_eq!(ticks, [0, 256, 512, 768, 1023]);
+        // Centered on their ticks, but inside the image at the edges
+        let width = |text: &str| text.len() * CHAR_WIDTH;
+        assert_eq!(labels[0].1, 0);
+        assert_eq!(labels[2].1, 512 - width("100.000") / 2);
+        assert_eq!(labels[4].1, 1024 - width("101.024"));
+
+        // Narrow, the ones that would collide are left out
+        let labels = axis_labels(100_000_000, 2_048_000, 200);
+        let texts: Vec<&str> = labels.iter().map(|(_, _, text)| text.as_str()).collect();
+        assert_eq!(texts, ["98.976", "100.000", "101.024"]);
+        // Or all of them, when even one doesn't fit
+        assert!(axis_labels(100_000_000, 2_048_000, 40).is_empty());
+        assert!(axis_labels(100_000_000, 2_048_000, 0).is_empty());
+    }
+
+    #[test]
+    fn test_ruler_rows() {
+        // 10 rows a second for a minute, labels at least 14 rows apart:
+        // every 2 seconds, on the even second
+        let times: Vec<i64> = (0..600).map(|i| 1_000_050 + i * 100).collect();
+        let rows = ruler_rows(&times, 14);
+        assert_eq!(rows[..3], [20, 40, 60]);
+        assert!(rows.windows(2).all(|w| w[1] - w[0] == 20));
+
+        // A row a second: labels every 15 seconds
+        let times: Vec<i64> = (0..100).map(|i| i * 1000).collect();
+        assert_eq!(ruler_rows(&times, 14)[..2], [15, 30]);
+
+        // A gap in the history doesn't crowd the labels
+        let times = [0, 100, 200, 60_000, 60_100, 120_000];
+        assert_eq!(ruler_rows(&times, 2), [3, 5]);
+        assert!(ruler_rows(&[], 14).is_empty());
+        assert!(ruler_rows(&[5], 14).is_empty());
+    }
+
+    #[test]
+    fn test_export_png() {
+        let dir = std::env::temp_dir().join(format!("waterfall_png_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("waterfall.png");
+
+        let rows = history(&[-60.0; 512], 40, 1_717_251_903_000);
+        assert_eq!(export(&snapshot(rows), &path).unwrap(), 40);
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.height as usize, AXIS_HEIGHT + 40);
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+
+        assert!(export(&snapshot(Vec::new()), &path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}